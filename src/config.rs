@@ -1,6 +1,9 @@
 //! 設定ファイルを扱う。
 
-use std::fs::File;
+pub mod schedule;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
@@ -9,12 +12,35 @@ use std::str::FromStr;
 use std::env;
 
 use anyhow::{Context, Error, Result};
-use log::{error, LevelFilter};
+use log::{error, warn, LevelFilter};
 use serde::{Deserialize, Serialize};
 use toml::de::from_slice;
-use toml::to_string_pretty;
+use toml::{to_string_pretty, Value};
 
+use crate::core::extend::CaseCollision;
+use crate::core::ignore::pattern::{
+    load_patterns, parse_lines, parse_lines_with, Patterns, Syntax,
+};
+use crate::notify::NotifyOn;
 use crate::smalllog;
+use crate::util::keyring;
+
+use schedule::Schedule;
+
+#[cfg(test)]
+mod test;
+
+/// リポジトリ内に置かれる設定ファイルの名前
+pub const REPOSITORY_CONFIG_FILE: &str = "config.toml";
+
+/// OSのキーリングに保存するメール送信用パスワードの名前
+pub const KEYRING_MAIL_PASSWORD: &str = "mail";
+
+/// OSのキーリングに保存する`serve`の認証用トークンの名前
+pub const KEYRING_SERVE_TOKEN: &str = "serve";
+
+/// OSのキーリングに保存できる秘密情報の名前の一覧
+pub const KEYRING_NAMES: &[&str] = &[KEYRING_MAIL_PASSWORD, KEYRING_SERVE_TOKEN];
 
 /// 指定パスから設定ファイルを読み込む
 pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
@@ -22,7 +48,10 @@ pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
     let mut buf = Vec::<u8>::new();
     f.read_to_end(&mut buf)?;
 
-    Ok(from_slice(&buf)?)
+    let mut config: Config = from_slice(&buf)?;
+    config.loaded_pathes.push(path.as_ref().to_owned());
+
+    Ok(config)
 }
 
 /// 指定パスからの設定ファイルの読み込みを試行する。
@@ -38,7 +67,10 @@ pub fn try_load<P: AsRef<Path>>(path: P) -> Result<Option<Config>> {
     Some(load(path)).transpose()
 }
 
-/// 既定のパス([`config_pathes()`](fn.config_pathes.html))から設定を読み込む
+/// 既定のパスから設定を読み込む
+///
+/// システム共通設定、ユーザー設定([`config_pathes()`](fn.config_pathes.html))の順に読み込んで上書きしていく。
+/// リポジトリ内の設定は、操作するリポジトリが決まってから[`Config::merged_repository()`](struct.Config.html#method.merged_repository)で読み込む。
 pub fn auto_load() -> Result<Config> {
     let mut config = Config::default();
 
@@ -51,8 +83,103 @@ pub fn auto_load() -> Result<Config> {
     Ok(config)
 }
 
+/// リポジトリ内の設定ファイルのパスを返す。
+pub fn repository_config_path<P: AsRef<Path>>(repo_path: P) -> PathBuf {
+    repo_path.as_ref().join(REPOSITORY_CONFIG_FILE)
+}
+
+/// 新たに設定ファイルを作成する場合のユーザー設定ファイルのパスを返す。
+///
+/// Linuxでは`$XDG_CONFIG_HOME/sbak/config.toml`、Windowsでは`%APPDATA%\sbak\config.toml`になる。
+/// このパスは[`config_pathes()`](fn.config_pathes.html)に含まれる。
+pub fn user_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|mut p| {
+        p.push("sbak");
+        p.push("config.toml");
+        p
+    })
+}
+
+/// ユーザー設定ファイル([`user_config_path()`](fn.user_config_path.html))の`repository_path`を`repo_path`にする。
+///
+/// `repo_path`は絶対パスにして書き込む。
+/// ファイルが存在しなければ作成する。既存のコメントや他の設定はそのまま残す。
+/// 書き込んだファイルのパスを返す。
+pub fn set_user_repository_path<P: AsRef<Path>>(repo_path: P) -> Result<PathBuf> {
+    let repo_path = fs::canonicalize(&repo_path)
+        .with_context(|| format!("resolving repository path {:?}", repo_path.as_ref()))?;
+    let path = user_config_path().context("can't detect config directory")?;
+
+    let content = match fs::read_to_string(&path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(Error::new(e).context("reading user config file")),
+    };
+    let content = with_repository_path(&content, &repo_path)?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("creating config directory")?;
+    }
+    fs::write(&path, content).context("writing user config file")?;
+
+    Ok(path)
+}
+
+// 設定ファイルの内容`content`の`repository_path`を`repo_path`に書き換えたものを返す。
+//
+// 最初のテーブルより前にある`repository_path`の行を置き換える。
+// 無ければコメントアウトされた行を置き換え、それも無ければ先頭に追加する。
+fn with_repository_path(content: &str, repo_path: &Path) -> Result<String> {
+    let repo_path = repo_path
+        .to_str()
+        .with_context(|| format!("repository path {:?} is not valid UTF-8", repo_path))?;
+    let new_line = format!("repository_path = {}", Value::String(repo_path.to_owned()));
+
+    let mut lines: Vec<&str> = content.lines().collect();
+    let top_level = lines
+        .iter()
+        .position(|l| l.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    let is_key = |l: &str| {
+        l.strip_prefix("repository_path")
+            .is_some_and(|rest| rest.trim_start().starts_with('='))
+    };
+    let found = lines[..top_level]
+        .iter()
+        .position(|l| is_key(l.trim_start()))
+        .or_else(|| {
+            lines[..top_level]
+                .iter()
+                .position(|l| is_key(l.trim_start().trim_start_matches('#').trim_start()))
+        });
+    match found {
+        Some(i) => lines[i] = &new_line,
+        None => lines.insert(0, &new_line),
+    }
+
+    let mut updated = lines.join("\n");
+    updated.push('\n');
+
+    // 書き換えた結果が設定ファイルとして読み込めることを確認する
+    from_slice::<Config>(updated.as_bytes()).context("parsing updated config file")?;
+
+    Ok(updated)
+}
+
+/// 全てのBankに共通の除外ファイルの既定のパスを返す。
+///
+/// Linuxでは`$XDG_CONFIG_HOME/sbak/ignore`、Windowsでは`%APPDATA%\sbak\ignore`になる。
+pub fn user_ignore_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|mut p| {
+        p.push("sbak");
+        p.push("ignore");
+        p
+    })
+}
+
 /// 起動時に読み込む設定ファイルの探索パスの一覧を返す。
 ///
+/// 後のものほど優先される。
 /// ターゲットとなる環境に応じて切り替えられる。
 /// 現在表示されているのはLinux向け。
 #[cfg(target_os = "linux")]
@@ -61,13 +188,19 @@ pub fn config_pathes() -> Result<Vec<PathBuf>> {
 
     // システム共通設定
     pathes.push("/etc/sbak.toml".parse().unwrap());
+    pathes.push("/etc/sbak/config.toml".parse().unwrap());
 
-    // ユーザー設定
+    // ユーザー設定（旧形式）
     if let Some(mut home_dir) = dirs::home_dir() {
         home_dir.push(".sbak.toml");
         pathes.push(home_dir);
     }
 
+    // ユーザー設定（XDG Base Directory）
+    if let Some(path) = user_config_path() {
+        pathes.push(path);
+    }
+
     Ok(pathes)
 }
 
@@ -79,6 +212,13 @@ pub fn config_pathes() -> Result<Vec<PathBuf>> {
 pub fn config_pathes() -> Result<Vec<PathBuf>> {
     let mut pathes = Vec::<PathBuf>::new();
 
+    // システム共通設定
+    if let Some(mut program_data) = env::var_os("ProgramData").map(PathBuf::from) {
+        program_data.push("sbak");
+        program_data.push("config.toml");
+        pathes.push(program_data);
+    }
+
     // インストール場所設定
     if let Ok(exe_path) = env::current_exe() {
         let mut exe_dir = exe_path.canonicalize()?.parent().unwrap().to_owned();
@@ -98,10 +238,8 @@ pub fn config_pathes() -> Result<Vec<PathBuf>> {
     }
 
     // ユーザー設定（Windowsスタイルのパス）
-    if let Some(mut roaming) = dirs::config_dir() {
-        roaming.push("sbak");
-        roaming.push("config.toml");
-        pathes.push(roaming);
+    if let Some(path) = user_config_path() {
+        pathes.push(path);
     }
 
     Ok(pathes)
@@ -111,8 +249,35 @@ pub fn config_pathes() -> Result<Vec<PathBuf>> {
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     repository_path: Option<PathBuf>,
+    default_bank: Option<String>,
+    lang: Option<String>,
+    keyring: Option<bool>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    exclude: Vec<String>,
+    ignore_file: Option<PathBuf>,
     #[serde(default)]
     log: Log,
+    #[serde(default)]
+    restore: RestoreSettings,
+    #[serde(default)]
+    history: HistorySettings,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    banks: BTreeMap<String, BankSettings>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    webhooks: Vec<Webhook>,
+    #[serde(default)]
+    mail: MailSettings,
+    #[serde(default)]
+    serve: ServeSettings,
+    #[serde(skip)]
+    loaded_pathes: Vec<PathBuf>,
+    #[serde(skip)]
+    quiet: bool,
+    #[serde(skip)]
+    verbose: u64,
 }
 
 impl Config {
@@ -126,6 +291,51 @@ impl Config {
         self.repository_path = Some(path.as_ref().to_owned());
     }
 
+    /// Bankの指定が省略された場合に使うBankの名前を取得する。
+    pub fn default_bank(&self) -> Option<&str> {
+        self.default_bank.as_deref()
+    }
+
+    /// 表示言語の指定を取得する。
+    pub fn lang(&self) -> Option<&str> {
+        self.lang.as_deref()
+    }
+
+    /// `restore`で既存のファイルを上書きするかどうかの既定値を取得する。
+    pub fn restore_overwrite(&self) -> bool {
+        self.restore.overwrite.unwrap_or(false)
+    }
+
+    /// `restore`でバックアップに含まれないファイルを削除するかどうかの既定値を取得する。
+    pub fn restore_remove(&self) -> bool {
+        self.restore.remove.unwrap_or(false)
+    }
+
+    /// `restore`でシンボリックリンクを作成するかどうかの既定値を取得する。
+    pub fn restore_create_symlinks(&self) -> bool {
+        self.restore.create_symlinks.unwrap_or(false)
+    }
+
+    /// `restore`で大文字と小文字だけが異なる名前が衝突した場合の扱いの既定値を取得する。
+    pub fn restore_case_collision(&self) -> CaseCollision {
+        self.restore.case_collision.unwrap_or_default()
+    }
+
+    /// `restore`でシンボリックリンクの一覧を表示するかどうかの既定値を取得する。
+    pub fn restore_show_symlinks(&self) -> bool {
+        self.restore.show_symlinks.unwrap_or(false)
+    }
+
+    /// `history`で表示する履歴の件数の既定値を取得する。
+    pub fn history_show_count(&self) -> Option<usize> {
+        self.history.show_count
+    }
+
+    /// `history`で使うタイムゾーンの既定値を取得する。
+    pub fn history_timezone(&self) -> Option<&str> {
+        self.history.timezone.as_deref()
+    }
+
     /// ログ表示のレベルを設定する。
     pub fn set_log_level(&mut self, level: LogLevel) {
         self.log.level = Some(level);
@@ -137,6 +347,219 @@ impl Config {
         Ok(())
     }
 
+    /// コマンドラインの`-q`と`-v`の指定を適用する。
+    ///
+    /// `quiet`が指定された場合はエラー以外のログとサブコマンドの結果表示を抑制する。
+    /// そうでない場合、`verbose`の回数に応じてログ表示のレベルを`info`, `debug`, `trace`に上げる。
+    pub fn set_verbosity(&mut self, quiet: bool, verbose: u64) {
+        self.quiet = quiet;
+        self.verbose = if quiet { 0 } else { verbose };
+
+        if quiet {
+            self.set_log_level(LogLevel::Error);
+        } else if verbose > 0 {
+            self.set_log_level(LogLevel::from_verbosity(verbose));
+        }
+    }
+
+    /// OSのキーリングを使用するかどうかを返す。
+    ///
+    /// 既定では使用する。
+    pub fn use_keyring(&self) -> bool {
+        self.keyring.unwrap_or(true)
+    }
+
+    /// コマンドラインの`--no-keyring`の指定を適用する。
+    pub fn disable_keyring(&mut self) {
+        self.keyring = Some(false);
+    }
+
+    /// サブコマンドの結果表示を抑制するかどうかを返す。
+    pub fn is_quiet(&self) -> bool {
+        self.quiet
+    }
+
+    /// `-v`が指定され、サブコマンドの詳細な結果を表示するかどうかを返す。
+    pub fn is_verbose(&self) -> bool {
+        self.verbose > 0
+    }
+
+    /// 指定されたBankに適用される除外パターンを返す。
+    ///
+    /// 全体設定の`exclude`の後にBankごとの`exclude`が連結される。
+    /// Bankごとの`exclude`は[`ignore_syntax`](#method.ignore_syntax)の書式で解釈される。
+    pub fn exclude_patterns(&self, bank_name: &str) -> Result<Patterns> {
+        let context = || format!("parsing exclude patterns for bank '{}'", bank_name);
+
+        let patterns = parse_lines(&self.exclude)
+            .with_context(context)?
+            .with_source("config exclude");
+        match self.banks.get(bank_name) {
+            Some(bank) => {
+                let bank_patterns = parse_lines_with(&bank.exclude, self.ignore_syntax(bank_name))
+                    .with_context(context)?
+                    .with_source(&format!("config banks.{}.exclude", bank_name));
+                Ok(patterns.merged(&bank_patterns))
+            }
+            None => Ok(patterns),
+        }
+    }
+
+    /// 全てのBankに共通の除外ファイルの除外パターンを返す。
+    ///
+    /// ファイルは`ignore_file`で指定でき、省略時は[`user_ignore_path()`](fn.user_ignore_path.html)が使われる。
+    /// ファイルが存在しない場合は空のパターンを返す。
+    pub fn ignore_file_patterns(&self) -> Result<Patterns> {
+        let path = match self.ignore_file.clone().or_else(user_ignore_path) {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Patterns::default()),
+        };
+
+        load_patterns(&path).with_context(|| format!("loading ignore file {:?}", path))
+    }
+
+    /// 指定されたBankの除外ファイルの書式を返す。
+    ///
+    /// 指定されていない場合はsbak独自の書式になる。
+    pub fn ignore_syntax(&self, bank_name: &str) -> Syntax {
+        self.banks
+            .get(bank_name)
+            .and_then(|bank| bank.ignore_syntax)
+            .unwrap_or_default()
+    }
+
+    /// 指定されたBankで、バックアップ時にテキストファイルの内容を索引に登録するかを返す。
+    ///
+    /// 指定されていない場合は登録しない。
+    pub fn content_index(&self, bank_name: &str) -> bool {
+        self.banks
+            .get(bank_name)
+            .and_then(|bank| bank.content_index)
+            .unwrap_or(false)
+    }
+
+    /// 指定されたBankで、バックアップ時にパスの索引を更新するかを返す。
+    ///
+    /// 指定されていない場合は更新しない。
+    pub fn path_index(&self, bank_name: &str) -> bool {
+        self.banks
+            .get(bank_name)
+            .and_then(|bank| bank.path_index)
+            .unwrap_or(false)
+    }
+
+    /// 指定されたBankで、バックアップ時にNTFSの代替データストリームを記録するかを返す。
+    ///
+    /// 指定されていない場合は記録しない。
+    pub fn alternate_streams(&self, bank_name: &str) -> bool {
+        self.banks
+            .get(bank_name)
+            .and_then(|bank| bank.alternate_streams)
+            .unwrap_or(false)
+    }
+
+    /// 指定されたBankのバックアップを複製する副リポジトリのパスを返す。
+    ///
+    /// 指定されていない場合は複製しない。
+    pub fn secondary_repository(&self, bank_name: &str) -> Option<&Path> {
+        self.banks
+            .get(bank_name)
+            .and_then(|bank| bank.secondary_repository.as_deref())
+    }
+
+    /// 設定ファイルに設定があるBankの名前を返す。
+    pub fn bank_names(&self) -> impl Iterator<Item = &str> {
+        self.banks.keys().map(|name| name.as_str())
+    }
+
+    /// 指定されたBankのバックアップ予定を返す。
+    ///
+    /// 予定が設定されていない場合は`None`を返す。
+    pub fn schedule(&self, bank_name: &str) -> Result<Option<Schedule>> {
+        self.banks
+            .get(bank_name)
+            .and_then(|bank| bank.schedule.as_ref())
+            .map(|s| {
+                s.parse()
+                    .with_context(|| format!("parsing schedule for bank '{}'", bank_name))
+            })
+            .transpose()
+    }
+
+    /// 実行結果を通知するWebhookの一覧を返す。
+    pub fn webhooks(&self) -> &[Webhook] {
+        &self.webhooks
+    }
+
+    /// 実行結果をメールで通知する設定を返す。
+    ///
+    /// `smtp_url`, `from`, `to`のいずれかが指定されていない場合は`None`を返す。
+    ///
+    /// `username`が指定され`password`が指定されていない場合、パスワードはOSのキーリングから
+    /// [`KEYRING_MAIL_PASSWORD`](constant.KEYRING_MAIL_PASSWORD.html)の名前で取得する。
+    pub fn mail(&self) -> Option<Mail> {
+        let m = &self.mail;
+        let smtp_url = m.smtp_url.clone()?;
+        let from = match m.from {
+            Some(ref from) if !m.to.is_empty() => from.clone(),
+            _ => {
+                warn!("mail notification is disabled: both `from` and `to` are required");
+                return None;
+            }
+        };
+
+        Some(Mail {
+            smtp_url,
+            from,
+            to: m.to.clone(),
+            username: m.username.clone(),
+            password: m.password.clone().or_else(|| {
+                m.username.as_ref()?;
+                self.keyring_secret(KEYRING_MAIL_PASSWORD)
+            }),
+            starttls: m.starttls.unwrap_or(false),
+            on: m.on.unwrap_or(NotifyOn::Failure),
+        })
+    }
+
+    /// `serve`でクライアントの認証に使うトークンを返す。
+    ///
+    /// `[serve]`セクションの`token`が指定されていない場合は、OSのキーリングから
+    /// [`KEYRING_SERVE_TOKEN`](constant.KEYRING_SERVE_TOKEN.html)の名前で取得する。
+    pub fn serve_token(&self) -> Option<String> {
+        self.serve
+            .token
+            .clone()
+            .or_else(|| self.keyring_secret(KEYRING_SERVE_TOKEN))
+            .filter(|t| !t.is_empty())
+    }
+
+    // キーリングから`name`の秘密情報を取得する。
+    //
+    // キーリングが無効な場合や取得に失敗した場合は`None`を返す。
+    fn keyring_secret(&self, name: &str) -> Option<String> {
+        if !self.use_keyring() {
+            return None;
+        }
+
+        match keyring::get(name) {
+            Ok(Some(secret)) => Some(secret),
+            Ok(None) => {
+                warn!("secret '{}' isn't stored in keyring", name);
+                None
+            }
+            Err(e) => {
+                warn!("can't read secret '{}' from keyring: {}", name, e);
+                None
+            }
+        }
+    }
+
+    /// 読み込まれた設定ファイルのパスを読み込んだ順に返す。
+    pub fn loaded_pathes(&self) -> &[PathBuf] {
+        &self.loaded_pathes
+    }
+
     /// ログ設定をロガーに適用する。
     pub fn apply_log(&self) {
         self.log.apply();
@@ -146,12 +569,76 @@ impl Config {
 
     /// 他の設定ファイルの設定値で上書きした新規の`Config`を返す。
     pub fn merged(&self, overwrite: &Config) -> Config {
+        let mut loaded_pathes = self.loaded_pathes.clone();
+        loaded_pathes.extend_from_slice(&overwrite.loaded_pathes);
+
+        let mut exclude = self.exclude.clone();
+        exclude.extend_from_slice(&overwrite.exclude);
+
+        let mut webhooks = self.webhooks.clone();
+        webhooks.extend_from_slice(&overwrite.webhooks);
+
+        let mut banks = self.banks.clone();
+        for (name, bank) in &overwrite.banks {
+            let merged = banks
+                .get(name)
+                .map(|b| b.merged(bank))
+                .unwrap_or_else(|| bank.clone());
+            banks.insert(name.clone(), merged);
+        }
+
         Config {
             repository_path: merge(&self.repository_path, &overwrite.repository_path),
+            default_bank: merge(&self.default_bank, &overwrite.default_bank),
+            lang: merge(&self.lang, &overwrite.lang),
+            ignore_file: merge(&self.ignore_file, &overwrite.ignore_file),
+            exclude,
             log: self.log.merged(&overwrite.log),
+            restore: self.restore.merged(&overwrite.restore),
+            history: self.history.merged(&overwrite.history),
+            banks,
+            webhooks,
+            mail: self.mail.merged(&overwrite.mail),
+            serve: self.serve.merged(&overwrite.serve),
+            keyring: merge(&self.keyring, &overwrite.keyring),
+            loaded_pathes,
+            quiet: self.quiet || overwrite.quiet,
+            verbose: self.verbose.max(overwrite.verbose),
         }
     }
 
+    /// リポジトリ`repo_path`内の設定([`repository_config_path()`](fn.repository_config_path.html))で上書きしたものを返す。
+    ///
+    /// リポジトリのパスは`repo_path`になり、リポジトリ内の設定の`repository_path`は無視する。
+    ///
+    /// リポジトリ内の設定はリポジトリを共有する他の利用者も書き換えられるため、
+    /// 既存のファイルを失う可能性のある`restore.overwrite`と`restore.remove`は無視する。
+    /// これらはシステム共通設定、ユーザー設定、`--config`で指定した設定でのみ有効になる。
+    pub fn merged_repository<P: AsRef<Path>>(&self, repo_path: P) -> Result<Config> {
+        let repo_path = repo_path.as_ref();
+
+        let mut config = match try_load(repository_config_path(repo_path))? {
+            Some(mut c) => {
+                for (name, value) in &mut [
+                    ("overwrite", &mut c.restore.overwrite),
+                    ("remove", &mut c.restore.remove),
+                ] {
+                    if value.take().is_some() {
+                        warn!(
+                            "`restore.{}` in repository config file is ignored; set it in user config file",
+                            name
+                        );
+                    }
+                }
+                self.merged(&c)
+            }
+            None => self.clone(),
+        };
+        config.set_repository_path(repo_path);
+
+        Ok(config)
+    }
+
     /// 設定値を標準出力に表示する
     pub fn show(&self) {
         let s = to_string_pretty(self).unwrap();
@@ -166,27 +653,249 @@ fn merge<T: Clone>(x: &Option<T>, overwrite: &Option<T>) -> Option<T> {
 #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 struct Log {
     output: Option<String>,
+    append: Option<bool>,
     level: Option<LogLevel>,
+    timestamp: Option<LogTimestamp>,
 }
 
 impl Log {
     fn apply(&self) {
         match self.output.as_deref().unwrap_or("stderr") {
             "stderr" => smalllog::use_stderr(),
+            "syslog" => {
+                if let Err(e) = smalllog::use_syslog() {
+                    smalllog::use_stderr();
+                    error!("can't use syslog: {}", e);
+                }
+            }
             name => {
-                if let Err(e) = smalllog::use_file(name) {
+                if let Err(e) = smalllog::use_file(name, self.append.unwrap_or(false)) {
+                    // 以前の出力先が残らないよう、標準エラー出力に戻してから報告する。
+                    smalllog::use_stderr();
                     error!("can't open log file {}: {}", name, e);
                 }
             }
         }
 
         smalllog::set_level(self.level.unwrap_or_default().into());
+        smalllog::set_time_format(self.timestamp.unwrap_or_default().into());
     }
 
     pub fn merged(&self, overwrite: &Log) -> Log {
         Log {
             output: merge(&self.output, &overwrite.output),
+            append: merge(&self.append, &overwrite.append),
             level: merge(&self.level, &overwrite.level),
+            timestamp: merge(&self.timestamp, &overwrite.timestamp),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct RestoreSettings {
+    overwrite: Option<bool>,
+    remove: Option<bool>,
+    create_symlinks: Option<bool>,
+    case_collision: Option<CaseCollision>,
+    show_symlinks: Option<bool>,
+}
+
+impl RestoreSettings {
+    fn merged(&self, overwrite: &RestoreSettings) -> RestoreSettings {
+        RestoreSettings {
+            overwrite: merge(&self.overwrite, &overwrite.overwrite),
+            remove: merge(&self.remove, &overwrite.remove),
+            create_symlinks: merge(&self.create_symlinks, &overwrite.create_symlinks),
+            case_collision: merge(&self.case_collision, &overwrite.case_collision),
+            show_symlinks: merge(&self.show_symlinks, &overwrite.show_symlinks),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct HistorySettings {
+    show_count: Option<usize>,
+    timezone: Option<String>,
+}
+
+impl HistorySettings {
+    fn merged(&self, overwrite: &HistorySettings) -> HistorySettings {
+        HistorySettings {
+            show_count: merge(&self.show_count, &overwrite.show_count),
+            timezone: merge(&self.timezone, &overwrite.timezone),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct BankSettings {
+    schedule: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    exclude: Vec<String>,
+    ignore_syntax: Option<Syntax>,
+    content_index: Option<bool>,
+    path_index: Option<bool>,
+    alternate_streams: Option<bool>,
+    secondary_repository: Option<PathBuf>,
+}
+
+impl BankSettings {
+    fn merged(&self, overwrite: &BankSettings) -> BankSettings {
+        let mut exclude = self.exclude.clone();
+        exclude.extend_from_slice(&overwrite.exclude);
+
+        BankSettings {
+            schedule: merge(&self.schedule, &overwrite.schedule),
+            exclude,
+            ignore_syntax: merge(&self.ignore_syntax, &overwrite.ignore_syntax),
+            content_index: merge(&self.content_index, &overwrite.content_index),
+            path_index: merge(&self.path_index, &overwrite.path_index),
+            alternate_streams: merge(&self.alternate_streams, &overwrite.alternate_streams),
+            secondary_repository: merge(
+                &self.secondary_repository,
+                &overwrite.secondary_repository,
+            ),
+        }
+    }
+}
+
+/// 実行結果を通知するWebhookの設定
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Webhook {
+    url: String,
+    #[serde(default)]
+    on: NotifyOn,
+}
+
+impl Webhook {
+    /// 送信先のURLを返す。
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// 送信する条件を返す。
+    pub fn on(&self) -> NotifyOn {
+        self.on
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct MailSettings {
+    smtp_url: Option<String>,
+    from: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    to: Vec<String>,
+    username: Option<String>,
+    #[serde(skip_serializing)]
+    password: Option<String>,
+    starttls: Option<bool>,
+    on: Option<NotifyOn>,
+}
+
+impl MailSettings {
+    fn merged(&self, overwrite: &MailSettings) -> MailSettings {
+        let mut to = self.to.clone();
+        to.extend_from_slice(&overwrite.to);
+
+        MailSettings {
+            smtp_url: merge(&self.smtp_url, &overwrite.smtp_url),
+            from: merge(&self.from, &overwrite.from),
+            to,
+            username: merge(&self.username, &overwrite.username),
+            password: merge(&self.password, &overwrite.password),
+            starttls: merge(&self.starttls, &overwrite.starttls),
+            on: merge(&self.on, &overwrite.on),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct ServeSettings {
+    #[serde(skip_serializing)]
+    token: Option<String>,
+}
+
+impl ServeSettings {
+    fn merged(&self, overwrite: &ServeSettings) -> ServeSettings {
+        ServeSettings {
+            token: merge(&self.token, &overwrite.token),
+        }
+    }
+}
+
+/// 実行結果をメールで通知する設定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mail {
+    smtp_url: String,
+    from: String,
+    to: Vec<String>,
+    username: Option<String>,
+    password: Option<String>,
+    starttls: bool,
+    on: NotifyOn,
+}
+
+impl Mail {
+    /// SMTPサーバーのURL(`smtp://host:port`または`smtps://host:port`)を返す。
+    pub fn smtp_url(&self) -> &str {
+        &self.smtp_url
+    }
+
+    /// 送信元のアドレスを返す。
+    pub fn from(&self) -> &str {
+        &self.from
+    }
+
+    /// 送信先のアドレスの一覧を返す。
+    pub fn to(&self) -> &[String] {
+        &self.to
+    }
+
+    /// SMTP認証のユーザー名を返す。
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    /// SMTP認証のパスワードを返す。
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+
+    /// STARTTLSを必須とするかどうかを返す。
+    pub fn starttls(&self) -> bool {
+        self.starttls
+    }
+
+    /// 送信する条件を返す。
+    pub fn on(&self) -> NotifyOn {
+        self.on
+    }
+}
+
+/// ログの各行に付ける時刻の種類
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogTimestamp {
+    /// 付けない
+    #[default]
+    None,
+    /// 現在時刻
+    Wall,
+    /// 起動からの経過時間
+    Elapsed,
+    /// 現在時刻と経過時間
+    Both,
+}
+
+impl From<LogTimestamp> for smalllog::TimeFormat {
+    fn from(t: LogTimestamp) -> smalllog::TimeFormat {
+        match t {
+            LogTimestamp::None => smalllog::TimeFormat::None,
+            LogTimestamp::Wall => smalllog::TimeFormat::Wall,
+            LogTimestamp::Elapsed => smalllog::TimeFormat::Elapsed,
+            LogTimestamp::Both => smalllog::TimeFormat::Both,
         }
     }
 }
@@ -215,6 +924,18 @@ impl Default for LogLevel {
     }
 }
 
+impl LogLevel {
+    /// `-v`の指定回数に対応するレベルを返す。
+    fn from_verbosity(verbose: u64) -> LogLevel {
+        match verbose {
+            0 => LogLevel::Warn,
+            1 => LogLevel::Info,
+            2 => LogLevel::Debug,
+            _ => LogLevel::Trace,
+        }
+    }
+}
+
 impl FromStr for LogLevel {
     type Err = Error;
 
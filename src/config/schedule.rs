@@ -0,0 +1,240 @@
+//! Bankごとのバックアップ予定を扱う。
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Error, Result};
+use chrono::{Datelike, Duration, Local, NaiveDateTime, TimeZone, Timelike, Weekday};
+
+use crate::core::timestamp::Timestamp;
+
+#[cfg(test)]
+mod test;
+
+/// バックアップの予定
+///
+/// 設定ファイルには以下の形式で記述する。
+///
+/// | 形式 | 意味 |
+/// |:-----|:-----|
+/// | `every <N>m`, `every <N>h`, `every <N>d` | 前回のバックアップからN分/時間/日経過するごと |
+/// | `hourly [:MM]` | 毎時MM分 |
+/// | `daily [HH:MM]` | 毎日HH時MM分 |
+/// | `weekly <曜日> [HH:MM]` | 毎週指定曜日のHH時MM分 |
+///
+/// 時刻は実行環境のタイムゾーンで解釈され、省略した場合は0時0分になる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// 一定間隔ごと（秒数）
+    Every(u64),
+    /// 毎時`minute`分
+    Hourly {
+        /// 分
+        minute: u32,
+    },
+    /// 毎日`hour`時`minute`分
+    Daily {
+        /// 時
+        hour: u32,
+        /// 分
+        minute: u32,
+    },
+    /// 毎週`weekday`の`hour`時`minute`分
+    Weekly {
+        /// 曜日
+        weekday: Weekday,
+        /// 時
+        hour: u32,
+        /// 分
+        minute: u32,
+    },
+}
+
+impl Schedule {
+    /// 前回のバックアップ時刻が`last`のとき、時刻`now`の時点でバックアップを実行すべきかどうかを返す。
+    ///
+    /// `last`が`None`のとき（一度もバックアップしていないとき）は常に`true`を返す。
+    pub fn is_due(&self, last: Option<Timestamp>, now: Timestamp) -> bool {
+        self.is_due_in(&Local, last, now)
+    }
+
+    fn is_due_in<Z: TimeZone>(&self, zone: &Z, last: Option<Timestamp>, now: Timestamp) -> bool {
+        let last = match last {
+            Some(last) => last,
+            None => return true,
+        };
+
+        if let Schedule::Every(secs) = *self {
+            return now.unix_epoch().saturating_sub(last.unix_epoch()) >= secs;
+        }
+
+        // 夏時間の切り替えで時刻が重複・欠落しても判定が破綻しないように、地方時のまま比較する。
+        let now_local = local_time(zone, now);
+        let last_local = local_time(zone, last);
+        last_local < self.last_point(now_local)
+    }
+
+    // `now`以前で直近の予定時刻を返す。
+    fn last_point(&self, now: NaiveDateTime) -> NaiveDateTime {
+        let today = now.date();
+
+        let (point, period) = match *self {
+            Schedule::Every(_) => unreachable!("Schedule::Every has no fixed point"),
+            Schedule::Hourly { minute } => {
+                (today.and_hms(now.hour(), minute, 0), Duration::hours(1))
+            }
+            Schedule::Daily { hour, minute } => (today.and_hms(hour, minute, 0), Duration::days(1)),
+            Schedule::Weekly {
+                weekday,
+                hour,
+                minute,
+            } => {
+                let days_since =
+                    (7 + now.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+                let day = today - Duration::days(i64::from(days_since));
+                (day.and_hms(hour, minute, 0), Duration::weeks(1))
+            }
+        };
+
+        if point > now {
+            point - period
+        } else {
+            point
+        }
+    }
+
+    /// systemdのタイマーの`OnCalendar=`に指定する形式の予定を返す。
+    ///
+    /// 一定間隔の予定は固定の時刻を持たないため`None`を返す。
+    pub fn on_calendar(&self) -> Option<String> {
+        match *self {
+            Schedule::Every(_) => None,
+            Schedule::Hourly { minute } => Some(format!("*-*-* *:{:02}:00", minute)),
+            Schedule::Daily { hour, minute } => Some(format!("*-*-* {:02}:{:02}:00", hour, minute)),
+            Schedule::Weekly {
+                weekday,
+                hour,
+                minute,
+            } => Some(format!("{:?} *-*-* {:02}:{:02}:00", weekday, hour, minute)),
+        }
+    }
+}
+
+fn local_time<Z: TimeZone>(zone: &Z, t: Timestamp) -> NaiveDateTime {
+    zone.timestamp(t.unix_epoch() as i64, 0).naive_local()
+}
+
+impl FromStr for Schedule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let words = s.split_whitespace().collect::<Vec<&str>>();
+
+        let schedule = match words.as_slice() {
+            ["every", interval] => Schedule::Every(parse_interval(interval)?),
+            ["hourly"] => Schedule::Hourly { minute: 0 },
+            ["hourly", minute] => Schedule::Hourly {
+                minute: parse_minute(minute.trim_start_matches(':'))?,
+            },
+            ["daily"] => Schedule::Daily { hour: 0, minute: 0 },
+            ["daily", time] => {
+                let (hour, minute) = parse_time(time)?;
+                Schedule::Daily { hour, minute }
+            }
+            ["weekly", weekday] => Schedule::Weekly {
+                weekday: parse_weekday(weekday)?,
+                hour: 0,
+                minute: 0,
+            },
+            ["weekly", weekday, time] => {
+                let (hour, minute) = parse_time(time)?;
+                Schedule::Weekly {
+                    weekday: parse_weekday(weekday)?,
+                    hour,
+                    minute,
+                }
+            }
+            _ => return Err(Error::msg(format!("invalid schedule: '{}'", s))),
+        };
+
+        Ok(schedule)
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Schedule::Every(secs) => {
+                if secs % 86400 == 0 {
+                    write!(f, "every {}d", secs / 86400)
+                } else if secs % 3600 == 0 {
+                    write!(f, "every {}h", secs / 3600)
+                } else {
+                    write!(f, "every {}m", secs / 60)
+                }
+            }
+            Schedule::Hourly { minute } => write!(f, "hourly :{:02}", minute),
+            Schedule::Daily { hour, minute } => write!(f, "daily {:02}:{:02}", hour, minute),
+            Schedule::Weekly {
+                weekday,
+                hour,
+                minute,
+            } => write!(
+                f,
+                "weekly {} {:02}:{:02}",
+                format!("{:?}", weekday).to_lowercase(),
+                hour,
+                minute
+            ),
+        }
+    }
+}
+
+fn parse_interval(s: &str) -> Result<u64> {
+    let invalid = || Error::msg(format!("invalid interval: '{}'", s));
+
+    if s.len() < 2 {
+        return Err(invalid());
+    }
+    let (num, unit) = s.split_at(s.len() - 1);
+    let num: u64 = num.parse().map_err(|_| invalid())?;
+    let unit_secs = match unit {
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(invalid()),
+    };
+
+    if num == 0 {
+        return Err(invalid());
+    }
+    num.checked_mul(unit_secs).ok_or_else(invalid)
+}
+
+fn parse_time(s: &str) -> Result<(u32, u32)> {
+    let invalid = || Error::msg(format!("invalid time: '{}'", s));
+
+    let mut parts = s.splitn(2, ':');
+    let hour: u32 = parts
+        .next()
+        .and_then(|h| h.parse().ok())
+        .ok_or_else(invalid)?;
+    let minute = parse_minute(parts.next().ok_or_else(invalid)?)?;
+
+    if hour >= 24 {
+        return Err(invalid());
+    }
+    Ok((hour, minute))
+}
+
+fn parse_minute(s: &str) -> Result<u32> {
+    match s.parse() {
+        Ok(m) if m < 60 => Ok(m),
+        _ => Err(Error::msg(format!("invalid minute: '{}'", s))),
+    }
+}
+
+fn parse_weekday(s: &str) -> Result<Weekday> {
+    s.parse()
+        .map_err(|_| Error::msg(format!("invalid weekday: '{}'", s)))
+}
@@ -0,0 +1,109 @@
+use super::*;
+
+use chrono::Utc;
+
+fn epoch(s: &str) -> Timestamp {
+    let t = Utc.datetime_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+    Timestamp::from(t.timestamp() as u64)
+}
+
+#[test]
+fn test_parse_success() {
+    let cases = vec![
+        ("every 30m", Schedule::Every(1800)),
+        ("every 6h", Schedule::Every(6 * 3600)),
+        ("every 2d", Schedule::Every(2 * 86400)),
+        ("hourly", Schedule::Hourly { minute: 0 }),
+        ("hourly :15", Schedule::Hourly { minute: 15 }),
+        ("daily", Schedule::Daily { hour: 0, minute: 0 }),
+        ("daily 02:00", Schedule::Daily { hour: 2, minute: 0 }),
+        (
+            "weekly sun 23:30",
+            Schedule::Weekly {
+                weekday: Weekday::Sun,
+                hour: 23,
+                minute: 30,
+            },
+        ),
+    ];
+
+    for (input, to_be) in cases {
+        let actual: Schedule = input.parse().unwrap();
+        assert_eq!(to_be, actual, "input = {}", input);
+
+        let reparsed: Schedule = actual.to_string().parse().unwrap();
+        assert_eq!(to_be, reparsed, "input = {}", input);
+    }
+}
+
+#[test]
+fn test_parse_fails() {
+    let cases = vec![
+        "",
+        "every",
+        "every 0h",
+        "every 3x",
+        "every 999999999999999999d",
+        "every 18446744073709551615m",
+        "daily 24:00",
+        "daily 2",
+        "hourly :60",
+        "weekly",
+        "weekly xyz",
+        "monthly",
+    ];
+
+    for input in cases {
+        assert!(input.parse::<Schedule>().is_err(), "input = {}", input);
+    }
+}
+
+#[test]
+fn test_is_due() {
+    let daily: Schedule = "daily 02:00".parse().unwrap();
+    let weekly: Schedule = "weekly mon 02:00".parse().unwrap();
+    let every: Schedule = "every 6h".parse().unwrap();
+
+    // 2019-08-14 は水曜日
+    let cases = vec![
+        (daily, None, "2019-08-14 01:00", true),
+        (daily, Some("2019-08-14 02:30"), "2019-08-14 03:00", false),
+        (daily, Some("2019-08-13 02:30"), "2019-08-14 01:00", false),
+        (daily, Some("2019-08-13 02:30"), "2019-08-14 02:00", true),
+        (daily, Some("2019-08-12 02:30"), "2019-08-14 01:00", true),
+        (weekly, Some("2019-08-12 03:00"), "2019-08-18 23:00", false),
+        (weekly, Some("2019-08-12 03:00"), "2019-08-19 02:01", true),
+        (weekly, Some("2019-08-12 01:00"), "2019-08-14 00:00", true),
+        (every, Some("2019-08-14 00:00"), "2019-08-14 05:59", false),
+        (every, Some("2019-08-14 00:00"), "2019-08-14 06:00", true),
+    ];
+
+    for (schedule, last, now, to_be) in cases {
+        let actual = schedule.is_due_in(&Utc, last.map(epoch), epoch(now));
+        assert_eq!(
+            to_be, actual,
+            "schedule = {}, last = {:?}, now = {}",
+            schedule, last, now
+        );
+    }
+}
+
+#[test]
+fn test_on_calendar() {
+    let cases = vec![
+        ("every 6h", None),
+        ("hourly :15", Some("*-*-* *:15:00")),
+        ("daily 02:00", Some("*-*-* 02:00:00")),
+        ("weekly sun 23:30", Some("Sun *-*-* 23:30:00")),
+    ];
+
+    for (input, to_be) in cases {
+        let schedule: Schedule = input.parse().unwrap();
+        assert_eq!(
+            to_be,
+            schedule.on_calendar().as_deref(),
+            "input = {}",
+            input
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use super::*;
+
+fn updated(content: &str) -> String {
+    with_repository_path(content, Path::new("/backup/sbak_repository")).unwrap()
+}
+
+#[test]
+fn test_with_repository_path_empty() {
+    assert_eq!(
+        updated(""),
+        "repository_path = \"/backup/sbak_repository\"\n"
+    );
+}
+
+#[test]
+fn test_with_repository_path_replace() {
+    let content = "# comment\nrepository_path = '/old'\nlang = 'ja'\n\n[log]\nlevel = 'warn'\n";
+    assert_eq!(
+        updated(content),
+        "# comment\nrepository_path = \"/backup/sbak_repository\"\nlang = 'ja'\n\n[log]\nlevel = 'warn'\n"
+    );
+}
+
+#[test]
+fn test_with_repository_path_commented() {
+    let content = "# Path of the backup repository.\n#repository_path = '/backup/sbak_repository'\n\n[log]\noutput = 'stderr'\n";
+    assert_eq!(
+        updated(content),
+        "# Path of the backup repository.\nrepository_path = \"/backup/sbak_repository\"\n\n[log]\noutput = 'stderr'\n"
+    );
+}
+
+#[test]
+fn test_with_repository_path_insert() {
+    // テーブル内の同名のキーは置き換えない
+    let content = "[banks.home]\nrepository_path = '/other'\n";
+    let config: Config = from_slice(updated(content).as_bytes()).unwrap();
+    assert_eq!(
+        config.repository_path(),
+        Some(Path::new("/backup/sbak_repository"))
+    );
+    assert!(updated(content).ends_with(content));
+}
+
+#[test]
+fn test_merged_repository() {
+    let temp = tempfile::tempdir().unwrap();
+    let repo_a = temp.path().join("A");
+    let repo_b = temp.path().join("B");
+    fs::create_dir_all(&repo_a).unwrap();
+    fs::create_dir_all(&repo_b).unwrap();
+    fs::write(
+        repository_config_path(&repo_a),
+        "repository_path = '/elsewhere'\n[restore]\ncreate_symlinks = true\n",
+    )
+    .unwrap();
+
+    let mut base = Config::default();
+    base.set_repository_path(&repo_a);
+
+    let config = base.merged_repository(&repo_a).unwrap();
+    assert_eq!(config.repository_path(), Some(repo_a.as_path()));
+    assert!(config.restore_create_symlinks());
+    assert_eq!(config.loaded_pathes(), &[repository_config_path(&repo_a)]);
+
+    // 他のリポジトリを操作する場合は、設定ファイルのリポジトリの設定を読み込まない
+    let config = base.merged_repository(&repo_b).unwrap();
+    assert_eq!(config.repository_path(), Some(repo_b.as_path()));
+    assert!(!config.restore_create_symlinks());
+    assert!(config.loaded_pathes().is_empty());
+}
+
+#[test]
+fn test_merged_repository_ignores_destructive_restore() {
+    let temp = tempfile::tempdir().unwrap();
+    let repo = temp.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(
+        repository_config_path(&repo),
+        "[restore]\noverwrite = true\nremove = true\ncase_collision = 'skip'\n",
+    )
+    .unwrap();
+
+    // リポジトリ内の設定では有効にできない
+    let config = Config::default().merged_repository(&repo).unwrap();
+    assert!(!config.restore_overwrite());
+    assert!(!config.restore_remove());
+    assert_eq!(config.restore_case_collision(), CaseCollision::Skip);
+
+    // ユーザー設定で有効にしたものを、リポジトリ内の設定で無効にすることもできない
+    let user: Config = toml::from_str("[restore]\noverwrite = true\nremove = true\n").unwrap();
+    fs::write(
+        repository_config_path(&repo),
+        "[restore]\noverwrite = false\nremove = false\n",
+    )
+    .unwrap();
+    let config = user.merged_repository(&repo).unwrap();
+    assert!(config.restore_overwrite());
+    assert!(config.restore_remove());
+}
+
+#[test]
+fn test_serve_token() {
+    let mut config: Config = toml::from_str("[serve]\ntoken = 's3cret'\n").unwrap();
+    config.disable_keyring();
+    assert_eq!(config.serve_token().as_deref(), Some("s3cret"));
+    // 設定値は表示しない
+    assert!(!to_string_pretty(&config).unwrap().contains("s3cret"));
+
+    let mut config: Config = toml::from_str("[serve]\ntoken = ''\n").unwrap();
+    config.disable_keyring();
+    assert_eq!(config.serve_token(), None);
+}
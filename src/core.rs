@@ -1,9 +1,22 @@
 //! バックアップシステムのコア部分
 
+pub mod diff;
+pub mod encoding;
 pub mod entry;
+pub mod export;
 pub mod extend;
+pub mod growth;
 pub mod hash;
 pub mod ignore;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod parity;
+pub mod remote;
+pub mod replicate;
 pub mod repo;
 pub mod scan;
+pub mod span;
 pub mod timestamp;
+pub mod upgrade;
+pub mod verify;
+pub mod walk;
@@ -0,0 +1,207 @@
+//! 2つの履歴の間でのファイルの変更を比較する。
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::core::entry::{DirEntry, FsHash};
+use crate::core::hash::HashID;
+use crate::core::repo::{Bank, Error};
+use crate::core::scan::ChangeKind;
+
+#[cfg(test)]
+mod test;
+
+/// 履歴間でのファイルやシンボリックリンクの変更
+///
+/// ディレクトリ自体は含まず、追加・削除されたディレクトリの中身が1つずつ含まれる。
+/// ただし中身の無いディレクトリの追加・削除は、パスの末尾に`/`を付けたディレクトリ自体の変更として含まれる。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TreeChange {
+    /// 変更の種類
+    ///
+    /// 内容や代替データストリームが変わった場合を`Updated`とし、更新日時などの属性のみの変更は含まない。
+    pub kind: ChangeKind,
+    /// バックアップ対象ディレクトリからの`/`区切りの相対パス
+    pub path: String,
+    /// 古い履歴でのバイト数
+    ///
+    /// 追加されたものは`None`、シンボリックリンクと空のディレクトリは0になる。
+    pub old_size: Option<u64>,
+    /// 新しい履歴でのバイト数
+    ///
+    /// 削除されたものは`None`、シンボリックリンクと空のディレクトリは0になる。
+    pub new_size: Option<u64>,
+}
+
+impl TreeChange {
+    /// 変更によるバイト数の増減を返す。
+    pub fn delta(&self) -> i64 {
+        self.new_size.unwrap_or(0) as i64 - self.old_size.unwrap_or(0) as i64
+    }
+}
+
+/// 最上位のディレクトリごとの変更の集計
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DirStat {
+    /// 最上位のディレクトリの名前
+    ///
+    /// バックアップ対象ディレクトリの直下のファイルは`.`に集計する。
+    pub dir: String,
+    /// 追加されたファイルの数
+    pub added: usize,
+    /// 更新されたファイルの数
+    pub updated: usize,
+    /// 削除されたファイルの数
+    pub removed: usize,
+    /// バイト数の増減
+    pub delta: i64,
+}
+
+/// ルートのIDが`old`と`new`のツリーを比較し、変更をパスの順に返す。
+///
+/// IDが一致するディレクトリは中を辿らない。
+pub fn diff_trees(bank: &Bank, old: &HashID, new: &HashID) -> Result<Vec<TreeChange>, Error> {
+    let mut changes = Vec::new();
+    if old != new {
+        let old_dir = bank.load_dir(old)?;
+        let new_dir = bank.load_dir(new)?;
+        diff_dir(bank, "", &old_dir, &new_dir, &mut changes)?;
+    }
+    Ok(changes)
+}
+
+/// 変更を最上位のディレクトリごとに集計し、ディレクトリの名前の順に返す。
+pub fn stat(changes: &[TreeChange]) -> Vec<DirStat> {
+    let mut stats = BTreeMap::<&str, DirStat>::new();
+    for change in changes {
+        let dir = match change.path.find('/') {
+            Some(i) => &change.path[..i],
+            None => ".",
+        };
+        let stat = stats.entry(dir).or_insert_with(|| DirStat {
+            dir: dir.to_owned(),
+            ..DirStat::default()
+        });
+        match change.kind {
+            ChangeKind::Added => stat.added += 1,
+            ChangeKind::Updated => stat.updated += 1,
+            ChangeKind::Removed => stat.removed += 1,
+        }
+        stat.delta += change.delta();
+    }
+    stats.into_values().collect()
+}
+
+fn diff_dir(
+    bank: &Bank,
+    path: &str,
+    old: &DirEntry,
+    new: &DirEntry,
+    changes: &mut Vec<TreeChange>,
+) -> Result<(), Error> {
+    let mut pairs = BTreeMap::<&str, (Option<&FsHash>, Option<&FsHash>)>::new();
+    for ch in old.children() {
+        pairs.entry(ch.attr().name()).or_default().0 = Some(ch);
+    }
+    for ch in new.children() {
+        pairs.entry(ch.attr().name()).or_default().1 = Some(ch);
+    }
+
+    for (name, pair) in pairs {
+        let ch_path = join(path, name);
+        match pair {
+            (Some(FsHash::Dir(o)), Some(FsHash::Dir(n))) => {
+                if o.id() != n.id() {
+                    let old_dir = bank.load_dir(&o.id())?;
+                    let new_dir = bank.load_dir(&n.id())?;
+                    diff_dir(bank, &ch_path, &old_dir, &new_dir, changes)?;
+                }
+            }
+            (Some(o), Some(n)) if !is_dir(o) && !is_dir(n) => {
+                if !same_content(o, n) {
+                    changes.push(TreeChange {
+                        kind: ChangeKind::Updated,
+                        path: ch_path,
+                        old_size: Some(entry_size(bank, o)?),
+                        new_size: Some(entry_size(bank, n)?),
+                    });
+                }
+            }
+            (o, n) => {
+                // 種類が変わったものは、削除と追加として扱う
+                if let Some(o) = o {
+                    push_all(bank, &ch_path, o, ChangeKind::Removed, changes)?;
+                }
+                if let Some(n) = n {
+                    push_all(bank, &ch_path, n, ChangeKind::Added, changes)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// `entry`とその中身を全て、`kind`の変更として追加する。
+//
+// 中身の無いディレクトリは、末尾に`/`を付けたパスで追加する。
+fn push_all(
+    bank: &Bank,
+    path: &str,
+    entry: &FsHash,
+    kind: ChangeKind,
+    changes: &mut Vec<TreeChange>,
+) -> Result<(), Error> {
+    let mut path = path.to_owned();
+    if let FsHash::Dir(d) = entry {
+        let dir = bank.load_dir(&d.id())?;
+        if dir.children().next().is_some() {
+            for ch in dir.children() {
+                push_all(bank, &join(&path, ch.attr().name()), ch, kind, changes)?;
+            }
+            return Ok(());
+        }
+        path.push('/');
+    }
+
+    let size = Some(entry_size(bank, entry)?);
+    let (old_size, new_size) = match kind {
+        ChangeKind::Removed => (size, None),
+        _ => (None, size),
+    };
+    changes.push(TreeChange {
+        kind,
+        path,
+        old_size,
+        new_size,
+    });
+    Ok(())
+}
+
+fn same_content(old: &FsHash, new: &FsHash) -> bool {
+    match (old, new) {
+        (FsHash::File(o), FsHash::File(n)) => o.id() == n.id() && o.streams() == n.streams(),
+        (FsHash::Symlink(o), FsHash::Symlink(n)) => o.id() == n.id(),
+        _ => false,
+    }
+}
+
+fn entry_size(bank: &Bank, entry: &FsHash) -> Result<u64, Error> {
+    match entry {
+        FsHash::File(f) => bank.object_size(&f.id()),
+        _ => Ok(0),
+    }
+}
+
+fn is_dir(entry: &FsHash) -> bool {
+    matches!(entry, FsHash::Dir(_))
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}/{}", path, name)
+    }
+}
@@ -0,0 +1,148 @@
+use super::*;
+
+use std::fs;
+use std::path::Path;
+
+use filetime::{set_file_mtime, FileTime};
+use tempfile::tempdir;
+
+use crate::core::repo::Repository;
+use crate::core::scan::Scanner;
+
+// 内容を書き換え、前回のスキャンと更新日時が重ならないようにする。
+fn write(path: &Path, content: &str) {
+    fs::write(path, content).unwrap();
+    set_file_mtime(path, FileTime::from_unix_time(1_000_000, 0)).unwrap();
+}
+
+fn change(kind: ChangeKind, path: &str, old: Option<u64>, new: Option<u64>) -> TreeChange {
+    TreeChange {
+        kind,
+        path: path.to_owned(),
+        old_size: old,
+        new_size: new,
+    }
+}
+
+#[test]
+fn test_diff_trees() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("docs/old")).unwrap();
+    fs::create_dir_all(target.join("same")).unwrap();
+    fs::write(target.join("top.txt"), "top").unwrap();
+    fs::write(target.join("docs/a.txt"), "a").unwrap();
+    fs::write(target.join("docs/old/b.txt"), "bb").unwrap();
+    fs::write(target.join("same/c.txt"), "c").unwrap();
+    fs::write(target.join("kind"), "file").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let old = Scanner::new(&bank).scan().unwrap().id();
+
+    write(&target.join("docs/a.txt"), "aaaa");
+    fs::remove_dir_all(target.join("docs/old")).unwrap();
+    fs::create_dir_all(target.join("docs/new")).unwrap();
+    fs::write(target.join("docs/new/d.txt"), "dddddd").unwrap();
+    fs::remove_file(target.join("kind")).unwrap();
+    fs::create_dir_all(target.join("kind")).unwrap();
+    fs::write(target.join("kind/e.txt"), "e").unwrap();
+    let new = Scanner::new(&bank).scan().unwrap().id();
+
+    let changes = diff_trees(&bank, &old, &new).unwrap();
+    assert_eq!(
+        changes,
+        vec![
+            change(ChangeKind::Updated, "docs/a.txt", Some(1), Some(4)),
+            change(ChangeKind::Added, "docs/new/d.txt", None, Some(6)),
+            change(ChangeKind::Removed, "docs/old/b.txt", Some(2), None),
+            change(ChangeKind::Removed, "kind", Some(4), None),
+            change(ChangeKind::Added, "kind/e.txt", None, Some(1)),
+        ]
+    );
+
+    assert!(diff_trees(&bank, &new, &new).unwrap().is_empty());
+    let reversed = diff_trees(&bank, &new, &old).unwrap();
+    assert_eq!(
+        reversed[0],
+        change(ChangeKind::Updated, "docs/a.txt", Some(4), Some(1))
+    );
+    assert_eq!(reversed[1].kind, ChangeKind::Removed);
+}
+
+#[test]
+fn test_diff_trees_empty_dir() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("removed")).unwrap();
+    fs::create_dir_all(target.join("docs/emptied")).unwrap();
+    fs::write(target.join("docs/emptied/a.txt"), "a").unwrap();
+    fs::write(target.join("kind"), "file").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let old = Scanner::new(&bank).scan().unwrap().id();
+
+    fs::remove_dir(target.join("removed")).unwrap();
+    fs::create_dir_all(target.join("docs/added/nested")).unwrap();
+    fs::remove_file(target.join("docs/emptied/a.txt")).unwrap();
+    fs::remove_file(target.join("kind")).unwrap();
+    fs::create_dir_all(target.join("kind")).unwrap();
+    let new = Scanner::new(&bank).scan().unwrap().id();
+
+    let changes = diff_trees(&bank, &old, &new).unwrap();
+    assert_eq!(
+        changes,
+        vec![
+            // 空のディレクトリだけを含むディレクトリは、その中の空のディレクトリとして表れる
+            change(ChangeKind::Added, "docs/added/nested/", None, Some(0)),
+            change(ChangeKind::Removed, "docs/emptied/a.txt", Some(1), None),
+            change(ChangeKind::Removed, "kind", Some(4), None),
+            change(ChangeKind::Added, "kind/", None, Some(0)),
+            change(ChangeKind::Removed, "removed/", Some(0), None),
+        ]
+    );
+    assert_eq!(
+        stat(&changes)
+            .iter()
+            .map(|s| (s.dir.as_str(), s.added, s.removed))
+            .collect::<Vec<_>>(),
+        vec![
+            (".", 0, 1),
+            ("docs", 1, 1),
+            ("kind", 1, 0),
+            ("removed", 0, 1)
+        ]
+    );
+}
+
+#[test]
+fn test_stat() {
+    let changes = vec![
+        change(ChangeKind::Updated, "docs/a.txt", Some(1), Some(4)),
+        change(ChangeKind::Added, "docs/new/d.txt", None, Some(6)),
+        change(ChangeKind::Removed, "docs/old/b.txt", Some(2), None),
+        change(ChangeKind::Removed, "top.txt", Some(10), None),
+    ];
+    assert_eq!(
+        stat(&changes),
+        vec![
+            DirStat {
+                dir: ".".to_owned(),
+                added: 0,
+                updated: 0,
+                removed: 1,
+                delta: -10,
+            },
+            DirStat {
+                dir: "docs".to_owned(),
+                added: 1,
+                updated: 1,
+                removed: 1,
+                delta: 7,
+            },
+        ]
+    );
+}
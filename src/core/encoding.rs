@@ -0,0 +1,297 @@
+//! エントリと履歴の保存形式を扱う。
+//!
+//! 保存形式はJSONとCBOR([RFC 8949](https://www.rfc-editor.org/rfc/rfc8949))から選べる。
+//! CBORで保存したデータは先頭に自己記述タグ(`0xd9d9f7`)を持つため、読み込み時はリポジトリの設定によらず内容から形式を判別する。
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+
+#[cfg(test)]
+mod test;
+
+// CBORの自己記述タグ55799
+const CBOR_MAGIC: &[u8; 3] = &[0xd9, 0xd9, 0xf7];
+
+// 入れ子の深さの上限。serde_jsonの既定値に合わせる。
+const MAX_DEPTH: usize = 128;
+
+/// エントリと履歴の保存形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    /// JSON
+    #[default]
+    Json,
+    /// CBOR
+    Cbor,
+}
+
+impl Encoding {
+    /// `value`をこの形式で符号化する。
+    pub fn encode<T: Serialize>(self, value: &T) -> serde_json::Result<Vec<u8>> {
+        match self {
+            Encoding::Json => serde_json::to_vec(value),
+            Encoding::Cbor => {
+                let value = serde_json::to_value(value)?;
+                let mut out = CBOR_MAGIC.to_vec();
+                write_cbor(&value, &mut out);
+                Ok(out)
+            }
+        }
+    }
+
+    /// 符号化されたデータ`bytes`の形式を返す。
+    pub fn detect(bytes: &[u8]) -> Encoding {
+        if bytes.starts_with(CBOR_MAGIC) {
+            Encoding::Cbor
+        } else {
+            Encoding::Json
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Encoding::Json => write!(f, "json"),
+            Encoding::Cbor => write!(f, "cbor"),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Encoding, String> {
+        match s {
+            "json" => Ok(Encoding::Json),
+            "cbor" => Ok(Encoding::Cbor),
+            _ => Err(format!("unknown encoding: {}", s)),
+        }
+    }
+}
+
+/// JSONまたはCBORで符号化された`bytes`を復号する。
+///
+/// 形式は[`Encoding::detect`](enum.Encoding.html#method.detect)で判別する。
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> serde_json::Result<T> {
+    match Encoding::detect(bytes) {
+        Encoding::Json => serde_json::from_slice(bytes),
+        Encoding::Cbor => {
+            let mut decoder = Decoder {
+                bytes: &bytes[CBOR_MAGIC.len()..],
+                depth: 0,
+            };
+            let value = decoder.read_value()?;
+            if !decoder.bytes.is_empty() {
+                return Err(serde_json::Error::custom("trailing bytes after CBOR value"));
+            }
+            serde_json::from_value(value)
+        }
+    }
+}
+
+// CBORの主要型
+const MAJOR_UINT: u8 = 0;
+const MAJOR_NEGATIVE: u8 = 1;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_SIMPLE: u8 = 7;
+
+const FALSE: u8 = 0xf4;
+const TRUE: u8 = 0xf5;
+const NULL: u8 = 0xf6;
+const FLOAT32: u8 = 0xfa;
+const FLOAT64: u8 = 0xfb;
+
+fn write_cbor(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(NULL),
+        Value::Bool(false) => out.push(FALSE),
+        Value::Bool(true) => out.push(TRUE),
+        Value::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                write_head(MAJOR_UINT, u, out);
+            } else if let Some(i) = n.as_i64() {
+                // 負の整数-1-nはnとして符号化する
+                write_head(MAJOR_NEGATIVE, !i as u64, out);
+            } else {
+                out.push(FLOAT64);
+                out.extend_from_slice(&n.as_f64().unwrap_or(0.0).to_be_bytes());
+            }
+        }
+        Value::String(s) => {
+            write_head(MAJOR_TEXT, s.len() as u64, out);
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Array(items) => {
+            write_head(MAJOR_ARRAY, items.len() as u64, out);
+            for item in items {
+                write_cbor(item, out);
+            }
+        }
+        Value::Object(map) => {
+            write_head(MAJOR_MAP, map.len() as u64, out);
+            for (k, v) in map {
+                write_head(MAJOR_TEXT, k.len() as u64, out);
+                out.extend_from_slice(k.as_bytes());
+                write_cbor(v, out);
+            }
+        }
+    }
+}
+
+// 主要型と引数を最短の形式で書き込む。
+fn write_head(major: u8, arg: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    if arg < 24 {
+        out.push(major | arg as u8);
+    } else if arg <= u64::from(u8::MAX) {
+        out.push(major | 24);
+        out.push(arg as u8);
+    } else if arg <= u64::from(u16::MAX) {
+        out.push(major | 25);
+        out.extend_from_slice(&(arg as u16).to_be_bytes());
+    } else if arg <= u64::from(u32::MAX) {
+        out.push(major | 26);
+        out.extend_from_slice(&(arg as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&arg.to_be_bytes());
+    }
+}
+
+// `write_cbor`で書き込む範囲のCBORを読み込む。
+//
+// 長さ不定のデータやバイト列、タグには対応しない。
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    depth: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn read_value(&mut self) -> serde_json::Result<Value> {
+        let initial = self.take(1)?[0];
+        let major = initial >> 5;
+        let info = initial & 0x1f;
+
+        if major == MAJOR_SIMPLE {
+            return match initial {
+                FALSE => Ok(Value::Bool(false)),
+                TRUE => Ok(Value::Bool(true)),
+                NULL => Ok(Value::Null),
+                FLOAT32 => {
+                    let f = f32::from_be_bytes(self.take_array()?);
+                    Ok(float_value(f64::from(f)))
+                }
+                FLOAT64 => {
+                    let f = f64::from_be_bytes(self.take_array()?);
+                    Ok(float_value(f))
+                }
+                _ => Err(invalid(format!(
+                    "unsupported simple value 0x{:02x}",
+                    initial
+                ))),
+            };
+        }
+
+        let arg = self.read_arg(info)?;
+        match major {
+            MAJOR_UINT => Ok(Value::Number(arg.into())),
+            MAJOR_NEGATIVE => {
+                if arg > i64::MAX as u64 {
+                    return Err(invalid("negative integer out of range".to_owned()));
+                }
+                Ok(Value::Number((!(arg as i64)).into()))
+            }
+            MAJOR_TEXT => Ok(Value::String(self.read_text(arg)?)),
+            MAJOR_ARRAY => {
+                self.enter()?;
+                let mut items = Vec::with_capacity(self.capacity(arg));
+                for _ in 0..arg {
+                    items.push(self.read_value()?);
+                }
+                self.depth -= 1;
+                Ok(Value::Array(items))
+            }
+            MAJOR_MAP => {
+                self.enter()?;
+                let mut map = Map::new();
+                for _ in 0..arg {
+                    let initial = self.take(1)?[0];
+                    if initial >> 5 != MAJOR_TEXT {
+                        return Err(invalid("map key is not text".to_owned()));
+                    }
+                    let len = self.read_arg(initial & 0x1f)?;
+                    let key = self.read_text(len)?;
+                    let value = self.read_value()?;
+                    map.insert(key, value);
+                }
+                self.depth -= 1;
+                Ok(Value::Object(map))
+            }
+            _ => Err(invalid(format!("unsupported major type {}", major))),
+        }
+    }
+
+    fn read_arg(&mut self, info: u8) -> serde_json::Result<u64> {
+        match info {
+            0..=23 => Ok(u64::from(info)),
+            24 => Ok(u64::from(self.take(1)?[0])),
+            25 => Ok(u64::from(u16::from_be_bytes(self.take_array()?))),
+            26 => Ok(u64::from(u32::from_be_bytes(self.take_array()?))),
+            27 => Ok(u64::from_be_bytes(self.take_array()?)),
+            _ => Err(invalid(format!("unsupported additional info {}", info))),
+        }
+    }
+
+    fn read_text(&mut self, len: u64) -> serde_json::Result<String> {
+        let len = usize::try_from(len).map_err(|_| invalid("text too long".to_owned()))?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("text is not UTF-8".to_owned()))
+    }
+
+    fn enter(&mut self) -> serde_json::Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(invalid("nesting too deep".to_owned()));
+        }
+        Ok(())
+    }
+
+    // 壊れたデータで巨大な領域を確保しないよう、残りのバイト数で上限を設ける。
+    fn capacity(&self, len: u64) -> usize {
+        usize::try_from(len)
+            .unwrap_or(usize::MAX)
+            .min(self.bytes.len())
+    }
+
+    fn take(&mut self, len: usize) -> serde_json::Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(serde_json::Error::custom("unexpected end of CBOR data"));
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> serde_json::Result<[u8; N]> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+}
+
+fn float_value(f: f64) -> Value {
+    Number::from_f64(f).map_or(Value::Null, Value::Number)
+}
+
+fn invalid(msg: String) -> serde_json::Error {
+    serde_json::Error::custom(format!("invalid CBOR data: {}", msg))
+}
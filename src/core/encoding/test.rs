@@ -0,0 +1,75 @@
+use super::*;
+
+use serde_json::json;
+
+use crate::core::entry::{Attributes, DirEntry, DirEntryBuilder};
+use crate::core::timestamp::Timestamp;
+
+#[test]
+fn test_cbor_round_trip() {
+    let value = json!({
+        "name": "日本語.txt",
+        "small": 23,
+        "u8": 24,
+        "u16": 1000,
+        "u32": 100_000,
+        "u64": u64::MAX,
+        "negative": -1,
+        "min": i64::MIN,
+        "float": 1.5,
+        "flags": [true, false, null],
+        "empty": {},
+    });
+
+    let encoded = Encoding::Cbor.encode(&value).unwrap();
+    assert_eq!(Encoding::detect(&encoded), Encoding::Cbor);
+    assert_eq!(decode::<Value>(&encoded).unwrap(), value);
+}
+
+#[test]
+fn test_cbor_bytes() {
+    let encoded = Encoding::Cbor.encode(&json!({"a": [1, -1, 500]})).unwrap();
+    assert_eq!(
+        encoded,
+        vec![0xd9, 0xd9, 0xf7, 0xa1, 0x61, b'a', 0x83, 0x01, 0x20, 0x19, 0x01, 0xf4]
+    );
+}
+
+#[test]
+fn test_decode_entry() {
+    let attr = Attributes::new("dir".to_owned(), false, Timestamp::from(1_000_000_000));
+    let dir: DirEntry = DirEntryBuilder::new(attr).build();
+
+    for encoding in &[Encoding::Json, Encoding::Cbor] {
+        let encoded = encoding.encode(&dir).unwrap();
+        assert_eq!(Encoding::detect(&encoded), *encoding);
+        let decoded: DirEntry = decode(&encoded).unwrap();
+        assert_eq!(decoded, dir);
+    }
+
+    // CBORの方が小さい
+    let json = Encoding::Json.encode(&dir).unwrap();
+    let cbor = Encoding::Cbor.encode(&dir).unwrap();
+    assert!(cbor.len() < json.len(), "{} >= {}", cbor.len(), json.len());
+}
+
+#[test]
+fn test_decode_invalid() {
+    // 途中で切れている
+    let encoded = Encoding::Cbor.encode(&json!({"a": "text"})).unwrap();
+    assert!(decode::<Value>(&encoded[..encoded.len() - 1]).is_err());
+
+    // 余分なデータ
+    let mut trailing = encoded.clone();
+    trailing.push(0x00);
+    assert!(decode::<Value>(&trailing).is_err());
+
+    // 長さ不定の配列
+    assert!(decode::<Value>(&[0xd9, 0xd9, 0xf7, 0x9f, 0xff]).is_err());
+
+    // 深すぎる入れ子
+    let mut deep = CBOR_MAGIC.to_vec();
+    deep.extend(std::iter::repeat_n(0x81, MAX_DEPTH + 1));
+    deep.push(0x00);
+    assert!(decode::<Value>(&deep).is_err());
+}
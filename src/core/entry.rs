@@ -1,15 +1,53 @@
 //!ファイルシステムのスキャン結果の表現
+//!
+//! リポジトリにオブジェクトとして保存されるエントリ([`DirEntry`](struct.DirEntry.html)と[`SymlinkEntry`](struct.SymlinkEntry.html))はスキーマのバージョンを持つ。
+//!
+//! | バージョン | 内容 |
+//! |:-----|:-----|
+//! | 0 | `version`フィールドを持たない初期の形式 |
+//! | 1 | `version`フィールドを追加 |
+//!
+//! 古いバージョンのエントリもそのまま読み込めるが、`upgrade`サブコマンドで現行のバージョンに書き換えられる。
 
 use std::convert::{TryFrom, TryInto};
+use std::error;
+use std::fmt;
 use std::path::PathBuf;
 
-use failure::Fail;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
 use crate::core::hash::HashID;
 use crate::core::timestamp::Timestamp;
 
+/// 現行のエントリのスキーマのバージョン
+///
+/// 古いバージョンのプログラムが読み飛ばすと誤った結果になるフィールドを追加した場合に上げる。
+/// 省略可能なフィールドの追加では上げない。未知のフィールドは読み込み時に無視される。
+pub const ENTRY_VERSION: u32 = 1;
+
+/// 子のリストを分割したディレクトリエントリのバージョン
+///
+/// 分割を知らないプログラムが空のディレクトリと誤認しないよう、通常のエントリより大きくしている。
+pub const CHUNKED_DIR_VERSION: u32 = 2;
+
+/// このプログラムが読み込めるエントリのバージョンの上限
+pub const MAX_ENTRY_VERSION: u32 = CHUNKED_DIR_VERSION;
+
+/// 子のリストを分割して保存するディレクトリの子の数の下限
+pub const CHUNK_THRESHOLD: usize = 4096;
+
+// 分割した部分の子の数の平均と上限
+//
+// 分割位置は子の名前のハッシュ値で決めるため、子の追加や削除は周囲の分割位置に影響しない。
+const CHUNK_TARGET: u64 = 1024;
+const CHUNK_MAX: usize = 4096;
+
+/// 記録するWindowsのファイル属性 (隠しファイル、システム、アーカイブ、圧縮)
+///
+/// 読み込み専用属性は`readonly`として別に記録する。
+pub const FILE_ATTRIBUTE_MASK: u32 = 0x2 | 0x4 | 0x20 | 0x800;
+
 /// ファイルシステムの1エントリの表現
 pub trait Entry: Serialize + DeserializeOwned {
     /// ハッシュ値によるIDを返す
@@ -18,6 +56,18 @@ pub trait Entry: Serialize + DeserializeOwned {
     fn set_id(&mut self, id: HashID);
     /// このエントリの属性を返す
     fn attr(&self) -> &Attributes;
+    /// このエントリのスキーマのバージョンを返す
+    fn version(&self) -> u32;
+
+    /// 別のオブジェクトに分割して保存された子のリストのIDを返す。
+    fn chunk_ids(&self) -> Vec<HashID> {
+        Vec::new()
+    }
+
+    /// 分割して保存された子のリストを読み込んだ結果を設定する。
+    ///
+    /// `chunks`は[`chunk_ids`](#method.chunk_ids)と同じ順序で渡す。
+    fn set_chunks(&mut self, _chunks: Vec<DirChunk>) {}
 }
 
 /// エントリの実表現
@@ -70,20 +120,68 @@ impl Entry for FsEntry {
             FsEntry::Symlink(ref x) => x.attr(),
         }
     }
+
+    fn version(&self) -> u32 {
+        match self {
+            FsEntry::Dir(ref x) => x.version(),
+            FsEntry::File(ref x) => x.version(),
+            FsEntry::Symlink(ref x) => x.version(),
+        }
+    }
 }
 
 /// ディレクトリの各種情報
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DirEntry {
+    #[serde(default)]
+    version: u32,
     #[serde(skip_serializing)]
     #[serde(default)]
     id: Option<HashID>,
     attr: Attributes,
     #[serde(default)]
     children: Vec<FsHash>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    chunks: Vec<ChunkRef>,
 }
 
 impl DirEntry {
+    /// 子の数が[`CHUNK_THRESHOLD`](constant.CHUNK_THRESHOLD.html)以上であれば、子のリストを分割して返す。
+    ///
+    /// 分割位置は子の名前から決まるため、子の一部が変わっても他の部分は同じ内容になる。
+    pub fn split_children(&self) -> Option<Vec<DirChunk>> {
+        if self.children.len() < CHUNK_THRESHOLD {
+            return None;
+        }
+
+        let mut chunks = Vec::new();
+        let mut current = Vec::new();
+        for ch in &self.children {
+            current.push(ch.clone());
+            if name_hash(ch.attr().name()).is_multiple_of(CHUNK_TARGET)
+                || current.len() >= CHUNK_MAX
+            {
+                chunks.push(DirChunk::new(current));
+                current = Vec::new();
+            }
+        }
+        if !current.is_empty() {
+            chunks.push(DirChunk::new(current));
+        }
+        Some(chunks)
+    }
+
+    /// 子のリストを、分割して保存した`chunks`への参照に置き換えたエントリを返す。
+    pub fn with_chunk_refs(&self, chunks: Vec<ChunkRef>) -> DirEntry {
+        DirEntry {
+            version: CHUNKED_DIR_VERSION,
+            id: None,
+            attr: self.attr.clone(),
+            children: Vec::new(),
+            chunks,
+        }
+    }
+
     /// 子エントリのイテレータを返す。
     pub fn children(&self) -> impl Iterator<Item = &FsHash> {
         self.children.iter()
@@ -128,6 +226,66 @@ impl Entry for DirEntry {
     fn attr(&self) -> &Attributes {
         &self.attr
     }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn chunk_ids(&self) -> Vec<HashID> {
+        self.chunks.iter().map(|c| c.id.clone()).collect()
+    }
+
+    fn set_chunks(&mut self, chunks: Vec<DirChunk>) {
+        self.children = chunks.into_iter().flat_map(|c| c.children).collect();
+    }
+}
+
+/// 分割して保存された子のリストへの参照
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkRef {
+    id: HashID,
+    len: usize,
+}
+
+impl ChunkRef {
+    /// `id`のオブジェクトとして保存された、子の数が`len`の部分への参照を生成する。
+    pub fn new(id: HashID, len: usize) -> ChunkRef {
+        ChunkRef { id, len }
+    }
+}
+
+/// 分割された子のリストの一部
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirChunk {
+    #[serde(default)]
+    version: u32,
+    children: Vec<FsHash>,
+}
+
+impl DirChunk {
+    fn new(children: Vec<FsHash>) -> DirChunk {
+        DirChunk {
+            version: ENTRY_VERSION,
+            children,
+        }
+    }
+
+    /// 子の数を返す。
+    pub fn len(&self) -> usize {
+        self.children.len()
+    }
+
+    /// 子が無いかどうかを返す。
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+// 分割位置の決定に使う名前のハッシュ値 (FNV-1a)
+fn name_hash(name: &str) -> u64 {
+    name.bytes().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
 }
 
 /// [`DirEntry`](struct.DirEntry.html)のBuilder
@@ -155,9 +313,11 @@ impl DirEntryBuilder {
         self.children.sort();
 
         DirEntry {
+            version: ENTRY_VERSION,
             id: None,
             attr: self.attr,
             children: self.children,
+            chunks: Vec::new(),
         }
     }
 }
@@ -165,16 +325,59 @@ impl DirEntryBuilder {
 /// ファイルの各種情報
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FileEntry {
+    #[serde(default)]
+    version: u32,
     #[serde(skip_serializing)]
     #[serde(default)]
     id: Option<HashID>,
     attr: Attributes,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    streams: Vec<DataStream>,
 }
 
 impl FileEntry {
     /// 新たなファイルエントリを生成する。
     pub fn new(attr: Attributes) -> FileEntry {
-        FileEntry { id: None, attr }
+        FileEntry {
+            version: ENTRY_VERSION,
+            id: None,
+            attr,
+            streams: Vec::new(),
+        }
+    }
+
+    /// 代替データストリームを設定する。
+    pub fn set_streams(&mut self, mut streams: Vec<DataStream>) {
+        streams.sort();
+        self.streams = streams;
+    }
+}
+
+/// NTFSの代替データストリーム
+///
+/// 内容はファイル本体と同様に、オブジェクトとして保存される。
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DataStream {
+    name: String,
+    id: HashID,
+}
+
+impl DataStream {
+    /// 名前が`name`で、内容が`id`のオブジェクトであるストリームを生成する。
+    ///
+    /// `name`は`Zone.Identifier`のように、ファイル名に続く`:`やストリームの種類(`:$DATA`)を含まない。
+    pub fn new(name: String, id: HashID) -> DataStream {
+        DataStream { name, id }
+    }
+
+    /// ストリームの名前を取得する。
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// ストリームの内容のハッシュ値を取得する。
+    pub fn id(&self) -> &HashID {
+        &self.id
     }
 }
 
@@ -190,11 +393,17 @@ impl Entry for FileEntry {
     fn attr(&self) -> &Attributes {
         &self.attr
     }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
 }
 
 /// シンボリックリンクの各種情報
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SymlinkEntry {
+    #[serde(default)]
+    version: u32,
     #[serde(skip_serializing)]
     #[serde(default)]
     id: Option<HashID>,
@@ -207,6 +416,7 @@ impl SymlinkEntry {
     /// 新たなシンボリックリンクエントリを生成する。
     pub fn new(attr: Attributes, target: String, is_dir: bool) -> SymlinkEntry {
         SymlinkEntry {
+            version: ENTRY_VERSION,
             id: None,
             attr,
             target,
@@ -237,6 +447,10 @@ impl Entry for SymlinkEntry {
     fn attr(&self) -> &Attributes {
         &self.attr
     }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
 }
 
 /// ファイルやディレクトリの属性
@@ -245,6 +459,12 @@ pub struct Attributes {
     name: String,
     readonly: bool,
     modified: Timestamp,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file_attributes: Option<u32>,
 }
 
 impl Attributes {
@@ -254,9 +474,34 @@ impl Attributes {
             name,
             readonly,
             modified,
+            created: None,
+            mode: None,
+            file_attributes: None,
         }
     }
 
+    /// 作成日時を設定したAttributesを返す。
+    pub fn with_created(mut self, created: Option<Timestamp>) -> Attributes {
+        self.created = created;
+        self
+    }
+
+    /// UNIX系のパーミッションを設定したAttributesを返す。
+    ///
+    /// `mode`のうちパーミッションとsetuid/setgid/stickyビット(下位12ビット)のみを保持する。
+    pub fn with_mode(mut self, mode: Option<u32>) -> Attributes {
+        self.mode = mode.map(|m| m & 0o7777);
+        self
+    }
+
+    /// Windowsのファイル属性を設定したAttributesを返す。
+    ///
+    /// `attributes`のうち[`FILE_ATTRIBUTE_MASK`](constant.FILE_ATTRIBUTE_MASK.html)に含まれるもののみを保持する。
+    pub fn with_file_attributes(mut self, attributes: Option<u32>) -> Attributes {
+        self.file_attributes = attributes.map(|a| a & FILE_ATTRIBUTE_MASK);
+        self
+    }
+
     /// エントリの名前を取得する。
     pub fn name(&self) -> &str {
         &self.name
@@ -271,6 +516,27 @@ impl Attributes {
     pub fn modified(&self) -> Timestamp {
         self.modified
     }
+
+    /// 作成日時を取得する。
+    ///
+    /// 作成日時を取得できないプラットフォームや、記録していない古い形式のエントリでは`None`を返す。
+    pub fn created(&self) -> Option<Timestamp> {
+        self.created
+    }
+
+    /// UNIX系のパーミッションを取得する。
+    ///
+    /// UNIX系以外でスキャンしたエントリや、記録していない古い形式のエントリでは`None`を返す。
+    pub fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+
+    /// Windowsのファイル属性を取得する。
+    ///
+    /// Windows以外でスキャンしたエントリや、記録していない古い形式のエントリでは`None`を返す。
+    pub fn file_attributes(&self) -> Option<u32> {
+        self.file_attributes
+    }
 }
 
 /// エントリのハッシュ値と属性
@@ -304,6 +570,20 @@ impl FsHash {
             FsHash::Symlink(x) => x.attr(),
         }
     }
+
+    /// ハッシュ値を`id`に置き換えた`FsHash`を返す。
+    pub fn with_id(&self, id: HashID) -> FsHash {
+        let attr = self.attr().clone();
+        match self {
+            FsHash::Dir(_) => FsHash::Dir(DirHash { attr, id }),
+            FsHash::File(x) => FsHash::File(FileHash {
+                attr,
+                id,
+                streams: x.streams.clone(),
+            }),
+            FsHash::Symlink(_) => FsHash::Symlink(SymlinkHash { attr, id }),
+        }
+    }
 }
 
 impl TryFrom<DirEntry> for FsHash {
@@ -395,6 +675,8 @@ impl TryFrom<FsHash> for DirHash {
 pub struct FileHash {
     attr: Attributes,
     id: HashID,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    streams: Vec<DataStream>,
 }
 
 impl FileHash {
@@ -407,6 +689,22 @@ impl FileHash {
     pub fn attr(&self) -> &Attributes {
         &self.attr
     }
+
+    /// 代替データストリームを取得する。
+    ///
+    /// 記録していない場合は空になる。
+    pub fn streams(&self) -> &[DataStream] {
+        &self.streams
+    }
+
+    /// 内容と代替データストリームはそのままで、属性を置き換えた`FileHash`を返す。
+    pub fn with_attr(self, attr: Attributes) -> FileHash {
+        FileHash {
+            attr,
+            id: self.id,
+            streams: self.streams,
+        }
+    }
 }
 
 impl TryFrom<FileEntry> for FileHash {
@@ -414,7 +712,11 @@ impl TryFrom<FileEntry> for FileHash {
 
     fn try_from(e: FileEntry) -> Result<Self, Self::Error> {
         if let Some(id) = e.id() {
-            Ok(FileHash { attr: e.attr, id })
+            Ok(FileHash {
+                attr: e.attr,
+                id,
+                streams: e.streams,
+            })
         } else {
             Err(NoIdError::NoId)
         }
@@ -475,14 +777,35 @@ impl TryFrom<FsHash> for SymlinkHash {
 }
 
 /// エントリの[`FsHash`](struct.FsHash.html)への変換で発生しうるエラー
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum NoIdError {
     /// IDが未設定である
-    #[fail(display = "entry id isn't calculated")]
     NoId,
 }
 
+impl fmt::Display for NoIdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NoIdError::NoId => write!(f, "entry id isn't calculated"),
+        }
+    }
+}
+
+impl error::Error for NoIdError {}
+
 /// [`FsHash`](struct.FsHash.html)から[`DirHash`](struct.DirHash.html)や[`FileHash`](struct.FileHash.html)への変換で発生しうるエラー
-#[derive(Debug, Fail)]
-#[fail(display = "mismatch hash type")]
+#[derive(Debug)]
 pub struct MismatchHashType(FsHash);
+
+impl fmt::Display for MismatchHashType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.0 {
+            FsHash::Dir(_) => "dir",
+            FsHash::File(_) => "file",
+            FsHash::Symlink(_) => "symlink",
+        };
+        write!(f, "mismatch hash type: {} is {}", self.0.id(), kind)
+    }
+}
+
+impl error::Error for MismatchHashType {}
@@ -0,0 +1,778 @@
+//! スナップショットの一部を、tarやzip形式のアーカイブとして書き出す。
+
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use chrono::{Datelike, Timelike};
+use log::trace;
+
+use crate::core::entry::{Attributes, FsHash, SymlinkEntry};
+use crate::core::hash::HashID;
+use crate::core::repo::{self, Bank};
+use crate::core::walk::Walk;
+use crate::util::time::Tz;
+
+#[cfg(test)]
+mod test;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// アーカイブの形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// pax拡張ヘッダーを使うtar
+    Tar,
+    /// 圧縮しないzip。4GiBを超える場合はZIP64形式になる。
+    Zip,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Format, String> {
+        match s {
+            "tar" => Ok(Format::Tar),
+            "zip" => Ok(Format::Zip),
+            _ => Err(format!("unknown format '{}' (expected tar or zip)", s)),
+        }
+    }
+}
+
+/// 書き出しの結果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Exported {
+    /// 書き出したファイルの数
+    pub files: u64,
+    /// 書き出したディレクトリの数
+    pub dirs: u64,
+    /// 書き出したシンボリックリンクの数
+    pub symlinks: u64,
+    /// 書き出したファイルの内容の合計バイト数
+    pub bytes: u64,
+    /// 形式が対応していないため書き出さなかったエントリの、アーカイブ内でのパス
+    pub skipped: Vec<String>,
+}
+
+/// スナップショットをアーカイブとして書き出す
+pub struct Exporter<'a> {
+    bank: &'a Bank<'a>,
+    format: Format,
+    timezone: Tz,
+}
+
+impl<'a> Exporter<'a> {
+    /// `bank`のスナップショットを`format`形式で書き出す`Exporter`を生成する。
+    pub fn new(bank: &'a Bank<'a>, format: Format) -> Exporter<'a> {
+        Exporter {
+            bank,
+            format,
+            timezone: Tz::Local,
+        }
+    }
+
+    /// zipに記録する更新日時のタイムゾーンを設定する。既定値は実行環境のタイムゾーンである。
+    ///
+    /// zipの更新日時は展開する環境の現地時刻として解釈される。
+    /// UTCでの更新日時も拡張フィールドに記録するため、対応した展開ツールでは設定によらず正しい日時になる。
+    pub fn set_timezone(&mut self, timezone: Tz) {
+        self.timezone = timezone;
+    }
+
+    /// ルートディレクトリが`root`のスナップショットのうち、`path`以下を`out`に書き出す。
+    ///
+    /// `path`は`/`区切りで、空の場合はスナップショット全体を書き出す。
+    /// アーカイブ内のパスは`path`の最後の要素から始まる。スナップショット全体の場合は、ルートの子がそのまま最上位に並ぶ。
+    ///
+    /// # Failures
+    ///
+    /// `path`がスナップショット内に無い場合、[`Error::NotFound`](enum.Error.html#variant.NotFound)を返す。
+    pub fn export<W: Write>(&self, root: &HashID, path: &str, out: W) -> Result<Exported> {
+        let parts = path
+            .split('/')
+            .filter(|s| !s.is_empty() && *s != ".")
+            .collect::<Vec<_>>();
+
+        let mut exported = Exported::default();
+        match self.format {
+            Format::Tar => {
+                let mut archive = TarWriter::new(out);
+                self.write_tree(&mut archive, root, &parts, &mut exported)?;
+                archive.finish()?;
+            }
+            Format::Zip => {
+                let mut archive = ZipWriter::new(out, self.timezone);
+                self.write_tree(&mut archive, root, &parts, &mut exported)?;
+                archive.finish()?;
+            }
+        }
+        Ok(exported)
+    }
+
+    fn write_tree<A: Archive>(
+        &self,
+        archive: &mut A,
+        root: &HashID,
+        parts: &[&str],
+        exported: &mut Exported,
+    ) -> Result<()> {
+        let (dir_id, prefix) = match parts.split_last() {
+            None => (root.clone(), String::new()),
+            Some((name, parent)) => {
+                let mut lazy = self.bank.lazy_dir(root.clone());
+                for (i, dir_name) in parent.iter().enumerate() {
+                    lazy = lazy
+                        .child_dir(dir_name)?
+                        .ok_or_else(|| Error::NotFound(parts[..=i].join("/")))?;
+                }
+                let entry = lazy
+                    .entry()?
+                    .find_child(name)
+                    .cloned()
+                    .ok_or_else(|| Error::NotFound(parts.join("/")))?;
+                self.write_entry(archive, name.to_string(), &entry, exported)?;
+                match entry {
+                    FsHash::Dir(dir) => (dir.id(), format!("{}/", name)),
+                    _ => return Ok(()),
+                }
+            }
+        };
+
+        for walked in Walk::new(self.bank, dir_id) {
+            let (path, entry) = walked?;
+            self.write_entry(archive, format!("{}{}", prefix, path), &entry, exported)?;
+        }
+        Ok(())
+    }
+
+    fn write_entry<A: Archive>(
+        &self,
+        archive: &mut A,
+        name: String,
+        entry: &FsHash,
+        exported: &mut Exported,
+    ) -> Result<()> {
+        trace!("export {:?}", name);
+        match entry {
+            FsHash::Dir(dir) => {
+                archive.dir(&name, dir.attr())?;
+                exported.dirs += 1;
+            }
+            FsHash::File(file) => {
+                let size = self.bank.object_size(&file.id())?;
+                let mut content = self.bank.stream_object(&file.id())?;
+                archive.file(&name, file.attr(), size, &mut content)?;
+                exported.files += 1;
+                exported.bytes += size;
+            }
+            FsHash::Symlink(symlink) => {
+                let symlink_entry: SymlinkEntry = self.bank.load_entry(&symlink.id())?;
+                let target = symlink_entry.target().to_string_lossy().into_owned();
+                if archive.symlink(&name, symlink.attr(), &target)? {
+                    exported.symlinks += 1;
+                } else {
+                    exported.skipped.push(name);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// アーカイブ形式ごとの書き込み
+trait Archive {
+    fn dir(&mut self, name: &str, attr: &Attributes) -> io::Result<()>;
+
+    fn file(
+        &mut self,
+        name: &str,
+        attr: &Attributes,
+        size: u64,
+        content: &mut dyn Read,
+    ) -> io::Result<()>;
+
+    // 形式がシンボリックリンクに対応していない場合は`false`を返す。
+    fn symlink(&mut self, name: &str, attr: &Attributes, target: &str) -> io::Result<bool>;
+
+    fn finish(self) -> io::Result<()>;
+}
+
+// `content`を`out`に書き込み、書き込んだバイト数とCRC-32を返す。
+fn copy_content<W: Write>(content: &mut dyn Read, out: &mut W) -> io::Result<(u64, u32)> {
+    let mut buf = vec![0u8; BUFFER_SIZE];
+    let mut written = 0;
+    let mut crc = 0;
+    loop {
+        let n = match content.read(&mut buf) {
+            Ok(0) => return Ok((written, crc)),
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        out.write_all(&buf[..n])?;
+        crc = crc32_update(crc, &buf[..n]);
+        written += n as u64;
+    }
+}
+
+fn check_size(name: &str, expected: u64, written: u64) -> io::Result<()> {
+    if expected == written {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "size of {:?} changed while exporting ({} -> {} bytes)",
+            name, expected, written
+        ),
+    ))
+}
+
+/* tar */
+
+const TAR_BLOCK: usize = 512;
+// ustarヘッダーの8進数11桁で表せる上限
+const TAR_OCTAL_LIMIT: u64 = 0o100_000_000_000;
+
+struct TarWriter<W> {
+    out: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    fn new(out: W) -> TarWriter<W> {
+        TarWriter { out }
+    }
+
+    fn header(
+        &mut self,
+        name: &str,
+        attr: &Attributes,
+        typeflag: u8,
+        size: u64,
+        linkname: &str,
+    ) -> io::Result<()> {
+        let mtime = attr.modified().unix_epoch();
+
+        // ustarヘッダーに収まらない値はpax拡張ヘッダーに記録する
+        let mut records = String::new();
+        if !fits_ustar(name) {
+            records.push_str(&pax_record("path", name));
+        }
+        if !fits_ustar(linkname) {
+            records.push_str(&pax_record("linkpath", linkname));
+        }
+        if size >= TAR_OCTAL_LIMIT {
+            records.push_str(&pax_record("size", &size.to_string()));
+        }
+        if !records.is_empty() {
+            let block = ustar_block(
+                "././@PaxHeader",
+                0o644,
+                records.len() as u64,
+                mtime,
+                b'x',
+                "",
+            );
+            self.out.write_all(&block)?;
+            self.out.write_all(records.as_bytes())?;
+            self.pad(records.len() as u64)?;
+        }
+
+        let mode = tar_mode(attr, typeflag);
+        let block = ustar_block(name, mode, size, mtime, typeflag, linkname);
+        self.out.write_all(&block)
+    }
+
+    fn pad(&mut self, size: u64) -> io::Result<()> {
+        let rem = (size % TAR_BLOCK as u64) as usize;
+        if rem != 0 {
+            self.out.write_all(&[0u8; TAR_BLOCK][rem..])?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Archive for TarWriter<W> {
+    fn dir(&mut self, name: &str, attr: &Attributes) -> io::Result<()> {
+        self.header(&format!("{}/", name), attr, b'5', 0, "")
+    }
+
+    fn file(
+        &mut self,
+        name: &str,
+        attr: &Attributes,
+        size: u64,
+        content: &mut dyn Read,
+    ) -> io::Result<()> {
+        self.header(name, attr, b'0', size, "")?;
+        let (written, _) = copy_content(content, &mut self.out)?;
+        check_size(name, size, written)?;
+        self.pad(size)
+    }
+
+    fn symlink(&mut self, name: &str, attr: &Attributes, target: &str) -> io::Result<bool> {
+        self.header(name, attr, b'2', 0, target)?;
+        Ok(true)
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.out.write_all(&[0u8; TAR_BLOCK * 2])?;
+        self.out.flush()
+    }
+}
+
+fn fits_ustar(s: &str) -> bool {
+    s.len() <= 100 && s.is_ascii()
+}
+
+fn tar_mode(attr: &Attributes, typeflag: u8) -> u32 {
+    if typeflag == b'2' {
+        return 0o777;
+    }
+    let mode = match attr.mode() {
+        Some(mode) => mode & 0o7777,
+        None if typeflag == b'5' => 0o755,
+        None => 0o644,
+    };
+    if attr.readonly() {
+        mode & !0o222
+    } else {
+        mode
+    }
+}
+
+/// pax拡張ヘッダーの1レコードを返す。先頭の長さは、長さ自身の桁を含むレコード全体のバイト数である。
+fn pax_record(key: &str, value: &str) -> String {
+    let base = key.len() + value.len() + 3;
+    let mut len = base + 1;
+    while base + len.to_string().len() != len {
+        len = base + len.to_string().len();
+    }
+    format!("{} {}={}\n", len, key, value)
+}
+
+fn ustar_block(
+    name: &str,
+    mode: u32,
+    size: u64,
+    mtime: u64,
+    typeflag: u8,
+    linkname: &str,
+) -> [u8; TAR_BLOCK] {
+    let mut block = [0u8; TAR_BLOCK];
+    put_ascii(&mut block[0..100], name);
+    put_octal(&mut block[100..108], u64::from(mode));
+    put_octal(&mut block[108..116], 0);
+    put_octal(&mut block[116..124], 0);
+    if size < TAR_OCTAL_LIMIT {
+        put_octal(&mut block[124..136], size);
+    } else {
+        // 8進数で表せない大きさはbase-256で記録する
+        block[124] = 0x80;
+        block[128..136].copy_from_slice(&size.to_be_bytes());
+    }
+    put_octal(&mut block[136..148], mtime.min(TAR_OCTAL_LIMIT - 1));
+    block[148..156].copy_from_slice(b"        ");
+    block[156] = typeflag;
+    put_ascii(&mut block[157..257], linkname);
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+
+    let checksum = block.iter().map(|&b| u64::from(b)).sum();
+    put_octal(&mut block[148..155], checksum);
+    block
+}
+
+// 収まる分だけ書き込み、ASCII以外のバイトは`_`に置き換える。正確な値はpax拡張ヘッダーに記録する。
+fn put_ascii(field: &mut [u8], s: &str) {
+    for (dst, b) in field.iter_mut().zip(s.bytes()) {
+        *dst = if b.is_ascii() { b } else { b'_' };
+    }
+}
+
+// 末尾をNULとして、残りの桁に0埋めの8進数を書き込む。
+fn put_octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let s = format!("{:0width$o}", value, width = digits);
+    field[..digits].copy_from_slice(&s.as_bytes()[s.len() - digits..]);
+    field[digits] = 0;
+}
+
+/* zip */
+
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4b50;
+const ZIP_DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+const ZIP_CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP64_END_OF_CENTRAL: u32 = 0x0606_4b50;
+const ZIP64_END_LOCATOR: u32 = 0x0706_4b50;
+const ZIP_END_OF_CENTRAL: u32 = 0x0605_4b50;
+
+// 汎用フラグ: CRC-32とサイズをデータの後に置く
+const ZIP_FLAG_DESCRIPTOR: u16 = 0x0008;
+// 汎用フラグ: 名前がUTF-8である
+const ZIP_FLAG_UTF8: u16 = 0x0800;
+
+const ZIP_VERSION: u16 = 20;
+const ZIP64_VERSION: u16 = 45;
+const ZIP_HOST_UNIX: u16 = 3 << 8;
+
+const ZIP_EXTRA_ZIP64: u16 = 0x0001;
+const ZIP_EXTRA_TIMESTAMP: u16 = 0x5455;
+
+// 32ビットのフィールドでこれ以上の値はZIP64の拡張フィールドに記録する
+const ZIP64_LIMIT: u64 = 0xFFFF_FFFF;
+const ZIP64_ENTRIES_LIMIT: u64 = 0xFFFF;
+
+struct ZipWriter<W> {
+    out: CountWriter<W>,
+    timezone: Tz,
+    central: Vec<u8>,
+    entries: u64,
+}
+
+impl<W: Write> ZipWriter<W> {
+    fn new(out: W, timezone: Tz) -> ZipWriter<W> {
+        ZipWriter {
+            out: CountWriter {
+                inner: out,
+                count: 0,
+            },
+            timezone,
+            central: Vec::new(),
+            entries: 0,
+        }
+    }
+
+    // 1エントリを書き込む。ディレクトリの場合は`content`が`None`となる。
+    fn entry(
+        &mut self,
+        name: &str,
+        attr: &Attributes,
+        size: u64,
+        content: Option<&mut dyn Read>,
+    ) -> io::Result<()> {
+        let offset = self.out.count;
+        let is_dir = content.is_none();
+        let zip64 = size >= ZIP64_LIMIT;
+        let mtime = attr.modified().unix_epoch();
+        let (time, date) = dos_datetime(self.timezone, mtime);
+        let flags = if is_dir {
+            ZIP_FLAG_UTF8
+        } else {
+            ZIP_FLAG_UTF8 | ZIP_FLAG_DESCRIPTOR
+        };
+
+        let mut local_extra = Vec::new();
+        if zip64 {
+            put_u16(&mut local_extra, ZIP_EXTRA_ZIP64);
+            put_u16(&mut local_extra, 16);
+            put_u64(&mut local_extra, 0);
+            put_u64(&mut local_extra, 0);
+        }
+        put_timestamp_extra(&mut local_extra, mtime);
+
+        let mut header = Vec::new();
+        put_u32(&mut header, ZIP_LOCAL_HEADER);
+        put_u16(&mut header, if zip64 { ZIP64_VERSION } else { ZIP_VERSION });
+        put_u16(&mut header, flags);
+        put_u16(&mut header, 0);
+        put_u16(&mut header, time);
+        put_u16(&mut header, date);
+        put_u32(&mut header, 0);
+        let header_size = if zip64 { ZIP64_LIMIT as u32 } else { 0 };
+        put_u32(&mut header, header_size);
+        put_u32(&mut header, header_size);
+        put_u16(&mut header, name.len() as u16);
+        put_u16(&mut header, local_extra.len() as u16);
+        header.extend_from_slice(name.as_bytes());
+        header.extend_from_slice(&local_extra);
+        self.out.write_all(&header)?;
+
+        let crc = match content {
+            Some(content) => {
+                let (written, crc) = copy_content(content, &mut self.out)?;
+                check_size(name, size, written)?;
+
+                let mut descriptor = Vec::new();
+                put_u32(&mut descriptor, ZIP_DATA_DESCRIPTOR);
+                put_u32(&mut descriptor, crc);
+                if zip64 {
+                    put_u64(&mut descriptor, size);
+                    put_u64(&mut descriptor, size);
+                } else {
+                    put_u32(&mut descriptor, size as u32);
+                    put_u32(&mut descriptor, size as u32);
+                }
+                self.out.write_all(&descriptor)?;
+                crc
+            }
+            None => 0,
+        };
+
+        let offset64 = offset >= ZIP64_LIMIT;
+        let mut central_extra = Vec::new();
+        if zip64 || offset64 {
+            let mut fields = Vec::new();
+            if zip64 {
+                put_u64(&mut fields, size);
+                put_u64(&mut fields, size);
+            }
+            if offset64 {
+                put_u64(&mut fields, offset);
+            }
+            put_u16(&mut central_extra, ZIP_EXTRA_ZIP64);
+            put_u16(&mut central_extra, fields.len() as u16);
+            central_extra.extend_from_slice(&fields);
+        }
+        put_timestamp_extra(&mut central_extra, mtime);
+
+        let (made_by, external) = zip_attributes(attr, is_dir);
+        let c = &mut self.central;
+        put_u32(c, ZIP_CENTRAL_HEADER);
+        put_u16(c, made_by);
+        put_u16(
+            c,
+            if zip64 || offset64 {
+                ZIP64_VERSION
+            } else {
+                ZIP_VERSION
+            },
+        );
+        put_u16(c, flags);
+        put_u16(c, 0);
+        put_u16(c, time);
+        put_u16(c, date);
+        put_u32(c, crc);
+        put_u32(c, size.min(ZIP64_LIMIT) as u32);
+        put_u32(c, size.min(ZIP64_LIMIT) as u32);
+        put_u16(c, name.len() as u16);
+        put_u16(c, central_extra.len() as u16);
+        put_u16(c, 0);
+        put_u16(c, 0);
+        put_u16(c, 0);
+        put_u32(c, external);
+        put_u32(c, offset.min(ZIP64_LIMIT) as u32);
+        c.extend_from_slice(name.as_bytes());
+        c.extend_from_slice(&central_extra);
+
+        self.entries += 1;
+        Ok(())
+    }
+}
+
+impl<W: Write> Archive for ZipWriter<W> {
+    fn dir(&mut self, name: &str, attr: &Attributes) -> io::Result<()> {
+        self.entry(&format!("{}/", name), attr, 0, None)
+    }
+
+    fn file(
+        &mut self,
+        name: &str,
+        attr: &Attributes,
+        size: u64,
+        content: &mut dyn Read,
+    ) -> io::Result<()> {
+        self.entry(name, attr, size, Some(content))
+    }
+
+    // zipのシンボリックリンクはUNIX以外の展開ツールでは通常のファイルになってしまうため、書き出さない。
+    fn symlink(&mut self, _name: &str, _attr: &Attributes, _target: &str) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        let central_offset = self.out.count;
+        let central_size = self.central.len() as u64;
+        self.out.write_all(&self.central)?;
+
+        let mut end = Vec::new();
+        if self.entries >= ZIP64_ENTRIES_LIMIT
+            || central_offset >= ZIP64_LIMIT
+            || central_size >= ZIP64_LIMIT
+        {
+            let end64_offset = central_offset + central_size;
+            put_u32(&mut end, ZIP64_END_OF_CENTRAL);
+            put_u64(&mut end, 44);
+            put_u16(&mut end, ZIP64_VERSION);
+            put_u16(&mut end, ZIP64_VERSION);
+            put_u32(&mut end, 0);
+            put_u32(&mut end, 0);
+            put_u64(&mut end, self.entries);
+            put_u64(&mut end, self.entries);
+            put_u64(&mut end, central_size);
+            put_u64(&mut end, central_offset);
+
+            put_u32(&mut end, ZIP64_END_LOCATOR);
+            put_u32(&mut end, 0);
+            put_u64(&mut end, end64_offset);
+            put_u32(&mut end, 1);
+        }
+        let entries = self.entries.min(ZIP64_ENTRIES_LIMIT) as u16;
+        put_u32(&mut end, ZIP_END_OF_CENTRAL);
+        put_u16(&mut end, 0);
+        put_u16(&mut end, 0);
+        put_u16(&mut end, entries);
+        put_u16(&mut end, entries);
+        put_u32(&mut end, central_size.min(ZIP64_LIMIT) as u32);
+        put_u32(&mut end, central_offset.min(ZIP64_LIMIT) as u32);
+        put_u16(&mut end, 0);
+        self.out.write_all(&end)?;
+        self.out.flush()
+    }
+}
+
+// 作成したシステムと外部属性を返す。
+//
+// 外部属性の下位にはMS-DOSの属性を、UNIXのパーミッションがある場合は上位にモードを記録する。
+fn zip_attributes(attr: &Attributes, is_dir: bool) -> (u16, u32) {
+    let mut dos = 0;
+    if is_dir {
+        dos |= 0x10;
+    }
+    if attr.readonly() {
+        dos |= 0x01;
+    }
+    match attr.mode() {
+        Some(mode) => {
+            let kind = if is_dir { 0o040_000 } else { 0o100_000 };
+            (
+                ZIP_HOST_UNIX | ZIP64_VERSION,
+                (kind | mode & 0o7777) << 16 | dos,
+            )
+        }
+        None => (ZIP64_VERSION, dos),
+    }
+}
+
+// UTCの更新日時を記録する拡張タイムスタンプフィールドを追加する。
+fn put_timestamp_extra(buf: &mut Vec<u8>, mtime: u64) {
+    put_u16(buf, ZIP_EXTRA_TIMESTAMP);
+    put_u16(buf, 5);
+    buf.push(1);
+    put_u32(buf, mtime.min(u64::from(u32::MAX)) as u32);
+}
+
+/// `timezone`での`unix_epoch`の日時を、MS-DOS形式の時刻と日付にする。
+///
+/// 表せる範囲は1980年から2107年までで、範囲外の日時は端に丸める。秒は2秒単位に切り捨てる。
+fn dos_datetime(timezone: Tz, unix_epoch: u64) -> (u16, u16) {
+    let datetime = timezone.at(unix_epoch).naive();
+    if datetime.year() < 1980 {
+        return (0, 1 << 5 | 1);
+    }
+    if datetime.year() > 2107 {
+        return (23 << 11 | 59 << 5 | 29, 127 << 9 | 12 << 5 | 31);
+    }
+    let time = datetime.hour() << 11 | datetime.minute() << 5 | (datetime.second() / 2);
+    let date = (datetime.year() as u32 - 1980) << 9 | datetime.month() << 5 | datetime.day();
+    (time as u16, date as u16)
+}
+
+fn put_u16(buf: &mut Vec<u8>, v: u16) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, v: u32) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, v: u64) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+// 書き込んだバイト数を数える
+struct CountWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+/// `crc`に`data`を続けたCRC-32を返す。最初は`crc`に0を渡す。
+fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut c = !crc;
+    for &b in data {
+        c = CRC32_TABLE[((c ^ u32::from(b)) & 0xFF) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// アーカイブへの書き出しで発生しうるエラー
+#[derive(Debug)]
+pub enum Error {
+    /// 入出力エラー
+    IO(io::Error),
+
+    /// 指定されたパスがスナップショット内に無い
+    NotFound(String),
+
+    /// リポジトリ操作エラーが発生
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IO(e) => write!(f, "failed export with IO error: {}", e),
+            Error::NotFound(path) => write!(f, "'{}' is not found in snapshot", path),
+            Error::Repo(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::IO(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IO(e)
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
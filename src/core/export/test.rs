@@ -0,0 +1,342 @@
+use super::*;
+
+use std::convert::TryInto;
+use std::fs;
+
+use filetime::{set_file_mtime, FileTime};
+use tempfile::{tempdir, TempDir};
+
+use crate::core::repo::Repository;
+use crate::core::scan::Scanner;
+
+// 読み込んだtarの1エントリ
+#[derive(Debug, PartialEq, Eq)]
+struct TarItem {
+    name: String,
+    typeflag: u8,
+    content: Vec<u8>,
+    linkname: String,
+}
+
+fn parse_octal(field: &[u8]) -> u64 {
+    let s = std::str::from_utf8(field).unwrap();
+    u64::from_str_radix(s.trim_end_matches('\0').trim(), 8).unwrap()
+}
+
+fn parse_tar(data: &[u8]) -> Vec<TarItem> {
+    let mut items = Vec::new();
+    let mut pax_path = None;
+    let mut pos = 0;
+    while data[pos..pos + TAR_BLOCK].iter().any(|&b| b != 0) {
+        let block = &data[pos..pos + TAR_BLOCK];
+        let sum = block
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                if (148..156).contains(&i) {
+                    32
+                } else {
+                    b as u64
+                }
+            })
+            .sum::<u64>();
+        assert_eq!(parse_octal(&block[148..155]), sum);
+        assert_eq!(&block[257..263], b"ustar\0");
+
+        let size = parse_octal(&block[124..136]) as usize;
+        let content = data[pos + TAR_BLOCK..pos + TAR_BLOCK + size].to_vec();
+        pos += TAR_BLOCK + size + (TAR_BLOCK - size % TAR_BLOCK) % TAR_BLOCK;
+
+        let field = |range: std::ops::Range<usize>| {
+            String::from_utf8(block[range].to_vec())
+                .unwrap()
+                .trim_end_matches('\0')
+                .to_owned()
+        };
+        if block[156] == b'x' {
+            let records = String::from_utf8(content).unwrap();
+            let (len, record) = records.split_at(records.find(' ').unwrap());
+            assert_eq!(len.parse::<usize>().unwrap(), records.len());
+            pax_path = Some(record.trim().trim_start_matches("path=").to_owned());
+            continue;
+        }
+        items.push(TarItem {
+            name: pax_path.take().unwrap_or_else(|| field(0..100)),
+            typeflag: block[156],
+            content,
+            linkname: field(157..257),
+        });
+    }
+    assert_eq!(data.len(), pos + TAR_BLOCK * 2);
+    items
+}
+
+// 読み込んだzipの1エントリ
+#[derive(Debug)]
+struct ZipItem {
+    name: String,
+    content: Vec<u8>,
+    time: u16,
+    date: u16,
+    external: u32,
+}
+
+fn u16_at(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes(data[pos..pos + 2].try_into().unwrap())
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+// 中央ディレクトリから各エントリを辿り、ローカルヘッダーとデータ記述子の内容も確かめる。
+fn parse_zip(data: &[u8]) -> Vec<ZipItem> {
+    let end = data.len() - 22;
+    assert_eq!(u32_at(data, end), ZIP_END_OF_CENTRAL);
+    let entries = u16_at(data, end + 10) as usize;
+    let central_size = u32_at(data, end + 12) as usize;
+    let mut pos = u32_at(data, end + 16) as usize;
+    assert_eq!(pos + central_size, end);
+
+    let mut items = Vec::new();
+    for _ in 0..entries {
+        assert_eq!(u32_at(data, pos), ZIP_CENTRAL_HEADER);
+        let crc = u32_at(data, pos + 16);
+        let size = u32_at(data, pos + 20) as usize;
+        let name_len = u16_at(data, pos + 28) as usize;
+        let extra_len = u16_at(data, pos + 30) as usize;
+        let external = u32_at(data, pos + 38);
+        let offset = u32_at(data, pos + 42) as usize;
+        let name = String::from_utf8(data[pos + 46..pos + 46 + name_len].to_vec()).unwrap();
+        let item_time = u16_at(data, pos + 12);
+        let item_date = u16_at(data, pos + 14);
+        pos += 46 + name_len + extra_len;
+
+        assert_eq!(u32_at(data, offset), ZIP_LOCAL_HEADER);
+        assert_ne!(u16_at(data, offset + 6) & ZIP_FLAG_UTF8, 0);
+        let local_name_len = u16_at(data, offset + 26) as usize;
+        let local_extra_len = u16_at(data, offset + 28) as usize;
+        let start = offset + 30 + local_name_len + local_extra_len;
+        let content = data[start..start + size].to_vec();
+        assert_eq!(crc32_update(0, &content), crc);
+        if !name.ends_with('/') {
+            assert_eq!(u32_at(data, start + size), ZIP_DATA_DESCRIPTOR);
+            assert_eq!(u32_at(data, start + size + 4), crc);
+        }
+
+        items.push(ZipItem {
+            name,
+            content,
+            time: item_time,
+            date: item_date,
+            external,
+        });
+    }
+    items
+}
+
+fn setup() -> (TempDir, Repository, HashID) {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("Photos/2023")).unwrap();
+    fs::write(target.join("top.txt"), "top").unwrap();
+    fs::write(target.join("Photos/2023/a.jpg"), "jpeg data").unwrap();
+    fs::write(target.join("Photos/2023/b.jpg"), "").unwrap();
+    // 2020-01-02 03:04:06 UTC
+    set_file_mtime(
+        target.join("Photos/2023/a.jpg"),
+        FileTime::from_unix_time(1_577_934_246, 0),
+    )
+    .unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let id = {
+        let bank = repo.open_bank("bank").unwrap();
+        Scanner::new(&bank).scan().unwrap().id()
+    };
+    (temp, repo, id)
+}
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc32_update(0, b""), 0);
+    assert_eq!(crc32_update(0, b"123456789"), 0xCBF4_3926);
+    assert_eq!(
+        crc32_update(crc32_update(0, b"12345"), b"6789"),
+        0xCBF4_3926
+    );
+}
+
+#[test]
+fn test_pax_record() {
+    assert_eq!(pax_record("path", "a"), "9 path=a\n");
+    // 長さの桁が増える境目
+    assert_eq!(pax_record("path", &"x".repeat(90)).len(), 99);
+    let record = pax_record("path", &"x".repeat(91));
+    assert_eq!(record.len(), 101);
+    assert!(record.starts_with("101 "));
+}
+
+#[test]
+fn test_dos_datetime() {
+    // 2020-01-02 03:04:06
+    let (time, date) = dos_datetime(Tz::Utc, 1_577_934_246);
+    assert_eq!(time, 3 << 11 | 4 << 5 | 3);
+    assert_eq!(date, 40 << 9 | 1 << 5 | 2);
+    // 1980年より前は1980-01-01に丸める
+    assert_eq!(dos_datetime(Tz::Utc, 0), (0, 1 << 5 | 1));
+}
+
+#[test]
+fn test_export_tar() {
+    let (_temp, repo, id) = setup();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let mut out = Vec::new();
+    let exported = Exporter::new(&bank, Format::Tar)
+        .export(&id, "", &mut out)
+        .unwrap();
+    assert_eq!(exported.files, 3);
+    assert_eq!(exported.dirs, 2);
+    assert_eq!(exported.bytes, 12);
+
+    let items = parse_tar(&out);
+    let names = items.iter().map(|i| i.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            "Photos/",
+            "Photos/2023/",
+            "Photos/2023/a.jpg",
+            "Photos/2023/b.jpg",
+            "top.txt"
+        ]
+    );
+    assert_eq!(items[0].typeflag, b'5');
+    assert_eq!(items[2].typeflag, b'0');
+    assert_eq!(items[2].content, b"jpeg data");
+    assert_eq!(items[4].content, b"top");
+}
+
+#[test]
+fn test_export_subtree() {
+    let (_temp, repo, id) = setup();
+    let bank = repo.open_bank("bank").unwrap();
+    let exporter = Exporter::new(&bank, Format::Tar);
+
+    let mut out = Vec::new();
+    exporter.export(&id, "Photos/2023/", &mut out).unwrap();
+    let names = parse_tar(&out)
+        .into_iter()
+        .map(|i| i.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["2023/", "2023/a.jpg", "2023/b.jpg"]);
+
+    let mut out = Vec::new();
+    exporter.export(&id, "Photos/2023/a.jpg", &mut out).unwrap();
+    let items = parse_tar(&out);
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name, "a.jpg");
+
+    for path in &["Photos/2022", "top.txt/a", "missing.txt"] {
+        assert!(
+            matches!(
+                exporter.export(&id, path, Vec::new()),
+                Err(Error::NotFound(_))
+            ),
+            "{}",
+            path
+        );
+    }
+}
+
+#[test]
+fn test_export_tar_long_name() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let long_name = format!("{}.txt", "長い名前".repeat(10));
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join(&long_name), "long").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let id = Scanner::new(&bank).scan().unwrap().id();
+
+    let mut out = Vec::new();
+    Exporter::new(&bank, Format::Tar)
+        .export(&id, "", &mut out)
+        .unwrap();
+    let items = parse_tar(&out);
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name, long_name);
+    assert_eq!(items[0].content, b"long");
+}
+
+#[test]
+fn test_export_zip() {
+    let (_temp, repo, id) = setup();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let mut exporter = Exporter::new(&bank, Format::Zip);
+    exporter.set_timezone(Tz::Utc);
+    let mut out = Vec::new();
+    let exported = exporter.export(&id, "Photos", &mut out).unwrap();
+    assert_eq!(exported.files, 2);
+    assert_eq!(exported.dirs, 2);
+
+    let items = parse_zip(&out);
+    let names = items.iter().map(|i| i.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            "Photos/",
+            "Photos/2023/",
+            "Photos/2023/a.jpg",
+            "Photos/2023/b.jpg"
+        ]
+    );
+    assert_ne!(items[0].external & 0x10, 0);
+    assert_eq!(items[2].external & 0x10, 0);
+    assert_eq!(items[2].content, b"jpeg data");
+    assert!(items[3].content.is_empty());
+    assert_eq!(
+        (items[2].time, items[2].date),
+        dos_datetime(Tz::Utc, 1_577_934_246)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_export_symlink() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("file.txt"), "file").unwrap();
+    std::os::unix::fs::symlink("file.txt", target.join("link")).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let id = Scanner::new(&bank).scan().unwrap().id();
+
+    let mut out = Vec::new();
+    let exported = Exporter::new(&bank, Format::Tar)
+        .export(&id, "", &mut out)
+        .unwrap();
+    assert_eq!(exported.symlinks, 1);
+    let items = parse_tar(&out);
+    assert_eq!(items[1].name, "link");
+    assert_eq!(items[1].typeflag, b'2');
+    assert_eq!(items[1].linkname, "file.txt");
+
+    // zipにはシンボリックリンクを書き出さない
+    let mut out = Vec::new();
+    let exported = Exporter::new(&bank, Format::Zip)
+        .export(&id, "", &mut out)
+        .unwrap();
+    assert_eq!(exported.symlinks, 0);
+    assert_eq!(exported.skipped, vec!["link".to_owned()]);
+    assert_eq!(parse_zip(&out).len(), 1);
+}
@@ -1,18 +1,88 @@
 //! ファイルを展開する
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
+use std::error;
+use std::fmt;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
 
-use failure::Fail;
 use filetime::set_file_mtime;
-use log::{info, trace};
+use log::{info, trace, warn};
+use serde::{Deserialize, Serialize};
 
-use crate::core::entry::{DirEntry, Entry, FileHash, FsHash, SymlinkEntry, SymlinkHash};
+use crate::core::entry::{
+    Attributes, DirEntry, Entry, FileHash, FsHash, SymlinkEntry, SymlinkHash,
+};
 use crate::core::repo::{self, Bank, History};
+use crate::core::scan::stream_path;
+use crate::core::span::Span;
 use crate::core::timestamp::{self, Timestamp};
+use crate::progress::Progress;
+use crate::util::copy::{copy_buffered, preallocate, DEFAULT_BUFFER_SIZE};
+use crate::util::long_path;
+
+#[cfg(test)]
+mod test;
+
+/// 大文字と小文字だけが異なる名前のエントリが、復元先で同じエントリになってしまう場合の扱い
+///
+/// 大文字と小文字を区別するファイルシステムから取ったバックアップを、NTFSやAPFSなどに復元した場合に起こる。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaseCollision {
+    /// 後のエントリの名前に`~1`のような接尾辞を付けて展開する
+    #[default]
+    Rename,
+    /// 後のエントリを展開しない
+    Skip,
+    /// 展開を中断する
+    Error,
+}
+
+impl FromStr for CaseCollision {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<CaseCollision, String> {
+        match s {
+            "rename" => Ok(CaseCollision::Rename),
+            "skip" => Ok(CaseCollision::Skip),
+            "error" => Ok(CaseCollision::Error),
+            _ => Err(format!(
+                "unknown case collision strategy '{}' (expected rename, skip or error)",
+                s
+            )),
+        }
+    }
+}
+
+/// 大文字と小文字だけが異なる名前の衝突
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    path: PathBuf,
+    existing: PathBuf,
+    renamed: Option<PathBuf>,
+}
+
+impl Collision {
+    /// 展開しようとしたエントリのパスを返す。
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 先に展開した、衝突の相手のエントリのパスを返す。
+    pub fn existing(&self) -> &Path {
+        &self.existing
+    }
+
+    /// 名前を変えて展開した場合は、そのパスを返す。飛ばした場合は`None`を返す。
+    pub fn renamed(&self) -> Option<&Path> {
+        self.renamed.as_deref()
+    }
+}
 
 /// ファイルの展開を行う
 #[derive(Debug)]
@@ -20,7 +90,12 @@ pub struct Extender<'a> {
     bank: &'a Bank<'a>,
     overwrite: bool,
     remove: bool,
+    create_symlinks: bool,
+    case_collision: CaseCollision,
     symlinks: Symlinks,
+    collisions: Vec<Collision>,
+    progress: Progress,
+    buffer_size: usize,
 }
 
 impl<'a> Extender<'a> {
@@ -30,7 +105,12 @@ impl<'a> Extender<'a> {
             bank,
             overwrite: false,
             remove: false,
+            create_symlinks: false,
+            case_collision: CaseCollision::default(),
             symlinks: Symlinks::new(),
+            collisions: Vec::new(),
+            progress: Progress::hidden(),
+            buffer_size: DEFAULT_BUFFER_SIZE,
         }
     }
 
@@ -44,41 +124,137 @@ impl<'a> Extender<'a> {
         self.remove = allow;
     }
 
+    /// シンボリックリンクを作成するかどうかを設定する。
+    ///
+    /// 作成しない場合は一覧に記録するのみとなる。
+    /// 作成の結果は[`symlinks`](#method.symlinks)で得られる。
+    pub fn allow_symlinks(&mut self, allow: bool) {
+        self.create_symlinks = allow;
+    }
+
+    /// 大文字と小文字だけが異なる名前が衝突した場合の扱いを設定する。
+    ///
+    /// 発生した衝突は[`collisions`](#method.collisions)で得られる。
+    pub fn set_case_collision(&mut self, strategy: CaseCollision) {
+        self.case_collision = strategy;
+    }
+
+    /// 展開したファイルの進捗を表示する`Progress`を設定する。
+    ///
+    /// 表示される場合は、展開前に総バイト数を数えて残り時間を表示する。
+    pub fn set_progress(&mut self, progress: Progress) {
+        self.progress = progress;
+    }
+
+    /// ファイルの展開に使うバッファのサイズを設定する。
+    pub fn set_buffer_size(&mut self, size: usize) {
+        self.buffer_size = size;
+    }
+
     /// 指定された`path`に`history`時点のファイルを展開する。
     pub fn extend<P: AsRef<Path>>(&mut self, target_path: P, history: &History) -> Result<()> {
         let path = target_path.as_ref();
+        let mut span = Span::enter(
+            "restore",
+            &[
+                ("bank", &self.bank.name()),
+                ("history", history.id()),
+                ("to", &path.display()),
+            ],
+        );
+        // 深い階層もWindowsで展開できるよう、拡張パス形式で扱う
+        let path = long_path::extended(path);
+        span.record(self.extend_root(&path, history))
+    }
+
+    /// スナップショット内の1つのエントリ`entry`を`target_path`に展開する。
+    ///
+    /// ディレクトリの場合はその中身ごと展開する。
+    /// 履歴全体ではなく、一部のファイルやディレクトリを取り出すのに使う。
+    pub fn extend_entry<P: AsRef<Path>>(&mut self, target_path: P, entry: &FsHash) -> Result<()> {
+        let path = long_path::extended(target_path.as_ref());
+        match entry {
+            FsHash::Dir(dir) => {
+                let dir = self.bank.load_dir(&dir.id())?;
+                self.extend_dir(&path, &dir)
+            }
+            FsHash::File(file) => self.extend_file(&path, file),
+            FsHash::Symlink(symlink) => self.extend_symlink(&path, symlink),
+        }
+    }
+
+    fn extend_root(&mut self, path: &Path, history: &History) -> Result<()> {
         info!(
             "start extend to {:?} from {} {}",
             path,
             history.timestamp(),
             history.id()
         );
-        let root_dir = self.bank.load_root(history)?;
+        let root_dir = self.bank.load_dir(history.id())?;
+        if self.progress.is_visible() {
+            self.progress.set_total(self.total_size(history)?);
+        }
         self.extend_dir(path, &root_dir)?;
+        self.progress.finish();
         Ok(())
     }
 
+    // 履歴に含まれるファイルのオブジェクトの合計サイズを返す。
+    fn total_size(&self, history: &History) -> Result<u64> {
+        let mut total = 0;
+
+        for entry in self.bank.walk(history) {
+            if let (_, FsHash::File(ref file)) = entry? {
+                total += self.bank.object_size(&file.id())?;
+            }
+        }
+
+        Ok(total)
+    }
+
     fn extend_dir(&mut self, path: &Path, dir_entry: &DirEntry) -> Result<()> {
         info!("extending directory {:?}", path);
-        if !path.exists() {
+        let created = !path.exists();
+        if created {
             trace!("create dir {:?}", path);
             fs::create_dir(path)?;
-        }
-
-        if self.overwrite {
+        } else if self.overwrite {
             // 内部にファイルを展開するために書き込みを許可
-            let mut permission = fs::metadata(path)?.permissions();
-            permission.set_readonly(false);
+            set_readonly(path, false)?;
         }
         let mut exists = HashSet::<PathBuf>::new();
+        // 大文字と小文字を区別しない名前と、その名前で最初に展開したエントリの名前
+        let mut folded = HashMap::<String, &str>::new();
+        // 名前を変えて展開する場合に、他のエントリと重ならないようにするための名前の一覧
+        let mut taken = dir_entry
+            .children()
+            .map(|ch| ch.attr().name().to_lowercase())
+            .collect::<HashSet<_>>();
 
         for ch in dir_entry.children() {
             let attr = ch.attr();
-            let ch_path = path.join(attr.name());
+            let mut ch_path = path.join(attr.name());
+
+            let key = attr.name().to_lowercase();
+            match folded.get(&key) {
+                Some(earlier) if same_entry(&path.join(earlier), &ch_path) => {
+                    match self.resolve_collision(&ch_path, &path.join(earlier), &mut taken)? {
+                        Some(renamed) => ch_path = renamed,
+                        None => {
+                            self.progress.add_file(0);
+                            continue;
+                        }
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    folded.insert(key, attr.name());
+                }
+            }
 
             match ch {
                 FsHash::Dir(ref dir) => {
-                    let ch_dir = self.bank.load_entry(&dir.id())?;
+                    let ch_dir = self.bank.load_dir(&dir.id())?;
                     self.extend_dir(&ch_path, &ch_dir)?;
                 }
                 FsHash::File(ref file) => {
@@ -117,24 +293,59 @@ impl<'a> Extender<'a> {
             }
         }
 
-        if self.overwrite {
-            let mut permission = fs::metadata(path)?.permissions();
-            trace!(
-                "overrite permission readonly={}",
-                dir_entry.attr().readonly()
-            );
-            permission.set_readonly(dir_entry.attr().readonly());
+        // 子エントリの展開で更新日時が変わるため、属性の適用は最後に行う。
+        if created || self.overwrite {
+            apply_attributes(path, dir_entry.attr())?;
         }
 
         info!("extended directory {:?}", path);
         Ok(())
     }
 
+    // 名前の衝突を設定に従って処理し、名前を変えて展開する場合はそのパスを返す。
+    fn resolve_collision(
+        &mut self,
+        path: &Path,
+        existing: &Path,
+        taken: &mut HashSet<String>,
+    ) -> Result<Option<PathBuf>> {
+        let renamed = match self.case_collision {
+            CaseCollision::Error => return Err(Error::CaseCollision(long_path::normal(path))),
+            CaseCollision::Skip => None,
+            CaseCollision::Rename => {
+                let name = path.file_name().unwrap().to_string_lossy();
+                let new_name = (1..)
+                    .map(|n| suffixed_name(&name, n))
+                    .find(|n| !taken.contains(&n.to_lowercase()))
+                    .unwrap();
+                taken.insert(new_name.to_lowercase());
+                Some(path.with_file_name(new_name))
+            }
+        };
+        warn!(
+            "{:?} collides with {:?} differing only in case: {}",
+            path,
+            existing,
+            match renamed {
+                Some(ref renamed) => format!("restored as {:?}", renamed),
+                None => "skipped".to_owned(),
+            }
+        );
+
+        self.collisions.push(Collision {
+            path: long_path::normal(path),
+            existing: long_path::normal(existing),
+            renamed: renamed.as_deref().map(long_path::normal),
+        });
+        Ok(renamed)
+    }
+
     fn extend_file(&self, path: &Path, file_hash: &FileHash) -> Result<()> {
         info!("extending file {:?}", path);
         let exists = path.exists();
         if exists && !self.overwrite {
             info!("skip existing file {:?}", path);
+            self.progress.add_file(0);
             return Ok(());
         }
 
@@ -149,37 +360,85 @@ impl<'a> Extender<'a> {
             );
             if timestamp == file_hash.attr().modified() {
                 info!("skip same timestamp: {:?}", path);
+                self.progress.add_file(0);
                 return Ok(());
             }
         }
 
-        info!("checking file checksum for {}", file_hash.id());
-        let mut f = self.bank.open_object(&file_hash.id())?;
-        let mut out = fs::File::create(path)?;
-        info!("extracting file to {:?}", path);
-        io::copy(&mut f, &mut out)?;
+        let size = self.bank.object_size(&file_hash.id())?;
+        let f = self.bank.stream_object(&file_hash.id())?;
+
+        // 同じディレクトリの一時ファイルに展開し、内容を検証できてから置き換える。
+        // 壊れたオブジェクトで既存のファイルを失わないよう、検証に失敗した場合は一時ファイルだけを削除する。
+        let (temp_path, mut out) = create_temp(path)?;
+        info!("extracting file to {:?}", temp_path);
+        let copied =
+            preallocate(&out, size).and_then(|_| copy_buffered(f, &mut out, self.buffer_size));
+        drop(out);
+        let bytes = match copied {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                return Err(repo::Error::from(e).into());
+            }
+        };
+
+        let replaced = if exists {
+            replace_contents(path, &temp_path)
+        } else {
+            fs::rename(&temp_path, path).map_err(Error::from)
+        };
+        if let Err(e) = replaced {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+        self.progress.add_file(bytes);
 
-        if !exists || self.overwrite {
-            let attr = file_hash.attr();
+        // ストリームの書き込みで更新日時が変わるため、属性の適用より前に行う
+        self.extend_streams(path, file_hash)?;
+        apply_attributes(path, file_hash.attr())?;
 
-            let mut permission = fs::metadata(path)?.permissions();
-            trace!("overrite permission readonly={}", attr.readonly());
-            permission.set_readonly(attr.readonly());
+        Ok(())
+    }
 
-            set_file_mtime(path, attr.modified().into())?;
+    // 代替データストリームを書き込む。Windows以外では飛ばす。
+    fn extend_streams(&self, path: &Path, file_hash: &FileHash) -> Result<()> {
+        let streams = file_hash.streams();
+        if streams.is_empty() {
+            return Ok(());
+        }
+        if !cfg!(windows) {
+            info!(
+                "skip {} alternate data streams of {:?}: not supported on this platform",
+                streams.len(),
+                path
+            );
+            return Ok(());
         }
 
+        for stream in streams {
+            let stream_path = stream_path(path, stream.name());
+            trace!("extracting stream to {:?}", stream_path);
+            let f = self.bank.stream_object(stream.id())?;
+            let mut out = fs::File::create(&stream_path)?;
+            copy_buffered(f, &mut out, self.buffer_size).map_err(repo::Error::from)?;
+        }
         Ok(())
     }
 
     fn extend_symlink(&mut self, path: &Path, symlink_hash: &SymlinkHash) -> Result<()> {
         let symlink_entry: SymlinkEntry = self.bank.load_entry(&symlink_hash.id())?;
-        let symink = Symlink::new(
+        let mut symlink = Symlink::new(
             path.to_owned(),
             symlink_entry.target(),
             symlink_entry.is_dir(),
         );
-        self.symlinks.add(symink);
+        if self.create_symlinks {
+            let result = create_link(&symlink, self.overwrite);
+            info!("symlink {:?}: {}", path, result);
+            symlink.result = Some(result);
+        }
+        self.symlinks.add(symlink);
         Ok(())
     }
 
@@ -187,6 +446,253 @@ impl<'a> Extender<'a> {
     pub fn symlinks(&self) -> &Symlinks {
         &self.symlinks
     }
+
+    /// 展開中に見つかった、大文字と小文字だけが異なる名前の衝突を返す。
+    pub fn collisions(&self) -> &[Collision] {
+        &self.collisions
+    }
+}
+
+// 名前`name`の拡張子の前に`~n`を付けた名前を返す。
+fn suffixed_name(name: &str, n: usize) -> String {
+    match name.rfind('.') {
+        Some(i) if i > 0 => format!("{}~{}{}", &name[..i], n, &name[i..]),
+        _ => format!("{}~{}", name, n),
+    }
+}
+
+// `a`と`b`がファイルシステム上の同じエントリかどうかを返す。どちらかが存在しない場合は`false`になる。
+#[cfg(unix)]
+fn same_entry(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+// Windowsでは、正規化したパスに実際のエントリの名前が使われることを利用する。
+#[cfg(not(unix))]
+fn same_entry(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// 検証済みの一時ファイルの内容で既存のファイルを上書きし、一時ファイルを削除する。
+//
+// ハードリンクなど既存のエントリとの関係を保つため、置き換えずにその場で書き込む。
+fn replace_contents(path: &Path, temp_path: &Path) -> Result<()> {
+    set_readonly(path, false)?;
+    // 隠しファイルやシステムファイルは属性を外さないと上書きできない
+    #[cfg(windows)]
+    set_file_attributes(path, 0, false)?;
+
+    let mut src = fs::File::open(temp_path)?;
+    let mut dst = fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(path)?;
+    io::copy(&mut src, &mut dst)?;
+    drop(src);
+    fs::remove_file(temp_path)?;
+    Ok(())
+}
+
+// `path`と同じディレクトリに、展開中の内容を書き込む一時ファイルを作成し、そのパスと共に返す。
+//
+// 新たに作成したファイルと同じパーミッションになるよう、`tempfile`ではなく通常の方法で作成する。
+fn create_temp(path: &Path) -> io::Result<(PathBuf, fs::File)> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    for n in 0.. {
+        let temp_path = dir.join(format!(".sbak-restore-{}-{}", process::id(), n));
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(f) => return Ok((temp_path, f)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+// 展開したエントリに更新日時と読み込み専用属性を適用する。
+//
+// Windowsでは記録されていれば作成日時とファイル属性も、UNIX系では記録されていればパーミッション全体を適用する。
+fn apply_attributes(path: &Path, attr: &Attributes) -> Result<()> {
+    trace!(
+        "apply attributes to {:?}: modified={}, created={:?}, readonly={}, mode={:?}, file_attributes={:?}",
+        path,
+        attr.modified(),
+        attr.created(),
+        attr.readonly(),
+        attr.mode(),
+        attr.file_attributes()
+    );
+    set_file_mtime(path, attr.modified().into())?;
+    #[cfg(windows)]
+    {
+        if let Some(created) = attr.created() {
+            set_created(path, created)?;
+        }
+        if let Some(attributes) = attr.file_attributes() {
+            return set_file_attributes(path, attributes, attr.readonly());
+        }
+    }
+    #[cfg(unix)]
+    {
+        if let Some(mode) = attr.mode() {
+            return set_mode(path, mode);
+        }
+    }
+    set_readonly(path, attr.readonly())?;
+    Ok(())
+}
+
+// Windowsのファイル属性と読み込み専用属性を設定する。
+//
+// [`FILE_ATTRIBUTE_MASK`]に含まれない属性は変更しない。
+// 圧縮属性は付与のみ行い、既に圧縮されているものを解除はしない。
+#[cfg(windows)]
+fn set_file_attributes(path: &Path, attributes: u32, readonly: bool) -> Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+
+    use winapi::um::fileapi::{GetFileAttributesW, SetFileAttributesW, INVALID_FILE_ATTRIBUTES};
+    use winapi::um::winnt::{
+        FILE_ATTRIBUTE_COMPRESSED, FILE_ATTRIBUTE_NORMAL, FILE_ATTRIBUTE_READONLY,
+    };
+
+    use crate::core::entry::FILE_ATTRIBUTE_MASK;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let current = unsafe { GetFileAttributesW(wide.as_ptr()) };
+    if current == INVALID_FILE_ATTRIBUTES {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    // 書き込みのために開く必要があるため、読み込み専用にする前に圧縮する
+    if attributes & FILE_ATTRIBUTE_COMPRESSED != 0 && current & FILE_ATTRIBUTE_COMPRESSED == 0 {
+        set_compressed(path)?;
+    }
+
+    // 圧縮属性はSetFileAttributesWでは変更できないため含めない
+    let mut new = current & !(FILE_ATTRIBUTE_MASK | FILE_ATTRIBUTE_READONLY)
+        | attributes & !FILE_ATTRIBUTE_COMPRESSED;
+    if readonly {
+        new |= FILE_ATTRIBUTE_READONLY;
+    }
+    if new == current {
+        return Ok(());
+    }
+    if new == 0 {
+        new = FILE_ATTRIBUTE_NORMAL;
+    }
+
+    if unsafe { SetFileAttributesW(wide.as_ptr(), new) } == 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+// NTFSの圧縮を有効にする。
+#[cfg(windows)]
+fn set_compressed(path: &Path) -> Result<()> {
+    use std::mem;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+
+    use winapi::um::ioapiset::DeviceIoControl;
+    use winapi::um::winioctl::FSCTL_SET_COMPRESSION;
+    use winapi::um::winnt::COMPRESSION_FORMAT_DEFAULT;
+
+    let f = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)?;
+
+    let mut format = COMPRESSION_FORMAT_DEFAULT;
+    let mut returned = 0;
+    let ok = unsafe {
+        DeviceIoControl(
+            f.as_raw_handle() as _,
+            FSCTL_SET_COMPRESSION,
+            &mut format as *mut u16 as _,
+            mem::size_of::<u16>() as u32,
+            ptr::null_mut(),
+            0,
+            &mut returned,
+            ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+// パーミッションを設定する。
+//
+// 書き込みでsetuid/setgidビットが落ちることがあるため、内容の展開後に呼ぶ必要がある。
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+// ディレクトリを開くために必要なフラグ
+#[cfg(windows)]
+const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+// 作成日時を設定する。
+//
+// 読み込み専用属性を設定する前に呼ぶ必要がある。
+#[cfg(windows)]
+fn set_created(path: &Path, created: Timestamp) -> Result<()> {
+    use std::fs::FileTimes;
+    use std::os::windows::fs::{FileTimesExt, OpenOptionsExt};
+
+    let f = fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)?;
+    f.set_times(FileTimes::new().set_created(created.into()))?;
+    Ok(())
+}
+
+// 読み込み専用属性を設定する。
+//
+// UNIX系では読み込み専用を解除する際に所有者の書き込み権限のみを付与する。
+fn set_readonly(path: &Path, readonly: bool) -> Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    if permissions.readonly() == readonly {
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = permissions.mode();
+        permissions.set_mode(if readonly {
+            mode & !0o222
+        } else {
+            mode | 0o200
+        });
+    }
+    #[cfg(not(unix))]
+    permissions.set_readonly(readonly);
+
+    fs::set_permissions(path, permissions)?;
+    Ok(())
 }
 
 /// シンボリックリンクのリスト
@@ -210,6 +716,19 @@ impl Symlinks {
             s.show();
         }
     }
+
+    /// シンボリックリンクのイテレータを返す。
+    pub fn iter(&self) -> impl Iterator<Item = &Symlink> {
+        self.list.iter()
+    }
+
+    /// 作成を試みて飛ばしたシンボリックリンクの数を返す。
+    pub fn skipped(&self) -> usize {
+        self.list
+            .iter()
+            .filter(|s| matches!(s.result, Some(LinkResult::Skipped(_))))
+            .count()
+    }
 }
 
 /// シンボリックリンクを表す
@@ -218,42 +737,263 @@ pub struct Symlink {
     from: PathBuf,
     to: PathBuf,
     is_dir: bool,
+    result: Option<LinkResult>,
 }
 
 impl Symlink {
     fn new(from: PathBuf, to: PathBuf, is_dir: bool) -> Symlink {
-        Symlink { from, to, is_dir }
+        Symlink {
+            from,
+            to,
+            is_dir,
+            result: None,
+        }
+    }
+
+    /// シンボリックリンクを展開する位置を返す。
+    pub fn path(&self) -> PathBuf {
+        long_path::normal(&self.from)
+    }
+
+    /// 作成を試みた場合は、その結果を返す。
+    pub fn result(&self) -> Option<&LinkResult> {
+        self.result.as_ref()
     }
 
     /// 標準出力にシンボリックリンクの種類、リンク元とリンク先を表示する。
+    ///
+    /// 作成を試みた場合は、その結果も表示する。
     pub fn show(&self) {
-        println!(
-            "{}\t{:?}\t{:?}",
-            if self.is_dir { "dir" } else { "file" },
-            self.from,
-            self.to
-        );
+        let kind = if self.is_dir { "dir" } else { "file" };
+        let from = long_path::normal(&self.from);
+        match self.result {
+            Some(ref result) => println!("{}\t{:?}\t{:?}\t{}", kind, from, self.to, result),
+            None => println!("{}\t{:?}\t{:?}", kind, from, self.to),
+        }
+    }
+}
+
+/// シンボリックリンクの作成結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkResult {
+    /// シンボリックリンクを作成した。
+    Symlink,
+    /// シンボリックリンクを作成する権限が無いため、代わりにジャンクションを作成した。
+    Junction,
+    /// 作成せずに飛ばした。
+    Skipped(String),
+}
+
+impl fmt::Display for LinkResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkResult::Symlink => write!(f, "created"),
+            LinkResult::Junction => write!(f, "created as junction"),
+            LinkResult::Skipped(reason) => write!(f, "skipped: {}", reason),
+        }
+    }
+}
+
+// シンボリックリンクを作成する。作成できない場合もエラーにせず、理由を結果として返す。
+fn create_link(symlink: &Symlink, overwrite: bool) -> LinkResult {
+    let path = symlink.from.as_path();
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if !overwrite {
+            return LinkResult::Skipped("already exists".to_owned());
+        }
+        if meta.is_dir() {
+            return LinkResult::Skipped("directory exists".to_owned());
+        }
+        // Windowsではディレクトリへのリンクは`remove_dir`でのみ削除できる
+        if let Err(e) = fs::remove_file(path).or_else(|_| fs::remove_dir(path)) {
+            return LinkResult::Skipped(format!("failed to remove existing entry: {}", e));
+        }
+    }
+
+    match create_symlink(&symlink.to, path, symlink.is_dir) {
+        Ok(result) => result,
+        Err(e) => LinkResult::Skipped(e.to_string()),
+    }
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, path: &Path, _is_dir: bool) -> io::Result<LinkResult> {
+    std::os::unix::fs::symlink(target, path)?;
+    Ok(LinkResult::Symlink)
+}
+
+// Windowsではディレクトリとファイルでリンクの種類が異なる。
+//
+// シンボリックリンクの作成には特権か開発者モードが必要なため、権限が無い場合はディレクトリに限りジャンクションで代用する。
+#[cfg(windows)]
+fn create_symlink(target: &Path, path: &Path, is_dir: bool) -> io::Result<LinkResult> {
+    use std::os::windows::fs::{symlink_dir, symlink_file};
+
+    use winapi::shared::winerror::ERROR_PRIVILEGE_NOT_HELD;
+
+    let result = if is_dir {
+        symlink_dir(target, path)
+    } else {
+        symlink_file(target, path)
+    };
+    match result {
+        Ok(()) => Ok(LinkResult::Symlink),
+        Err(e) if e.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD as i32) => {
+            if !is_dir {
+                return Ok(LinkResult::Skipped(
+                    "symbolic link privilege is required for links to files".to_owned(),
+                ));
+            }
+            create_junction(&absolute_target(path, target)?, path)?;
+            Ok(LinkResult::Junction)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn create_symlink(_target: &Path, _path: &Path, _is_dir: bool) -> io::Result<LinkResult> {
+    Ok(LinkResult::Skipped(
+        "symbolic links are not supported on this platform".to_owned(),
+    ))
+}
+
+// ジャンクションを作成する。
+//
+// ジャンクションは空のディレクトリにマウントポイントのリパースポイントを設定して作る。
+#[cfg(windows)]
+fn create_junction(target: &Path, path: &Path) -> io::Result<()> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+
+    use winapi::um::ioapiset::DeviceIoControl;
+    use winapi::um::winioctl::FSCTL_SET_REPARSE_POINT;
+
+    const FILE_FLAG_OPEN_REPARSE_POINT: u32 = 0x0020_0000;
+
+    let data = mount_point_data(&target.to_string_lossy());
+    fs::create_dir(path)?;
+    let set = fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT)
+        .open(path)
+        .and_then(|f| {
+            let mut returned = 0;
+            let ok = unsafe {
+                DeviceIoControl(
+                    f.as_raw_handle() as _,
+                    FSCTL_SET_REPARSE_POINT,
+                    data.as_ptr() as _,
+                    data.len() as u32,
+                    ptr::null_mut(),
+                    0,
+                    &mut returned,
+                    ptr::null_mut(),
+                )
+            };
+            if ok == 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        });
+    if set.is_err() {
+        let _ = fs::remove_dir(path);
+    }
+    set
+}
+
+// ジャンクションのリンク先は絶対パスでなければならないため、リンクの位置から解決し`..`などを取り除く。
+#[cfg_attr(not(windows), allow(dead_code))]
+fn absolute_target(path: &Path, target: &Path) -> io::Result<PathBuf> {
+    use std::path::Component;
+
+    let base = std::env::current_dir()?.join(path.parent().unwrap_or_else(|| Path::new("")));
+    let mut resolved = PathBuf::new();
+    for c in base.join(target).components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            c => resolved.push(c),
+        }
+    }
+    Ok(resolved)
+}
+
+// マウントポイントのリパースポイントのデータ(`REPARSE_DATA_BUFFER`)を作る。
+#[cfg_attr(not(windows), allow(dead_code))]
+fn mount_point_data(target: &str) -> Vec<u8> {
+    const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+
+    let target = target.strip_prefix(r"\\?\").unwrap_or(target);
+    let substitute: Vec<u16> = format!(r"\??\{}", target).encode_utf16().collect();
+    let print: Vec<u16> = target.encode_utf16().collect();
+
+    // 各名前の後ろには終端のNULを置く
+    let substitute_len = substitute.len() as u16 * 2;
+    let print_len = print.len() as u16 * 2;
+    let path_buffer_len = substitute_len + 2 + print_len + 2;
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&IO_REPARSE_TAG_MOUNT_POINT.to_le_bytes());
+    data.extend_from_slice(&(8 + path_buffer_len).to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&substitute_len.to_le_bytes());
+    data.extend_from_slice(&(substitute_len + 2).to_le_bytes());
+    data.extend_from_slice(&print_len.to_le_bytes());
+    for c in substitute.iter().chain(&[0]).chain(&print).chain(&[0]) {
+        data.extend_from_slice(&c.to_le_bytes());
     }
+    data
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
 /// ファイルシステムのスキャンで発生しうるエラー
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
+    /// 大文字と小文字だけが異なる名前が、復元先で同じエントリになる
+    CaseCollision(PathBuf),
+
     /// 入出力エラー
-    #[fail(display = "failed scan with IO error: {}", _0)]
-    IO(#[fail(cause)] io::Error),
+    IO(io::Error),
 
     /// リポジトリ操作エラーが発生
-    #[fail(display = "{}", _0)]
-    Repo(#[fail(cause)] repo::Error),
+    Repo(repo::Error),
 
     /// 対応範囲外のタイムスタンプを検出
-    #[fail(display = "timestamp is older than UNIX epoch")]
     Timestamp,
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::CaseCollision(path) => write!(
+                f,
+                "{:?} collides with another entry differing only in case",
+                path
+            ),
+            Error::IO(e) => write!(f, "failed scan with IO error: {}", e),
+            Error::Repo(e) => write!(f, "{}", e),
+            Error::Timestamp => write!(f, "timestamp is older than UNIX epoch"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::IO(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Error {
         Error::IO(e)
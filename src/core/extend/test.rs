@@ -0,0 +1,472 @@
+use super::*;
+
+use std::path::Path;
+
+use filetime::{set_file_mtime, FileTime};
+use tempfile::{tempdir, TempDir};
+
+use crate::core::repo::Repository;
+use crate::core::scan::Scanner;
+
+fn mtime(path: &Path) -> i64 {
+    FileTime::from_last_modification_time(&fs::metadata(path).unwrap()).unix_seconds()
+}
+
+fn readonly(path: &Path) -> bool {
+    fs::metadata(path).unwrap().permissions().readonly()
+}
+
+fn make_readonly(path: &Path) {
+    let mut permissions = fs::metadata(path).unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(path, permissions).unwrap();
+}
+
+fn backup(repo: &Repository, target: &Path) -> History {
+    repo.create_bank("bank", target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let id = Scanner::new(&bank).scan().unwrap();
+    bank.save_history(id.id(), Timestamp::now().unwrap(), None)
+        .unwrap();
+
+    bank.last_scan().unwrap().unwrap()
+}
+
+#[test]
+fn test_extend_empty_dirs_with_attributes() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let restored = temp.path().join("restored");
+
+    fs::create_dir_all(target.join("empty")).unwrap();
+    fs::create_dir_all(target.join("readonly_empty")).unwrap();
+    fs::create_dir_all(target.join("nested/inner_empty")).unwrap();
+
+    set_file_mtime(
+        target.join("empty"),
+        FileTime::from_unix_time(1_000_000_000, 0),
+    )
+    .unwrap();
+    set_file_mtime(
+        target.join("readonly_empty"),
+        FileTime::from_unix_time(1_100_000_000, 0),
+    )
+    .unwrap();
+    make_readonly(&target.join("readonly_empty"));
+    set_file_mtime(
+        target.join("nested/inner_empty"),
+        FileTime::from_unix_time(1_200_000_000, 0),
+    )
+    .unwrap();
+    set_file_mtime(
+        target.join("nested"),
+        FileTime::from_unix_time(1_300_000_000, 0),
+    )
+    .unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    let history = backup(&repo, &target);
+    let bank = repo.open_bank("bank").unwrap();
+
+    let mut extender = Extender::new(&bank);
+    extender.extend(&restored, &history).unwrap();
+
+    let cases = vec![
+        ("empty", 1_000_000_000, false),
+        ("readonly_empty", 1_100_000_000, true),
+        ("nested/inner_empty", 1_200_000_000, false),
+        ("nested", 1_300_000_000, false),
+    ];
+
+    for (name, to_be_mtime, to_be_readonly) in cases {
+        let path = restored.join(name);
+        assert!(path.is_dir(), "{} is not restored", name);
+        assert_eq!(to_be_mtime, mtime(&path), "mtime of {}", name);
+        assert_eq!(to_be_readonly, readonly(&path), "readonly of {}", name);
+    }
+    assert_eq!(fs::read_dir(restored.join("empty")).unwrap().count(), 0);
+    assert_eq!(
+        fs::read_dir(restored.join("readonly_empty"))
+            .unwrap()
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn test_extend_overwrites_existing_dir_attributes() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let restored = temp.path().join("restored");
+
+    fs::create_dir_all(target.join("empty")).unwrap();
+    set_file_mtime(
+        target.join("empty"),
+        FileTime::from_unix_time(1_000_000_000, 0),
+    )
+    .unwrap();
+
+    fs::create_dir_all(restored.join("empty")).unwrap();
+    make_readonly(&restored.join("empty"));
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    let history = backup(&repo, &target);
+    let bank = repo.open_bank("bank").unwrap();
+
+    // 上書きしない場合は既存のディレクトリの属性を変更しない。
+    let mut extender = Extender::new(&bank);
+    extender.extend(&restored, &history).unwrap();
+    assert!(readonly(&restored.join("empty")));
+
+    let mut extender = Extender::new(&bank);
+    extender.allow_overwrite(true);
+    extender.extend(&restored, &history).unwrap();
+    assert!(!readonly(&restored.join("empty")));
+    assert_eq!(1_000_000_000, mtime(&restored.join("empty")));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_extend_restores_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let restored = temp.path().join("restored");
+
+    fs::create_dir_all(target.join("shared")).unwrap();
+    fs::write(target.join("shared/tool"), "#!/bin/sh\n").unwrap();
+    fs::write(target.join("secret.conf"), "key").unwrap();
+    let modes = vec![
+        ("shared/tool", 0o4755),
+        ("secret.conf", 0o600),
+        ("shared", 0o1777),
+    ];
+    for (name, mode) in &modes {
+        fs::set_permissions(target.join(name), fs::Permissions::from_mode(*mode)).unwrap();
+    }
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    let history = backup(&repo, &target);
+    let bank = repo.open_bank("bank").unwrap();
+
+    let mut extender = Extender::new(&bank);
+    extender.extend(&restored, &history).unwrap();
+
+    for (name, mode) in modes {
+        let actual = fs::metadata(restored.join(name))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(actual & 0o7777, mode, "mode of {}", name);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_extend_creates_symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let restored = temp.path().join("restored");
+
+    fs::create_dir_all(target.join("sub")).unwrap();
+    fs::write(target.join("sub/a.txt"), "a").unwrap();
+    symlink("sub", target.join("dir_link")).unwrap();
+    symlink("sub/a.txt", target.join("file_link")).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    let history = backup(&repo, &target);
+    let bank = repo.open_bank("bank").unwrap();
+
+    // 作成しない場合は一覧に記録するのみ
+    let mut extender = Extender::new(&bank);
+    extender.extend(&restored, &history).unwrap();
+    assert!(fs::symlink_metadata(restored.join("dir_link")).is_err());
+    assert!(extender.symlinks().iter().all(|s| s.result().is_none()));
+
+    let mut extender = Extender::new(&bank);
+    extender.allow_symlinks(true);
+    extender.extend(&restored, &history).unwrap();
+    assert_eq!(extender.symlinks().skipped(), 0);
+    assert_eq!(
+        fs::read_link(restored.join("dir_link")).unwrap(),
+        Path::new("sub")
+    );
+    assert_eq!(fs::read_to_string(restored.join("file_link")).unwrap(), "a");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_extend_skips_existing_symlink_path() {
+    use std::os::unix::fs::symlink;
+
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let restored = temp.path().join("restored");
+
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("a.txt"), "a").unwrap();
+    symlink("a.txt", target.join("link")).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    let history = backup(&repo, &target);
+    let bank = repo.open_bank("bank").unwrap();
+
+    fs::create_dir_all(&restored).unwrap();
+    fs::write(restored.join("link"), "existing").unwrap();
+
+    let mut extender = Extender::new(&bank);
+    extender.allow_symlinks(true);
+    extender.extend(&restored, &history).unwrap();
+    assert_eq!(extender.symlinks().skipped(), 1);
+    assert_eq!(
+        fs::read_to_string(restored.join("link")).unwrap(),
+        "existing"
+    );
+
+    // 上書きを許可すればファイルをリンクに置き換える
+    let mut extender = Extender::new(&bank);
+    extender.allow_symlinks(true);
+    extender.allow_overwrite(true);
+    extender.extend(&restored, &history).unwrap();
+    let results = extender
+        .symlinks()
+        .iter()
+        .map(|s| s.result().cloned())
+        .collect::<Vec<_>>();
+    assert_eq!(results, vec![Some(LinkResult::Symlink)]);
+    assert_eq!(
+        fs::read_link(restored.join("link")).unwrap(),
+        Path::new("a.txt")
+    );
+}
+
+#[test]
+fn test_absolute_target() {
+    let cwd = std::env::current_dir().unwrap();
+    let resolved =
+        absolute_target(Path::new("restored/sub/link"), Path::new("../other/./dir")).unwrap();
+    assert_eq!(resolved, cwd.join("restored").join("other").join("dir"));
+
+    let root = cwd.ancestors().last().unwrap().join("data");
+    assert_eq!(
+        absolute_target(Path::new("restored/link"), &root).unwrap(),
+        root
+    );
+}
+
+#[test]
+fn test_mount_point_data() {
+    let data = mount_point_data(r"C:\data");
+    let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+
+    // "\??\C:\data"が11文字、"C:\data"が7文字で、それぞれ終端のNULを含む
+    let path_buffer_len = (11 + 1 + 7 + 1) * 2;
+    assert_eq!(&data[0..4], &0xA000_0003u32.to_le_bytes());
+    assert_eq!(u16_at(4) as usize, 8 + path_buffer_len);
+    assert_eq!(u16_at(8), 0);
+    assert_eq!(u16_at(10), 11 * 2);
+    assert_eq!(u16_at(12), 12 * 2);
+    assert_eq!(u16_at(14), 7 * 2);
+    assert_eq!(data.len(), 16 + path_buffer_len);
+
+    let names = data[16..]
+        .chunks(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect::<Vec<_>>();
+    assert_eq!(String::from_utf16(&names[..11]).unwrap(), r"\??\C:\data");
+    assert_eq!(String::from_utf16(&names[12..19]).unwrap(), r"C:\data");
+
+    // 拡張パス形式の接頭辞は取り除く
+    assert_eq!(mount_point_data(r"\\?\C:\data"), data);
+}
+
+#[test]
+fn test_extend_deep_tree() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let restored = temp.path().join("restored");
+
+    // Windowsの`MAX_PATH`を超える深さのパス
+    let deep = (0..20).fold(PathBuf::new(), |p, i| {
+        p.join(format!("dir{:02}_{}", i, "x".repeat(10)))
+    });
+    assert!(target.join(&deep).as_os_str().len() > 260);
+    fs::create_dir_all(target.join(&deep)).unwrap();
+    fs::write(target.join(&deep).join("file.txt"), "deep").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    let history = backup(&repo, &target);
+    let bank = repo.open_bank("bank").unwrap();
+
+    let mut extender = Extender::new(&bank);
+    extender.extend(&restored, &history).unwrap();
+    assert_eq!(
+        fs::read_to_string(restored.join(&deep).join("file.txt")).unwrap(),
+        "deep"
+    );
+}
+
+#[test]
+fn test_extend_broken_object_keeps_existing_file() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let restored = temp.path().join("restored");
+    let repo_path = temp.path().join("repo");
+
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("data.txt"), "backed up").unwrap();
+    set_file_mtime(
+        target.join("data.txt"),
+        FileTime::from_unix_time(1_000_000, 0),
+    )
+    .unwrap();
+
+    let repo = Repository::create(&repo_path).unwrap();
+    let history = backup(&repo, &target);
+    let bank = repo.open_bank("bank").unwrap();
+
+    // リポジトリ内のオブジェクトを壊す
+    let root = bank.load_root(&history).unwrap();
+    let id = root.children().next().unwrap().id();
+    let obj_path = repo_path.join("objects").join(id.shard_path());
+    fs::remove_file(&obj_path).unwrap();
+    fs::write(&obj_path, "corrupted").unwrap();
+
+    fs::create_dir_all(&restored).unwrap();
+    fs::write(restored.join("data.txt"), "user's file").unwrap();
+
+    let mut extender = Extender::new(&bank);
+    extender.allow_overwrite(true);
+    assert!(extender.extend(&restored, &history).is_err());
+
+    // 既存のファイルは元の内容のまま残り、一時ファイルも残らない
+    assert_eq!(
+        fs::read_to_string(restored.join("data.txt")).unwrap(),
+        "user's file"
+    );
+    let names = fs::read_dir(&restored)
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["data.txt"]);
+}
+
+#[test]
+fn test_suffixed_name() {
+    assert_eq!(suffixed_name("Readme.txt", 1), "Readme~1.txt");
+    assert_eq!(suffixed_name("archive.tar.gz", 2), "archive.tar~2.gz");
+    assert_eq!(suffixed_name("README", 1), "README~1");
+    assert_eq!(suffixed_name(".bashrc", 1), ".bashrc~1");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_extend_without_case_collision() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let restored = temp.path().join("restored");
+
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("README"), "upper").unwrap();
+    fs::write(target.join("Readme"), "mixed").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    let history = backup(&repo, &target);
+    let bank = repo.open_bank("bank").unwrap();
+
+    // 大文字と小文字を区別するファイルシステムでは、どちらもそのまま展開する
+    let mut extender = Extender::new(&bank);
+    extender.extend(&restored, &history).unwrap();
+    assert!(extender.collisions().is_empty());
+    assert_eq!(
+        fs::read_to_string(restored.join("README")).unwrap(),
+        "upper"
+    );
+    assert_eq!(
+        fs::read_to_string(restored.join("Readme")).unwrap(),
+        "mixed"
+    );
+}
+
+// 大文字と小文字を区別しないファイルシステムを、同じファイルへのハードリンクで模して展開する。
+#[cfg(unix)]
+fn extend_colliding(strategy: CaseCollision) -> (TempDir, PathBuf, Result<Vec<Collision>>) {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let restored = temp.path().join("restored");
+
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("README.md"), "upper").unwrap();
+    fs::write(target.join("Readme.md"), "mixed").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    let history = backup(&repo, &target);
+    let bank = repo.open_bank("bank").unwrap();
+
+    fs::create_dir_all(&restored).unwrap();
+    fs::write(restored.join("README.md"), "old").unwrap();
+    fs::hard_link(restored.join("README.md"), restored.join("Readme.md")).unwrap();
+    set_file_mtime(
+        restored.join("README.md"),
+        FileTime::from_unix_time(1_000_000, 0),
+    )
+    .unwrap();
+
+    let mut extender = Extender::new(&bank);
+    extender.allow_overwrite(true);
+    extender.set_case_collision(strategy);
+    let result = extender
+        .extend(&restored, &history)
+        .map(|_| extender.collisions().to_vec());
+    (temp, restored, result)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_extend_case_collision_rename() {
+    let (_temp, restored, result) = extend_colliding(CaseCollision::Rename);
+    let collisions = result.unwrap();
+    assert_eq!(collisions.len(), 1);
+    assert_eq!(collisions[0].path(), restored.join("Readme.md"));
+    assert_eq!(collisions[0].existing(), restored.join("README.md"));
+    assert_eq!(
+        collisions[0].renamed(),
+        Some(restored.join("Readme~1.md").as_path())
+    );
+    assert_eq!(
+        fs::read_to_string(restored.join("README.md")).unwrap(),
+        "upper"
+    );
+    assert_eq!(
+        fs::read_to_string(restored.join("Readme~1.md")).unwrap(),
+        "mixed"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_extend_case_collision_skip() {
+    let (_temp, restored, result) = extend_colliding(CaseCollision::Skip);
+    let collisions = result.unwrap();
+    assert_eq!(collisions.len(), 1);
+    assert_eq!(collisions[0].renamed(), None);
+    assert_eq!(
+        fs::read_to_string(restored.join("README.md")).unwrap(),
+        "upper"
+    );
+    assert!(!restored.join("Readme~1.md").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_extend_case_collision_error() {
+    let (_temp, restored, result) = extend_colliding(CaseCollision::Error);
+    match result {
+        Err(Error::CaseCollision(path)) => assert_eq!(path, restored.join("Readme.md")),
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
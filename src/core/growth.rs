@@ -0,0 +1,220 @@
+//! 履歴間でのリポジトリの容量増加の要因を分析する。
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use log::trace;
+
+use crate::core::entry::{DirEntry, Entry, FsHash};
+use crate::core::hash::HashID;
+use crate::core::repo::{Bank, Error, History};
+use crate::core::timestamp::Timestamp;
+
+#[cfg(test)]
+mod test;
+
+/// 分析で新たに保存されたことが判明したファイルのオブジェクト
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewObject {
+    /// バックアップ対象ディレクトリからの相対パス
+    pub path: String,
+    /// オブジェクトのID
+    pub id: HashID,
+    /// オブジェクトのサイズ
+    pub size: u64,
+    /// 最初に保存された履歴のタイムスタンプ
+    pub timestamp: Timestamp,
+}
+
+/// 容量増加の分析結果
+#[derive(Debug, Clone, Default)]
+pub struct GrowthReport {
+    new_objects: Vec<NewObject>,
+    changes: HashMap<String, usize>,
+    dir_bytes: BTreeMap<String, u64>,
+    metadata_bytes: u64,
+}
+
+impl GrowthReport {
+    /// 新たに保存されたファイルのオブジェクトをサイズの降順で返す。
+    pub fn largest_objects(&self) -> Vec<&NewObject> {
+        let mut objects = self.new_objects.iter().collect::<Vec<_>>();
+        objects.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        objects
+    }
+
+    /// 追加・変更された回数の多い順にパスと回数を返す。
+    pub fn frequent_changes(&self) -> Vec<(&str, usize)> {
+        let mut changes = self
+            .changes
+            .iter()
+            .map(|(p, c)| (p.as_str(), *c))
+            .collect::<Vec<_>>();
+        changes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        changes
+    }
+
+    /// 新たに保存されたファイルのバイト数を、集計対象のディレクトリごとに降順で返す。
+    pub fn directory_bytes(&self) -> Vec<(&str, u64)> {
+        let mut dirs = self
+            .dir_bytes
+            .iter()
+            .map(|(p, b)| (p.as_str(), *b))
+            .collect::<Vec<_>>();
+        dirs.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        dirs
+    }
+
+    /// 新たに保存されたディレクトリやシンボリックリンクのエントリのバイト数を返す。
+    pub fn metadata_bytes(&self) -> u64 {
+        self.metadata_bytes
+    }
+
+    /// 新たに保存されたオブジェクトの合計バイト数を返す。
+    pub fn total_bytes(&self) -> u64 {
+        self.new_objects.iter().map(|o| o.size).sum::<u64>() + self.metadata_bytes
+    }
+}
+
+/// `histories`の各履歴で新たに保存されたオブジェクトを分析する。
+///
+/// `base`が指定された場合、その時点で既に保存されていたオブジェクトは新規として扱わない。
+/// ディレクトリごとの集計は、ルートから`depth`階層目までのディレクトリ単位で行われる。
+pub fn analyze(
+    bank: &Bank,
+    base: Option<&History>,
+    histories: &[History],
+    depth: usize,
+) -> Result<GrowthReport, Error> {
+    let mut analyzer = Analyzer {
+        bank,
+        depth,
+        known: HashSet::new(),
+        report: GrowthReport::default(),
+    };
+
+    let mut prev_root = None;
+    if let Some(base) = base {
+        trace!("collect objects in base history {}", base.id());
+        analyzer.collect_known(base.id())?;
+        prev_root = Some(base.id().clone());
+    }
+
+    for history in histories {
+        trace!("analyze history {}", history.id());
+        let prev = match prev_root {
+            Some(ref id) if id == history.id() => continue,
+            Some(ref id) => Some(bank.load_dir(id)?),
+            None => None,
+        };
+        analyzer.diff_dir(&[], prev.as_deref(), history.id(), history.timestamp())?;
+        prev_root = Some(history.id().clone());
+    }
+
+    Ok(analyzer.report)
+}
+
+struct Analyzer<'a> {
+    bank: &'a Bank<'a>,
+    depth: usize,
+    known: HashSet<HashID>,
+    report: GrowthReport,
+}
+
+impl<'a> Analyzer<'a> {
+    fn collect_known(&mut self, dir_id: &HashID) -> Result<(), Error> {
+        if !self.known.insert(dir_id.clone()) {
+            return Ok(());
+        }
+
+        let dir = self.bank.load_dir(dir_id)?;
+        self.known.extend(dir.chunk_ids());
+        for ch in dir.children() {
+            match ch {
+                FsHash::Dir(x) => self.collect_known(&x.id())?,
+                ch => {
+                    self.known.insert(ch.id());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn diff_dir(
+        &mut self,
+        path: &[String],
+        prev: Option<&DirEntry>,
+        dir_id: &HashID,
+        timestamp: Timestamp,
+    ) -> Result<(), Error> {
+        if self.known.insert(dir_id.clone()) {
+            self.report.metadata_bytes += self.bank.object_size(dir_id)?;
+        }
+
+        let dir = self.bank.load_dir(dir_id)?;
+        for chunk_id in dir.chunk_ids() {
+            if self.known.insert(chunk_id.clone()) {
+                self.report.metadata_bytes += self.bank.object_size(&chunk_id)?;
+            }
+        }
+        let prev_children = prev
+            .map(|p| {
+                p.children()
+                    .map(|ch| (ch.attr().name(), ch))
+                    .collect::<HashMap<&str, &FsHash>>()
+            })
+            .unwrap_or_default();
+
+        for ch in dir.children() {
+            let prev_ch = prev_children.get(ch.attr().name()).copied();
+            if prev_ch.map(|p| p.id()) == Some(ch.id()) {
+                continue;
+            }
+
+            let mut ch_path = path.to_vec();
+            ch_path.push(ch.attr().name().to_owned());
+
+            match ch {
+                FsHash::Dir(x) => {
+                    let prev_dir = match prev_ch {
+                        Some(FsHash::Dir(p)) => Some(self.bank.load_dir(&p.id())?),
+                        _ => None,
+                    };
+                    self.diff_dir(&ch_path, prev_dir.as_deref(), &x.id(), timestamp)?;
+                }
+                FsHash::File(x) => {
+                    let path_str = ch_path.join("/");
+                    *self.report.changes.entry(path_str.clone()).or_insert(0) += 1;
+
+                    let id = x.id();
+                    if self.known.insert(id.clone()) {
+                        let size = self.bank.object_size(&id)?;
+
+                        let dir_len = self.depth.min(ch_path.len() - 1);
+                        let dir_str = if dir_len == 0 {
+                            ".".to_owned()
+                        } else {
+                            ch_path[..dir_len].join("/")
+                        };
+                        *self.report.dir_bytes.entry(dir_str).or_insert(0) += size;
+
+                        self.report.new_objects.push(NewObject {
+                            path: path_str,
+                            id,
+                            size,
+                            timestamp,
+                        });
+                    }
+                }
+                FsHash::Symlink(x) => {
+                    let id = x.id();
+                    if self.known.insert(id.clone()) {
+                        self.report.metadata_bytes += self.bank.object_size(&id)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+use super::*;
+
+use std::fs;
+use std::path::Path;
+
+use filetime::{set_file_mtime, FileTime};
+use tempfile::tempdir;
+
+use crate::core::repo::Repository;
+use crate::core::scan::Scanner;
+
+// 内容を書き込み、前回のスキャンと更新日時が重ならないようにする。
+fn write(path: &Path, content: &str, mtime: i64) {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).unwrap();
+    }
+    fs::write(path, content).unwrap();
+    set_file_mtime(path, FileTime::from_unix_time(mtime, 0)).unwrap();
+}
+
+// バックアップ対象をスキャンし、`timestamp`の履歴として保存する。
+fn backup(bank: &Bank, timestamp: u64) -> History {
+    let root = Scanner::new(bank).scan().unwrap();
+    bank.save_history(root.id(), Timestamp::from(timestamp), None)
+        .unwrap()
+}
+
+fn paths(report: &GrowthReport) -> Vec<&str> {
+    let mut paths = report
+        .largest_objects()
+        .iter()
+        .map(|o| o.path.as_str())
+        .collect::<Vec<_>>();
+    paths.sort_unstable();
+    paths
+}
+
+#[test]
+fn test_analyze_excludes_base() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    write(&target.join("a.txt"), "aaaaa", 1);
+    write(&target.join("docs/b.txt"), "bbb", 1);
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let base = backup(&bank, 1);
+
+    // 内容が基準の履歴にあるものと同じファイルは、パスが違っても新規に保存されない
+    write(&target.join("copy/a.txt"), "aaaaa", 2);
+    write(&target.join("docs/c.txt"), "cccc", 2);
+    let history = backup(&bank, 2);
+
+    let report = analyze(&bank, Some(&base), std::slice::from_ref(&history), 1).unwrap();
+    assert_eq!(paths(&report), vec!["docs/c.txt"]);
+    let object = report.largest_objects()[0];
+    assert_eq!(object.size, 4);
+    assert_eq!(object.timestamp, Timestamp::from(2));
+    assert_eq!(
+        report.frequent_changes(),
+        vec![("copy/a.txt", 1), ("docs/c.txt", 1)]
+    );
+    assert_eq!(report.directory_bytes(), vec![("docs", 4)]);
+    assert!(report.metadata_bytes() > 0);
+    assert_eq!(report.total_bytes(), 4 + report.metadata_bytes());
+
+    // 基準の履歴が無ければ、最初の履歴の全てのファイルが新規になる
+    let report = analyze(&bank, None, &[base, history], 1).unwrap();
+    assert_eq!(paths(&report), vec!["a.txt", "docs/b.txt", "docs/c.txt"]);
+}
+
+#[test]
+fn test_analyze_change_frequency() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    write(&target.join("log.txt"), "1", 1);
+    write(&target.join("stable.txt"), "stable", 1);
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let mut histories = vec![backup(&bank, 1)];
+    write(&target.join("log.txt"), "12", 2);
+    histories.push(backup(&bank, 2));
+    // 変更が無くルートが同じ履歴は数えない
+    histories.push(backup(&bank, 3));
+    // 以前と同じ内容に戻った場合も変更として数えるが、オブジェクトは新規ではない
+    write(&target.join("log.txt"), "1", 4);
+    histories.push(backup(&bank, 4));
+
+    let report = analyze(&bank, None, &histories, 1).unwrap();
+    assert_eq!(
+        report.frequent_changes(),
+        vec![("log.txt", 3), ("stable.txt", 1)]
+    );
+    let objects = report
+        .largest_objects()
+        .iter()
+        .map(|o| (o.path.as_str(), o.size, o.timestamp))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        objects,
+        vec![
+            ("stable.txt", 6, Timestamp::from(1)),
+            ("log.txt", 2, Timestamp::from(2)),
+            ("log.txt", 1, Timestamp::from(1)),
+        ]
+    );
+}
+
+#[test]
+fn test_analyze_depth() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    write(&target.join("top.txt"), "t", 1);
+    write(&target.join("x/f3.txt"), &"3".repeat(30), 1);
+    write(&target.join("x/y/f2.txt"), &"2".repeat(20), 1);
+    write(&target.join("x/y/z/f1.txt"), &"1".repeat(10), 1);
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let histories = vec![backup(&bank, 1)];
+
+    let cases = vec![
+        (0, vec![(".", 61)]),
+        (1, vec![("x", 60), (".", 1)]),
+        (2, vec![("x", 30), ("x/y", 30), (".", 1)]),
+        (3, vec![("x", 30), ("x/y", 20), ("x/y/z", 10), (".", 1)]),
+        (10, vec![("x", 30), ("x/y", 20), ("x/y/z", 10), (".", 1)]),
+    ];
+
+    for (depth, to_be) in cases {
+        let report = analyze(&bank, None, &histories, depth).unwrap();
+        assert_eq!(to_be, report.directory_bytes(), "depth = {}", depth);
+    }
+}
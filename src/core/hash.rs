@@ -1,39 +1,242 @@
 //! ファイルやディレクトリのハッシュの生成。
 
+use std::cell::RefCell;
+use std::error;
 use std::fmt;
 use std::fs::File;
-use std::io::{self, copy, Read, Seek, SeekFrom, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
 
-use failure::Fail;
-use hex::encode;
+use hex::decode;
+use serde::de::{self, Deserializer, Visitor};
+use serde::ser::Serializer;
 use serde::{Deserialize, Serialize};
+use sha3::digest::generic_array::typenum::U32;
+use sha3::digest::generic_array::GenericArray;
 use sha3::{Digest, Sha3_256};
 use tempfile::tempfile;
 
-const BUFFER_SIZE: usize = 4096;
+#[cfg(test)]
+mod test;
 
-/// エントリのSHA3-256ハッシュID
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
-pub struct HashID(String);
+// 読み込みのシステムコールの回数を減らすため、大きめのバッファを使う。
+const BUFFER_SIZE: usize = 256 * 1024;
+
+thread_local! {
+    static BUFFER: RefCell<Vec<u8>> = RefCell::new(vec![0u8; BUFFER_SIZE]);
+}
+
+/// ハッシュ値のバイト数
+pub const HASH_SIZE: usize = 32;
+
+/// ハッシュ値の計算に使うアルゴリズム
+///
+/// どのアルゴリズムも[`HASH_SIZE`]バイトのハッシュ値を生成する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Algorithm {
+    /// SHA3-256
+    Sha3,
+}
+
+/// タグを省略した文字列表現で使われるアルゴリズム
+///
+/// タグ導入前に作成されたリポジトリとの互換性のため、このアルゴリズムのIDはタグなしで表し、保存先も変えない。
+pub const DEFAULT_ALGORITHM: Algorithm = Algorithm::Sha3;
+
+impl Algorithm {
+    /// 文字列表現の先頭に付けるタグを返す。
+    ///
+    /// タグはオブジェクトの保存先ディレクトリ名にも使うため、4文字の16進数と紛らわしい名前は避けること。
+    pub fn tag(self) -> &'static str {
+        match self {
+            Algorithm::Sha3 => "sha3",
+        }
+    }
+
+    /// タグからアルゴリズムを返す。
+    pub fn from_tag(tag: &str) -> Option<Algorithm> {
+        match tag {
+            "sha3" => Some(Algorithm::Sha3),
+            _ => None,
+        }
+    }
+
+    /// 索引ファイルで使う1バイトの識別子を返す。
+    pub fn code(self) -> u8 {
+        match self {
+            Algorithm::Sha3 => 1,
+        }
+    }
+
+    /// 1バイトの識別子からアルゴリズムを返す。
+    pub fn from_code(code: u8) -> Option<Algorithm> {
+        match code {
+            1 => Some(Algorithm::Sha3),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Algorithm {
+    fn default() -> Algorithm {
+        DEFAULT_ALGORITHM
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.tag())
+    }
+}
+
+/// エントリのハッシュID
+///
+/// 内部ではアルゴリズムと固定長のバイト列として保持し、文字列表現は必要になった時点で生成する。
+/// 文字列表現は`sha3:`のようなアルゴリズムのタグに小文字の16進数を続けたものだが、
+/// [`DEFAULT_ALGORITHM`]のIDはタグを省略する。
+/// `{:#}`で表示した場合は常にタグを付ける。
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HashID {
+    algorithm: Algorithm,
+    bytes: [u8; HASH_SIZE],
+}
 
 impl HashID {
-    /// ハッシュ値の文字列表現を4-4-56文字に分割して返す。
+    fn from_digest(digest: GenericArray<u8, U32>) -> HashID {
+        let mut bytes = [0u8; HASH_SIZE];
+        bytes.copy_from_slice(&digest);
+        HashID::from_bytes(Algorithm::Sha3, bytes)
+    }
+
+    /// アルゴリズムとバイト列からIDを生成する。
+    pub fn from_bytes(algorithm: Algorithm, bytes: [u8; HASH_SIZE]) -> HashID {
+        HashID { algorithm, bytes }
+    }
+
+    /// タグを省略した16進数文字列からIDを生成する。
+    ///
+    /// 文字列が64文字の16進数でない場合はNoneを返す。
+    pub fn from_hex(s: &str) -> Option<HashID> {
+        HashID::from_hex_with(DEFAULT_ALGORITHM, s)
+    }
+
+    fn from_hex_with(algorithm: Algorithm, s: &str) -> Option<HashID> {
+        let decoded = decode(s).ok()?;
+        if decoded.len() != HASH_SIZE {
+            return None;
+        }
+
+        let mut bytes = [0u8; HASH_SIZE];
+        bytes.copy_from_slice(&decoded);
+        Some(HashID::from_bytes(algorithm, bytes))
+    }
+
+    /// `sha3:<hex>`形式またはタグを省略した形式の文字列からIDを生成する。
+    ///
+    /// 未知のタグや不正な16進数の場合はNoneを返す。
+    pub fn parse(s: &str) -> Option<HashID> {
+        match s.find(':') {
+            Some(i) => HashID::from_hex_with(Algorithm::from_tag(&s[..i])?, &s[i + 1..]),
+            None => HashID::from_hex(s),
+        }
+    }
+
+    /// ハッシュ値の計算に使ったアルゴリズムを返す。
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// ハッシュ値の16進数表現を4-4-56文字に分割して返す。
     ///
     /// リポジトリでの保存先ディレクトリの階層化に使われる。
-    pub fn parts(&self) -> (&str, &str, &str) {
-        let s = self.0.as_str();
-        (&s[0..4], &s[4..8], &s[8..])
+    pub fn parts(&self) -> (String, String, String) {
+        let s = hex::encode(self.bytes);
+        (s[0..4].to_owned(), s[4..8].to_owned(), s[8..].to_owned())
     }
 
-    /// 文字列表現への参照を返す。
-    pub fn as_str(&self) -> &str {
-        &self.0
+    /// オブジェクトディレクトリからの相対的な保存先を返す。
+    ///
+    /// [`DEFAULT_ALGORITHM`]以外のIDは、タグの名前のサブディレクトリ以下に保存される。
+    pub fn shard_path(&self) -> PathBuf {
+        let mut res = PathBuf::new();
+        if self.algorithm != DEFAULT_ALGORITHM {
+            res.push(self.algorithm.tag());
+        }
+
+        let (p0, p1, p2) = self.parts();
+        res.push(p0);
+        res.push(p1);
+        res.push(p2);
+        res
+    }
+
+    /// 文字列表現が`prefix`で始まるかどうかを返す。
+    ///
+    /// `prefix`はタグを含んでいてもよい。
+    pub fn starts_with(&self, prefix: &str) -> bool {
+        self.to_string().starts_with(prefix) || format!("{:#}", self).starts_with(prefix)
+    }
+
+    /// バイト列への参照を返す。
+    pub fn as_bytes(&self) -> &[u8; HASH_SIZE] {
+        &self.bytes
+    }
+}
+
+impl FromStr for HashID {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<HashID, String> {
+        HashID::parse(s).ok_or_else(|| format!("invalid hash ID: {}", s))
     }
 }
 
 impl fmt::Display for HashID {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        if f.alternate() || self.algorithm != DEFAULT_ALGORITHM {
+            write!(f, "{}:", self.algorithm.tag())?;
+        }
+        for b in self.bytes.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for HashID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HashID({:?})", self.to_string())
+    }
+}
+
+impl Serialize for HashID {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for HashID {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<HashID, D::Error> {
+        deserializer.deserialize_str(HashIDVisitor)
+    }
+}
+
+struct HashIDVisitor;
+
+impl<'de> Visitor<'de> for HashIDVisitor {
+    type Value = HashID;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "a hex string of {} bytes with optional algorithm tag",
+            HASH_SIZE
+        )
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<HashID, E> {
+        HashID::parse(v).ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
     }
 }
 
@@ -42,46 +245,111 @@ impl fmt::Display for HashID {
 /// ファイル`f`は一旦最後まで読み込まれた後、シーク位置が先頭に巻き戻される。
 pub fn hash(f: &mut File) -> Result<HashID> {
     let mut hasher = Sha3_256::new();
-    copy(f, &mut hasher)?;
+    with_buffer(|buffer| -> io::Result<()> {
+        loop {
+            let read_size = read_retry(f, buffer)?;
+            if read_size == 0 {
+                return Ok(());
+            }
+            hasher.input(&buffer[..read_size]);
+        }
+    })?;
     f.seek(SeekFrom::Start(0))?;
 
-    Ok(HashID(encode(hasher.result())))
+    Ok(HashID::from_digest(hasher.result()))
+}
+
+/// 逐次的にハッシュ値を計算する。
+#[derive(Default)]
+pub struct Hasher(Sha3_256);
+
+impl Hasher {
+    /// 空の`Hasher`を生成する。
+    pub fn new() -> Hasher {
+        Hasher::default()
+    }
+
+    /// `bytes`を追加する。
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.0.input(bytes);
+    }
+
+    /// これまでに追加された内容のハッシュ値を返す。
+    pub fn finish(self) -> HashID {
+        HashID::from_digest(self.0.result())
+    }
 }
 
 /// `r`から内容を一時ファイルにコピーしつつ、ハッシュ値を計算する。
 pub fn hash_reader<R: Read>(mut r: R) -> Result<(HashID, File)> {
     let mut hasher = Sha3_256::new();
     let mut temp = tempfile()?;
-    let mut buffer = [0u8; BUFFER_SIZE];
 
-    loop {
-        let read_size = r.read(&mut buffer)?;
-        if read_size == 0 {
-            break;
-        }
-        let bytes = &buffer[..read_size];
+    with_buffer(|buffer| -> io::Result<()> {
+        loop {
+            let read_size = read_retry(&mut r, buffer)?;
+            if read_size == 0 {
+                return Ok(());
+            }
+            let bytes = &buffer[..read_size];
 
-        hasher.write_all(&bytes)?;
-        temp.write_all(&bytes)?;
-    }
+            hasher.input(bytes);
+            temp.write_all(bytes)?;
+        }
+    })?;
 
     temp.flush()?;
     temp.seek(SeekFrom::Start(0))?; // 読み込みに備えてファイル先頭に巻き戻しておく
 
-    let hash = HashID(encode(hasher.result()));
+    let hash = HashID::from_digest(hasher.result());
 
     Ok((hash, temp))
 }
 
+// スレッドごとのバッファを貸し出す。
+//
+// ハッシュの計算中に再び呼ばれた場合は一時的なバッファを確保する。
+fn with_buffer<T, F: FnOnce(&mut [u8]) -> T>(f: F) -> T {
+    BUFFER.with(|buffer| match buffer.try_borrow_mut() {
+        Ok(mut buffer) => f(&mut buffer),
+        Err(_) => f(&mut vec![0u8; BUFFER_SIZE]),
+    })
+}
+
+// `Interrupted`で失敗した読み込みを再試行する。
+fn read_retry<R: Read>(r: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    loop {
+        match r.read(buffer) {
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            res => return res,
+        }
+    }
+}
+
 #[allow(missing_docs)]
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// ファイルシステムのスキャンで発生しうるエラー
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
     /// 入出力エラーが発生した
-    #[fail(display = "failed scan with IO error: {}", _0)]
-    IO(#[fail(cause)] io::Error),
+    IO(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IO(e) => write!(f, "failed scan with IO error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::IO(e) => Some(e),
+        }
+    }
 }
 
 impl From<io::Error> for Error {
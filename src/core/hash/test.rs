@@ -0,0 +1,67 @@
+use super::*;
+
+const HELLO: &str = "3338be694f50c5f338814986cdf0686453a888b84f424d792af4b9202398f392";
+
+#[test]
+fn test_hash_reader() {
+    let (id, _) = hash_reader("hello".as_bytes()).unwrap();
+    assert_eq!(id.to_string(), HELLO);
+
+    let (p0, p1, p2) = id.parts();
+    assert_eq!(format!("{}{}{}", p0, p1, p2), HELLO);
+    assert_eq!((p0.len(), p1.len(), p2.len()), (4, 4, 56));
+    assert!(id.starts_with("3338be69"));
+}
+
+#[test]
+fn test_serde_compatible() {
+    let id = HashID::from_hex(HELLO).unwrap();
+
+    let json = serde_json::to_string(&id).unwrap();
+    assert_eq!(json, format!("\"{}\"", HELLO));
+    assert_eq!(serde_json::from_str::<HashID>(&json).unwrap(), id);
+
+    assert!(serde_json::from_str::<HashID>("\"3338be69\"").is_err());
+    assert!(serde_json::from_str::<HashID>("\"not hex\"").is_err());
+
+    let tagged = format!("\"sha3:{}\"", HELLO);
+    assert_eq!(serde_json::from_str::<HashID>(&tagged).unwrap(), id);
+}
+
+#[test]
+fn test_algorithm_tag() {
+    let id = HashID::from_hex(HELLO).unwrap();
+    assert_eq!(id.algorithm(), Algorithm::Sha3);
+    assert_eq!(format!("{:#}", id), format!("sha3:{}", HELLO));
+
+    assert_eq!(HashID::parse(&format!("sha3:{}", HELLO)), Some(id.clone()));
+    assert_eq!(format!("sha3:{}", HELLO).parse::<HashID>().unwrap(), id);
+    assert_eq!(HashID::parse(&format!("md5:{}", HELLO)), None);
+    assert_eq!(HashID::parse("sha3:3338be69"), None);
+
+    assert!(id.starts_with("sha3:3338"));
+    assert!(!id.starts_with("sha3:4"));
+
+    // 既定のアルゴリズムは保存先を変えない
+    let (p0, p1, p2) = id.parts();
+    assert_eq!(id.shard_path(), PathBuf::from(p0).join(p1).join(p2));
+}
+
+#[test]
+fn test_larger_than_buffer() {
+    let data = (0..BUFFER_SIZE * 2 + 3)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+
+    let mut hasher = Hasher::new();
+    hasher.update(&data);
+    let expected = hasher.finish();
+
+    let (id, mut temp) = hash_reader(data.as_slice()).unwrap();
+    assert_eq!(id, expected);
+    assert_eq!(hash(&mut temp).unwrap(), expected);
+
+    let mut copied = Vec::new();
+    temp.read_to_end(&mut copied).unwrap();
+    assert_eq!(copied, data);
+}
@@ -2,24 +2,34 @@
 
 pub mod pattern;
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error;
 use std::ffi::OsString;
+use std::fmt;
+use std::fs;
 use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
 
 use log::trace;
-use pattern::{load_patterns, Match, Patterns};
+use pattern::{load_patterns_with, Pattern, Patterns, Syntax};
 
-use failure::Fail;
+use crate::core::timestamp::Timestamp;
+use crate::util::long_path;
 
 #[cfg(test)]
 mod test;
 
 const IGNORE_FILE: &str = ".sbakignore";
+const GIT_IGNORE_FILE: &str = ".gitignore";
 
 /// エントリのパスを表す。
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct EntryPath {
     parts: Vec<String>,
     is_dir: bool,
+    meta: Option<EntryMeta>,
 }
 
 impl EntryPath {
@@ -30,10 +40,16 @@ impl EntryPath {
     /// # Failures
     ///
     /// `entry`が`root`の子でないかパスの処理中に`root`の外に出た場合、[`Error::NotChild`](enum.Error.html#variant.NotChild)を返す。
+    ///
+    /// 片方のみが拡張パス形式(`\\?\`)の場合も、通常の形式に揃えて比較する。
     pub fn from_path(root: &Path, entry: &Path, is_dir: bool) -> Result<EntryPath> {
-        let relative = entry
-            .strip_prefix(&root)
-            .map_err(|_| Error::NotChild(entry.to_owned(), root.to_owned()))?;
+        let relative = match entry.strip_prefix(root) {
+            Ok(relative) => relative.to_owned(),
+            Err(_) => long_path::normal(entry)
+                .strip_prefix(long_path::normal(root))
+                .map(Path::to_owned)
+                .map_err(|_| Error::NotChild(entry.to_owned(), root.to_owned()))?,
+        };
 
         let mut parts = Vec::new();
 
@@ -57,7 +73,63 @@ impl EntryPath {
             }
         }
 
-        Ok(EntryPath { parts, is_dir })
+        Ok(EntryPath {
+            parts,
+            is_dir,
+            meta: None,
+        })
+    }
+
+    /// ルートディレクトリを表す`EntryPath`を生成する。
+    pub fn root() -> EntryPath {
+        EntryPath {
+            parts: Vec::new(),
+            is_dir: true,
+            meta: None,
+        }
+    }
+
+    /// 子エントリの`EntryPath`を生成する。
+    pub fn join(&self, name: &str, is_dir: bool) -> EntryPath {
+        let mut parts = self.parts.clone();
+        parts.push(name.to_owned());
+        EntryPath {
+            parts,
+            is_dir,
+            meta: None,
+        }
+    }
+
+    /// 条件付きのパターンの判定に使う属性を設定した`EntryPath`を返す。
+    pub fn with_meta(mut self, meta: EntryMeta) -> EntryPath {
+        self.meta = Some(meta);
+        self
+    }
+
+    /// エントリの名前を返す。
+    ///
+    /// ルートディレクトリの場合は`None`を返す。
+    pub fn name(&self) -> Option<&str> {
+        self.parts.last().map(|s| s.as_str())
+    }
+
+    /// ルートからの深さを返す。
+    ///
+    /// ルートディレクトリの直下のエントリの深さは1である。
+    pub fn depth(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// エントリがディレクトリであるかを返す。
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// エントリの属性を返す。
+    ///
+    /// 設定されていない場合、条件付きのパターンにはマッチしない。
+    pub fn meta(&self) -> Option<&EntryMeta> {
+        self.meta.as_ref()
     }
 
     /// エントリのルートからの相対パスのパーツのリストを返す。
@@ -66,92 +138,251 @@ impl EntryPath {
     }
 }
 
+impl fmt::Display for EntryPath {
+    /// `/`区切りの相対パスを表示する。
+    ///
+    /// ルートディレクトリは`.`と表示する。
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.parts.is_empty() {
+            write!(f, ".")
+        } else {
+            write!(f, "{}", self.parts.join("/"))
+        }
+    }
+}
+
+/// 条件付きのパターンの判定に使うエントリの属性
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntryMeta {
+    size: u64,
+    modified: Timestamp,
+}
+
+impl EntryMeta {
+    /// `EntryMeta`を生成する。
+    pub fn new(size: u64, modified: Timestamp) -> EntryMeta {
+        EntryMeta { size, modified }
+    }
+
+    /// ファイルシステムのメタデータから`EntryMeta`を生成する。
+    ///
+    /// 更新日時が取得できない場合はUNIX epochとみなす。
+    pub fn from_metadata(meta: &fs::Metadata) -> EntryMeta {
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|t| Timestamp::try_from(t).ok())
+            .unwrap_or_default();
+        EntryMeta::new(meta.len(), modified)
+    }
+
+    /// バイト単位のサイズを返す。
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// 更新日時を返す。
+    pub fn modified(&self) -> Timestamp {
+        self.modified
+    }
+}
+
 /// 除外判定の設定を親ディレクトリに遡るためのスタック。
+///
+/// スタックの底はBank由来の設定で、その上に各ディレクトリの`.sbakignore`の設定が積まれる。
+/// 各層のパターンは、その層のディレクトリからの相対パスで判定される。
+/// `!!reset`指示を含む層があれば、それより下の層は判定に使わない。
+///
+/// 書式が[`Syntax::Gitignore`](pattern/enum.Syntax.html#variant.Gitignore)の場合は`.gitignore`も読み込み、
+/// 同じディレクトリの`.sbakignore`の方を優先する。
 #[derive(Debug, Clone)]
-pub struct IgnoreStack<'a> {
-    root_path: PathBuf,
-    parent: Option<&'a IgnoreStack<'a>>,
-    current_patterns: Patterns,
+pub struct IgnoreStack {
+    // 底から順に、基準となるディレクトリとパターン
+    layers: Vec<(Rc<PathBuf>, Rc<Patterns>)>,
+    cache: Rc<RefCell<HashMap<PathBuf, Rc<Patterns>>>>,
+    syntax: Syntax,
 }
 
-impl<'a> IgnoreStack<'a> {
+impl IgnoreStack {
     /// 新たな除外パターンのスタックを作成する。
-    pub fn new(root_path: &'a Path, bank_patterns: Patterns) -> IgnoreStack<'a> {
+    ///
+    /// `bank_patterns`はバックアップ対象の起点ディレクトリ`root_path`からの相対パスで判定される。
+    pub fn new(root_path: &Path, bank_patterns: Patterns) -> IgnoreStack {
+        IgnoreStack::with_syntax(root_path, bank_patterns, Syntax::Sbak)
+    }
+
+    /// 各ディレクトリの除外ファイルを`syntax`の書式で読み込むスタックを作成する。
+    pub fn with_syntax(root_path: &Path, bank_patterns: Patterns, syntax: Syntax) -> IgnoreStack {
         IgnoreStack {
-            root_path: root_path.to_owned(),
-            parent: None,
-            current_patterns: bank_patterns,
+            layers: vec![(Rc::new(root_path.to_owned()), Rc::new(bank_patterns))],
+            cache: Rc::new(RefCell::new(HashMap::new())),
+            syntax,
         }
     }
 
-    /// ディレクトリ名を指定して子エントリ用の除外判定を生成する。
-    pub fn child<'b>(&'a self, dir_name: String) -> Result<IgnoreStack<'b>>
-    where
-        'a: 'b,
-    {
-        // スタックの底の場合はBank由来の設定なので、パスに加えない。
-        let root_path = if self.parent.is_some() {
-            self.root_path.join(&dir_name)
-        } else {
-            self.root_path.clone()
-        };
-
-        // 除外設定を読み込み
-        let ignore_file = root_path.join(IGNORE_FILE);
-        let current_patterns = if ignore_file.exists() {
-            load_patterns(&ignore_file)?
-        } else {
-            Patterns::default()
-        };
+    /// ディレクトリ`dir_path`の除外設定を積んだ、その子エントリ用の除外判定を生成する。
+    ///
+    /// `dir_path`の`.sbakignore`は一度だけ読み込まれ、以降はキャッシュされた内容が使われる。
+    pub fn child(&self, dir_path: &Path) -> Result<IgnoreStack> {
+        let mut layers = self.layers.clone();
+        if let Some(patterns) = self.load_patterns(dir_path)? {
+            layers.push((Rc::new(dir_path.to_owned()), patterns));
+        }
 
         Ok(IgnoreStack {
-            root_path,
-            parent: Some(&self),
-            current_patterns,
+            layers,
+            cache: self.cache.clone(),
+            syntax: self.syntax,
         })
     }
 
+    // 除外ファイルが無いか空の場合は`None`を返す。
+    fn load_patterns(&self, dir_path: &Path) -> Result<Option<Rc<Patterns>>> {
+        if let Some(patterns) = self.cache.borrow().get(dir_path) {
+            return Ok(Some(patterns.clone()));
+        }
+
+        let file_names: &[&str] = match self.syntax {
+            Syntax::Sbak => &[IGNORE_FILE],
+            Syntax::Gitignore => &[GIT_IGNORE_FILE, IGNORE_FILE],
+        };
+
+        let mut patterns = Patterns::default();
+        for name in file_names {
+            let ignore_file = dir_path.join(name);
+            if !ignore_file.exists() {
+                continue;
+            }
+
+            trace!("load ignore file {:?}", ignore_file);
+            patterns = patterns.merged(&load_patterns_with(&ignore_file, self.syntax)?);
+        }
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+
+        let patterns = Rc::new(patterns);
+        self.cache
+            .borrow_mut()
+            .insert(dir_path.to_owned(), patterns.clone());
+        Ok(Some(patterns))
+    }
+
     /// 除外対象かどうかチェックする。
     pub fn ignored(&self, path: &Path, is_dir: bool) -> Result<bool> {
-        let entry_path = EntryPath::from_path(&self.root_path, path, is_dir)?;
+        Ok(self
+            .explain(path, is_dir)?
+            .is_some_and(|(_, pattern)| !pattern.is_allow()))
+    }
 
-        match self.current_patterns.matches(&entry_path) {
-            Match::Allowed => return Ok(false),
-            Match::Ignored => return Ok(true),
-            _ => {
-                trace!("STACK not match {:?} at {:?}", path, self.root_path);
+    // `!!reset`指示を含む最も上の層から上の、判定に使う層を返す。
+    fn effective_layers(&self) -> &[(Rc<PathBuf>, Rc<Patterns>)] {
+        let start = self
+            .layers
+            .iter()
+            .rposition(|(_, patterns)| patterns.is_reset())
+            .unwrap_or(0);
+        &self.layers[start..]
+    }
+
+    /// 除外されたディレクトリ`dir_path`の中に、除外を取り消されうるエントリがあるかを返す。
+    ///
+    /// `dir_path`の中の除外ファイルは考慮しない。
+    pub fn may_allow_inside(&self, dir_path: &Path) -> Result<bool> {
+        for (base, patterns) in self.effective_layers() {
+            let entry_path = EntryPath::from_path(base, dir_path, true)?;
+            if patterns.may_allow_inside(&entry_path) {
+                return Ok(true);
             }
         }
 
-        if let Some(parent) = self.parent {
-            return parent.ignored(path, is_dir);
-        }
         Ok(false)
     }
+
+    /// 除外対象かどうかを決めたパターンと、そのパターンの基準となるディレクトリを返す。
+    ///
+    /// どのパターンにもマッチしない場合は`None`を返す。
+    /// 属性の条件付きのパターンにはマッチしない。
+    pub fn explain(&self, path: &Path, is_dir: bool) -> Result<Option<(&Path, &Pattern)>> {
+        self.explain_with(path, is_dir, None)
+    }
+
+    /// エントリのメタデータ`fs_meta`を使い、属性の条件付きのパターンも含めて[`explain`](#method.explain)と同様に判定する。
+    pub fn explain_entry(
+        &self,
+        path: &Path,
+        fs_meta: &fs::Metadata,
+    ) -> Result<Option<(&Path, &Pattern)>> {
+        let meta = EntryMeta::from_metadata(fs_meta);
+        self.explain_with(path, fs_meta.is_dir(), Some(meta))
+    }
+
+    fn explain_with(
+        &self,
+        path: &Path,
+        is_dir: bool,
+        meta: Option<EntryMeta>,
+    ) -> Result<Option<(&Path, &Pattern)>> {
+        for (dir_path, patterns) in self.effective_layers().iter().rev() {
+            let mut entry_path = EntryPath::from_path(dir_path, path, is_dir)?;
+            if let Some(meta) = meta {
+                entry_path = entry_path.with_meta(meta);
+            }
+
+            match patterns.find_match(&entry_path) {
+                Some(pattern) => return Ok(Some((dir_path.as_path(), pattern))),
+                None => {
+                    trace!("STACK not match {:?} at {:?}", path, dir_path);
+                }
+            }
+        }
+
+        Ok(None)
+    }
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
 /// ファイルの除外判定で発生しうるエラー
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
     /// 除外リストの読み込みに失敗した
-    #[fail(display = "failed load ignore patterns: {}", _0)]
-    IgnorePattern(#[fail(cause)] pattern::ParseError),
+    IgnorePattern(pattern::ParseError),
 
     /// エントリのパスがバックアップ対象のルートの子ではない。
-    #[fail(display = "invalid path: {:?} is not child of {:?} ", _0, _1)]
     NotChild(PathBuf, PathBuf),
 
     /// エントリのパスの一部が正しいUnicodeに変換できない。
-    #[fail(display = "invalid path: contains non-unicode part {:?} ", _0)]
     NotValidUnicode(OsString),
 
     /// エントリのパスの一部にファイル名ではない部分がある。
-    #[fail(display = "invalid path: contains non-normal part {} ", _0)]
     UnexpectedComponent(String),
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IgnorePattern(e) => write!(f, "failed load ignore patterns: {}", e),
+            Error::NotChild(a, b) => write!(f, "invalid path: {:?} is not child of {:?} ", a, b),
+            Error::NotValidUnicode(name) => {
+                write!(f, "invalid path: contains non-unicode part {:?} ", name)
+            }
+            Error::UnexpectedComponent(msg) => {
+                write!(f, "invalid path: contains non-normal part {} ", msg)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::IgnorePattern(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl From<pattern::ParseError> for Error {
     fn from(e: pattern::ParseError) -> Error {
         Error::IgnorePattern(e)
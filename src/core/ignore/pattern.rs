@@ -1,35 +1,103 @@
 //! 除外ファイルのパターンを表す。
 
+mod gitignore;
 mod parser;
+mod predicate;
+
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
 
 use super::EntryPath;
+use predicate::Predicate;
 
 #[cfg(test)]
 mod test;
 
-pub use parser::{load_patterns, parse, Error as ParseError};
+pub use gitignore::{convert_gitignore, ConvertIssue, Converted, IssueKind};
+pub use parser::{
+    load_patterns, load_patterns_with, parse, parse_lines, parse_lines_with, parse_with,
+    Error as ParseError,
+};
+
+/// 除外パターンの書式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Syntax {
+    /// sbak独自の書式
+    #[default]
+    Sbak,
+    /// gitの`.gitignore`と同じ規則に従う書式
+    Gitignore,
+}
 
 /// パターンのリストを表す。
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct Patterns {
     patterns: Vec<Pattern>,
+    reset: bool,
 }
 
 impl Patterns {
-    fn new(patterns: Vec<Pattern>) -> Patterns {
-        Patterns { patterns }
+    fn new(patterns: Vec<Pattern>, reset: bool) -> Patterns {
+        Patterns { patterns, reset }
     }
 
-    /// エントリがパターンにマッチするか検査する。
-    pub fn matches(&self, entry_path: &EntryPath) -> Match {
-        for pat in self.patterns.iter().rev() {
-            match pat.matches(entry_path) {
-                Match::Allowed => return Match::Allowed,
-                Match::Ignored => return Match::Ignored,
-                _ => {}
+    /// 他のパターンのリストを後ろに連結した新規の`Patterns`を返す。
+    ///
+    /// 後ろのパターンほど優先されるため、`overwrite`の判定が優先される。
+    pub fn merged(&self, overwrite: &Patterns) -> Patterns {
+        let mut patterns = self.patterns.clone();
+        patterns.extend_from_slice(&overwrite.patterns);
+        Patterns {
+            patterns,
+            reset: self.reset || overwrite.reset,
+        }
+    }
+
+    /// パターンも`!!reset`指示も含まないかを返す。
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty() && !self.reset
+    }
+
+    /// `!!reset`指示を含み、親ディレクトリの除外設定を引き継がないかを返す。
+    pub fn is_reset(&self) -> bool {
+        self.reset
+    }
+
+    /// 各パターンの記述元を`source`に置き換えた`Patterns`を返す。
+    pub fn with_source(mut self, source: &str) -> Patterns {
+        for pat in &mut self.patterns {
+            if let Some(origin) = pat.origin.as_mut() {
+                Arc::make_mut(origin).source = source.to_owned();
             }
         }
-        Match::Parent
+        self
+    }
+
+    /// エントリがパターンにマッチするか検査する。
+    pub fn matches(&self, entry_path: &EntryPath) -> Match {
+        self.find_match(entry_path)
+            .map(|pat| pat.matches(entry_path))
+            .unwrap_or(Match::Parent)
+    }
+
+    /// ディレクトリ`dir_path`の子孫の除外を取り消しうるパターンを含むかを返す。
+    pub fn may_allow_inside(&self, dir_path: &EntryPath) -> bool {
+        self.patterns
+            .iter()
+            .any(|pat| pat.may_allow_inside(dir_path))
+    }
+
+    /// エントリの判定を決めるパターンを返す。
+    ///
+    /// どのパターンにもマッチしない場合は`None`を返す。
+    pub fn find_match(&self, entry_path: &EntryPath) -> Option<&Pattern> {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pat| pat.matches(entry_path) != Match::Parent)
     }
 }
 
@@ -39,14 +107,40 @@ pub struct Pattern {
     parts: Vec<PatternPart>,
     allow: bool,
     dir_only: bool,
+    // 全て満たすファイルにのみマッチする、属性の条件
+    predicates: Vec<Predicate>,
+    origin: Option<Arc<Origin>>,
 }
 
 impl Pattern {
+    /// `!`で始まる、除外を取り消すパターンかどうかを返す。
+    pub fn is_allow(&self) -> bool {
+        self.allow
+    }
+
+    /// パターンの記述元を返す。
+    ///
+    /// 行単位でパースされたものでない場合は`None`を返す。
+    pub fn origin(&self) -> Option<&Origin> {
+        self.origin.as_deref()
+    }
+
     /// エントリがパターンにマッチするか検査する。
     pub fn matches(&self, entry_path: &EntryPath) -> Match {
         if self.dir_only && !entry_path.is_dir {
             return Match::Parent;
         }
+        if !self.predicates.is_empty() {
+            let satisfied = match entry_path.meta() {
+                Some(meta) if !entry_path.is_dir => {
+                    self.predicates.iter().all(|pred| pred.matches(meta))
+                }
+                _ => false,
+            };
+            if !satisfied {
+                return Match::Parent;
+            }
+        }
 
         if match_path(&self.parts, entry_path.parts()) {
             if self.allow {
@@ -59,6 +153,11 @@ impl Pattern {
         }
     }
 
+    /// ディレクトリ`dir_path`の子孫の除外を取り消しうるかを返す。
+    pub fn may_allow_inside(&self, dir_path: &EntryPath) -> bool {
+        self.allow && match_path_prefix(&self.parts, dir_path.parts())
+    }
+
     fn from_parts(
         allow: bool,
         cascade: bool,
@@ -92,43 +191,147 @@ impl Pattern {
             normalized_parts.push(last_part);
         }
 
+        // 末尾の`**`は中身の全てにマッチさせるため、1階層以上を要求する
+        if normalized_parts.last() == Some(&PatternPart::AnyPath) {
+            let any_name = NamePattern::from_parts(vec![NamePatternPart::AnyStr]);
+            normalized_parts.insert(normalized_parts.len() - 1, PatternPart::Normal(any_name));
+        }
+
         Pattern {
             parts: normalized_parts,
             allow,
             dir_only,
+            predicates: Vec::new(),
+            origin: None,
         }
     }
 }
 
+/// パターンの記述元
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    source: String,
+    line: usize,
+    text: String,
+}
+
+impl Origin {
+    /// 記述されていたファイルなどの名前を返す。
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// 1から始まる行番号を返す。
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// 記述されていたままのパターンを返す。
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl fmt::Display for Origin {
+    /// `git check-ignore -v`と同じ`<source>:<line>:<pattern>`の形式で表示する。
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.source, self.line, self.text)
+    }
+}
+
 fn match_path(parts: &[PatternPart], path: &[String]) -> bool {
-    if parts.is_empty() {
-        return path.is_empty();
+    simulate(
+        parts,
+        path.iter(),
+        |part| *part == PatternPart::AnyPath,
+        |part, name| match part {
+            PatternPart::Normal(pat) => pat.match_str(name),
+            PatternPart::AnyPath => true,
+        },
+    )
+}
+
+// パターンの要素列`tokens`が入力列`input`の全体にマッチするかを検査する。
+//
+// 各要素は入力の1要素にマッチするか、任意個(0個を含む)の入力にマッチする繰り返しである。
+// バックトラックせずに到達可能な状態の集合を更新していくので、計算量は`tokens`と`input`の長さの積に比例する。
+fn simulate<T, I, R, A>(tokens: &[T], input: I, is_repeat: R, accepts: A) -> bool
+where
+    I: Iterator,
+    R: Fn(&T) -> bool,
+    A: Fn(&T, &I::Item) -> bool,
+{
+    let close = |states: &mut [bool]| {
+        for i in 0..tokens.len() {
+            if states[i] && is_repeat(&tokens[i]) {
+                states[i + 1] = true;
+            }
+        }
+    };
+
+    let mut current = vec![false; tokens.len() + 1];
+    let mut next = vec![false; tokens.len() + 1];
+    current[0] = true;
+    close(&mut current);
+
+    for x in input {
+        next.iter_mut().for_each(|s| *s = false);
+        let mut alive = false;
+        for (i, token) in tokens.iter().enumerate() {
+            if !current[i] {
+                continue;
+            }
+            if is_repeat(token) {
+                next[i] = true;
+                alive = true;
+            } else if accepts(token, &x) {
+                next[i + 1] = true;
+                alive = true;
+            }
+        }
+        if !alive {
+            return false;
+        }
+        close(&mut next);
+        std::mem::swap(&mut current, &mut next);
     }
 
-    let p = &parts[0];
-    let left_parts = &parts[1..];
+    current[tokens.len()]
+}
 
-    match p {
-        PatternPart::Normal(pat) => {
-            if let Some(ref s) = path.first() {
-                if !pat.match_str(s) {
-                    return false;
-                }
-                let left_path = &path[1..];
-                return match_path(left_parts, left_path);
+// パターンの要素列`parts`が、`path`の子孫のいずれかにマッチしうるかを検査する。
+//
+// `path`を全て読み込んだ後に、さらに1要素以上を読み込める状態が残っていればマッチしうる。
+fn match_path_prefix(parts: &[PatternPart], path: &[String]) -> bool {
+    let mut current = vec![false; parts.len() + 1];
+    current[0] = true;
+    for (i, part) in parts.iter().enumerate() {
+        if current[i] && *part == PatternPart::AnyPath {
+            current[i + 1] = true;
+        }
+    }
+
+    for name in path {
+        let mut next = vec![false; parts.len() + 1];
+        for (i, part) in parts.iter().enumerate() {
+            if !current[i] {
+                continue;
+            }
+            match part {
+                PatternPart::AnyPath => next[i] = true,
+                PatternPart::Normal(pat) if pat.match_str(name) => next[i + 1] = true,
+                PatternPart::Normal(_) => {}
             }
-            false
         }
-        PatternPart::AnyPath => {
-            for drop_cnt in 0..path.len() {
-                let left_path = &path[drop_cnt..];
-                if match_path(left_parts, left_path) {
-                    return true;
-                }
+        for i in 0..parts.len() {
+            if next[i] && parts[i] == PatternPart::AnyPath {
+                next[i + 1] = true;
             }
-            false
         }
+        current = next;
     }
+
+    current[..parts.len()].iter().any(|s| *s)
 }
 
 /// 除外パターンのマッチ結果を表す。
@@ -151,6 +354,8 @@ enum PatternPart {
 #[derive(Debug, Clone, PartialEq)]
 struct NamePattern {
     parts: Vec<NamePatternPart>,
+    // 照合用に1文字ずつに展開したもの
+    tokens: Vec<NameToken>,
 }
 
 impl NamePattern {
@@ -184,85 +389,132 @@ impl NamePattern {
             normalized_parts.push(last_part);
         }
 
+        let tokens = normalized_parts
+            .iter()
+            .flat_map(|part| match part {
+                NamePatternPart::Str(s) => s.chars().map(NameToken::Char).collect(),
+                NamePatternPart::Class(class) => vec![NameToken::Class(class.clone())],
+                NamePatternPart::AnyChar => vec![NameToken::AnyChar],
+                NamePatternPart::AnyStr => vec![NameToken::AnyStr],
+            })
+            .collect();
+
         NamePattern {
             parts: normalized_parts,
+            tokens,
         }
     }
 
     fn match_str(&self, s: &str) -> bool {
-        match_np(&self.parts, s)
+        simulate(
+            &self.tokens,
+            s.chars(),
+            |token| *token == NameToken::AnyStr,
+            |token, c| match token {
+                NameToken::Char(ch) => ch == c,
+                NameToken::Class(class) => class.matches(*c),
+                NameToken::AnyChar | NameToken::AnyStr => true,
+            },
+        )
     }
 }
 
-fn match_np(parts: &[NamePatternPart], s: &str) -> bool {
-    if parts.is_empty() {
-        // マッチすべきパートがなく、その時点でマッチ対象文字列が空であることがマッチ成功の条件。
-        return s.is_empty();
-    }
-
-    let p = &parts[0];
-    let left_parts = &parts[1..];
+#[derive(Debug, Clone, PartialEq)]
+enum NameToken {
+    Char(char),
+    Class(CharClass),
+    AnyChar,
+    AnyStr,
+}
 
-    match p {
-        NamePatternPart::Str(ref ps) => {
-            // 単純な文字列へのマッチ
-            if s.starts_with(ps) {
-                // trim_start_matches は複数回取り除いてしまうので不可。
-                let (_, left_s) = s.split_at(ps.len());
-                match_np(left_parts, left_s)
-            } else {
-                false
-            }
-        }
-        NamePatternPart::AnyChar => {
-            // マッチ対象文字列がない場合失敗が確定。
-            if s.is_empty() {
-                return false;
-            }
-            let left_s = trim_char(s);
-            match_np(left_parts, left_s)
-        }
-        NamePatternPart::AnyStr => {
-            // 最後のパーツなら自明にマッチ成功
-            if left_parts.is_empty() {
-                return true;
-            }
+#[derive(Debug, Clone, PartialEq)]
+enum NamePatternPart {
+    Str(String),
+    Class(CharClass),
+    AnyChar,
+    AnyStr,
+}
 
-            let mut left_s = s;
-            while !left_s.is_empty() {
-                // 成功パターン
-                if match_np(left_parts, left_s) {
-                    return true;
-                }
+// `[a-z]`のような文字集合
+#[derive(Debug, Clone, PartialEq)]
+struct CharClass {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
 
-                // 1文字消費してバックトラック
-                left_s = trim_char(left_s);
-            }
-            // まだパターンのパーツがあり、全てのケースで失敗したので失敗。
-            false
-        }
+impl CharClass {
+    fn matches(&self, c: char) -> bool {
+        self.items.iter().any(|item| item.matches(c)) != self.negated
     }
 }
 
-fn trim_char(s: &str) -> &str {
-    let mut i = 1usize;
-    while i < s.len() {
-        if s.is_char_boundary(i) {
-            // 文字区切りが見つかったらそこで切断して返す。
-            let (_, left_s) = s.split_at(i);
-            return left_s;
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    Named(NamedClass),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ClassItem::Range(from, to) => *from <= c && c <= *to,
+            ClassItem::Named(named) => named.matches(c),
         }
-        i += 1;
     }
-    // 文字列が空でなく文字区切りが見つからないので、残っているのは1文字であるから、空文字列を返す。
-    ""
 }
 
-#[derive(Debug, Clone, PartialEq)]
-enum NamePatternPart {
-    Str(String),
-    AnyChar,
-    AnyStr,
+// `[:alpha:]`のようなPOSIXの文字クラス
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NamedClass {
+    Alnum,
+    Alpha,
+    Blank,
+    Cntrl,
+    Digit,
+    Graph,
+    Lower,
+    Print,
+    Punct,
+    Space,
+    Upper,
+    Xdigit,
+}
+
+impl NamedClass {
+    fn from_name(name: &str) -> Option<NamedClass> {
+        Some(match name {
+            "alnum" => NamedClass::Alnum,
+            "alpha" => NamedClass::Alpha,
+            "blank" => NamedClass::Blank,
+            "cntrl" => NamedClass::Cntrl,
+            "digit" => NamedClass::Digit,
+            "graph" => NamedClass::Graph,
+            "lower" => NamedClass::Lower,
+            "print" => NamedClass::Print,
+            "punct" => NamedClass::Punct,
+            "space" => NamedClass::Space,
+            "upper" => NamedClass::Upper,
+            "xdigit" => NamedClass::Xdigit,
+            _ => return None,
+        })
+    }
+
+    fn matches(self, c: char) -> bool {
+        match self {
+            NamedClass::Alnum => c.is_ascii_alphanumeric(),
+            NamedClass::Alpha => c.is_ascii_alphabetic(),
+            NamedClass::Blank => c == ' ' || c == '\t',
+            NamedClass::Cntrl => c.is_ascii_control(),
+            NamedClass::Digit => c.is_ascii_digit(),
+            NamedClass::Graph => c.is_ascii_graphic(),
+            NamedClass::Lower => c.is_ascii_lowercase(),
+            NamedClass::Print => c.is_ascii_graphic() || c == ' ',
+            NamedClass::Punct => c.is_ascii_punctuation(),
+            NamedClass::Space => c.is_ascii_whitespace() || c == '\x0b',
+            NamedClass::Upper => c.is_ascii_uppercase(),
+            NamedClass::Xdigit => c.is_ascii_hexdigit(),
+        }
+    }
 }
 
 impl NamePatternPart {
@@ -0,0 +1,348 @@
+//! gitの`.gitignore`と同じ規則でパターンを解釈する。
+//!
+//! 規則は`gitignore(5)`に従い、名前の照合は`wildmatch`と同じ動作をする。
+
+use super::*;
+
+#[cfg(test)]
+mod test;
+
+/// 1行をパースする。
+///
+/// コメント、空行、および無効なパターンの場合は`None`を返す。
+pub(super) fn parse_line(line: &str) -> Option<Pattern> {
+    // 行頭の`#`のみがコメント (前に空白があればパターンの一部)
+    if line.starts_with('#') {
+        return None;
+    }
+
+    let mut input = trim_trailing_spaces(line);
+    if input.is_empty() {
+        return None;
+    }
+
+    let allow = input.starts_with('!');
+    if allow {
+        input = &input[1..];
+    }
+
+    let dir_only = input.ends_with('/') && !ends_with_escape(input);
+    if dir_only {
+        input = &input[..input.len() - 1];
+    }
+
+    // 先頭または途中に`/`があればそのディレクトリからの相対パスとしてのみマッチする
+    let mut cascade = true;
+    if let Some(left) = input.strip_prefix('/') {
+        cascade = false;
+        input = left;
+    }
+
+    let mut parts = Vec::new();
+    for part_str in split_parts(input) {
+        if part_str == "**" {
+            parts.push(PatternPart::AnyPath);
+        } else {
+            parts.push(PatternPart::Normal(parse_name(part_str)?));
+        }
+    }
+    if parts.len() >= 2 {
+        cascade = false;
+    }
+
+    Some(Pattern::from_parts(allow, cascade, dir_only, parts))
+}
+
+// 末尾の空白を取り除く。`\`でエスケープされた空白は残す。
+fn trim_trailing_spaces(line: &str) -> &str {
+    let mut end = line.len();
+    let mut last_space: Option<usize> = None;
+    let mut chars = line.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            ' ' => {
+                if last_space.is_none() {
+                    last_space = Some(i);
+                }
+            }
+            '\\' => {
+                last_space = None;
+                chars.next();
+            }
+            _ => last_space = None,
+        }
+    }
+
+    if let Some(i) = last_space {
+        end = i;
+    }
+    &line[..end]
+}
+
+// `s`の末尾の文字が`\`でエスケープされているかを返す。
+fn ends_with_escape(s: &str) -> bool {
+    let body = &s[..s.len() - s.chars().last().map_or(0, char::len_utf8)];
+    body.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
+}
+
+// エスケープされていない`/`で分割する。
+fn split_parts(input: &str) -> Vec<&str> {
+    let mut res = Vec::new();
+    let mut start = 0;
+    let mut chars = input.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '/' => {
+                res.push(&input[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    res.push(&input[start..]);
+    res
+}
+
+// 1階層分の名前のパターンをパースする。
+//
+// 末尾の単独の`\`や閉じていない`[`はgitでは何にもマッチしないため、`None`を返す。
+fn parse_name(input: &str) -> Option<NamePattern> {
+    let chars = input.chars().collect::<Vec<_>>();
+    let mut res = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        match c {
+            '\\' => {
+                res.push(NamePatternPart::Str(chars.get(i)?.to_string()));
+                i += 1;
+            }
+            '*' => res.push(NamePatternPart::AnyStr),
+            '?' => res.push(NamePatternPart::AnyChar),
+            '[' => {
+                let (class, next) = parse_class(&chars, i)?;
+                res.push(NamePatternPart::Class(class));
+                i = next;
+            }
+            c => res.push(NamePatternPart::Str(c.to_string())),
+        }
+    }
+
+    Some(NamePattern::from_parts(res))
+}
+
+// `chars[i]`(`[`の直後)から`]`までをパースし、文字集合と`]`の次の位置を返す。
+fn parse_class(chars: &[char], mut i: usize) -> Option<(CharClass, usize)> {
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut items = Vec::new();
+    let start = i;
+    loop {
+        let c = *chars.get(i)?;
+        i += 1;
+        match c {
+            // 先頭の`]`は文字として扱う
+            ']' if i - 1 != start => return Some((CharClass { negated, items }, i)),
+            '[' if chars.get(i) == Some(&':') => {
+                let name_start = i + 1;
+                let name_len = chars[name_start..]
+                    .windows(2)
+                    .position(|w| w == [':', ']'])?;
+                let name = chars[name_start..name_start + name_len]
+                    .iter()
+                    .collect::<String>();
+                items.push(ClassItem::Named(NamedClass::from_name(&name)?));
+                i = name_start + name_len + 2;
+            }
+            c => {
+                let from = if c == '\\' {
+                    i += 1;
+                    *chars.get(i - 1)?
+                } else {
+                    c
+                };
+                let mut to = from;
+                // `-`の後が`]`なら`-`は文字として扱う
+                if chars.get(i) == Some(&'-') && chars.get(i + 1).is_some_and(|&c| c != ']') {
+                    i += 1;
+                    to = chars[i];
+                    i += 1;
+                    if to == '\\' {
+                        to = *chars.get(i)?;
+                        i += 1;
+                    }
+                }
+                items.push(ClassItem::Range(from, to));
+            }
+        }
+    }
+}
+
+/// `.gitignore`をsbak独自の書式に変換した結果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Converted {
+    /// 変換後の行。変換できなかった行はコメントとして残す。
+    pub lines: Vec<String>,
+    /// 変換で問題のあった行
+    pub issues: Vec<ConvertIssue>,
+}
+
+/// 変換で問題のあった行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConvertIssue {
+    /// 1から始まる行番号
+    pub line: usize,
+    /// 元の行
+    pub text: String,
+    /// 問題の種類
+    pub kind: IssueKind,
+}
+
+impl fmt::Display for ConvertIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.text, self.kind)
+    }
+}
+
+/// 変換で問題のあった行の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    /// gitでも何にもマッチしない無効なパターンのため、コメントにした
+    Invalid,
+    /// sbakの書式で表せないため、コメントにした
+    Unsupported(&'static str),
+    /// 変換したが、gitと異なり除外されたディレクトリの中でも除外を取り消す
+    AllowInsideExcluded,
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IssueKind::Invalid => write!(f, "invalid pattern ignored by git"),
+            IssueKind::Unsupported(reason) => write!(f, "unsupported: {}", reason),
+            IssueKind::AllowInsideExcluded => write!(
+                f,
+                "re-includes files inside an excluded directory, which git doesn't"
+            ),
+        }
+    }
+}
+
+/// `.gitignore`の各行を、同じエントリにマッチするsbak独自の書式の行に変換する。
+///
+/// コメントと空行はそのまま残す。
+/// 変換した行は、sbakの書式でパースした結果がgitの規則でパースした結果と一致することを確かめる。
+pub fn convert_gitignore<I, S>(lines: I) -> Converted
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut converted = Converted::default();
+    let mut previous = Vec::new();
+
+    for (i, line) in lines.into_iter().enumerate() {
+        let line = line.as_ref();
+        let mut issue = |kind| {
+            converted.issues.push(ConvertIssue {
+                line: i + 1,
+                text: line.to_owned(),
+                kind,
+            })
+        };
+
+        if line.starts_with('#') || trim_trailing_spaces(line).is_empty() {
+            converted.lines.push(line.to_owned());
+            continue;
+        }
+        let git_pattern = match parse_line(line) {
+            Some(pat) => pat,
+            None => {
+                issue(IssueKind::Invalid);
+                converted.lines.push(format!("# invalid: {}", line));
+                continue;
+            }
+        };
+        let text = match to_sbak(trim_trailing_spaces(line), &git_pattern) {
+            Ok(text) => text,
+            Err(reason) => {
+                issue(IssueKind::Unsupported(reason));
+                converted.lines.push(format!("# unsupported: {}", line));
+                continue;
+            }
+        };
+
+        if git_pattern.allow && allows_inside_excluded(&text, &previous) {
+            issue(IssueKind::AllowInsideExcluded);
+        }
+        previous.push(git_pattern);
+        converted.lines.push(text);
+    }
+    converted
+}
+
+// 末尾の空白を取り除いた1行を、sbakの書式に書き換える。
+//
+// 書き換えた結果が`expected`と同じパターンにならない場合は理由を返す。
+fn to_sbak(input: &str, expected: &Pattern) -> std::result::Result<String, &'static str> {
+    let mut text = String::with_capacity(input.len());
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            // sbakで特別な意味を持つ文字のエスケープのみ残す
+            '\\' => match chars.next() {
+                Some(c @ '\\') | Some(c @ '*') | Some(c @ '?') | Some(c @ '/') => {
+                    text.push('\\');
+                    text.push(c);
+                }
+                Some(c) => text.push(c),
+                None => return Err("trailing backslash"),
+            },
+            '[' => return Err("character class"),
+            c => text.push(c),
+        }
+    }
+
+    let patterns =
+        parser::parse_lines(vec![&text]).map_err(|_| "cannot be written in sbak syntax")?;
+    let mut actual = match (patterns.patterns.as_slice(), patterns.reset) {
+        ([pat], false) => pat.clone(),
+        _ => return Err("cannot be written in sbak syntax"),
+    };
+    actual.origin = None;
+    if &actual != expected {
+        return Err("cannot be written in sbak syntax");
+    }
+    Ok(text)
+}
+
+// 除外を取り消すパターン`text`の祖先のディレクトリが、`previous`で除外されるかを返す。
+//
+// gitでは除外されたディレクトリの中は読まれないため、そのようなパターンは効果が無い。
+// 判定できるのは、ワイルドカードを含まない祖先のディレクトリのみ。
+fn allows_inside_excluded(text: &str, previous: &[Pattern]) -> bool {
+    let patterns = Patterns::new(previous.to_vec(), false);
+    let body = text.trim_start_matches('!').trim_start_matches('/');
+    let parts = split_parts(body.trim_end_matches('/'));
+
+    let mut dir = EntryPath::root();
+    for name in &parts[..parts.len() - 1] {
+        if name.contains(&['*', '?', '\\'][..]) {
+            return false;
+        }
+        dir = dir.join(name, true);
+        if patterns.matches(&dir) == Match::Ignored {
+            return true;
+        }
+    }
+    false
+}
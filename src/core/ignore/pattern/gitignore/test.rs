@@ -0,0 +1,233 @@
+use super::*;
+
+// パターンを`path`に照合した結果。無効なパターンやコメントは`Match::Parent`になる。
+fn check(pattern: &str, path: &str, is_dir: bool) -> Match {
+    let mut entry = EntryPath::root();
+    let parts = path.split('/').collect::<Vec<_>>();
+    for (i, name) in parts.iter().enumerate() {
+        entry = entry.join(name, is_dir || i + 1 < parts.len());
+    }
+
+    match parse_line(pattern) {
+        Some(pat) => pat.matches(&entry),
+        None => Match::Parent,
+    }
+}
+
+const I: Match = Match::Ignored;
+const P: Match = Match::Parent;
+const A: Match = Match::Allowed;
+
+#[test]
+fn test_gitignore_corpus() {
+    #[rustfmt::skip]
+    let cases: &[(&str, &str, bool, Match)] = &[
+        // スラッシュを含まないパターンは全ての階層にマッチ
+        ("foo", "foo", false, I),
+        ("foo", "a/b/foo", false, I),
+        ("foo", "foo", true, I),
+        ("foo", "foobar", false, P),
+        // 先頭のスラッシュで起点に固定
+        ("/foo", "foo", false, I),
+        ("/foo", "a/foo", false, P),
+        // 途中のスラッシュでも起点に固定
+        ("doc/frotz", "doc/frotz", false, I),
+        ("doc/frotz", "a/doc/frotz", false, P),
+        ("doc/frotz/", "doc/frotz", true, I),
+        // 末尾のスラッシュはディレクトリのみ
+        ("foo/", "foo", true, I),
+        ("foo/", "foo", false, P),
+        ("foo/", "a/foo", true, I),
+        // ワイルドカード
+        ("*.txt", "a/b.txt", false, I),
+        ("*", ".hidden", false, I),
+        ("f?o", "foo", false, I),
+        ("f?o", "fo", false, P),
+        ("foo/*", "foo/bar", false, I),
+        ("foo/*", "foo/bar/baz", false, P),
+        ("a**b", "axyzb", false, I),
+        ("**.txt", "x/a.txt", false, I),
+        // `**`
+        ("**/foo", "foo", false, I),
+        ("**/foo", "a/b/foo", false, I),
+        ("**/foo/bar", "foo/bar", false, I),
+        ("**/foo/bar", "x/foo/bar", false, I),
+        ("abc/**", "abc/x", false, I),
+        ("abc/**", "abc/x/y", false, I),
+        ("abc/**", "abc", true, P),
+        ("a/**/b", "a/b", false, I),
+        ("a/**/b", "a/x/b", false, I),
+        ("a/**/b", "a/x/y/b", false, I),
+        ("a/**/b", "a/xb", false, P),
+        // コメントと空行
+        ("#foo", "#foo", false, P),
+        ("\\#foo", "#foo", false, I),
+        ("  #foo", "  #foo", false, I),
+        ("", "foo", false, P),
+        ("   ", "foo", false, P),
+        // 末尾の空白
+        ("foo   ", "foo", false, I),
+        ("foo\\ ", "foo ", false, I),
+        ("foo\\ ", "foo", false, P),
+        ("foo\\  ", "foo ", false, I),
+        ("\\ ", " ", false, I),
+        // 否定
+        ("!foo", "foo", false, A),
+        ("\\!foo", "!foo", false, I),
+        ("!/foo/", "foo", true, A),
+        // エスケープ
+        ("\\*", "*", false, I),
+        ("\\*", "x", false, P),
+        ("\\a\\b", "ab", false, I),
+        ("foo\\", "foo", false, P),
+        ("foo\\", "foo\\", false, P),
+        // 文字集合
+        ("[abc].txt", "b.txt", false, I),
+        ("[abc].txt", "d.txt", false, P),
+        ("[a-c]x", "bx", false, I),
+        ("[!a-c]x", "dx", false, I),
+        ("[!a-c]x", "ax", false, P),
+        ("[^a]x", "bx", false, I),
+        ("[]]", "]", false, I),
+        ("[!]]", "a", false, I),
+        ("[a-]", "-", false, I),
+        ("[\\]]", "]", false, I),
+        ("[[:digit:]]*", "1abc", false, I),
+        ("[[:digit:]]*", "abc", false, P),
+        ("[[:upper:][:digit:]]", "Q", false, I),
+        ("[abc", "a", false, P),
+        ("[abc", "[abc", false, P),
+        ("[[:bogus:]]", "b", false, P),
+    ];
+
+    for &(pattern, path, is_dir, expected) in cases {
+        assert_eq!(
+            check(pattern, path, is_dir),
+            expected,
+            "pattern {:?}, path {:?}, is_dir {}",
+            pattern,
+            path,
+            is_dir
+        );
+    }
+}
+
+#[test]
+fn test_gitignore_last_match_wins() {
+    let patterns = parse_lines_with(
+        ["*.log", "!keep.log", "# comment", "keep.log  "],
+        Syntax::Gitignore,
+    )
+    .unwrap();
+    let entry = EntryPath::root().join("keep.log", false);
+    assert_eq!(patterns.matches(&entry), Match::Ignored);
+
+    let patterns = parse_lines_with(["*.log", "!keep.log"], Syntax::Gitignore).unwrap();
+    assert_eq!(patterns.matches(&entry), Match::Allowed);
+}
+
+#[test]
+fn test_syntax_differences() {
+    // sbakの書式では不正なエスケープはエラーだが、gitでは文字そのもの
+    assert!(parse_lines(["foo\\x"]).is_err());
+    let patterns = parse_lines_with(["foo\\x"], Syntax::Gitignore).unwrap();
+    assert_eq!(
+        patterns.matches(&EntryPath::root().join("foox", false)),
+        Match::Ignored
+    );
+}
+
+#[test]
+fn test_convert_gitignore() {
+    let converted = convert_gitignore([
+        "# build outputs",
+        "",
+        "/target/",
+        "*.log   ",
+        "\\#notes",
+        "\\!important",
+        "docs/**/*.pdf",
+        "a\\bc",
+        "file\\*",
+        "[abc].txt",
+        "foo\\ ",
+        "bar\\",
+        "!keep.log",
+    ]);
+    assert_eq!(
+        converted.lines,
+        vec![
+            "# build outputs",
+            "",
+            "/target/",
+            "*.log",
+            "# unsupported: \\#notes",
+            "# unsupported: \\!important",
+            "docs/**/*.pdf",
+            "abc",
+            "file\\*",
+            "# unsupported: [abc].txt",
+            "# unsupported: foo\\ ",
+            "# invalid: bar\\",
+            "!keep.log",
+        ]
+    );
+    let kinds = converted
+        .issues
+        .iter()
+        .map(|issue| (issue.line, issue.kind))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            (
+                5,
+                IssueKind::Unsupported("cannot be written in sbak syntax")
+            ),
+            (
+                6,
+                IssueKind::Unsupported("cannot be written in sbak syntax")
+            ),
+            (10, IssueKind::Unsupported("character class")),
+            (
+                11,
+                IssueKind::Unsupported("cannot be written in sbak syntax")
+            ),
+            (12, IssueKind::Invalid),
+        ]
+    );
+
+    // 変換後の行はsbakの書式で読み込め、gitと同じ判定になる
+    let sbak = parse_lines(&converted.lines).unwrap();
+    for (path, is_dir) in &[
+        ("target", true),
+        ("sub/target", true),
+        ("a.log", false),
+        ("keep.log", false),
+        ("docs/x/y.pdf", false),
+        ("abc", false),
+        ("file*", false),
+    ] {
+        let mut entry = EntryPath::root();
+        let parts = path.split('/').collect::<Vec<_>>();
+        for (i, name) in parts.iter().enumerate() {
+            entry = entry.join(name, *is_dir || i + 1 < parts.len());
+        }
+        let git = parse_lines_with(&converted.lines, Syntax::Gitignore).unwrap();
+        assert_eq!(sbak.matches(&entry), git.matches(&entry), "{}", path);
+    }
+}
+
+#[test]
+fn test_convert_gitignore_allow_inside_excluded() {
+    let converted = convert_gitignore(["build/", "!build/keep.txt", "logs/*", "!logs/keep"]);
+    assert_eq!(converted.lines.len(), 4);
+    assert_eq!(
+        converted.issues,
+        vec![ConvertIssue {
+            line: 2,
+            text: "!build/keep.txt".to_owned(),
+            kind: IssueKind::AllowInsideExcluded,
+        }]
+    );
+}
@@ -1,15 +1,28 @@
+use std::error;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Read};
 use std::path::Path;
 
-use failure::Fail;
-
+use super::predicate::split_predicates;
 use super::*;
+use crate::core::timestamp::Timestamp;
+
+// 親ディレクトリの除外設定を引き継がない指示
+const RESET_DIRECTIVE: &str = "!!reset";
 
 /// 除外パターンファイルを読み込む。
 pub fn load_patterns<P: AsRef<Path>>(path: P) -> Result<Patterns> {
+    load_patterns_with(path, Syntax::Sbak)
+}
+
+/// 除外パターンファイルを`syntax`の書式で読み込む。
+///
+/// 各パターンの記述元は`path`になる。
+pub fn load_patterns_with<P: AsRef<Path>>(path: P, syntax: Syntax) -> Result<Patterns> {
+    let path = path.as_ref();
     let f = File::open(path)?;
-    parse(f)
+    Ok(parse_with(f, syntax)?.with_source(&path.display().to_string()))
 }
 
 /// パターンのリストをパースする。
@@ -17,12 +30,64 @@ pub fn load_patterns<P: AsRef<Path>>(path: P) -> Result<Patterns> {
 /// `#`で始まる行はコメントとみなす。
 /// 空行は無視される。
 pub fn parse<R: Read>(r: R) -> Result<Patterns> {
+    parse_with(r, Syntax::Sbak)
+}
+
+/// パターンのリストを`syntax`の書式でパースする。
+pub fn parse_with<R: Read>(r: R, syntax: Syntax) -> Result<Patterns> {
     let r = BufReader::new(r);
 
+    let lines = r.lines().collect::<io::Result<Vec<String>>>()?;
+    parse_lines_with(lines, syntax)
+}
+
+/// 1行ずつに分割済みのパターンのリストをパースする。
+///
+/// 行の扱いは[`parse`](fn.parse.html)と同じ。
+pub fn parse_lines<I, S>(lines: I) -> Result<Patterns>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    parse_lines_with(lines, Syntax::Sbak)
+}
+
+/// 1行ずつに分割済みのパターンのリストを`syntax`の書式でパースする。
+///
+/// どちらの書式でも、`!!reset`の行は親ディレクトリの除外設定を引き継がない指示として扱う。
+///
+/// [`Syntax::Gitignore`](../enum.Syntax.html#variant.Gitignore)の場合、gitが無効とみなすパターンは何にもマッチしないものとして読み飛ばす。
+pub fn parse_lines_with<I, S>(lines: I, syntax: Syntax) -> Result<Patterns>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
     let mut patterns = Vec::new();
+    let mut reset = false;
+    let now = Timestamp::now().unwrap_or_default();
+
+    for (i, line) in lines.into_iter().enumerate() {
+        let line = line.as_ref();
+        let origin = || {
+            Some(Arc::new(Origin {
+                source: String::new(),
+                line: i + 1,
+                text: line.to_owned(),
+            }))
+        };
+
+        if line.trim() == RESET_DIRECTIVE {
+            reset = true;
+            continue;
+        }
 
-    for line in r.lines() {
-        let line = line?;
+        if syntax == Syntax::Gitignore {
+            if let Some(mut pat) = gitignore::parse_line(line) {
+                pat.origin = origin();
+                patterns.push(pat);
+            }
+            continue;
+        }
 
         // コメント行をスキップ
         if line.trim_start().starts_with('#') {
@@ -33,11 +98,21 @@ pub fn parse<R: Read>(r: R) -> Result<Patterns> {
             continue;
         }
 
-        let pat = parse_pattern(&line)?;
+        let (line_pattern, predicates) =
+            split_predicates(line, now).map_err(Error::InvalidPredicate)?;
+        // 条件のみの行は全てのファイルを対象にする
+        let line_pattern = match line_pattern {
+            "" if !predicates.is_empty() => "*",
+            "!" if !predicates.is_empty() => "!*",
+            p => p,
+        };
+        let mut pat = parse_pattern(line_pattern)?;
+        pat.predicates = predicates;
+        pat.origin = origin();
         patterns.push(pat);
     }
 
-    Ok(Patterns::new(patterns))
+    Ok(Patterns::new(patterns, reset))
 }
 
 fn parse_pattern(mut input: &str) -> Result<Pattern> {
@@ -80,24 +155,25 @@ fn pattern_split(mut input: &str) -> Result<Vec<String>> {
     while !input.is_empty() {
         let mut part = String::new();
 
-        'part: while !input.is_empty() {
-            while let Some((_, left)) = trim_if_match(input, r"\/") {
+        while let Some((ch, left)) = trim_char(input) {
+            if let Some((_, left)) = trim_if_match(input, r"\/") {
                 part.push('/');
                 input = left;
+                continue;
             }
 
-            while let Some((ch, left)) = trim_char(input) {
-                // エスケープシーケンスの先頭なのでパースし直す。
-                if ch == '\\' {
-                    continue 'part;
-                }
+            input = left;
+            if ch == '/' {
+                break;
+            }
 
-                input = left;
-                if ch == '/' {
-                    break 'part;
+            part.push(ch);
+            // エスケープシーケンスは名前のパースで処理するため、次の文字ごと残す。
+            if ch == '\\' {
+                if let Some((next, left)) = trim_char(input) {
+                    part.push(next);
+                    input = left;
                 }
-
-                part.push(ch)
             }
         }
         res.push(part);
@@ -192,15 +268,35 @@ mod test;
 type Result<T> = std::result::Result<T, Error>;
 
 /// パターンのパースで発生しうるエラー
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
     /// パターン表現の文字列が不正である。
-    #[fail(display = "invalid pattern string: {}", _0)]
     InvalidPattern(String),
 
+    /// パターンに付加した属性の条件が不正である。
+    InvalidPredicate(String),
+
     /// 入出力エラー
-    #[fail(display = "failed scan with IO error: {}", _0)]
-    IO(#[fail(cause)] io::Error),
+    IO(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidPattern(msg) => write!(f, "invalid pattern string: {}", msg),
+            Error::InvalidPredicate(msg) => write!(f, "invalid predicate: {}", msg),
+            Error::IO(e) => write!(f, "failed scan with IO error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::IO(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 impl From<io::Error> for Error {
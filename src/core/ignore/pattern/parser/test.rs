@@ -124,3 +124,16 @@ fn test_parse_name_pattern_fails() {
         let _ = parse_name_pattern(pat_str).unwrap_err();
     }
 }
+
+#[test]
+fn test_parse_reset_directive() {
+    let patterns = parse_lines(["*.log", "  !!reset  "]).unwrap();
+    assert!(patterns.is_reset());
+    assert_eq!(patterns.patterns.len(), 1);
+
+    let only_reset = parse_lines_with(["!!reset"], Syntax::Gitignore).unwrap();
+    assert!(only_reset.is_reset());
+    assert!(!only_reset.is_empty());
+
+    assert!(!parse_lines(["!reset"]).unwrap().is_reset());
+}
@@ -0,0 +1,149 @@
+//! パターンに付加する、エントリの属性に対する条件を扱う。
+//!
+//! 条件は`*.iso size>100M`のように、パターンの後に空白区切りで書く。
+
+use super::super::EntryMeta;
+use crate::core::timestamp::Timestamp;
+use crate::util::size::Size;
+use crate::util::time::{parse_duration, parse_local_datetime};
+
+#[cfg(test)]
+mod test;
+
+/// エントリの属性に対する条件
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct Predicate {
+    attr: Attr,
+    op: CompareOp,
+    value: u64,
+}
+
+impl Predicate {
+    /// エントリの属性が条件を満たすかを返す。
+    pub(super) fn matches(&self, meta: &EntryMeta) -> bool {
+        let actual = match self.attr {
+            Attr::Size => meta.size(),
+            Attr::Modified => meta.modified().unix_epoch(),
+        };
+        match self.op {
+            CompareOp::Less => actual < self.value,
+            CompareOp::LessEq => actual <= self.value,
+            CompareOp::Greater => actual > self.value,
+            CompareOp::GreaterEq => actual >= self.value,
+        }
+    }
+}
+
+// 比較する属性
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Attr {
+    // バイト単位のサイズ
+    Size,
+    // 更新日時のUNIX epochからの経過秒数
+    Modified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+}
+
+impl CompareOp {
+    // 左右を入れ替えた比較を返す。
+    fn flipped(self) -> CompareOp {
+        match self {
+            CompareOp::Less => CompareOp::Greater,
+            CompareOp::LessEq => CompareOp::GreaterEq,
+            CompareOp::Greater => CompareOp::Less,
+            CompareOp::GreaterEq => CompareOp::LessEq,
+        }
+    }
+}
+
+/// 行末の条件を取り除き、残りのパターン部分と条件のリストを返す。
+///
+/// 条件は`size`, `mtime`, `age`のいずれかに比較演算子と値を続けたもので、条件として解釈できない語に達した時点で終了する。
+/// `age`は`now`を基準とした更新日時の条件に変換される。
+///
+/// # Failures
+///
+/// 値が不正な条件がある場合、その条件の文字列を返す。
+pub(super) fn split_predicates(
+    line: &str,
+    now: Timestamp,
+) -> Result<(&str, Vec<Predicate>), String> {
+    let mut rest = line.trim_end();
+    let mut predicates = Vec::new();
+
+    loop {
+        let (head, word) = match rest.rfind(char::is_whitespace) {
+            Some(i) => (&rest[..i], &rest[i + 1..]),
+            None => ("", rest),
+        };
+        // 否定のパターンの`!`は条件に含めない
+        let (word, allow) = match word.strip_prefix('!') {
+            Some(word) if head.is_empty() => (word, "!"),
+            _ => (word, ""),
+        };
+
+        match parse_predicate(word, now)? {
+            Some(predicate) => predicates.push(predicate),
+            None => break,
+        }
+        if head.is_empty() {
+            rest = allow;
+            break;
+        }
+        rest = head.trim_end();
+    }
+
+    predicates.reverse();
+    Ok((rest, predicates))
+}
+
+// 条件でない語の場合は`None`を返す。
+fn parse_predicate(word: &str, now: Timestamp) -> Result<Option<Predicate>, String> {
+    let (name, rest) = match word.find(['<', '>']) {
+        Some(i) => word.split_at(i),
+        None => return Ok(None),
+    };
+    if !["size", "mtime", "age"].contains(&name) {
+        return Ok(None);
+    }
+
+    let (op, value) = if let Some(v) = rest.strip_prefix("<=") {
+        (CompareOp::LessEq, v)
+    } else if let Some(v) = rest.strip_prefix(">=") {
+        (CompareOp::GreaterEq, v)
+    } else if let Some(v) = rest.strip_prefix('<') {
+        (CompareOp::Less, v)
+    } else {
+        (CompareOp::Greater, &rest[1..])
+    };
+
+    let invalid = || word.to_owned();
+    let predicate = match name {
+        "size" => Predicate {
+            attr: Attr::Size,
+            op,
+            value: value.parse::<Size>().map_err(|_| invalid())?.bytes(),
+        },
+        "mtime" => Predicate {
+            attr: Attr::Modified,
+            op,
+            value: parse_local_datetime(value).ok_or_else(invalid)?,
+        },
+        _ => Predicate {
+            // 経過時間が長いほど更新日時は古い
+            attr: Attr::Modified,
+            op: op.flipped(),
+            value: now
+                .unix_epoch()
+                .saturating_sub(parse_duration(value).ok_or_else(invalid)?),
+        },
+    };
+    Ok(Some(predicate))
+}
@@ -0,0 +1,52 @@
+use super::*;
+
+#[test]
+fn test_split_predicates() {
+    let now = Timestamp::from(10 * 86400);
+    let cases = vec![
+        ("*.iso", "*.iso", 0),
+        ("*.iso size>100M", "*.iso", 1),
+        ("dir/*.log  size>=1K   age>7d ", "dir/*.log", 2),
+        ("size>1G", "", 1),
+        ("!size<1K", "!", 1),
+        ("!keep.txt size<1K", "!keep.txt", 1),
+        ("my file.txt", "my file.txt", 0),
+        ("size>1G foo", "size>1G foo", 0),
+        ("sizes>1G", "sizes>1G", 0),
+    ];
+
+    for (line, pattern, count) in cases {
+        let (actual, predicates) = split_predicates(line, now).unwrap();
+        assert_eq!(pattern, actual, "line = {:?}", line);
+        assert_eq!(count, predicates.len(), "line = {:?}", line);
+    }
+}
+
+#[test]
+fn test_split_predicates_invalid() {
+    let now = Timestamp::from(0);
+    for line in &["a size>big", "a mtime<yesterday", "a age>3y", "a size>"] {
+        assert!(split_predicates(line, now).is_err(), "line = {:?}", line);
+    }
+}
+
+#[test]
+fn test_predicate_matches() {
+    let now = Timestamp::from(100 * 86400);
+    let parse = |word: &str| parse_predicate(word, now).unwrap().unwrap();
+    let meta = EntryMeta::new(2048, Timestamp::from(90 * 86400));
+
+    assert!(parse("size>1K").matches(&meta));
+    assert!(parse("size>=2K").matches(&meta));
+    assert!(!parse("size<2K").matches(&meta));
+    assert!(parse("size<=2K").matches(&meta));
+
+    // 更新日時は10日前
+    assert!(parse("age>7d").matches(&meta));
+    assert!(!parse("age>2w").matches(&meta));
+    assert!(!parse("age<240h").matches(&meta));
+    assert!(parse("age<=240h").matches(&meta));
+
+    assert!(parse("mtime<2100-01-01").matches(&meta));
+    assert!(!parse("mtime>1970-12-31T00:00:00").matches(&meta));
+}
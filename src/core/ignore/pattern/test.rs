@@ -168,3 +168,96 @@ fn test_match_name_pattern() {
         }
     }
 }
+
+#[test]
+fn test_merged_overwrite_has_priority() {
+    let base = parse_lines(["*.log", "!keep.txt"]).unwrap();
+    let overwrite = parse_lines(["!important.log", "*.txt"]).unwrap();
+    let merged = base.merged(&overwrite);
+
+    let root = PathBuf::from("/d");
+
+    let cases = vec![
+        (Match::Ignored, "/d/a.log"),
+        (Match::Allowed, "/d/important.log"),
+        (Match::Ignored, "/d/keep.txt"),
+        (Match::Parent, "/d/a.png"),
+    ];
+
+    for (to_be, path_str) in cases {
+        let path = PathBuf::from(path_str);
+        let entry_path = EntryPath::from_path(&root, &path, false).unwrap();
+        let actual = merged.matches(&entry_path);
+
+        assert_eq!(to_be, actual, "path = {}", path_str);
+    }
+}
+
+#[test]
+fn test_match_many_wildcards_linear() {
+    let patterns = parse_lines([
+        "*a*a*a*a*a*a*a*a*a*a*a*a*b",
+        "**/x/**/x/**/x/**/x/**/x/**/y",
+    ])
+    .unwrap();
+    let root = PathBuf::from("/d");
+
+    let name = "a".repeat(5000);
+    let entry_path = EntryPath::from_path(&root, &root.join(&name), false).unwrap();
+    assert_eq!(patterns.matches(&entry_path), Match::Parent);
+
+    let mut deep = root.clone();
+    for _ in 0..500 {
+        deep.push("x");
+    }
+    let entry_path = EntryPath::from_path(&root, &deep, false).unwrap();
+    assert_eq!(patterns.matches(&entry_path), Match::Parent);
+
+    let entry_path = EntryPath::from_path(&root, &deep.join("y"), false).unwrap();
+    assert_eq!(patterns.matches(&entry_path), Match::Ignored);
+}
+
+#[test]
+fn test_match_trailing_any_path() {
+    let patterns = parse_lines(["a/**"]).unwrap();
+    let root = PathBuf::from("/d");
+
+    let cases = vec![
+        (Match::Parent, "/d/a", true),
+        (Match::Ignored, "/d/a/b", false),
+        (Match::Ignored, "/d/a/b/c", true),
+        (Match::Parent, "/d/b/a/c", false),
+    ];
+
+    for (to_be, path_str, is_dir) in cases {
+        let entry_path = EntryPath::from_path(&root, &PathBuf::from(path_str), is_dir).unwrap();
+        assert_eq!(to_be, patterns.matches(&entry_path), "path = {}", path_str);
+    }
+}
+
+#[test]
+fn test_may_allow_inside() {
+    let root = PathBuf::from("/d");
+    let cases = vec![
+        ("!dir/keep.txt", "/d/dir", true),
+        ("!dir/keep.txt", "/d/other", false),
+        ("!dir/keep.txt", "/d/dir/sub", false),
+        ("!/a/**/b", "/d/a/x/y", true),
+        ("!*.txt", "/d/a/b", true),
+        ("!dir/", "/d/dir", true),
+        ("!/dir/", "/d/dir", false),
+        ("dir/keep.txt", "/d/dir", false),
+    ];
+
+    for (line, path_str, to_be) in cases {
+        let patterns = parse_lines([line]).unwrap();
+        let entry_path = EntryPath::from_path(&root, &PathBuf::from(path_str), true).unwrap();
+        assert_eq!(
+            to_be,
+            patterns.may_allow_inside(&entry_path),
+            "pattern = {}, path = {}",
+            line,
+            path_str
+        );
+    }
+}
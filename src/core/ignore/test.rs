@@ -1,7 +1,11 @@
+use super::pattern::parse_lines;
 use super::*;
 
+use std::fs;
 use std::path::PathBuf;
 
+use tempfile::tempdir;
+
 #[test]
 #[cfg(target_os = "windows")]
 fn test_entry_path_new_sucess_with_absolute_path_win() {
@@ -101,3 +105,140 @@ fn test_entry_path_new_success_with_parent() {
     let ep = EntryPath::from_path(&root, &entry, false).unwrap();
     assert_eq!(ep.parts(), &["b".to_owned(), "c.txt".to_owned()],);
 }
+
+fn write_ignore(dir: &Path, lines: &str) {
+    fs::create_dir_all(dir).unwrap();
+    fs::write(dir.join(IGNORE_FILE), lines).unwrap();
+}
+
+#[test]
+fn test_ignore_stack_nested_files() {
+    let temp = tempdir().unwrap();
+    let root = temp.path();
+    write_ignore(root, "*.log\n");
+    write_ignore(&root.join("a"), "x.txt\n");
+    write_ignore(&root.join("a/b"), "y.txt\n!keep.log\n");
+
+    let base = IgnoreStack::new(root, Patterns::default());
+    let top = base.child(root).unwrap();
+    let a = top.child(&root.join("a")).unwrap();
+    let b = a.child(&root.join("a/b")).unwrap();
+
+    assert!(top.ignored(&root.join("t.log"), false).unwrap());
+    assert!(!top.ignored(&root.join("x.txt"), false).unwrap());
+
+    assert!(a.ignored(&root.join("a/x.txt"), false).unwrap());
+    assert!(a.ignored(&root.join("a/t.log"), false).unwrap());
+    assert!(!a.ignored(&root.join("a/y.txt"), false).unwrap());
+
+    assert!(b.ignored(&root.join("a/b/y.txt"), false).unwrap());
+    assert!(b.ignored(&root.join("a/b/x.txt"), false).unwrap());
+    assert!(!b.ignored(&root.join("a/b/z.txt"), false).unwrap());
+    assert!(b.ignored(&root.join("a/b/t.log"), false).unwrap());
+    assert!(!b.ignored(&root.join("a/b/keep.log"), false).unwrap());
+}
+
+#[test]
+fn test_ignore_stack_loads_once() {
+    let temp = tempdir().unwrap();
+    let root = temp.path();
+    write_ignore(&root.join("a"), "x.txt\n");
+
+    let base = IgnoreStack::new(root, Patterns::default());
+    let top = base.child(root).unwrap();
+    let a = top.child(&root.join("a")).unwrap();
+
+    // 読み込み済みのファイルは再度読み込まない
+    fs::write(root.join("a").join(IGNORE_FILE), "y.txt\n").unwrap();
+    let again = top.child(&root.join("a")).unwrap();
+
+    assert!(a.ignored(&root.join("a/x.txt"), false).unwrap());
+    assert!(again.ignored(&root.join("a/x.txt"), false).unwrap());
+    assert!(!again.ignored(&root.join("a/y.txt"), false).unwrap());
+}
+
+#[test]
+fn test_ignore_stack_gitignore_syntax() {
+    let temp = tempdir().unwrap();
+    let root = temp.path();
+    fs::write(root.join(GIT_IGNORE_FILE), "*.o\nbuild/\n[Tt]emp*\n").unwrap();
+    write_ignore(root, "!keep.o\n");
+
+    let sbak = IgnoreStack::new(root, Patterns::default())
+        .child(root)
+        .unwrap();
+    assert!(!sbak.ignored(&root.join("a.o"), false).unwrap());
+
+    let git = IgnoreStack::with_syntax(root, Patterns::default(), Syntax::Gitignore)
+        .child(root)
+        .unwrap();
+    assert!(git.ignored(&root.join("a.o"), false).unwrap());
+    assert!(git.ignored(&root.join("build"), true).unwrap());
+    assert!(!git.ignored(&root.join("build"), false).unwrap());
+    assert!(git.ignored(&root.join("Temp1"), false).unwrap());
+    // 同じディレクトリでは`.sbakignore`が優先される
+    assert!(!git.ignored(&root.join("keep.o"), false).unwrap());
+}
+
+#[test]
+fn test_ignore_stack_explain() {
+    let temp = tempdir().unwrap();
+    let root = temp.path();
+    write_ignore(root, "*.log\n");
+    write_ignore(&root.join("a"), "# comment\n!keep.log\n");
+
+    let bank_patterns = parse_lines(["*.tmp"]).unwrap().with_source("bank");
+    let a = IgnoreStack::new(root, bank_patterns)
+        .child(root)
+        .unwrap()
+        .child(&root.join("a"))
+        .unwrap();
+
+    let (base, pattern) = a.explain(&root.join("a/keep.log"), false).unwrap().unwrap();
+    assert_eq!(base, root.join("a"));
+    assert!(pattern.is_allow());
+    let origin = pattern.origin().unwrap();
+    assert_eq!(
+        origin.source(),
+        root.join("a").join(IGNORE_FILE).display().to_string()
+    );
+    assert_eq!(origin.line(), 2);
+    assert_eq!(origin.text(), "!keep.log");
+
+    let (base, pattern) = a.explain(&root.join("a/drop.log"), false).unwrap().unwrap();
+    assert_eq!(base, root);
+    assert_eq!(pattern.origin().unwrap().line(), 1);
+
+    let (_, pattern) = a.explain(&root.join("a/x.tmp"), false).unwrap().unwrap();
+    assert_eq!(pattern.origin().unwrap().source(), "bank");
+
+    assert!(a.explain(&root.join("a/x.txt"), false).unwrap().is_none());
+}
+
+#[test]
+fn test_ignore_stack_reset() {
+    let temp = tempdir().unwrap();
+    let root = temp.path();
+    write_ignore(root, "*.log\nvendor/*.txt\n");
+    write_ignore(&root.join("vendor"), "!!reset\n*.tmp\n");
+    write_ignore(&root.join("vendor/sub"), "a.txt\n");
+
+    let bank_patterns = parse_lines(["*.bak"]).unwrap();
+    let top = IgnoreStack::new(root, bank_patterns).child(root).unwrap();
+    let vendor = top.child(&root.join("vendor")).unwrap();
+    let sub = vendor.child(&root.join("vendor/sub")).unwrap();
+
+    // `vendor`自身の判定には親の設定が使われる
+    assert!(top.ignored(&root.join("vendor/x.txt"), false).unwrap());
+    assert!(top.ignored(&root.join("x.bak"), false).unwrap());
+
+    assert!(!vendor.ignored(&root.join("vendor/x.txt"), false).unwrap());
+    assert!(!vendor.ignored(&root.join("vendor/x.log"), false).unwrap());
+    assert!(!vendor.ignored(&root.join("vendor/x.bak"), false).unwrap());
+    assert!(vendor.ignored(&root.join("vendor/x.tmp"), false).unwrap());
+
+    // 子ディレクトリには`!!reset`の層から上の設定が引き継がれる
+    assert!(sub.ignored(&root.join("vendor/sub/a.txt"), false).unwrap());
+    assert!(sub.ignored(&root.join("vendor/sub/b.tmp"), false).unwrap());
+    assert!(!sub.ignored(&root.join("vendor/sub/b.log"), false).unwrap());
+}
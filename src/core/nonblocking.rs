@@ -0,0 +1,339 @@
+//! 非同期処理から使用するためのAPI
+//!
+//! `async` featureを有効にした場合のみ使用できる。
+//!
+//! 各処理は共有のワーカースレッドのプールで実行され、完了すると結果を返す`Future`を返す。
+//! プールのスレッド数はCPU数までに制限され、空きが無い間は処理が待たされる。
+//! 返される`Future`は特定の非同期ランタイムに依存しないため、どのランタイムからでも`.await`できる。
+//! 処理はこの関数を呼んだ時点で開始を予約され、`Future`を破棄しても中断されない。
+
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use lazy_static::lazy_static;
+
+use crate::core::entry::FsHash;
+use crate::core::extend::{self, Extender, Symlinks};
+use crate::core::hash::{self, hash_reader, HashID};
+use crate::core::repo::{self, History, Repository, ScanStats, VerifyingReader};
+use crate::core::scan::{self, ScannerBuilder};
+use crate::core::timestamp::Timestamp;
+
+#[cfg(test)]
+mod test;
+
+/// [`ObjectReader::read_chunk`](struct.ObjectReader.html#method.read_chunk)で一度に読み込む最大のバイト数
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+lazy_static! {
+    // 最初に使われたときにワーカースレッドを起動する
+    static ref POOL: Mutex<Sender<Job>> = Mutex::new(start_workers(worker_count()));
+}
+
+/// ワーカースレッドのプールのスレッド数を返す。
+pub fn worker_count() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+fn start_workers(count: usize) -> Sender<Job> {
+    let (tx, rx) = channel::<Job>();
+    let rx = Arc::new(Mutex::new(rx));
+    for i in 0..count {
+        let rx = rx.clone();
+        thread::Builder::new()
+            .name(format!("sbak-nonblocking-{}", i))
+            .spawn(move || run_worker(&rx))
+            .expect("failed to spawn worker thread");
+    }
+    tx
+}
+
+fn run_worker(rx: &Mutex<Receiver<Job>>) {
+    loop {
+        // 受信を待つ間だけロックを保持する
+        let job = rx.lock().unwrap().recv();
+        match job {
+            Ok(job) => job(),
+            Err(_) => return,
+        }
+    }
+}
+
+/// `f`をワーカースレッドのプールで実行し、その結果を返す`Future`を返す。
+///
+/// `f`がパニックした場合、`Future`をポーリングしたスレッドでパニックを再開する。
+/// ワーカースレッドはパニックしても終了しない。
+pub fn spawn<F, T>(f: F) -> Blocking<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        result: None,
+        waker: None,
+    }));
+
+    let job_shared = shared.clone();
+    let job: Job = Box::new(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+
+        let mut shared = job_shared.lock().unwrap();
+        shared.result = Some(result);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    });
+    POOL.lock()
+        .unwrap()
+        .send(job)
+        .expect("worker threads are stopped");
+
+    Blocking { shared }
+}
+
+/// ワーカースレッドで実行される処理の結果を返す`Future`
+///
+/// [`spawn`](fn.spawn.html)などで生成する。
+#[derive(Debug)]
+pub struct Blocking<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let mut shared = self.shared.lock().unwrap();
+        match shared.result.take() {
+            Some(Ok(v)) => Poll::Ready(v),
+            Some(Err(e)) => {
+                drop(shared);
+                panic::resume_unwind(e)
+            }
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// `repo_path`のリポジトリの`bank_name`のBankをスキャンする。
+///
+/// スキャン中はBankのロックを取得する。
+/// スキャナの設定は`configure`で[`ScannerBuilder`](../scan/struct.ScannerBuilder.html)を変更して行う。
+/// 履歴の保存は行わないため、必要であれば[`save_history`](fn.save_history.html)を使用する。
+/// ルートのエントリとスキャンの統計を返す。
+pub fn scan<F>(
+    repo_path: PathBuf,
+    bank_name: String,
+    configure: F,
+) -> Blocking<Result<(FsHash, ScanStats)>>
+where
+    F: for<'a> FnOnce(ScannerBuilder<'a>) -> ScannerBuilder<'a> + Send + 'static,
+{
+    spawn(move || {
+        let repo = Repository::open(&repo_path)?;
+        let bank = repo.open_bank(&bank_name)?;
+        let _lock = bank.lock()?;
+
+        let scanner = configure(ScannerBuilder::new(&bank)).build();
+        let root = scanner.scan()?;
+        Ok((root, scanner.stats()))
+    })
+}
+
+/// スキャン結果のエンティティのIDを`timestamp`時点での履歴として保存し、保存した履歴を返す。
+///
+/// `stats`を指定した場合は、スキャンの統計も履歴に記録する。
+pub fn save_history(
+    repo_path: PathBuf,
+    bank_name: String,
+    id: HashID,
+    timestamp: Timestamp,
+    stats: Option<ScanStats>,
+) -> Blocking<Result<History>> {
+    spawn(move || {
+        let repo = Repository::open(&repo_path)?;
+        let bank = repo.open_bank(&bank_name)?;
+        Ok(bank.save_history(id, timestamp, stats)?)
+    })
+}
+
+/// `history`時点のファイルを`target_path`に展開する。
+///
+/// 展開の設定は`configure`で[`Extender`](../extend/struct.Extender.html)を変更して行う。
+/// 展開されなかったシンボリックリンクの一覧を返す。
+pub fn restore<F>(
+    repo_path: PathBuf,
+    bank_name: String,
+    history: History,
+    target_path: PathBuf,
+    configure: F,
+) -> Blocking<Result<Symlinks>>
+where
+    F: for<'a> FnOnce(&mut Extender<'a>) + Send + 'static,
+{
+    spawn(move || {
+        let repo = Repository::open(&repo_path)?;
+        let bank = repo.open_bank(&bank_name)?;
+
+        let mut extender = Extender::new(&bank);
+        configure(&mut extender);
+        extender.extend(&target_path, &history)?;
+        Ok(extender.symlinks().clone())
+    })
+}
+
+/// オブジェクトを開き、内容を少しずつ読み込む[`ObjectReader`](struct.ObjectReader.html)を返す。
+pub fn open_object(
+    repo_path: PathBuf,
+    bank_name: String,
+    id: HashID,
+) -> Blocking<Result<ObjectReader>> {
+    spawn(move || {
+        let repo = Repository::open(&repo_path)?;
+        let bank = repo.open_bank(&bank_name)?;
+
+        Ok(ObjectReader {
+            inner: Arc::new(Mutex::new(bank.stream_object(&id)?)),
+        })
+    })
+}
+
+/// オブジェクトの内容を[`CHUNK_SIZE`](constant.CHUNK_SIZE.html)バイトずつ読み込む
+///
+/// [`open_object`](fn.open_object.html)で生成する。
+/// 内容は読み込みながら検証され、最後まで読み込んだ時点でハッシュが一致しなければエラーになる。
+pub struct ObjectReader {
+    inner: Arc<Mutex<VerifyingReader<File>>>,
+}
+
+impl ObjectReader {
+    /// 続きを最大[`CHUNK_SIZE`](constant.CHUNK_SIZE.html)バイト読み込む。
+    ///
+    /// 最後まで読み込んだ場合は`None`を返す。
+    pub fn read_chunk(&mut self) -> Blocking<Result<Option<Vec<u8>>>> {
+        let inner = self.inner.clone();
+        spawn(move || {
+            let mut inner = inner.lock().unwrap();
+            let mut buf = Vec::with_capacity(CHUNK_SIZE);
+            (&mut *inner)
+                .take(CHUNK_SIZE as u64)
+                .read_to_end(&mut buf)
+                .map_err(repo::Error::from)?;
+            if buf.is_empty() {
+                Ok(None)
+            } else {
+                Ok(Some(buf))
+            }
+        })
+    }
+}
+
+/// `data`をオブジェクトとして保存し、そのIDを返す。
+pub fn save_object(
+    repo_path: PathBuf,
+    bank_name: String,
+    data: Vec<u8>,
+) -> Blocking<Result<HashID>> {
+    spawn(move || {
+        let repo = Repository::open(&repo_path)?;
+        let bank = repo.open_bank(&bank_name)?;
+
+        let (id, temp) = hash_reader(data.as_slice())?;
+        bank.save_object(&id, temp)?;
+        Ok(id)
+    })
+}
+
+#[allow(missing_docs)]
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// 非同期処理で発生しうるエラー
+#[derive(Debug)]
+pub enum Error {
+    /// 展開に失敗した
+    Extend(extend::Error),
+
+    /// 入出力エラー
+    IO(io::Error),
+
+    /// リポジトリ操作エラーが発生
+    Repo(repo::Error),
+
+    /// スキャンに失敗した
+    Scan(scan::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Extend(e) => write!(f, "failed extend: {}", e),
+            Error::IO(e) => write!(f, "IO error: {}", e),
+            Error::Repo(e) => write!(f, "{}", e),
+            Error::Scan(e) => write!(f, "file scan error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Extend(e) => Some(e),
+            Error::IO(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            Error::Scan(e) => Some(e),
+        }
+    }
+}
+
+impl From<extend::Error> for Error {
+    fn from(e: extend::Error) -> Error {
+        Error::Extend(e)
+    }
+}
+
+impl From<hash::Error> for Error {
+    fn from(e: hash::Error) -> Error {
+        match e {
+            hash::Error::IO(e) => Error::IO(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IO(e)
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<scan::Error> for Error {
+    fn from(e: scan::Error) -> Error {
+        Error::Scan(e)
+    }
+}
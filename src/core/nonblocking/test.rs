@@ -0,0 +1,145 @@
+use super::*;
+
+use std::fs;
+use std::sync::Arc;
+use std::task::Wake;
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+use tempfile::tempdir;
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// テスト用の最小限の実行器
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(v) => return v,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn test_spawn() {
+    assert_eq!(block_on(spawn(|| 1 + 2)), 3);
+}
+
+#[test]
+#[should_panic(expected = "in worker")]
+fn test_spawn_panic() {
+    block_on(spawn(|| panic!("in worker")));
+}
+
+#[test]
+fn test_backup_and_restore() {
+    let temp = tempdir().unwrap();
+    let repo_path = temp.path().join("repo");
+    let target = temp.path().join("target");
+    let restored = temp.path().join("restored");
+    fs::create_dir_all(target.join("sub")).unwrap();
+    fs::write(target.join("a.txt"), "a").unwrap();
+    fs::write(target.join("sub/b.txt"), "b").unwrap();
+
+    let repo = Repository::create(&repo_path).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+
+    block_on(async {
+        let (root, stats) = scan(repo_path.clone(), "bank".to_owned(), |b| b.threads(2))
+            .await
+            .unwrap();
+
+        let history = save_history(
+            repo_path.clone(),
+            "bank".to_owned(),
+            root.id(),
+            Timestamp::now().unwrap(),
+            Some(stats),
+        )
+        .await
+        .unwrap();
+
+        restore(
+            repo_path.clone(),
+            "bank".to_owned(),
+            history,
+            restored.clone(),
+            |_| {},
+        )
+        .await
+        .unwrap();
+    });
+
+    assert_eq!(fs::read_to_string(restored.join("a.txt")).unwrap(), "a");
+    assert_eq!(fs::read_to_string(restored.join("sub/b.txt")).unwrap(), "b");
+}
+
+// 最後まで読み込み、読み込んだチャンクを返す。
+async fn read_all(mut reader: ObjectReader) -> Result<Vec<Vec<u8>>> {
+    let mut chunks = Vec::new();
+    while let Some(chunk) = reader.read_chunk().await? {
+        chunks.push(chunk);
+    }
+    Ok(chunks)
+}
+
+#[test]
+fn test_spawn_bounded() {
+    let count = worker_count() * 4;
+    let mut names = block_on(async {
+        let tasks = (0..count)
+            .map(|_| {
+                spawn(|| {
+                    thread::sleep(Duration::from_millis(1));
+                    thread::current().name().unwrap().to_owned()
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut names = Vec::new();
+        for task in tasks {
+            names.push(task.await);
+        }
+        names
+    });
+
+    names.sort();
+    names.dedup();
+    assert!(names.len() <= worker_count(), "{:?}", names);
+    assert!(names.iter().all(|n| n.starts_with("sbak-nonblocking-")));
+}
+
+#[test]
+fn test_object_io() {
+    let temp = tempdir().unwrap();
+    let repo_path = temp.path().join("repo");
+
+    let repo = Repository::create(&repo_path).unwrap();
+    repo.create_bank("bank", temp.path()).unwrap();
+
+    let data = (0..CHUNK_SIZE * 2 + 10)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    let chunks = block_on(async {
+        let id = save_object(repo_path.clone(), "bank".to_owned(), data.clone())
+            .await
+            .unwrap();
+        let reader = open_object(repo_path.clone(), "bank".to_owned(), id)
+            .await
+            .unwrap();
+        read_all(reader).await.unwrap()
+    });
+
+    let sizes = chunks.iter().map(|c| c.len()).collect::<Vec<_>>();
+    assert_eq!(sizes, vec![CHUNK_SIZE, CHUNK_SIZE, 10]);
+    assert_eq!(chunks.concat(), data);
+}
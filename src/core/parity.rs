@@ -0,0 +1,241 @@
+//! オブジェクトの破損を修復するためのパリティ。
+//!
+//! オブジェクトを[`BLOCK_SIZE`](constant.BLOCK_SIZE.html)ごとのブロックに分け、
+//! ブロックごとのチェックサムと、[`GROUP_SIZE`](constant.GROUP_SIZE.html)個のブロックの排他的論理和をとったパリティブロックを記録する。
+//! チェックサムで壊れたブロックを特定し、同じグループ内で壊れたブロックが1つだけであれば、パリティと残りのブロックから復元できる。
+
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+use crate::core::hash::Hasher;
+
+#[cfg(test)]
+mod test;
+
+/// パリティを計算するブロックのサイズ
+pub const BLOCK_SIZE: usize = 4096;
+
+/// 1つのパリティブロックがカバーするブロックの数
+pub const GROUP_SIZE: usize = 32;
+
+// ファイルの先頭に置く識別子。形式を変える場合は末尾の番号を上げる。
+const MAGIC: &[u8; 8] = b"SBAKPAR1";
+
+// ブロックごとのチェックサムのバイト数
+const CHECKSUM_SIZE: usize = 8;
+
+type Checksum = [u8; CHECKSUM_SIZE];
+
+/// 1つのオブジェクトのパリティ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parity {
+    len: u64,
+    checksums: Vec<Checksum>,
+    parities: Vec<Vec<u8>>,
+}
+
+/// 修復の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repair {
+    /// 壊れたブロックは無かった
+    Intact,
+    /// 壊れたブロックを指定された個数だけ復元した
+    Repaired(usize),
+    /// 同じグループ内で複数のブロックが壊れているため、復元できなかった
+    Unrepairable,
+}
+
+impl Parity {
+    /// `r`の内容を終端まで読み込み、パリティを計算する。
+    pub fn generate<R: Read>(r: R) -> io::Result<Parity> {
+        let mut r = BufReader::new(r);
+
+        let mut parity = Parity {
+            len: 0,
+            checksums: Vec::new(),
+            parities: Vec::new(),
+        };
+        let mut block = vec![0u8; BLOCK_SIZE];
+        loop {
+            let n = read_block(&mut r, &mut block)?;
+            if n == 0 {
+                break;
+            }
+
+            let index = parity.checksums.len();
+            if index.is_multiple_of(GROUP_SIZE) {
+                parity.parities.push(vec![0u8; BLOCK_SIZE]);
+            }
+            xor_into(parity.parities.last_mut().unwrap(), &block);
+            parity.checksums.push(checksum(&block[..n]));
+            parity.len += n as u64;
+
+            if n < BLOCK_SIZE {
+                break;
+            }
+        }
+        Ok(parity)
+    }
+
+    /// パリティの元になったオブジェクトのバイト数を返す。
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// パリティの元になったオブジェクトが空かどうかを返す。
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// `f`の内容のうち、壊れたブロックをパリティから復元して書き戻す。
+    ///
+    /// サイズが異なる場合は、元のサイズに合わせてから復元を試みる。
+    /// 復元できなかった場合も`f`は変更されていることがあるため、複製に対して呼び出すこと。
+    pub fn repair(&self, f: &mut fs::File) -> io::Result<Repair> {
+        let actual_len = f.seek(SeekFrom::End(0))?;
+        if actual_len != self.len {
+            f.set_len(self.len)?;
+        }
+
+        // 壊れたブロックを探す。元のサイズに満たない部分も壊れたものとみなす。
+        let mut broken = Vec::new();
+        let mut block = vec![0u8; BLOCK_SIZE];
+        f.seek(SeekFrom::Start(0))?;
+        for (i, expected) in self.checksums.iter().enumerate() {
+            let size = self.block_len(i);
+            let n = read_block(f, &mut block[..size])?;
+            if n < size || &checksum(&block[..size]) != expected {
+                broken.push(i);
+            }
+        }
+        if broken.is_empty() && actual_len == self.len {
+            return Ok(Repair::Intact);
+        }
+        if broken
+            .windows(2)
+            .any(|w| w[0] / GROUP_SIZE == w[1] / GROUP_SIZE)
+        {
+            return Ok(Repair::Unrepairable);
+        }
+
+        // パリティと同じグループの他のブロックの排他的論理和が、壊れたブロックの内容になる。
+        let mut restored = Vec::with_capacity(broken.len());
+        for &i in &broken {
+            let group = i / GROUP_SIZE;
+            let mut data = self.parities[group].clone();
+            let end = ((group + 1) * GROUP_SIZE).min(self.checksums.len());
+            for j in (group * GROUP_SIZE..end).filter(|&j| j != i) {
+                let size = self.block_len(j);
+                block.iter_mut().for_each(|b| *b = 0);
+                f.seek(SeekFrom::Start((j * BLOCK_SIZE) as u64))?;
+                read_block(f, &mut block[..size])?;
+                xor_into(&mut data, &block);
+            }
+
+            data.truncate(self.block_len(i));
+            if checksum(&data) != self.checksums[i] {
+                return Ok(Repair::Unrepairable);
+            }
+            restored.push((i, data));
+        }
+
+        for (i, data) in &restored {
+            f.seek(SeekFrom::Start((i * BLOCK_SIZE) as u64))?;
+            f.write_all(data)?;
+        }
+        f.flush()?;
+        Ok(Repair::Repaired(restored.len()))
+    }
+
+    /// `r`から保存済みのパリティを読み込む。
+    ///
+    /// 形式が異なる場合は`InvalidData`のエラーを返す。
+    pub fn read_from<R: Read>(r: R) -> io::Result<Parity> {
+        let mut r = BufReader::new(r);
+
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unknown parity format",
+            ));
+        }
+
+        let mut len = [0u8; 8];
+        r.read_exact(&mut len)?;
+        let len = u64::from_le_bytes(len);
+        let blocks = (len as usize).div_ceil(BLOCK_SIZE);
+        let groups = blocks.div_ceil(GROUP_SIZE);
+
+        let mut checksums = vec![[0u8; CHECKSUM_SIZE]; blocks];
+        for c in &mut checksums {
+            r.read_exact(c)?;
+        }
+        let mut parities = vec![vec![0u8; BLOCK_SIZE]; groups];
+        for p in &mut parities {
+            r.read_exact(p)?;
+        }
+        if r.read(&mut [0u8; 1])? != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "trailing data after parity",
+            ));
+        }
+
+        Ok(Parity {
+            len,
+            checksums,
+            parities,
+        })
+    }
+
+    /// パリティを`w`に書き出す。
+    pub fn write_to<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut w = BufWriter::new(w);
+        w.write_all(MAGIC)?;
+        w.write_all(&self.len.to_le_bytes())?;
+        for c in &self.checksums {
+            w.write_all(c)?;
+        }
+        for p in &self.parities {
+            w.write_all(p)?;
+        }
+        w.flush()
+    }
+
+    // `i`番目のブロックのバイト数
+    fn block_len(&self, i: usize) -> usize {
+        let start = (i * BLOCK_SIZE) as u64;
+        (self.len - start).min(BLOCK_SIZE as u64) as usize
+    }
+}
+
+// `buf`が埋まるか終端に達するまで読み込み、読み込んだバイト数を返す。残りは0で埋める。
+fn read_block<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    buf[filled..].iter_mut().for_each(|b| *b = 0);
+    Ok(filled)
+}
+
+fn xor_into(acc: &mut [u8], block: &[u8]) {
+    for (a, b) in acc.iter_mut().zip(block) {
+        *a ^= b;
+    }
+}
+
+fn checksum(block: &[u8]) -> Checksum {
+    let mut hasher = Hasher::new();
+    hasher.update(block);
+    let mut c = [0u8; CHECKSUM_SIZE];
+    c.copy_from_slice(&hasher.finish().as_bytes()[..CHECKSUM_SIZE]);
+    c
+}
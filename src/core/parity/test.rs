@@ -0,0 +1,86 @@
+use super::*;
+
+use std::io::SeekFrom;
+
+use tempfile::tempfile;
+
+// ブロックごとに内容が異なるデータ
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / BLOCK_SIZE) as u8).collect()
+}
+
+fn file_of(bytes: &[u8]) -> fs::File {
+    let mut f = tempfile().unwrap();
+    f.write_all(bytes).unwrap();
+    f
+}
+
+fn content(f: &mut fs::File) -> Vec<u8> {
+    let mut buf = Vec::new();
+    f.seek(SeekFrom::Start(0)).unwrap();
+    f.read_to_end(&mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn test_write_and_read() {
+    for &len in &[0, 1, BLOCK_SIZE, BLOCK_SIZE * GROUP_SIZE + 10] {
+        let parity = Parity::generate(data(len).as_slice()).unwrap();
+        assert_eq!(parity.len(), len as u64);
+
+        let mut buf = Vec::new();
+        parity.write_to(&mut buf).unwrap();
+        let read = Parity::read_from(buf.as_slice()).unwrap();
+        assert_eq!(read, parity, "len = {}", len);
+
+        buf.push(0);
+        let err = Parity::read_from(buf.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    let err = Parity::read_from(&b"NOTPARITY0000000"[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_repair() {
+    let original = data(BLOCK_SIZE * (GROUP_SIZE + 2) + 100);
+    let parity = Parity::generate(original.as_slice()).unwrap();
+
+    let mut f = file_of(&original);
+    assert_eq!(parity.repair(&mut f).unwrap(), Repair::Intact);
+
+    // 異なるグループの1バイトずつと、末尾の半端なブロックを壊す
+    let mut broken = original.clone();
+    broken[10] ^= 0xff;
+    broken[BLOCK_SIZE * GROUP_SIZE + 1] ^= 0x01;
+    let last = broken.len() - 1;
+    broken[last] ^= 0x80;
+    // 2つ目(ブロック32)と末尾(ブロック34)は同じグループ1にあり、1つのグループで2ブロック壊れると修復できない
+    let mut f = file_of(&broken);
+    assert_eq!(parity.repair(&mut f).unwrap(), Repair::Unrepairable);
+
+    broken[last] ^= 0x80;
+    let mut f = file_of(&broken);
+    assert_eq!(parity.repair(&mut f).unwrap(), Repair::Repaired(2));
+    assert_eq!(content(&mut f), original);
+
+    // 切り詰められたファイル
+    let mut f = file_of(&original[..original.len() - 50]);
+    assert_eq!(parity.repair(&mut f).unwrap(), Repair::Repaired(1));
+    assert_eq!(content(&mut f), original);
+
+    // 余分な末尾
+    let mut longer = original.clone();
+    longer.extend_from_slice(b"garbage");
+    let mut f = file_of(&longer);
+    assert_eq!(parity.repair(&mut f).unwrap(), Repair::Repaired(0));
+    assert_eq!(content(&mut f), original);
+
+    // 同じグループの2ブロック
+    let mut broken = original.clone();
+    broken[0] ^= 1;
+    broken[BLOCK_SIZE] ^= 1;
+    let mut f = file_of(&broken);
+    assert_eq!(parity.repair(&mut f).unwrap(), Repair::Unrepairable);
+}
@@ -0,0 +1,798 @@
+//! SSH越しにリモートのディレクトリをスキャンする。
+//!
+//! リモートでは`sbak agent <path>`を起動し、標準入出力を使って以下のプロトコルで通信する。
+//!
+//! * 要求は1行のJSONで、`{"op":"list","path":...}`はディレクトリの子の一覧を、
+//!   `{"op":"read","path":...}`はファイルの内容を要求する。パスは起点からの`/`区切りの相対パスである。
+//! * 応答は1行のJSONで、ファイルの内容はその後に続くチャンクで送る。
+//!   チャンクは8バイトのビッグエンディアンの長さとデータからなり、長さ0で終わる。
+//!   読み込みに失敗した場合は長さの代わりに`u64::MAX`を送り、続けてエラーの応答を送る。
+
+use std::convert::{TryFrom, TryInto};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Instant;
+use std::vec;
+
+use log::{info, trace, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::core::entry::*;
+use crate::core::hash::{self, hash_reader, HashID};
+use crate::core::ignore::pattern::{Match, Patterns};
+use crate::core::ignore::{EntryMeta, EntryPath};
+use crate::core::repo::{self, Bank, ScanStats};
+use crate::core::scan::{self, convert_metadata};
+use crate::progress::Progress;
+use crate::util::size::Size;
+
+#[cfg(test)]
+mod test;
+
+const SCHEME: &str = "ssh://";
+const CHUNK_SIZE: usize = 64 * 1024;
+const CHUNK_END: u64 = 0;
+const CHUNK_FAILED: u64 = u64::MAX;
+
+/// `ssh://[user@]host[:port]/path`形式で指定されたスキャン元
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshSource {
+    destination: String,
+    port: Option<u16>,
+    path: String,
+}
+
+impl SshSource {
+    /// URLを解析する。
+    pub fn parse(url: &str) -> Result<SshSource> {
+        let invalid = |reason: &str| Error::InvalidSource(format!("{}: {}", reason, url));
+
+        let rest = url
+            .strip_prefix(SCHEME)
+            .ok_or_else(|| invalid("scheme must be ssh://"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => return Err(invalid("path is missing")),
+        };
+
+        // IPv6アドレスは`[...]`で囲まれているため、その中の`:`はポートの区切りとみなさない
+        let host_end = authority.rfind(']').unwrap_or(0);
+        let (destination, port) = match authority[host_end..].rfind(':') {
+            Some(i) => {
+                let (destination, port) = authority.split_at(host_end + i);
+                let port = port[1..]
+                    .parse::<u16>()
+                    .map_err(|_| invalid("invalid port"))?;
+                (destination, Some(port))
+            }
+            None => (authority, None),
+        };
+        if destination.is_empty() || destination.ends_with('@') {
+            return Err(invalid("host is missing"));
+        }
+        // `-`で始まる接続先はsshにオプションとして解釈され、任意のコマンドを実行できてしまう
+        let host = destination.rsplit('@').next().unwrap_or(destination);
+        if destination.starts_with('-') || host.starts_with('-') {
+            return Err(invalid("destination must not start with '-'"));
+        }
+
+        Ok(SshSource {
+            destination: destination.to_owned(),
+            port,
+            path: path.to_owned(),
+        })
+    }
+
+    /// sshに渡す接続先(`[user@]host`)を返す。
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// リモートのディレクトリのパスを返す。
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// リモートで`remote_command agent <path>`を起動し、標準入出力をパイプにした`ssh`のコマンドを返す。
+    pub fn command(&self, remote_command: &str) -> Command {
+        let mut cmd = Command::new("ssh");
+        if let Some(port) = self.port {
+            cmd.arg("-p").arg(port.to_string());
+        }
+        // 接続先をオプションとして解釈させないよう、`--`の後に置く
+        // リモートではシェルを介して実行されるため、パスを引用する
+        cmd.arg("--")
+            .arg(&self.destination)
+            .arg(format!(
+                "{} agent {}",
+                remote_command,
+                shell_quote(&self.path)
+            ))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped());
+        cmd
+    }
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Request {
+    List { path: String },
+    Read { path: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "reply", rename_all = "lowercase")]
+enum Reply {
+    Dir {
+        attr: Attributes,
+        children: Vec<RemoteEntry>,
+        // 子エントリのうち、読み込めずに飛ばしたもののエラー
+        errors: Vec<String>,
+    },
+    Content {
+        size: u64,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RemoteEntry {
+    attr: Attributes,
+    kind: RemoteKind,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum RemoteKind {
+    Dir,
+    File { size: u64 },
+    Symlink { target: String, target_is_dir: bool },
+}
+
+/// `root`以下のファイルを、`r`からの要求に応じて`w`に送る。
+///
+/// `r`が終端に達すると終了する。
+pub fn serve_agent<R: BufRead, W: Write>(root: &Path, mut r: R, mut w: W) -> io::Result<()> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if r.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let request = serde_json::from_str::<Request>(&line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        trace!("agent request: {:?}", request);
+
+        match request {
+            Request::List { path } => {
+                let reply = resolve(root, &path)
+                    .and_then(|p| list_dir(&p))
+                    .unwrap_or_else(|e| Reply::Error {
+                        message: e.to_string(),
+                    });
+                write_reply(&mut w, &reply)?;
+            }
+            Request::Read { path } => send_file(root, &path, &mut w)?,
+        }
+        w.flush()?;
+    }
+}
+
+// 相対パスを`root`以下のパスに変換する。`root`の外を指すパスは拒否する。
+fn resolve(root: &Path, path: &str) -> std::result::Result<PathBuf, scan::Error> {
+    let mut resolved = root.to_owned();
+    for name in path.split('/').filter(|s| !s.is_empty()) {
+        match Path::new(name).components().next() {
+            Some(Component::Normal(_)) if !name.contains('\\') => resolved.push(name),
+            _ => {
+                return Err(scan::Error::IO(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid path: {:?}", path),
+                )))
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+fn list_dir(p: &Path) -> std::result::Result<Reply, scan::Error> {
+    let attr = convert_metadata(p, &fs::metadata(p)?)?;
+
+    let mut children = Vec::new();
+    let mut errors = Vec::new();
+    for ch in fs::read_dir(p)? {
+        match ch
+            .map_err(scan::Error::from)
+            .and_then(|ch| remote_entry(&ch.path()))
+        {
+            Ok(Some(entry)) => children.push(entry),
+            Ok(None) => {}
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+
+    Ok(Reply::Dir {
+        attr,
+        children,
+        errors,
+    })
+}
+
+fn remote_entry(p: &Path) -> std::result::Result<Option<RemoteEntry>, scan::Error> {
+    let meta = fs::symlink_metadata(p)?;
+    let attr = convert_metadata(p, &meta)?;
+
+    let file_type = meta.file_type();
+    let kind = if file_type.is_dir() {
+        RemoteKind::Dir
+    } else if file_type.is_file() {
+        RemoteKind::File { size: meta.len() }
+    } else if file_type.is_symlink() {
+        let target = fs::read_link(p)?;
+        let target = target
+            .to_str()
+            .ok_or_else(|| scan::Error::NameIsInvalidUnicode(target.to_owned()))?
+            .to_owned();
+        // リンク切れはディレクトリ以外を指すものとして扱う
+        let target_is_dir = fs::metadata(p).map(|m| m.is_dir()).unwrap_or(false);
+        RemoteKind::Symlink {
+            target,
+            target_is_dir,
+        }
+    } else {
+        warn!("{:?} is not dir nor file", p);
+        return Ok(None);
+    };
+
+    Ok(Some(RemoteEntry { attr, kind }))
+}
+
+fn send_file<W: Write>(root: &Path, path: &str, w: &mut W) -> io::Result<()> {
+    let opened = resolve(root, path).and_then(|p| {
+        let f = fs::File::open(&p)?;
+        let size = f.metadata()?.len();
+        Ok((f, size))
+    });
+    let (mut f, size) = match opened {
+        Ok(opened) => opened,
+        Err(e) => {
+            return write_reply(
+                w,
+                &Reply::Error {
+                    message: e.to_string(),
+                },
+            )
+        }
+    };
+    write_reply(w, &Reply::Content { size })?;
+
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        match f.read(&mut buf) {
+            Ok(0) => return w.write_all(&CHUNK_END.to_be_bytes()),
+            Ok(n) => {
+                w.write_all(&(n as u64).to_be_bytes())?;
+                w.write_all(&buf[..n])?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                w.write_all(&CHUNK_FAILED.to_be_bytes())?;
+                return write_reply(
+                    w,
+                    &Reply::Error {
+                        message: e.to_string(),
+                    },
+                );
+            }
+        }
+    }
+}
+
+fn write_reply<W: Write>(w: &mut W, reply: &Reply) -> io::Result<()> {
+    serde_json::to_writer(&mut *w, reply)?;
+    w.write_all(b"\n")
+}
+
+/// エージェントとの接続
+#[derive(Debug)]
+pub struct Connection<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: BufRead, W: Write> Connection<R, W> {
+    /// エージェントの標準出力`reader`と標準入力`writer`から接続を生成する。
+    pub fn new(reader: R, writer: W) -> Connection<R, W> {
+        Connection { reader, writer }
+    }
+
+    fn send(&mut self, request: &Request) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, request)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Reply> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(Error::Protocol("agent closed connection".to_owned()));
+        }
+        serde_json::from_str(&line).map_err(|e| Error::Protocol(format!("broken reply: {}", e)))
+    }
+
+    // ディレクトリの属性と子エントリ、読み込めなかった子エントリのエラーを返す。
+    fn list(&mut self, path: &str) -> Result<(Attributes, Vec<RemoteEntry>, Vec<String>)> {
+        self.send(&Request::List {
+            path: path.to_owned(),
+        })?;
+        match self.receive()? {
+            Reply::Dir {
+                attr,
+                children,
+                errors,
+            } => Ok((attr, children, errors)),
+            Reply::Error { message } => Err(Error::Remote(format!("{}: {}", path, message))),
+            reply => Err(Error::Protocol(format!("unexpected reply: {:?}", reply))),
+        }
+    }
+
+    // ファイルの内容を一時ファイルに受け取り、ハッシュ値と一時ファイル、バイト数を返す。
+    fn fetch(&mut self, path: &str) -> Result<(HashID, fs::File, u64)> {
+        self.send(&Request::Read {
+            path: path.to_owned(),
+        })?;
+        match self.receive()? {
+            Reply::Content { .. } => {}
+            Reply::Error { message } => {
+                return Err(Error::Remote(format!("{}: {}", path, message)))
+            }
+            reply => return Err(Error::Protocol(format!("unexpected reply: {:?}", reply))),
+        }
+
+        let mut chunks = Chunks {
+            reader: &mut self.reader,
+            remaining: 0,
+            finished: false,
+            len: 0,
+            failed: None,
+        };
+        let hashed = hash_reader(&mut chunks);
+        if let Some(message) = chunks.failed {
+            return Err(Error::Remote(format!("{}: {}", path, message)));
+        }
+        let (id, file) = hashed?;
+        Ok((id, file, chunks.len))
+    }
+}
+
+// チャンクに分けて送られるファイルの内容
+struct Chunks<'a, R> {
+    reader: &'a mut R,
+    remaining: u64,
+    finished: bool,
+    len: u64,
+    // エージェントが送ってきた読み込み失敗のエラー
+    failed: Option<String>,
+}
+
+impl<'a, R: BufRead> Read for Chunks<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.remaining == 0 {
+            if self.finished {
+                return Ok(0);
+            }
+            let mut header = [0; 8];
+            self.reader.read_exact(&mut header)?;
+            match u64::from_be_bytes(header) {
+                CHUNK_END => self.finished = true,
+                CHUNK_FAILED => {
+                    self.finished = true;
+                    let mut line = String::new();
+                    self.reader.read_line(&mut line)?;
+                    let message = match serde_json::from_str(&line) {
+                        Ok(Reply::Error { message }) => message,
+                        _ => format!("broken reply: {:?}", line),
+                    };
+                    self.failed = Some(message);
+                    return Err(io::Error::other("agent failed to read"));
+                }
+                n => self.remaining = n,
+            }
+        }
+
+        let max = buf.len().min(self.remaining as usize);
+        let n = self.reader.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= n as u64;
+        self.len += n as u64;
+        Ok(n)
+    }
+}
+
+/// エージェントを介してリモートのディレクトリをスキャンし、Bankに保存する。
+///
+/// ローカルの[`Scanner`](../scan/struct.Scanner.html)と異なり、各ディレクトリの`.sbakignore`は読まず、
+/// リポジトリとBank、設定ファイル、追加で指定された除外パターンのみを適用する。
+/// リモートで読み込めなかったエントリは常に飛ばし、統計の`errors`に数える。
+pub struct RemoteScanner<'a, R, W> {
+    bank: &'a Bank<'a>,
+    conn: Connection<R, W>,
+    config_patterns: Patterns,
+    extra_patterns: Patterns,
+    progress: Progress,
+    max_size: Option<Size>,
+    max_depth: Option<usize>,
+    dry_run: bool,
+    stats: ScanStats,
+}
+
+// 走査中のディレクトリ
+struct Frame {
+    path: EntryPath,
+    // エージェントに送る相対パス
+    remote_path: String,
+    builder: DirEntryBuilder,
+    appended: usize,
+    children: vec::IntoIter<RemoteEntry>,
+    old_entry: DirEntry,
+    // 除外されたディレクトリで、除外を取り消された子孫だけを含む。子孫が無ければ記録しない。
+    pruned: bool,
+}
+
+impl Frame {
+    fn append(&mut self, hash: FsHash) {
+        self.builder.append(hash);
+        self.appended += 1;
+    }
+}
+
+impl<'a, R: BufRead, W: Write> RemoteScanner<'a, R, W> {
+    /// `conn`のエージェントから読み込み、`bank`に保存するスキャナを生成する。
+    pub fn new(bank: &'a Bank<'a>, conn: Connection<R, W>) -> RemoteScanner<'a, R, W> {
+        RemoteScanner {
+            bank,
+            conn,
+            config_patterns: Patterns::default(),
+            extra_patterns: Patterns::default(),
+            progress: Progress::hidden(),
+            max_size: None,
+            max_depth: None,
+            dry_run: false,
+            stats: ScanStats::default(),
+        }
+    }
+
+    /// 設定ファイルで指定された除外パターンを設定する。
+    pub fn config_patterns(mut self, patterns: Patterns) -> Self {
+        self.config_patterns = patterns;
+        self
+    }
+
+    /// コマンドラインなどで追加で指定された除外パターンを設定する。
+    pub fn extra_patterns(mut self, patterns: Patterns) -> Self {
+        self.extra_patterns = patterns;
+        self
+    }
+
+    /// 進捗の表示先を設定する。
+    pub fn progress(mut self, progress: Progress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// これより大きいファイルを飛ばす。
+    pub fn max_size(mut self, max_size: Option<Size>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// これより深いディレクトリを飛ばす。
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// リポジトリに書き込まずにスキャンするかを設定する。
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// リモートのディレクトリをスキャンする。
+    ///
+    /// スキャンの統計は[`stats`](#method.stats)で得られる。
+    pub fn scan(&mut self) -> Result<FsHash> {
+        self.stats = ScanStats::default();
+        let start = Instant::now();
+        let result = self.scan_root();
+        self.stats.duration_ms = start.elapsed().as_millis() as u64;
+        self.progress.finish();
+        result
+    }
+
+    /// 直前の[`scan`](#method.scan)の統計を返す。
+    pub fn stats(&self) -> ScanStats {
+        self.stats.clone()
+    }
+
+    fn scan_root(&mut self) -> Result<FsHash> {
+        let patterns = self
+            .bank
+            .load_repository_ignore_patterns()?
+            .merged(&self.config_patterns)
+            .merged(&self.bank.load_ignore_patterns()?)
+            .merged(&self.extra_patterns);
+        let last_id = self.bank.last_scan()?.map(|h| h.id().clone());
+
+        let root = self.open_dir(EntryPath::root(), String::new(), last_id, false)?;
+        let mut stack = vec![root];
+        loop {
+            let frame = stack.last_mut().unwrap();
+            let ch = match frame.children.next() {
+                Some(ch) => ch,
+                None => {
+                    let frame = stack.pop().unwrap();
+                    if frame.pruned && frame.appended == 0 {
+                        trace!("ignore {}: no entries allowed", frame.path);
+                        continue;
+                    }
+                    let hash = self.save_dir(frame.builder)?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.append(hash),
+                        None => return Ok(hash),
+                    }
+                    continue;
+                }
+            };
+
+            let name = ch.attr.name().to_owned();
+            let remote_path = if frame.remote_path.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", frame.remote_path, name)
+            };
+            let (is_dir, size) = match ch.kind {
+                RemoteKind::Dir => (true, 0),
+                RemoteKind::File { size } => (false, size),
+                RemoteKind::Symlink { .. } => (false, 0),
+            };
+            let path = frame
+                .path
+                .join(&name, is_dir)
+                .with_meta(EntryMeta::new(size, ch.attr.modified()));
+
+            // マッチするパターンが無ければ親ディレクトリの判定に従う
+            let ignored = match patterns.matches(&path) {
+                Match::Ignored => true,
+                Match::Allowed => false,
+                Match::Parent => frame.pruned,
+            };
+            // 除外されたディレクトリでも、中のエントリの除外が取り消されうるなら走査する
+            let pruned = ignored && is_dir && patterns.may_allow_inside(&path);
+            if ignored && !pruned {
+                trace!("ignore {}", path);
+                continue;
+            }
+            info!("{}", remote_path);
+
+            let last_entry = frame.old_entry.find_child(&name).cloned();
+            let result = match ch.kind {
+                RemoteKind::Dir => {
+                    if let Some(max_depth) = self.max_depth {
+                        if stack.len() > max_depth {
+                            warn!("skip {}: deeper than {}", remote_path, max_depth);
+                            continue;
+                        }
+                    }
+                    let last_id = last_entry
+                        .filter(|h| matches!(h, FsHash::Dir(_)))
+                        .map(|h| h.id());
+                    match self.open_dir(path, remote_path.clone(), last_id, pruned) {
+                        Ok(dir) => {
+                            stack.push(dir);
+                            continue;
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+                RemoteKind::File { size } => {
+                    if let Some(max_size) = self.max_size {
+                        if size > max_size.bytes() {
+                            info!("skip {}: larger than {}", remote_path, max_size);
+                            continue;
+                        }
+                    }
+                    self.scan_file(&remote_path, ch.attr, last_entry)
+                }
+                RemoteKind::Symlink {
+                    target,
+                    target_is_dir,
+                } => self.save_symlink(ch.attr, target, target_is_dir),
+            };
+
+            match result {
+                Ok(hash) => stack.last_mut().unwrap().append(hash),
+                Err(Error::Remote(message)) => self.skip(&message),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // エージェントで起きたエラーを飛ばして記録する。
+    fn skip(&mut self, message: &str) {
+        warn!("skip {}", message);
+        self.stats.errors += 1;
+    }
+
+    fn open_dir(
+        &mut self,
+        path: EntryPath,
+        remote_path: String,
+        last_id: Option<HashID>,
+        pruned: bool,
+    ) -> Result<Frame> {
+        trace!("scan dir {:?}", remote_path);
+        let (attr, children, errors) = self.conn.list(&remote_path)?;
+        for e in errors {
+            self.skip(&format!("{}: {}", remote_path, e));
+        }
+
+        let old_entry = match last_id {
+            Some(ref id) => self.bank.load_entry(id)?,
+            None => DirEntryBuilder::new(attr.clone()).build(),
+        };
+
+        Ok(Frame {
+            path,
+            remote_path,
+            builder: DirEntryBuilder::new(attr),
+            appended: 0,
+            children: children.into_iter(),
+            old_entry,
+            pruned,
+        })
+    }
+
+    fn scan_file(
+        &mut self,
+        remote_path: &str,
+        attr: Attributes,
+        last_entry: Option<FsHash>,
+    ) -> Result<FsHash> {
+        self.stats.files += 1;
+
+        let old_hash: Option<FileHash> = last_entry.and_then(|h| h.try_into().ok());
+        if let Some(old_hash) = old_hash {
+            if old_hash.attr().modified() == attr.modified() {
+                trace!("skip fetch file {:?}", remote_path);
+                self.progress.add_file(0);
+                // 内容は前回のまま、作成日時などの属性は今回のものにする
+                return Ok(old_hash.with_attr(attr).into());
+            }
+        }
+
+        let (id, temp, len) = self.conn.fetch(remote_path)?;
+        trace!("file hash {:?} = {}", remote_path, id);
+        if !self.dry_run {
+            self.stats.new_bytes += self.bank.save_object(&id, temp)?;
+        }
+        self.stats.bytes_read += len;
+        self.progress.add_file(len);
+
+        let mut entry = FileEntry::new(attr);
+        entry.set_id(id);
+        Ok(FsHash::try_from(entry).unwrap())
+    }
+
+    fn save_symlink(&mut self, attr: Attributes, target: String, is_dir: bool) -> Result<FsHash> {
+        let mut entry = SymlinkEntry::new(attr, target, is_dir);
+
+        let encoded = self.bank.entry_encoding().encode(&entry)?;
+        let (id, temp) = hash_reader(encoded.as_slice())?;
+        if !self.dry_run {
+            self.stats.new_bytes += self.bank.save_object(&id, temp)?;
+        }
+
+        entry.set_id(id);
+        Ok(FsHash::try_from(entry).unwrap())
+    }
+
+    fn save_dir(&mut self, builder: DirEntryBuilder) -> Result<FsHash> {
+        let mut entry = builder.build();
+        let (id, stored) = self.bank.save_dir_entry(&entry, self.dry_run)?;
+        self.stats.dirs += 1;
+        self.stats.new_bytes += stored;
+
+        entry.set_id(id);
+        Ok(FsHash::try_from(entry).unwrap())
+    }
+}
+
+#[allow(missing_docs)]
+type Result<T> = std::result::Result<T, Error>;
+
+/// リモートのスキャンで発生しうるエラー
+#[derive(Debug)]
+pub enum Error {
+    /// 要求やエントリのJSONへのエンコードの失敗
+    Encode(serde_json::Error),
+
+    /// スキャン元のURLが不正
+    InvalidSource(String),
+
+    /// 入出力エラー
+    IO(io::Error),
+
+    /// エージェントとの通信内容が不正
+    Protocol(String),
+
+    /// エージェントがエントリを読み込めなかった
+    Remote(String),
+
+    /// リポジトリ操作エラーが発生
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Encode(e) => write!(f, "failed to encode: {}", e),
+            Error::InvalidSource(msg) => write!(f, "invalid source: {}", msg),
+            Error::IO(e) => write!(f, "failed remote scan with IO error: {}", e),
+            Error::Protocol(msg) => write!(f, "agent protocol error: {}", msg),
+            Error::Remote(msg) => write!(f, "remote error: {}", msg),
+            Error::Repo(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Encode(e) => Some(e),
+            Error::IO(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IO(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Encode(e)
+    }
+}
+
+impl From<hash::Error> for Error {
+    fn from(e: hash::Error) -> Error {
+        match e {
+            hash::Error::IO(e) => Error::IO(e),
+        }
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
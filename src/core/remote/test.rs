@@ -0,0 +1,212 @@
+use super::*;
+
+use tempfile::tempdir;
+
+use crate::core::ignore::pattern::parse_lines;
+use crate::core::repo::Repository;
+use crate::core::scan::Scanner;
+use crate::core::timestamp::Timestamp;
+
+#[test]
+fn test_parse_source() {
+    let source = SshSource::parse("ssh://user@example.com:2222/home/user/data").unwrap();
+    assert_eq!(source.destination(), "user@example.com");
+    assert_eq!(source.port, Some(2222));
+    assert_eq!(source.path(), "/home/user/data");
+
+    let source = SshSource::parse("ssh://[::1]/srv").unwrap();
+    assert_eq!(source.destination(), "[::1]");
+    assert_eq!(source.port, None);
+    assert_eq!(source.path(), "/srv");
+}
+
+#[test]
+fn test_parse_source_invalid() {
+    for url in &[
+        "example.com:/data",
+        "ssh://example.com",
+        "ssh:///data",
+        "ssh://user@/data",
+        "ssh://example.com:ssh/data",
+    ] {
+        assert!(
+            matches!(SshSource::parse(url), Err(Error::InvalidSource(_))),
+            "{}",
+            url
+        );
+    }
+}
+
+#[test]
+fn test_parse_source_option_injection() {
+    for url in &[
+        "ssh://-oProxyCommand=touch%20pwned/path",
+        "ssh://-oProxyCommand=touch pwned:22/path",
+        "ssh://user@-oProxyCommand=id/path",
+    ] {
+        assert!(
+            matches!(SshSource::parse(url), Err(Error::InvalidSource(_))),
+            "{}",
+            url
+        );
+    }
+}
+
+#[test]
+fn test_command_args() {
+    let source = SshSource::parse("ssh://user@example.com:2222/home/user/data").unwrap();
+    let cmd = source.command("sbak");
+    let args: Vec<_> = cmd.get_args().map(|a| a.to_str().unwrap()).collect();
+    assert_eq!(
+        args,
+        vec![
+            "-p",
+            "2222",
+            "--",
+            "user@example.com",
+            "sbak agent '/home/user/data'"
+        ]
+    );
+}
+
+#[test]
+fn test_shell_quote() {
+    assert_eq!(shell_quote("/data"), "'/data'");
+    assert_eq!(shell_quote("/it's mine"), r"'/it'\''s mine'");
+}
+
+#[test]
+fn test_resolve_rejects_outside() {
+    let root = Path::new("/data");
+    assert_eq!(resolve(root, "").unwrap(), root);
+    assert_eq!(resolve(root, "a/b").unwrap(), root.join("a").join("b"));
+    assert!(resolve(root, "a/../..").is_err());
+    assert!(resolve(root, "./a").is_err());
+}
+
+#[test]
+fn test_fetch() {
+    let temp = tempdir().unwrap();
+    let data = (0..CHUNK_SIZE * 2 + 10)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    fs::write(temp.path().join("data.bin"), &data).unwrap();
+
+    let mut requests = Vec::new();
+    for path in &["data.bin", "missing.bin"] {
+        serde_json::to_writer(
+            &mut requests,
+            &Request::Read {
+                path: path.to_string(),
+            },
+        )
+        .unwrap();
+        requests.push(b'\n');
+    }
+    let mut replies = Vec::new();
+    serve_agent(temp.path(), requests.as_slice(), &mut replies).unwrap();
+
+    let mut conn = Connection::new(replies.as_slice(), io::sink());
+    let (id, _, len) = conn.fetch("data.bin").unwrap();
+    assert_eq!(id, hash_reader(data.as_slice()).unwrap().0);
+    assert_eq!(len, data.len() as u64);
+    assert!(matches!(conn.fetch("missing.bin"), Err(Error::Remote(_))));
+}
+
+#[test]
+fn test_fetch_failed_chunk() {
+    let mut replies = Vec::new();
+    write_reply(&mut replies, &Reply::Content { size: 10 }).unwrap();
+    replies.extend_from_slice(&3u64.to_be_bytes());
+    replies.extend_from_slice(b"abc");
+    replies.extend_from_slice(&CHUNK_FAILED.to_be_bytes());
+    write_reply(
+        &mut replies,
+        &Reply::Error {
+            message: "disk error".to_owned(),
+        },
+    )
+    .unwrap();
+
+    let mut conn = Connection::new(replies.as_slice(), io::sink());
+    match conn.fetch("file") {
+        Err(Error::Remote(msg)) => assert_eq!(msg, "file: disk error"),
+        r => panic!("unexpected result: {:?}", r.map(|(id, _, len)| (id, len))),
+    }
+}
+
+#[cfg(unix)]
+fn remote_scan(bank: &Bank, root: &Path, excludes: Patterns) -> (FsHash, ScanStats) {
+    use std::io::BufReader;
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+
+    let (local, remote) = UnixStream::pair().unwrap();
+    let root = root.to_owned();
+    let agent = thread::spawn(move || {
+        serve_agent(&root, BufReader::new(&remote), &remote).unwrap();
+    });
+
+    let conn = Connection::new(BufReader::new(local.try_clone().unwrap()), local);
+    let mut scanner = RemoteScanner::new(bank, conn).extra_patterns(excludes);
+    let hash = scanner.scan().unwrap();
+    let stats = scanner.stats();
+    drop(scanner);
+    agent.join().unwrap();
+    (hash, stats)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_remote_scan_same_as_local() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("sub/deep")).unwrap();
+    fs::write(target.join("top.txt"), "top").unwrap();
+    fs::write(target.join("sub/a.txt"), "a").unwrap();
+    fs::write(target.join("sub/deep/b.txt"), "b").unwrap();
+    std::os::unix::fs::symlink("sub", target.join("link")).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let local = Scanner::new(&bank).scan().unwrap();
+    let (remote, stats) = remote_scan(&bank, &target, Patterns::default());
+    assert_eq!(local, remote);
+    assert_eq!(stats.files, 3);
+    assert_eq!(stats.dirs, 3);
+    assert_eq!(stats.errors, 0);
+
+    // 前回のスキャンから更新されていないファイルは読み込まない
+    bank.save_history(remote.id(), Timestamp::now().unwrap(), None)
+        .unwrap();
+    let (again, stats) = remote_scan(&bank, &target, Patterns::default());
+    assert_eq!(again, remote);
+    assert_eq!(stats.bytes_read, 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_remote_scan_excludes() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("cache")).unwrap();
+    fs::write(target.join("keep.txt"), "keep").unwrap();
+    fs::write(target.join("skip.log"), "skip").unwrap();
+    fs::write(target.join("cache/data"), "data").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let excludes = parse_lines(vec!["*.log", "cache/"]).unwrap();
+    let (hash, stats) = remote_scan(&bank, &target, excludes);
+    let dir: DirEntry = bank.load_entry(&hash.id()).unwrap();
+    let names = dir
+        .children()
+        .map(|ch| ch.attr().name().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["keep.txt"]);
+    assert_eq!(stats.files, 1);
+}
@@ -0,0 +1,123 @@
+//! Bankの履歴を、参照するオブジェクトごと他のリポジトリのBankに複製する。
+
+use std::sync::Arc;
+
+use log::{info, trace};
+
+use crate::core::entry::{DirEntry, Entry, FsHash};
+use crate::core::hash::HashID;
+use crate::core::repo::{Bank, Error, History};
+
+#[cfg(test)]
+mod test;
+
+/// 複製の結果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Replicated {
+    /// 新たに複製したオブジェクトの数
+    pub objects: u64,
+    /// 新たに書き込んだバイト数
+    pub bytes: u64,
+}
+
+// 子エントリの複製を待っているディレクトリ
+struct Frame {
+    id: HashID,
+    dir: Arc<DirEntry>,
+    next: usize,
+}
+
+/// `src`の履歴`history`を、参照する全てのオブジェクトとともに`dst`に複製し、保存した履歴と複製の結果を返す。
+///
+/// 子エントリを全て複製してからディレクトリ自体を複製するため、`dst`に既にあるディレクトリの中は辿らない。
+pub fn replicate_history(
+    src: &Bank,
+    dst: &Bank,
+    history: &History,
+) -> Result<(History, Replicated), Error> {
+    let mut replicated = Replicated::default();
+
+    let mut stack = Vec::new();
+    if !dst.has_object(history.id()) {
+        stack.push(Frame {
+            id: history.id().clone(),
+            dir: src.load_dir(history.id())?,
+            next: 0,
+        });
+    }
+    while let Some(frame) = stack.last_mut() {
+        let ch = frame.dir.children().nth(frame.next).cloned();
+        frame.next += 1;
+        match ch {
+            Some(FsHash::Dir(d)) => {
+                let id = d.id();
+                if !dst.has_object(&id) {
+                    let dir = src.load_dir(&id)?;
+                    stack.push(Frame { id, dir, next: 0 });
+                }
+            }
+            Some(FsHash::File(f)) => {
+                for stream in f.streams() {
+                    copy_object(src, dst, stream.id(), &mut replicated)?;
+                }
+                copy_object(src, dst, &f.id(), &mut replicated)?;
+            }
+            Some(ch) => copy_object(src, dst, &ch.id(), &mut replicated)?,
+            None => {
+                let frame = stack.pop().unwrap();
+                for chunk_id in frame.dir.chunk_ids() {
+                    copy_object(src, dst, &chunk_id, &mut replicated)?;
+                }
+                copy_object(src, dst, &frame.id, &mut replicated)?;
+            }
+        }
+    }
+
+    if let Some(id) = history.inventory_id() {
+        copy_object(src, dst, id, &mut replicated)?;
+    }
+    let saved = dst.import_history(history)?;
+    info!(
+        "replicated {} objects ({} bytes) of {} to {}",
+        replicated.objects,
+        replicated.bytes,
+        src.name(),
+        dst.name()
+    );
+
+    Ok((saved, replicated))
+}
+
+/// `src`の全ての履歴を古い順に`dst`に複製し、複製した履歴の数と複製の結果を返す。
+///
+/// `dst`の最新の履歴は`src`の最新の履歴に揃える。
+pub fn replicate_bank(src: &Bank, dst: &Bank) -> Result<(usize, Replicated), Error> {
+    let histories = src.histories()?;
+    let mut total = Replicated::default();
+    for history in &histories {
+        let (_, replicated) = replicate_history(src, dst, history)?;
+        total.objects += replicated.objects;
+        total.bytes += replicated.bytes;
+    }
+
+    if let Some(last_scan) = src.last_scan()? {
+        replicate_history(src, dst, &last_scan)?;
+    }
+
+    Ok((histories.len(), total))
+}
+
+fn copy_object(
+    src: &Bank,
+    dst: &Bank,
+    id: &HashID,
+    replicated: &mut Replicated,
+) -> Result<(), Error> {
+    if dst.has_object(id) {
+        return Ok(());
+    }
+    trace!("replicate object {}", id);
+    replicated.bytes += dst.save_object(id, src.open_object(id)?)?;
+    replicated.objects += 1;
+    Ok(())
+}
@@ -0,0 +1,216 @@
+use super::*;
+
+use std::convert::TryFrom;
+use std::fs;
+
+use filetime::{set_file_mtime, FileTime};
+use tempfile::tempdir;
+
+use crate::core::entry::{Attributes, DataStream, DirEntryBuilder, FileEntry, CHUNK_THRESHOLD};
+use crate::core::hash::hash_reader;
+use crate::core::repo::Repository;
+use crate::core::scan::Scanner;
+use crate::core::timestamp::Timestamp;
+
+fn file_names(bank: &Bank, history: &History) -> Vec<String> {
+    bank.walk(history)
+        .map(|e| e.unwrap())
+        .filter(|(path, _)| !path.is_dir())
+        .map(|(path, _)| path.to_string())
+        .collect()
+}
+
+#[test]
+fn test_replicate_history() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("sub/deep")).unwrap();
+    fs::write(target.join("top.txt"), "top").unwrap();
+    fs::write(target.join("sub/a.txt"), "a").unwrap();
+    fs::write(target.join("sub/deep/b.txt"), "b").unwrap();
+
+    let primary = Repository::create(temp.path().join("primary")).unwrap();
+    primary.create_bank("bank", &target).unwrap();
+    let src = primary.open_bank("bank").unwrap();
+    let secondary = Repository::create(temp.path().join("secondary")).unwrap();
+    secondary.create_bank("bank", &target).unwrap();
+    let dst = secondary.open_bank("bank").unwrap();
+
+    let id = Scanner::new(&src).scan().unwrap();
+    let history = src.save_history(id.id(), Timestamp::from(1), None).unwrap();
+    let (saved, replicated) = replicate_history(&src, &dst, &history).unwrap();
+    assert_eq!(saved, history);
+    assert_eq!(dst.last_scan().unwrap(), Some(history.clone()));
+    assert_eq!(replicated.objects, 6);
+    assert_eq!(file_names(&dst, &history), file_names(&src, &history));
+
+    // 変更されたファイルとその祖先のディレクトリだけを複製する
+    fs::write(target.join("sub/deep/b.txt"), "changed").unwrap();
+    set_file_mtime(
+        target.join("sub/deep/b.txt"),
+        FileTime::from_unix_time(1_000_000, 0),
+    )
+    .unwrap();
+    let id = Scanner::new(&src).scan().unwrap();
+    let history = src.save_history(id.id(), Timestamp::from(2), None).unwrap();
+    let (_, replicated) = replicate_history(&src, &dst, &history).unwrap();
+    assert_eq!(replicated.objects, 4);
+
+    let (_, replicated) = replicate_history(&src, &dst, &history).unwrap();
+    assert_eq!(replicated, Replicated::default());
+}
+
+#[test]
+fn test_replicate_chunked_dir() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let primary = Repository::create(temp.path().join("primary")).unwrap();
+    primary.create_bank("bank", &target).unwrap();
+    let src = primary.open_bank("bank").unwrap();
+    let secondary = Repository::create(temp.path().join("secondary")).unwrap();
+    secondary.create_bank("bank", &target).unwrap();
+    let dst = secondary.open_bank("bank").unwrap();
+
+    let (file_id, temp_file) = hash_reader("same".as_bytes()).unwrap();
+    src.save_object(&file_id, temp_file).unwrap();
+    let mut builder = DirEntryBuilder::new(Attributes::new(
+        "target".to_owned(),
+        false,
+        Timestamp::from(1),
+    ));
+    for i in 0..CHUNK_THRESHOLD {
+        let mut file = FileEntry::new(Attributes::new(
+            format!("file{:05}", i),
+            false,
+            Timestamp::from(1),
+        ));
+        file.set_id(file_id.clone());
+        builder.append(FsHash::try_from(file).unwrap());
+    }
+    let (id, _) = src.save_dir_entry(&builder.build(), false).unwrap();
+    let chunks = src.load_dir(&id).unwrap().chunk_ids().len();
+    assert!(chunks > 1);
+
+    let history = src.save_history(id, Timestamp::from(1), None).unwrap();
+    let (_, replicated) = replicate_history(&src, &dst, &history).unwrap();
+    assert_eq!(replicated.objects as usize, 1 + chunks + 1);
+    assert_eq!(file_names(&dst, &history).len(), CHUNK_THRESHOLD);
+}
+
+#[test]
+fn test_replicate_streams() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let primary = Repository::create(temp.path().join("primary")).unwrap();
+    primary.create_bank("bank", &target).unwrap();
+    let src = primary.open_bank("bank").unwrap();
+    let secondary = Repository::create(temp.path().join("secondary")).unwrap();
+    secondary.create_bank("bank", &target).unwrap();
+    let dst = secondary.open_bank("bank").unwrap();
+
+    let (file_id, temp_file) = hash_reader("content".as_bytes()).unwrap();
+    src.save_object(&file_id, temp_file).unwrap();
+    let (stream_id, temp_file) = hash_reader("[ZoneTransfer]".as_bytes()).unwrap();
+    src.save_object(&stream_id, temp_file).unwrap();
+
+    let mut file = FileEntry::new(Attributes::new(
+        "file.txt".to_owned(),
+        false,
+        Timestamp::from(1),
+    ));
+    file.set_id(file_id.clone());
+    file.set_streams(vec![DataStream::new(
+        "Zone.Identifier".to_owned(),
+        stream_id.clone(),
+    )]);
+    let mut builder = DirEntryBuilder::new(Attributes::new(
+        "target".to_owned(),
+        false,
+        Timestamp::from(1),
+    ));
+    builder.append(FsHash::try_from(file).unwrap());
+    let (id, _) = src.save_dir_entry(&builder.build(), false).unwrap();
+
+    let history = src
+        .save_history(id.clone(), Timestamp::from(1), None)
+        .unwrap();
+    let (_, replicated) = replicate_history(&src, &dst, &history).unwrap();
+    assert_eq!(replicated.objects, 3);
+    assert!(dst.has_object(&stream_id));
+
+    // 保存したエントリからストリームを読み戻せる
+    match dst.load_dir(&id).unwrap().find_file("file.txt") {
+        Some(file) => assert_eq!(file.streams()[0].id(), &stream_id),
+        None => panic!("file.txt is not found"),
+    }
+}
+
+#[test]
+fn test_replicate_bank() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("a.txt"), "a").unwrap();
+
+    let primary = Repository::create(temp.path().join("primary")).unwrap();
+    primary.create_bank("bank", &target).unwrap();
+    let src = primary.open_bank("bank").unwrap();
+    src.append_ignore_lines(&["*.tmp"]).unwrap();
+
+    let id = Scanner::new(&src).scan().unwrap();
+    let first = src.save_history(id.id(), Timestamp::from(1), None).unwrap();
+    fs::write(target.join("b.txt"), "b").unwrap();
+    let id = Scanner::new(&src).scan().unwrap();
+    let second = src.save_history(id.id(), Timestamp::from(2), None).unwrap();
+
+    let secondary = Repository::create(temp.path().join("secondary")).unwrap();
+    secondary.create_bank_from(&src).unwrap();
+    let dst = secondary.open_bank("bank").unwrap();
+    assert_eq!(dst.target_path(), src.target_path());
+    assert_eq!(dst.created_at(), src.created_at());
+    assert_eq!(
+        fs::read_to_string(temp.path().join("secondary/banks/bank/ignore")).unwrap(),
+        "*.tmp\n"
+    );
+
+    let (count, replicated) = replicate_bank(&src, &dst).unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(replicated.objects, 4);
+    assert_eq!(dst.histories().unwrap(), vec![first, second.clone()]);
+    assert_eq!(dst.last_scan().unwrap(), Some(second.clone()));
+    assert_eq!(file_names(&dst, &second), vec!["a.txt", "b.txt"]);
+
+    // 中断した移行は同じ操作で再開できる
+    secondary.create_bank_from(&src).unwrap();
+    let (_, replicated) = replicate_bank(&src, &dst).unwrap();
+    assert_eq!(replicated, Replicated::default());
+
+    let lock = src.lock().unwrap();
+    src.remove(lock).unwrap();
+    assert!(!primary.bank_exists("bank").unwrap());
+    assert!(primary.has_object(second.id()));
+}
+
+#[test]
+fn test_create_bank_from_conflict() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let other = temp.path().join("other");
+    fs::create_dir_all(&target).unwrap();
+    fs::create_dir_all(&other).unwrap();
+
+    let primary = Repository::create(temp.path().join("primary")).unwrap();
+    primary.create_bank("bank", &target).unwrap();
+    let src = primary.open_bank("bank").unwrap();
+    let secondary = Repository::create(temp.path().join("secondary")).unwrap();
+    secondary.create_bank("bank", &other).unwrap();
+
+    match secondary.create_bank_from(&src) {
+        Err(Error::InvalidInput(_)) => {}
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
@@ -1,22 +1,99 @@
 //! バックアップ先となるリポジトリの操作
-
+//!
+//! # 並行アクセス
+//!
+//! NASなどに置かれた1つのリポジトリに、複数のマシンから同時にバックアップすることを想定している。
+//!
+//! * オブジェクトはハッシュ値で名前が決まるため、同じIDのオブジェクトは常に同じ内容になる。
+//!   一時ファイルに書き込んでからリネームするため、書き込み途中のオブジェクトが他から見えることはなく、
+//!   同じオブジェクトを同時に書き込んでも結果は変わらない。
+//! * Bankの履歴や`last_scan.json`も一時ファイルからのリネームで置き換えるため、読み込み側が書き込み途中の内容を見ることはない。
+//! * 同じBankへの同時バックアップは[`Bank::lock`](struct.Bank.html#method.lock)によるロックファイルで排除される。
+//!   [`Bank::lock_wait`](struct.Bank.html#method.lock_wait)を使えば、先に始めた方の終了を待てる。
+//!
+//! したがって、異なるBankへの同時バックアップは安全であり、同じBankへの同時バックアップは後から始めた方がエラーになる。
+
+use std::collections::HashMap;
+use std::error;
 use std::ffi::OsString;
+use std::fmt;
 use std::fs;
-use std::io;
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-
-use failure::Fail;
-use log::trace;
+use std::process;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{Local, TimeZone};
+use log::{info, trace, warn};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::{self, from_reader, to_writer};
+use tempfile::NamedTempFile;
 
-use crate::core::entry::{DirEntry, Entry};
-use crate::core::hash::{self, HashID};
+use crate::core::encoding::{self, Encoding};
+use crate::core::entry::{ChunkRef, DirEntry, Entry, FsHash, MAX_ENTRY_VERSION};
+use crate::core::hash::{self, HashID, Hasher};
 use crate::core::ignore::pattern::{self, load_patterns, Patterns};
+use crate::core::parity::{Parity, Repair};
+use crate::core::scan::ChangeKind;
 use crate::core::timestamp::Timestamp;
+use crate::core::walk::{LazyDir, Walk};
+use crate::util::copy::{copy_buffered, drop_cache, preallocate, sync_dir, DEFAULT_BUFFER_SIZE};
+use crate::util::disk::{disk_space, DiskSpace};
+use crate::util::host::{hostname, username};
+use crate::util::lru::LruCache;
+use crate::util::size::Size;
+
+pub mod content;
+mod history_index;
+pub mod index;
+pub mod path_index;
+pub mod verified;
+
+#[cfg(test)]
+mod test;
+
+use self::content::{extract_tokens, ContentIndex};
+use self::index::ObjectIndex;
+use self::path_index::{PathIndex, PathRecord};
+use self::verified::VerifiedTimes;
+
+const FORMAT_FILE: &str = "format.json";
+
+/// 現行の履歴のスキーマのバージョン
+///
+/// [`ENTRY_VERSION`](../entry/constant.ENTRY_VERSION.html)と同じく、古いプログラムが読み飛ばすと誤った結果になるフィールドを追加した場合に上げる。
+pub const HISTORY_VERSION: u32 = 1;
+
+/// 棚卸し履歴のバージョン
+///
+/// 棚卸しを知らないプログラムが通常の履歴と誤認しないよう、通常の履歴より大きくしている。
+pub const INVENTORY_HISTORY_VERSION: u32 = 2;
 
+/// このプログラムが読み込める履歴のバージョンの上限
+pub const MAX_HISTORY_VERSION: u32 = INVENTORY_HISTORY_VERSION;
 const BANK_CONFIG_FILE: &str = "config.json";
+const BANK_LOCK_FILE: &str = "lock.json";
+const CONTENT_INDEX_FILE: &str = "content.idx";
+const PATH_INDEX_FILE: &str = "paths.json";
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(500);
 const HISTORY_SUFFIX: &str = ".history.json";
+const HISTORY_INDEX_FILE: &str = "history.idx";
+// 履歴のファイル名に含めるルートのIDの桁数
+const HISTORY_ID_DIGITS: usize = 16;
+const OBJECT_INDEX_FILE: &str = "objects.idx";
+const VERIFIED_FILE: &str = "verified.idx";
+const IGNORE_FILE: &str = "ignore";
+
+// バックアップを中止または警告する空き容量と空きinode数
+const FREE_SPACE_LIMITS: FreeSpaceLimits = FreeSpaceLimits {
+    min_bytes: 64 * 1024 * 1024,
+    warn_bytes: 1024 * 1024 * 1024,
+    min_inodes: 1024,
+    warn_inodes: 64 * 1024,
+};
 
 /// バックアップ先となるリポジトリのディレクトリを管理する型。
 ///
@@ -26,6 +103,22 @@ pub struct Repository {
     path: PathBuf,
     objects_dir: PathBuf,
     banks_dir: PathBuf,
+    copy_buffer_size: usize,
+    object_index: Mutex<Option<ObjectIndex>>,
+    format: RepoFormat,
+}
+
+// リポジトリ全体のデータ形式の設定
+//
+// 設定ファイルが無い古いリポジトリでは既定値になる。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct RepoFormat {
+    #[serde(default)]
+    entry_encoding: Encoding,
+    #[serde(default)]
+    parity: bool,
+    #[serde(default)]
+    verify_after_write: bool,
 }
 
 impl Repository {
@@ -35,31 +128,78 @@ impl Repository {
     ///
     /// 必須のリポジトリとして必要なルートディレクトリ、`objects`ディレクトリ、`banks`ディレクトリのどれかが存在しないか書き込み不可能な場合、[`Error::IncompleteRepo`](enum.Error.html)を返す。
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Repository, Error> {
-        let repo = Repository::new(path);
+        let mut repo = Repository::new(path);
 
         check_path(&repo.path, "repository directory")?;
         check_path(&repo.objects_dir, "/object")?;
         check_path(&repo.banks_dir, "/banks")?;
+        repo.load_format()?;
 
         Ok(repo)
     }
 
     /// リポジトリを生成する。
     ///
+    /// 既にリポジトリであるディレクトリを指定した場合は、そのまま開く。
+    ///
     /// # Failures
     ///
+    /// リポジトリではないディレクトリに既にファイルがある場合、[`Error::NotEmptyDirectory`](enum.Error.html#variant.NotEmptyDirectory)を返す。
+    /// 無関係なデータとの混在を防ぐためで、そのようなディレクトリに作成するには[`create_force`](#method.create_force)を使う。
+    ///
     /// 生成に失敗した場合、[`Error::IO`](enum.Error.html)を返す。
     pub fn create<P: AsRef<Path>>(path: P) -> Result<Repository, Error> {
+        let path = path.as_ref();
+        if !Repository::is_repository(path) && path.is_dir() && path.read_dir()?.next().is_some() {
+            return Err(Error::NotEmptyDirectory(path.to_owned()));
+        }
+        Repository::create_force(path)
+    }
+
+    /// 既にファイルがあるディレクトリでも、そのままリポジトリを生成する。
+    ///
+    /// # Failures
+    ///
+    /// 生成に失敗した場合、[`Error::IO`](enum.Error.html)を返す。
+    pub fn create_force<P: AsRef<Path>>(path: P) -> Result<Repository, Error> {
         // TODO: 読み込み専用の場合エラーにする。
-        let repo = Repository::new(path);
+        let mut repo = Repository::new(path);
 
         ensure_dir(&repo.path)?;
         ensure_dir(&repo.objects_dir)?;
         ensure_dir(&repo.banks_dir)?;
+        repo.load_format()?;
 
         Ok(repo)
     }
 
+    /// `start_dir`とその祖先のディレクトリを近い順に調べ、最初に見つかったリポジトリを開く。
+    ///
+    /// 見つからなければ`None`を返す。
+    pub fn discover<P: AsRef<Path>>(start_dir: P) -> Result<Option<Repository>, Error> {
+        let start_dir = start_dir.as_ref().canonicalize()?;
+        for dir in start_dir.ancestors() {
+            if Repository::is_repository(dir) {
+                trace!("repository found at {:?}", dir);
+                return Repository::open(dir).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// `path`がリポジトリのディレクトリかどうかを返す。
+    ///
+    /// `objects`ディレクトリと`banks`ディレクトリを両方持つディレクトリをリポジトリとみなす。
+    pub fn is_repository<P: AsRef<Path>>(path: P) -> bool {
+        let path = path.as_ref();
+        path.join("objects").is_dir() && path.join("banks").is_dir()
+    }
+
+    /// リポジトリのルートディレクトリのパスを返す。
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     // ディレクトリの存在を保証するため、`new`は内部専用。
     fn new<P: AsRef<Path>>(path: P) -> Repository {
         let path = path.as_ref().to_owned();
@@ -70,6 +210,205 @@ impl Repository {
             path,
             objects_dir,
             banks_dir,
+            copy_buffer_size: DEFAULT_BUFFER_SIZE,
+            object_index: Mutex::new(None),
+            format: RepoFormat::default(),
+        }
+    }
+
+    fn load_format(&mut self) -> Result<(), Error> {
+        let path = self.path.join(FORMAT_FILE);
+        if path.exists() {
+            self.format = from_reader(fs::File::open(&path)?)?;
+        }
+        Ok(())
+    }
+
+    /// 新たに保存するエントリと履歴の形式を返す。
+    pub fn entry_encoding(&self) -> Encoding {
+        self.format.entry_encoding
+    }
+
+    /// 新たに保存するエントリと履歴の形式を設定し、リポジトリに記録する。
+    ///
+    /// 既に保存されているエントリと履歴は書き換えない。読み込み時は形式を判別するため、異なる形式が混在してもよい。
+    pub fn set_entry_encoding(&mut self, encoding: Encoding) -> Result<(), Error> {
+        self.format.entry_encoding = encoding;
+        self.save_format()
+    }
+
+    /// 新たに保存するオブジェクトのパリティを生成するかどうかを返す。
+    pub fn parity_enabled(&self) -> bool {
+        self.format.parity
+    }
+
+    /// 新たに保存するオブジェクトのパリティを生成するかどうかを設定し、リポジトリに記録する。
+    ///
+    /// 既に保存されているオブジェクトのパリティは[`save_parity`](#method.save_parity)で生成する。
+    pub fn set_parity_enabled(&mut self, enabled: bool) -> Result<(), Error> {
+        self.format.parity = enabled;
+        self.save_format()
+    }
+
+    /// 保存したオブジェクトをすぐに読み直して検証するかどうかを返す。
+    pub fn verify_after_write_enabled(&self) -> bool {
+        self.format.verify_after_write
+    }
+
+    /// 保存したオブジェクトをすぐに読み直して検証するかどうかを設定し、リポジトリに記録する。
+    ///
+    /// 有効な場合、オブジェクトを書き込むたびにディレクトリまでディスクに書き込み、
+    /// ページキャッシュを破棄してからハッシュを計算し直す。
+    /// 書き込みが遅くなる代わりに、不安定な記録媒体への書き込みの失敗をバックアップ時に検出できる。
+    pub fn set_verify_after_write(&mut self, enabled: bool) -> Result<(), Error> {
+        self.format.verify_after_write = enabled;
+        self.save_format()
+    }
+
+    fn save_format(&self) -> Result<(), Error> {
+        write_atomic(&self.path.join(FORMAT_FILE), |f| {
+            to_writer(f, &self.format)?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// オブジェクトの保存時に使うバッファのサイズを設定する。
+    pub fn set_copy_buffer_size(&mut self, size: usize) {
+        self.copy_buffer_size = size;
+    }
+
+    /// 存在するオブジェクトの索引を読み込み、索引に含まれるオブジェクトの個数を返す。
+    ///
+    /// 索引を読み込んだ後は、オブジェクトの保存時に索引で重複を判定する。
+    /// 索引に含まれるオブジェクトはファイルの存在のみを確認し、存在しなければ索引から取り除いて保存し直す。
+    /// 保存済みの索引がないか読み込めない場合は、`objects`ディレクトリを走査して作り直す。
+    /// 索引は[`save_object_index`](#method.save_object_index)で保存される。
+    pub fn load_object_index(&self) -> Result<usize, Error> {
+        let path = self.path.join(OBJECT_INDEX_FILE);
+        let loaded = match fs::File::open(&path) {
+            Ok(f) => match ObjectIndex::read_from(f) {
+                Ok(index) => Some(index),
+                Err(e) => {
+                    warn!(
+                        "rebuild object index because failed to read {:?}: {}",
+                        path, e
+                    );
+                    None
+                }
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        let index = match loaded {
+            Some(index) => index,
+            None => {
+                trace!("scan objects to build object index");
+                ObjectIndex::scan(&self.objects_dir)?
+            }
+        };
+        trace!("object index loaded: {} objects", index.len());
+
+        let len = index.len();
+        *self.object_index_lock() = Some(index);
+        Ok(len)
+    }
+
+    /// 読み込んだオブジェクトの索引を保存する。
+    ///
+    /// 索引を読み込んでいない場合は何もしない。
+    pub fn save_object_index(&self) -> Result<(), Error> {
+        if let Some(index) = self.object_index_lock().as_ref() {
+            write_atomic(&self.path.join(OBJECT_INDEX_FILE), |f| index.write_to(f))?;
+            trace!("object index saved: {} objects", index.len());
+        }
+        Ok(())
+    }
+
+    /// オブジェクトごとの最終検証日時の記録を読み込む。
+    ///
+    /// 記録が無い場合や読み込めない場合は、空の記録を返す。
+    pub fn load_verified_times(&self) -> Result<VerifiedTimes, Error> {
+        let path = self.path.join(VERIFIED_FILE);
+        match fs::File::open(&path) {
+            Ok(f) => match VerifiedTimes::read_from(f) {
+                Ok(verified) => Ok(verified),
+                Err(e) => {
+                    warn!(
+                        "ignore verified times because failed to read {:?}: {}",
+                        path, e
+                    );
+                    Ok(VerifiedTimes::new())
+                }
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(VerifiedTimes::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// オブジェクトごとの最終検証日時の記録を保存する。
+    pub fn save_verified_times(&self, verified: &VerifiedTimes) -> Result<(), Error> {
+        write_atomic(&self.path.join(VERIFIED_FILE), |f| verified.write_to(f))?;
+        trace!("verified times saved: {} objects", verified.len());
+        Ok(())
+    }
+
+    /// 保存済みのオブジェクトの索引を破棄する。
+    ///
+    /// オブジェクトを削除した場合は、削除したオブジェクトを存在するものと誤認しないように呼び出す必要がある。
+    pub fn invalidate_object_index(&self) -> Result<(), Error> {
+        *self.object_index_lock() = None;
+        match fs::remove_file(self.path.join(OBJECT_INDEX_FILE)) {
+            Ok(()) => Ok(()),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // 他のスレッドがパニックしても索引自体は壊れないため、そのまま使う。
+    fn object_index_lock(&self) -> MutexGuard<'_, Option<ObjectIndex>> {
+        self.object_index
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// 全てのBankに共通の除外リストを読み込む。
+    ///
+    /// 除外リストはリポジトリ直下の`ignore`ファイルで、存在しない場合は空のリストを返す。
+    pub fn load_ignore_patterns(&self) -> Result<Patterns, Error> {
+        load_ignore_file(&self.path.join(IGNORE_FILE))
+    }
+
+    /// リポジトリのファイルシステムの空き容量と空きinode数を検査する。
+    ///
+    /// 空きが少ない場合は警告をログに出力する。
+    /// 空き状況を取得できない環境では検査をせずに`None`を返す。
+    ///
+    /// # Failures
+    ///
+    /// 空きがバックアップを継続できないほど少ない場合、[`Error::InsufficientSpace`](enum.Error.html#variant.InsufficientSpace)を返す。
+    pub fn check_free_space(&self) -> Result<Option<DiskSpace>, Error> {
+        let space = match disk_space(&self.path)? {
+            Some(space) => space,
+            None => {
+                trace!("disk space check isn't supported");
+                return Ok(None);
+            }
+        };
+        trace!("repository disk space = {:?}", space);
+
+        match FREE_SPACE_LIMITS.classify(&space) {
+            Ok(warnings) => {
+                for w in warnings {
+                    warn!("repository filesystem is running out of {}", w);
+                }
+                Ok(Some(space))
+            }
+            Err(msg) => Err(Error::InsufficientSpace(format!(
+                "{} on {:?}",
+                msg, self.path
+            ))),
         }
     }
 
@@ -86,7 +425,33 @@ impl Repository {
     /// 全ての[`Bank`](struct.Bank.html)を開くイテレータを取得する。
     ///
     /// 要素の順序はBankの名前の辞書順になる。
+    /// 開けなかったBankは[`Error::BrokenBank`](enum.Error.html#variant.BrokenBank)として返されるため、残りのBankの処理を続けられる。
     pub fn open_all_banks(&self) -> Result<Banks, Error> {
+        let mut names = self.bank_names()?;
+        names.reverse();
+
+        Ok(Banks { repo: self, names })
+    }
+
+    /// 全ての[`Bank`](struct.Bank.html)の概要を名前の辞書順で取得する。
+    ///
+    /// 概要を読み込めなかったBankは、[`Error::BrokenBank`](enum.Error.html#variant.BrokenBank)として別に返す。
+    pub fn bank_infos(&self) -> Result<(Vec<BankInfo>, Vec<Error>), Error> {
+        let mut infos = Vec::new();
+        let mut broken = Vec::new();
+        for bank in self.open_all_banks()? {
+            let info =
+                bank.and_then(|bank| bank.info().map_err(|e| Error::broken_bank(bank.name(), e)));
+            match info {
+                Ok(info) => infos.push(info),
+                Err(e) => broken.push(e),
+            }
+        }
+        Ok((infos, broken))
+    }
+
+    /// 全ての[`Bank`](struct.Bank.html)の名前を辞書順で取得する。
+    pub fn bank_names(&self) -> Result<Vec<String>, Error> {
         let mut names = Vec::<String>::new();
 
         for dir_entry in self.banks_dir.read_dir()? {
@@ -98,9 +463,7 @@ impl Repository {
         }
 
         names.sort();
-        names.reverse();
-
-        Ok(Banks { repo: self, names })
+        Ok(names)
     }
 
     /// 指定された名前の[`Bank`](struct.Bank.html)を作成する。
@@ -114,37 +477,193 @@ impl Repository {
                 target_path
             )));
         }
-        let bank_config = BankConfig { target_path };
+        let bank_config = BankConfig {
+            target_path,
+            created_at: Timestamp::now().ok(),
+        };
 
         let bank = Bank::new(self, name, bank_dir, bank_config);
         bank.create()?;
         Ok(())
     }
 
+    /// 他のリポジトリの`src`と同じ名前・バックアップ元・作成日時のBankを作成し、除外リストも複製する。
+    ///
+    /// 同じバックアップ元の同名のBankが既にあれば何もしない。
+    /// 中断した移行を再開できるようにするためである。
+    ///
+    /// # Failures
+    ///
+    /// バックアップ元が異なる同名のBankがある場合、[`Error::InvalidInput`](enum.Error.html#variant.InvalidInput)を返す。
+    pub fn create_bank_from(&self, src: &Bank) -> Result<(), Error> {
+        if self.bank_exists(src.name())? {
+            let existing = self.open_bank(src.name())?;
+            if existing.target_path() != src.target_path() {
+                return Err(Error::InvalidInput(format!(
+                    "bank '{}' already exists with other target path {:?}",
+                    src.name(),
+                    existing.target_path()
+                )));
+            }
+            return Ok(());
+        }
+
+        let bank = Bank::new(
+            self,
+            src.name(),
+            self.bank_path(src.name()),
+            src.config.clone(),
+        );
+        bank.create()?;
+
+        let ignore_file = src.ignore_file();
+        if ignore_file.exists() {
+            let content = fs::read(&ignore_file)?;
+            write_atomic(&bank.ignore_file(), |f| f.write_all(&content))?;
+        }
+        Ok(())
+    }
+
     /// 指定された名前のbankがあるかどうかチェックする。
     pub fn bank_exists(&self, name: &str) -> Result<bool, Error> {
         let bank_dir = self.bank_path(name);
         Ok(bank_dir.exists())
     }
 
-    fn save_object(&self, id: &HashID, mut temp: fs::File) -> Result<(), io::Error> {
+    /// リポジトリに保存されている全てのオブジェクトのIDを返す。
+    ///
+    /// 索引は使わず、オブジェクトのディレクトリを走査する。
+    pub fn object_ids(&self) -> Result<Vec<HashID>, Error> {
+        Ok(ObjectIndex::scan(&self.objects_dir)?.into_ids())
+    }
+
+    /// 指定された`id`のオブジェクトのパリティが無ければ生成し、生成したかどうかを返す。
+    ///
+    /// 壊れたオブジェクトからパリティを生成しないよう、オブジェクトを検証しながら計算する。
+    pub fn save_parity(&self, id: &HashID) -> Result<bool, Error> {
+        let parity_path = self.parity_path(id);
+        if parity_path.exists() {
+            return Ok(false);
+        }
+
+        let parity = Parity::generate(self.stream_object(id)?)?;
+        fs::create_dir_all(parity_path.parent().unwrap())?;
+        write_atomic(&parity_path, |f| parity.write_to(f))?;
+        Ok(true)
+    }
+
+    /// 指定された`id`の壊れたオブジェクトをパリティから修復する。
+    ///
+    /// パリティが無い場合は`None`を返す。
+    /// 修復したオブジェクトはハッシュ値を確認してから置き換えるため、修復できなかった場合はオブジェクトを変更しない。
+    pub fn repair_object(&self, id: &HashID) -> Result<Option<Repair>, Error> {
+        let parity = match fs::File::open(self.parity_path(id)) {
+            Ok(f) => Parity::read_from(f)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let obj_path = self.object_path(id);
+        if !obj_path.exists() {
+            return Err(Error::EntryNotFound(id.clone()));
+        }
+
+        let mut temp = NamedTempFile::new_in(obj_path.parent().unwrap())?;
+        io::copy(&mut fs::File::open(&obj_path)?, temp.as_file_mut())?;
+        let result = parity.repair(temp.as_file_mut())?;
+        if let Repair::Repaired(_) = result {
+            temp.as_file_mut().seek(SeekFrom::Start(0))?;
+            let mut r = VerifyingReader::new(temp.as_file_mut(), id.clone());
+            match io::copy(&mut r, &mut io::sink()).map_err(Error::from) {
+                Ok(_) => {}
+                Err(Error::BrokenObject { .. }) => return Ok(Some(Repair::Unrepairable)),
+                Err(e) => return Err(e),
+            }
+            temp.as_file().sync_all()?;
+            temp.persist(&obj_path).map_err(|e| e.error)?;
+            info!("object {} repaired", id);
+        }
+        Ok(Some(result))
+    }
+
+    /// 指定された`id`のオブジェクトを最後まで読み込んで整合性を検証し、サイズを返す。
+    pub fn verify_object(&self, id: &HashID) -> Result<u64, Error> {
+        let mut r = self.stream_object(id)?;
+        Ok(io::copy(&mut r, &mut io::sink())?)
+    }
+
+    // オブジェクトを保存し、新たに書き込んだバイト数を返す。既に保存されていた場合は0を返す。
+    fn save_object(&self, id: &HashID, mut temp: fs::File) -> Result<u64, io::Error> {
         let out_path = self.object_path(id);
 
-        if out_path.exists() {
+        // 索引がある場合は索引にないものを新規とみなし、存在の確認を省く。
+        let index_loaded = match self.object_index_lock().as_mut() {
+            Some(index) => {
+                if confirm_indexed(index, id, &out_path) {
+                    trace!("skip save indexed object {}", id);
+                    return Ok(0);
+                }
+                true
+            }
+            None => false,
+        };
+
+        if !index_loaded && out_path.exists() {
             let new_size = temp.metadata()?.len();
             let old_size = out_path.metadata()?.len();
             if new_size == old_size {
                 trace!("skip save existing object {}", id);
-                return Ok(());
+                return Ok(0);
             }
         }
 
         let out_dir = out_path.parent().unwrap();
         fs::create_dir_all(out_dir)?;
 
-        let mut f = fs::File::create(&out_path)?;
-        io::copy(&mut temp, &mut f)?;
+        let len = temp.metadata()?.len();
+        write_atomic(&out_path, |f| {
+            preallocate(f, len)?;
+            copy_buffered(&mut temp, f, self.copy_buffer_size)?;
+            Ok(())
+        })?;
+        if self.format.verify_after_write {
+            self.verify_written_object(id, &out_path)?;
+        }
+
+        if self.format.parity {
+            let parity = Parity::generate(fs::File::open(&out_path)?)?;
+            let parity_path = self.parity_path(id);
+            fs::create_dir_all(parity_path.parent().unwrap())?;
+            write_atomic(&parity_path, |f| parity.write_to(f))?;
+        }
 
+        if let Some(index) = self.object_index_lock().as_mut() {
+            index.insert(id.clone());
+        }
+        Ok(len)
+    }
+
+    // 書き込んだばかりのオブジェクトをディスクから読み直し、IDが一致するかを確認する。
+    //
+    // 一致しない場合や読み込めない場合は、壊れたオブジェクトを残さないように削除する。
+    fn verify_written_object(&self, id: &HashID, path: &Path) -> Result<(), io::Error> {
+        // 作成したシャードのディレクトリも含めて、`objects`ディレクトリまでを書き込む。
+        for dir in path.ancestors().skip(1) {
+            sync_dir(dir)?;
+            if dir == self.objects_dir {
+                break;
+            }
+        }
+
+        let f = fs::File::open(path)?;
+        drop_cache(&f)?;
+        let mut r = VerifyingReader::new(f, id.clone());
+        if let Err(e) = io::copy(&mut r, &mut io::sink()) {
+            drop(r);
+            fs::remove_file(path)?;
+            return Err(e);
+        }
+        trace!("verified written object {}", id);
         Ok(())
     }
 
@@ -166,15 +685,43 @@ impl Repository {
         Ok(f)
     }
 
-    fn object_path(&self, id: &HashID) -> PathBuf {
-        let mut res = self.object_dir().to_owned();
+    fn stream_object(&self, id: &HashID) -> Result<VerifyingReader<fs::File>, Error> {
+        let obj_path = self.object_path(id);
+        if !obj_path.exists() {
+            return Err(Error::EntryNotFound(id.clone()));
+        }
+
+        let f = fs::File::open(&obj_path)?;
+        Ok(VerifyingReader::new(f, id.clone()))
+    }
+
+    /// 指定された`id`のオブジェクトが保存されているかを返す。
+    ///
+    /// 索引を読み込んでいる場合は、索引に含まれるもののみファイルの存在を確認する。
+    pub fn has_object(&self, id: &HashID) -> bool {
+        let path = self.object_path(id);
+        match self.object_index_lock().as_mut() {
+            Some(index) => confirm_indexed(index, id, &path),
+            None => path.exists(),
+        }
+    }
+
+    /// 指定された`id`のオブジェクトのリポジトリ内でのサイズを得る。
+    pub fn object_size(&self, id: &HashID) -> Result<u64, Error> {
+        let obj_path = self.object_path(id);
+        if !obj_path.exists() {
+            return Err(Error::EntryNotFound(id.clone()));
+        }
+
+        Ok(obj_path.metadata()?.len())
+    }
 
-        let (p0, p1, p2) = id.parts();
-        res.push(p0);
-        res.push(p1);
-        res.push(p2);
+    fn object_path(&self, id: &HashID) -> PathBuf {
+        self.object_dir().join(id.shard_path())
+    }
 
-        res
+    fn parity_path(&self, id: &HashID) -> PathBuf {
+        self.path.join("parity").join(id.shard_path())
     }
 
     fn bank_path(&self, name: &str) -> PathBuf {
@@ -206,14 +753,18 @@ impl<'a> Iterator for Banks<'a> {
     type Item = Result<Bank<'a>, Error>;
 
     fn next(&mut self) -> Option<Result<Bank<'a>, Error>> {
-        if let Some(name) = self.names.pop() {
-            Some(self.repo.open_bank(&name))
-        } else {
-            None
-        }
+        let name = self.names.pop()?;
+        Some(
+            self.repo
+                .open_bank(&name)
+                .map_err(|e| Error::broken_bank(&name, e)),
+        )
     }
 }
 
+/// [`Bank`](struct.Bank.html)がキャッシュするディレクトリエントリの個数の既定値
+pub const DEFAULT_DIR_CACHE_SIZE: usize = 1024;
+
 /// バックアップ元に対応した履歴の保存先を表す型
 #[derive(Debug)]
 pub struct Bank<'a> {
@@ -221,6 +772,7 @@ pub struct Bank<'a> {
     name: String,
     path: PathBuf,
     config: BankConfig,
+    dir_cache: Mutex<LruCache<HashID, Arc<DirEntry>>>,
 }
 
 impl<'a> Bank<'a> {
@@ -230,47 +782,410 @@ impl<'a> Bank<'a> {
             name: name.to_owned(),
             path,
             config,
+            dir_cache: Mutex::new(LruCache::new(DEFAULT_DIR_CACHE_SIZE)),
         }
     }
 
-    /// ファイルを指定された`id`のオブジェクトとして保存する。
-    pub fn save_object(&self, id: &HashID, file: fs::File) -> Result<(), io::Error> {
+    /// 読み込んだディレクトリエントリをキャッシュする個数を設定する。
+    ///
+    /// 0を指定するとキャッシュしない。
+    pub fn set_dir_cache_size(&self, size: usize) {
+        self.dir_cache_lock().set_capacity(size);
+    }
+
+    /// ファイルを指定された`id`のオブジェクトとして保存し、新たに書き込んだバイト数を返す。
+    ///
+    /// 同じオブジェクトが既に保存されていた場合は何もせず0を返す。
+    pub fn save_object(&self, id: &HashID, file: fs::File) -> Result<u64, io::Error> {
         self.repo.save_object(id, file)
     }
 
-    /// スキャン結果のエンティティのIDを`timestamp`時点での履歴として保存する。
-    pub fn save_history(&self, id: HashID, timestamp: Timestamp) -> Result<(), io::Error> {
+    /// 指定された`id`のオブジェクトが保存されているかを返す。
+    pub fn has_object(&self, id: &HashID) -> bool {
+        self.repo.has_object(id)
+    }
+
+    /// ディレクトリエントリをオブジェクトとして保存し、そのIDと新たに書き込んだバイト数を返す。
+    ///
+    /// 子の多いディレクトリは子のリストを分割し、それぞれ別のオブジェクトとして保存する。
+    /// `dry_run`が`true`の場合はIDを計算するだけで保存しない。
+    pub fn save_dir_entry(&self, entry: &DirEntry, dry_run: bool) -> Result<(HashID, u64), Error> {
+        let chunks = match entry.split_children() {
+            Some(chunks) => chunks,
+            None => return self.save_encoded(entry, dry_run),
+        };
+
+        let mut refs = Vec::with_capacity(chunks.len());
+        let mut stored = 0;
+        for chunk in &chunks {
+            let (id, len) = self.save_encoded(chunk, dry_run)?;
+            refs.push(ChunkRef::new(id, chunk.len()));
+            stored += len;
+        }
+        trace!("dir entry split into {} chunks", refs.len());
+        let (id, len) = self.save_encoded(&entry.with_chunk_refs(refs), dry_run)?;
+        Ok((id, stored + len))
+    }
+
+    fn save_encoded<T: Serialize>(&self, value: &T, dry_run: bool) -> Result<(HashID, u64), Error> {
+        let encoded = self.entry_encoding().encode(value)?;
+        let (id, temp) = hash::hash_reader(encoded.as_slice())?;
+        let stored = if dry_run {
+            0
+        } else {
+            self.save_object(&id, temp)?
+        };
+        Ok((id, stored))
+    }
+
+    /// スキャン結果のエンティティのIDを`timestamp`時点での履歴として保存し、保存した履歴を返す。
+    ///
+    /// `stats`を指定した場合は、スキャンの統計も履歴に記録する。
+    ///
+    /// 同じ時刻に同じルートの履歴が既に保存されている場合は重複した実行とみなし、新たに保存せずに既存の履歴を返す。
+    /// 同じ時刻でもルートが異なる履歴は別のファイルに保存されるため、互いに上書きしない。
+    pub fn save_history(
+        &self,
+        id: HashID,
+        timestamp: Timestamp,
+        stats: Option<ScanStats>,
+    ) -> Result<History, Error> {
+        self.save_new_history(History::new(id, timestamp, stats))
+    }
+
+    /// `base`の履歴以降に変更されたファイルの一覧`entries`を、`timestamp`時点での棚卸し履歴として保存し、保存した履歴を返す。
+    ///
+    /// 棚卸し履歴のルートは`base`と同じで、変更されたファイルの内容は保存しない。
+    /// 一覧はオブジェクトとして保存され、[`load_inventory`](#method.load_inventory)で読み込める。
+    pub fn save_inventory(
+        &self,
+        base: &History,
+        entries: &[InventoryEntry],
+        timestamp: Timestamp,
+        stats: Option<ScanStats>,
+    ) -> Result<History, Error> {
+        let (inventory_id, _) = self.save_encoded(&entries, false)?;
+        let mut history = History::new(base.id.clone(), timestamp, stats);
+        history.inventory = Some(inventory_id);
+        history.version = INVENTORY_HISTORY_VERSION;
+        self.save_new_history(history)
+    }
+
+    /// 棚卸し履歴に記録された、変更されたファイルの一覧を読み込む。
+    ///
+    /// 通常の履歴では`None`を返す。
+    pub fn load_inventory(&self, history: &History) -> Result<Option<Vec<InventoryEntry>>, Error> {
+        match history.inventory {
+            Some(ref id) => Ok(Some(read_encoded(self.stream_object(id)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// 他のBankの履歴`history`を、保存したホストやメモなども含めてそのまま保存し、保存した履歴を返す。
+    ///
+    /// 履歴が参照するオブジェクトは先に保存しておく必要がある。
+    pub fn import_history(&self, history: &History) -> Result<History, Error> {
+        self.save_new_history(history.clone())
+    }
+
+    fn save_new_history(&self, last_scan: History) -> Result<History, Error> {
         let history_dir = self.history_dir();
         trace!("history_dir = {:?}", history_dir);
         ensure_dir(&history_dir)?;
-
-        let last_scan = History { id, timestamp };
         trace!("history entry = {:?}", last_scan);
 
-        let history_file = history_dir.join(&last_scan.file_name());
-        trace!("history_file = {:?}", history_file);
-        let f = fs::File::create(&history_file)?;
-        to_writer(f, &last_scan)?;
-        trace!("finish save history file");
+        let last_scan = match self.find_duplicate_history(&last_scan)? {
+            Some(existing) => {
+                info!(
+                    "history {} at {} is already saved",
+                    existing.id, existing.timestamp
+                );
+                existing
+            }
+            None => {
+                let history_file = history_dir.join(last_scan.file_name());
+                trace!("history_file = {:?}", history_file);
+                write_encoded_atomic(&history_file, &last_scan, self.entry_encoding())?;
+                trace!("finish save history file");
+                self.append_history_index(&last_scan.file_name(), &last_scan);
+                last_scan
+            }
+        };
 
         let last_scan_file = self.last_scan_file();
         trace!("last_scan_file = {:?}", last_scan_file);
-        let f = fs::File::create(&last_scan_file)?;
-        to_writer(f, &last_scan)?;
+        write_encoded_atomic(&last_scan_file, &last_scan, self.entry_encoding())?;
         trace!("finish save last_scan");
 
+        Ok(last_scan)
+    }
+
+    /// 既存の履歴のルートのエントリを`id`に置き換える。
+    ///
+    /// 置き換えた履歴が最新のものであれば、最新の履歴も更新する。
+    pub fn replace_history(&self, history: &History, id: HashID) -> Result<(), Error> {
+        let replaced = History {
+            id,
+            ..history.clone()
+        };
+
+        let history_file = self.history_dir().join(replaced.file_name());
+        trace!("replace history_file = {:?}", history_file);
+        write_encoded_atomic(&history_file, &replaced, self.entry_encoding())?;
+        self.append_history_index(&replaced.file_name(), &replaced);
+
+        // ファイル名がルートのIDを含むため、置き換え前のファイルを削除する
+        if let Some(old_file) = self.history_file(history)? {
+            if old_file != history_file {
+                trace!("remove old history_file = {:?}", old_file);
+                fs::remove_file(old_file)?;
+            }
+        }
+
+        if self.last_scan()?.as_ref() == Some(history) {
+            write_encoded_atomic(&self.last_scan_file(), &replaced, self.entry_encoding())?;
+            trace!("replace last_scan");
+        }
+
         Ok(())
     }
 
+    /// 既存の履歴のメモを`memo`に置き換え、置き換えた履歴を返す。
+    ///
+    /// `None`を指定するとメモを削除する。
+    /// 置き換えた履歴が最新のものであれば、最新の履歴も更新する。
+    pub fn annotate_history(
+        &self,
+        history: &History,
+        memo: Option<String>,
+    ) -> Result<History, Error> {
+        let annotated = History {
+            memo,
+            ..history.clone()
+        };
+
+        let history_file = self.history_file(history)?.ok_or_else(|| {
+            Error::InvalidInput(format!(
+                "history {} at {} is not saved",
+                history.id, history.timestamp
+            ))
+        })?;
+        trace!("annotate history_file = {:?}", history_file);
+        write_encoded_atomic(&history_file, &annotated, self.entry_encoding())?;
+
+        // 以前の形式のファイル名の履歴もあるため、索引には実際のファイル名で記録する
+        let name = history_file
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| history.file_name());
+        self.append_history_index(&name, &annotated);
+
+        if self.last_scan()?.as_ref() == Some(history) {
+            write_encoded_atomic(&self.last_scan_file(), &annotated, self.entry_encoding())?;
+            trace!("annotate last_scan");
+        }
+
+        Ok(annotated)
+    }
+
+    // 保存した履歴を、ファイル名`name`の履歴として索引に追加する。
+    //
+    // 失敗しても、索引に無い履歴は次に一覧を得る際に追加されるため、警告のみとする。
+    fn append_history_index(&self, name: &str, history: &History) {
+        let index_file = self.history_index_file();
+        if let Err(e) = history_index::append(&index_file, vec![(name, history)]) {
+            warn!("failed to update history index {:?}: {}", index_file, e);
+        }
+    }
+
+    // `history`と同じ時刻に同じルートを保存した履歴があれば返す。
+    fn find_duplicate_history(&self, history: &History) -> Result<Option<History>, Error> {
+        match self.history_file(history)? {
+            Some(path) => {
+                let existing = check_history_version(read_encoded(fs::File::open(path)?)?)?;
+                Ok(Some(existing))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // `history`と同じ時刻に同じルートを保存した履歴のファイルのパスを返す。
+    //
+    // タイムスタンプのみをファイル名とする以前の形式のファイルは、内容を読んで確認する。
+    fn history_file(&self, history: &History) -> Result<Option<PathBuf>, Error> {
+        let path = self.history_dir().join(history.file_name());
+        if path.exists() {
+            return Ok(Some(path));
+        }
+
+        let legacy = self.history_dir().join(history.legacy_file_name());
+        if legacy.exists() {
+            let existing: History = read_encoded(fs::File::open(&legacy)?)?;
+            if existing.timestamp == history.timestamp && existing.id == history.id {
+                return Ok(Some(legacy));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Bankのロックを取得する。
+    ///
+    /// 返された[`BankLock`](struct.BankLock.html)がドロップされるまで、他のプロセスはロックを取得できない。
+    /// ロックはBankのディレクトリ内のファイルの排他的な作成で実現されるため、ネットワークファイルシステム上でも複数のマシン間で有効である。
+    ///
+    /// # Failures
+    ///
+    /// 既に他のプロセスがロックを取得している場合、[`Error::Locked`](enum.Error.html#variant.Locked)を返す。
+    pub fn lock(&self) -> Result<BankLock, Error> {
+        let path = self.path.join(BANK_LOCK_FILE);
+        let owner = LockOwner {
+            host: hostname(),
+            pid: process::id(),
+            timestamp: Timestamp::now().map_err(|_| {
+                Error::InvalidInput("current time is older than UNIX epoch".to_owned())
+            })?,
+        };
+
+        let mut f = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let owner = fs::File::open(&path)
+                    .ok()
+                    .and_then(|f| from_reader::<_, LockOwner>(f).ok())
+                    .map(|o| o.to_string())
+                    .unwrap_or_else(|| "unknown process".to_owned());
+                return Err(Error::Locked {
+                    bank: self.name.clone(),
+                    owner,
+                    path,
+                });
+            }
+            Err(e) => return Err(e.into()),
+        };
+        trace!("bank {} locked by {}", self.name, owner);
+
+        let lock = BankLock { path };
+        to_writer(&mut f, &owner)?;
+        f.sync_all()?;
+
+        Ok(lock)
+    }
+
+    /// Bankを履歴や除外リストごと削除し、取得済みのロック`lock`を解放する。
+    ///
+    /// 履歴が参照していたオブジェクトは、他のBankと共有している可能性があるためリポジトリに残す。
+    pub fn remove(self, lock: BankLock) -> Result<(), Error> {
+        let config_file = self.path.join(BANK_CONFIG_FILE);
+        for dir_entry in self.path.read_dir()? {
+            let path = dir_entry?.path();
+            if path == lock.path || path == config_file {
+                continue;
+            }
+            trace!("remove {:?}", path);
+            if path.is_dir() {
+                fs::remove_dir_all(&path)?;
+            } else {
+                fs::remove_file(&path)?;
+            }
+        }
+
+        // 設定ファイルを最後に消し、途中で失敗してもBankとして認識できるようにする
+        fs::remove_file(&config_file)?;
+        drop(lock);
+        fs::remove_dir(&self.path)?;
+        Ok(())
+    }
+
+    /// Bankのロックを、他のプロセスが解放するまで最大`timeout`待って取得する。
+    ///
+    /// # Failures
+    ///
+    /// `timeout`が経過してもロックを取得できない場合、[`Error::Locked`](enum.Error.html#variant.Locked)を返す。
+    pub fn lock_wait(&self, timeout: Duration) -> Result<BankLock, Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.lock() {
+                Err(Error::Locked { .. }) if Instant::now() < deadline => {
+                    let wait = LOCK_RETRY_INTERVAL.min(deadline - Instant::now());
+                    trace!("bank {} is locked, retry after {:?}", self.name, wait);
+                    thread::sleep(wait);
+                }
+                result => return result,
+            }
+        }
+    }
+
     /// 指定された時点でのBankのルートディレクトリのエントリを読み込む。
     pub fn load_root(&'a self, history: &History) -> Result<DirEntry, Error> {
         self.load_entry(&history.id)
     }
 
+    /// 指定された時点のスナップショット内の全てのエントリを辿るイテレータを返す。
+    pub fn walk(&'a self, history: &History) -> Walk<'a> {
+        Walk::new(self, history.id.clone())
+    }
+
     /// 指定された`id`のディレクトリエントリを読み込む。
+    ///
+    /// 古いバージョンのエントリはそのまま読み込まれる。
+    /// 分割して保存された子のリストもあわせて読み込む。
+    ///
+    /// # Failures
+    ///
+    /// エントリのバージョンが[`MAX_ENTRY_VERSION`](../entry/constant.MAX_ENTRY_VERSION.html)より新しい場合、[`Error::UnsupportedVersion`](enum.Error.html#variant.UnsupportedVersion)を返す。
     pub fn load_entry<E: Entry>(&'a self, id: &HashID) -> Result<E, Error> {
         let f = self.open_object(id)?;
-        Ok(from_reader(f)?)
+        let mut entry: E = read_encoded(f)?;
+
+        if entry.version() > MAX_ENTRY_VERSION {
+            return Err(Error::UnsupportedVersion {
+                id: id.clone(),
+                version: entry.version(),
+            });
+        }
+
+        let chunk_ids = entry.chunk_ids();
+        if !chunk_ids.is_empty() {
+            let mut chunks = Vec::with_capacity(chunk_ids.len());
+            for chunk_id in &chunk_ids {
+                chunks.push(read_encoded(self.open_object(chunk_id)?)?);
+            }
+            entry.set_chunks(chunks);
+        }
+
+        Ok(entry)
+    }
+
+    /// 指定された`id`のディレクトリエントリを読み込む。
+    ///
+    /// 読み込んだエントリはキャッシュされ、同じ`id`で再び呼ばれた場合はオブジェクトを読み直さない。
+    /// キャッシュの容量は[`set_dir_cache_size`](#method.set_dir_cache_size)で変更できる。
+    pub fn load_dir(&'a self, id: &HashID) -> Result<Arc<DirEntry>, Error> {
+        if let Some(dir) = self.dir_cache_lock().get(id) {
+            trace!("dir cache hit: {}", id);
+            return Ok(dir.clone());
+        }
+
+        let dir = Arc::new(self.load_entry::<DirEntry>(id)?);
+        self.dir_cache_lock().insert(id.clone(), dir.clone());
+        Ok(dir)
+    }
+
+    /// `id`のディレクトリを、必要になるまで読み込まない[`LazyDir`](../walk/struct.LazyDir.html)として返す。
+    pub fn lazy_dir(&'a self, id: HashID) -> LazyDir<'a> {
+        LazyDir::new(self, id)
+    }
+
+    // 他のスレッドがパニックしてもキャッシュ自体は壊れないため、そのまま使う。
+    fn dir_cache_lock(&self) -> MutexGuard<'_, LruCache<HashID, Arc<DirEntry>>> {
+        self.dir_cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
     }
 
     /// 指定された`id`のファイルを開く。
@@ -280,6 +1195,24 @@ impl<'a> Bank<'a> {
         self.repo.open_object(id)
     }
 
+    /// 指定された`id`のファイルを検証しながら読み込むためのリーダーを得る。
+    ///
+    /// [`open_object`](#method.open_object)と異なり、事前にファイル全体を読み込まない。
+    /// 整合性チェックは最後まで読み込んだ時点で行われる。
+    pub fn stream_object(&self, id: &HashID) -> Result<VerifyingReader<fs::File>, Error> {
+        self.repo.stream_object(id)
+    }
+
+    /// 指定された`id`のオブジェクトのリポジトリ内でのサイズを得る。
+    pub fn object_size(&self, id: &HashID) -> Result<u64, Error> {
+        self.repo.object_size(id)
+    }
+
+    /// 新たに保存するエントリと履歴の形式を返す。
+    pub fn entry_encoding(&self) -> Encoding {
+        self.repo.entry_encoding()
+    }
+
     /// `Bank`の名前を得る。
     pub fn name(&self) -> &str {
         &self.name
@@ -287,50 +1220,140 @@ impl<'a> Bank<'a> {
 
     /// 最新の履歴を得る。
     ///
-    /// 存在しない場合はNoneを返す。
+    /// `last_scan.json`が無いか壊れている場合は、読み込める履歴のうち最新のものを返す。
+    /// 履歴が存在しない場合はNoneを返す。
     pub fn last_scan(&self) -> Result<Option<History>, Error> {
         let path = self.last_scan_file();
 
         if !path.exists() {
-            return Ok(None);
+            // 履歴を保存した後、最新の履歴を保存する前に中断した場合も含む
+            return self.newest_valid_history();
         }
 
         let f = fs::File::open(&path)?;
-        let history: History = from_reader(f)?;
+        match read_encoded(f) {
+            Ok(history) => Ok(Some(check_history_version(history)?)),
+            Err(Error::Parse(e)) => {
+                warn!(
+                    "failed to parse {:?}, use the newest history instead: {}",
+                    path, e
+                );
+                self.newest_valid_history()
+            }
+            Err(e) => Err(e),
+        }
+    }
 
-        Ok(Some(history))
+    // 読み込める履歴のうち最新のものを返す。壊れた履歴のファイルは無視する。
+    fn newest_valid_history(&self) -> Result<Option<History>, Error> {
+        let mut newest: Option<History> = None;
+        for name in self.history_file_names()? {
+            let path = self.history_dir().join(name);
+            let history: History = match fs::File::open(&path)
+                .map_err(Error::from)
+                .and_then(read_encoded)
+            {
+                Ok(history) => check_history_version(history)?,
+                Err(e) => {
+                    warn!("skip broken history {:?}: {}", path, e);
+                    continue;
+                }
+            };
+            if newest.as_ref().is_none_or(|n| history > *n) {
+                newest = Some(history);
+            }
+        }
+        Ok(newest)
     }
 
-    /// 履歴の一覧を得る。
-    ///
-    /// 古い順にソートされて返される。
-    pub fn histories(&self) -> Result<Vec<History>, Error> {
-        let mut res = Vec::<History>::new();
+    // 履歴のファイル名を返す。
+    fn history_file_names(&self) -> Result<Vec<String>, Error> {
+        let dir = self.history_dir();
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
 
-        for file in self.history_dir().read_dir()? {
-            let file = file?;
-            let name = file
+        let mut names = Vec::new();
+        for file in dir.read_dir()? {
+            let name = file?
                 .file_name()
                 .into_string()
                 .map_err(Error::InvalidFileName)?;
 
             if name.ends_with(HISTORY_SUFFIX) {
-                let f = fs::File::open(file.path())?;
-                let history: History = from_reader(f)?;
-                res.push(history);
+                names.push(name);
             }
         }
+        Ok(names)
+    }
 
+    /// 履歴の一覧を得る。
+    ///
+    /// 古い順にソートされて返される。
+    ///
+    /// 履歴の内容は索引から読み込み、索引に無い履歴のみファイルから読み込んで索引に追加する。
+    pub fn histories(&self) -> Result<Vec<History>, Error> {
+        let index_file = self.history_index_file();
+        let mut indexed = history_index::read(&index_file)?;
+
+        let mut entries = Vec::new();
+        let mut added = Vec::new();
+        for name in self.history_file_names()? {
+            let history = match indexed.remove(&name) {
+                Some(history) => history,
+                None => {
+                    let f = fs::File::open(self.history_dir().join(&name))?;
+                    added.push(entries.len());
+                    read_encoded(f)?
+                }
+            };
+            entries.push((name, check_history_version(history)?));
+        }
+
+        // 索引の更新に失敗しても、次回に再び更新を試みればよい
+        let index_result = if !indexed.is_empty() {
+            trace!("remove {} deleted histories from index", indexed.len());
+            history_index::rewrite(&index_file, entries.iter().map(|(n, h)| (n.as_str(), h)))
+        } else if !added.is_empty() {
+            trace!("add {} histories to index", added.len());
+            let new_entries = added
+                .iter()
+                .map(|&i| (entries[i].0.as_str(), &entries[i].1));
+            history_index::append(&index_file, new_entries)
+        } else {
+            Ok(())
+        };
+        if let Err(e) = index_result {
+            warn!("failed to update history index {:?}: {}", index_file, e);
+        }
+
+        let mut res = entries.into_iter().map(|(_, h)| h).collect::<Vec<_>>();
         res.sort();
         Ok(res)
     }
 
+    /// `since`以降かつ`until`より前にバックアップを開始した履歴の一覧を古い順に返す。
+    ///
+    /// `None`を指定した側は制限しない。
+    pub fn histories_between(
+        &self,
+        since: Option<Timestamp>,
+        until: Option<Timestamp>,
+    ) -> Result<Vec<History>, Error> {
+        Ok(self
+            .histories()?
+            .into_iter()
+            .filter(|h| since.is_none_or(|t| h.timestamp >= t))
+            .filter(|h| until.is_none_or(|t| h.timestamp < t))
+            .collect())
+    }
+
     /// 指定されたハッシュ値のプレフィックスを持つ履歴の一覧を返す。
     pub fn find_hash(&self, hash_prefix: &str) -> Result<Vec<History>, Error> {
         let mut res = Vec::new();
 
         for h in self.histories()? {
-            if h.id().as_str().starts_with(hash_prefix) {
+            if h.id().starts_with(hash_prefix) {
                 res.push(h);
             }
         }
@@ -343,23 +1366,275 @@ impl<'a> Bank<'a> {
         &self.config.target_path
     }
 
+    /// `Bank`の作成日時を取得する。
+    ///
+    /// 作成日時を記録していない古いバージョンで作成された場合はNoneを返す。
+    pub fn created_at(&self) -> Option<Timestamp> {
+        self.config.created_at
+    }
+
+    /// `Bank`の概要を取得する。
+    pub fn info(&self) -> Result<BankInfo, Error> {
+        let histories = self.histories()?;
+
+        Ok(BankInfo {
+            name: self.name.clone(),
+            target_path: self.target_path().to_owned(),
+            created_at: self.created_at(),
+            last_scan: self.last_scan()?,
+            history_count: histories.len(),
+        })
+    }
+
+    /// テキストファイルの内容の索引を読み込む。
+    ///
+    /// 索引が無い場合や読み込めない場合は、空の索引を返す。
+    pub fn load_content_index(&self) -> Result<ContentIndex, Error> {
+        let path = self.path.join(CONTENT_INDEX_FILE);
+        match fs::File::open(&path) {
+            Ok(f) => match ContentIndex::read_from(f) {
+                Ok(index) => Ok(index),
+                Err(e) => {
+                    warn!(
+                        "ignore content index because failed to read {:?}: {}",
+                        path, e
+                    );
+                    Ok(ContentIndex::new())
+                }
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(ContentIndex::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// テキストファイルの内容の索引を保存する。
+    pub fn save_content_index(&self, index: &ContentIndex) -> Result<(), Error> {
+        write_atomic(&self.path.join(CONTENT_INDEX_FILE), |f| index.write_to(f))?;
+        trace!("content index saved: {} objects", index.len());
+        Ok(())
+    }
+
+    /// `history`のスナップショット内のファイルのうち、索引に未登録のものの内容を`index`に登録する。
+    ///
+    /// 新たに登録したファイルの数を返す。
+    /// 読み込みに失敗したファイルは警告を出力して飛ばし、次回に再び登録を試みる。
+    pub fn update_content_index(
+        &self,
+        history: &History,
+        index: &mut ContentIndex,
+    ) -> Result<usize, Error> {
+        let mut added = 0;
+        for entry in self.walk(history) {
+            let (path, entry) = entry?;
+            let id = match entry {
+                FsHash::File(file) => file.id(),
+                _ => continue,
+            };
+            if index.contains(&id) {
+                continue;
+            }
+
+            let tokens = match self.stream_object(&id).and_then(|r| Ok(extract_tokens(r)?)) {
+                Ok(tokens) => tokens.unwrap_or_default(),
+                Err(e) => {
+                    warn!("failed to index content of {}: {}", path, e);
+                    continue;
+                }
+            };
+            index.insert(id, tokens);
+            added += 1;
+        }
+        trace!("{} objects added to content index", added);
+        Ok(added)
+    }
+
+    /// パスの索引を読み込む。
+    ///
+    /// 索引が無い場合や読み込めない場合は、空の索引を返す。
+    pub fn load_path_index(&self) -> Result<PathIndex, Error> {
+        let path = self.path.join(PATH_INDEX_FILE);
+        match fs::File::open(&path) {
+            Ok(f) => match PathIndex::read_from(f) {
+                Ok(index) => Ok(index),
+                Err(e) => {
+                    warn!("ignore path index because failed to read {:?}: {}", path, e);
+                    Ok(PathIndex::new())
+                }
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(PathIndex::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// パスの索引を保存する。
+    pub fn save_path_index(&self, index: &PathIndex) -> Result<(), Error> {
+        write_atomic(&self.path.join(PATH_INDEX_FILE), |f| index.write_to(f))?;
+        trace!("path index saved: {} files", index.len());
+        Ok(())
+    }
+
+    /// `index`を`history`のスナップショットの内容に更新し、追加・更新・削除したファイルの数を返す。
+    ///
+    /// 前回索引に反映したスナップショットと比べ、IDの変わったディレクトリだけを辿る。
+    /// 前回のスナップショットを読み込めない場合は、索引を作り直す。
+    pub fn update_path_index(
+        &self,
+        history: &History,
+        index: &mut PathIndex,
+    ) -> Result<usize, Error> {
+        if index.root() == Some(&history.id) {
+            return Ok(0);
+        }
+
+        let old = match index.root() {
+            Some(root) => match self.load_dir(root) {
+                Ok(dir) => Some(dir),
+                Err(e) => {
+                    warn!(
+                        "rebuild path index because failed to load previous snapshot: {}",
+                        e
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+        if old.is_none() {
+            index.clear();
+        }
+
+        let new = self.load_dir(&history.id)?;
+        let mut changed = 0;
+        self.update_path_index_dir("", old.as_deref(), &new, &history.id, index, &mut changed)?;
+        index.set_root(history.id.clone());
+        trace!("{} files changed in path index", changed);
+        Ok(changed)
+    }
+
+    // ディレクトリ`path`の古い内容`old`と新しい内容`new`を比べ、変わったファイルを`index`に反映する。
+    fn update_path_index_dir(
+        &self,
+        path: &str,
+        old: Option<&DirEntry>,
+        new: &DirEntry,
+        history: &HashID,
+        index: &mut PathIndex,
+        changed: &mut usize,
+    ) -> Result<(), Error> {
+        let mut old_children = old
+            .into_iter()
+            .flat_map(|d| d.children())
+            .map(|ch| (ch.attr().name(), ch))
+            .collect::<HashMap<_, _>>();
+
+        for ch in new.children() {
+            let name = ch.attr().name();
+            let ch_path = if path.is_empty() {
+                name.to_owned()
+            } else {
+                format!("{}/{}", path, name)
+            };
+            let old_ch = old_children.remove(name);
+
+            match (ch, old_ch) {
+                (FsHash::Dir(n), Some(FsHash::Dir(o))) if n.id() == o.id() => {}
+                (FsHash::File(n), Some(FsHash::File(o))) if n == o => {}
+                (FsHash::Dir(n), old_ch) => {
+                    let old_dir = match old_ch {
+                        Some(FsHash::Dir(o)) => Some(self.load_dir(&o.id())?),
+                        Some(_) => {
+                            *changed += index.remove(&ch_path);
+                            None
+                        }
+                        None => None,
+                    };
+                    let new_dir = self.load_dir(&n.id())?;
+                    self.update_path_index_dir(
+                        &ch_path,
+                        old_dir.as_deref(),
+                        &new_dir,
+                        history,
+                        index,
+                        changed,
+                    )?;
+                }
+                (FsHash::File(n), old_ch) => {
+                    if let Some(FsHash::Dir(_)) = old_ch {
+                        *changed += index.remove(&ch_path);
+                    }
+                    // 属性のみが変わった場合は、内容を最初に記録した履歴を引き継ぐ
+                    let first = match index.get(&ch_path) {
+                        Some(r) if r.id == n.id() => r.history.clone(),
+                        _ => history.clone(),
+                    };
+                    index.insert(
+                        ch_path,
+                        PathRecord {
+                            history: first,
+                            id: n.id(),
+                            size: self.object_size(&n.id())?,
+                            modified: n.attr().modified(),
+                        },
+                    );
+                    *changed += 1;
+                }
+                (FsHash::Symlink(_), Some(_)) => *changed += index.remove(&ch_path),
+                (FsHash::Symlink(_), None) => {}
+            }
+        }
+
+        for name in old_children.keys() {
+            let ch_path = if path.is_empty() {
+                (*name).to_owned()
+            } else {
+                format!("{}/{}", path, name)
+            };
+            *changed += index.remove(&ch_path);
+        }
+        Ok(())
+    }
+
     /// `Bank`で指定されている除外リストを読み込む。
     pub fn load_ignore_patterns(&self) -> Result<Patterns, Error> {
-        let path = self.ignore_file();
+        load_ignore_file(&self.ignore_file())
+    }
 
-        if path.exists() {
-            Ok(load_patterns(path)?)
+    /// `Bank`の除外リストの末尾に`lines`を追加する。
+    ///
+    /// 追加後の除外リストがパースできない場合は、書き込まずにエラーを返す。
+    pub fn append_ignore_lines<S: AsRef<str>>(&self, lines: &[S]) -> Result<(), Error> {
+        let path = self.ignore_file();
+        let mut content = if path.exists() {
+            fs::read_to_string(&path)?
         } else {
-            Ok(Patterns::default())
+            String::new()
+        };
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        for line in lines {
+            content.push_str(line.as_ref());
+            content.push('\n');
         }
+
+        pattern::parse(content.as_bytes())?;
+        write_atomic(&path, |f| f.write_all(content.as_bytes()))?;
+        Ok(())
+    }
+
+    /// リポジトリの全てのBankに共通の除外リストを読み込む。
+    pub fn load_repository_ignore_patterns(&self) -> Result<Patterns, Error> {
+        self.repo.load_ignore_patterns()
     }
 
     fn create(&self) -> Result<(), Error> {
         ensure_dir(&self.path)?;
         ensure_dir(&self.history_dir())?;
 
-        let f = fs::File::create(&self.path.join(BANK_CONFIG_FILE))?;
-        to_writer(f, &self.config)?;
+        write_atomic(&self.path.join(BANK_CONFIG_FILE), |f| {
+            to_writer(f, &self.config)?;
+            Ok(())
+        })?;
 
         Ok(())
     }
@@ -368,18 +1643,97 @@ impl<'a> Bank<'a> {
         self.path.join("history")
     }
 
+    fn history_index_file(&self) -> PathBuf {
+        self.path.join(HISTORY_INDEX_FILE)
+    }
+
     fn last_scan_file(&self) -> PathBuf {
         self.path.join("last_scan.json")
     }
 
     fn ignore_file(&self) -> PathBuf {
-        self.path.join("ignore")
+        self.path.join(IGNORE_FILE)
+    }
+}
+
+/// Bankのロックを表す。
+///
+/// ドロップ時にロックが解放される。
+#[derive(Debug)]
+pub struct BankLock {
+    path: PathBuf,
+}
+
+impl Drop for BankLock {
+    fn drop(&mut self) {
+        trace!("unlock {:?}", self.path);
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("failed to remove lock file {:?}: {}", self.path, e);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LockOwner {
+    host: String,
+    pid: u32,
+    timestamp: Timestamp,
+}
+
+impl std::fmt::Display for LockOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "process {} on {} since {}",
+            self.pid, self.host, self.timestamp
+        )
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct BankConfig {
     target_path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created_at: Option<Timestamp>,
+}
+
+/// [`Bank`](struct.Bank.html)の概要
+#[derive(Debug, Clone)]
+pub struct BankInfo {
+    name: String,
+    target_path: PathBuf,
+    created_at: Option<Timestamp>,
+    last_scan: Option<History>,
+    history_count: usize,
+}
+
+impl BankInfo {
+    /// Bankの名前を得る。
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// バックアップ対象ディレクトリのパスを得る。
+    pub fn target_path(&self) -> &Path {
+        &self.target_path
+    }
+
+    /// Bankの作成日時を得る。
+    ///
+    /// 作成日時が記録されていない場合はNoneを返す。
+    pub fn created_at(&self) -> Option<Timestamp> {
+        self.created_at
+    }
+
+    /// 最新の履歴を得る。
+    pub fn last_scan(&self) -> Option<&History> {
+        self.last_scan.as_ref()
+    }
+
+    /// 履歴の数を得る。
+    pub fn history_count(&self) -> usize {
+        self.history_count
+    }
 }
 
 /// バックアップ履歴を表す
@@ -387,9 +1741,42 @@ struct BankConfig {
 pub struct History {
     timestamp: Timestamp,
     id: HashID,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    utc_offset: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stats: Option<ScanStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    memo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inventory: Option<HashID>,
+    #[serde(default)]
+    version: u32,
 }
 
 impl History {
+    // このマシンで`timestamp`時点に保存する履歴を作る。
+    fn new(id: HashID, timestamp: Timestamp, stats: Option<ScanStats>) -> History {
+        let utc_offset = Local
+            .timestamp(timestamp.unix_epoch() as i64, 0)
+            .offset()
+            .local_minus_utc();
+        History {
+            id,
+            timestamp,
+            host: Some(hostname()),
+            user: Some(username()),
+            utc_offset: Some(utc_offset),
+            stats,
+            memo: None,
+            inventory: None,
+            version: HISTORY_VERSION,
+        }
+    }
+
     /// 履歴のルートのディレクトリエントリのIDを得る。
     pub fn id(&self) -> &HashID {
         &self.id
@@ -400,11 +1787,209 @@ impl History {
         self.timestamp
     }
 
+    /// バックアップを実行したマシンのホスト名を得る。
+    ///
+    /// 記録されていない古い形式の履歴では`None`を返す。
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    /// バックアップを実行したユーザーの名前を得る。
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// バックアップを実行したマシンの、バックアップ開始時刻におけるUTCからの時差を秒単位で得る。
+    pub fn utc_offset(&self) -> Option<i32> {
+        self.utc_offset
+    }
+
+    /// バックアップ時のスキャンの統計を得る。
+    ///
+    /// 記録されていない古い形式の履歴では`None`を返す。
+    pub fn stats(&self) -> Option<&ScanStats> {
+        self.stats.as_ref()
+    }
+
+    /// 履歴に付けられたメモを得る。
+    pub fn memo(&self) -> Option<&str> {
+        self.memo.as_deref()
+    }
+
+    /// 変更されたファイルを一覧のみ記録した棚卸し履歴かどうかを返す。
+    ///
+    /// 棚卸し履歴のルートは、元にした通常の履歴のルートと同じである。
+    pub fn is_inventory(&self) -> bool {
+        self.inventory.is_some()
+    }
+
+    /// 棚卸し履歴であれば、変更されたファイルの一覧のオブジェクトのIDを返す。
+    pub fn inventory_id(&self) -> Option<&HashID> {
+        self.inventory.as_ref()
+    }
+
+    // 同じ時刻に保存された別の履歴と衝突しないよう、ファイル名にはルートのIDの先頭を含める。
     fn file_name(&self) -> String {
+        let id = hex::encode(self.id.as_bytes());
+        format!(
+            "{}-{}{}",
+            self.timestamp.unix_epoch(),
+            &id[..HISTORY_ID_DIGITS],
+            HISTORY_SUFFIX
+        )
+    }
+
+    // タイムスタンプのみの、以前の形式のファイル名
+    fn legacy_file_name(&self) -> String {
         format!("{}{}", self.timestamp.unix_epoch(), HISTORY_SUFFIX)
     }
 }
 
+/// 棚卸し履歴に記録された、元にした履歴から変更されたファイル
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InventoryEntry {
+    /// 変更の種類
+    pub kind: ChangeKind,
+    /// 対象ディレクトリからの相対パス
+    pub path: String,
+    /// ファイルのバイト数
+    pub size: u64,
+}
+
+/// 1回のバックアップのスキャンの統計
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ScanStats {
+    /// スキャンにかかった時間(ミリ秒)
+    #[serde(default)]
+    pub duration_ms: u64,
+    /// 記録したファイルの数
+    #[serde(default)]
+    pub files: u64,
+    /// 記録したディレクトリの数
+    #[serde(default)]
+    pub dirs: u64,
+    /// 内容を読み込んだファイルの合計バイト数
+    #[serde(default)]
+    pub bytes_read: u64,
+    /// リポジトリに新たに保存したオブジェクトの合計バイト数
+    #[serde(default)]
+    pub new_bytes: u64,
+    /// エラー処理の方針に従って飛ばしたエラーの数
+    #[serde(default)]
+    pub errors: u64,
+}
+
+// 空き容量と空きinode数の閾値
+#[derive(Debug, Clone, Copy)]
+struct FreeSpaceLimits {
+    // 空き容量がこれを下回るとバックアップを中止する。
+    min_bytes: u64,
+    // 空き容量がこれを下回ると警告する。
+    warn_bytes: u64,
+    // 空きinode数がこれを下回るとバックアップを中止する。
+    min_inodes: u64,
+    // 空きinode数がこれを下回ると警告する。
+    warn_inodes: u64,
+}
+
+impl FreeSpaceLimits {
+    // `space`を閾値と比べ、警告すべき不足の説明を返す。
+    //
+    // バックアップを中止すべき不足がある場合は、その説明をエラーとして返す。
+    fn classify(&self, space: &DiskSpace) -> Result<Vec<String>, String> {
+        let mut warnings = Vec::new();
+
+        let bytes = space.available_bytes;
+        if bytes < self.min_bytes {
+            return Err(format!("only {} free", Size::from(bytes)));
+        }
+        if bytes < self.warn_bytes {
+            warnings.push(format!("space: {} free", Size::from(bytes)));
+        }
+
+        if let Some(inodes) = space.available_inodes {
+            if inodes < self.min_inodes {
+                return Err(format!("only {} inodes free", inodes));
+            }
+            if inodes < self.warn_inodes {
+                warnings.push(format!("inodes: {} inodes free", inodes));
+            }
+        }
+
+        Ok(warnings)
+    }
+}
+
+// 索引に`id`が含まれ、そのファイル`path`が存在するかを返す。
+//
+// 索引を保存した後にオブジェクトが削除された場合に保存済みと誤認しないよう、ファイルが無ければ索引から取り除く。
+fn confirm_indexed(index: &mut ObjectIndex, id: &HashID, path: &Path) -> bool {
+    if !index.contains(id) {
+        return false;
+    }
+    if fs::metadata(path).is_ok() {
+        return true;
+    }
+    warn!(
+        "object {} is in index but missing; remove it from index",
+        id
+    );
+    index.remove(id);
+    false
+}
+
+// 同じディレクトリの一時ファイルに書き込んでからリネームすることで、`path`の内容を不可分に置き換える。
+fn write_atomic<F>(path: &Path, write: F) -> Result<(), io::Error>
+where
+    F: FnOnce(&mut fs::File) -> Result<(), io::Error>,
+{
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut temp = NamedTempFile::new_in(dir)?;
+
+    write(temp.as_file_mut())?;
+    temp.as_file_mut().flush()?;
+    temp.as_file().sync_all()?;
+
+    temp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+fn write_encoded_atomic<T: Serialize>(
+    path: &Path,
+    value: &T,
+    encoding: Encoding,
+) -> Result<(), io::Error> {
+    let encoded = encoding.encode(value)?;
+    write_atomic(path, |f| f.write_all(&encoded))
+}
+
+// 履歴のバージョンがこのプログラムで扱えるかを確認する。
+fn check_history_version(history: History) -> Result<History, Error> {
+    if history.version > MAX_HISTORY_VERSION {
+        return Err(Error::UnsupportedHistoryVersion {
+            timestamp: history.timestamp,
+            version: history.version,
+        });
+    }
+    Ok(history)
+}
+
+// JSONまたはCBORで保存されたエントリや履歴を読み込む。
+fn read_encoded<T: DeserializeOwned, R: Read>(mut r: R) -> Result<T, Error> {
+    let mut bytes = Vec::new();
+    r.read_to_end(&mut bytes)?;
+    Ok(encoding::decode(&bytes)?)
+}
+
+// 除外リストのファイルを読み込む。ファイルが存在しない場合は空のリストを返す。
+fn load_ignore_file(path: &Path) -> Result<Patterns, Error> {
+    if path.exists() {
+        Ok(load_patterns(path)?)
+    } else {
+        Ok(Patterns::default())
+    }
+}
+
 fn ensure_dir(path: &Path) -> Result<(), io::Error> {
     if !path.exists() {
         fs::create_dir_all(path)?;
@@ -412,11 +1997,70 @@ fn ensure_dir(path: &Path) -> Result<(), io::Error> {
     Ok(())
 }
 
+/// 読み込みながらハッシュ値を検証するリーダー
+///
+/// 終端まで読み込んだ時点でハッシュ値が期待される値と一致しない場合、
+/// [`io::ErrorKind::InvalidData`](https://doc.rust-lang.org/std/io/enum.ErrorKind.html)のエラーを返す。
+/// このエラーは[`Error`](enum.Error.html)に変換すると[`Error::BrokenObject`](enum.Error.html#variant.BrokenObject)になる。
+pub struct VerifyingReader<R> {
+    inner: R,
+    hasher: Option<Hasher>,
+    expected: HashID,
+}
+
+impl<R: Read> VerifyingReader<R> {
+    /// `inner`の内容のハッシュ値が`expected`であることを検証するリーダーを生成する。
+    pub fn new(inner: R, expected: HashID) -> VerifyingReader<R> {
+        VerifyingReader {
+            inner,
+            hasher: Some(Hasher::new()),
+            expected,
+        }
+    }
+
+    /// 検証対象のIDを返す。
+    pub fn expected(&self) -> &HashID {
+        &self.expected
+    }
+
+    /// 終端まで読み込み、検証が完了していれば`true`を返す。
+    pub fn is_verified(&self) -> bool {
+        self.hasher.is_none()
+    }
+}
+
+impl<R: Read> Read for VerifyingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let hasher = match self.hasher.as_mut() {
+            Some(hasher) => hasher,
+            None => return Ok(0),
+        };
+
+        let read_size = self.inner.read(buf)?;
+        if read_size > 0 || buf.is_empty() {
+            hasher.update(&buf[..read_size]);
+            return Ok(read_size);
+        }
+
+        let actual = self.hasher.take().unwrap().finish();
+        if actual != self.expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                Error::BrokenObject {
+                    to_be: self.expected.clone(),
+                    actual,
+                },
+            ));
+        }
+
+        Ok(0)
+    }
+}
+
 /// リポジトリ操作に関わるエラー
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
     /// エントリのハッシュ値が一致しない
-    #[fail(display = "object not exists: {}", _0)]
     BrokenObject {
         /// 期待されるID値
         to_be: HashID,
@@ -424,33 +2068,128 @@ pub enum Error {
         actual: HashID,
     },
 
+    /// Bankの設定を読み込めず、Bankを開けない
+    BrokenBank {
+        /// Bankの名前
+        bank: String,
+        /// 開けなかった原因
+        cause: Box<Error>,
+    },
+
     /// 指定されたエントリが存在しない
-    #[fail(display = "object not exists: {}", _0)]
     EntryNotFound(HashID),
 
     /// 除外リストの読み込みに失敗した
-    #[fail(display = "failed load ignore patterns: {}", _0)]
-    IgnorePattern(#[fail(cause)] pattern::ParseError),
+    IgnorePattern(pattern::ParseError),
 
     /// リポジトリが不完全な状態である
-    #[fail(display = "repository isn't complete: {} is {}", _0, _1)]
     IncompleteRepo(&'static str, &'static str),
 
     /// パスがUnicodeで表現できない
-    #[fail(display = "invalid file name {:?}", _0)]
     InvalidFileName(OsString),
 
+    /// リポジトリのファイルシステムの空きが不足している
+    InsufficientSpace(String),
+
+    /// Bankが他のプロセスによってロックされている
+    Locked {
+        /// Bankの名前
+        bank: String,
+        /// ロックを保持しているプロセスの説明
+        owner: String,
+        /// ロックファイルのパス
+        path: PathBuf,
+    },
+
     /// 入力が不正である。
-    #[fail(display = "invalid input: {}", _0)]
     InvalidInput(String),
 
+    /// リポジトリを作成しようとしたディレクトリに、リポジトリ以外のファイルがある
+    NotEmptyDirectory(PathBuf),
+
     /// 入出力エラーが発生した
-    #[fail(display = "failed scan with IO error: {}", _0)]
-    IO(#[fail(cause)] io::Error),
+    IO(io::Error),
 
     /// JSONのパースに失敗した
-    #[fail(display = "failed parse entry: {}", _0)]
-    Parse(#[fail(cause)] serde_json::Error),
+    Parse(serde_json::Error),
+
+    /// エントリのバージョンがこのプログラムより新しい
+    UnsupportedVersion {
+        /// エントリのID
+        id: HashID,
+        /// エントリのバージョン
+        version: u32,
+    },
+
+    /// 履歴のバージョンがこのプログラムより新しい
+    UnsupportedHistoryVersion {
+        /// 履歴のタイムスタンプ
+        timestamp: Timestamp,
+        /// 履歴のバージョン
+        version: u32,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::BrokenObject { to_be, .. } => write!(f, "object not exists: {}", to_be),
+            Error::BrokenBank { bank, cause } => {
+                write!(f, "failed to open bank '{}': {}", bank, cause)
+            }
+            Error::EntryNotFound(id) => write!(f, "object not exists: {}", id),
+            Error::IgnorePattern(e) => write!(f, "failed load ignore patterns: {}", e),
+            Error::IncompleteRepo(a, b) => write!(f, "repository isn't complete: {} is {}", a, b),
+            Error::InvalidFileName(name) => write!(f, "invalid file name {:?}", name),
+            Error::InsufficientSpace(msg) => write!(f, "insufficient space in repository: {}", msg),
+            Error::Locked { bank, owner, path } => write!(
+                f,
+                "bank '{}' is locked by {} (remove {:?} if the process no longer exists)",
+                bank, owner, path
+            ),
+            Error::InvalidInput(msg) => write!(f, "invalid input: {}", msg),
+            Error::NotEmptyDirectory(path) => {
+                write!(
+                    f,
+                    "directory {:?} is not empty and is not a repository",
+                    path
+                )
+            }
+            Error::IO(e) => write!(f, "failed scan with IO error: {}", e),
+            Error::Parse(e) => write!(f, "failed parse entry: {}", e),
+            Error::UnsupportedVersion { id, version } => write!(
+                f,
+                "entry {} has unsupported version {} (update sbak to read it)",
+                id, version
+            ),
+            Error::UnsupportedHistoryVersion { timestamp, version } => write!(
+                f,
+                "history at {} has unsupported version {} (update sbak to read it)",
+                timestamp, version
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::BrokenBank { cause, .. } => Some(cause.as_ref()),
+            Error::IgnorePattern(e) => Some(e),
+            Error::IO(e) => Some(e),
+            Error::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    fn broken_bank(bank: &str, cause: Error) -> Error {
+        Error::BrokenBank {
+            bank: bank.to_owned(),
+            cause: Box::new(cause),
+        }
+    }
 }
 
 impl From<pattern::ParseError> for Error {
@@ -461,6 +2200,12 @@ impl From<pattern::ParseError> for Error {
 
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Error {
+        // VerifyingReaderが返したエラーは元のエラーに戻す
+        if e.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = e.into_inner().unwrap();
+            return *inner.downcast::<Error>().unwrap();
+        }
+
         Error::IO(e)
     }
 }
@@ -0,0 +1,193 @@
+//! テキストファイルの内容の索引。
+//!
+//! バックアップしたテキストファイルに含まれる単語をオブジェクトごとに記録し、
+//! ファイルを復元せずに、ある単語を含むファイルがどのスナップショットにあるかを調べるために使う。
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use crate::core::hash::{Algorithm, HashID, HASH_SIZE};
+
+#[cfg(test)]
+mod test;
+
+/// 索引に登録するファイルの最大サイズ
+pub const MAX_INDEXED_SIZE: u64 = 1024 * 1024;
+
+/// 単語として扱う最小の文字数
+pub const MIN_TOKEN_CHARS: usize = 2;
+
+/// 単語として扱う最大のバイト数
+pub const MAX_TOKEN_BYTES: usize = 64;
+
+// ファイルの先頭に置く識別子。形式を変える場合は末尾の番号を上げる。
+const MAGIC: &[u8; 8] = b"SBAKCTX1";
+
+/// オブジェクトのIDと、そのオブジェクトに含まれる単語の対応
+///
+/// ファイルには識別子に続けて、アルゴリズムの識別子1バイト、IDのバイト列、単語数4バイト(リトルエンディアン)、
+/// 単語ごとのバイト数1バイトと単語のバイト列を並べた形式で保存される。
+/// テキストでないオブジェクトも、再び読み込まないように単語数0で記録する。
+#[derive(Default)]
+pub struct ContentIndex {
+    tokens: HashMap<HashID, Vec<String>>,
+}
+
+impl ContentIndex {
+    /// 空の索引を生成する。
+    pub fn new() -> ContentIndex {
+        ContentIndex::default()
+    }
+
+    /// `r`から保存済みの索引を読み込む。
+    ///
+    /// 形式が異なる場合は`InvalidData`のエラーを返す。
+    pub fn read_from<R: Read>(r: R) -> io::Result<ContentIndex> {
+        let mut r = BufReader::new(r);
+
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unknown content index format",
+            ));
+        }
+
+        let mut index = ContentIndex::new();
+        let mut code = [0u8; 1];
+        loop {
+            if r.read(&mut code)? == 0 {
+                break;
+            }
+            let algorithm = Algorithm::from_code(code[0]).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown hash algorithm code: {}", code[0]),
+                )
+            })?;
+            let mut id = [0u8; HASH_SIZE];
+            r.read_exact(&mut id)?;
+            let mut count = [0u8; 4];
+            r.read_exact(&mut count)?;
+
+            let count = u32::from_le_bytes(count) as usize;
+            let mut tokens = Vec::with_capacity(count);
+            for _ in 0..count {
+                let mut len = [0u8; 1];
+                r.read_exact(&mut len)?;
+                let mut token = vec![0u8; len[0] as usize];
+                r.read_exact(&mut token)?;
+                let token = String::from_utf8(token)
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "token isn't UTF-8"))?;
+                tokens.push(token);
+            }
+            index
+                .tokens
+                .insert(HashID::from_bytes(algorithm, id), tokens);
+        }
+
+        Ok(index)
+    }
+
+    /// 索引を`w`に書き出す。
+    pub fn write_to<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut w = BufWriter::new(w);
+        w.write_all(MAGIC)?;
+        for (id, tokens) in &self.tokens {
+            w.write_all(&[id.algorithm().code()])?;
+            w.write_all(id.as_bytes())?;
+            w.write_all(&(tokens.len() as u32).to_le_bytes())?;
+            for token in tokens {
+                w.write_all(&[token.len() as u8])?;
+                w.write_all(token.as_bytes())?;
+            }
+        }
+        w.flush()
+    }
+
+    /// `id`のオブジェクトが索引に登録済みかどうかを返す。
+    pub fn contains(&self, id: &HashID) -> bool {
+        self.tokens.contains_key(id)
+    }
+
+    /// `id`のオブジェクトに含まれる単語として`tokens`を登録する。
+    ///
+    /// テキストでないオブジェクトは空の`tokens`で登録する。
+    pub fn insert(&mut self, id: HashID, tokens: Vec<String>) {
+        self.tokens.insert(id, tokens);
+    }
+
+    /// `words`の全ての単語を含むオブジェクトのIDを返す。
+    ///
+    /// 単語は[`tokenize`](fn.tokenize.html)と同じ規則で正規化してから比較する。
+    pub fn search(&self, words: &[&str]) -> HashSet<HashID> {
+        let words = words.iter().flat_map(|w| tokenize(w)).collect::<Vec<_>>();
+        if words.is_empty() {
+            return HashSet::new();
+        }
+
+        self.tokens
+            .iter()
+            .filter(|(_, tokens)| words.iter().all(|w| tokens.binary_search(w).is_ok()))
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    /// `f`が`true`を返すオブジェクトの記録のみを残す。
+    pub fn retain<F: FnMut(&HashID) -> bool>(&mut self, mut f: F) {
+        self.tokens.retain(|id, _| f(id));
+    }
+
+    /// 登録されているオブジェクトの個数を返す。
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// 索引が空かどうかを返す。
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+}
+
+impl fmt::Debug for ContentIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ContentIndex")
+            .field("len", &self.tokens.len())
+            .finish()
+    }
+}
+
+/// `r`の内容がテキストであれば、含まれる単語を整列して重複を除いたものを返す。
+///
+/// NUL文字を含むか、UTF-8として読めない場合はテキストでないとみなして`None`を返す。
+/// [`MAX_INDEXED_SIZE`](constant.MAX_INDEXED_SIZE.html)より大きい場合も`None`を返す。
+pub fn extract_tokens<R: Read>(r: R) -> io::Result<Option<Vec<String>>> {
+    let mut bytes = Vec::new();
+    r.take(MAX_INDEXED_SIZE + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > MAX_INDEXED_SIZE || bytes.contains(&0) {
+        return Ok(None);
+    }
+    let text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => return Ok(None),
+    };
+
+    let mut tokens = tokenize(&text).collect::<Vec<_>>();
+    tokens.sort();
+    tokens.dedup();
+    Ok(Some(tokens))
+}
+
+/// `text`を単語に分割する。
+///
+/// 英数字と`_`の連続を1つの単語とし、小文字にそろえる。
+/// [`MIN_TOKEN_CHARS`](constant.MIN_TOKEN_CHARS.html)文字未満のものと、
+/// [`MAX_TOKEN_BYTES`](constant.MAX_TOKEN_BYTES.html)バイトを超えるものは単語として扱わない。
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|w| w.chars().nth(MIN_TOKEN_CHARS - 1).is_some())
+        .map(|w| w.to_lowercase())
+        .filter(|w| w.len() <= MAX_TOKEN_BYTES)
+}
@@ -0,0 +1,68 @@
+use super::*;
+
+use crate::core::hash::hash_reader;
+
+fn id_of(s: &str) -> HashID {
+    hash_reader(s.as_bytes()).unwrap().0
+}
+
+#[test]
+fn test_extract_tokens() {
+    let tokens = extract_tokens("Hello, world! hello_world a ID42 日本語".as_bytes())
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        tokens,
+        vec!["hello", "hello_world", "id42", "world", "日本語"]
+    );
+}
+
+#[test]
+fn test_extract_tokens_binary() {
+    assert_eq!(extract_tokens(&b"text\0binary"[..]).unwrap(), None);
+    assert_eq!(extract_tokens(&b"\xff\xfe"[..]).unwrap(), None);
+
+    let large = vec![b'a'; MAX_INDEXED_SIZE as usize + 1];
+    assert_eq!(extract_tokens(large.as_slice()).unwrap(), None);
+}
+
+#[test]
+fn test_tokenize_long_word() {
+    let long = "x".repeat(MAX_TOKEN_BYTES + 1);
+    let text = format!("{} ok", long);
+    assert_eq!(tokenize(&text).collect::<Vec<_>>(), vec!["ok"]);
+}
+
+#[test]
+fn test_write_read_and_search() {
+    let mut index = ContentIndex::new();
+    index.insert(
+        id_of("a"),
+        extract_tokens("alpha beta".as_bytes()).unwrap().unwrap(),
+    );
+    index.insert(
+        id_of("b"),
+        extract_tokens("beta gamma".as_bytes()).unwrap().unwrap(),
+    );
+    index.insert(id_of("c"), Vec::new());
+
+    let mut buf = Vec::new();
+    index.write_to(&mut buf).unwrap();
+    let read = ContentIndex::read_from(buf.as_slice()).unwrap();
+    assert_eq!(read.len(), 3);
+    assert!(read.contains(&id_of("c")));
+    assert!(!read.contains(&id_of("d")));
+
+    let found = read.search(&["BETA"]);
+    assert_eq!(found.len(), 2);
+    let found = read.search(&["beta", "gamma"]);
+    assert_eq!(found.into_iter().collect::<Vec<_>>(), vec![id_of("b")]);
+    assert!(read.search(&["delta"]).is_empty());
+    assert!(read.search(&["-"]).is_empty());
+}
+
+#[test]
+fn test_read_invalid() {
+    let err = ContentIndex::read_from(&b"NOTINDEX"[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
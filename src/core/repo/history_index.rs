@@ -0,0 +1,101 @@
+//! Bankの履歴の索引。
+//!
+//! 履歴の一覧を得るたびに全ての履歴のファイルを読み込まずに済むよう、履歴の内容をファイル名とともに1行ずつ記録する。
+//! 索引は追記のみで更新し、削除された履歴が見つかった場合に限り書き直す。
+//! 途中で切れた行などの解釈できない行は無視するため、索引に無い履歴はファイルから読み込んで補う。
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::{write_atomic, History};
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    file: String,
+    #[serde(flatten)]
+    history: History,
+}
+
+/// 索引ファイル`path`を読み込み、履歴のファイル名と履歴の組を返す。
+///
+/// 同じファイル名の行が複数ある場合は後の行を使う。索引ファイルが無い場合は空を返す。
+pub fn read(path: &Path) -> io::Result<HashMap<String, History>> {
+    let f = match fs::File::open(path) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut histories = HashMap::new();
+    for line in BufReader::new(f).split(b'\n') {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        match serde_json::from_slice::<Record>(&line) {
+            Ok(record) => {
+                histories.insert(record.file, record.history);
+            }
+            Err(e) => warn!("ignore broken line in history index {:?}: {}", path, e),
+        }
+    }
+    Ok(histories)
+}
+
+/// 索引ファイル`path`に履歴を追記する。
+pub fn append<'a, I>(path: &Path, histories: I) -> io::Result<()>
+where
+    I: IntoIterator<Item = (&'a str, &'a History)>,
+{
+    let mut f = fs::OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)?;
+
+    let mut buf = Vec::new();
+    // 前回の書き込みが途中で切れていた場合は、その行と混ざらないよう改行を補う
+    let len = f.metadata()?.len();
+    if len > 0 {
+        let mut last = [0u8; 1];
+        f.seek(SeekFrom::Start(len - 1))?;
+        f.read_exact(&mut last)?;
+        if last[0] != b'\n' {
+            buf.push(b'\n');
+        }
+    }
+    write_records(&mut buf, histories)?;
+
+    f.write_all(&buf)?;
+    f.sync_all()
+}
+
+/// 索引ファイル`path`を`histories`だけを含むように書き直す。
+pub fn rewrite<'a, I>(path: &Path, histories: I) -> io::Result<()>
+where
+    I: IntoIterator<Item = (&'a str, &'a History)>,
+{
+    let mut buf = Vec::new();
+    write_records(&mut buf, histories)?;
+    write_atomic(path, |f| f.write_all(&buf))
+}
+
+fn write_records<'a, I>(buf: &mut Vec<u8>, histories: I) -> io::Result<()>
+where
+    I: IntoIterator<Item = (&'a str, &'a History)>,
+{
+    for (file, history) in histories {
+        let record = Record {
+            file: file.to_owned(),
+            history: history.clone(),
+        };
+        serde_json::to_writer(&mut *buf, &record)?;
+        buf.push(b'\n');
+    }
+    Ok(())
+}
@@ -0,0 +1,187 @@
+//! リポジトリに存在するオブジェクトの索引。
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::core::hash::{Algorithm, HashID, DEFAULT_ALGORITHM, HASH_SIZE};
+
+#[cfg(test)]
+mod test;
+
+// 索引ファイルの先頭に置く識別子。形式を変える場合は末尾の番号を上げる。
+const MAGIC: &[u8; 8] = b"SBAKIDX2";
+
+// アルゴリズムの識別子を持たない旧形式の識別子
+const MAGIC_V1: &[u8; 8] = b"SBAKIDX1";
+
+/// 存在するオブジェクトのIDの集合
+///
+/// バックアップ中の重複判定で、オブジェクトごとにファイルシステムを問い合わせずに済ませるために使う。
+/// ファイルには識別子に続けて、アルゴリズムの識別子1バイトとIDのバイト列の組を並べた形式で保存される。
+#[derive(Default)]
+pub struct ObjectIndex {
+    ids: HashSet<HashID>,
+}
+
+impl ObjectIndex {
+    /// 空の索引を生成する。
+    pub fn new() -> ObjectIndex {
+        ObjectIndex::default()
+    }
+
+    /// `objects_dir`以下を走査して索引を生成する。
+    ///
+    /// オブジェクトのパスとして解釈できないファイルは無視する。
+    pub fn scan(objects_dir: &Path) -> io::Result<ObjectIndex> {
+        let mut index = ObjectIndex::new();
+        index.scan_shards(objects_dir, DEFAULT_ALGORITHM)?;
+
+        // 既定以外のアルゴリズムのオブジェクトはタグの名前のディレクトリ以下にある
+        for d in fs::read_dir(objects_dir)? {
+            let d = d?;
+            let algorithm = match Algorithm::from_tag(&d.file_name().to_string_lossy()) {
+                Some(a) if a != DEFAULT_ALGORITHM => a,
+                _ => continue,
+            };
+            if d.file_type()?.is_dir() {
+                index.scan_shards(&d.path(), algorithm)?;
+            }
+        }
+
+        Ok(index)
+    }
+
+    // 4-4-56文字に階層化されたオブジェクトを`algorithm`のIDとして追加する。
+    fn scan_shards(&mut self, dir: &Path, algorithm: Algorithm) -> io::Result<()> {
+        for d0 in fs::read_dir(dir)? {
+            let d0 = d0?;
+            if !d0.file_type()?.is_dir() {
+                continue;
+            }
+            for d1 in fs::read_dir(d0.path())? {
+                let d1 = d1?;
+                if !d1.file_type()?.is_dir() {
+                    continue;
+                }
+                for obj in fs::read_dir(d1.path())? {
+                    let obj = obj?;
+                    let id = format!(
+                        "{}:{}{}{}",
+                        algorithm.tag(),
+                        d0.file_name().to_string_lossy(),
+                        d1.file_name().to_string_lossy(),
+                        obj.file_name().to_string_lossy()
+                    );
+                    if let Some(id) = HashID::parse(&id) {
+                        self.insert(id);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `r`から保存済みの索引を読み込む。
+    ///
+    /// アルゴリズムの識別子を持たない旧形式の索引は、全て[`DEFAULT_ALGORITHM`]のIDとして読み込む。
+    /// 形式が異なる場合は`InvalidData`のエラーを返す。
+    pub fn read_from<R: Read>(r: R) -> io::Result<ObjectIndex> {
+        let mut r = BufReader::new(r);
+
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        let tagged = if &magic == MAGIC {
+            true
+        } else if &magic == MAGIC_V1 {
+            false
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unknown object index format",
+            ));
+        };
+        let record_size = if tagged { HASH_SIZE + 1 } else { HASH_SIZE };
+
+        let mut bytes = Vec::new();
+        r.read_to_end(&mut bytes)?;
+        if bytes.len() % record_size != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated object index",
+            ));
+        }
+
+        let mut index = ObjectIndex::new();
+        index.ids.reserve(bytes.len() / record_size);
+        for chunk in bytes.chunks(record_size) {
+            let (algorithm, chunk) = if tagged {
+                let algorithm = Algorithm::from_code(chunk[0]).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unknown hash algorithm code: {}", chunk[0]),
+                    )
+                })?;
+                (algorithm, &chunk[1..])
+            } else {
+                (DEFAULT_ALGORITHM, chunk)
+            };
+            let mut id = [0u8; HASH_SIZE];
+            id.copy_from_slice(chunk);
+            index.insert(HashID::from_bytes(algorithm, id));
+        }
+
+        Ok(index)
+    }
+
+    /// 索引を`w`に書き出す。
+    pub fn write_to<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut w = BufWriter::new(w);
+        w.write_all(MAGIC)?;
+        for id in &self.ids {
+            w.write_all(&[id.algorithm().code()])?;
+            w.write_all(id.as_bytes())?;
+        }
+        w.flush()
+    }
+
+    /// `id`のオブジェクトが索引に含まれるかどうかを返す。
+    pub fn contains(&self, id: &HashID) -> bool {
+        self.ids.contains(id)
+    }
+
+    /// `id`のオブジェクトを索引に追加する。
+    pub fn insert(&mut self, id: HashID) {
+        self.ids.insert(id);
+    }
+
+    /// `id`のオブジェクトを索引から取り除き、含まれていたかどうかを返す。
+    pub fn remove(&mut self, id: &HashID) -> bool {
+        self.ids.remove(id)
+    }
+
+    /// 索引に含まれるIDを返す。順序は不定。
+    pub fn into_ids(self) -> Vec<HashID> {
+        self.ids.into_iter().collect()
+    }
+
+    /// 索引に含まれるオブジェクトの個数を返す。
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// 索引が空かどうかを返す。
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+impl fmt::Debug for ObjectIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ObjectIndex")
+            .field("len", &self.ids.len())
+            .finish()
+    }
+}
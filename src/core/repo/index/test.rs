@@ -0,0 +1,78 @@
+use super::*;
+
+use tempfile::tempdir;
+
+use crate::core::hash::hash_reader;
+use crate::core::repo::Repository;
+
+fn id_of(s: &str) -> HashID {
+    hash_reader(s.as_bytes()).unwrap().0
+}
+
+#[test]
+fn test_write_and_read() {
+    let mut index = ObjectIndex::new();
+    index.insert(id_of("a"));
+    index.insert(id_of("b"));
+
+    let mut buf = Vec::new();
+    index.write_to(&mut buf).unwrap();
+    assert_eq!(buf.len(), MAGIC.len() + 2 * (HASH_SIZE + 1));
+
+    let read = ObjectIndex::read_from(buf.as_slice()).unwrap();
+    assert_eq!(read.len(), 2);
+    assert!(read.contains(&id_of("a")));
+    assert!(read.contains(&id_of("b")));
+    assert!(!read.contains(&id_of("c")));
+}
+
+#[test]
+fn test_read_invalid() {
+    let err = ObjectIndex::read_from(&b"NOTINDEX"[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let mut buf = MAGIC.to_vec();
+    buf.extend_from_slice(&[0u8; HASH_SIZE]);
+    let err = ObjectIndex::read_from(buf.as_slice()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    // 未知のアルゴリズム
+    let mut buf = MAGIC.to_vec();
+    buf.push(0xff);
+    buf.extend_from_slice(&[0u8; HASH_SIZE]);
+    let err = ObjectIndex::read_from(buf.as_slice()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_read_v1() {
+    let mut buf = MAGIC_V1.to_vec();
+    buf.extend_from_slice(id_of("a").as_bytes());
+    buf.extend_from_slice(id_of("b").as_bytes());
+
+    let read = ObjectIndex::read_from(buf.as_slice()).unwrap();
+    assert_eq!(read.len(), 2);
+    assert!(read.contains(&id_of("a")));
+    assert!(read.contains(&id_of("b")));
+}
+
+#[test]
+fn test_scan_objects() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo_path = temp.path().join("repo");
+    let repo = Repository::create(&repo_path).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    for s in &["a", "b"] {
+        let (id, f) = hash_reader(s.as_bytes()).unwrap();
+        bank.save_object(&id, f).unwrap();
+    }
+
+    let index = ObjectIndex::scan(&repo_path.join("objects")).unwrap();
+    assert_eq!(index.len(), 2);
+    assert!(index.contains(&id_of("a")));
+    assert!(index.contains(&id_of("b")));
+}
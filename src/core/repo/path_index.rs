@@ -0,0 +1,120 @@
+//! Bankのパスの索引。
+//!
+//! 最新のスナップショットに含まれるファイルのパスごとに、その内容を最初に記録した履歴、オブジェクトのID、
+//! バイト数、更新日時を記録する。スナップショットのディレクトリを辿らずにファイルを探すために使う。
+//! 索引はJSONで保存されるため、他のツールからも読み込める。
+
+use std::collections::BTreeMap;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::hash::HashID;
+use crate::core::timestamp::Timestamp;
+
+#[cfg(test)]
+mod test;
+
+/// 索引に記録するファイルの情報
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathRecord {
+    /// この内容のファイルを最初に記録した履歴のルートのID
+    pub history: HashID,
+    /// 内容のオブジェクトのID
+    pub id: HashID,
+    /// バイト数
+    pub size: u64,
+    /// 更新日時
+    pub modified: Timestamp,
+}
+
+/// パスとファイルの情報の対応
+///
+/// パスはバックアップ対象ディレクトリからの`/`区切りの相対パスで、ファイルのみを含む。
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathIndex {
+    root: Option<HashID>,
+    paths: BTreeMap<String, PathRecord>,
+}
+
+impl PathIndex {
+    /// 空の索引を生成する。
+    pub fn new() -> PathIndex {
+        PathIndex::default()
+    }
+
+    /// `r`から保存済みの索引を読み込む。
+    ///
+    /// 形式が異なる場合は`InvalidData`のエラーを返す。
+    pub fn read_from<R: Read>(r: R) -> io::Result<PathIndex> {
+        Ok(serde_json::from_reader(BufReader::new(r))?)
+    }
+
+    /// 索引を`w`に書き出す。
+    pub fn write_to<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut w = BufWriter::new(w);
+        serde_json::to_writer(&mut w, self)?;
+        w.flush()
+    }
+
+    /// 索引に反映したスナップショットのルートのIDを返す。
+    ///
+    /// まだ反映していない場合は`None`を返す。
+    pub fn root(&self) -> Option<&HashID> {
+        self.root.as_ref()
+    }
+
+    /// 索引に反映したスナップショットのルートのIDを設定する。
+    pub fn set_root(&mut self, root: HashID) {
+        self.root = Some(root);
+    }
+
+    /// `path`のファイルの情報を返す。
+    pub fn get(&self, path: &str) -> Option<&PathRecord> {
+        self.paths.get(path)
+    }
+
+    /// パスとファイルの情報をパスの順に返す。
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &PathRecord)> {
+        self.paths.iter().map(|(p, r)| (p.as_str(), r))
+    }
+
+    /// `path`のファイルの情報を`record`にする。
+    pub fn insert(&mut self, path: String, record: PathRecord) {
+        self.paths.insert(path, record);
+    }
+
+    /// `path`のファイルと、`path`以下のファイルを全て取り除き、取り除いた数を返す。
+    pub fn remove(&mut self, path: &str) -> usize {
+        let mut removed = usize::from(self.paths.remove(path).is_some());
+
+        let prefix = format!("{}/", path);
+        let under = self
+            .paths
+            .range(prefix.clone()..)
+            .take_while(|(p, _)| p.starts_with(&prefix))
+            .map(|(p, _)| p.clone())
+            .collect::<Vec<_>>();
+        for p in under {
+            self.paths.remove(&p);
+            removed += 1;
+        }
+        removed
+    }
+
+    /// 全てのファイルを取り除き、反映したスナップショットも無かったことにする。
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.paths.clear();
+    }
+
+    /// 索引に含まれるファイルの数を返す。
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// 索引が空かどうかを返す。
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+}
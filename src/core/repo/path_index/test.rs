@@ -0,0 +1,56 @@
+use super::*;
+
+use crate::core::hash::hash_reader;
+
+fn record(content: &str, size: u64) -> PathRecord {
+    let id = hash_reader(content.as_bytes()).unwrap().0;
+    PathRecord {
+        history: id.clone(),
+        id,
+        size,
+        modified: Timestamp::from(1_000_000_000),
+    }
+}
+
+#[test]
+fn test_write_and_read() {
+    let mut index = PathIndex::new();
+    index.set_root(hash_reader(&b"root"[..]).unwrap().0);
+    index.insert("a.txt".to_owned(), record("a", 1));
+    index.insert("docs/b.txt".to_owned(), record("bb", 2));
+
+    let mut buf = Vec::new();
+    index.write_to(&mut buf).unwrap();
+    let read = PathIndex::read_from(buf.as_slice()).unwrap();
+    assert_eq!(read, index);
+    assert_eq!(read.get("docs/b.txt"), Some(&record("bb", 2)));
+    assert_eq!(read.get("docs"), None);
+}
+
+#[test]
+fn test_read_invalid() {
+    let err = PathIndex::read_from(&b"SBAKIDX2"[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_remove() {
+    let mut index = PathIndex::new();
+    for path in &["a", "a/b", "a/b/c", "a/bc", "a.txt", "a0"] {
+        index.insert((*path).to_owned(), record(path, 0));
+    }
+
+    // 名前が`a/b`で始まるだけのものは取り除かない
+    assert_eq!(index.remove("a/b"), 2);
+    assert_eq!(
+        index.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+        vec!["a", "a.txt", "a/bc", "a0"]
+    );
+    assert_eq!(index.remove("a"), 2);
+    assert_eq!(index.remove("missing"), 0);
+    assert_eq!(index.len(), 2);
+
+    index.clear();
+    assert!(index.is_empty());
+    assert_eq!(index.root(), None);
+}
@@ -0,0 +1,961 @@
+use super::*;
+
+use tempfile::tempdir;
+
+use std::convert::TryFrom;
+
+use crate::core::entry::{
+    Attributes, DirEntryBuilder, FileEntry, FsHash, CHUNKED_DIR_VERSION, CHUNK_THRESHOLD,
+    ENTRY_VERSION,
+};
+use crate::core::ignore::pattern::Match;
+use crate::core::ignore::EntryPath;
+use crate::core::scan::{ChangeKind, Scanner};
+
+#[test]
+fn test_bank_lock_is_exclusive() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let lock = bank.lock().unwrap();
+    match bank.lock().unwrap_err() {
+        Error::Locked { bank, .. } => assert_eq!(bank, "bank"),
+        e => panic!("{:?}", e),
+    }
+
+    drop(lock);
+    bank.lock().unwrap();
+}
+
+#[test]
+fn test_save_history_replaces_last_scan() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let (id1, _) = hash::hash_reader("first".as_bytes()).unwrap();
+    let (id2, _) = hash::hash_reader("second".as_bytes()).unwrap();
+    bank.save_history(id1, Timestamp::from(1_000_000_000), None)
+        .unwrap();
+    bank.save_history(id2.clone(), Timestamp::from(1_000_000_100), None)
+        .unwrap();
+
+    assert_eq!(bank.last_scan().unwrap().unwrap().id(), &id2);
+    assert_eq!(bank.histories().unwrap().len(), 2);
+
+    // 一時ファイルが残っていないこと
+    for dir in &[bank.path.clone(), bank.history_dir()] {
+        for f in dir.read_dir().unwrap() {
+            let name = f.unwrap().file_name().into_string().unwrap();
+            assert!(!name.starts_with(".tmp"), "temp file {} remains", name);
+        }
+    }
+}
+
+#[test]
+fn test_save_history_same_second() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let (id1, _) = hash::hash_reader("first".as_bytes()).unwrap();
+    let (id2, _) = hash::hash_reader("second".as_bytes()).unwrap();
+    let at = Timestamp::from(1_000_000_000);
+    let stats = ScanStats {
+        files: 1,
+        ..ScanStats::default()
+    };
+
+    // 同じ秒でもルートが異なれば両方残る
+    let first = bank.save_history(id1.clone(), at, Some(stats)).unwrap();
+    bank.save_history(id2, at, None).unwrap();
+    assert_eq!(bank.histories().unwrap().len(), 2);
+
+    // 同じルートの重複した実行は既存の履歴を返す
+    let duplicate = bank.save_history(id1.clone(), at, None).unwrap();
+    assert_eq!(duplicate, first);
+    assert_eq!(bank.histories().unwrap().len(), 2);
+    assert_eq!(bank.last_scan().unwrap(), Some(first.clone()));
+
+    // 置き換えると元のファイルは消える
+    let (id3, _) = hash::hash_reader("third".as_bytes()).unwrap();
+    bank.replace_history(&first, id3.clone()).unwrap();
+    let ids = bank
+        .histories()
+        .unwrap()
+        .into_iter()
+        .map(|h| h.id().clone())
+        .collect::<Vec<_>>();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.contains(&id3));
+    assert!(!ids.contains(&id1));
+}
+
+#[test]
+fn test_save_history_legacy_name() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let (id, _) = hash::hash_reader("legacy".as_bytes()).unwrap();
+    let legacy = bank.history_dir().join("1000000000.history.json");
+    fs::create_dir_all(bank.history_dir()).unwrap();
+    fs::write(
+        &legacy,
+        format!(r#"{{"timestamp":1000000000,"id":"{}"}}"#, id),
+    )
+    .unwrap();
+
+    // 以前の形式のファイル名の履歴も重複として扱う
+    let history = bank
+        .save_history(id.clone(), Timestamp::from(1_000_000_000), None)
+        .unwrap();
+    assert_eq!(history.host(), None);
+    assert_eq!(bank.histories().unwrap().len(), 1);
+
+    let (new_id, _) = hash::hash_reader("replaced".as_bytes()).unwrap();
+    bank.replace_history(&history, new_id.clone()).unwrap();
+    assert!(!legacy.exists());
+    let histories = bank.histories().unwrap();
+    assert_eq!(histories.len(), 1);
+    assert_eq!(histories[0].id(), &new_id);
+}
+
+#[test]
+fn test_last_scan_recovery() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    assert_eq!(bank.last_scan().unwrap(), None);
+
+    let (id1, _) = hash::hash_reader("first".as_bytes()).unwrap();
+    let (id2, _) = hash::hash_reader("second".as_bytes()).unwrap();
+    let (id3, _) = hash::hash_reader("third".as_bytes()).unwrap();
+    bank.save_history(id1, Timestamp::from(1_000_000_000), None)
+        .unwrap();
+    let second = bank
+        .save_history(id2, Timestamp::from(1_000_000_100), None)
+        .unwrap();
+    let third = bank
+        .save_history(id3, Timestamp::from(1_000_000_200), None)
+        .unwrap();
+
+    // 途中で切れた最新の履歴の代わりに、読み込める最新の履歴を使う
+    fs::write(bank.last_scan_file(), r#"{"timestamp":10"#).unwrap();
+    assert_eq!(bank.last_scan().unwrap(), Some(third.clone()));
+
+    fs::remove_file(bank.last_scan_file()).unwrap();
+    assert_eq!(bank.last_scan().unwrap(), Some(third.clone()));
+
+    // 壊れた履歴のファイルは飛ばす
+    fs::write(bank.history_dir().join(third.file_name()), "").unwrap();
+    assert_eq!(bank.last_scan().unwrap(), Some(second));
+}
+
+#[test]
+fn test_stream_object() {
+    let temp = tempdir().unwrap();
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", temp.path()).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let (id, file) = hash::hash_reader("content".as_bytes()).unwrap();
+    bank.save_object(&id, file).unwrap();
+
+    let mut reader = bank.stream_object(&id).unwrap();
+    let mut buf = String::new();
+    reader.read_to_string(&mut buf).unwrap();
+    assert_eq!(buf, "content");
+    assert!(reader.is_verified());
+}
+
+#[test]
+fn test_verifying_reader_broken() {
+    let (id, _) = hash::hash_reader("expected".as_bytes()).unwrap();
+    let (actual_id, _) = hash::hash_reader("actual".as_bytes()).unwrap();
+
+    let mut reader = VerifyingReader::new("actual".as_bytes(), id.clone());
+    let mut buf = Vec::new();
+    let e = reader.read_to_end(&mut buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+    match Error::from(e) {
+        Error::BrokenObject { to_be, actual } => {
+            assert_eq!(to_be, id);
+            assert_eq!(actual, actual_id);
+        }
+        e => panic!("{:?}", e),
+    }
+}
+
+#[test]
+fn test_bank_info() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("b", &target).unwrap();
+    repo.create_bank("a", &target).unwrap();
+
+    let bank = repo.open_bank("b").unwrap();
+    let (id, _) = hash::hash_reader("root".as_bytes()).unwrap();
+    bank.save_history(id.clone(), Timestamp::from(1_000_000_000), None)
+        .unwrap();
+
+    let (infos, broken) = repo.bank_infos().unwrap();
+    assert!(broken.is_empty());
+    let names = infos.iter().map(|i| i.name()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["a", "b"]);
+
+    assert!(infos[0].created_at().is_some());
+    assert_eq!(infos[0].history_count(), 0);
+    assert!(infos[0].last_scan().is_none());
+    assert_eq!(infos[1].history_count(), 1);
+    assert_eq!(infos[1].last_scan().unwrap().id(), &id);
+    assert_eq!(infos[1].target_path(), target.canonicalize().unwrap());
+}
+
+#[test]
+fn test_create_non_empty() {
+    let temp = tempdir().unwrap();
+    let path = temp.path().join("data");
+    fs::create_dir_all(&path).unwrap();
+    fs::write(path.join("photo.jpg"), "jpeg").unwrap();
+
+    match Repository::create(&path) {
+        Err(Error::NotEmptyDirectory(p)) => assert_eq!(p, path),
+        r => panic!("unexpected result: {:?}", r.map(|_| ())),
+    }
+    assert!(!path.join("objects").exists());
+
+    Repository::create_force(&path).unwrap();
+    assert!(Repository::is_repository(&path));
+    // 既存のリポジトリに対しては何もしない
+    Repository::create(&path).unwrap();
+
+    let empty = temp.path().join("empty");
+    fs::create_dir_all(&empty).unwrap();
+    Repository::create(&empty).unwrap();
+}
+
+#[test]
+fn test_discover() {
+    let temp = tempdir().unwrap();
+    let repo_path = temp.path().join("repo");
+    Repository::create(&repo_path).unwrap();
+    let repo_path = repo_path.canonicalize().unwrap();
+
+    let repo = Repository::discover(&repo_path).unwrap().unwrap();
+    assert_eq!(repo.path(), repo_path);
+    let repo = Repository::discover(repo_path.join("banks"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(repo.path(), repo_path);
+
+    // `objects`だけのディレクトリはリポジトリとみなさない
+    let other = temp.path().join("other");
+    fs::create_dir_all(other.join("objects/sub")).unwrap();
+    assert!(Repository::discover(other.join("objects/sub"))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_broken_bank() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("a", &target).unwrap();
+    repo.create_bank("b", &target).unwrap();
+    repo.create_bank("c", &target).unwrap();
+    fs::write(temp.path().join("repo/banks/b/config.json"), "{").unwrap();
+
+    let results = repo.open_all_banks().unwrap().collect::<Vec<_>>();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap().name(), "a");
+    match &results[1] {
+        Err(Error::BrokenBank { bank, cause }) => {
+            assert_eq!(bank, "b");
+            assert!(matches!(**cause, Error::Parse(_)));
+        }
+        r => panic!("unexpected result: {:?}", r.as_ref().map(|b| b.name())),
+    }
+    assert_eq!(results[2].as_ref().unwrap().name(), "c");
+
+    let (infos, broken) = repo.bank_infos().unwrap();
+    let names = infos.iter().map(|i| i.name()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["a", "c"]);
+    assert_eq!(broken.len(), 1);
+}
+
+#[test]
+fn test_history_records_environment() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let (id, _) = hash::hash_reader("data".as_bytes()).unwrap();
+    let saved = bank
+        .save_history(id, Timestamp::from(1_000_000_000), None)
+        .unwrap();
+    assert_eq!(saved.host(), Some(hostname().as_str()));
+    assert_eq!(saved.user(), Some(username().as_str()));
+    assert!(saved.utc_offset().is_some());
+    assert_eq!(bank.histories().unwrap(), vec![saved]);
+
+    // 実行環境が記録されていない古い形式の履歴も読み込める
+    let old: History = serde_json::from_str(&format!(
+        r#"{{"timestamp":1,"id":"{}"}}"#,
+        bank.last_scan().unwrap().unwrap().id()
+    ))
+    .unwrap();
+    assert_eq!(old.host(), None);
+    assert_eq!(old.utc_offset(), None);
+}
+
+#[test]
+fn test_bank_config_without_created_at() {
+    let config: BankConfig = serde_json::from_str(r#"{"target_path":"/tmp"}"#).unwrap();
+    assert_eq!(config.created_at, None);
+
+    let json = serde_json::to_string(&config).unwrap();
+    assert_eq!(json, r#"{"target_path":"/tmp"}"#);
+}
+
+#[test]
+fn test_load_dir_cached() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("a")).unwrap();
+    fs::write(target.join("a/x.txt"), "x").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let root_id = Scanner::new(&bank).scan().unwrap().id();
+
+    let first = bank.load_dir(&root_id).unwrap();
+    fs::remove_file(repo.object_path(&root_id)).unwrap();
+
+    // キャッシュ済みのエントリはオブジェクトを読み直さない
+    let second = bank.load_dir(&root_id).unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+
+    bank.set_dir_cache_size(0);
+    assert!(bank.load_dir(&root_id).is_err());
+}
+
+#[test]
+fn test_object_index() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo_path = temp.path().join("repo");
+    let repo = Repository::create(&repo_path).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let (a, f) = hash::hash_reader("a".as_bytes()).unwrap();
+    bank.save_object(&a, f).unwrap();
+
+    // 保存済みの索引がなければ走査して作る
+    assert_eq!(repo.load_object_index().unwrap(), 1);
+    let (b, f) = hash::hash_reader("b".as_bytes()).unwrap();
+    bank.save_object(&b, f).unwrap();
+    repo.save_object_index().unwrap();
+    assert!(repo_path.join(OBJECT_INDEX_FILE).exists());
+
+    // 索引に含まれていてもファイルが無ければ、索引から取り除いて保存し直す
+    fs::remove_file(repo.object_path(&a)).unwrap();
+    assert!(!bank.has_object(&a));
+    let (_, f) = hash::hash_reader("a".as_bytes()).unwrap();
+    assert_eq!(bank.save_object(&a, f).unwrap(), 1);
+    assert!(repo.object_path(&a).exists());
+    assert!(bank.has_object(&a));
+
+    fs::remove_file(repo.object_path(&a)).unwrap();
+    let reopened = Repository::open(&repo_path).unwrap();
+    assert_eq!(reopened.load_object_index().unwrap(), 2);
+
+    reopened.invalidate_object_index().unwrap();
+    assert!(!repo_path.join(OBJECT_INDEX_FILE).exists());
+    assert_eq!(reopened.load_object_index().unwrap(), 1);
+}
+
+#[test]
+fn test_object_index_missing_object() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("a.txt"), "a").unwrap();
+
+    let repo_path = temp.path().join("repo");
+    let repo = Repository::create(&repo_path).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    repo.load_object_index().unwrap();
+    let root = Scanner::new(&bank).scan().unwrap();
+    repo.save_object_index().unwrap();
+    let id = bank
+        .load_dir(&root.id())
+        .unwrap()
+        .children()
+        .next()
+        .unwrap()
+        .id();
+
+    // 索引を保存した後でオブジェクトが失われた場合
+    fs::remove_file(repo.object_path(&id)).unwrap();
+
+    // 同じ内容のファイルを含むバックアップで保存し直される
+    fs::write(target.join("b.txt"), "a").unwrap();
+    let reopened = Repository::open(&repo_path).unwrap();
+    reopened.load_object_index().unwrap();
+    let bank = reopened.open_bank("bank").unwrap();
+    Scanner::new(&bank).scan().unwrap();
+    reopened.save_object_index().unwrap();
+    assert!(reopened.object_path(&id).exists());
+    assert_eq!(reopened.verify_object(&id).unwrap(), 1);
+}
+
+#[test]
+fn test_entry_encoding() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("d")).unwrap();
+    fs::write(target.join("d/a.txt"), "a").unwrap();
+
+    let repo_path = temp.path().join("repo");
+    let repo = Repository::create(&repo_path).unwrap();
+    assert_eq!(repo.entry_encoding(), Encoding::Json);
+    repo.create_bank("bank", &target).unwrap();
+
+    let json_root = {
+        let bank = repo.open_bank("bank").unwrap();
+        let id = Scanner::new(&bank).scan().unwrap().id();
+        bank.save_history(id.clone(), Timestamp::from(1_000_000_000), None)
+            .unwrap();
+        id
+    };
+
+    let mut repo = Repository::open(&repo_path).unwrap();
+    repo.set_entry_encoding(Encoding::Cbor).unwrap();
+    let repo = Repository::open(&repo_path).unwrap();
+    assert_eq!(repo.entry_encoding(), Encoding::Cbor);
+
+    let bank = repo.open_bank("bank").unwrap();
+    let cbor_root = Scanner::new(&bank).scan().unwrap().id();
+    bank.save_history(cbor_root.clone(), Timestamp::from(1_000_000_100), None)
+        .unwrap();
+    assert_ne!(json_root, cbor_root);
+
+    let bytes = fs::read(repo.object_path(&cbor_root)).unwrap();
+    assert_eq!(Encoding::detect(&bytes), Encoding::Cbor);
+    let bytes = fs::read(bank.last_scan_file()).unwrap();
+    assert_eq!(Encoding::detect(&bytes), Encoding::Cbor);
+
+    // 異なる形式のエントリと履歴が混在していても読み込める
+    let histories = bank.histories().unwrap();
+    assert_eq!(histories.len(), 2);
+    for history in &histories {
+        let names = bank
+            .walk(history)
+            .map(|e| e.unwrap().0.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["d", "d/a.txt"]);
+    }
+}
+
+#[test]
+fn test_history_version() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let (id, _) = hash::hash_reader("root".as_bytes()).unwrap();
+    let saved = bank
+        .save_history(id.clone(), Timestamp::from(1_000_000_000), None)
+        .unwrap();
+    assert_eq!(saved.version, HISTORY_VERSION);
+
+    // バージョンの無い古い履歴と、未知のフィールドを持つ履歴は読み込める
+    let old = format!(r#"{{"timestamp":1000000100,"id":"{}"}}"#, id);
+    fs::write(bank.history_dir().join("1000000100.history.json"), old).unwrap();
+    let unknown = format!(
+        r#"{{"timestamp":1000000200,"id":"{}","version":{},"future":1}}"#,
+        id, HISTORY_VERSION
+    );
+    fs::write(bank.history_dir().join("1000000200.history.json"), unknown).unwrap();
+    assert_eq!(bank.histories().unwrap().len(), 3);
+
+    // 新しいバージョンの履歴は読み込まない
+    let newer = format!(
+        r#"{{"timestamp":1000000300,"id":"{}","version":{}}}"#,
+        id,
+        MAX_HISTORY_VERSION + 1
+    );
+    fs::write(bank.last_scan_file(), &newer).unwrap();
+    match bank.last_scan().unwrap_err() {
+        Error::UnsupportedHistoryVersion { timestamp, version } => {
+            assert_eq!(timestamp, Timestamp::from(1_000_000_300));
+            assert_eq!(version, MAX_HISTORY_VERSION + 1);
+        }
+        e => panic!("{:?}", e),
+    }
+}
+
+#[test]
+fn test_verify_after_write() {
+    let temp = tempdir().unwrap();
+    let repo_path = temp.path().join("repo");
+    let mut repo = Repository::create(&repo_path).unwrap();
+    assert!(!repo.verify_after_write_enabled());
+    repo.set_verify_after_write(true).unwrap();
+    let repo = Repository::open(&repo_path).unwrap();
+    assert!(repo.verify_after_write_enabled());
+
+    let (id, f) = hash::hash_reader("content".as_bytes()).unwrap();
+    assert_eq!(repo.save_object(&id, f).unwrap(), 7);
+    assert!(repo.object_path(&id).exists());
+
+    // 読み直した内容がIDと一致しない場合はエラーになり、オブジェクトは残らない
+    let (other, _) = hash::hash_reader("other".as_bytes()).unwrap();
+    let (_, f) = hash::hash_reader("content".as_bytes()).unwrap();
+    let err = repo.save_object(&other, f).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(!repo.object_path(&other).exists());
+}
+
+#[test]
+fn test_entry_unknown_fields() {
+    let json = r#"{"version":1,"attr":{"name":"d","readonly":false,"modified":1,"xattrs":{}},"children":[],"future":true}"#;
+    let dir: DirEntry = serde_json::from_str(json).unwrap();
+    assert_eq!(dir.version(), ENTRY_VERSION);
+    assert_eq!(dir.attr().name(), "d");
+}
+
+// `len`個のファイルを持つディレクトリエントリを生成する。`changed`番目のファイルだけ内容を変える。
+fn large_dir(len: usize, changed: Option<usize>) -> DirEntry {
+    let mut builder = DirEntryBuilder::new(Attributes::new(
+        "large".to_owned(),
+        false,
+        Timestamp::from(1),
+    ));
+    for i in 0..len {
+        let name = format!("file{:05}.txt", i);
+        let content = if Some(i) == changed {
+            format!("{} changed", name)
+        } else {
+            name.clone()
+        };
+        let (id, _) = hash::hash_reader(content.as_bytes()).unwrap();
+        let mut file = FileEntry::new(Attributes::new(name, false, Timestamp::from(1)));
+        file.set_id(id);
+        builder.append(FsHash::try_from(file).unwrap());
+    }
+    builder.build()
+}
+
+#[test]
+fn test_save_dir_entry_chunked() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    bank.set_dir_cache_size(0);
+
+    // 少ない子は分割しない
+    let small = large_dir(CHUNK_THRESHOLD - 1, None);
+    let (small_id, _) = bank.save_dir_entry(&small, false).unwrap();
+    let loaded = bank.load_dir(&small_id).unwrap();
+    assert_eq!(loaded.version(), ENTRY_VERSION);
+    assert!(loaded.chunk_ids().is_empty());
+    let objects = repo.object_ids().unwrap().len();
+
+    let dir = large_dir(10_000, None);
+    let (id, _) = bank.save_dir_entry(&dir, false).unwrap();
+    let loaded = bank.load_dir(&id).unwrap();
+    assert_eq!(loaded.version(), CHUNKED_DIR_VERSION);
+    let chunks = loaded.chunk_ids();
+    assert!(chunks.len() > 1);
+    assert_eq!(
+        loaded.children().collect::<Vec<_>>(),
+        dir.children().collect::<Vec<_>>()
+    );
+    assert_eq!(repo.object_ids().unwrap().len(), objects + chunks.len() + 1);
+
+    // 1つの子の変更では、その子を含む部分とディレクトリ自体だけが新たに保存される
+    let objects = repo.object_ids().unwrap().len();
+    let changed = large_dir(10_000, Some(5_000));
+    let (changed_id, _) = bank.save_dir_entry(&changed, false).unwrap();
+    assert_ne!(changed_id, id);
+    assert_eq!(repo.object_ids().unwrap().len(), objects + 2);
+    let changed_chunks = bank.load_dir(&changed_id).unwrap().chunk_ids();
+    assert_eq!(
+        changed_chunks
+            .iter()
+            .filter(|c| !chunks.contains(c))
+            .count(),
+        1
+    );
+
+    // 分割されたオブジェクトが無ければ読み込めない
+    fs::remove_file(repo.object_path(&chunks[0])).unwrap();
+    assert!(bank.load_dir(&id).is_err());
+}
+
+#[test]
+fn test_bank_lock_wait() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let lock = bank.lock().unwrap();
+    match bank.lock_wait(Duration::from_millis(100)).unwrap_err() {
+        Error::Locked { bank, .. } => assert_eq!(bank, "bank"),
+        e => panic!("{:?}", e),
+    }
+
+    // 待っている間に解放されればロックを取得できる
+    let release = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        drop(lock);
+    });
+    bank.lock_wait(Duration::from_secs(10)).unwrap();
+    release.join().unwrap();
+}
+
+#[test]
+fn test_history_index() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let mut saved = Vec::new();
+    for (i, content) in ["first", "second", "third"].iter().enumerate() {
+        let (id, _) = hash::hash_reader(content.as_bytes()).unwrap();
+        let at = Timestamp::from(1_000_000_000 + 100 * i as u64);
+        saved.push(bank.save_history(id, at, None).unwrap());
+    }
+    let index = fs::read_to_string(bank.history_index_file()).unwrap();
+    assert_eq!(index.lines().count(), 3);
+
+    // 索引に記録済みの履歴はファイルを読まない
+    fs::write(bank.history_dir().join(saved[0].file_name()), "broken").unwrap();
+    assert_eq!(bank.histories().unwrap(), saved);
+
+    // 削除された履歴は索引からも除かれる
+    fs::remove_file(bank.history_dir().join(saved[0].file_name())).unwrap();
+    assert_eq!(bank.histories().unwrap(), saved[1..].to_vec());
+    let index = fs::read_to_string(bank.history_index_file()).unwrap();
+    assert_eq!(index.lines().count(), 2);
+
+    // 途中で切れた行があっても、索引に無い履歴はファイルから補う
+    let mut f = fs::OpenOptions::new()
+        .append(true)
+        .open(bank.history_index_file())
+        .unwrap();
+    f.write_all(br#"{"file":"10"#).unwrap();
+    drop(f);
+    let (id, _) = hash::hash_reader("fourth".as_bytes()).unwrap();
+    let fourth = bank
+        .save_history(id, Timestamp::from(1_000_000_300), None)
+        .unwrap();
+    fs::write(
+        bank.history_dir().join("1000000400.history.json"),
+        format!(r#"{{"timestamp":1000000400,"id":"{}"}}"#, fourth.id()),
+    )
+    .unwrap();
+    let histories = bank.histories().unwrap();
+    assert_eq!(histories.len(), 4);
+    assert_eq!(histories[2], fourth);
+    assert_eq!(histories[3].timestamp(), Timestamp::from(1_000_000_400));
+
+    let between = bank
+        .histories_between(
+            Some(Timestamp::from(1_000_000_200)),
+            Some(Timestamp::from(1_000_000_400)),
+        )
+        .unwrap();
+    assert_eq!(between, vec![saved[2].clone(), fourth]);
+}
+
+#[test]
+fn test_annotate_history() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let (id1, _) = hash::hash_reader("first".as_bytes()).unwrap();
+    let (id2, _) = hash::hash_reader("second".as_bytes()).unwrap();
+    let first = bank
+        .save_history(id1, Timestamp::from(1_000_000_000), None)
+        .unwrap();
+    let second = bank
+        .save_history(id2, Timestamp::from(1_000_000_100), None)
+        .unwrap();
+
+    // 索引とファイルの両方に反映される
+    let annotated = bank
+        .annotate_history(&first, Some("before incident".to_owned()))
+        .unwrap();
+    assert_eq!(annotated.memo(), Some("before incident"));
+    assert_eq!(
+        bank.histories().unwrap(),
+        vec![annotated.clone(), second.clone()]
+    );
+    fs::remove_file(bank.history_index_file()).unwrap();
+    assert_eq!(bank.histories().unwrap()[0], annotated);
+    assert_eq!(bank.last_scan().unwrap(), Some(second.clone()));
+
+    // 最新の履歴であれば最新の履歴も更新される
+    let latest = bank
+        .annotate_history(&second, Some("latest".to_owned()))
+        .unwrap();
+    assert_eq!(bank.last_scan().unwrap(), Some(latest.clone()));
+
+    // メモを削除できる
+    let cleared = bank.annotate_history(&latest, None).unwrap();
+    assert_eq!(cleared, second);
+    assert_eq!(bank.histories().unwrap()[1].memo(), None);
+}
+
+#[test]
+fn test_save_inventory() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let (id, _) = hash::hash_reader("root".as_bytes()).unwrap();
+    let base = bank
+        .save_history(id.clone(), Timestamp::from(1_000_000_000), None)
+        .unwrap();
+    assert!(!base.is_inventory());
+    assert_eq!(bank.load_inventory(&base).unwrap(), None);
+
+    let entries = vec![
+        InventoryEntry {
+            kind: ChangeKind::Added,
+            path: "dir/new.txt".to_owned(),
+            size: 10,
+        },
+        InventoryEntry {
+            kind: ChangeKind::Removed,
+            path: "old.txt".to_owned(),
+            size: 20,
+        },
+    ];
+    let inventory = bank
+        .save_inventory(&base, &entries, Timestamp::from(1_000_000_100), None)
+        .unwrap();
+
+    // ルートは元にした履歴と同じで、一覧は別に読み込める
+    assert!(inventory.is_inventory());
+    assert_eq!(inventory.id(), &id);
+    assert_eq!(inventory.version, INVENTORY_HISTORY_VERSION);
+    assert_eq!(bank.load_inventory(&inventory).unwrap(), Some(entries));
+    assert_eq!(bank.last_scan().unwrap(), Some(inventory.clone()));
+    assert_eq!(bank.histories().unwrap(), vec![base, inventory]);
+}
+
+#[test]
+fn test_append_ignore_lines() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    fs::write(bank.ignore_file(), "*.tmp").unwrap();
+    bank.append_ignore_lines(&["# imported", "/build/"])
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(bank.ignore_file()).unwrap(),
+        "*.tmp\n# imported\n/build/\n"
+    );
+
+    // パースできない行は書き込まない
+    assert!(matches!(
+        bank.append_ignore_lines(&["foo\\x"]),
+        Err(Error::IgnorePattern(_))
+    ));
+    let patterns = bank.load_ignore_patterns().unwrap();
+    let entry = EntryPath::root().join("build", true);
+    assert_eq!(patterns.matches(&entry), Match::Ignored);
+}
+
+#[test]
+fn test_free_space_limits() {
+    let limits = FreeSpaceLimits {
+        min_bytes: 100,
+        warn_bytes: 1000,
+        min_inodes: 10,
+        warn_inodes: 100,
+    };
+    let space = |bytes, inodes| DiskSpace {
+        available_bytes: bytes,
+        available_inodes: inodes,
+    };
+
+    assert_eq!(limits.classify(&space(1000, Some(100))), Ok(vec![]));
+    assert_eq!(limits.classify(&space(1000, None)), Ok(vec![]));
+    assert_eq!(
+        limits.classify(&space(999, Some(100))),
+        Ok(vec!["space: 999 B free".to_owned()])
+    );
+    assert_eq!(
+        limits.classify(&space(100, Some(99))),
+        Ok(vec![
+            "space: 100 B free".to_owned(),
+            "inodes: 99 inodes free".to_owned()
+        ])
+    );
+    assert_eq!(
+        limits.classify(&space(99, Some(1000))),
+        Err("only 99 B free".to_owned())
+    );
+    assert_eq!(
+        limits.classify(&space(500, Some(9))),
+        Err("only 9 inodes free".to_owned())
+    );
+    // inode数に上限がなければ容量のみで判定する
+    assert_eq!(
+        limits.classify(&space(500, None)),
+        Ok(vec!["space: 500 B free".to_owned()])
+    );
+}
+
+#[test]
+fn test_path_index() {
+    use filetime::{set_file_mtime, FileTime};
+
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let write = |path: &str, content: &str, mtime: i64| {
+        let path = target.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        set_file_mtime(&path, FileTime::from_unix_time(mtime, 0)).unwrap();
+    };
+    write("a.txt", "a", 1_000_000);
+    write("same/b.txt", "bb", 1_000_000);
+    write("touched.txt", "t", 1_000_000);
+    write("gone/c.txt", "c", 1_000_000);
+    write("kind/e.txt", "e", 1_000_000);
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let backup = |at: u64| {
+        let root = Scanner::new(&bank).scan().unwrap();
+        bank.save_history(root.id(), Timestamp::from(at), None)
+            .unwrap()
+    };
+
+    let first = backup(1_000_000_000);
+    let mut index = bank.load_path_index().unwrap();
+    assert_eq!(bank.update_path_index(&first, &mut index).unwrap(), 5);
+    bank.save_path_index(&index).unwrap();
+    assert_eq!(
+        index.get("same/b.txt").unwrap(),
+        &PathRecord {
+            history: first.id().clone(),
+            id: hash::hash_reader("bb".as_bytes()).unwrap().0,
+            size: 2,
+            modified: Timestamp::from(1_000_000),
+        }
+    );
+
+    write("a.txt", "aaa", 2_000_000);
+    write("touched.txt", "t", 2_000_000);
+    write("new/d.txt", "d", 2_000_000);
+    fs::remove_dir_all(target.join("gone")).unwrap();
+    fs::remove_dir_all(target.join("kind")).unwrap();
+    write("kind", "file", 2_000_000);
+    let second = backup(1_000_000_100);
+
+    let mut index = bank.load_path_index().unwrap();
+    assert_eq!(index.root(), Some(first.id()));
+    // a.txtとtouched.txtの更新、new/d.txtとkindの追加、gone/c.txtとkind/e.txtの削除
+    assert_eq!(bank.update_path_index(&second, &mut index).unwrap(), 6);
+    assert_eq!(index.root(), Some(second.id()));
+    assert_eq!(
+        index.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+        vec!["a.txt", "kind", "new/d.txt", "same/b.txt", "touched.txt"]
+    );
+    assert_eq!(index.get("a.txt").unwrap().history, *second.id());
+    assert_eq!(index.get("a.txt").unwrap().size, 3);
+    // 内容が同じなら最初に記録した履歴を引き継ぐ
+    let touched = index.get("touched.txt").unwrap();
+    assert_eq!(touched.history, *first.id());
+    assert_eq!(touched.modified, Timestamp::from(2_000_000));
+    assert_eq!(index.get("same/b.txt").unwrap().history, *first.id());
+
+    // 同じスナップショットでは何も変わらない
+    assert_eq!(bank.update_path_index(&second, &mut index).unwrap(), 0);
+
+    // 作り直しても、履歴以外は同じ内容になる
+    let mut rebuilt = PathIndex::new();
+    bank.update_path_index(&second, &mut rebuilt).unwrap();
+    let strip = |index: &PathIndex| {
+        index
+            .iter()
+            .map(|(p, r)| (p.to_owned(), r.id.clone(), r.size, r.modified))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(strip(&rebuilt), strip(&index));
+}
@@ -0,0 +1,139 @@
+//! オブジェクトごとの最終検証日時の記録。
+//!
+//! 検証に時間の上限を設けて少しずつ進める場合に、最も長く検証されていないオブジェクトから順に検証するために使う。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use crate::core::hash::{Algorithm, HashID, HASH_SIZE};
+use crate::core::timestamp::Timestamp;
+
+#[cfg(test)]
+mod test;
+
+// ファイルの先頭に置く識別子。形式を変える場合は末尾の番号を上げる。
+const MAGIC: &[u8; 8] = b"SBAKVRF1";
+
+// アルゴリズムの識別子、ID、UNIX時間(リトルエンディアン)
+const RECORD_SIZE: usize = 1 + HASH_SIZE + 8;
+
+/// オブジェクトのIDと、そのオブジェクトの検証に最後に成功した日時の対応
+///
+/// ファイルには識別子に続けて、アルゴリズムの識別子1バイト、IDのバイト列、UNIX時間8バイトの組を並べた形式で保存される。
+#[derive(Default)]
+pub struct VerifiedTimes {
+    times: HashMap<HashID, u64>,
+}
+
+impl VerifiedTimes {
+    /// 空の記録を生成する。
+    pub fn new() -> VerifiedTimes {
+        VerifiedTimes::default()
+    }
+
+    /// `r`から保存済みの記録を読み込む。
+    ///
+    /// 形式が異なる場合は`InvalidData`のエラーを返す。
+    pub fn read_from<R: Read>(r: R) -> io::Result<VerifiedTimes> {
+        let mut r = BufReader::new(r);
+
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unknown verified times format",
+            ));
+        }
+
+        let mut bytes = Vec::new();
+        r.read_to_end(&mut bytes)?;
+        if bytes.len() % RECORD_SIZE != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated verified times",
+            ));
+        }
+
+        let mut verified = VerifiedTimes::new();
+        verified.times.reserve(bytes.len() / RECORD_SIZE);
+        for chunk in bytes.chunks(RECORD_SIZE) {
+            let algorithm = Algorithm::from_code(chunk[0]).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown hash algorithm code: {}", chunk[0]),
+                )
+            })?;
+            let mut id = [0u8; HASH_SIZE];
+            id.copy_from_slice(&chunk[1..=HASH_SIZE]);
+            let mut epoch = [0u8; 8];
+            epoch.copy_from_slice(&chunk[1 + HASH_SIZE..]);
+            verified
+                .times
+                .insert(HashID::from_bytes(algorithm, id), u64::from_le_bytes(epoch));
+        }
+
+        Ok(verified)
+    }
+
+    /// 記録を`w`に書き出す。
+    pub fn write_to<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut w = BufWriter::new(w);
+        w.write_all(MAGIC)?;
+        for (id, epoch) in &self.times {
+            w.write_all(&[id.algorithm().code()])?;
+            w.write_all(id.as_bytes())?;
+            w.write_all(&epoch.to_le_bytes())?;
+        }
+        w.flush()
+    }
+
+    /// `id`のオブジェクトの検証に最後に成功した日時を返す。
+    ///
+    /// 記録が無い場合は`None`を返す。
+    pub fn get(&self, id: &HashID) -> Option<Timestamp> {
+        self.times.get(id).map(|&epoch| Timestamp::from(epoch))
+    }
+
+    /// `id`のオブジェクトの検証に`at`に成功したことを記録する。
+    pub fn mark(&mut self, id: HashID, at: Timestamp) {
+        self.times.insert(id, at.unix_epoch());
+    }
+
+    /// `f`が`true`を返すオブジェクトの記録のみを残す。
+    ///
+    /// 削除されたオブジェクトの記録を取り除くために使う。
+    pub fn retain<F: FnMut(&HashID) -> bool>(&mut self, mut f: F) {
+        self.times.retain(|id, _| f(id));
+    }
+
+    /// `ids`を最後に検証に成功した日時の古い順に並べ替える。
+    ///
+    /// 記録が無いオブジェクトは最も古いものとして扱う。日時が同じ場合はIDの順とする。
+    pub fn sort_oldest_first(&self, ids: &mut [HashID]) {
+        ids.sort_by(|a, b| {
+            let a_time = self.times.get(a).copied().unwrap_or(0);
+            let b_time = self.times.get(b).copied().unwrap_or(0);
+            a_time.cmp(&b_time).then_with(|| a.cmp(b))
+        });
+    }
+
+    /// 記録されているオブジェクトの個数を返す。
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    /// 記録が空かどうかを返す。
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+}
+
+impl fmt::Debug for VerifiedTimes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VerifiedTimes")
+            .field("len", &self.times.len())
+            .finish()
+    }
+}
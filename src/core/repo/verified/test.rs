@@ -0,0 +1,51 @@
+use super::*;
+
+use crate::core::hash::hash_reader;
+
+fn id_of(s: &str) -> HashID {
+    hash_reader(s.as_bytes()).unwrap().0
+}
+
+#[test]
+fn test_write_and_read() {
+    let mut verified = VerifiedTimes::new();
+    verified.mark(id_of("a"), Timestamp::from(1_000_000_000));
+    verified.mark(id_of("b"), Timestamp::from(1_000_000_100));
+    verified.mark(id_of("a"), Timestamp::from(1_000_000_200));
+
+    let mut buf = Vec::new();
+    verified.write_to(&mut buf).unwrap();
+    assert_eq!(buf.len(), MAGIC.len() + 2 * RECORD_SIZE);
+
+    let read = VerifiedTimes::read_from(buf.as_slice()).unwrap();
+    assert_eq!(read.len(), 2);
+    assert_eq!(read.get(&id_of("a")), Some(Timestamp::from(1_000_000_200)));
+    assert_eq!(read.get(&id_of("b")), Some(Timestamp::from(1_000_000_100)));
+    assert_eq!(read.get(&id_of("c")), None);
+}
+
+#[test]
+fn test_read_invalid() {
+    let err = VerifiedTimes::read_from(&b"NOTTIMES"[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let mut buf = MAGIC.to_vec();
+    buf.extend_from_slice(&[0u8; HASH_SIZE]);
+    let err = VerifiedTimes::read_from(buf.as_slice()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_sort_oldest_first() {
+    let mut verified = VerifiedTimes::new();
+    verified.mark(id_of("a"), Timestamp::from(300));
+    verified.mark(id_of("b"), Timestamp::from(100));
+    verified.mark(id_of("removed"), Timestamp::from(200));
+    verified.retain(|id| id != &id_of("removed"));
+    assert_eq!(verified.len(), 2);
+
+    let mut ids = vec![id_of("a"), id_of("b"), id_of("c")];
+    verified.sort_oldest_first(&mut ids);
+    // 記録の無いものが先頭
+    assert_eq!(ids, vec![id_of("c"), id_of("b"), id_of("a")]);
+}
@@ -1,188 +1,1005 @@
 //! ファイルやディレクトリのスキャンを行う。
 
+use std::collections::HashSet;
 use std::convert::{TryFrom, TryInto};
+use std::error;
+use std::fmt;
 use std::fs;
 use std::io;
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Instant;
+use std::vec;
 
-use failure::Fail;
 use log::{info, trace, warn};
-use serde_json::to_writer;
+use serde::{Deserialize, Serialize};
 
 use crate::core::entry::*;
 use crate::core::hash::{self, hash as hash_file, hash_reader, HashID};
+use crate::core::ignore::pattern::{Pattern, Patterns, Syntax};
 use crate::core::ignore::{self, IgnoreStack};
-use crate::core::repo::{self, Bank};
+use crate::core::repo::{self, Bank, ScanStats};
+use crate::core::span::Span;
 use crate::core::timestamp;
+use crate::progress::Progress;
+use crate::util::long_path;
+use crate::util::size::Size;
+
+#[cfg(test)]
+mod test;
+
+/// スキャン中に入出力エラーが発生した場合の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// スキャンを中断する
+    Abort,
+    /// アクセス権限がないファイルのみ飛ばし、その他のエラーではスキャンを中断する
+    #[default]
+    SkipDenied,
+    /// エラーが発生したファイルを全て飛ばす
+    SkipAll,
+}
+
+impl FromStr for ErrorPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<ErrorPolicy, String> {
+        match s {
+            "abort" => Ok(ErrorPolicy::Abort),
+            "skip-denied" => Ok(ErrorPolicy::SkipDenied),
+            "skip" => Ok(ErrorPolicy::SkipAll),
+            _ => Err(format!(
+                "unknown error policy '{}' (expected abort, skip-denied or skip)",
+                s
+            )),
+        }
+    }
+}
+
+/// [`Scanner`](struct.Scanner.html)のBuilder
+#[derive(Debug)]
+pub struct ScannerBuilder<'a> {
+    scanner: Scanner<'a>,
+}
+
+impl<'a> ScannerBuilder<'a> {
+    /// 指定された`Bank`に保存する、デフォルト設定のBuilderを生成する。
+    pub fn new(bank: &'a Bank<'a>) -> ScannerBuilder<'a> {
+        ScannerBuilder {
+            scanner: Scanner {
+                bank,
+                root: long_path::extended(bank.target_path()),
+                config_patterns: Patterns::default(),
+                extra_patterns: Patterns::default(),
+                ignore_syntax: Syntax::default(),
+                progress: Progress::hidden(),
+                follow_symlinks: false,
+                alternate_streams: false,
+                max_size: None,
+                max_depth: None,
+                threads: 1,
+                error_policy: ErrorPolicy::default(),
+                dry_run: false,
+                stats: Mutex::new(ScanStats::default()),
+                changes: Mutex::new(None),
+            },
+        }
+    }
+
+    /// 設定ファイルで指定された除外パターンを設定する。
+    ///
+    /// リポジトリ共通の除外リストより優先度が高く、Bankの除外リストより優先度が低いものとして扱われる。
+    pub fn config_patterns(mut self, patterns: Patterns) -> Self {
+        self.scanner.config_patterns = patterns;
+        self
+    }
+
+    /// 追加の除外パターンを設定する。
+    ///
+    /// Bankの除外リストより優先度が高いものとして扱われる。
+    pub fn extra_patterns(mut self, patterns: Patterns) -> Self {
+        self.scanner.extra_patterns = patterns;
+        self
+    }
+
+    /// 各ディレクトリの除外ファイルの書式を設定する。
+    pub fn ignore_syntax(mut self, syntax: Syntax) -> Self {
+        self.scanner.ignore_syntax = syntax;
+        self
+    }
+
+    /// スキャンしたファイルの進捗を表示する`Progress`を設定する。
+    pub fn progress(mut self, progress: Progress) -> Self {
+        self.scanner.progress = progress;
+        self
+    }
+
+    /// シンボリックリンクをリンク先のファイルやディレクトリとしてスキャンするかどうかを設定する。
+    ///
+    /// リンク先が祖先ディレクトリである場合は、循環を避けるためシンボリックリンクとして記録する。
+    /// デフォルト値は`false`。
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.scanner.follow_symlinks = follow;
+        self
+    }
+
+    /// ファイルのNTFSの代替データストリームを記録するかどうかを設定する。
+    ///
+    /// Windows以外では無視される。
+    /// 更新日時が前回のスキャンと同じファイルは読み込まないため、前回のストリームがそのまま引き継がれる。
+    /// デフォルト値は`false`。
+    pub fn alternate_streams(mut self, capture: bool) -> Self {
+        self.scanner.alternate_streams = capture;
+        self
+    }
+
+    /// スキャンするファイルの最大サイズを設定する。
+    ///
+    /// これより大きいファイルはバックアップされない。
+    /// デフォルト値は`None`(制限なし)。
+    pub fn max_size(mut self, max_size: Option<Size>) -> Self {
+        self.scanner.max_size = max_size;
+        self
+    }
+
+    /// スキャンするディレクトリの最大の深さを設定する。
+    ///
+    /// ルートディレクトリの直下の深さを1として、これより深いディレクトリはバックアップされない。
+    /// デフォルト値は`None`(制限なし)。
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.scanner.max_depth = max_depth;
+        self
+    }
+
+    /// ファイルのハッシュ計算と保存に使用するスレッド数を設定する。
+    ///
+    /// ディレクトリの走査はこれらのスレッドとは別に、呼び出し元のスレッドで並行して行われる。
+    /// 0を指定した場合は1として扱う。
+    /// デフォルト値は1。
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.scanner.threads = threads.max(1);
+        self
+    }
+
+    /// 入出力エラーの扱いを設定する。
+    ///
+    /// デフォルト値は`ErrorPolicy::SkipDenied`。
+    pub fn error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.scanner.error_policy = policy;
+        self
+    }
+
+    /// リポジトリに書き込まずにスキャンのみを行うかどうかを設定する。
+    ///
+    /// デフォルト値は`false`。
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.scanner.dry_run = dry_run;
+        self
+    }
+
+    /// 設定に従った[`Scanner`](struct.Scanner.html)を生成する。
+    pub fn build(self) -> Scanner<'a> {
+        self.scanner
+    }
+}
 
 /// 更新されたファイルやディレクトリをスキャンするスキャナ
 #[derive(Debug)]
 pub struct Scanner<'a> {
     bank: &'a Bank<'a>,
+    // 深い階層もWindowsで扱えるよう、拡張パス形式にした対象ディレクトリ
+    root: PathBuf,
+    config_patterns: Patterns,
+    extra_patterns: Patterns,
+    ignore_syntax: Syntax,
+    progress: Progress,
+    follow_symlinks: bool,
+    alternate_streams: bool,
+    max_size: Option<Size>,
+    max_depth: Option<usize>,
+    threads: usize,
+    error_policy: ErrorPolicy,
+    dry_run: bool,
+    stats: Mutex<ScanStats>,
+    // `preview`の実行中のみ`Some`で、見つけた変更を記録する。
+    changes: Mutex<Option<Vec<Change>>>,
+}
+
+/// [`Scanner::check_ignore`](struct.Scanner.html#method.check_ignore)の判定結果
+#[derive(Debug, Clone)]
+pub struct IgnoreCheck {
+    path: PathBuf,
+    base: PathBuf,
+    pattern: Pattern,
+}
+
+impl IgnoreCheck {
+    /// 判定されたパスを返す。
+    ///
+    /// 祖先のディレクトリが除外された場合は、そのディレクトリのパスである。
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// パターンの基準となるディレクトリを返す。
+    pub fn base_dir(&self) -> &Path {
+        &self.base
+    }
+
+    /// 判定を決めたパターンを返す。
+    pub fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+
+    /// 除外されるかどうかを返す。
+    pub fn is_ignored(&self) -> bool {
+        !self.pattern.is_allow()
+    }
+}
+
+/// 前回のスキャンからの変更の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    /// 新たに追加された
+    Added,
+    /// 更新日時が変わった
+    Updated,
+    /// 削除されたか、除外されるようになった
+    Removed,
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Removed => "removed",
+        })
+    }
+}
+
+/// [`Scanner::preview`](struct.Scanner.html#method.preview)で見つかった、前回のスキャンからのファイルの変更
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    kind: ChangeKind,
+    path: PathBuf,
+    size: u64,
+}
+
+impl Change {
+    /// 変更の種類を返す。
+    pub fn kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    /// 変更されたファイルのパスを返す。
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// ファイルのバイト数を返す。
+    ///
+    /// 削除されたファイルは前回のスキャン時のバイト数である。シンボリックリンクは0になる。
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+// ワーカースレッドで内容のスキャンを行うファイル
+struct FileJob {
+    path: PathBuf,
+    attr: Attributes,
+}
+
+// ワーカースレッドによるファイルのスキャン結果
+type FileResult = (usize, PathBuf, Result<StoredFile>);
+
+// 保存したファイルのエントリ、読み込んだバイト数、新たに書き込んだバイト数
+type StoredFile = (FsHash, u64, u64);
+
+// ファイルのスキャン結果の受け渡し
+struct Pipeline {
+    jobs: Option<SyncSender<(usize, FileJob)>>,
+    results: Receiver<FileResult>,
+    // 依頼順のスキャン結果。未着の間と飛ばした場合は`None`。
+    hashes: Vec<Option<Option<FsHash>>>,
+}
+
+// 子エントリのスキャンの完了を待っているディレクトリ
+struct PendingDir {
+    path: PathBuf,
+    attr: Attributes,
+    children: Vec<Pending>,
+    // 除外されたディレクトリで、除外を取り消された子孫だけを含む。子孫が無ければ記録しない。
+    pruned: bool,
+}
+
+impl PendingDir {
+    fn new(path: &Path, attr: Attributes, pruned: bool) -> PendingDir {
+        PendingDir {
+            path: path.to_owned(),
+            attr,
+            children: Vec::new(),
+            pruned,
+        }
+    }
+}
+
+impl Drop for PendingDir {
+    // 深いディレクトリ構造でスタックが溢れないよう、再帰せずに破棄する。
+    fn drop(&mut self) {
+        let mut children = mem::take(&mut self.children);
+        while let Some(ch) = children.pop() {
+            if let Pending::Dir(mut dir) = ch {
+                children.append(&mut dir.children);
+            }
+        }
+    }
+}
+
+// 走査中のディレクトリ
+struct Frame {
+    dir: PendingDir,
+    entries: fs::ReadDir,
+    old_entry: DirEntry,
+    ignore_stack: IgnoreStack,
+    key: DirKey,
+}
+
+// 走査中のディレクトリのスタック
+struct Walker {
+    frames: Vec<Frame>,
+    // 循環の検出に使う、走査中のディレクトリの識別子
+    ancestors: HashSet<DirKey>,
+}
+
+// エントリの生成を待っているディレクトリ
+struct BuildFrame {
+    path: PathBuf,
+    builder: DirEntryBuilder,
+    children: vec::IntoIter<Pending>,
+}
+
+impl BuildFrame {
+    fn new(mut dir: PendingDir) -> BuildFrame {
+        BuildFrame {
+            path: mem::take(&mut dir.path),
+            builder: DirEntryBuilder::new(dir.attr.clone()),
+            children: mem::take(&mut dir.children).into_iter(),
+        }
+    }
+}
+
+// ディレクトリの識別子
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DirKey(#[cfg(unix)] (u64, u64), #[cfg(not(unix))] PathBuf);
+
+// スキャン中の子エントリ
+enum Pending {
+    Ready(FsHash),
+    File(usize),
+    Dir(PendingDir),
+    // `preview`で内容を読まずに変更として記録したファイル
+    Preview(String),
 }
 
 impl<'a> Scanner<'a> {
     /// 指定された`Bank`に保存する、デフォルト設定のスキャナを生成する
-    pub fn new(bank: &'a Bank<'a>) -> Scanner {
-        Scanner { bank }
+    ///
+    /// 設定を変更する場合は[`ScannerBuilder`](struct.ScannerBuilder.html)を使用する。
+    pub fn new(bank: &'a Bank<'a>) -> Scanner<'a> {
+        ScannerBuilder::new(bank).build()
     }
 
     /// Bankの対象ディレクトリをスキャンする
+    ///
+    /// スキャンの統計は[`stats`](#method.stats)で得られる。
     pub fn scan(&self) -> Result<FsHash> {
-        let path = self.bank.target_path();
+        let mut span = Span::enter(
+            "scan",
+            &[("bank", &self.bank.name()), ("dry_run", &self.dry_run)],
+        );
+        *self.stats_lock() = ScanStats::default();
+        let start = Instant::now();
+        let result = self.scan_root();
+        self.stats_lock().duration_ms = start.elapsed().as_millis() as u64;
+        span.record(result)
+    }
+
+    /// Bankの対象ディレクトリを前回のスキャンと比べ、次のスキャンで追加・更新・削除されるファイルを返す。
+    ///
+    /// 更新日時が変わったファイルは内容を読まずに更新されたものとみなす。
+    /// リポジトリには書き込まない。結果はパスの順に並ぶ。
+    pub fn preview(&self) -> Result<Vec<Change>> {
+        *self.changes_lock() = Some(Vec::new());
+        let result = self.scan();
+        let mut changes = self.changes_lock().take().unwrap_or_default();
+        result?;
+
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(changes)
+    }
+
+    /// 直前の[`scan`](#method.scan)の統計を返す。
+    pub fn stats(&self) -> ScanStats {
+        self.stats_lock().clone()
+    }
+
+    fn stats_lock(&self) -> MutexGuard<'_, ScanStats> {
+        self.stats
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn changes_lock(&self) -> MutexGuard<'_, Option<Vec<Change>>> {
+        self.changes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn is_preview(&self) -> bool {
+        self.changes_lock().is_some()
+    }
+
+    // リポジトリに書き込まないかどうか
+    fn is_dry_run(&self) -> bool {
+        self.dry_run || self.is_preview()
+    }
+
+    fn record_change(&self, kind: ChangeKind, path: PathBuf, size: u64) {
+        if let Some(changes) = self.changes_lock().as_mut() {
+            changes.push(Change {
+                kind,
+                path: long_path::normal(&path),
+                size,
+            });
+        }
+    }
+
+    // 走査を終えたディレクトリについて、前回のスキャンにあって今回は無い子エントリを削除されたものとして記録する。
+    //
+    // 種類が変わったエントリも、前回のものは削除されたものとして扱う。
+    fn record_removed(&self, dir: &PendingDir, old_entry: &DirEntry) -> Result<()> {
+        let mut current = HashSet::new();
+        for ch in &dir.children {
+            let (name, is_dir) = match ch {
+                Pending::Ready(hash) => (hash.attr().name().to_owned(), false),
+                Pending::Dir(d) => (d.attr.name().to_owned(), true),
+                Pending::Preview(name) => (name.clone(), false),
+                Pending::File(_) => continue,
+            };
+            current.insert((name, is_dir));
+        }
+
+        // 削除されたディレクトリは中のファイルを全て記録する
+        let mut removed = old_entry
+            .children()
+            .filter(|ch| {
+                let is_dir = matches!(ch, FsHash::Dir(_));
+                !current.contains(&(ch.attr().name().to_owned(), is_dir))
+            })
+            .map(|ch| (dir.path.join(ch.attr().name()), ch.clone()))
+            .collect::<Vec<_>>();
+        while let Some((p, ch)) = removed.pop() {
+            match ch {
+                FsHash::Dir(d) => {
+                    let entry: DirEntry = self.bank.load_entry(&d.id())?;
+                    removed.extend(
+                        entry
+                            .children()
+                            .map(|ch| (p.join(ch.attr().name()), ch.clone())),
+                    );
+                }
+                FsHash::File(f) => {
+                    let size = self.bank.object_size(&f.id())?;
+                    self.record_change(ChangeKind::Removed, p, size);
+                }
+                FsHash::Symlink(_) => self.record_change(ChangeKind::Removed, p, 0),
+            }
+        }
+        Ok(())
+    }
+
+    /// `path`がスキャンで除外されるかどうかと、その判定を決めたパターンを返す。
+    ///
+    /// `path`自身にマッチするパターンが無く、祖先のディレクトリの判定に従う場合は、そのディレクトリの判定を返す。
+    /// どのパターンにもマッチしない場合は`None`を返す。
+    ///
+    /// `path`は対象ディレクトリ以下の絶対パスでなければならない。
+    pub fn check_ignore(&self, path: &Path) -> Result<Option<IgnoreCheck>> {
+        let root = self.root.as_path();
+        let path = long_path::extended(path);
+        let relative = path
+            .strip_prefix(root)
+            .map_err(|_| ignore::Error::NotChild(path.to_owned(), root.to_owned()))?;
+
+        let mut stack = self.root_ignore_stack()?.child(root)?;
+        let mut current = root.to_owned();
+        // 除外された祖先のディレクトリと、その中で除外を取り消した祖先のディレクトリの判定
+        let mut inherited: Option<IgnoreCheck> = None;
+        let mut components = relative.components().peekable();
+        while let Some(c) = components.next() {
+            current.push(c);
+            let is_last = components.peek().is_none();
+            let fs_meta = if is_last {
+                self.metadata(&current)
+            } else {
+                None
+            };
+            // 祖先はディレクトリ、存在しないパスはファイルとして判定する
+            let explained = match fs_meta {
+                Some(fs_meta) => stack.explain_entry(&current, &fs_meta)?,
+                None => stack.explain(&current, !is_last)?,
+            };
+
+            let check = explained.map(|(base, pattern)| IgnoreCheck {
+                path: long_path::normal(&current),
+                base: long_path::normal(base),
+                pattern: pattern.clone(),
+            });
+            if is_last {
+                return Ok(check.or(inherited));
+            }
+
+            if let Some(check) = check {
+                if check.is_ignored() || inherited.is_some() {
+                    inherited = Some(check);
+                }
+            }
+            if inherited.as_ref().is_some_and(IgnoreCheck::is_ignored)
+                && !stack.may_allow_inside(&current)?
+            {
+                return Ok(inherited);
+            }
+            stack = stack.child(&current)?;
+        }
+
+        Ok(None)
+    }
+
+    // スキャン時と同じ基準でメタデータを取得する。存在しないパスの場合は`None`を返す。
+    fn metadata(&self, p: &Path) -> Option<fs::Metadata> {
+        let meta = if self.follow_symlinks {
+            fs::metadata(p)
+        } else {
+            fs::symlink_metadata(p)
+        };
+        meta.ok()
+    }
+
+    // ルートディレクトリに適用する除外パターンのスタックを作成する。
+    fn root_ignore_stack(&self) -> Result<IgnoreStack> {
+        trace!("load ing bank ignore patterns");
+        let repo_patterns = self.bank.load_repository_ignore_patterns()?;
+        let bank_patterns = self.bank.load_ignore_patterns()?;
+        let ignore_patterns = repo_patterns
+            .merged(&self.config_patterns)
+            .merged(&bank_patterns)
+            .merged(&self.extra_patterns);
+        Ok(IgnoreStack::with_syntax(
+            &self.root,
+            ignore_patterns,
+            self.ignore_syntax,
+        ))
+    }
+
+    fn scan_root(&self) -> Result<FsHash> {
+        let path = self.root.as_path();
         trace!("scan root path = {:?}", path);
         let last_id = self.bank.last_scan()?.map(|e| e.id().clone());
         trace!("last_scan root entry id = {:?}", last_id);
         let attr = convert_metadata(path, &fs::metadata(path)?)?;
 
-        trace!("load ing bank ignore patterns");
-        let ignore_patterns = self.bank.load_ignore_patterns()?;
-        let ignore_stack = IgnoreStack::new(path, ignore_patterns);
+        let ignore_stack = self.root_ignore_stack()?;
 
-        trace!("start scan root dir");
-        let id = self.scan_dir(path, &ignore_stack, attr, last_id)?;
+        let (job_tx, job_rx) = mpsc::sync_channel::<(usize, FileJob)>(self.threads * 4);
+        let (result_tx, result_rx) = mpsc::channel();
+        let job_rx = Mutex::new(job_rx);
+
+        thread::scope(|s| {
+            for _ in 0..self.threads {
+                let job_rx = &job_rx;
+                let result_tx = result_tx.clone();
+                let bank = self.bank;
+                let dry_run = self.is_dry_run();
+                let streams = self.alternate_streams;
+                s.spawn(move || loop {
+                    let received = job_rx.lock().unwrap().recv();
+                    let (index, job) = match received {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    };
+                    let result = store_file(bank, dry_run, streams, &job);
+                    if result_tx.send((index, job.path, result)).is_err() {
+                        return;
+                    }
+                });
+            }
+            drop(result_tx);
 
-        Ok(id)
+            let mut pipeline = Pipeline {
+                jobs: Some(job_tx),
+                results: result_rx,
+                hashes: Vec::new(),
+            };
+
+            trace!("start scan root dir");
+            let root_meta = fs::metadata(path)?;
+            let mut walker = Walker {
+                frames: Vec::new(),
+                ancestors: HashSet::new(),
+            };
+            let key = dir_key(path, &root_meta)?;
+            let root_dir = PendingDir::new(path, attr, false);
+            self.enter_dir(&mut walker, &ignore_stack, root_dir, last_id, key)?;
+            let pending = self.walk(&mut walker, &mut pipeline)?;
+            trace!("wait for file scans");
+            self.wait_files(&mut pipeline)?;
+            trace!("start build dir entries");
+            let id = self.build_tree(pending, &mut pipeline)?;
+            self.progress.finish();
+
+            Ok(id)
+        })
     }
 
-    fn scan_node(
-        &self,
-        p: &Path,
-        ignore_stack: &IgnoreStack,
-        last_entry: Option<&FsHash>,
-    ) -> Result<Option<FsHash>> {
-        match self.scan_node_inner(p, ignore_stack, last_entry) {
-            Ok(v) => Ok(v),
+    // エラー処理の方針に従い、飛ばせるエラーであれば`None`にする。
+    fn recover<T>(&self, p: &Path, result: Result<Option<T>>) -> Result<Option<T>> {
+        match result {
             Err(Error::IO(e)) => {
-                if e.kind() == io::ErrorKind::PermissionDenied {
+                let skip = match self.error_policy {
+                    ErrorPolicy::Abort => false,
+                    ErrorPolicy::SkipDenied => e.kind() == io::ErrorKind::PermissionDenied,
+                    ErrorPolicy::SkipAll => true,
+                };
+                if skip {
+                    warn!("skip {:?}: {}", p, e);
+                    self.stats_lock().errors += 1;
                     Ok(None)
                 } else {
                     Err(Error::IO(e))
                 }
             }
-            Err(e) => Err(e),
+            r => r,
         }
     }
 
-    fn scan_node_inner(
+    // メタデータを取得済みのエントリをスキャンする。
+    //
+    // ディレクトリの場合は走査中のディレクトリのスタックに積み、`None`を返す。
+    fn scan_node(
         &self,
+        walker: &mut Walker,
         p: &Path,
-        ignore_stack: &IgnoreStack,
+        fs_meta: fs::Metadata,
         last_entry: Option<&FsHash>,
-    ) -> Result<Option<FsHash>> {
+        pruned: bool,
+        pipeline: &mut Pipeline,
+    ) -> Result<Option<Pending>> {
         info!("{:?}", p);
-        let fs_meta = fs::symlink_metadata(p)?;
         let attr = convert_metadata(p, &fs_meta)?;
         trace!("{:?}: {:?}", p, attr);
 
         let file_type = fs_meta.file_type();
         if file_type.is_dir() {
             trace!("{:?} is dir.", p);
-            let dir_hash = self.scan_dir(p, ignore_stack, attr, last_entry.map(|x| x.id()))?;
-            Ok(Some(dir_hash))
+            if let Some(max_depth) = self.max_depth {
+                if walker.frames.len() > max_depth {
+                    warn!("skip {:?}: deeper than {}", p, max_depth);
+                    return Ok(None);
+                }
+            }
+
+            let key = dir_key(p, &fs_meta)?;
+            if walker.ancestors.contains(&key) {
+                warn!("skip {:?}: directory loop", p);
+                return Ok(None);
+            }
+
+            let ignore_stack = walker.frames.last().unwrap().ignore_stack.clone();
+            let last_id = last_entry.map(|x| x.id());
+            let dir = PendingDir::new(p, attr, pruned);
+            self.enter_dir(walker, &ignore_stack, dir, last_id, key)?;
+            Ok(None)
         } else if file_type.is_file() {
             trace!("{:?} is file.", p);
-            let old_hash = last_entry.and_then(|h| h.clone().try_into().ok());
-            let file_hash = self.scan_file(p, attr, old_hash)?;
-            Ok(Some(file_hash))
+            if let Some(max_size) = self.max_size {
+                if fs_meta.len() > max_size.bytes() {
+                    info!("skip {:?}: larger than {}", p, max_size);
+                    return Ok(None);
+                }
+            }
+
+            let old_hash: Option<FileHash> = last_entry.and_then(|h| h.clone().try_into().ok());
+            let kind = if old_hash.is_some() {
+                ChangeKind::Updated
+            } else {
+                ChangeKind::Added
+            };
+            if let Some(old_hash) = old_hash {
+                if old_hash.attr().modified() == attr.modified() {
+                    trace!("skip scan file {:?}", p);
+                    self.progress.add_file(0);
+                    // 内容は前回のまま、作成日時などの属性は今回のものにする
+                    return Ok(Some(Pending::Ready(old_hash.with_attr(attr).into())));
+                }
+            }
+
+            if self.is_preview() {
+                let name = attr.name().to_owned();
+                self.record_change(kind, p.to_owned(), fs_meta.len());
+                self.progress.add_file(0);
+                return Ok(Some(Pending::Preview(name)));
+            }
+
+            let index = self.submit_file(
+                pipeline,
+                FileJob {
+                    path: p.to_owned(),
+                    attr,
+                },
+            )?;
+            Ok(Some(Pending::File(index)))
         } else if file_type.is_symlink() {
             let symlink_hash = self.scan_symlink(p, attr)?;
-            Ok(Some(symlink_hash))
+            match last_entry {
+                Some(FsHash::Symlink(old)) if old.id() == symlink_hash.id() => {}
+                Some(FsHash::Symlink(_)) => {
+                    self.record_change(ChangeKind::Updated, p.to_owned(), 0)
+                }
+                _ => self.record_change(ChangeKind::Added, p.to_owned(), 0),
+            }
+            Ok(Some(Pending::Ready(symlink_hash)))
         } else {
             warn!("{:?} is not dir nor file", p);
             Ok(None)
         }
     }
 
-    fn scan_dir(
+    // ディレクトリを走査中のディレクトリのスタックに積む。
+    fn enter_dir(
         &self,
-        p: &Path,
+        walker: &mut Walker,
         ignore_stack: &IgnoreStack,
-        attr: Attributes,
+        dir: PendingDir,
         last_id: Option<HashID>,
-    ) -> Result<FsHash> {
+        key: DirKey,
+    ) -> Result<()> {
+        let p = dir.path.as_path();
         trace!("scan dir {:?}", p);
         let old_entry = if let Some(ref id) = last_id {
             trace!("dir has last_id = {}", id);
             self.bank.load_entry(id)?
         } else {
             trace!("dir has no last_id");
-            DirEntryBuilder::new(attr.clone()).build()
+            DirEntryBuilder::new(dir.attr.clone()).build()
         };
 
-        let current_stack = ignore_stack.child(attr.name().to_owned())?;
-        trace!("IGNORE STACK = {:?}", current_stack);
-
-        let mut builder = DirEntryBuilder::new(attr);
+        let ignore_stack = ignore_stack.child(p)?;
+        trace!("IGNORE STACK = {:?}", ignore_stack);
 
+        let entries = fs::read_dir(p)?;
         trace!("start scan dir children: {:?}", p);
-        for ch in fs::read_dir(p)? {
-            let ch = ch?;
-            let name = ch
-                .file_name()
-                .into_string()
-                .map_err(|_| Error::NameIsInvalidUnicode(ch.path()))?;
-            trace!("child name = {}", name);
-
-            let fs_meta = fs::metadata(p)?;
-            if current_stack.ignored(&ch.path(), fs_meta.is_dir())? {
-                trace!("ignore {:?}", ch.path());
-                continue;
+
+        walker.ancestors.insert(key.clone());
+        walker.frames.push(Frame {
+            dir,
+            entries,
+            old_entry,
+            ignore_stack,
+            key,
+        });
+        Ok(())
+    }
+
+    // ディレクトリを走査し、ファイルの内容のスキャンを`pipeline`に依頼する。
+    //
+    // 再帰を使わず、走査中のディレクトリのスタックが空になるまで処理する。
+    fn walk(&self, walker: &mut Walker, pipeline: &mut Pipeline) -> Result<PendingDir> {
+        loop {
+            let frame = walker.frames.last_mut().unwrap();
+            let ch = match frame.entries.next() {
+                Some(ch) => ch,
+                None => {
+                    let frame = walker.frames.pop().unwrap();
+                    walker.ancestors.remove(&frame.key);
+                    trace!("finish scan dir children: {:?}", frame.dir.path);
+                    if self.is_preview() {
+                        self.record_removed(&frame.dir, &frame.old_entry)?;
+                    }
+
+                    if frame.dir.pruned && frame.dir.children.is_empty() {
+                        trace!("ignore {:?}: no entries allowed", frame.dir.path);
+                        continue;
+                    }
+                    match walker.frames.last_mut() {
+                        Some(parent) => parent.dir.children.push(Pending::Dir(frame.dir)),
+                        None => return Ok(frame.dir),
+                    }
+                    continue;
+                }
+            };
+
+            let result = self.scan_child(walker, ch, pipeline);
+            match result {
+                Ok(Some(pending)) => walker.frames.last_mut().unwrap().dir.children.push(pending),
+                Ok(None) => {}
+                Err(e) if walker.frames.len() == 1 => return Err(e),
+                Err(e) => {
+                    // 子エントリで回復できないエラーが起きたディレクトリは、方針に従い丸ごと飛ばす
+                    let frame = walker.frames.pop().unwrap();
+                    walker.ancestors.remove(&frame.key);
+                    self.recover::<()>(&frame.dir.path, Err(e))?;
+                }
             }
+        }
+    }
+
+    fn scan_child(
+        &self,
+        walker: &mut Walker,
+        ch: io::Result<fs::DirEntry>,
+        pipeline: &mut Pipeline,
+    ) -> Result<Option<Pending>> {
+        let ch = ch?;
+        let name = ch
+            .file_name()
+            .into_string()
+            .map_err(|_| Error::NameIsInvalidUnicode(ch.path()))?;
+        trace!("child name = {}", name);
 
-            if let Some(ch_hash) =
-                self.scan_node(&ch.path(), &current_stack, old_entry.find_child(&name))?
+        let path = ch.path();
+        let result = self.scan_child_inner(walker, &ch, &path, &name, pipeline);
+        self.recover(&path, result)
+    }
+
+    fn scan_child_inner(
+        &self,
+        walker: &mut Walker,
+        ch: &fs::DirEntry,
+        p: &Path,
+        name: &str,
+        pipeline: &mut Pipeline,
+    ) -> Result<Option<Pending>> {
+        let fs_meta = self.child_metadata(walker, ch, p)?;
+
+        let frame = walker.frames.last().unwrap();
+        let is_dir = fs_meta.is_dir();
+        // マッチするパターンが無ければ親ディレクトリの判定に従う
+        let ignored = match frame.ignore_stack.explain_entry(p, &fs_meta)? {
+            Some((_, pattern)) => !pattern.is_allow(),
+            None => frame.dir.pruned,
+        };
+        // 除外されたディレクトリでも、中のエントリの除外が取り消されうるなら走査する
+        let pruned = ignored && is_dir && frame.ignore_stack.may_allow_inside(p)?;
+        if ignored && !pruned {
+            trace!("ignore {:?}", p);
+            return Ok(None);
+        }
+
+        let last_entry = frame.old_entry.find_child(name).cloned();
+        self.scan_node(walker, p, fs_meta, last_entry.as_ref(), pruned, pipeline)
+    }
+
+    // `read_dir`で得たエントリのメタデータを返す。
+    //
+    // シンボリックリンクを辿る場合は、祖先のディレクトリを指していない限りリンク先のメタデータを返す。
+    fn child_metadata(&self, walker: &Walker, ch: &fs::DirEntry, p: &Path) -> Result<fs::Metadata> {
+        let fs_meta = ch.metadata()?;
+        if !(self.follow_symlinks && fs_meta.file_type().is_symlink()) {
+            return Ok(fs_meta);
+        }
+
+        match fs::metadata(p) {
+            Ok(target_meta)
+                if target_meta.is_dir()
+                    && walker.ancestors.contains(&dir_key(p, &target_meta)?) =>
             {
-                builder.append(ch_hash);
+                info!("keep symlink {:?} to avoid loop", p);
+                Ok(fs_meta)
+            }
+            Ok(target_meta) => Ok(target_meta),
+            Err(e) => {
+                info!("keep broken symlink {:?}: {}", p, e);
+                Ok(fs_meta)
             }
         }
-        trace!("finish scan dir children: {:?}", p);
+    }
 
-        let mut entry = builder.build();
+    // 全ての子エントリが揃ったディレクトリのエントリを、深い方から順に生成して保存する。
+    fn build_tree(&self, root: PendingDir, pipeline: &mut Pipeline) -> Result<FsHash> {
+        let mut stack = vec![BuildFrame::new(root)];
 
-        trace!("start encode dir entry {:?}", p);
-        let mut encoded = Vec::<u8>::new();
-        to_writer(&mut encoded, &entry)?;
+        loop {
+            let frame = stack.last_mut().unwrap();
+            match frame.children.next() {
+                Some(Pending::Ready(hash)) => {
+                    if let FsHash::File(_) = hash {
+                        self.stats_lock().files += 1;
+                    }
+                    frame.builder.append(hash);
+                }
+                Some(Pending::File(index)) => {
+                    if let Some(hash) = pipeline.hashes[index].take().flatten() {
+                        self.stats_lock().files += 1;
+                        frame.builder.append(hash);
+                    }
+                }
+                Some(Pending::Dir(dir)) => stack.push(BuildFrame::new(dir)),
+                Some(Pending::Preview(_)) => {}
+                None => {
+                    let frame = stack.pop().unwrap();
+                    let hash = self.save_dir(&frame.path, frame.builder)?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.builder.append(hash),
+                        None => return Ok(hash),
+                    }
+                }
+            }
+        }
+    }
 
-        trace!("start hash dir entry {:?}", p);
-        let (id, temp) = hash_reader(encoded.as_slice())?;
-        trace!("start save dir entry {:?} = {}", p, id);
-        self.bank.save_object(&id, temp)?;
+    fn save_dir(&self, p: &Path, builder: DirEntryBuilder) -> Result<FsHash> {
+        let mut entry = builder.build();
+
+        trace!("start save dir entry {:?}", p);
+        let (id, stored) = self.bank.save_dir_entry(&entry, self.is_dry_run())?;
         trace!("dir entry saved {:?} = {}", p, id);
 
+        let mut stats = self.stats_lock();
+        stats.dirs += 1;
+        stats.new_bytes += stored;
+        drop(stats);
+
         entry.set_id(id);
 
         Ok(FsHash::try_from(entry).unwrap())
     }
 
-    fn scan_file(
-        &self,
-        p: &Path,
-        attr: Attributes,
-        last_entry: Option<FileHash>,
-    ) -> Result<FsHash> {
-        trace!("scan file {:?}", p);
-
-        if let Some(last_entry) = last_entry {
-            if last_entry.attr().modified() == attr.modified() {
-                trace!("skip scan file {:?}", p);
-                return Ok(last_entry.into());
-            }
-        }
+    // ファイルの内容のスキャンをワーカースレッドに依頼し、結果の受け取り先の番号を返す。
+    //
+    // 依頼の待ち行列が一杯の場合は、空くまで待つ。
+    fn submit_file(&self, pipeline: &mut Pipeline, job: FileJob) -> Result<usize> {
+        let index = pipeline.hashes.len();
+        pipeline.hashes.push(None);
 
-        let mut entry = FileEntry::new(attr);
+        let jobs = pipeline.jobs.as_ref().unwrap();
+        if let Err(mpsc::SendError((_, job))) = jobs.send((index, job)) {
+            // ワーカースレッドは全て終了している
+            panic!("file scan workers stopped before {:?}", job.path);
+        }
 
-        trace!("start scan file {:?}", p);
-        let mut f = fs::File::open(p)?;
-        let id = hash_file(&mut f)?;
-        trace!("file hash {:?} = {}", p, id);
-        trace!("start save file object {}", id);
-        self.bank.save_object(&id, f)?;
-        trace!("finish save file object {}", id);
+        while let Ok(received) = pipeline.results.try_recv() {
+            self.receive_file(pipeline, received)?;
+        }
+        Ok(index)
+    }
 
-        entry.set_id(id);
+    // 依頼した全てのファイルのスキャンの完了を待つ。
+    fn wait_files(&self, pipeline: &mut Pipeline) -> Result<()> {
+        pipeline.jobs = None;
+        while let Ok(received) = pipeline.results.recv() {
+            self.receive_file(pipeline, received)?;
+        }
+        Ok(())
+    }
 
-        Ok(FsHash::try_from(entry).unwrap())
+    fn receive_file(&self, pipeline: &mut Pipeline, received: FileResult) -> Result<()> {
+        let (index, path, result) = received;
+        let stored = self.recover(&path, result.map(Some))?;
+        pipeline.hashes[index] = Some(stored.map(|(hash, len, stored)| {
+            self.progress.add_file(len);
+            let mut stats = self.stats_lock();
+            stats.bytes_read += len;
+            stats.new_bytes += stored;
+            hash
+        }));
+        Ok(())
     }
 
     fn scan_symlink(&self, p: &Path, attr: Attributes) -> Result<FsHash> {
@@ -200,14 +1017,15 @@ impl<'a> Scanner<'a> {
         let mut entry = SymlinkEntry::new(attr, target_path_str, target_meta.is_dir());
 
         trace!("start encode dir entry {:?}", p);
-        let mut encoded = Vec::<u8>::new();
-        to_writer(&mut encoded, &entry)?;
+        let encoded = self.bank.entry_encoding().encode(&entry)?;
 
         trace!("start hash symlink entry {:?}", p);
         let (id, temp) = hash_reader(encoded.as_slice())?;
-        trace!("start save symlink entry {:?} = {}", p, id);
-        self.bank.save_object(&id, temp)?;
-        trace!("symlink entry saved {:?} = {}", p, id);
+        if !self.is_dry_run() {
+            trace!("start save symlink entry {:?} = {}", p, id);
+            self.stats_lock().new_bytes += self.bank.save_object(&id, temp)?;
+            trace!("symlink entry saved {:?} = {}", p, id);
+        }
 
         entry.set_id(id);
 
@@ -215,16 +1033,173 @@ impl<'a> Scanner<'a> {
     }
 }
 
-fn convert_metadata(path: &Path, fs_meta: &fs::Metadata) -> Result<Attributes> {
+// ファイルの内容のハッシュを計算して保存し、そのエントリとファイルサイズ、新たに書き込んだバイト数を返す。
+//
+// `streams`が`true`であれば、代替データストリームも保存する。ファイルサイズにはストリームの分も含む。
+fn store_file(bank: &Bank, dry_run: bool, streams: bool, job: &FileJob) -> Result<StoredFile> {
+    let p = &job.path;
+    let mut entry = FileEntry::new(job.attr.clone());
+
+    trace!("start scan file {:?}", p);
+    let (id, mut len, mut stored) = store_content(bank, dry_run, p)?;
+    entry.set_id(id);
+
+    if streams {
+        let mut data_streams = Vec::new();
+        for name in list_streams(p)? {
+            let (id, stream_len, stream_stored) =
+                store_content(bank, dry_run, &stream_path(p, &name))?;
+            trace!("stream {:?}:{} = {}", p, name, id);
+            len += stream_len;
+            stored += stream_stored;
+            data_streams.push(DataStream::new(name, id));
+        }
+        entry.set_streams(data_streams);
+    }
+
+    Ok((FsHash::try_from(entry).unwrap(), len, stored))
+}
+
+// `p`の内容のハッシュを計算して保存し、そのハッシュ値とバイト数、新たに書き込んだバイト数を返す。
+fn store_content(bank: &Bank, dry_run: bool, p: &Path) -> Result<(HashID, u64, u64)> {
+    let mut f = fs::File::open(p)?;
+    let len = f.metadata()?.len();
+    let id = hash_file(&mut f)?;
+    trace!("file hash {:?} = {}", p, id);
+    let stored = if dry_run {
+        0
+    } else {
+        trace!("start save file object {}", id);
+        let stored = bank.save_object(&id, f)?;
+        trace!("finish save file object {}", id);
+        stored
+    };
+    Ok((id, len, stored))
+}
+
+/// ファイル`p`の代替データストリーム`name`を読み書きするためのパスを返す。
+pub(crate) fn stream_path(p: &Path, name: &str) -> PathBuf {
+    let mut path = p.as_os_str().to_owned();
+    path.push(":");
+    path.push(name);
+    PathBuf::from(path)
+}
+
+// `FindFirstStreamW`が返すストリーム名(`:name:$DATA`)から名前を取り出す。
+//
+// 本体の内容(`::$DATA`)やデータ以外のストリームは`None`になる。
+#[cfg_attr(not(windows), allow(dead_code))]
+fn parse_stream_name(raw: &str) -> Option<&str> {
+    let name = raw.strip_prefix(':')?.strip_suffix(":$DATA")?;
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+// ファイル`p`の代替データストリームの名前を列挙する。
+#[cfg(windows)]
+fn list_streams(p: &Path) -> Result<Vec<String>> {
+    use std::ffi::OsStr;
+    use std::mem;
+    use std::os::windows::ffi::OsStrExt;
+
+    use winapi::shared::winerror::{ERROR_HANDLE_EOF, ERROR_NO_MORE_FILES};
+    use winapi::um::fileapi::{FindClose, FindFirstStreamW, FindNextStreamW};
+    use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+    use winapi::um::minwinbase::{FindStreamInfoStandard, WIN32_FIND_STREAM_DATA};
+
+    let wide: Vec<u16> = OsStr::new(p).encode_wide().chain(Some(0)).collect();
+    let mut data: WIN32_FIND_STREAM_DATA = unsafe { mem::zeroed() };
+    let handle = unsafe {
+        FindFirstStreamW(
+            wide.as_ptr(),
+            FindStreamInfoStandard,
+            &mut data as *mut _ as _,
+            0,
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        let e = io::Error::last_os_error();
+        // ストリームを持たないファイルシステムでは無いものとして扱う
+        return match e.raw_os_error() {
+            Some(c) if c == ERROR_HANDLE_EOF as i32 => Ok(Vec::new()),
+            _ => Err(e.into()),
+        };
+    }
+
+    let mut names = Vec::new();
+    let result = loop {
+        let len = data
+            .cStreamName
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(data.cStreamName.len());
+        let raw = String::from_utf16_lossy(&data.cStreamName[..len]);
+        if let Some(name) = parse_stream_name(&raw) {
+            names.push(name.to_owned());
+        }
+        if unsafe { FindNextStreamW(handle, &mut data as *mut _ as _) } == 0 {
+            let e = io::Error::last_os_error();
+            break match e.raw_os_error() {
+                Some(c) if c == ERROR_HANDLE_EOF as i32 || c == ERROR_NO_MORE_FILES as i32 => {
+                    Ok(names)
+                }
+                _ => Err(e.into()),
+            };
+        }
+    };
+    unsafe { FindClose(handle) };
+    result
+}
+
+#[cfg(not(windows))]
+fn list_streams(_p: &Path) -> Result<Vec<String>> {
+    Ok(Vec::new())
+}
+
+#[cfg(unix)]
+fn dir_key(_p: &Path, meta: &fs::Metadata) -> Result<DirKey> {
+    use std::os::unix::fs::MetadataExt;
+
+    Ok(DirKey((meta.dev(), meta.ino())))
+}
+
+#[cfg(not(unix))]
+fn dir_key(p: &Path, _meta: &fs::Metadata) -> Result<DirKey> {
+    Ok(DirKey(fs::canonicalize(p)?))
+}
+
+pub(crate) fn convert_metadata(path: &Path, fs_meta: &fs::Metadata) -> Result<Attributes> {
     if let Some(name) = path.file_name() {
         let readonly = fs_meta.permissions().readonly();
         let timestamp = fs_meta.modified()?.try_into()?;
+        // 作成日時はプラットフォームやファイルシステムが対応している場合のみ記録する
+        let created = fs_meta.created().ok().and_then(|t| t.try_into().ok());
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::MetadataExt;
+            Some(fs_meta.mode())
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+        #[cfg(windows)]
+        let file_attributes = {
+            use std::os::windows::fs::MetadataExt;
+            Some(fs_meta.file_attributes())
+        };
+        #[cfg(not(windows))]
+        let file_attributes = None;
 
         let name = name
             .to_str()
             .ok_or_else(|| Error::NameIsInvalidUnicode(path.to_owned()))?;
 
-        Ok(Attributes::new(name.to_owned(), readonly, timestamp))
+        Ok(Attributes::new(name.to_owned(), readonly, timestamp)
+            .with_created(created)
+            .with_mode(mode)
+            .with_file_attributes(file_attributes))
     } else {
         Err(Error::NameIsEmpty(path.to_owned()))
     }
@@ -234,37 +1209,56 @@ fn convert_metadata(path: &Path, fs_meta: &fs::Metadata) -> Result<Attributes> {
 type Result<T> = std::result::Result<T, Error>;
 
 /// ファイルシステムのスキャンで発生しうるエラー
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
     /// エントリのJSONへのエンコードの失敗
-    #[fail(display = "failed parse FsEntry: {}", _0)]
-    Encode(#[fail(cause)] serde_json::Error),
+    Encode(serde_json::Error),
 
     /// 除外判定に失敗した。
-    #[fail(display = "failed load ignore patterns: {}", _0)]
-    Ignore(#[fail(cause)] ignore::Error),
+    Ignore(ignore::Error),
 
     /// 入出力エラー
-    #[fail(display = "failed scan with IO error: {}", _0)]
-    IO(#[fail(cause)] io::Error),
+    IO(io::Error),
 
     /// 名前が空文字列である要素を発見した
-    #[fail(display = "found empty name entry at {:?}", _0)]
     NameIsEmpty(PathBuf),
 
     /// パスがUnicodeで表現できない
-    #[fail(display = "found empty name entry at {:?}", _0)]
     NameIsInvalidUnicode(PathBuf),
 
     /// リポジトリ操作エラーが発生
-    #[fail(display = "{}", _0)]
-    Repo(#[fail(cause)] repo::Error),
+    Repo(repo::Error),
 
     /// 対応範囲外のタイムスタンプを検出
-    #[fail(display = "timestamp is older than UNIX epoch")]
     Timestamp,
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Encode(e) => write!(f, "failed parse FsEntry: {}", e),
+            Error::Ignore(e) => write!(f, "failed load ignore patterns: {}", e),
+            Error::IO(e) => write!(f, "failed scan with IO error: {}", e),
+            Error::NameIsEmpty(path) => write!(f, "found empty name entry at {:?}", path),
+            Error::NameIsInvalidUnicode(path) => write!(f, "found empty name entry at {:?}", path),
+            Error::Repo(e) => write!(f, "{}", e),
+            Error::Timestamp => write!(f, "timestamp is older than UNIX epoch"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Encode(e) => Some(e),
+            Error::Ignore(e) => Some(e),
+            Error::IO(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl From<ignore::Error> for Error {
     fn from(e: ignore::Error) -> Error {
         Error::Ignore(e)
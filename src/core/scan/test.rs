@@ -0,0 +1,636 @@
+use super::*;
+
+use tempfile::tempdir;
+
+use crate::core::ignore::pattern::parse_lines;
+use crate::core::repo::Repository;
+use crate::core::timestamp::Timestamp;
+
+fn children(bank: &Bank, hash: &FsHash) -> Vec<String> {
+    let dir: DirEntry = bank.load_entry(&hash.id()).unwrap();
+    dir.children()
+        .map(|ch| ch.attr().name().to_owned())
+        .collect()
+}
+
+#[test]
+fn test_scan_threads_same_id() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("sub")).unwrap();
+    for i in 0..8 {
+        fs::write(target.join(format!("file{}.txt", i)), format!("data {}", i)).unwrap();
+        fs::write(
+            target.join(format!("sub/file{}.txt", i)),
+            format!("sub {}", i),
+        )
+        .unwrap();
+    }
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let single = Scanner::new(&bank).scan().unwrap();
+    let multi = ScannerBuilder::new(&bank)
+        .threads(4)
+        .build()
+        .scan()
+        .unwrap();
+
+    assert_eq!(single, multi);
+    assert_eq!(children(&bank, &multi).len(), 9);
+}
+
+#[test]
+fn test_scan_pipeline_nested_dirs() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    for d in 0..5 {
+        let dir = target.join(format!("d{}/e{}", d, d));
+        fs::create_dir_all(&dir).unwrap();
+        for i in 0..d * 3 {
+            fs::write(dir.join(format!("f{}.txt", i)), format!("{} {}", d, i)).unwrap();
+        }
+    }
+    fs::write(target.join("top.txt"), "top").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let single = Scanner::new(&bank).scan().unwrap();
+    let multi = ScannerBuilder::new(&bank)
+        .threads(3)
+        .build()
+        .scan()
+        .unwrap();
+    assert_eq!(single, multi);
+
+    let files = bank
+        .walk(
+            &bank
+                .save_history(multi.id(), Timestamp::from(1), None)
+                .unwrap(),
+        )
+        .map(|e| e.unwrap())
+        .filter(|(path, _)| !path.is_dir())
+        .count();
+    assert_eq!(files, 1 + 3 + 6 + 9 + 12);
+}
+
+#[test]
+fn test_scan_max_size_and_extra_patterns() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("small.txt"), "small").unwrap();
+    fs::write(target.join("large.bin"), vec![0u8; 2048]).unwrap();
+    fs::write(target.join("skip.log"), "log").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let hash = ScannerBuilder::new(&bank)
+        .max_size(Some(Size::from(1024)))
+        .extra_patterns(parse_lines(["*.log"]).unwrap())
+        .build()
+        .scan()
+        .unwrap();
+
+    assert_eq!(children(&bank, &hash), vec!["small.txt".to_owned()]);
+}
+
+#[test]
+fn test_scan_dry_run_writes_nothing() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("a.txt"), "a").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let dry = ScannerBuilder::new(&bank)
+        .dry_run(true)
+        .build()
+        .scan()
+        .unwrap();
+    assert!(bank.load_entry::<DirEntry>(&dry.id()).is_err());
+
+    let hash = Scanner::new(&bank).scan().unwrap();
+    assert_eq!(dry, hash);
+    assert!(bank.load_entry::<DirEntry>(&hash.id()).is_ok());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_scan_follow_symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let outside = temp.path().join("outside");
+    fs::create_dir_all(target.join("sub")).unwrap();
+    fs::create_dir_all(&outside).unwrap();
+    fs::write(outside.join("o.txt"), "outside").unwrap();
+    symlink(&outside, target.join("linked")).unwrap();
+    symlink(&target, target.join("sub/loop")).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let hash = ScannerBuilder::new(&bank)
+        .follow_symlinks(true)
+        .build()
+        .scan()
+        .unwrap();
+
+    let root: DirEntry = bank.load_entry(&hash.id()).unwrap();
+    let linked = root.find_child("linked").unwrap();
+    assert!(matches!(linked, FsHash::Dir(_)));
+    assert_eq!(children(&bank, linked), vec!["o.txt".to_owned()]);
+
+    let sub = root.find_child("sub").unwrap();
+    let sub: DirEntry = bank.load_entry(&sub.id()).unwrap();
+    assert!(matches!(sub.find_child("loop"), Some(FsHash::Symlink(_))));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_scan_dir_only_pattern_with_symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let outside = temp.path().join("outside");
+    fs::create_dir_all(&target).unwrap();
+    fs::create_dir_all(&outside).unwrap();
+    fs::write(outside.join("o.txt"), "outside").unwrap();
+    fs::write(target.join(".sbakignore"), "cache/\nfile/\n").unwrap();
+    symlink(&outside, target.join("cache")).unwrap();
+    symlink(outside.join("o.txt"), target.join("file")).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    // 辿らない場合、ディレクトリへのリンクはディレクトリとして扱わない
+    let hash = Scanner::new(&bank).scan().unwrap();
+    assert_eq!(
+        children(&bank, &hash),
+        vec![
+            ".sbakignore".to_owned(),
+            "cache".to_owned(),
+            "file".to_owned()
+        ]
+    );
+
+    // 辿る場合はリンク先の種類で判定する
+    let hash = ScannerBuilder::new(&bank)
+        .follow_symlinks(true)
+        .build()
+        .scan()
+        .unwrap();
+    assert_eq!(
+        children(&bank, &hash),
+        vec![".sbakignore".to_owned(), "file".to_owned()]
+    );
+}
+
+#[test]
+fn test_scan_nested_ignore_files() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("a/b/cache")).unwrap();
+    fs::create_dir_all(target.join("cache")).unwrap();
+    fs::write(target.join(".sbakignore"), "cache/\n*.log\n").unwrap();
+    fs::write(target.join("a/.sbakignore"), "x.txt\n").unwrap();
+    fs::write(target.join("a/b/.sbakignore"), "!keep.log\n").unwrap();
+    fs::write(target.join("a/cache"), "file named cache").unwrap();
+    fs::write(target.join("a/x.txt"), "x").unwrap();
+    fs::write(target.join("a/b/keep.log"), "keep").unwrap();
+    fs::write(target.join("a/b/drop.log"), "drop").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let hash = Scanner::new(&bank).scan().unwrap();
+    let history = bank
+        .save_history(hash.id(), Timestamp::from(1), None)
+        .unwrap();
+    let paths = bank
+        .walk(&history)
+        .map(|e| e.unwrap().0.to_string())
+        .filter(|p| !p.ends_with(".sbakignore"))
+        .collect::<Vec<_>>();
+
+    assert_eq!(paths, vec!["a", "a/b", "a/b/keep.log", "a/cache"]);
+}
+
+#[test]
+fn test_scan_deep_tree_and_max_depth() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let mut deepest = target.clone();
+    for _ in 0..300 {
+        deepest.push("d");
+    }
+    fs::create_dir_all(&deepest).unwrap();
+    fs::write(deepest.join("leaf.txt"), "leaf").unwrap();
+    fs::write(target.join("d/d/top.txt"), "top").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let hash = Scanner::new(&bank).scan().unwrap();
+    let history = bank
+        .save_history(hash.id(), Timestamp::from(1), None)
+        .unwrap();
+    let depths = bank
+        .walk(&history)
+        .map(|e| e.unwrap().0.depth())
+        .collect::<Vec<_>>();
+    assert_eq!(depths.iter().max(), Some(&301));
+
+    let hash = ScannerBuilder::new(&bank)
+        .max_depth(Some(2))
+        .build()
+        .scan()
+        .unwrap();
+    let history = bank
+        .save_history(hash.id(), Timestamp::from(2), None)
+        .unwrap();
+    let paths = bank
+        .walk(&history)
+        .map(|e| e.unwrap().0.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(paths, vec!["d", "d/d", "d/d/top.txt"]);
+}
+
+#[test]
+fn test_scan_repository_ignore_file() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("Thumbs.db"), "junk").unwrap();
+    fs::write(target.join(".DS_Store"), "junk").unwrap();
+    fs::write(target.join("a.txt"), "a").unwrap();
+
+    let repo_path = temp.path().join("repo");
+    let repo = Repository::create(&repo_path).unwrap();
+    fs::write(repo_path.join("ignore"), "Thumbs.db\n.DS_Store\n*.txt\n").unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    fs::write(repo_path.join("banks/bank/ignore"), "!a.txt\n").unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    // 設定ファイルの除外パターンはリポジトリ共通の除外リストより優先される
+    let hash = ScannerBuilder::new(&bank)
+        .config_patterns(parse_lines(["!.DS_Store"]).unwrap())
+        .build()
+        .scan()
+        .unwrap();
+    assert_eq!(
+        children(&bank, &hash),
+        vec![".DS_Store".to_owned(), "a.txt".to_owned()]
+    );
+}
+
+#[test]
+fn test_scan_reinclude_in_ignored_dir() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("build/out")).unwrap();
+    fs::create_dir_all(target.join("build/empty")).unwrap();
+    fs::create_dir_all(target.join("tmp")).unwrap();
+    fs::write(target.join("build/keep.txt"), "keep").unwrap();
+    fs::write(target.join("build/drop.txt"), "drop").unwrap();
+    fs::write(target.join("build/out/a.o"), "a").unwrap();
+    fs::write(target.join("build/out/note.md"), "note").unwrap();
+    fs::write(target.join("tmp/note.md"), "tmp").unwrap();
+    fs::write(
+        target.join(".sbakignore"),
+        "build/\n!build/keep.txt\n!build/**/*.md\ntmp/\n",
+    )
+    .unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let hash = Scanner::new(&bank).scan().unwrap();
+    let history = bank
+        .save_history(hash.id(), Timestamp::from(1), None)
+        .unwrap();
+    let paths = bank
+        .walk(&history)
+        .map(|e| e.unwrap().0.to_string())
+        .filter(|p| !p.ends_with(".sbakignore"))
+        .collect::<Vec<_>>();
+
+    // 除外を取り消されたエントリを含まないディレクトリは記録しない
+    assert_eq!(
+        paths,
+        vec!["build", "build/out", "build/out/note.md", "build/keep.txt"]
+    );
+}
+
+#[test]
+fn test_scan_predicates() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("big")).unwrap();
+    fs::write(target.join("small.iso"), vec![0u8; 10]).unwrap();
+    fs::write(target.join("large.iso"), vec![0u8; 2048]).unwrap();
+    fs::write(target.join("large.txt"), vec![0u8; 2048]).unwrap();
+    fs::write(target.join("big/keep.iso"), vec![0u8; 4096]).unwrap();
+    fs::write(
+        target.join(".sbakignore"),
+        "*.iso size>1K\n!keep.iso\nmtime<1990-01-01\n",
+    )
+    .unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let hash = Scanner::new(&bank).scan().unwrap();
+    let history = bank
+        .save_history(hash.id(), Timestamp::from(1), None)
+        .unwrap();
+    let paths = bank
+        .walk(&history)
+        .map(|e| e.unwrap().0.to_string())
+        .filter(|p| !p.ends_with(".sbakignore"))
+        .collect::<Vec<_>>();
+    assert_eq!(paths, vec!["big", "big/keep.iso", "large.txt", "small.iso"]);
+
+    let check = Scanner::new(&bank)
+        .check_ignore(&bank.target_path().join("large.iso"))
+        .unwrap()
+        .unwrap();
+    assert!(check.is_ignored());
+    assert_eq!(check.pattern().origin().unwrap().text(), "*.iso size>1K");
+}
+
+#[test]
+fn test_check_ignore() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("cache/keep")).unwrap();
+    fs::create_dir_all(target.join("src")).unwrap();
+    fs::write(target.join(".sbakignore"), "cache/\n*.log\n").unwrap();
+    fs::write(target.join("src/.sbakignore"), "!*.log\n").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let target = bank.target_path().to_owned();
+    let scanner = ScannerBuilder::new(&bank)
+        .extra_patterns(parse_lines(["*.tmp"]).unwrap().with_source("--exclude"))
+        .build();
+
+    // 祖先のディレクトリが除外される場合はそのディレクトリの判定になる
+    let check = scanner
+        .check_ignore(&target.join("cache/keep/a.txt"))
+        .unwrap()
+        .unwrap();
+    assert!(check.is_ignored());
+    assert_eq!(check.path(), target.join("cache"));
+    assert_eq!(check.base_dir(), target);
+    assert_eq!(check.pattern().origin().unwrap().line(), 1);
+
+    let check = scanner
+        .check_ignore(&target.join("src/a.log"))
+        .unwrap()
+        .unwrap();
+    assert!(!check.is_ignored());
+    assert_eq!(check.base_dir(), target.join("src"));
+
+    let check = scanner
+        .check_ignore(&target.join("a.tmp"))
+        .unwrap()
+        .unwrap();
+    assert!(check.is_ignored());
+    assert_eq!(check.pattern().origin().unwrap().source(), "--exclude");
+
+    // 除外されたディレクトリの中で、どのパターンにもマッチしないものは親ディレクトリの判定に従う
+    fs::write(target.join(".sbakignore"), "cache/\n*.log\n!cache/keep/\n").unwrap();
+    let scanner = Scanner::new(&bank);
+    let check = scanner
+        .check_ignore(&target.join("cache/a.txt"))
+        .unwrap()
+        .unwrap();
+    assert!(check.is_ignored());
+    assert_eq!(check.path(), target.join("cache"));
+    let check = scanner
+        .check_ignore(&target.join("cache/keep/a.txt"))
+        .unwrap()
+        .unwrap();
+    assert!(!check.is_ignored());
+    assert_eq!(check.path(), target.join("cache/keep"));
+
+    assert!(scanner
+        .check_ignore(&target.join("src/a.txt"))
+        .unwrap()
+        .is_none());
+    assert!(scanner.check_ignore(temp.path()).is_err());
+}
+
+#[test]
+fn test_scan_records_created() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("a.txt"), "a").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    // 作成日時を取得できない環境では記録されない
+    let created = fs::metadata(target.join("a.txt"))
+        .unwrap()
+        .created()
+        .ok()
+        .map(|t| Timestamp::try_from(t).unwrap());
+    let hash = Scanner::new(&bank).scan().unwrap();
+    let root: DirEntry = bank.load_entry(&hash.id()).unwrap();
+    assert_eq!(root.find_child("a.txt").unwrap().attr().created(), created);
+
+    // 作成日時の無い古い形式のエントリも読み込める
+    let attr: Attributes =
+        serde_json::from_str(r#"{"name":"a.txt","readonly":false,"modified":1}"#).unwrap();
+    assert_eq!(attr.created(), None);
+    assert_eq!(
+        serde_json::to_string(&attr).unwrap(),
+        r#"{"name":"a.txt","readonly":false,"modified":1}"#
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_scan_records_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("a.sh"), "a").unwrap();
+    fs::set_permissions(target.join("a.sh"), fs::Permissions::from_mode(0o2750)).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let hash = Scanner::new(&bank).scan().unwrap();
+    let root: DirEntry = bank.load_entry(&hash.id()).unwrap();
+    assert_eq!(root.find_child("a.sh").unwrap().attr().mode(), Some(0o2750));
+
+    // 内容が変わらなくてもパーミッションの変更は記録される
+    fs::set_permissions(target.join("a.sh"), fs::Permissions::from_mode(0o640)).unwrap();
+    let hash = Scanner::new(&bank).scan().unwrap();
+    let root: DirEntry = bank.load_entry(&hash.id()).unwrap();
+    assert_eq!(root.find_child("a.sh").unwrap().attr().mode(), Some(0o640));
+}
+
+#[test]
+fn test_attributes_file_attributes() {
+    // 隠しファイル、読み込み専用、ディレクトリ、アーカイブ
+    let attr = Attributes::new("a.txt".to_owned(), true, Timestamp::from(1))
+        .with_file_attributes(Some(0x2 | 0x1 | 0x10 | 0x20));
+    assert_eq!(attr.file_attributes(), Some(0x2 | 0x20));
+
+    let json = serde_json::to_string(&attr).unwrap();
+    assert_eq!(
+        json,
+        r#"{"name":"a.txt","readonly":true,"modified":1,"file_attributes":34}"#
+    );
+    assert_eq!(serde_json::from_str::<Attributes>(&json).unwrap(), attr);
+}
+
+#[test]
+fn test_scan_stats() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("sub")).unwrap();
+    fs::write(target.join("a.txt"), "aaaa").unwrap();
+    fs::write(target.join("sub/b.txt"), "bb").unwrap();
+    fs::write(target.join("sub/c.txt"), "aaaa").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let scanner = Scanner::new(&bank);
+    let hash = scanner.scan().unwrap();
+    let stats = scanner.stats();
+    assert_eq!(stats.files, 3);
+    assert_eq!(stats.dirs, 2);
+    assert_eq!(stats.bytes_read, 10);
+    // 同じ内容のファイルは1度だけ保存される
+    let entry_bytes: u64 = repo
+        .object_ids()
+        .unwrap()
+        .iter()
+        .map(|id| repo.object_size(id).unwrap())
+        .sum();
+    assert_eq!(stats.new_bytes, entry_bytes);
+    assert_eq!(stats.errors, 0);
+
+    let history = bank
+        .save_history(hash.id(), Timestamp::from(1), Some(stats.clone()))
+        .unwrap();
+    assert_eq!(history.stats(), Some(&stats));
+    assert_eq!(bank.last_scan().unwrap().unwrap().stats(), Some(&stats));
+
+    // 変更が無ければ何も読み込まず、何も保存しない
+    let scanner = Scanner::new(&bank);
+    scanner.scan().unwrap();
+    let stats = scanner.stats();
+    assert_eq!(stats.files, 3);
+    assert_eq!(stats.dirs, 2);
+    assert_eq!(stats.bytes_read, 0);
+    assert_eq!(stats.new_bytes, 0);
+}
+
+#[test]
+fn test_preview() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("old")).unwrap();
+    fs::write(target.join("keep.txt"), "keep").unwrap();
+    fs::write(target.join("mod.txt"), "mod").unwrap();
+    fs::write(target.join("gone.txt"), "gone").unwrap();
+    fs::write(target.join("old/x.txt"), "xx").unwrap();
+    fs::write(target.join("old/y.txt"), "yyy").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let hash = Scanner::new(&bank).scan().unwrap();
+    bank.save_history(hash.id(), Timestamp::from(1), None)
+        .unwrap();
+    let objects = repo.object_ids().unwrap().len();
+
+    fs::write(target.join("mod.txt"), "modified").unwrap();
+    filetime::set_file_mtime(
+        target.join("mod.txt"),
+        filetime::FileTime::from_unix_time(1_000_000, 0),
+    )
+    .unwrap();
+    fs::remove_file(target.join("gone.txt")).unwrap();
+    fs::rename(target.join("old"), target.join("moved")).unwrap();
+    fs::write(target.join("new.txt"), "new").unwrap();
+
+    let changes = Scanner::new(&bank)
+        .preview()
+        .unwrap()
+        .into_iter()
+        .map(|c| {
+            let path = c.path().strip_prefix(&target).unwrap().to_owned();
+            (
+                c.kind(),
+                path.to_string_lossy().replace('\\', "/"),
+                c.size(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        vec![
+            (ChangeKind::Removed, "gone.txt".to_owned(), 4),
+            (ChangeKind::Updated, "mod.txt".to_owned(), 8),
+            (ChangeKind::Added, "moved/x.txt".to_owned(), 2),
+            (ChangeKind::Added, "moved/y.txt".to_owned(), 3),
+            (ChangeKind::Added, "new.txt".to_owned(), 3),
+            (ChangeKind::Removed, "old/x.txt".to_owned(), 2),
+            (ChangeKind::Removed, "old/y.txt".to_owned(), 3),
+        ]
+    );
+
+    // リポジトリには何も書き込まない
+    assert_eq!(repo.object_ids().unwrap().len(), objects);
+}
+
+#[test]
+fn test_parse_stream_name() {
+    assert_eq!(
+        parse_stream_name(":Zone.Identifier:$DATA"),
+        Some("Zone.Identifier")
+    );
+    assert_eq!(parse_stream_name("::$DATA"), None);
+    assert_eq!(parse_stream_name(":summary:$OTHER"), None);
+    assert_eq!(
+        stream_path(
+            Path::new("dir").join("file.txt").as_path(),
+            "Zone.Identifier"
+        ),
+        Path::new("dir").join("file.txt:Zone.Identifier")
+    );
+}
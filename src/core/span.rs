@@ -0,0 +1,76 @@
+//! 処理単位ごとの開始・終了のログ出力
+//!
+//! [`Span`](struct.Span.html)は生成時に開始を、ドロップ時に経過時間付きで終了をログに記録する。
+//! ログのターゲットは`sbak::span::<処理名>`になるため、`log`クレートのロガー側で処理単位ごとに絞り込める。
+//! メッセージは`key=value`を空白区切りで並べた形式になる。
+
+use std::fmt::Write;
+use std::time::Instant;
+
+use log::{log, Level};
+
+#[cfg(test)]
+mod test;
+
+/// 1つの処理単位
+#[derive(Debug)]
+pub struct Span {
+    target: String,
+    fields: String,
+    start: Instant,
+    failed: bool,
+}
+
+impl Span {
+    /// `name`の処理の開始を記録する。
+    ///
+    /// `fields`は処理を識別するための`(キー, 値)`の並びである。
+    pub fn enter(name: &str, fields: &[(&str, &dyn std::fmt::Display)]) -> Span {
+        let mut s = String::new();
+        for (key, value) in fields {
+            if !s.is_empty() {
+                s.push(' ');
+            }
+            write!(s, "{}={}", key, value).unwrap();
+        }
+
+        let span = Span {
+            target: format!("sbak::span::{}", name),
+            fields: s,
+            start: Instant::now(),
+            failed: false,
+        };
+        log!(target: &span.target, Level::Info, "start {}", span.fields);
+        span
+    }
+
+    /// 処理が失敗したことを記録する。
+    ///
+    /// 終了時のログが`failed`になる。
+    /// エラーの内容は呼び出し側で報告されるため、ログのレベルは変えない。
+    pub fn fail(&mut self) {
+        self.failed = true;
+    }
+
+    /// 処理の結果を記録し、そのまま返す。
+    pub fn record<T, E>(&mut self, result: Result<T, E>) -> Result<T, E> {
+        if result.is_err() {
+            self.fail();
+        }
+        result
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let state = if self.failed { "failed" } else { "end" };
+        log!(
+            target: &self.target,
+            Level::Info,
+            "{} {} elapsed={:.3}s",
+            state,
+            self.fields,
+            self.start.elapsed().as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use super::*;
+
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use log::{LevelFilter, Log, Metadata, Record};
+
+lazy_static! {
+    static ref RECORDS: Mutex<Vec<(Level, String, String)>> = Mutex::new(Vec::new());
+}
+
+struct Capture;
+
+impl Log for Capture {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        if record.target().starts_with("sbak::span::test_") {
+            RECORDS.lock().unwrap().push((
+                record.level(),
+                record.target().to_owned(),
+                record.args().to_string(),
+            ));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static CAPTURE: Capture = Capture;
+
+// `target`で記録されたログを、経過時間を除いて返す。
+fn records(target: &str) -> Vec<(Level, String)> {
+    RECORDS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, t, _)| t == target)
+        .map(|(level, _, msg)| {
+            let msg = msg.split(" elapsed=").next().unwrap().to_owned();
+            (*level, msg)
+        })
+        .collect()
+}
+
+fn install() {
+    // 他のテストで設定済みの場合は無視する
+    let _ = log::set_logger(&CAPTURE);
+    log::set_max_level(LevelFilter::Trace);
+}
+
+#[test]
+fn test_span_end() {
+    install();
+    {
+        let mut span = Span::enter("test_end", &[("bank", &"home"), ("dry_run", &false)]);
+        span.record(Ok::<(), ()>(())).unwrap();
+    }
+    assert_eq!(
+        records("sbak::span::test_end"),
+        vec![
+            (Level::Info, "start bank=home dry_run=false".to_owned()),
+            (Level::Info, "end bank=home dry_run=false".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn test_span_failed() {
+    install();
+    {
+        let mut span = Span::enter("test_failed", &[("bank", &"home")]);
+        span.record(Err::<(), ()>(())).unwrap_err();
+    }
+    // 失敗してもWarnは出さず、終了の記録だけが`failed`になる
+    assert_eq!(
+        records("sbak::span::test_failed"),
+        vec![
+            (Level::Info, "start bank=home".to_owned()),
+            (Level::Info, "failed bank=home".to_owned()),
+        ]
+    );
+}
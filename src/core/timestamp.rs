@@ -1,11 +1,11 @@
 //! タイムスタンプ
 
 use std::convert::TryFrom;
+use std::error;
 use std::fmt;
-use std::time::{SystemTime, SystemTimeError, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, SystemTimeError, UNIX_EPOCH};
 
 use chrono::{Local, TimeZone, Utc};
-use failure::Fail;
 use filetime::FileTime;
 use serde::{Deserialize, Serialize};
 
@@ -13,7 +13,9 @@ use serde::{Deserialize, Serialize};
 ///
 /// [`std::time::SystemTime`](https://doc.rust-lang.org/std/time/struct.SystemTime.html) 由来の時刻をUNIX epochからの経過秒数で管理する。
 /// UNIX epochより古い時刻には対応していない。
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
 pub struct Timestamp(u64);
 
 impl Timestamp {
@@ -53,12 +55,24 @@ impl fmt::Display for Timestamp {
     }
 }
 
+impl From<u64> for Timestamp {
+    fn from(unix_epoch: u64) -> Timestamp {
+        Timestamp(unix_epoch)
+    }
+}
+
 impl Into<FileTime> for Timestamp {
     fn into(self) -> FileTime {
         FileTime::from_unix_time(self.0 as i64, 0)
     }
 }
 
+impl From<Timestamp> for SystemTime {
+    fn from(t: Timestamp) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(t.0)
+    }
+}
+
 impl Into<u64> for Timestamp {
     fn into(self) -> u64 {
         self.0
@@ -74,13 +88,22 @@ impl Into<i64> for Timestamp {
 type Result<T> = std::result::Result<T, Error>;
 
 /// タイムスタンプ操作に関わるエラー
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
     /// 対象日時のUNIX epochが負になっている
-    #[fail(display = "timestamp is older than UNIX epoch")]
     NegativeUnixTime,
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NegativeUnixTime => write!(f, "timestamp is older than UNIX epoch"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
 impl From<SystemTimeError> for Error {
     fn from(_e: SystemTimeError) -> Error {
         Error::NegativeUnixTime
@@ -0,0 +1,240 @@
+//! 古いバージョンのエントリを現行のバージョンに書き換える。
+//!
+//! エントリはハッシュ値で参照されているため、書き換えたエントリのIDは変わる。
+//! そのため親ディレクトリのエントリと履歴も合わせて書き換える。
+//! 元のエントリのオブジェクトは削除しない。
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::io;
+
+use log::{info, trace};
+use serde::Serialize;
+
+use crate::core::entry::{DirEntry, DirEntryBuilder, Entry, FsHash, SymlinkEntry, ENTRY_VERSION};
+use crate::core::hash::{self, hash_reader, HashID};
+use crate::core::repo::{self, Bank};
+use crate::core::span::Span;
+
+#[cfg(test)]
+mod test;
+
+/// エントリの書き換えを行う
+#[derive(Debug)]
+pub struct Upgrader<'a> {
+    bank: &'a Bank<'a>,
+    dry_run: bool,
+    converted: HashMap<HashID, HashID>,
+    stats: UpgradeStats,
+}
+
+impl<'a> Upgrader<'a> {
+    /// 指定した[`Bank`](../repo/struct.Bank.html)のエントリを書き換える`Upgrader`を生成する。
+    pub fn new(bank: &'a Bank) -> Upgrader<'a> {
+        Upgrader {
+            bank,
+            dry_run: false,
+            converted: HashMap::new(),
+            stats: UpgradeStats::default(),
+        }
+    }
+
+    /// 書き換えずに対象の数え上げのみを行うかどうかを設定する。
+    pub fn dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Bankの全ての履歴のエントリを現行のバージョンに書き換える。
+    pub fn upgrade(&mut self) -> Result<()> {
+        let mut span = Span::enter(
+            "upgrade",
+            &[("bank", &self.bank.name()), ("dry_run", &self.dry_run)],
+        );
+        span.record(self.upgrade_histories())
+    }
+
+    fn upgrade_histories(&mut self) -> Result<()> {
+        for history in self.bank.histories()? {
+            self.stats.histories += 1;
+
+            let root = history.id().clone();
+            let new_root = self.upgrade_dir(&root)?;
+
+            if new_root != root {
+                info!(
+                    "upgrade history {}: {} -> {}",
+                    history.timestamp(),
+                    root,
+                    new_root
+                );
+                self.stats.upgraded_histories += 1;
+                if !self.dry_run {
+                    self.bank.replace_history(&history, new_root)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// これまでの書き換えの集計を返す。
+    pub fn stats(&self) -> &UpgradeStats {
+        &self.stats
+    }
+
+    fn upgrade_dir(&mut self, id: &HashID) -> Result<HashID> {
+        if let Some(new_id) = self.converted.get(id) {
+            return Ok(new_id.clone());
+        }
+
+        trace!("check dir entry {}", id);
+        let entry: DirEntry = self.bank.load_entry(id)?;
+        self.stats.entries += 1;
+
+        let mut children = Vec::new();
+        let mut children_changed = false;
+        for ch in entry.children() {
+            let new_id = match ch {
+                FsHash::Dir(_) => self.upgrade_dir(&ch.id())?,
+                FsHash::Symlink(_) => self.upgrade_symlink(&ch.id())?,
+                FsHash::File(_) => ch.id(),
+            };
+            children_changed |= new_id != ch.id();
+            children.push(ch.with_id(new_id));
+        }
+
+        let new_id = if children_changed || entry.version() < ENTRY_VERSION {
+            let mut builder = DirEntryBuilder::new(entry.attr().clone());
+            for ch in children {
+                builder.append(ch);
+            }
+            self.save_dir(&builder.build())?
+        } else {
+            id.clone()
+        };
+
+        self.converted.insert(id.clone(), new_id.clone());
+        Ok(new_id)
+    }
+
+    fn upgrade_symlink(&mut self, id: &HashID) -> Result<HashID> {
+        if let Some(new_id) = self.converted.get(id) {
+            return Ok(new_id.clone());
+        }
+
+        trace!("check symlink entry {}", id);
+        let entry: SymlinkEntry = self.bank.load_entry(id)?;
+        self.stats.entries += 1;
+
+        let new_id = if entry.version() < ENTRY_VERSION {
+            let target = entry.target().to_string_lossy().into_owned();
+            self.save(&SymlinkEntry::new(
+                entry.attr().clone(),
+                target,
+                entry.is_dir(),
+            ))?
+        } else {
+            id.clone()
+        };
+
+        self.converted.insert(id.clone(), new_id.clone());
+        Ok(new_id)
+    }
+
+    fn save_dir(&mut self, entry: &DirEntry) -> Result<HashID> {
+        let (id, _) = self.bank.save_dir_entry(entry, self.dry_run)?;
+        self.stats.upgraded_entries += 1;
+        trace!("save upgraded dir entry {}", id);
+        Ok(id)
+    }
+
+    fn save<E: Serialize>(&mut self, entry: &E) -> Result<HashID> {
+        let encoded = self.bank.entry_encoding().encode(entry)?;
+
+        let (id, temp) = hash_reader(encoded.as_slice())?;
+        self.stats.upgraded_entries += 1;
+
+        if !self.dry_run {
+            trace!("save upgraded entry {}", id);
+            self.bank.save_object(&id, temp)?;
+        }
+
+        Ok(id)
+    }
+}
+
+/// 書き換えの集計
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpgradeStats {
+    /// 調べた履歴の数
+    pub histories: usize,
+    /// 書き換えた履歴の数
+    pub upgraded_histories: usize,
+    /// 調べたエントリの数
+    pub entries: usize,
+    /// 書き換えたエントリの数
+    pub upgraded_entries: usize,
+}
+
+#[allow(missing_docs)]
+type Result<T> = std::result::Result<T, Error>;
+
+/// エントリの書き換えで発生しうるエラー
+#[derive(Debug)]
+pub enum Error {
+    /// エントリのエンコードに失敗した
+    Encode(serde_json::Error),
+
+    /// 入出力エラーが発生した
+    IO(io::Error),
+
+    /// リポジトリ操作エラーが発生した
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Encode(e) => write!(f, "failed encode entry: {}", e),
+            Error::IO(e) => write!(f, "failed upgrade with IO error: {}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Encode(e) => Some(e),
+            Error::IO(e) => Some(e),
+            Error::Repo(e) => Some(e),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Encode(e)
+    }
+}
+
+impl From<hash::Error> for Error {
+    fn from(e: hash::Error) -> Error {
+        match e {
+            hash::Error::IO(e) => Error::IO(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IO(e)
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
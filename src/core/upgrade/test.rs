@@ -0,0 +1,104 @@
+use super::*;
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+use tempfile::tempdir;
+
+use crate::core::entry::MAX_ENTRY_VERSION;
+use crate::core::repo::Repository;
+use crate::core::scan::Scanner;
+use crate::core::timestamp::Timestamp;
+
+fn backup(repo: &Repository, target: &Path) {
+    repo.create_bank("bank", target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    let id = Scanner::new(&bank).scan().unwrap();
+    bank.save_history(id.id(), Timestamp::now().unwrap(), None)
+        .unwrap();
+}
+
+// エントリの`version`フィールドを書き換えたオブジェクトを保存し、そのIDを返す。
+fn save_with_version(bank: &Bank, id: &HashID, version: Option<u32>) -> HashID {
+    let mut value: Value = serde_json::from_reader(bank.open_object(id).unwrap()).unwrap();
+    match version {
+        Some(v) => value["version"] = v.into(),
+        None => {
+            value.as_object_mut().unwrap().remove("version");
+        }
+    }
+
+    let encoded = serde_json::to_vec(&value).unwrap();
+    let (new_id, temp) = hash_reader(encoded.as_slice()).unwrap();
+    bank.save_object(&new_id, temp).unwrap();
+    new_id
+}
+
+#[test]
+fn test_upgrade_legacy_root() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("sub")).unwrap();
+    fs::write(target.join("sub/file.txt"), "content").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    backup(&repo, &target);
+    let bank = repo.open_bank("bank").unwrap();
+
+    let history = bank.last_scan().unwrap().unwrap();
+    let current: DirEntry = bank.load_root(&history).unwrap();
+
+    let legacy_id = save_with_version(&bank, history.id(), None);
+    bank.replace_history(&history, legacy_id.clone()).unwrap();
+    let legacy_history = bank.last_scan().unwrap().unwrap();
+    assert_eq!(&legacy_id, legacy_history.id());
+    let legacy: DirEntry = bank.load_root(&legacy_history).unwrap();
+    assert_eq!(0, legacy.version());
+
+    let mut upgrader = Upgrader::new(&bank);
+    upgrader.dry_run(true);
+    upgrader.upgrade().unwrap();
+    assert_eq!(1, upgrader.stats().upgraded_histories);
+    assert_eq!(&legacy_id, bank.last_scan().unwrap().unwrap().id());
+
+    let mut upgrader = Upgrader::new(&bank);
+    upgrader.upgrade().unwrap();
+    assert_eq!(1, upgrader.stats().upgraded_histories);
+    assert_eq!(1, upgrader.stats().upgraded_entries);
+
+    let upgraded_history = bank.last_scan().unwrap().unwrap();
+    assert_eq!(history, upgraded_history);
+    let upgraded: DirEntry = bank.load_root(&upgraded_history).unwrap();
+    assert_eq!(ENTRY_VERSION, upgraded.version());
+    assert_eq!(
+        current.children().collect::<Vec<_>>(),
+        upgraded.children().collect::<Vec<_>>()
+    );
+
+    let mut upgrader = Upgrader::new(&bank);
+    upgrader.upgrade().unwrap();
+    assert_eq!(0, upgrader.stats().upgraded_histories);
+}
+
+#[test]
+fn test_reject_newer_version() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    backup(&repo, &target);
+    let bank = repo.open_bank("bank").unwrap();
+
+    let history = bank.last_scan().unwrap().unwrap();
+    let newer_id = save_with_version(&bank, history.id(), Some(MAX_ENTRY_VERSION + 1));
+
+    match bank.load_entry::<DirEntry>(&newer_id) {
+        Err(repo::Error::UnsupportedVersion { version, .. }) => {
+            assert_eq!(MAX_ENTRY_VERSION + 1, version)
+        }
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
@@ -0,0 +1,306 @@
+//! リポジトリのオブジェクトの整合性を並列に検証する。
+
+use std::collections::HashSet;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{trace, warn};
+
+use crate::core::entry::{Entry, FsHash};
+use crate::core::hash::HashID;
+use crate::core::parity::Repair;
+use crate::core::repo::{Bank, Error, History, Repository};
+use crate::core::timestamp::Timestamp;
+use crate::progress::Progress;
+
+#[cfg(test)]
+mod test;
+
+// 1つのオブジェクトの検証結果。予算切れで検証しなかった場合は`None`。
+type Outcome = Option<Result<(u64, bool), Error>>;
+
+/// 検証に失敗したオブジェクト
+#[derive(Debug)]
+pub struct Failure {
+    /// オブジェクトのID
+    pub id: HashID,
+    /// 失敗の原因
+    pub error: Error,
+}
+
+/// 検証結果
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    objects: u64,
+    bytes: u64,
+    verified: Vec<HashID>,
+    repaired: Vec<HashID>,
+    failures: Vec<Failure>,
+    unchecked: u64,
+}
+
+impl VerifyReport {
+    /// 検証に成功したオブジェクトの個数を返す。
+    pub fn objects(&self) -> u64 {
+        self.objects
+    }
+
+    /// 検証に成功したオブジェクトの合計バイト数を返す。
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// 検証に成功したオブジェクトのIDを返す。順序は不定。
+    pub fn verified(&self) -> &[HashID] {
+        &self.verified
+    }
+
+    /// 壊れていたがパリティから修復したオブジェクトのIDを返す。順序は不定。
+    ///
+    /// 修復したオブジェクトは検証に成功したものとしても数える。
+    pub fn repaired(&self) -> &[HashID] {
+        &self.repaired
+    }
+
+    /// 時間の上限に達したために検証しなかったオブジェクトの個数を返す。
+    pub fn unchecked(&self) -> u64 {
+        self.unchecked
+    }
+
+    /// 検証に失敗したオブジェクトをIDの順に返す。
+    pub fn failures(&self) -> &[Failure] {
+        &self.failures
+    }
+
+    /// 全てのオブジェクトの検証に成功したかどうかを返す。
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// オブジェクトを複数のスレッドで読み込み、ハッシュ値を検証する。
+///
+/// 同時に開くオブジェクトは[`threads`](#method.threads)で指定した数に制限される。
+pub struct Verifier<'a> {
+    repo: &'a Repository,
+    threads: usize,
+    budget: Option<Duration>,
+    repair: bool,
+    progress: Progress,
+}
+
+impl<'a> Verifier<'a> {
+    /// `repo`のオブジェクトを検証する`Verifier`を生成する。
+    pub fn new(repo: &'a Repository) -> Verifier<'a> {
+        Verifier {
+            repo,
+            threads: 1,
+            budget: None,
+            repair: false,
+            progress: Progress::hidden(),
+        }
+    }
+
+    /// 検証に使うスレッド数を設定する。
+    ///
+    /// 0を指定した場合は1とみなす。
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// 検証に掛ける時間の上限を設定する。
+    ///
+    /// 検証を始めてから`budget`が経過すると、残りのオブジェクトは検証せずに終える。
+    /// 検証中のオブジェクトは最後まで検証するため、上限をわずかに超えることがある。
+    pub fn budget(mut self, budget: Duration) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// 壊れたオブジェクトをパリティから修復するかどうかを設定する。
+    ///
+    /// パリティが無いオブジェクトや、修復できなかったオブジェクトは検証の失敗として集計する。
+    pub fn repair(mut self, repair: bool) -> Self {
+        self.repair = repair;
+        self
+    }
+
+    /// 進捗の表示先を設定する。
+    pub fn progress(mut self, progress: Progress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// リポジトリに保存されている全てのオブジェクトを検証する。
+    pub fn verify_all(&self) -> Result<VerifyReport, Error> {
+        let mut ids = self.repo.object_ids()?;
+        ids.sort();
+        self.verify(ids)
+    }
+
+    /// リポジトリの全てのオブジェクトを、最後に検証に成功した日時の古い順に検証する。
+    ///
+    /// [`budget`](#method.budget)で上限を設定すれば、繰り返し実行することで少しずつ全体を検証できる。
+    /// 検証に成功したオブジェクトは`at`に検証したものとして記録し、既に存在しないオブジェクトの記録は取り除く。
+    pub fn scrub(&self, at: Timestamp) -> Result<VerifyReport, Error> {
+        let mut verified = self.repo.load_verified_times()?;
+        let mut ids = self.repo.object_ids()?;
+        verified.sort_oldest_first(&mut ids);
+
+        let existing: HashSet<HashID> = ids.iter().cloned().collect();
+        let report = self.verify(ids)?;
+
+        verified.retain(|id| existing.contains(id));
+        for id in report.verified() {
+            verified.mark(id.clone(), at);
+        }
+        self.repo.save_verified_times(&verified)?;
+        Ok(report)
+    }
+
+    /// `bank`の履歴`history`から参照される全てのオブジェクトを検証する。
+    ///
+    /// ディレクトリのエントリを辿れない場合はエラーを返す。
+    pub fn verify_history(&self, bank: &Bank, history: &History) -> Result<VerifyReport, Error> {
+        let mut ids = HashSet::new();
+        ids.insert(history.id().clone());
+        ids.extend(bank.load_dir(history.id())?.chunk_ids());
+        for entry in bank.walk(history) {
+            let (_, hash) = entry?;
+            if let FsHash::Dir(_) = hash {
+                ids.extend(bank.load_dir(&hash.id())?.chunk_ids());
+            }
+            ids.insert(hash.id());
+        }
+        let mut ids: Vec<HashID> = ids.into_iter().collect();
+        ids.sort();
+        self.verify(ids)
+    }
+
+    /// `ids`のオブジェクトを`ids`の順に検証する。
+    ///
+    /// 個々のオブジェクトの検証の失敗は中断せずに[`VerifyReport`](struct.VerifyReport.html)に集計する。
+    pub fn verify(&self, mut ids: Vec<HashID>) -> Result<VerifyReport, Error> {
+        let mut seen = HashSet::new();
+        ids.retain(|id| seen.insert(id.clone()));
+        trace!("verify {} objects with {} threads", ids.len(), self.threads);
+        let deadline = self.budget.map(|budget| Instant::now() + budget);
+        let expired = || deadline.is_some_and(|d| Instant::now() >= d);
+
+        if self.progress.is_visible() {
+            let total = ids
+                .iter()
+                .filter_map(|id| self.repo.object_size(id).ok())
+                .sum();
+            self.progress.set_total(total);
+        }
+
+        let (job_tx, job_rx) = mpsc::sync_channel::<HashID>(self.threads * 4);
+        let (result_tx, result_rx) = mpsc::channel();
+        let job_rx = Mutex::new(job_rx);
+
+        let mut report = VerifyReport::default();
+        thread::scope(|s| {
+            for _ in 0..self.threads {
+                let job_rx = &job_rx;
+                let result_tx = result_tx.clone();
+                let repo = self.repo;
+                let repair = self.repair;
+                let expired = &expired;
+                s.spawn(move || loop {
+                    let received = job_rx.lock().unwrap().recv();
+                    let id = match received {
+                        Ok(id) => id,
+                        Err(_) => return,
+                    };
+                    // 待っている間に上限に達した場合は検証しない
+                    let result = if expired() {
+                        None
+                    } else {
+                        Some(verify_one(repo, &id, repair))
+                    };
+                    if result_tx.send((id, result)).is_err() {
+                        return;
+                    }
+                });
+            }
+            drop(result_tx);
+
+            let total = ids.len();
+            for (i, id) in ids.into_iter().enumerate() {
+                if expired() {
+                    report.unchecked += (total - i) as u64;
+                    break;
+                }
+                job_tx.send(id).expect("verify workers exited early");
+                while let Ok(result) = result_rx.try_recv() {
+                    self.record(&mut report, result);
+                }
+            }
+            drop(job_tx);
+
+            for result in result_rx {
+                self.record(&mut report, result);
+            }
+        });
+        self.progress.finish();
+
+        report.failures.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(report)
+    }
+
+    fn record(&self, report: &mut VerifyReport, (id, result): (HashID, Outcome)) {
+        match result {
+            Some(Ok((size, repaired))) => {
+                report.objects += 1;
+                report.bytes += size;
+                if repaired {
+                    report.repaired.push(id.clone());
+                }
+                report.verified.push(id);
+                self.progress.add_file(size);
+            }
+            Some(Err(error)) => {
+                warn!("failed verify object {}: {}", id, error);
+                report.failures.push(Failure { id, error });
+            }
+            None => report.unchecked += 1,
+        }
+    }
+}
+
+// オブジェクトを検証し、サイズと修復したかどうかを返す。
+fn verify_one(repo: &Repository, id: &HashID, repair: bool) -> Result<(u64, bool), Error> {
+    let error = match repo.verify_object(id) {
+        Err(e @ Error::BrokenObject { .. }) if repair => e,
+        result => return result.map(|size| (size, false)),
+    };
+
+    match repo.repair_object(id) {
+        Ok(Some(Repair::Repaired(blocks))) => {
+            trace!("repaired {} blocks of object {}", blocks, id);
+            repo.verify_object(id).map(|size| (size, true))
+        }
+        Ok(_) => Err(error),
+        Err(e) => {
+            warn!("failed to repair object {}: {}", id, e);
+            Err(error)
+        }
+    }
+}
+
+/// `report`で検証に成功したオブジェクトを、`at`に検証したものとして`repo`に記録する。
+pub fn record_verified(
+    repo: &Repository,
+    report: &VerifyReport,
+    at: Timestamp,
+) -> Result<(), Error> {
+    let mut verified = repo.load_verified_times()?;
+    for id in report.verified() {
+        verified.mark(id.clone(), at);
+    }
+    repo.save_verified_times(&verified)
+}
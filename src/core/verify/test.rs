@@ -0,0 +1,211 @@
+use super::*;
+
+use std::fs;
+
+use tempfile::tempdir;
+
+use crate::core::hash::hash_reader;
+use crate::core::scan::Scanner;
+use crate::core::timestamp::Timestamp;
+
+fn id_of(s: &str) -> HashID {
+    hash_reader(s.as_bytes()).unwrap().0
+}
+
+#[test]
+fn test_verify() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("d")).unwrap();
+    for name in &["a", "b", "c", "d/e"] {
+        fs::write(target.join(name), name).unwrap();
+    }
+
+    let repo_path = temp.path().join("repo");
+    let repo = Repository::create(&repo_path).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let root_id = Scanner::new(&bank).scan().unwrap().id();
+    let history = bank
+        .save_history(root_id, Timestamp::from(1_000_000_000), None)
+        .unwrap();
+
+    // 履歴から参照されないオブジェクト
+    let (extra, f) = hash_reader("extra".as_bytes()).unwrap();
+    bank.save_object(&extra, f).unwrap();
+
+    let report = Verifier::new(&repo).threads(4).verify_all().unwrap();
+    assert!(report.is_ok());
+    // ルート、d、4つのファイル、extra
+    assert_eq!(report.objects(), 7);
+
+    let report = Verifier::new(&repo)
+        .threads(4)
+        .verify_history(&bank, &history)
+        .unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.objects(), 6);
+
+    // 1つを壊し、1つを削除する
+    let objects = repo_path.join("objects");
+    let path_of = |id: &HashID| objects.join(id.shard_path());
+    fs::remove_file(path_of(&id_of("a"))).unwrap();
+    fs::write(path_of(&id_of("a")), "broken").unwrap();
+    fs::remove_file(path_of(&id_of("b"))).unwrap();
+
+    let report = Verifier::new(&repo)
+        .threads(4)
+        .verify_history(&bank, &history)
+        .unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.objects(), 4);
+
+    let mut failures = report
+        .failures()
+        .iter()
+        .map(|f| match &f.error {
+            Error::BrokenObject { to_be, .. } => ("broken", to_be.clone()),
+            Error::EntryNotFound(id) => ("missing", id.clone()),
+            e => panic!("{:?}", e),
+        })
+        .collect::<Vec<_>>();
+    failures.sort();
+    assert_eq!(
+        failures,
+        vec![("broken", id_of("a")), ("missing", id_of("b"))]
+    );
+
+    // 削除したオブジェクトは走査で見つからない
+    let report = Verifier::new(&repo).verify_all().unwrap();
+    assert_eq!(report.objects(), 5);
+    assert_eq!(report.failures().len(), 1);
+}
+
+#[test]
+fn test_verify_budget() {
+    let temp = tempdir().unwrap();
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", temp.path()).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let ids = ["a", "b", "c"]
+        .iter()
+        .map(|s| {
+            let (id, f) = hash_reader(s.as_bytes()).unwrap();
+            bank.save_object(&id, f).unwrap();
+            id
+        })
+        .collect::<Vec<_>>();
+
+    // 上限に達した後は検証しない
+    let report = Verifier::new(&repo)
+        .budget(Duration::from_secs(0))
+        .verify(ids.clone())
+        .unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.objects(), 0);
+    assert_eq!(report.unchecked(), 3);
+
+    let report = Verifier::new(&repo)
+        .threads(2)
+        .budget(Duration::from_secs(3600))
+        .verify(ids.clone())
+        .unwrap();
+    assert_eq!(report.objects(), 3);
+    assert_eq!(report.unchecked(), 0);
+    let mut verified = report.verified().to_vec();
+    verified.sort();
+    let mut to_be = ids;
+    to_be.sort();
+    assert_eq!(verified, to_be);
+}
+
+#[test]
+fn test_scrub() {
+    let temp = tempdir().unwrap();
+    let repo_path = temp.path().join("repo");
+    let repo = Repository::create(&repo_path).unwrap();
+    repo.create_bank("bank", temp.path()).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    for s in &["a", "b", "c"] {
+        let (id, f) = hash_reader(s.as_bytes()).unwrap();
+        bank.save_object(&id, f).unwrap();
+    }
+
+    let report = Verifier::new(&repo).verify(vec![id_of("b")]).unwrap();
+    record_verified(&repo, &report, Timestamp::from(100)).unwrap();
+
+    // 記録の無いものから検証する
+    let mut ids = repo.object_ids().unwrap();
+    repo.load_verified_times()
+        .unwrap()
+        .sort_oldest_first(&mut ids);
+    assert_eq!(ids[2], id_of("b"));
+
+    let report = Verifier::new(&repo).scrub(Timestamp::from(200)).unwrap();
+    assert_eq!(report.objects(), 3);
+    let verified = repo.load_verified_times().unwrap();
+    for s in &["a", "b", "c"] {
+        assert_eq!(verified.get(&id_of(s)), Some(Timestamp::from(200)));
+    }
+
+    // 削除したオブジェクトの記録は取り除かれ、壊れたオブジェクトの日時は更新されない
+    let objects = repo_path.join("objects");
+    fs::remove_file(objects.join(id_of("a").shard_path())).unwrap();
+    fs::write(objects.join(id_of("b").shard_path()), "broken").unwrap();
+    let report = Verifier::new(&repo).scrub(Timestamp::from(300)).unwrap();
+    assert_eq!(report.failures().len(), 1);
+    let verified = repo.load_verified_times().unwrap();
+    assert_eq!(verified.len(), 2);
+    assert_eq!(verified.get(&id_of("b")), Some(Timestamp::from(200)));
+    assert_eq!(verified.get(&id_of("c")), Some(Timestamp::from(300)));
+}
+
+#[test]
+fn test_verify_repair() {
+    let temp = tempdir().unwrap();
+    let repo_path = temp.path().join("repo");
+    let mut repo = Repository::create(&repo_path).unwrap();
+    repo.create_bank("bank", temp.path()).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+
+    // パリティを有効にする前に保存したオブジェクト
+    let content = (0..20_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let (old, f) = hash_reader(content.as_slice()).unwrap();
+    bank.save_object(&old, f).unwrap();
+    drop(bank);
+
+    repo.set_parity_enabled(true).unwrap();
+    let repo = Repository::open(&repo_path).unwrap();
+    assert!(repo.parity_enabled());
+    let bank = repo.open_bank("bank").unwrap();
+    let (new, f) = hash_reader(&content[..10_000]).unwrap();
+    bank.save_object(&new, f).unwrap();
+    let (no_parity, f) = hash_reader("no parity".as_bytes()).unwrap();
+    bank.save_object(&no_parity, f).unwrap();
+    fs::remove_file(repo_path.join("parity").join(no_parity.shard_path())).unwrap();
+
+    assert!(repo.save_parity(&old).unwrap());
+    assert!(!repo.save_parity(&new).unwrap());
+
+    let objects = repo_path.join("objects");
+    for id in &[&old, &new, &no_parity] {
+        let path = objects.join(id.shard_path());
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[5] ^= 0xff;
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, bytes).unwrap();
+    }
+
+    let report = Verifier::new(&repo).repair(true).verify_all().unwrap();
+    let mut repaired = report.repaired().to_vec();
+    repaired.sort();
+    let mut to_be = vec![old.clone(), new.clone()];
+    to_be.sort();
+    assert_eq!(repaired, to_be);
+    assert_eq!(report.objects(), 2);
+    assert_eq!(report.failures().len(), 1);
+    assert_eq!(report.failures()[0].id, no_parity);
+
+    assert_eq!(repo.verify_object(&old).unwrap(), 20_000);
+    assert_eq!(repo.verify_object(&new).unwrap(), 10_000);
+}
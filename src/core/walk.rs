@@ -0,0 +1,135 @@
+//! スナップショットのエントリを辿る。
+
+use std::cell::OnceCell;
+use std::sync::Arc;
+use std::vec;
+
+use crate::core::entry::{DirEntry, FsHash};
+use crate::core::hash::HashID;
+use crate::core::ignore::EntryPath;
+use crate::core::repo::{Bank, Error};
+
+#[cfg(test)]
+mod test;
+
+/// スナップショット内の全てのエントリを深さ優先で辿るイテレータ
+///
+/// [`Bank::walk`](../repo/struct.Bank.html#method.walk)で生成する。
+/// ディレクトリのエントリはその子を辿る直前に読み込まれるため、
+/// [`skip_current_dir`](#method.skip_current_dir)で飛ばしたディレクトリは読み込まれない。
+///
+/// 同じディレクトリ内の子は名前順に返される。ルートディレクトリ自体は返されない。
+pub struct Walk<'a> {
+    bank: &'a Bank<'a>,
+    stack: Vec<Frame>,
+    pending: Option<(EntryPath, HashID)>,
+}
+
+struct Frame {
+    path: EntryPath,
+    children: vec::IntoIter<FsHash>,
+}
+
+impl<'a> Walk<'a> {
+    /// `root_id`のディレクトリ以下を辿る`Walk`を生成する。
+    pub fn new(bank: &'a Bank<'a>, root_id: HashID) -> Walk<'a> {
+        Walk {
+            bank,
+            stack: Vec::new(),
+            pending: Some((EntryPath::root(), root_id)),
+        }
+    }
+
+    /// 直前に返したディレクトリの子を辿らないようにする。
+    ///
+    /// 直前に返したエントリがディレクトリでない場合は何もしない。
+    pub fn skip_current_dir(&mut self) {
+        if self.stack.is_empty() {
+            return;
+        }
+        self.pending = None;
+    }
+
+    fn load_pending(&mut self) -> Result<(), Error> {
+        if let Some((path, id)) = self.pending.take() {
+            let dir = self.bank.load_dir(&id)?;
+            self.stack.push(Frame {
+                path,
+                children: dir.children().cloned().collect::<Vec<_>>().into_iter(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = Result<(EntryPath, FsHash), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.load_pending() {
+            return Some(Err(e));
+        }
+
+        while let Some(frame) = self.stack.last_mut() {
+            if let Some(ch) = frame.children.next() {
+                let is_dir = matches!(ch, FsHash::Dir(_));
+                let path = frame.path.join(ch.attr().name(), is_dir);
+                if is_dir {
+                    self.pending = Some((path.clone(), ch.id()));
+                }
+                return Some(Ok((path, ch)));
+            }
+            self.stack.pop();
+        }
+
+        None
+    }
+}
+
+/// 初めて参照された時点で読み込まれるディレクトリ
+///
+/// [`Bank::lazy_dir`](../repo/struct.Bank.html#method.lazy_dir)で生成する。
+/// [`child_dir`](#method.child_dir)で得た子ディレクトリも、参照されるまで読み込まれない。
+#[derive(Debug)]
+pub struct LazyDir<'a> {
+    bank: &'a Bank<'a>,
+    id: HashID,
+    entry: OnceCell<Arc<DirEntry>>,
+}
+
+impl<'a> LazyDir<'a> {
+    pub(crate) fn new(bank: &'a Bank<'a>, id: HashID) -> LazyDir<'a> {
+        LazyDir {
+            bank,
+            id,
+            entry: OnceCell::new(),
+        }
+    }
+
+    /// ディレクトリのIDを返す。
+    pub fn id(&self) -> &HashID {
+        &self.id
+    }
+
+    /// ディレクトリのエントリを返す。
+    ///
+    /// まだ読み込まれていなければ、この時点で読み込む。
+    pub fn entry(&self) -> Result<&DirEntry, Error> {
+        if let Some(dir) = self.entry.get() {
+            return Ok(dir);
+        }
+        let dir = self.bank.load_dir(&self.id)?;
+        Ok(self.entry.get_or_init(|| dir))
+    }
+
+    /// 名前が`name`の子ディレクトリを返す。
+    ///
+    /// このディレクトリは読み込まれるが、子ディレクトリは読み込まれない。
+    /// `name`の子が存在しないか、ディレクトリでない場合はNoneを返す。
+    pub fn child_dir(&self, name: &str) -> Result<Option<LazyDir<'a>>, Error> {
+        Ok(self
+            .entry()?
+            .find_dir(name)
+            .map(|dir| LazyDir::new(self.bank, dir.id())))
+    }
+}
@@ -0,0 +1,110 @@
+use super::*;
+
+use std::fs;
+
+use tempfile::tempdir;
+
+use crate::core::repo::Repository;
+use crate::core::scan::Scanner;
+use crate::core::timestamp::Timestamp;
+
+fn paths(walk: Walk) -> Vec<String> {
+    walk.map(|e| e.unwrap().0.to_string()).collect()
+}
+
+#[test]
+fn test_walk_depth_first() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("a/b")).unwrap();
+    fs::create_dir_all(target.join("c")).unwrap();
+    fs::write(target.join("a/b/x.txt"), "x").unwrap();
+    fs::write(target.join("a/y.txt"), "y").unwrap();
+    fs::write(target.join("z.txt"), "z").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let id = Scanner::new(&bank).scan().unwrap();
+    bank.save_history(id.id(), Timestamp::now().unwrap(), None)
+        .unwrap();
+    let history = bank.last_scan().unwrap().unwrap();
+
+    assert_eq!(
+        paths(bank.walk(&history)),
+        vec!["a", "a/b", "a/b/x.txt", "a/y.txt", "c", "z.txt"]
+    );
+
+    let entries = bank
+        .walk(&history)
+        .map(|e| e.unwrap())
+        .filter(|(path, _)| path.is_dir())
+        .map(|(path, _)| (path.to_string(), path.depth()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        entries,
+        vec![
+            ("a".to_owned(), 1),
+            ("a/b".to_owned(), 2),
+            ("c".to_owned(), 1)
+        ]
+    );
+}
+
+#[test]
+fn test_walk_skip_current_dir() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("a/b")).unwrap();
+    fs::write(target.join("a/b/x.txt"), "x").unwrap();
+    fs::write(target.join("a/y.txt"), "y").unwrap();
+    fs::write(target.join("z.txt"), "z").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let id = Scanner::new(&bank).scan().unwrap();
+
+    let mut walk = Walk::new(&bank, id.id());
+    let mut found = Vec::new();
+    while let Some(entry) = walk.next() {
+        let (path, _) = entry.unwrap();
+        if path.to_string() == "a/b" {
+            walk.skip_current_dir();
+        }
+        found.push(path.to_string());
+    }
+
+    assert_eq!(found, vec!["a", "a/b", "a/y.txt", "z.txt"]);
+}
+
+#[test]
+fn test_lazy_dir_loads_on_demand() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("a/b")).unwrap();
+    fs::write(target.join("a/b/x.txt"), "x").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let root_id = Scanner::new(&bank).scan().unwrap().id();
+
+    let root = bank.lazy_dir(root_id.clone());
+    let a = root.child_dir("a").unwrap().unwrap();
+    assert!(root.child_dir("missing").unwrap().is_none());
+
+    let b = a.child_dir("b").unwrap().unwrap();
+    let b_id = b.id().clone();
+    let b_path = temp
+        .path()
+        .join("repo/objects")
+        .join(b_id.parts().0)
+        .join(b_id.parts().1)
+        .join(b_id.parts().2);
+
+    // 子ディレクトリは参照されるまで読み込まれない
+    fs::remove_file(&b_path).unwrap();
+    assert!(b.entry().is_err());
+    assert_eq!(a.entry().unwrap().children().count(), 1);
+}
@@ -0,0 +1,276 @@
+//! クレート全体で共通のエラー型
+//!
+//! 各モジュールのエラー型は[`Categorized`](trait.Categorized.html)を実装しており、
+//! `?`演算子で[`Error`](struct.Error.html)に変換できる。
+//! 変換後も元のエラーは[`Error::cause`](struct.Error.html#method.cause)や`downcast_ref`で参照できる。
+//!
+//! 全てのエラー型は`std::error::Error + Send + Sync + 'static`を満たすため、`anyhow`などでそのまま扱える。
+
+use std::error;
+use std::fmt;
+use std::io;
+
+use crate::core::{export, extend, hash, ignore, remote, repo, scan, timestamp, upgrade};
+
+#[cfg(test)]
+mod test;
+
+/// [`Error`](struct.Error.html)の分類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// コマンドライン引数や入力値が不正
+    Usage,
+    /// 設定ファイルが不正
+    Config,
+    /// 指定されたBank、履歴、エントリなどが存在しない
+    NotFound,
+    /// リポジトリが存在しないか、リポジトリとして必要なディレクトリが欠けている
+    Repository,
+    /// Bankが他のプロセスによってロックされている
+    Locked,
+    /// リポジトリのデータが壊れているか、対応していない形式である
+    Data,
+    /// 入出力エラー
+    IO,
+    /// 利用者の操作により中断された
+    Canceled,
+    /// バックアップは完了したが、読み込めずに飛ばしたファイルがある
+    ///
+    /// エラーとしては返さず、サブコマンドの終了コードとしてのみ使用する。
+    Partial,
+    /// 検証に失敗したオブジェクトがある
+    ///
+    /// エラーとしては返さず、サブコマンドの終了コードとしてのみ使用する。
+    VerifyFailed,
+    /// その他のエラー
+    Other,
+}
+
+impl ErrorKind {
+    /// コマンドラインツールとして終了する際の終了コードを返す。
+    ///
+    /// 可能なものは`sysexits.h`の値に合わせている。
+    /// 処理自体は最後まで行われた場合(`Partial`, `VerifyFailed`)は、`sysexits.h`と重ならない小さな値を使う。
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Partial => 2,
+            ErrorKind::VerifyFailed => 3,
+            ErrorKind::Usage => 64,
+            ErrorKind::Data => 65,
+            ErrorKind::NotFound => 66,
+            ErrorKind::Repository => 69,
+            ErrorKind::IO => 74,
+            ErrorKind::Locked => 75,
+            ErrorKind::Config => 78,
+            ErrorKind::Canceled | ErrorKind::Other => 1,
+        }
+    }
+}
+
+/// 分類を持つエラー
+///
+/// [`Error`](struct.Error.html)への変換で分類を決めるために使用する。
+pub trait Categorized: error::Error + Send + Sync + 'static {
+    /// エラーの分類を返す。
+    fn kind(&self) -> ErrorKind;
+}
+
+/// sbakのエラー
+pub struct Error {
+    kind: ErrorKind,
+    inner: Box<dyn error::Error + Send + Sync + 'static>,
+}
+
+impl Error {
+    /// 分類と原因から`Error`を生成する。
+    pub fn new<E>(kind: ErrorKind, cause: E) -> Error
+    where
+        E: error::Error + Send + Sync + 'static,
+    {
+        Error {
+            kind,
+            inner: Box::new(cause),
+        }
+    }
+
+    /// エラーの分類を返す。
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// コマンドラインツールとして終了する際の終了コードを返す。
+    pub fn exit_code(&self) -> i32 {
+        self.kind.exit_code()
+    }
+
+    /// 変換元のエラーを返す。
+    pub fn cause(&self) -> &(dyn error::Error + 'static) {
+        &*self.inner
+    }
+
+    /// 変換元のエラーが`T`であれば、その参照を返す。
+    pub fn downcast_ref<T: error::Error + 'static>(&self) -> Option<&T> {
+        self.inner.downcast_ref()
+    }
+}
+
+impl<E: Categorized> From<E> for Error {
+    fn from(e: E) -> Error {
+        Error::new(e.kind(), e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(self.cause())
+    }
+}
+
+impl Categorized for io::Error {
+    fn kind(&self) -> ErrorKind {
+        match io::Error::kind(self) {
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::Interrupted => ErrorKind::Canceled,
+            _ => ErrorKind::IO,
+        }
+    }
+}
+
+impl Categorized for repo::Error {
+    fn kind(&self) -> ErrorKind {
+        use repo::Error::*;
+
+        match self {
+            BrokenObject { .. }
+            | Parse(_)
+            | UnsupportedVersion { .. }
+            | UnsupportedHistoryVersion { .. } => ErrorKind::Data,
+            BrokenBank { cause, .. } => cause.kind(),
+            EntryNotFound(_) => ErrorKind::NotFound,
+            IncompleteRepo(..) => ErrorKind::Repository,
+            IgnorePattern(_) | InvalidFileName(_) | InvalidInput(_) | NotEmptyDirectory(_) => {
+                ErrorKind::Usage
+            }
+            InsufficientSpace(_) => ErrorKind::IO,
+            Locked { .. } => ErrorKind::Locked,
+            IO(e) => Categorized::kind(e),
+        }
+    }
+}
+
+impl Categorized for scan::Error {
+    fn kind(&self) -> ErrorKind {
+        use scan::Error::*;
+
+        match self {
+            Encode(_) => ErrorKind::Other,
+            Ignore(e) => e.kind(),
+            IO(e) => Categorized::kind(e),
+            NameIsEmpty(_) | NameIsInvalidUnicode(_) | Timestamp => ErrorKind::Data,
+            Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl Categorized for remote::Error {
+    fn kind(&self) -> ErrorKind {
+        use remote::Error::*;
+
+        match self {
+            Encode(_) => ErrorKind::Other,
+            InvalidSource(_) => ErrorKind::Usage,
+            IO(e) => Categorized::kind(e),
+            Protocol(_) | Remote(_) => ErrorKind::IO,
+            Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl Categorized for export::Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            export::Error::IO(e) => Categorized::kind(e),
+            export::Error::NotFound(_) => ErrorKind::NotFound,
+            export::Error::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl Categorized for extend::Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            extend::Error::CaseCollision(_) => ErrorKind::Other,
+            extend::Error::IO(e) => Categorized::kind(e),
+            extend::Error::Repo(e) => e.kind(),
+            extend::Error::Timestamp => ErrorKind::Data,
+        }
+    }
+}
+
+impl Categorized for upgrade::Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            upgrade::Error::Encode(_) => ErrorKind::Other,
+            upgrade::Error::IO(e) => Categorized::kind(e),
+            upgrade::Error::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl Categorized for ignore::Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            ignore::Error::IgnorePattern(e) => e.kind(),
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl Categorized for ignore::pattern::ParseError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            ignore::pattern::ParseError::IO(e) => Categorized::kind(e),
+            _ => ErrorKind::Usage,
+        }
+    }
+}
+
+impl Categorized for hash::Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            hash::Error::IO(e) => Categorized::kind(e),
+        }
+    }
+}
+
+impl Categorized for timestamp::Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Data
+    }
+}
+
+#[cfg(feature = "async")]
+impl Categorized for crate::core::nonblocking::Error {
+    fn kind(&self) -> ErrorKind {
+        use crate::core::nonblocking::Error::*;
+
+        match self {
+            Extend(e) => e.kind(),
+            IO(e) => Categorized::kind(e),
+            Repo(e) => e.kind(),
+            Scan(e) => e.kind(),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use super::*;
+
+use std::path::PathBuf;
+
+fn locked() -> repo::Error {
+    repo::Error::Locked {
+        bank: "home".to_owned(),
+        owner: "pid 1 on host".to_owned(),
+        path: PathBuf::from("lock.json"),
+    }
+}
+
+#[test]
+fn test_from_repo_error() {
+    let e = Error::from(locked());
+
+    assert_eq!(e.kind(), ErrorKind::Locked);
+    assert_eq!(e.exit_code(), 75);
+    assert_eq!(e.to_string(), locked().to_string());
+    assert!(e.downcast_ref::<repo::Error>().is_some());
+}
+
+#[test]
+fn test_nested_kind() {
+    let e = Error::from(scan::Error::Repo(locked()));
+    assert_eq!(e.kind(), ErrorKind::Locked);
+    assert!(e.downcast_ref::<scan::Error>().is_some());
+
+    let e = Error::from(extend::Error::IO(io::Error::new(
+        io::ErrorKind::NotFound,
+        "missing",
+    )));
+    assert_eq!(e.kind(), ErrorKind::NotFound);
+    assert_eq!(e.exit_code(), 66);
+}
+
+#[test]
+fn test_io_error() {
+    let e = Error::from(io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+    assert_eq!(e.kind(), ErrorKind::IO);
+    assert_eq!(e.exit_code(), 74);
+}
+
+#[test]
+fn test_source_chain() {
+    fn assert_send_sync<T: Send + Sync + 'static>() {}
+    assert_send_sync::<Error>();
+
+    let e = Error::from(scan::Error::Repo(locked()));
+    let source = error::Error::source(&e).unwrap();
+    assert!(source.downcast_ref::<scan::Error>().is_some());
+
+    let source = source.source().unwrap();
+    assert_eq!(source.to_string(), locked().to_string());
+}
+
+#[test]
+fn test_incomplete_repo() {
+    let e = Error::from(repo::Error::IncompleteRepo(
+        "repository directory",
+        "missing",
+    ));
+    assert_eq!(e.kind(), ErrorKind::Repository);
+    assert_eq!(e.exit_code(), 69);
+}
+
+#[test]
+fn test_exit_codes_distinct() {
+    let kinds = [
+        ErrorKind::Usage,
+        ErrorKind::Config,
+        ErrorKind::NotFound,
+        ErrorKind::Repository,
+        ErrorKind::Locked,
+        ErrorKind::Data,
+        ErrorKind::IO,
+        ErrorKind::Partial,
+        ErrorKind::VerifyFailed,
+    ];
+
+    let mut codes = kinds.iter().map(|k| k.exit_code()).collect::<Vec<_>>();
+    assert!(codes.iter().all(|&c| c != 0 && c != 1));
+    codes.sort();
+    codes.dedup();
+    assert_eq!(codes.len(), kinds.len());
+}
@@ -3,10 +3,28 @@
 //! sbak ライブラリ部分
 //!
 //! 外部から使用する場合は [`core`](core/index.html) を参照すること。
+//!
+//! # ログ
+//!
+//! `core`は[`log`](https://docs.rs/log)クレートのマクロでログを出力する。
+//! ライブラリとして組み込む場合は[`smalllog`](smalllog/index.html)を初期化せず、任意の`log`対応ロガーを設定してよい。
+//!
+//! スキャン・展開・アップグレードの開始と終了は[`core::span`](core/span/index.html)によって
+//! `sbak::span::scan`、`sbak::span::restore`、`sbak::span::upgrade`をターゲットとして記録される。
+//!
+//! # 非同期処理
+//!
+//! `async` featureを有効にすると、非同期処理から使用するための`core::nonblocking`が使用できる。
 
 pub mod config;
 pub mod core;
+pub mod error;
+pub mod message;
+pub mod notify;
+pub mod progress;
 pub mod smalllog;
 pub mod sub;
 pub mod util;
 pub mod version;
+
+pub use error::{Error, ErrorKind};
@@ -1,63 +1,90 @@
+use std::env::current_dir;
 use std::io::{stderr, Write};
+use std::path::PathBuf;
 use std::process::exit;
 
 use anyhow::{Context, Result};
-use clap::{crate_description, crate_name, App, Arg};
-use log::{error, trace};
+use clap::ArgMatches;
+use log::{error, trace, warn};
 
 use sbak::config::{auto_load, load};
+use sbak::core::repo::Repository;
+use sbak::message::{set_lang, Lang};
 use sbak::smalllog;
-use sbak::sub::sub_commands;
+use sbak::sub::{app, sub_commands};
 use sbak::version::version;
+use sbak::ErrorKind;
 
 fn main() {
     smalllog::init();
 
-    if let Err(e) = w_main() {
-        error!("Error: {}", e);
-        for c in e.chain().skip(1) {
-            error!("    at: {}", c);
+    match w_main() {
+        Ok(code) => exit(code),
+        Err(e) => {
+            error!("Error: {}", e);
+            for c in e.chain().skip(1) {
+                error!("    at: {}", c);
+            }
+            // 設定ファイルの読み込みや引数による設定の適用に失敗した場合
+            exit(ErrorKind::Config.exit_code());
         }
-        exit(1);
     }
 }
 
-fn w_main() -> Result<()> {
+fn w_main() -> Result<i32> {
     let mut config = auto_load().context("loading aut-detected config file")?;
     config.apply_log();
+    set_lang(Lang::detect(config.lang()));
 
     let subs = sub_commands();
 
     let ver = version(8);
-    let mut app = App::new(crate_name!())
-        .author("Igaguri <igagurimk@gmail.com>")
-        .about(crate_description!())
-        .version(ver.as_str())
-        .arg(
-            Arg::with_name("config")
-                .short("c")
-                .long("config")
-                .takes_value(true)
-                .help("Extra config file"),
-        )
-        .arg(
-            Arg::with_name("log_level")
-                .short("L")
-                .long("log-level")
-                .takes_value(true)
-                .help("Log level"),
-        )
-        .subcommands(subs.arg_defs());
+    let app = app(&subs, &ver);
 
+    // ヘルプの生成でグローバルな引数がサブコマンドに追加され、`get_matches`で重複してしまうため、複製から生成する。
     let mut help_str = Vec::<u8>::new();
-    app.write_long_help(&mut help_str).unwrap();
+    app.clone().write_long_help(&mut help_str).unwrap();
 
     let matches = app.get_matches();
 
-    if let Some(extra_config_file) = matches.value_of("config") {
-        let extra_config = load(extra_config_file)
-            .with_context(|| format!("loading optional config file '{}'", extra_config_file))?;
-        config = config.merged(&extra_config);
+    let extra_config = match matches.value_of("config") {
+        Some(extra_config_file) => Some(
+            load(extra_config_file)
+                .with_context(|| format!("loading optional config file '{}'", extra_config_file))?,
+        ),
+        None => None,
+    };
+
+    // リポジトリ内の設定は、操作するリポジトリが決まってから読み込む
+    let repo_path = match global_value(&matches, "repo") {
+        Some(repo_path) => Some(PathBuf::from(repo_path)),
+        None => discover_repository().or_else(|| {
+            extra_config
+                .as_ref()
+                .and_then(|c| c.repository_path())
+                .or_else(|| config.repository_path())
+                .map(|p| p.to_owned())
+        }),
+    };
+    if let Some(ref repo_path) = repo_path {
+        config = config
+            .merged_repository(repo_path)
+            .context("loading repository config file")?;
+    }
+    if let Some(ref extra_config) = extra_config {
+        config = config.merged(extra_config);
+    }
+    if let Some(repo_path) = repo_path {
+        config.set_repository_path(repo_path);
+    }
+    config.apply_log();
+    set_lang(Lang::detect(config.lang()));
+
+    if matches.is_present("quiet") || matches.is_present("verbose") {
+        config.set_verbosity(
+            matches.is_present("quiet"),
+            matches.occurrences_of("verbose"),
+        );
         config.apply_log();
     }
 
@@ -68,15 +95,52 @@ fn w_main() -> Result<()> {
         config.apply_log();
     }
 
+    if matches.is_present("no_keyring") {
+        config.disable_keyring();
+    }
+
     trace!("config = {:?}", config);
 
     if let (subcmd_name, Some(matches)) = matches.subcommand() {
-        // 成功したらそのままプロセスを終了する
-        subs.execute(subcmd_name, matches, config);
+        if let Some(result) = subs.execute(subcmd_name, matches, config) {
+            return Ok(result.unwrap_or_else(|e| {
+                if cfg!(debug_assertions) {
+                    error!("{:#?}", e);
+                } else {
+                    error!("{}", e);
+                }
+                e.exit_code()
+            }));
+        }
     }
 
     let mut out = stderr();
     out.write_all(&help_str).unwrap();
     eprintln!();
-    exit(1);
+    Ok(1)
+}
+
+// カレントディレクトリから親を辿ってリポジトリを探し、そのパスを返す。
+//
+// 見つけたリポジトリを開けない場合は警告し、設定ファイルのリポジトリを使う。
+fn discover_repository() -> Option<PathBuf> {
+    let dir = current_dir().ok()?;
+    match Repository::discover(&dir) {
+        Ok(repo) => repo.map(|r| r.path().to_owned()),
+        Err(e) => {
+            warn!("failed to open repository found from {:?}: {}", dir, e);
+            None
+        }
+    }
+}
+
+// グローバルな引数`name`の値を返す。
+//
+// サブコマンドの後に指定された値はサブコマンド側にのみ格納されるため、最も深いサブコマンドの値を優先する。
+fn global_value<'a>(matches: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    let inner = match matches.subcommand() {
+        (_, Some(sub)) => global_value(sub, name),
+        _ => None,
+    };
+    inner.or_else(|| matches.value_of(name))
 }
@@ -0,0 +1,445 @@
+//! 画面に表示するメッセージの言語切り替え
+//!
+//! 言語は設定ファイルの`lang`、環境変数`LC_ALL`, `LC_MESSAGES`, `LANG`の順に決定される。
+//! いずれも指定がない場合や対応していない言語の場合は英語になる。
+//!
+//! エラーメッセージとログは常に英語で表示される。
+
+use std::env::var;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(test)]
+mod test;
+
+static JAPANESE: AtomicBool = AtomicBool::new(false);
+
+/// 表示言語
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    /// 英語
+    En,
+    /// 日本語
+    Ja,
+}
+
+impl Lang {
+    /// 設定値と環境変数から表示言語を決定する。
+    ///
+    /// `config_lang`が解釈できない場合は環境変数を参照する。
+    pub fn detect(config_lang: Option<&str>) -> Lang {
+        if let Some(lang) = config_lang.and_then(|s| s.parse().ok()) {
+            return lang;
+        }
+
+        for name in &["LC_ALL", "LC_MESSAGES", "LANG"] {
+            if let Ok(value) = var(name) {
+                if !value.is_empty() {
+                    return value.parse().unwrap_or(Lang::En);
+                }
+            }
+        }
+
+        Lang::En
+    }
+}
+
+impl FromStr for Lang {
+    type Err = String;
+
+    /// `en`, `ja`や`ja_JP.UTF-8`のようなロケール名を解析する。
+    fn from_str(s: &str) -> Result<Lang, String> {
+        let code = s
+            .split(&['_', '-', '.'][..])
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+
+        match code.as_str() {
+            "en" | "c" | "posix" => Ok(Lang::En),
+            "ja" => Ok(Lang::Ja),
+            _ => Err(s.to_owned()),
+        }
+    }
+}
+
+/// 表示言語を設定する。
+pub fn set_lang(lang: Lang) {
+    JAPANESE.store(lang == Lang::Ja, Ordering::Relaxed);
+}
+
+/// 現在の表示言語を返す。
+pub fn lang() -> Lang {
+    if JAPANESE.load(Ordering::Relaxed) {
+        Lang::Ja
+    } else {
+        Lang::En
+    }
+}
+
+/// 表示メッセージの一覧
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum Msg {
+    AboutAgent,
+    AboutAnnotate,
+    AboutBackup,
+    AboutBank,
+    AboutBankMove,
+    AboutCheckIgnore,
+    AboutConfig,
+    AboutConfigInit,
+    AboutDaemon,
+    AboutDiff,
+    AboutExport,
+    AboutGenMan,
+    AboutGrep,
+    AboutHistory,
+    AboutIgnore,
+    AboutIgnoreImport,
+    AboutInfo,
+    AboutInit,
+    AboutInitBank,
+    AboutInitRepo,
+    AboutKeyring,
+    AboutKeyringDelete,
+    AboutKeyringSet,
+    AboutList,
+    AboutLs,
+    AboutParity,
+    AboutRestore,
+    AboutRunDue,
+    AboutScrub,
+    AboutSearch,
+    AboutServe,
+    AboutService,
+    AboutServiceInstall,
+    AboutServiceRun,
+    AboutServiceUninstall,
+    AboutShell,
+    AboutStatus,
+    AboutUpgrade,
+    AboutVerify,
+    AboutWhyBig,
+
+    BankAlreadyExists,
+    BankMoved,
+    BankRemoved,
+    ConfigWritten,
+    ContentIndexUpdated,
+    DiffNoChanges,
+    DiffStatTotal,
+    ExportDone,
+    HistoryAnnotated,
+    HistoryMemoCleared,
+    IgnoreImported,
+    InventorySaved,
+    KeyringDeleted,
+    KeyringNotStored,
+    KeyringPrompt,
+    KeyringStored,
+    LastBackupAt,
+    ManPagesWritten,
+    NoBackups,
+    ParityDisabled,
+    ParityDone,
+    PromptRepositoryPath,
+    PromptLogOutput,
+    PromptLogLevel,
+    PromptBankName,
+    PickerNumber,
+    PickerInvalidNumber,
+    RepoAlreadyExists,
+    RepoSetDefault,
+    RestoreSelectHistory,
+    RestoreSymlinks,
+    ScrubDone,
+    ServeListening,
+    ServiceInstalled,
+    ServiceUninstalled,
+    ShellHelp,
+    ShellSaved,
+    StatusAdded,
+    StatusNoChanges,
+    StatusRemoved,
+    StatusUpdated,
+    UpgradeDone,
+    UpgradeDryRun,
+    VerifyDone,
+
+    InfoVersion,
+    InfoConfig,
+    InfoConfigSearchPaths,
+    InfoLoadedConfigFiles,
+
+    WhyBigGrowth,
+    WhyBigLargest,
+    WhyBigFrequent,
+    WhyBigByDirectory,
+}
+
+impl Msg {
+    /// 現在の表示言語でのメッセージを返す。
+    ///
+    /// メッセージ中の`{}`は[`fill`](#method.fill)で置き換えられる。
+    pub fn text(self) -> &'static str {
+        match lang() {
+            Lang::En => self.en(),
+            Lang::Ja => self.ja(),
+        }
+    }
+
+    /// メッセージ中の`{}`を先頭から順に`args`で置き換えた文字列を返す。
+    pub fn fill(self, args: &[&dyn fmt::Display]) -> String {
+        let mut parts = self.text().split("{}");
+        let mut s = parts.next().unwrap_or("").to_owned();
+
+        for (i, part) in parts.enumerate() {
+            if let Some(arg) = args.get(i) {
+                s.push_str(&arg.to_string());
+            }
+            s.push_str(part);
+        }
+
+        s
+    }
+
+    fn en(self) -> &'static str {
+        match self {
+            Msg::AboutAgent => "Serve files to a remote backup over standard input/output",
+            Msg::AboutAnnotate => "Set memo of history",
+            Msg::AboutBackup => "Backup files",
+            Msg::AboutBank => "Manage banks",
+            Msg::AboutBankMove => "Copy bank with all histories to another repository",
+            Msg::AboutCheckIgnore => "Show which pattern excludes paths from backup",
+            Msg::AboutConfig => "Manage config files",
+            Msg::AboutConfigInit => "Write a starter config file",
+            Msg::AboutDaemon => "Run scheduled backups as a background service",
+            Msg::AboutDiff => "Show files changed between two backups",
+            Msg::AboutExport => "Export files in backup as tar or zip archive",
+            Msg::AboutGenMan => "Generate man pages of all commands",
+            Msg::AboutGrep => "Search lines matching regular expression in backed up files",
+            Msg::AboutHistory => "Show history",
+            Msg::AboutIgnore => "Manage ignore rules of bank",
+            Msg::AboutIgnoreImport => {
+                "Convert .gitignore rules and add them to ignore rules of bank"
+            }
+            Msg::AboutInfo => "Show informations",
+            Msg::AboutInit => "Create or initialize repository/bank",
+            Msg::AboutInitBank => "Create or initialize bank",
+            Msg::AboutInitRepo => "Create or initialize repository",
+            Msg::AboutKeyring => "Manage secrets stored in OS keyring",
+            Msg::AboutKeyringDelete => "Delete secret from OS keyring",
+            Msg::AboutKeyringSet => "Store secret read from standard input in OS keyring",
+            Msg::AboutList => "Show banks",
+            Msg::AboutLs => "List files in backup",
+            Msg::AboutParity => "Store parity data for all objects to repair corruption",
+            Msg::AboutRestore => "Restore files",
+            Msg::AboutRunDue => "Backup banks whose schedule is due",
+            Msg::AboutScrub => "Verify least recently verified objects within time budget",
+            Msg::AboutSearch => "Find backed up text files containing words",
+            Msg::AboutServe => "Serve repository contents over HTTP API",
+            Msg::AboutService => "Manage Windows service running scheduled backups",
+            Msg::AboutServiceInstall => "Register sbak as Windows service",
+            Msg::AboutServiceRun => "Run as Windows service (called by service control manager)",
+            Msg::AboutServiceUninstall => "Stop and remove Windows service",
+            Msg::AboutShell => "Browse backup interactively",
+            Msg::AboutStatus => "Show files the next backup would add, update or remove",
+            Msg::AboutUpgrade => "Rewrite entries in old format to current format",
+            Msg::AboutVerify => "Check integrity of objects in repository",
+            Msg::AboutWhyBig => "Find the largest contributors to repository growth",
+
+            Msg::BankAlreadyExists => "bank '{}' already exists.",
+            Msg::BankMoved => "bank '{}' with {} histories copied to {} ({} objects, {} written)",
+            Msg::BankRemoved => "bank '{}' removed from {}",
+            Msg::ConfigWritten => "config file written to {}",
+            Msg::ContentIndexUpdated => "{} files added to content index",
+            Msg::DiffNoChanges => "No changes",
+            Msg::DiffStatTotal => "{} added, {} updated, {} removed, {}",
+            Msg::ExportDone => "{} files and {} directories ({}) exported to {}",
+            Msg::HistoryAnnotated => "memo of history {} set",
+            Msg::HistoryMemoCleared => "memo of history {} cleared",
+            Msg::IgnoreImported => {
+                "{} patterns imported from {} to bank '{}', {} lines need attention"
+            }
+            Msg::InventorySaved => "inventory of {} changed files since {}",
+            Msg::KeyringDeleted => "secret '{}' deleted from keyring",
+            Msg::KeyringNotStored => "secret '{}' isn't stored in keyring",
+            Msg::KeyringPrompt => "Secret for '{}': ",
+            Msg::KeyringStored => "secret '{}' stored in keyring",
+            Msg::LastBackupAt => "last backup at {}",
+            Msg::ManPagesWritten => "{} man pages written to {}",
+            Msg::NoBackups => "No backups",
+            Msg::ParityDisabled => "parity is no longer stored for new objects",
+            Msg::ParityDone => "parity created for {} objects, {} failed",
+            Msg::PromptRepositoryPath => "Repository path",
+            Msg::PromptLogOutput => "Log output",
+            Msg::PromptLogLevel => "Log level",
+            Msg::PromptBankName => "Example bank name",
+            Msg::PickerNumber => "number (empty to cancel): ",
+            Msg::PickerInvalidNumber => "invalid number: {}",
+            Msg::RepoAlreadyExists => "repository already exists at {}.",
+            Msg::RepoSetDefault => "repository {} set as default in {}",
+            Msg::RestoreSelectHistory => "Select history of '{}' to restore",
+            Msg::RestoreSymlinks => {
+                "{} symbolic links created, {} created as junctions, {} skipped"
+            }
+            Msg::ScrubDone => "{} objects ({}) verified, {} failed, {} left for next scrub",
+            Msg::ServeListening => "listening on http://{}",
+            Msg::ServiceInstalled => "service '{}' installed",
+            Msg::ServiceUninstalled => "service '{}' uninstalled",
+            Msg::ShellHelp => {
+                "Commands:
+  ls [path]           list directory
+  cd [path]           change directory (root if omitted)
+  pwd                 show current directory
+  cat <file>          write file contents to standard output
+  get <path> [local]  extract file or directory to local path
+  help                show this help
+  exit                leave shell"
+            }
+            Msg::ShellSaved => "'{}' extracted to {}",
+            Msg::StatusAdded => "Added: {} files ({})",
+            Msg::StatusNoChanges => "No changes since last backup",
+            Msg::StatusRemoved => "Removed: {} files ({})",
+            Msg::StatusUpdated => "Updated: {} files ({})",
+            Msg::UpgradeDone => "{}/{} histories, {}/{} entries upgraded",
+            Msg::UpgradeDryRun => "{}/{} histories, {}/{} entries to be upgraded",
+            Msg::VerifyDone => "{} objects ({}) verified, {} failed",
+
+            Msg::InfoVersion => "Version:",
+            Msg::InfoConfig => "Config:",
+            Msg::InfoConfigSearchPaths => "Config search paths:",
+            Msg::InfoLoadedConfigFiles => "Loaded config files:",
+
+            Msg::WhyBigGrowth => "Growth over last {} histories: {} ({} of directory entries)",
+            Msg::WhyBigLargest => "Largest new objects:",
+            Msg::WhyBigFrequent => "Most frequently changed files:",
+            Msg::WhyBigByDirectory => "New bytes by directory:",
+        }
+    }
+
+    fn ja(self) -> &'static str {
+        match self {
+            Msg::AboutAgent => "リモートからのバックアップに標準入出力でファイルを提供する",
+            Msg::AboutAnnotate => "履歴にメモを付ける",
+            Msg::AboutBackup => "ファイルをバックアップする",
+            Msg::AboutBank => "Bankを管理する",
+            Msg::AboutBankMove => "Bankを全ての履歴ごと他のリポジトリに複製する",
+            Msg::AboutCheckIgnore => "パスがどのパターンでバックアップから除外されるかを表示する",
+            Msg::AboutConfig => "設定ファイルを管理する",
+            Msg::AboutConfigInit => "設定ファイルのひな形を書き出す",
+            Msg::AboutDaemon => "予定されたバックアップをサービスとして実行し続ける",
+            Msg::AboutDiff => "2つのバックアップの間で変更されたファイルを表示する",
+            Msg::AboutExport => "バックアップ内のファイルをtarやzip形式のアーカイブに書き出す",
+            Msg::AboutGenMan => "全てのコマンドのmanページを生成する",
+            Msg::AboutGrep => "バックアップ済みのファイルから正規表現にマッチする行を探す",
+            Msg::AboutHistory => "履歴を表示する",
+            Msg::AboutIgnore => "Bankの除外リストを管理する",
+            Msg::AboutIgnoreImport => ".gitignoreの規則を変換してBankの除外リストに追加する",
+            Msg::AboutInfo => "各種情報を表示する",
+            Msg::AboutInit => "リポジトリやBankを作成する",
+            Msg::AboutInitBank => "Bankを作成する",
+            Msg::AboutInitRepo => "リポジトリを作成する",
+            Msg::AboutKeyring => "OSのキーリングに保存する秘密情報を管理する",
+            Msg::AboutKeyringDelete => "OSのキーリングから秘密情報を削除する",
+            Msg::AboutKeyringSet => "標準入力から読み込んだ秘密情報をOSのキーリングに保存する",
+            Msg::AboutList => "Bankの一覧を表示する",
+            Msg::AboutLs => "バックアップ内のファイルを一覧表示する",
+            Msg::AboutParity => "全てのオブジェクトに破損を修復するためのパリティを付ける",
+            Msg::AboutRestore => "ファイルを復元する",
+            Msg::AboutRunDue => "予定時刻を過ぎたBankをバックアップする",
+            Msg::AboutScrub => {
+                "最後の検証から最も時間が経ったオブジェクトから、時間の上限まで検証する"
+            }
+            Msg::AboutSearch => "指定した単語を含むバックアップ済みのテキストファイルを探す",
+            Msg::AboutServe => "リポジトリの内容をHTTP APIで提供する",
+            Msg::AboutService => "予定されたバックアップを実行するWindowsサービスを管理する",
+            Msg::AboutServiceInstall => "sbakをWindowsサービスとして登録する",
+            Msg::AboutServiceRun => {
+                "Windowsサービスとして実行する（サービス制御マネージャーから起動される）"
+            }
+            Msg::AboutServiceUninstall => "Windowsサービスを停止して登録を削除する",
+            Msg::AboutShell => "バックアップの内容を対話的に閲覧する",
+            Msg::AboutStatus => "次のバックアップで追加・更新・削除されるファイルを表示する",
+            Msg::AboutUpgrade => "古い形式のエントリを現在の形式に書き換える",
+            Msg::AboutVerify => "リポジトリのオブジェクトが壊れていないか検証する",
+            Msg::AboutWhyBig => "リポジトリの容量増加の要因を調べる",
+
+            Msg::BankAlreadyExists => "Bank '{}' は既に存在します。",
+            Msg::BankMoved => "Bank '{}' の {} 件の履歴を {} に複製しました ({} 個のオブジェクト, {} 書き込み)",
+            Msg::BankRemoved => "Bank '{}' を {} から削除しました",
+            Msg::ConfigWritten => "設定ファイルを {} に書き込みました",
+            Msg::ContentIndexUpdated => "内容の索引に {} 個のファイルを登録しました",
+            Msg::DiffNoChanges => "変更はありません",
+            Msg::DiffStatTotal => "追加 {} 件, 更新 {} 件, 削除 {} 件, {}",
+            Msg::ExportDone => "{} 個のファイルと {} 個のディレクトリ ({}) を {} に書き出しました",
+            Msg::HistoryAnnotated => "履歴 {} にメモを付けました",
+            Msg::HistoryMemoCleared => "履歴 {} のメモを削除しました",
+            Msg::IgnoreImported => "{} 個のパターンを {} からBank '{}' に取り込みました。確認が必要な行が {} 行あります",
+            Msg::InventorySaved => "変更された {} 個のファイルを {} からの棚卸しとして記録",
+            Msg::KeyringDeleted => "キーリングから '{}' を削除しました",
+            Msg::KeyringNotStored => "キーリングに '{}' は保存されていません",
+            Msg::KeyringPrompt => "'{}' の値: ",
+            Msg::KeyringStored => "キーリングに '{}' を保存しました",
+            Msg::LastBackupAt => "最終バックアップ {}",
+            Msg::ManPagesWritten => "{} 個のmanページを {} に書き出しました",
+            Msg::NoBackups => "バックアップなし",
+            Msg::ParityDisabled => "新しいオブジェクトにはパリティを付けません",
+            Msg::ParityDone => "{} 個のオブジェクトにパリティを付け、{} 個が失敗しました",
+            Msg::PromptRepositoryPath => "リポジトリのパス",
+            Msg::PromptLogOutput => "ログの出力先",
+            Msg::PromptLogLevel => "ログレベル",
+            Msg::PromptBankName => "例として設定するBank名",
+            Msg::PickerNumber => "番号 (空欄で中止): ",
+            Msg::PickerInvalidNumber => "無効な番号です: {}",
+            Msg::RepoAlreadyExists => "リポジトリ {} は既に存在します。",
+            Msg::RepoSetDefault => "リポジトリ {} を既定として {} に書き込みました",
+            Msg::RestoreSelectHistory => "'{}' の復元する履歴を選択してください",
+            Msg::RestoreSymlinks => {
+                "{} 個のシンボリックリンクを作成し、{} 個をジャンクションとして作成しました。{} 個は飛ばしました"
+            }
+            Msg::ScrubDone => {
+                "{} 個のオブジェクト ({}) を検証し、{} 個が失敗しました。{} 個は次回に検証します"
+            }
+            Msg::ServeListening => "http://{} で待ち受けています",
+            Msg::ServiceInstalled => "サービス '{}' を登録しました",
+            Msg::ServiceUninstalled => "サービス '{}' を削除しました",
+            Msg::ShellHelp => {
+                "コマンド:
+  ls [パス]                 ディレクトリの内容を一覧表示する
+  cd [パス]                 現在のディレクトリを変更する (省略時はルート)
+  pwd                       現在のディレクトリを表示する
+  cat <ファイル>            ファイルの内容を標準出力に書き出す
+  get <パス> [手元のパス]   ファイルやディレクトリを手元に取り出す
+  help                      このヘルプを表示する
+  exit                      シェルを終了する"
+            }
+            Msg::ShellSaved => "'{}' を {} に取り出しました",
+            Msg::StatusAdded => "追加: {} 個のファイル ({})",
+            Msg::StatusNoChanges => "前回のバックアップから変更はありません",
+            Msg::StatusRemoved => "削除: {} 個のファイル ({})",
+            Msg::StatusUpdated => "更新: {} 個のファイル ({})",
+            Msg::UpgradeDone => "履歴 {}/{} 件、エントリ {}/{} 件を書き換えました",
+            Msg::UpgradeDryRun => "履歴 {}/{} 件、エントリ {}/{} 件が書き換え対象です",
+            Msg::VerifyDone => "{} 個のオブジェクト ({}) を検証し、{} 個が失敗しました",
+
+            Msg::InfoVersion => "バージョン:",
+            Msg::InfoConfig => "設定:",
+            Msg::InfoConfigSearchPaths => "設定ファイルの検索パス:",
+            Msg::InfoLoadedConfigFiles => "読み込まれた設定ファイル:",
+
+            Msg::WhyBigGrowth => "直近 {} 件の履歴での増加量: {} (うちディレクトリエントリ {})",
+            Msg::WhyBigLargest => "新たに保存された大きいファイル:",
+            Msg::WhyBigFrequent => "変更回数の多いファイル:",
+            Msg::WhyBigByDirectory => "ディレクトリごとの増加量:",
+        }
+    }
+}
+
+impl fmt::Display for Msg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.text())
+    }
+}
@@ -0,0 +1,30 @@
+use super::*;
+
+#[test]
+fn test_parse_lang() {
+    let cases = vec![
+        ("en", Some(Lang::En)),
+        ("en_US.UTF-8", Some(Lang::En)),
+        ("C", Some(Lang::En)),
+        ("ja", Some(Lang::Ja)),
+        ("ja_JP.UTF-8", Some(Lang::Ja)),
+        ("JA-jp", Some(Lang::Ja)),
+        ("fr_FR.UTF-8", None),
+    ];
+
+    for (s, to_be) in cases {
+        assert_eq!(to_be, s.parse::<Lang>().ok(), "input: {}", s);
+    }
+}
+
+#[test]
+fn test_fill() {
+    assert_eq!(
+        "1/2 histories, 3/4 entries upgraded",
+        Msg::UpgradeDone.fill(&[&1, &2, &3, &4])
+    );
+    assert_eq!(
+        "bank 'home' already exists.",
+        Msg::BankAlreadyExists.fill(&[&"home"])
+    );
+}
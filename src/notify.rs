@@ -0,0 +1,317 @@
+//! バックアップなどの実行結果を外部に通知する。
+//!
+//! 通知の送信には`curl`コマンドを使用するため、`curl`が`PATH`から実行できる必要がある。
+//! 通知はWebhookとメールで送信でき、メールはSMTPサーバーを経由して送る。
+//! 送信に失敗しても実行結果自体は変えず、警告をログに記録する。
+
+use std::error;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::process::{Child, Command, Stdio};
+
+use chrono::{Local, TimeZone};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, Mail};
+use crate::core::repo::ScanStats;
+use crate::core::timestamp::Timestamp;
+use crate::util::host::hostname;
+
+#[cfg(test)]
+mod test;
+
+const CURL: &str = "curl";
+
+// 1件の通知の送信にかける時間の上限(秒)
+const TIMEOUT_SECS: &str = "30";
+
+/// 通知を送る条件
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyOn {
+    /// 常に送る
+    #[default]
+    Always,
+    /// 成功した場合のみ送る
+    Success,
+    /// 失敗した場合のみ送る
+    Failure,
+}
+
+impl NotifyOn {
+    /// 実行結果が`status`の場合に通知を送るかどうかを返す。
+    pub fn matches(self, status: Status) -> bool {
+        match self {
+            NotifyOn::Always => true,
+            NotifyOn::Success => status == Status::Success,
+            NotifyOn::Failure => status == Status::Failure,
+        }
+    }
+}
+
+/// 実行結果の成否
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    /// 成功
+    Success,
+    /// 失敗
+    Failure,
+}
+
+/// 通知する実行結果
+///
+/// Webhookには、このままJSONとして送信される。
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// 実行したサブコマンドの名前
+    pub command: String,
+    /// 対象のBankの名前
+    ///
+    /// リポジトリ全体が対象の場合は`None`となる。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bank: Option<String>,
+    /// 成否
+    pub status: Status,
+    /// 実行したマシンのホスト名
+    pub host: String,
+    /// 通知の生成時刻
+    pub timestamp: u64,
+    /// スキャンの統計
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<ScanStats>,
+    /// 失敗の原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 失敗の原因の原因を、近いものから順に並べたもの
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub causes: Vec<String>,
+}
+
+impl Report {
+    /// 成功した実行結果を生成する。
+    pub fn success(command: &str, bank: Option<&str>, stats: Option<ScanStats>) -> Report {
+        Report {
+            stats,
+            ..Report::new(command, bank, Status::Success)
+        }
+    }
+
+    /// `err`で失敗した実行結果を生成する。
+    pub fn failure(command: &str, bank: Option<&str>, err: &dyn error::Error) -> Report {
+        let mut causes = Vec::new();
+        let mut source = err.source();
+        while let Some(e) = source {
+            causes.push(e.to_string());
+            source = e.source();
+        }
+
+        Report::failure_message(command, bank, err.to_string(), causes)
+    }
+
+    /// エラー型を伴わない失敗の実行結果を、メッセージと原因の一覧から生成する。
+    pub fn failure_message(
+        command: &str,
+        bank: Option<&str>,
+        error: String,
+        causes: Vec<String>,
+    ) -> Report {
+        Report {
+            error: Some(error),
+            causes,
+            ..Report::new(command, bank, Status::Failure)
+        }
+    }
+
+    fn new(command: &str, bank: Option<&str>, status: Status) -> Report {
+        Report {
+            command: command.to_owned(),
+            bank: bank.map(|b| b.to_owned()),
+            status,
+            host: hostname(),
+            timestamp: Timestamp::now().map_or(0, |t| t.unix_epoch()),
+            stats: None,
+            error: None,
+            causes: Vec::new(),
+        }
+    }
+}
+
+/// 設定されたWebhookとメールのうち、条件に合うもの全てに`report`を送信する。
+pub fn notify(config: &Config, report: &Report) {
+    for hook in config.webhooks() {
+        if !hook.on().matches(report.status) {
+            continue;
+        }
+
+        match post_json(hook.url(), report) {
+            Ok(()) => info!("webhook sent to {}", hook.url()),
+            Err(e) => warn!("failed to send webhook to {}: {}", hook.url(), e),
+        }
+    }
+
+    if let Some(mail) = config.mail() {
+        if mail.on().matches(report.status) {
+            match send_mail(&mail, report) {
+                Ok(()) => info!("mail sent to {}", mail.to().join(", ")),
+                Err(e) => warn!("failed to send mail via {}: {}", mail.smtp_url(), e),
+            }
+        }
+    }
+}
+
+// `url`に`report`をJSONとしてPOSTする。
+fn post_json(url: &str, report: &Report) -> io::Result<()> {
+    let body = serde_json::to_vec(report)?;
+
+    let mut child = Command::new(CURL)
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--max-time",
+            TIMEOUT_SECS,
+            "--header",
+            "Content-Type: application/json",
+            "--data-binary",
+            "@-",
+            "--url",
+            url,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(&body)?;
+
+    wait_curl(child)
+}
+
+// `mail`の設定に従い、`report`の要約をメールで送信する。
+fn send_mail(mail: &Mail, report: &Report) -> io::Result<()> {
+    let message = mail_message(mail, report);
+
+    let mut cmd = Command::new(CURL);
+    cmd.args([
+        "--silent",
+        "--show-error",
+        "--max-time",
+        TIMEOUT_SECS,
+        "--url",
+        mail.smtp_url(),
+        "--mail-from",
+        mail.from(),
+        "--upload-file",
+        "-",
+    ]);
+    for to in mail.to() {
+        cmd.arg("--mail-rcpt").arg(to);
+    }
+    if mail.starttls() {
+        cmd.arg("--ssl-reqd");
+    }
+
+    // パスワードがプロセス一覧に表示されないよう、認証情報はcurlの設定ファイル経由で渡す
+    let mut auth = None;
+    if let Some(username) = mail.username() {
+        let mut f = tempfile::NamedTempFile::new()?;
+        let user = format!("{}:{}", username, mail.password().unwrap_or(""));
+        writeln!(f, "user = \"{}\"", escape_curl_config(&user))?;
+        f.flush()?;
+        cmd.arg("--config").arg(f.path());
+        auth = Some(f);
+    }
+
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(message.as_bytes())?;
+
+    let result = wait_curl(child);
+    drop(auth);
+    result
+}
+
+// `report`の要約をメールのメッセージ(ヘッダーと本文)に変換する。
+fn mail_message(mail: &Mail, report: &Report) -> String {
+    let target = report.bank.as_deref().unwrap_or("repository");
+    let result = match report.status {
+        Status::Success => "succeeded",
+        Status::Failure => "failed",
+    };
+    let date = Local
+        .timestamp_opt(report.timestamp as i64, 0)
+        .single()
+        .unwrap_or_else(|| Local.timestamp_opt(0, 0).unwrap());
+
+    let mut msg = String::new();
+    let _ = write!(msg, "From: {}\r\n", mail.from());
+    let _ = write!(msg, "To: {}\r\n", mail.to().join(", "));
+    let _ = write!(
+        msg,
+        "Subject: [sbak] {} of {} {} on {}\r\n",
+        report.command, target, result, report.host
+    );
+    let _ = write!(msg, "Date: {}\r\n", date.to_rfc2822());
+    msg.push_str("MIME-Version: 1.0\r\n");
+    msg.push_str("Content-Type: text/plain; charset=utf-8\r\n");
+    msg.push_str("\r\n");
+
+    let _ = write!(msg, "command: {}\r\n", report.command);
+    let _ = write!(msg, "target:  {}\r\n", target);
+    let _ = write!(msg, "host:    {}\r\n", report.host);
+    let _ = write!(msg, "time:    {}\r\n", date.format("%Y-%m-%d %H:%M:%S %z"));
+    let _ = write!(msg, "result:  {}\r\n", result);
+
+    if let Some(ref stats) = report.stats {
+        msg.push_str("\r\n");
+        let _ = write!(msg, "duration:   {} ms\r\n", stats.duration_ms);
+        let _ = write!(msg, "files:      {}\r\n", stats.files);
+        let _ = write!(msg, "dirs:       {}\r\n", stats.dirs);
+        let _ = write!(msg, "bytes read: {}\r\n", stats.bytes_read);
+        let _ = write!(msg, "new bytes:  {}\r\n", stats.new_bytes);
+        let _ = write!(msg, "errors:     {}\r\n", stats.errors);
+    }
+
+    if let Some(ref error) = report.error {
+        msg.push_str("\r\n");
+        let _ = write!(msg, "error: {}\r\n", error);
+        for cause in &report.causes {
+            let _ = write!(msg, "  caused by: {}\r\n", cause);
+        }
+    }
+
+    msg
+}
+
+// curlの設定ファイルの二重引用符で囲まれた値として使えるように文字列をエスケープする。
+fn escape_curl_config(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// curlの終了を待ち、失敗していればエラー出力の内容をエラーとして返す。
+fn wait_curl(child: Child) -> io::Result<()> {
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "curl exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
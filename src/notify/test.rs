@@ -0,0 +1,154 @@
+use super::*;
+
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::net::TcpListener;
+use std::thread;
+
+#[derive(Debug)]
+struct Outer(Inner);
+
+#[derive(Debug)]
+struct Inner;
+
+impl fmt::Display for Outer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "outer")
+    }
+}
+
+impl error::Error for Outer {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+impl fmt::Display for Inner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "inner")
+    }
+}
+
+impl error::Error for Inner {}
+
+#[test]
+fn test_notify_on() {
+    assert!(NotifyOn::Always.matches(Status::Success));
+    assert!(NotifyOn::Always.matches(Status::Failure));
+    assert!(NotifyOn::Success.matches(Status::Success));
+    assert!(!NotifyOn::Success.matches(Status::Failure));
+    assert!(!NotifyOn::Failure.matches(Status::Success));
+    assert!(NotifyOn::Failure.matches(Status::Failure));
+}
+
+#[test]
+fn test_report_failure() {
+    let report = Report::failure("backup", Some("bank"), &Outer(Inner));
+    assert_eq!(report.status, Status::Failure);
+    assert_eq!(report.error.as_deref(), Some("outer"));
+    assert_eq!(report.causes, vec!["inner".to_owned()]);
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["status"], "failure");
+    assert_eq!(json["bank"], "bank");
+    assert!(json.get("stats").is_none());
+}
+
+#[test]
+fn test_post_json() {
+    if Command::new(CURL).arg("--version").output().is_err() {
+        eprintln!("skip: curl is not available");
+        return;
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut len = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                len = v.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body).unwrap();
+
+        let mut stream = stream;
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        (request_line, body)
+    });
+
+    let stats = ScanStats {
+        files: 3,
+        ..ScanStats::default()
+    };
+    post_json(&url, &Report::success("backup", Some("bank"), Some(stats))).unwrap();
+
+    let (request_line, body) = server.join().unwrap();
+    assert!(request_line.starts_with("POST /hook "));
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["status"], "success");
+    assert_eq!(json["stats"]["files"], 3);
+}
+
+fn mail_config(extra: &str) -> Mail {
+    let toml = format!(
+        "[mail]\nsmtp_url = \"{}\"\nfrom = \"sbak@example.com\"\nto = [\"admin@example.com\", \"sub@example.com\"]\n{}",
+        "smtp://127.0.0.1:25", extra
+    );
+    let config: Config = toml::from_str(&toml).unwrap();
+    config.mail().unwrap()
+}
+
+#[test]
+fn test_mail_config() {
+    let config: Config = toml::from_str("[mail]\nsmtp_url = \"smtp://localhost\"\n").unwrap();
+    assert!(config.mail().is_none());
+
+    let mail = mail_config("");
+    assert_eq!(mail.on(), NotifyOn::Failure);
+    assert!(!mail.starttls());
+    assert_eq!(mail.username(), None);
+
+    let mail = mail_config("on = \"always\"\nstarttls = true\nusername = \"user\"\n");
+    assert_eq!(mail.on(), NotifyOn::Always);
+    assert!(mail.starttls());
+    assert_eq!(mail.username(), Some("user"));
+}
+
+#[test]
+fn test_mail_message() {
+    let mail = mail_config("");
+    let stats = ScanStats {
+        files: 3,
+        ..ScanStats::default()
+    };
+    let msg = mail_message(&mail, &Report::success("backup", Some("bank"), Some(stats)));
+    assert!(msg.contains("To: admin@example.com, sub@example.com\r\n"));
+    assert!(msg.contains("Subject: [sbak] backup of bank succeeded on "));
+    assert!(msg.contains("files:      3\r\n"));
+    assert!(!msg.contains("error:"));
+
+    let msg = mail_message(&mail, &Report::failure("verify", None, &Outer(Inner)));
+    assert!(msg.contains("Subject: [sbak] verify of repository failed on "));
+    assert!(msg.contains("error: outer\r\n  caused by: inner\r\n"));
+    assert!(!msg.contains("files:"));
+}
+
+#[test]
+fn test_escape_curl_config() {
+    assert_eq!(escape_curl_config("user:pass"), "user:pass");
+    assert_eq!(escape_curl_config("a\"b\\c\n"), "a\\\"b\\\\c\\n");
+}
@@ -0,0 +1,174 @@
+//! 端末への進捗表示
+
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+use crate::smalllog;
+use crate::util::size::Size;
+
+const BAR_WIDTH: usize = 20;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 処理したファイル数とバイト数を標準エラー出力の最終行に表示する。
+///
+/// 表示は[`smalllog`](../smalllog/index.html)の状態行として行われるため、ログの出力と混ざらない。
+/// 非表示の`Progress`は集計のみを行う。
+#[derive(Debug)]
+pub struct Progress {
+    visible: bool,
+    state: RefCell<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    label: String,
+    total: Option<u64>,
+    bytes: u64,
+    files: u64,
+    start: Instant,
+    last_draw: Option<Instant>,
+}
+
+impl Progress {
+    /// `label`を先頭に表示する`Progress`を生成する。
+    ///
+    /// 標準エラー出力が端末でない場合や、[`smalllog`](../smalllog/index.html)がロガーとして設定されていない場合は非表示になる。
+    pub fn new(label: &str) -> Progress {
+        Progress {
+            visible: smalllog::is_installed() && atty::is(atty::Stream::Stderr),
+            state: RefCell::new(State {
+                label: label.to_owned(),
+                total: None,
+                bytes: 0,
+                files: 0,
+                start: Instant::now(),
+                last_draw: None,
+            }),
+        }
+    }
+
+    /// 非表示の`Progress`を生成する。
+    pub fn hidden() -> Progress {
+        let mut progress = Progress::new("");
+        progress.visible = false;
+        progress
+    }
+
+    /// 進捗を表示するかどうかを返す。
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// 処理予定の総バイト数を設定する。
+    ///
+    /// 設定した場合は進捗バーと残り時間が表示される。
+    pub fn set_total(&self, bytes: u64) {
+        self.state.borrow_mut().total = Some(bytes);
+        self.draw(true);
+    }
+
+    /// ファイル1つの処理が終わったことを記録する。
+    pub fn add_file(&self, bytes: u64) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.files += 1;
+            state.bytes += bytes;
+        }
+        self.draw(false);
+    }
+
+    /// 処理したファイル数を返す。
+    pub fn files(&self) -> u64 {
+        self.state.borrow().files
+    }
+
+    /// 処理したバイト数を返す。
+    pub fn bytes(&self) -> u64 {
+        self.state.borrow().bytes
+    }
+
+    /// 進捗表示を消去する。
+    pub fn finish(&self) {
+        if self.visible && self.state.borrow().last_draw.is_some() {
+            smalllog::set_status(None);
+            self.state.borrow_mut().last_draw = None;
+        }
+    }
+
+    fn draw(&self, force: bool) {
+        if !self.visible {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut state = self.state.borrow_mut();
+        if let Some(last) = state.last_draw {
+            if !force && now.duration_since(last) < REDRAW_INTERVAL {
+                return;
+            }
+        }
+        state.last_draw = Some(now);
+
+        smalllog::set_status(Some(state.line(now)));
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+impl State {
+    fn line(&self, now: Instant) -> String {
+        let elapsed = now.duration_since(self.start).as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            (self.bytes as f64 / elapsed) as u64
+        } else {
+            0
+        };
+
+        let mut line = format!("{}: ", self.label);
+
+        if let Some(total) = self.total {
+            let ratio = if total > 0 {
+                (self.bytes as f64 / total as f64).min(1.0)
+            } else {
+                1.0
+            };
+            let filled = (ratio * BAR_WIDTH as f64) as usize;
+            line.push_str(&format!(
+                "[{}{}] {:3}% ",
+                "#".repeat(filled),
+                "-".repeat(BAR_WIDTH - filled),
+                (ratio * 100.0) as u64
+            ));
+        }
+
+        line.push_str(&format!(
+            "{} files, {}, {}/s",
+            self.files,
+            Size::from(self.bytes),
+            Size::from(rate)
+        ));
+
+        if let Some(total) = self.total {
+            if rate > 0 && total > self.bytes {
+                line.push_str(&format!(
+                    ", ETA {}",
+                    format_duration((total - self.bytes) / rate)
+                ));
+            }
+        }
+
+        line
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
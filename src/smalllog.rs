@@ -1,13 +1,16 @@
 //! 簡易ロガー
 
 use std::env::var;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::{self, stderr, Stderr, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
+use std::time::Instant;
 
+use chrono::Local;
 use lazy_static::{initialize, lazy_static};
-use log::{set_logger, set_max_level, warn, LevelFilter, Log, Metadata, Record};
+use log::{set_logger, set_max_level, warn, LevelFilter, Log, Metadata, Record, SetLoggerError};
 
 lazy_static! {
     static ref STATE: Mutex<LoggerState> = {
@@ -15,18 +18,41 @@ lazy_static! {
             out: Out::Stderr(stderr()),
             level: LevelFilter::Trace,
             show_detail_level: LevelFilter::Debug,
+            time_format: TimeFormat::None,
+            start: Instant::now(),
+            status: None,
         })
     };
 }
 
 const LOGGER: Logger = Logger {};
 
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+// カーソルを行頭に戻して行末まで消去する。
+const CLEAR_LINE: &str = "\r\x1b[K";
+
 /// ロガーを初期化する。
 ///
 /// 環境変数`sbak_log`が設定されている場合、その指定レベルに設定する。
+///
+/// # Panics
+/// 既に他のロガーが設定されている場合はパニックする。
 pub fn init() {
+    try_init().unwrap();
+}
+
+/// ロガーの初期化を試みる。
+///
+/// ライブラリとして組み込む場合など、既に別のロガーが設定されている可能性がある場合に使用する。
+///
+/// # Failures
+/// 既に他のロガーが設定されている場合はエラーを返す。
+/// その場合、設定済みのロガーはそのまま使用される。
+pub fn try_init() -> Result<(), SetLoggerError> {
     initialize(&STATE);
-    set_logger(&LOGGER).unwrap();
+    set_logger(&LOGGER)?;
+    INSTALLED.store(true, Ordering::SeqCst);
     set_max_level(LevelFilter::Trace);
 
     if let Ok(sbak_log) = var("SBAK_LOG") {
@@ -40,6 +66,13 @@ pub fn init() {
             s => warn!("unknown log level SBAK_LOG={}", s),
         }
     }
+
+    Ok(())
+}
+
+/// このロガーが`log`クレートのロガーとして設定されているかを返す。
+pub fn is_installed() -> bool {
+    INSTALLED.load(Ordering::SeqCst)
 }
 
 /// ログ出力先を標準エラー出力にする。
@@ -48,14 +81,50 @@ pub fn use_stderr() {
     state.out = Out::Stderr(stderr());
 }
 
-/// ログ出力先を標準エラー出力にする。
+/// ログ出力先をファイルにする。
+///
+/// `append`が`true`の場合は既存のファイルに追記し、`false`の場合は内容を消去してから書き込む。
+/// 既に同じパスのファイルに出力している場合は開き直さない。
 ///
 /// # Failures
 /// ファイルのオープンに失敗した場合、エラーを返す。
-pub fn use_file<P: AsRef<Path>>(path: P) -> Result<(), io::Error> {
-    let f = File::create(&path)?;
+/// その場合、出力先は変更されない。
+pub fn use_file<P: AsRef<Path>>(path: P, append: bool) -> Result<(), io::Error> {
+    let path = path.as_ref();
+
+    let mut state = STATE.lock().unwrap();
+    if let Out::File(_, ref current) = state.out {
+        if current == path {
+            return Ok(());
+        }
+    }
+
+    let f = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?;
+    state.out = Out::File(f, path.to_owned());
+    Ok(())
+}
+
+/// ログ出力先をsyslogにする。
+///
+/// systemdを使用している環境では、syslogに送られたログはjournaldにも記録される。
+/// Windowsではsyslogの代わりにイベントログ(ソース名`sbak`)に記録する。
+///
+/// # Failures
+/// syslogに対応していない環境ではエラーを返す。
+/// その場合、出力先は変更されない。
+pub fn use_syslog() -> Result<(), io::Error> {
     let mut state = STATE.lock().unwrap();
-    state.out = Out::File(f);
+    if let Out::Syslog = state.out {
+        return Ok(());
+    }
+
+    syslog::open()?;
+    state.out = Out::Syslog;
     Ok(())
 }
 
@@ -75,6 +144,52 @@ pub fn set_show_detail_level(level: LevelFilter) {
     state.show_detail_level = level;
 }
 
+/// ログの各行の先頭に付ける時刻の形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// 時刻を付けない
+    None,
+    /// 現在時刻
+    Wall,
+    /// 起動からの経過時間
+    Elapsed,
+    /// 現在時刻と起動からの経過時間
+    Both,
+}
+
+/// ログの各行の先頭に付ける時刻の形式を設定する。
+///
+/// デフォルト値は`TimeFormat::None`。
+pub fn set_time_format(format: TimeFormat) {
+    let mut state = STATE.lock().unwrap();
+    state.time_format = format;
+}
+
+/// 標準エラー出力の最終行に表示する状態行を設定する。
+///
+/// ログが標準エラー出力に書かれる際は、状態行を一旦消去してから書き込み、その後に状態行を再表示する。
+/// `None`を指定すると状態行を消去する。
+///
+/// このロガーが設定されていない場合は何もしない。
+pub fn set_status(status: Option<String>) {
+    if !is_installed() {
+        return;
+    }
+
+    let mut state = STATE.lock().unwrap();
+    let mut err = stderr();
+
+    if state.status.is_some() {
+        write!(err, "{}", CLEAR_LINE).unwrap();
+    }
+    if let Some(ref line) = status {
+        write!(err, "{}", line).unwrap();
+    }
+    err.flush().unwrap();
+
+    state.status = status;
+}
+
 struct Logger;
 
 impl Log for Logger {
@@ -90,14 +205,43 @@ impl Log for Logger {
 
         let mut state = STATE.lock().unwrap();
         let show_detail = record.level() >= state.show_detail_level;
-        let w = state.out.writer();
+        let time_prefix = match state.time_format {
+            TimeFormat::None => None,
+            TimeFormat::Wall => Some(wall_time()),
+            TimeFormat::Elapsed => Some(elapsed_time(state.start)),
+            TimeFormat::Both => Some(format!("{} {}", wall_time(), elapsed_time(state.start))),
+        };
+        let status = match state.out {
+            Out::Stderr(_) => state.status.clone(),
+            Out::File(..) | Out::Syslog => None,
+        };
+
+        let w = match state.out.writer() {
+            Some(w) => w,
+            None => {
+                // 時刻やレベルはsyslog側で記録される。
+                syslog::write(
+                    record.level(),
+                    &format!("{} : {}", record.target(), record.args()),
+                );
+                return;
+            }
+        };
+
+        if status.is_some() {
+            write!(w, "{}", CLEAR_LINE).unwrap();
+        }
+
+        if let Some(ref prefix) = time_prefix {
+            write!(w, "{} ", prefix).unwrap();
+        }
 
         if show_detail {
             writeln!(
                 w,
                 "[ {:5} ] {}:{} : {}",
                 record.level(),
-                record.module_path().unwrap_or("<unknown module>"),
+                record.target(),
                 record
                     .line()
                     .map(|n| n.to_string())
@@ -110,36 +254,176 @@ impl Log for Logger {
                 w,
                 "[ {:5} ] {} : {}",
                 record.level(),
-                record.module_path().unwrap_or("<unknown module>"),
+                record.target(),
                 record.args()
             )
             .unwrap();
         }
+
+        if let Some(line) = status {
+            write!(w, "{}", line).unwrap();
+            w.flush().unwrap();
+        }
     }
 
     fn flush(&self) {
         let mut state = STATE.lock().unwrap();
-        let w = state.out.writer();
-        w.flush().unwrap();
+        if let Some(w) = state.out.writer() {
+            w.flush().unwrap();
+        }
     }
 }
 
+fn wall_time() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string()
+}
+
+fn elapsed_time(start: Instant) -> String {
+    format!("+{:.3}s", start.elapsed().as_secs_f64())
+}
+
 struct LoggerState {
     out: Out,
     level: LevelFilter,
     show_detail_level: LevelFilter,
+    time_format: TimeFormat,
+    start: Instant,
+    status: Option<String>,
 }
 
 enum Out {
     Stderr(Stderr),
-    File(File),
+    File(File, PathBuf),
+    Syslog,
 }
 
 impl Out {
-    fn writer(&mut self) -> &mut dyn Write {
+    fn writer(&mut self) -> Option<&mut dyn Write> {
         match self {
-            Out::Stderr(ref mut w) => w,
-            Out::File(ref mut w) => w,
+            Out::Stderr(ref mut w) => Some(w),
+            Out::File(ref mut w, _) => Some(w),
+            Out::Syslog => None,
         }
     }
 }
+
+#[cfg(unix)]
+mod syslog {
+    use std::ffi::CString;
+    use std::io;
+
+    use log::Level;
+
+    const IDENT: &[u8] = b"sbak\0";
+    const FORMAT: &[u8] = b"%s\0";
+
+    pub fn open() -> Result<(), io::Error> {
+        unsafe {
+            libc::openlog(
+                IDENT.as_ptr() as *const libc::c_char,
+                libc::LOG_PID,
+                libc::LOG_USER,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn write(level: Level, message: &str) {
+        let priority = match level {
+            Level::Error => libc::LOG_ERR,
+            Level::Warn => libc::LOG_WARNING,
+            Level::Info => libc::LOG_INFO,
+            Level::Debug | Level::Trace => libc::LOG_DEBUG,
+        };
+        // NUL文字を含むとCStringにできないため置き換える。
+        let message = CString::new(message.replace('\0', "\\0")).unwrap();
+
+        unsafe {
+            libc::syslog(
+                priority,
+                FORMAT.as_ptr() as *const libc::c_char,
+                message.as_ptr(),
+            );
+        }
+    }
+}
+
+// Windowsではsyslogの代わりにイベントログに記録する。
+#[cfg(windows)]
+mod syslog {
+    use std::ffi::OsStr;
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use log::Level;
+    use winapi::um::winbase::{RegisterEventSourceW, ReportEventW};
+    use winapi::um::winnt::{
+        EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE, HANDLE,
+    };
+
+    const SOURCE: &str = "sbak";
+
+    // イベントソースのハンドル（`HANDLE`はスレッド間で共有できないため、アドレスとして保持する）
+    static EVENT_SOURCE: AtomicUsize = AtomicUsize::new(0);
+
+    pub fn open() -> Result<(), io::Error> {
+        if EVENT_SOURCE.load(Ordering::SeqCst) != 0 {
+            return Ok(());
+        }
+
+        let source: Vec<u16> = OsStr::new(SOURCE).encode_wide().chain(Some(0)).collect();
+        let handle = unsafe { RegisterEventSourceW(ptr::null(), source.as_ptr()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        EVENT_SOURCE.store(handle as usize, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn write(level: Level, message: &str) {
+        let handle = EVENT_SOURCE.load(Ordering::SeqCst) as HANDLE;
+        if handle.is_null() {
+            return;
+        }
+
+        let event_type = match level {
+            Level::Error => EVENTLOG_ERROR_TYPE,
+            Level::Warn => EVENTLOG_WARNING_TYPE,
+            Level::Info | Level::Debug | Level::Trace => EVENTLOG_INFORMATION_TYPE,
+        };
+        let message: Vec<u16> = OsStr::new(message).encode_wide().chain(Some(0)).collect();
+        let mut strings = [message.as_ptr()];
+
+        unsafe {
+            ReportEventW(
+                handle,
+                event_type,
+                0,
+                0,
+                ptr::null_mut(),
+                1,
+                0,
+                strings.as_mut_ptr(),
+                ptr::null_mut(),
+            );
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod syslog {
+    use std::io;
+
+    use log::Level;
+
+    pub fn open() -> Result<(), io::Error> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "syslog isn't supported on this platform",
+        ))
+    }
+
+    pub fn write(_level: Level, _message: &str) {}
+}
@@ -1,47 +1,167 @@
 //! サブコマンドの基盤部分
 
 use std::collections::BTreeMap;
+use std::error;
 use std::fmt;
+use std::path::PathBuf;
 
-use clap::{App, ArgMatches};
+use clap::{crate_description, crate_name, App, AppSettings, Arg, ArgMatches};
+use log::error;
 
 use crate::config::Config;
+use crate::core::hash::HashID;
+use crate::core::repo::{self, Bank, History, Repository};
+use crate::error::{Categorized, Error, ErrorKind};
+use crate::progress::Progress;
 
+mod agent;
+mod annotate;
 mod backup;
+mod bank;
+mod check_ignore;
+mod config;
+mod daemon;
+mod diff;
+mod export;
+mod gen_man;
+mod grep;
 mod history;
+mod ignore;
 mod info;
 mod init;
+mod keyring;
 mod list;
+mod ls;
+mod parity;
 mod restore;
+mod run_due;
+mod scrub;
+mod search;
+mod serve;
+mod service;
+mod shell;
+mod status;
+mod upgrade;
+mod verify;
+mod why_big;
+
+#[cfg(test)]
+mod test;
 
 /// サブコマンドを表現するトレイト
 pub trait SubCmd {
     /// サブコマンドの名前を返す。
     fn name(&self) -> &str;
 
+    /// サブコマンドの別名を返す。
+    ///
+    /// 別名はヘルプに表示され、名前と同じように起動に使える。
+    fn aliases(&self) -> &[&'static str] {
+        &[]
+    }
+
     /// コマンドライン引数の定義を返す。
     fn command_args(&self) -> App;
 
-    /// サブコマンドを実行する。
+    /// サブコマンドを実行し、プロセスの終了コードを返す。
     ///
-    /// 完了後は制御を返さず、 [`std::process::exit`](https://doc.rust-lang.org/std/process/fn.exit.html) などでプロセスを終了する。
-    fn exec(&self, matches: &ArgMatches, config: Config) -> !;
+    /// プロセスの終了は呼び出し側で行う。
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult;
 }
 
+/// サブコマンドの実行結果
+///
+/// 成功した場合はプロセスの終了コードを持つ。
+pub type ExecResult = Result<i32, Error>;
+
 /// 組み込まれているサブコマンドすべてを含む [`SubCommandSet`](struct.SubCommandSet.html) を返す。
 pub fn sub_commands() -> SubCommandSet {
     let mut set = SubCommandSet::new();
 
+    set.append(agent::new());
+    set.append(annotate::new());
     set.append(backup::new());
+    set.append(bank::new());
+    set.append(check_ignore::new());
+    set.append(config::new());
+    set.append(daemon::new());
+    set.append(diff::new());
+    set.append(export::new());
+    set.append(gen_man::new());
+    set.append(grep::new());
     set.append(history::new());
+    set.append(ignore::new());
     set.append(init::new());
     set.append(info::new());
+    set.append(keyring::new());
     set.append(list::new());
+    set.append(ls::new());
+    set.append(parity::new());
     set.append(restore::new());
+    set.append(run_due::new());
+    set.append(scrub::new());
+    set.append(search::new());
+    set.append(serve::new());
+    set.append(service::new());
+    set.append(shell::new());
+    set.append(status::new());
+    set.append(upgrade::new());
+    set.append(verify::new());
+    set.append(why_big::new());
 
     set
 }
 
+/// `subs`をサブコマンドとして持つ、コマンド全体の引数の定義を返す。
+pub fn app<'a>(subs: &'a SubCommandSet, version: &'a str) -> App<'a, 'a> {
+    App::new(crate_name!())
+        .author("Igaguri <igagurimk@gmail.com>")
+        .about(crate_description!())
+        .version(version)
+        .global_setting(AppSettings::InferSubcommands)
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .takes_value(true)
+                .help("Extra config file"),
+        )
+        .arg(
+            Arg::with_name("repo")
+                .long("repo")
+                .takes_value(true)
+                .global(true)
+                .help("Overwrite repository path"),
+        )
+        .arg(
+            Arg::with_name("log_level")
+                .short("L")
+                .long("log-level")
+                .takes_value(true)
+                .help("Log level"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .conflicts_with("verbose")
+                .help("Only show errors"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .multiple(true)
+                .help("Show more logs (repeatable)"),
+        )
+        .arg(
+            Arg::with_name("no_keyring")
+                .long("no-keyring")
+                .help("Don't read secrets from OS keyring"),
+        )
+        .subcommands(subs.arg_defs())
+}
+
 /// サブコマンドの一覧を表現する
 #[derive(Default)]
 pub struct SubCommandSet {
@@ -57,18 +177,19 @@ impl<'a> SubCommandSet {
     }
 
     /// clapで使用するサブコマンドの定義リストを返す。
+    ///
+    /// 一意に定まる名前の先頭部分での起動は、呼び出し側で`AppSettings::InferSubcommands`を設定して有効にする。
     pub fn arg_defs(&'a self) -> impl Iterator<Item = App<'a, 'a>> {
-        self.table.iter().map(|(_, c)| c.command_args())
+        self.table
+            .values()
+            .map(|c| c.command_args().visible_aliases(c.aliases()))
     }
 
-    /// サブコマンド `name` を起動する。
+    /// サブコマンド `name` を起動し、 [`SubCmd::exec`](trait.SubCmd.html#tymethod.exec) の結果を返す。
     ///
-    /// `name` が一致したサブコマンドがある場合、 [`SubCmd::exec`](trait.SubCmd.html#tymethod.exec) が実行されるため制御は返らない。
-    /// 制御が返った場合、一致するサブコマンドは存在しない。
-    pub fn execute(&self, name: &str, matches: &ArgMatches, config: Config) {
-        if let Some(cmd) = self.table.get(name) {
-            cmd.exec(matches, config);
-        }
+    /// 一致するサブコマンドが存在しない場合は`None`を返す。
+    pub fn execute(&self, name: &str, matches: &ArgMatches, config: Config) -> Option<ExecResult> {
+        self.table.get(name).map(|cmd| cmd.exec(matches, config))
     }
 
     /// サブコマンドを追加する。
@@ -84,3 +205,264 @@ impl fmt::Debug for SubCommandSet {
         f.debug_set().entries(self.table.keys()).finish()
     }
 }
+
+/// サブコマンドの進捗表示に使う[`Progress`](../progress/struct.Progress.html)を返す。
+///
+/// `-q`が指定された場合は非表示になる。
+pub fn progress(config: &Config, label: &str) -> Progress {
+    if config.is_quiet() {
+        Progress::hidden()
+    } else {
+        Progress::new(label)
+    }
+}
+
+/// 操作対象のリポジトリのパスを返す。
+///
+/// コマンドライン引数`--repo`は起動時に設定に反映されるため、設定ファイルの`repository_path`より優先される。
+pub fn repository_path(config: &Config) -> Result<PathBuf, RepoSelectError> {
+    config
+        .repository_path()
+        .map(|p| p.to_owned())
+        .ok_or(RepoSelectError::NoPath)
+}
+
+/// 操作対象のリポジトリを開く。
+///
+/// パスは[`repository_path`](fn.repository_path.html)で決定する。
+pub fn open_repository(config: &Config) -> Result<Repository, RepoSelectError> {
+    Ok(Repository::open(repository_path(config)?)?)
+}
+
+/// 操作対象のBankの名前を決定する。
+///
+/// 以下の順に探し、最初に見つかったものを返す。
+///
+/// 1. コマンドライン引数`bank`
+/// 2. 設定ファイルの`default_bank`
+/// 3. リポジトリにBankが1つしかない場合、そのBank
+pub fn resolve_bank(
+    matches: &ArgMatches,
+    config: &Config,
+    repository: &Repository,
+) -> Result<String, BankSelectError> {
+    if let Some(name) = matches.value_of("bank").or_else(|| config.default_bank()) {
+        return Ok(name.to_owned());
+    }
+
+    let mut names = repository.bank_names()?;
+    match names.len() {
+        0 => Err(BankSelectError::NoBanks),
+        1 => Ok(names.pop().unwrap()),
+        _ => Err(BankSelectError::Ambiguous(names.into())),
+    }
+}
+
+/// 開けなかったBankのエラー`broken`を表示し、あれば最初のエラーの種類に応じた終了コードを返す。
+///
+/// 全てのBankを処理するサブコマンドで、開けたBankの処理を終えた後にまとめて報告するために使う。
+pub fn report_broken_banks(broken: &[repo::Error]) -> Option<i32> {
+    for e in broken {
+        error!("{}", e);
+    }
+    broken.first().map(|e| e.kind().exit_code())
+}
+
+/// 操作対象の履歴を決定する。
+///
+/// `revision`が指定された場合はそのハッシュ値のプレフィックスを持つ履歴を、そうでなければ最新の履歴を返す。
+pub fn select_history(bank: &Bank, revision: Option<&str>) -> Result<History, HistorySelectError> {
+    let hash_prefix = match revision {
+        Some(hash_prefix) => hash_prefix,
+        None => return bank.last_scan()?.ok_or(HistorySelectError::NoHistory),
+    };
+
+    let mut histories = bank.find_hash(hash_prefix)?;
+
+    let mut hashes = histories
+        .iter()
+        .map(|h| h.id().clone())
+        .collect::<Vec<HashID>>();
+    hashes.sort();
+    hashes.dedup();
+
+    match hashes.len() {
+        0 => Err(HistorySelectError::NotFound(hash_prefix.to_owned())),
+        1 => Ok(histories.pop().unwrap()),
+        _ => Err(HistorySelectError::Ambiguous(
+            hash_prefix.to_owned(),
+            hashes
+                .iter()
+                .map(|h| h.to_string())
+                .collect::<Vec<_>>()
+                .into(),
+        )),
+    }
+}
+
+/// 操作対象の履歴の決定で発生しうるエラー
+#[derive(Debug)]
+pub enum HistorySelectError {
+    /// Bankに履歴が存在しない
+    NoHistory,
+
+    /// 指定されたハッシュ値の履歴が存在しない
+    NotFound(String),
+
+    /// 指定されたハッシュ値に複数の履歴が一致した
+    Ambiguous(String, NameList),
+
+    /// リポジトリ操作エラーが発生
+    Repo(repo::Error),
+}
+
+impl fmt::Display for HistorySelectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HistorySelectError::NoHistory => write!(f, "no scans in bank"),
+            HistorySelectError::NotFound(msg) => write!(f, "no histories with hash {}", msg),
+            HistorySelectError::Ambiguous(a, b) => write!(f, "multiple hash matched {}: {}", a, b),
+            HistorySelectError::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for HistorySelectError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            HistorySelectError::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for HistorySelectError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            HistorySelectError::NoHistory => ErrorKind::NotFound,
+            HistorySelectError::NotFound(_) => ErrorKind::NotFound,
+            HistorySelectError::Ambiguous(..) => ErrorKind::Usage,
+            HistorySelectError::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl From<repo::Error> for HistorySelectError {
+    fn from(e: repo::Error) -> HistorySelectError {
+        HistorySelectError::Repo(e)
+    }
+}
+
+/// 操作対象のリポジトリの決定で発生しうるエラー
+#[derive(Debug)]
+pub enum RepoSelectError {
+    /// `--repo`も設定ファイルの`repository_path`も指定されていない
+    NoPath,
+
+    /// リポジトリ操作エラーが発生
+    Repo(repo::Error),
+}
+
+impl fmt::Display for RepoSelectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RepoSelectError::NoPath => write!(
+                f,
+                "no repository path, run inside repository or specify one with --repo or repository_path in config"
+            ),
+            RepoSelectError::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for RepoSelectError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RepoSelectError::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for RepoSelectError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            RepoSelectError::NoPath => ErrorKind::Usage,
+            RepoSelectError::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl From<repo::Error> for RepoSelectError {
+    fn from(e: repo::Error) -> RepoSelectError {
+        RepoSelectError::Repo(e)
+    }
+}
+
+/// 操作対象のBankの決定で発生しうるエラー
+#[derive(Debug)]
+pub enum BankSelectError {
+    /// リポジトリにBankが存在しない
+    NoBanks,
+
+    /// Bankが複数あり、どれを使うか決められない
+    Ambiguous(NameList),
+
+    /// リポジトリ操作エラーが発生
+    Repo(repo::Error),
+}
+
+impl fmt::Display for BankSelectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BankSelectError::NoBanks => write!(f, "no banks in repository"),
+            BankSelectError::Ambiguous(v) => write!(
+                f,
+                "multiple banks in repository, specify one with -b/--bank: {}",
+                v
+            ),
+            BankSelectError::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for BankSelectError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            BankSelectError::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for BankSelectError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            BankSelectError::NoBanks => ErrorKind::NotFound,
+            BankSelectError::Ambiguous(_) => ErrorKind::Usage,
+            BankSelectError::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl From<repo::Error> for BankSelectError {
+    fn from(e: repo::Error) -> BankSelectError {
+        BankSelectError::Repo(e)
+    }
+}
+
+/// エラー表示用の名前のリスト
+#[derive(Debug)]
+pub struct NameList(Vec<String>);
+
+impl From<Vec<String>> for NameList {
+    fn from(names: Vec<String>) -> NameList {
+        NameList(names)
+    }
+}
+
+impl fmt::Display for NameList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.join(", "))
+    }
+}
@@ -0,0 +1,96 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+use super::{ExecResult, SubCmd};
+
+use crate::config::Config;
+use crate::core::remote::serve_agent;
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Agent::new())
+}
+
+pub struct Agent();
+
+impl Agent {
+    pub fn new() -> Agent {
+        Agent()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, _config: Config) -> Result<()> {
+        let root = fs::canonicalize(matches.value_of("path").unwrap())?;
+
+        // 標準出力はプロトコルに使うため、ログ以外は何も出力しない
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        serve_agent(&root, stdin.lock(), stdout.lock())?;
+        Ok(())
+    }
+}
+
+impl SubCmd for Agent {
+    fn name(&self) -> &'static str {
+        "agent"
+    }
+
+    fn command_args(&self) -> App {
+        // `backup --source`がSSH越しに起動するためのもので、直接使うものではない
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutAgent.text())
+            .setting(AppSettings::Hidden)
+            .arg(
+                Arg::with_name("path")
+                    .index(1)
+                    .required(true)
+                    .help("Directory to serve"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    IO(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IO(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::IO(e) => Some(e),
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::IO(e) => Categorized::kind(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IO(e)
+    }
+}
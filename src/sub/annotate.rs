@@ -0,0 +1,168 @@
+use std::error;
+use std::fmt;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::{
+    open_repository, resolve_bank, select_history, BankSelectError, ExecResult, HistorySelectError,
+    RepoSelectError, SubCmd,
+};
+
+use crate::config::Config;
+use crate::core::repo;
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Annotate::new())
+}
+
+pub struct Annotate();
+
+impl Annotate {
+    pub fn new() -> Annotate {
+        Annotate()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let repo = open_repository(&config)?;
+
+        let bank_name = resolve_bank(matches, &config, &repo)?;
+        let bank = repo.open_bank(&bank_name)?;
+        let _lock = bank.lock()?;
+
+        let history = select_history(&bank, matches.value_of("revision"))?;
+        // 空のメモはメモの削除として扱う
+        let memo = matches
+            .value_of("message")
+            .filter(|m| !m.is_empty())
+            .map(|m| m.to_owned());
+        let cleared = memo.is_none();
+
+        let annotated = bank.annotate_history(&history, memo)?;
+
+        if !config.is_quiet() {
+            let msg = if cleared {
+                Msg::HistoryMemoCleared
+            } else {
+                Msg::HistoryAnnotated
+            };
+            println!("{}", msg.fill(&[&annotated.id()]));
+        }
+        Ok(())
+    }
+}
+
+impl SubCmd for Annotate {
+    fn name(&self) -> &'static str {
+        "annotate"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutAnnotate.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Bank name"),
+            )
+            .arg(
+                Arg::with_name("revision")
+                    .short("r")
+                    .long("revision")
+                    .takes_value(true)
+                    .help("Specify revision to annotate [default: last backup]"),
+            )
+            .arg(
+                Arg::with_name("message")
+                    .short("m")
+                    .long("message")
+                    .takes_value(true)
+                    .required_unless("clear")
+                    .help("Memo to set"),
+            )
+            .arg(
+                Arg::with_name("clear")
+                    .long("clear")
+                    .conflicts_with("message")
+                    .help("Remove memo"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Bank(BankSelectError),
+
+    History(HistorySelectError),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Bank(e) => write!(f, "{}", e),
+            Error::History(e) => write!(f, "{}", e),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Bank(e) => Some(e),
+            Error::History(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Bank(e) => e.kind(),
+            Error::History(e) => e.kind(),
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<BankSelectError> for Error {
+    fn from(e: BankSelectError) -> Error {
+        Error::Bank(e)
+    }
+}
+
+impl From<HistorySelectError> for Error {
+    fn from(e: HistorySelectError) -> Error {
+        Error::History(e)
+    }
+}
@@ -1,16 +1,29 @@
-use std::io;
-use std::process::exit;
+use std::error;
+use std::fmt;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::time::Duration;
 
 use clap::{App, Arg, ArgMatches, SubCommand};
-use failure::Fail;
-use log::{error, info, trace};
+use log::{info, trace, warn};
 
-use super::SubCmd;
+use super::{open_repository, progress, report_broken_banks, ExecResult, RepoSelectError, SubCmd};
 
 use crate::config::Config;
-use crate::core::repo::{self, Bank, Repository};
-use crate::core::scan::{self, Scanner};
+use crate::core::entry::FsHash;
+use crate::core::ignore::pattern::{parse_lines, Patterns};
+use crate::core::remote::{self, Connection, RemoteScanner, SshSource};
+use crate::core::replicate::replicate_history;
+use crate::core::repo::{self, Bank, History, InventoryEntry, Repository, ScanStats};
+use crate::core::scan::{self, ErrorPolicy, Scanner, ScannerBuilder};
 use crate::core::timestamp::{self, Timestamp};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::notify::{notify, Report};
+use crate::util::size::Size;
+
+// `--remote-command`が指定されない場合にリモートで起動するコマンド
+const DEFAULT_REMOTE_COMMAND: &str = "sbak";
 
 pub fn new() -> Box<dyn SubCmd> {
     Box::new(Backup::new())
@@ -23,39 +36,367 @@ impl Backup {
         Backup()
     }
 
-    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
-        let repo_path = matches
-            .value_of("repo")
-            .map(|s| s.parse().unwrap())
-            .or_else(|| config.repository_path().map(|p| p.to_owned()))
-            .ok_or_else(|| Error::Arg("no repository path"))?;
-        let repo = Repository::open(&repo_path)?;
+    // 全てのBankで飛ばしたエラーの合計と、開けなかったBankのエラーを返す。
+    fn wrapped_exec(
+        &self,
+        matches: &ArgMatches,
+        config: Config,
+    ) -> Result<(u64, Vec<repo::Error>)> {
+        let options = ScanOptions::from_matches(matches)?;
+        let repo = open_repository(&config)?;
+        if !options.dry_run {
+            repo.check_free_space()?;
+            repo.load_object_index()?;
+        }
 
+        let mut errors = 0;
+        let mut broken = Vec::new();
         if let Some(bank_name) = matches.value_of("bank") {
             let bank = repo.open_bank(bank_name)?;
-            scan(bank)?;
+            errors += scan(bank, &config, &options)?.errors;
         } else {
             for bank in repo.open_all_banks()? {
-                let bank = bank?;
-                scan(bank)?;
+                match bank {
+                    Ok(bank) => errors += scan(bank, &config, &options)?.errors,
+                    Err(e) => broken.push(e),
+                }
             }
         }
 
-        Ok(())
+        repo.save_object_index()?;
+        Ok((errors, broken))
+    }
+}
+
+/// コマンドライン引数で指定されたスキャンの設定
+#[derive(Debug, Default)]
+pub(super) struct ScanOptions {
+    dry_run: bool,
+    inventory: bool,
+    follow_symlinks: bool,
+    max_size: Option<Size>,
+    max_depth: Option<usize>,
+    threads: usize,
+    error_policy: ErrorPolicy,
+    excludes: Patterns,
+    wait_lock: Option<Duration>,
+    source: Option<SshSource>,
+    remote_command: Option<String>,
+}
+
+impl ScanOptions {
+    /// コマンドライン引数からスキャンの設定を読み取る。
+    ///
+    /// 定義されていない引数は指定されなかったものとして扱う。
+    pub(super) fn from_matches(matches: &ArgMatches) -> Result<ScanOptions> {
+        let max_size = matches
+            .value_of("max_size")
+            .map(|s| s.parse::<Size>())
+            .transpose()
+            .map_err(|s| Error::InvalidArg(format!("invalid size: {}", s)))?;
+        let max_depth = matches
+            .value_of("max_depth")
+            .map(|s| s.parse::<usize>())
+            .transpose()
+            .map_err(|e| Error::InvalidArg(format!("invalid max depth: {}", e)))?;
+        let threads = matches
+            .value_of("threads")
+            .map(|s| s.parse::<usize>())
+            .transpose()
+            .map_err(|e| Error::InvalidArg(format!("invalid thread count: {}", e)))?
+            .unwrap_or(1);
+        let error_policy = matches
+            .value_of("on_error")
+            .map(|s| s.parse::<ErrorPolicy>())
+            .transpose()
+            .map_err(Error::InvalidArg)?
+            .unwrap_or_default();
+        let wait_lock = matches
+            .value_of("wait_lock")
+            .map(|s| s.parse::<u64>())
+            .transpose()
+            .map_err(|e| Error::InvalidArg(format!("invalid lock wait seconds: {}", e)))?
+            .map(Duration::from_secs);
+        let excludes = parse_lines(matches.values_of("exclude").into_iter().flatten())
+            .map_err(|e| Error::InvalidArg(format!("invalid exclude pattern: {}", e)))?
+            .with_source("--exclude");
+        let source = matches
+            .value_of("source")
+            .map(SshSource::parse)
+            .transpose()?;
+
+        Ok(ScanOptions {
+            dry_run: matches.is_present("dry_run"),
+            inventory: matches.is_present("inventory"),
+            follow_symlinks: matches.is_present("follow_symlinks"),
+            max_size,
+            max_depth,
+            threads,
+            error_policy,
+            excludes,
+            wait_lock,
+            source,
+            remote_command: matches.value_of("remote_command").map(|s| s.to_owned()),
+        })
+    }
+}
+
+/// スキャン中に飛ばしたエラーの数`errors`に応じた終了コードを返す。
+///
+/// 飛ばしたファイルがあれば[`ErrorKind::Partial`](../../error/enum.ErrorKind.html)の終了コードになる。
+pub(super) fn partial_exit_code(errors: u64) -> i32 {
+    if errors == 0 {
+        0
+    } else {
+        warn!("{} files or directories were skipped due to errors", errors);
+        ErrorKind::Partial.exit_code()
+    }
+}
+
+/// 設定ファイルで指定された、Bank`bank_name`に適用する除外パターンを返す。
+pub(super) fn config_patterns(config: &Config, bank_name: &str) -> anyhow::Result<Patterns> {
+    Ok(config
+        .ignore_file_patterns()?
+        .merged(&config.exclude_patterns(bank_name)?))
+}
+
+/// 設定ファイルと`options`に従って`bank`をスキャンする[`ScannerBuilder`](../../core/scan/struct.ScannerBuilder.html)を返す。
+pub(super) fn scanner_builder<'a>(
+    bank: &'a Bank<'a>,
+    config: &Config,
+    options: &ScanOptions,
+) -> Result<ScannerBuilder<'a>> {
+    let exclude_patterns = config_patterns(config, bank.name()).map_err(Error::Config)?;
+
+    Ok(ScannerBuilder::new(bank)
+        .config_patterns(exclude_patterns)
+        .extra_patterns(options.excludes.clone())
+        .ignore_syntax(config.ignore_syntax(bank.name()))
+        .follow_symlinks(options.follow_symlinks)
+        .alternate_streams(config.alternate_streams(bank.name()))
+        .max_size(options.max_size)
+        .max_depth(options.max_depth)
+        .threads(options.threads)
+        .error_policy(options.error_policy)
+        .dry_run(options.dry_run))
+}
+
+/// Bankをスキャンして履歴を保存し、結果を設定されたWebhookに通知する。
+pub(super) fn scan(bank: Bank, config: &Config, options: &ScanOptions) -> Result<ScanStats> {
+    let bank_name = bank.name().to_owned();
+    let result = scan_bank(bank, config, options);
+
+    if !options.dry_run {
+        let report = match result {
+            Ok(ref stats) => Report::success("backup", Some(&bank_name), Some(stats.clone())),
+            Err(ref e) => Report::failure("backup", Some(&bank_name), e),
+        };
+        notify(config, &report);
     }
+
+    result
 }
 
-fn scan(bank: Bank) -> Result<()> {
+fn scan_bank(bank: Bank, config: &Config, options: &ScanOptions) -> Result<ScanStats> {
+    let _lock = match options.wait_lock {
+        Some(timeout) => bank.lock_wait(timeout)?,
+        None => bank.lock()?,
+    };
+
     let scan_start = Timestamp::now()?;
     info!("scan start at {}", scan_start);
 
-    let scanner = Scanner::new(&bank);
-    let id = scanner.scan()?;
+    let (id, stats) = match options.source {
+        Some(ref source) => scan_remote(&bank, config, options, source)?,
+        None => {
+            let scanner = scanner_builder(&bank, config, options)?
+                .progress(progress(config, &format!("backup {}", bank.name())))
+                .build();
+            if options.inventory {
+                return save_inventory(&bank, config, options, scanner, scan_start);
+            }
+            let id = scanner.scan()?;
+            (id, scanner.stats())
+        }
+    };
 
-    trace!("start save history");
-    bank.save_history(id.id(), scan_start)?;
-    trace!("finish scan {:?}", bank.target_path());
+    if options.dry_run {
+        info!("dry run: skip save history");
+    } else {
+        trace!("start save history");
+        let history = bank.save_history(id.id(), scan_start, Some(stats.clone()))?;
+        trace!("finish scan {:?}", bank.target_path());
+
+        if config.content_index(bank.name()) {
+            // 索引はバックアップの補助のため、失敗してもバックアップは成功とする
+            if let Err(e) = update_content_index(&bank, &history) {
+                warn!("failed to update content index of {}: {}", bank.name(), e);
+            }
+        }
+        if config.path_index(bank.name()) {
+            if let Err(e) = update_path_index(&bank, &history) {
+                warn!("failed to update path index of {}: {}", bank.name(), e);
+            }
+        }
+        replicate_to_secondary(&bank, config, &history);
+    }
+
+    if config.is_verbose() {
+        println!("{}    {}", bank.name(), id.id());
+    }
+
+    Ok(stats)
+}
+
+// SSHでリモートにエージェントを起動し、`source`のディレクトリをスキャンする。
+fn scan_remote(
+    bank: &Bank,
+    config: &Config,
+    options: &ScanOptions,
+    source: &SshSource,
+) -> Result<(FsHash, ScanStats)> {
+    let remote_command = options
+        .remote_command
+        .as_deref()
+        .unwrap_or(DEFAULT_REMOTE_COMMAND);
+    info!(
+        "start agent on {} for {}",
+        source.destination(),
+        source.path()
+    );
+    let mut child = source.command(remote_command).spawn()?;
+    let conn = Connection::new(
+        BufReader::new(child.stdout.take().unwrap()),
+        child.stdin.take().unwrap(),
+    );
+
+    let exclude_patterns = config_patterns(config, bank.name()).map_err(Error::Config)?;
+    let mut scanner = RemoteScanner::new(bank, conn)
+        .config_patterns(exclude_patterns)
+        .extra_patterns(options.excludes.clone())
+        .progress(progress(config, &format!("backup {}", bank.name())))
+        .max_size(options.max_size)
+        .max_depth(options.max_depth)
+        .dry_run(options.dry_run);
+    let result = scanner.scan();
+    let stats = scanner.stats();
+
+    // 標準入力を閉じるとエージェントは終了する
+    drop(scanner);
+    let status = child.wait()?;
+    let id = result?;
+    if !status.success() {
+        warn!("agent on {} exited with {}", source.destination(), status);
+    }
+    Ok((id, stats))
+}
+
+// 変更されたファイルの一覧のみを棚卸し履歴として保存する。
+fn save_inventory(
+    bank: &Bank,
+    config: &Config,
+    options: &ScanOptions,
+    scanner: Scanner,
+    scan_start: Timestamp,
+) -> Result<ScanStats> {
+    let base = bank.last_scan()?.ok_or_else(|| {
+        Error::InvalidArg(format!(
+            "bank '{}' has no history to take inventory from",
+            bank.name()
+        ))
+    })?;
+
+    let changes = scanner.preview()?;
+    let stats = scanner.stats();
+    let entries = changes
+        .iter()
+        .map(|c| InventoryEntry {
+            kind: c.kind(),
+            path: c
+                .path()
+                .strip_prefix(bank.target_path())
+                .unwrap_or_else(|_| c.path())
+                .to_string_lossy()
+                .into_owned(),
+            size: c.size(),
+        })
+        .collect::<Vec<_>>();
+
+    if options.dry_run {
+        info!("dry run: skip save inventory");
+    } else {
+        let history = bank.save_inventory(&base, &entries, scan_start, Some(stats.clone()))?;
+        info!(
+            "inventory of {} changed files saved at {}",
+            entries.len(),
+            history.timestamp()
+        );
+        replicate_to_secondary(bank, config, &history);
+    }
+
+    if !config.is_quiet() {
+        println!(
+            "{}    {}",
+            bank.name(),
+            Msg::InventorySaved.fill(&[&entries.len(), &base.id()])
+        );
+    }
+
+    Ok(stats)
+}
+
+// 副リポジトリが設定されていれば、保存した履歴を複製する。
+//
+// 副リポジトリへの複製は補助のため、失敗しても警告のみとしバックアップは成功とする。
+fn replicate_to_secondary(bank: &Bank, config: &Config, history: &History) {
+    if let Some(path) = config.secondary_repository(bank.name()) {
+        if let Err(e) = replicate_to(bank, path, history) {
+            warn!(
+                "failed to copy backup of {} to secondary repository {:?}: {}",
+                bank.name(),
+                path,
+                e
+            );
+        }
+    }
+}
+
+fn replicate_to(
+    bank: &Bank,
+    path: &Path,
+    history: &History,
+) -> std::result::Result<(), repo::Error> {
+    let repo = Repository::open(path)?;
+    if !repo.bank_exists(bank.name())? {
+        info!("create bank {} in secondary repository", bank.name());
+        repo.create_bank(bank.name(), bank.target_path())?;
+    }
+    let secondary = repo.open_bank(bank.name())?;
+    let _lock = secondary.lock()?;
+
+    repo.check_free_space()?;
+    repo.load_object_index()?;
+    let result = replicate_history(bank, &secondary, history);
+    repo.save_object_index()?;
+    result?;
+    Ok(())
+}
+
+fn update_content_index(bank: &Bank, history: &History) -> std::result::Result<(), repo::Error> {
+    let mut index = bank.load_content_index()?;
+    let added = bank.update_content_index(history, &mut index)?;
+    if added > 0 {
+        bank.save_content_index(&index)?;
+    }
+    info!("{} files added to content index", added);
+    Ok(())
+}
 
+fn update_path_index(bank: &Bank, history: &History) -> std::result::Result<(), repo::Error> {
+    let mut index = bank.load_path_index()?;
+    let changed = bank.update_path_index(history, &mut index)?;
+    bank.save_path_index(&index)?;
+    info!("{} files changed in path index", changed);
     Ok(())
 }
 
@@ -66,13 +407,7 @@ impl SubCmd for Backup {
 
     fn command_args(&self) -> App {
         SubCommand::with_name(self.name())
-            .about("Backup files")
-            .arg(
-                Arg::with_name("repo")
-                    .long("repo")
-                    .takes_value(true)
-                    .help("Overwrite repository path"),
-            )
+            .about(Msg::AboutBackup.text())
             .arg(
                 Arg::with_name("bank")
                     .short("b")
@@ -80,49 +415,163 @@ impl SubCmd for Backup {
                     .takes_value(true)
                     .help("Bank name"),
             )
+            .arg(
+                Arg::with_name("dry_run")
+                    .short("n")
+                    .long("dry-run")
+                    .help("Scan files without writing to the repository"),
+            )
+            .arg(
+                Arg::with_name("inventory")
+                    .long("inventory")
+                    .help("Record only a list of changed files, judged by modification time, without storing them"),
+            )
+            .arg(
+                Arg::with_name("source")
+                    .long("source")
+                    .takes_value(true)
+                    .requires("bank")
+                    .conflicts_with("inventory")
+                    .help("Back up a remote directory (ssh://[user@]host[:port]/path) instead of the bank's target"),
+            )
+            .arg(
+                Arg::with_name("remote_command")
+                    .long("remote-command")
+                    .takes_value(true)
+                    .requires("source")
+                    .help("Command to run sbak on the remote host [default: sbak]"),
+            )
+            .arg(
+                Arg::with_name("exclude")
+                    .short("e")
+                    .long("exclude")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Additional exclude pattern (same syntax as .sbakignore)"),
+            )
+            .arg(
+                Arg::with_name("follow_symlinks")
+                    .long("follow-symlinks")
+                    .help("Back up targets of symbolic links instead of the links"),
+            )
+            .arg(
+                Arg::with_name("max_size")
+                    .long("max-size")
+                    .takes_value(true)
+                    .help("Skip files larger than this size (e.g. 500MiB)"),
+            )
+            .arg(
+                Arg::with_name("max_depth")
+                    .long("max-depth")
+                    .takes_value(true)
+                    .help("Skip directories deeper than this depth"),
+            )
+            .arg(
+                Arg::with_name("threads")
+                    .short("j")
+                    .long("threads")
+                    .takes_value(true)
+                    .help("Number of threads to hash files"),
+            )
+            .arg(
+                Arg::with_name("on_error")
+                    .long("on-error")
+                    .takes_value(true)
+                    .possible_values(&["abort", "skip-denied", "skip"])
+                    .help("How to handle IO errors while scanning (default: skip-denied)"),
+            )
+            .arg(
+                Arg::with_name("wait_lock")
+                    .long("wait-lock")
+                    .takes_value(true)
+                    .help("Wait up to this many seconds for another backup of the bank to finish"),
+            )
     }
 
-    fn exec(&self, matches: &ArgMatches, config: Config) -> ! {
-        match self.wrapped_exec(matches, config) {
-            Ok(()) => exit(0),
-            Err(e) => {
-                if cfg!(debug_assertions) {
-                    error!("{:#?}", e);
-                } else {
-                    error!("{}", e);
-                }
-                exit(1)
-            }
-        }
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        let (errors, broken) = self.wrapped_exec(matches, config)?;
+        let code = partial_exit_code(errors);
+        Ok(report_broken_banks(&broken).unwrap_or(code))
     }
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
-    #[fail(display = "{}", _0)]
-    Arg(&'static str),
+    Config(anyhow::Error),
 
-    #[fail(display = "failed scan with IO error: {}", _0)]
-    IO(#[fail(cause)] io::Error),
+    InvalidArg(String),
 
-    #[fail(display = "file scan error: {}", _0)]
-    Scan(#[fail(cause)] scan::Error),
+    IO(io::Error),
 
-    #[fail(display = "repository operation error: {}", _0)]
-    Repo(#[fail(cause)] repo::Error),
+    Remote(remote::Error),
+
+    Scan(scan::Error),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
 
-    #[fail(display = "timestamp is older than UNIX epoch")]
     Timestamp,
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Config(e) => write!(f, "invalid config: {}", e),
+            Error::InvalidArg(msg) => write!(f, "Invalid command-line arguments: {}", msg),
+            Error::IO(e) => write!(f, "failed scan with IO error: {}", e),
+            Error::Remote(e) => write!(f, "remote scan error: {}", e),
+            Error::Scan(e) => write!(f, "file scan error: {}", e),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+            Error::Timestamp => write!(f, "timestamp is older than UNIX epoch"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::IO(e) => Some(e),
+            Error::Remote(e) => Some(e),
+            Error::Scan(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::InvalidArg(_) => ErrorKind::Usage,
+            Error::Config(_) => ErrorKind::Config,
+            Error::IO(e) => Categorized::kind(e),
+            Error::Remote(e) => e.kind(),
+            Error::Scan(e) => e.kind(),
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+            Error::Timestamp => ErrorKind::Data,
+        }
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Error {
         Error::IO(e)
     }
 }
 
+impl From<remote::Error> for Error {
+    fn from(e: remote::Error) -> Error {
+        Error::Remote(e)
+    }
+}
+
 impl From<scan::Error> for Error {
     fn from(e: scan::Error) -> Error {
         Error::Scan(e)
@@ -135,6 +584,12 @@ impl From<repo::Error> for Error {
     }
 }
 
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
 impl From<timestamp::Error> for Error {
     fn from(_e: timestamp::Error) -> Error {
         Error::Timestamp
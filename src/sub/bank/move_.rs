@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::PathBuf;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::super::{repository_path, resolve_bank, ExecResult, SubCmd};
+use super::{Error, Result};
+
+use crate::config::Config;
+use crate::core::replicate::replicate_bank;
+use crate::core::repo::Repository;
+use crate::message::Msg;
+use crate::util::size::Size;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Move::new())
+}
+
+pub struct Move();
+
+impl Move {
+    pub fn new() -> Move {
+        Move()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let from = match matches.value_of("from") {
+            Some(path) => PathBuf::from(path),
+            None => repository_path(&config)?,
+        };
+        let to = PathBuf::from(matches.value_of("to").unwrap());
+
+        let src_repo = Repository::open(&from)?;
+        let dst_repo = Repository::open(&to)?;
+        if fs::canonicalize(&from)? == fs::canonicalize(&to)? {
+            return Err(Error::Arg(format!(
+                "source and destination are the same repository: {:?}",
+                to
+            )));
+        }
+
+        let bank_name = resolve_bank(matches, &config, &src_repo)?;
+        let src = src_repo.open_bank(&bank_name)?;
+        let src_lock = src.lock()?;
+
+        dst_repo.check_free_space()?;
+        dst_repo.create_bank_from(&src)?;
+        let dst = dst_repo.open_bank(&bank_name)?;
+        let dst_lock = dst.lock()?;
+
+        dst_repo.load_object_index()?;
+        let result = replicate_bank(&src, &dst);
+        dst_repo.save_object_index()?;
+        let (histories, replicated) = result?;
+        drop(dst_lock);
+
+        if !config.is_quiet() {
+            println!(
+                "{}",
+                Msg::BankMoved.fill(&[
+                    &bank_name,
+                    &histories,
+                    &to.display(),
+                    &replicated.objects,
+                    &Size::from(replicated.bytes)
+                ])
+            );
+        }
+
+        // 複製が全て成功した場合のみ移動元を削除する
+        if matches.is_present("remove_source") {
+            src.remove(src_lock)?;
+            if !config.is_quiet() {
+                println!("{}", Msg::BankRemoved.fill(&[&bank_name, &from.display()]));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl SubCmd for Move {
+    fn name(&self) -> &'static str {
+        "move"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutBankMove.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Bank name"),
+            )
+            .arg(
+                Arg::with_name("from")
+                    .long("from")
+                    .takes_value(true)
+                    .value_name("REPOSITORY")
+                    .help("Source repository [default: repository in config]"),
+            )
+            .arg(
+                Arg::with_name("to")
+                    .long("to")
+                    .takes_value(true)
+                    .required(true)
+                    .value_name("REPOSITORY")
+                    .help("Destination repository"),
+            )
+            .arg(
+                Arg::with_name("remove_source")
+                    .long("remove-source")
+                    .help("Remove bank from source repository after copy"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
@@ -0,0 +1,258 @@
+use std::env;
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::backup::config_patterns;
+use super::{open_repository, resolve_bank, BankSelectError, ExecResult, RepoSelectError, SubCmd};
+
+use crate::config::Config;
+use crate::core::ignore::pattern::parse_lines;
+use crate::core::repo;
+use crate::core::scan::{self, IgnoreCheck, ScannerBuilder};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(CheckIgnore::new())
+}
+
+pub struct CheckIgnore();
+
+impl CheckIgnore {
+    pub fn new() -> CheckIgnore {
+        CheckIgnore()
+    }
+
+    // 除外されるパスが1つ以上あれば`true`を返す。
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<bool> {
+        let repo = open_repository(&config)?;
+
+        let bank_name = resolve_bank(matches, &config, &repo)?;
+        let bank = repo.open_bank(&bank_name)?;
+
+        let excludes = parse_lines(matches.values_of("exclude").into_iter().flatten())
+            .map_err(|e| Error::Arg(format!("invalid exclude pattern: {}", e)))?
+            .with_source("--exclude");
+        let scanner = ScannerBuilder::new(&bank)
+            .config_patterns(config_patterns(&config, bank.name()).map_err(Error::Config)?)
+            .extra_patterns(excludes)
+            .ignore_syntax(config.ignore_syntax(bank.name()))
+            .follow_symlinks(matches.is_present("follow_symlinks"))
+            .build();
+
+        let verbose = matches.is_present("verbose");
+        let non_matching = matches.is_present("non_matching");
+        let current_dir = env::current_dir()?;
+        let target = bank.target_path();
+
+        let mut any_ignored = false;
+        for arg in matches.values_of("path").into_iter().flatten() {
+            let path = normalize(&current_dir.join(arg));
+            if !path.starts_with(target) {
+                return Err(Error::Arg(format!(
+                    "'{}' is outside of bank target {:?}",
+                    arg, target
+                )));
+            }
+
+            let check = scanner.check_ignore(&path)?;
+            let ignored = check.as_ref().is_some_and(IgnoreCheck::is_ignored);
+            any_ignored |= ignored;
+
+            if !ignored && !non_matching {
+                continue;
+            }
+            if verbose || non_matching {
+                println!("{}\t{}", describe(target, check.as_ref()), arg);
+            } else {
+                println!("{}", arg);
+            }
+        }
+
+        Ok(any_ignored)
+    }
+}
+
+// `git check-ignore -v`と同様に`<source>:<line>:<pattern>\t<level>`の形式で判定の根拠を表示する。
+fn describe(target: &Path, check: Option<&IgnoreCheck>) -> String {
+    let check = match check {
+        Some(check) => check,
+        None => return "::\t".to_owned(),
+    };
+
+    let origin = match check.pattern().origin() {
+        Some(origin) => origin.to_string(),
+        None => "::".to_owned(),
+    };
+    let level = match check.base_dir().strip_prefix(target) {
+        Ok(p) if p.as_os_str().is_empty() => ".".to_owned(),
+        Ok(p) => p.display().to_string(),
+        Err(_) => check.base_dir().display().to_string(),
+    };
+    format!("{}\t{}", origin, level)
+}
+
+// ファイルシステムを参照せずに`.`と`..`を取り除く。
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+impl SubCmd for CheckIgnore {
+    fn name(&self) -> &'static str {
+        "check-ignore"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutCheckIgnore.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Bank name"),
+            )
+            .arg(
+                Arg::with_name("exclude")
+                    .short("e")
+                    .long("exclude")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Additional exclude pattern (same syntax as .sbakignore)"),
+            )
+            .arg(
+                Arg::with_name("follow_symlinks")
+                    .long("follow-symlinks")
+                    .help("Judge symbolic links by their targets as backup does"),
+            )
+            .arg(
+                Arg::with_name("verbose")
+                    .short("v")
+                    .long("verbose")
+                    .help("Show the pattern file, line and directory that decided"),
+            )
+            .arg(
+                Arg::with_name("non_matching")
+                    .short("n")
+                    .long("non-matching")
+                    .help("Show paths that are not ignored too (implies --verbose)"),
+            )
+            .arg(
+                Arg::with_name("path")
+                    .index(1)
+                    .required(true)
+                    .multiple(true)
+                    .help("Paths to check"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        let any_ignored = self.wrapped_exec(matches, config)?;
+        Ok(if any_ignored { 0 } else { 1 })
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Arg(String),
+
+    Bank(BankSelectError),
+
+    Config(anyhow::Error),
+
+    IO(io::Error),
+
+    Scan(scan::Error),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Arg(msg) => write!(f, "{}", msg),
+            Error::Bank(e) => write!(f, "{}", e),
+            Error::Config(e) => write!(f, "invalid config: {}", e),
+            Error::IO(e) => write!(f, "IO error: {}", e),
+            Error::Scan(e) => write!(f, "failed check ignore: {}", e),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Bank(e) => Some(e),
+            Error::IO(e) => Some(e),
+            Error::Scan(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Arg(_) => ErrorKind::Usage,
+            Error::Bank(e) => e.kind(),
+            Error::Config(_) => ErrorKind::Config,
+            Error::IO(e) => Categorized::kind(e),
+            Error::Scan(e) => e.kind(),
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl From<BankSelectError> for Error {
+    fn from(e: BankSelectError) -> Error {
+        Error::Bank(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IO(e)
+    }
+}
+
+impl From<scan::Error> for Error {
+    fn from(e: scan::Error) -> Error {
+        Error::Scan(e)
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
@@ -0,0 +1,51 @@
+mod init;
+
+use std::io::stderr;
+
+use clap::{App, ArgMatches, SubCommand};
+
+use super::{ExecResult, SubCmd, SubCommandSet};
+
+use crate::config::Config;
+use crate::message::Msg;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(ConfigCmd::new())
+}
+
+pub struct ConfigCmd(SubCommandSet);
+
+impl ConfigCmd {
+    pub fn new() -> ConfigCmd {
+        let mut subs: SubCommandSet = SubCommandSet::new();
+
+        subs.append(init::new());
+
+        ConfigCmd(subs)
+    }
+}
+
+impl SubCmd for ConfigCmd {
+    fn name(&self) -> &'static str {
+        "config"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutConfig.text())
+            .subcommands(self.0.arg_defs())
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        if let (subcmd_name, Some(matches)) = matches.subcommand() {
+            if let Some(result) = self.0.execute(subcmd_name, matches, config) {
+                return result;
+            }
+        }
+
+        let mut out = stderr();
+        self.command_args().write_long_help(&mut out).unwrap();
+        eprintln!();
+        Ok(1)
+    }
+}
@@ -0,0 +1,281 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{self, stdin, stdout, BufRead, Write};
+use std::path::PathBuf;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use toml::Value;
+
+use super::super::{ExecResult, SubCmd};
+
+use crate::config::{user_config_path, Config, LogLevel};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+
+#[cfg(test)]
+mod test;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Init::new())
+}
+
+pub struct Init();
+
+impl Init {
+    pub fn new() -> Init {
+        Init()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let interactive = !matches.is_present("no_input") && atty::is(atty::Stream::Stdin);
+
+        let mut settings = StarterConfig {
+            repository_path: config.repository_path().map(|p| p.display().to_string()),
+            log_output: matches
+                .value_of("log_output")
+                .unwrap_or("stderr")
+                .to_owned(),
+            log_level: matches.value_of("log_level").unwrap_or("warn").to_owned(),
+            bank: matches.value_of("bank").map(|s| s.to_owned()),
+        };
+
+        if interactive {
+            settings.repository_path =
+                prompt(Msg::PromptRepositoryPath.text(), settings.repository_path)?;
+            settings.log_output = prompt(Msg::PromptLogOutput.text(), Some(settings.log_output))?
+                .unwrap_or_else(|| "stderr".to_owned());
+            settings.log_level = prompt(Msg::PromptLogLevel.text(), Some(settings.log_level))?
+                .unwrap_or_else(|| "warn".to_owned());
+            settings.bank = prompt(Msg::PromptBankName.text(), settings.bank)?;
+        }
+
+        settings
+            .log_level
+            .parse::<LogLevel>()
+            .map_err(|e| Error::Arg(e.to_string()))?;
+
+        let content = settings.render();
+
+        if matches.is_present("stdout") {
+            print!("{}", content);
+            return Ok(());
+        }
+
+        let path = matches
+            .value_of("output")
+            .map(PathBuf::from)
+            .or_else(user_config_path)
+            .ok_or_else(|| Error::Arg("can't detect config directory".to_owned()))?;
+
+        if path.exists() && !matches.is_present("force") {
+            return Err(Error::Arg(format!(
+                "config file {:?} already exists (use --force to overwrite)",
+                path
+            )));
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, content)?;
+        if !config.is_quiet() {
+            println!("{}", Msg::ConfigWritten.fill(&[&path.display()]));
+        }
+
+        Ok(())
+    }
+}
+
+struct StarterConfig {
+    repository_path: Option<String>,
+    log_output: String,
+    log_level: String,
+    bank: Option<String>,
+}
+
+impl StarterConfig {
+    fn render(&self) -> String {
+        let mut s = String::new();
+
+        s.push_str("# sbak config file\n");
+        s.push_str("# Run `sbak info --config-paths` to see which config files are loaded.\n");
+        s.push('\n');
+
+        s.push_str("# Path of the backup repository.\n");
+        match self.repository_path {
+            Some(ref p) => s.push_str(&format!("repository_path = {}\n", quote(p))),
+            None => s.push_str("#repository_path = '/backup/sbak_repository'\n"),
+        }
+        s.push('\n');
+
+        s.push_str("# Bank used when -b/--bank is omitted.\n");
+        match self.bank {
+            Some(ref b) => s.push_str(&format!("default_bank = {}\n", quote(b))),
+            None => s.push_str("#default_bank = 'home'\n"),
+        }
+        s.push('\n');
+
+        s.push_str("# Display language ('en' or 'ja'). Detected from LANG if omitted.\n");
+        s.push_str("#lang = 'en'\n");
+        s.push('\n');
+
+        s.push_str("# Exclude patterns applied to every bank.\n");
+        s.push_str("#exclude = ['Thumbs.db', '.DS_Store', '*.tmp']\n");
+        s.push('\n');
+
+        s.push_str("[log]\n");
+        s.push_str("# 'stderr' or path of a log file.\n");
+        s.push_str(&format!("output = {}\n", quote(&self.log_output)));
+        s.push_str("# off, error, warn, info, debug or trace.\n");
+        s.push_str(&format!("level = {}\n", quote(&self.log_level)));
+        s.push('\n');
+
+        s.push_str("# Per-bank settings.\n");
+        let (prefix, name) = match self.bank {
+            Some(ref b) => ("", b.as_str()),
+            None => ("#", "home"),
+        };
+        s.push_str(&format!("{}[banks.{}]\n", prefix, quote_key(name)));
+        s.push_str("# Used by `sbak run-due`.\n");
+        s.push_str("#schedule = 'daily 02:00'\n");
+        s.push_str("#exclude = ['node_modules/', '/.cache/']\n");
+
+        s
+    }
+}
+
+fn quote(s: &str) -> String {
+    Value::String(s.to_owned()).to_string()
+}
+
+fn quote_key(s: &str) -> String {
+    let bare = !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        s.to_owned()
+    } else {
+        quote(s)
+    }
+}
+
+fn prompt(message: &str, default: Option<String>) -> Result<Option<String>> {
+    let mut out = stdout();
+    match default {
+        Some(ref d) => write!(out, "{} [{}]: ", message, d)?,
+        None => write!(out, "{}: ", message)?,
+    }
+    out.flush()?;
+
+    let mut line = String::new();
+    stdin().lock().read_line(&mut line)?;
+    let line = line.trim();
+
+    if line.is_empty() {
+        Ok(default)
+    } else {
+        Ok(Some(line.to_owned()))
+    }
+}
+
+impl SubCmd for Init {
+    fn name(&self) -> &'static str {
+        "init"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutConfigInit.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Name of the example bank section"),
+            )
+            .arg(
+                Arg::with_name("log_output")
+                    .long("log-output")
+                    .takes_value(true)
+                    .help("Log output ('stderr' or file path)"),
+            )
+            .arg(
+                Arg::with_name("log_level")
+                    .long("log-level")
+                    .takes_value(true)
+                    .help("Log level"),
+            )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .takes_value(true)
+                    .help("Write to specified path instead of the user config path"),
+            )
+            .arg(
+                Arg::with_name("stdout")
+                    .long("stdout")
+                    .conflicts_with("output")
+                    .help("Print to stdout instead of writing file"),
+            )
+            .arg(
+                Arg::with_name("force")
+                    .short("f")
+                    .long("force")
+                    .help("Overwrite existing config file"),
+            )
+            .arg(
+                Arg::with_name("no_input")
+                    .long("no-input")
+                    .help("Don't ask values interactively"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Arg(String),
+
+    IO(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Arg(msg) => write!(f, "{}", msg),
+            Error::IO(e) => write!(f, "failed with IO error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Arg(_) => ErrorKind::Usage,
+            Error::IO(e) => Categorized::kind(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IO(e)
+    }
+}
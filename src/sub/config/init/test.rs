@@ -0,0 +1,48 @@
+use super::*;
+
+use std::path::Path;
+
+use tempfile::tempdir;
+
+use crate::config;
+
+// 生成した設定ファイルを書き出し、`config::load`で読み込む。
+fn load(settings: &StarterConfig) -> Config {
+    let temp = tempdir().unwrap();
+    let path = temp.path().join("config.toml");
+    fs::write(&path, settings.render()).unwrap();
+    config::load(&path).unwrap()
+}
+
+#[test]
+fn test_render_with_bank() {
+    let settings = StarterConfig {
+        repository_path: Some("/backup/sbak repository".to_owned()),
+        log_output: "stderr".to_owned(),
+        log_level: "info".to_owned(),
+        bank: Some("my bank".to_owned()),
+    };
+
+    let config = load(&settings);
+    assert_eq!(
+        config.repository_path(),
+        Some(Path::new("/backup/sbak repository"))
+    );
+    assert_eq!(config.default_bank(), Some("my bank"));
+    assert_eq!(config.bank_names().collect::<Vec<_>>(), vec!["my bank"]);
+}
+
+#[test]
+fn test_render_without_bank() {
+    let settings = StarterConfig {
+        repository_path: None,
+        log_output: "/var/log/sbak.log".to_owned(),
+        log_level: "warn".to_owned(),
+        bank: None,
+    };
+
+    let config = load(&settings);
+    assert_eq!(config.repository_path(), None);
+    assert_eq!(config.default_bank(), None);
+    assert_eq!(config.bank_names().count(), 0);
+}
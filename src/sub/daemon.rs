@@ -0,0 +1,472 @@
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::{error, info, warn};
+
+use super::run_due;
+use super::{report_broken_banks, repository_path, ExecResult, RepoSelectError, SubCmd};
+
+use crate::config::schedule::Schedule;
+use crate::config::Config;
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::util::systemd;
+
+// 予定を確認する間隔の既定値（秒）
+const DEFAULT_INTERVAL_SECS: u64 = 60;
+
+// 生成するサービスに設定するウォッチドッグの時間（秒）
+const WATCHDOG_SECS: u64 = 300;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Daemon::new())
+}
+
+pub struct Daemon();
+
+impl Daemon {
+    pub fn new() -> Daemon {
+        Daemon()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let repo_path = repository_path(&config)?;
+
+        if matches.is_present("generate_units") {
+            let units = generate_units(&repo_path, &config, &current_exe()?)?;
+            match matches.value_of("unit_dir") {
+                Some(dir) => write_units(Path::new(dir), &units)?,
+                None => print_units(&units),
+            }
+            return Ok(());
+        }
+
+        let interval = interval_arg(matches)?;
+        run(&repo_path, &config, interval, &Control::new())
+    }
+}
+
+/// コマンドライン引数`interval`で指定された、予定を確認する間隔を返す。
+pub(super) fn interval_arg(matches: &ArgMatches) -> Result<Duration> {
+    let interval = matches
+        .value_of("interval")
+        .map(|s| s.parse::<u64>())
+        .transpose()
+        .map_err(|e| Error::InvalidArg(format!("invalid interval: {}", e)))?
+        .unwrap_or(DEFAULT_INTERVAL_SECS);
+    if interval == 0 {
+        return Err(Error::InvalidArg("interval must be positive".to_owned()));
+    }
+    Ok(Duration::from_secs(interval))
+}
+
+impl SubCmd for Daemon {
+    fn name(&self) -> &'static str {
+        "daemon"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutDaemon.text())
+            .arg(
+                Arg::with_name("interval")
+                    .long("interval")
+                    .takes_value(true)
+                    .value_name("SECS")
+                    .help("Seconds between schedule checks (default: 60)"),
+            )
+            .arg(
+                Arg::with_name("generate_units")
+                    .long("generate-units")
+                    .help("Print systemd service and timer units for scheduled banks"),
+            )
+            .arg(
+                Arg::with_name("unit_dir")
+                    .long("unit-dir")
+                    .takes_value(true)
+                    .value_name("DIR")
+                    .requires("generate_units")
+                    .help("Write generated units into DIR instead of printing them"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
+
+/// デーモンの実行状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum State {
+    /// 予定に従ってバックアップする
+    Running,
+    /// 再開されるまでバックアップしない
+    Paused,
+    /// 実行中のバックアップが終わり次第終了する
+    Stopped,
+}
+
+/// 実行中のデーモンを他のスレッドから一時停止・終了させる。
+#[derive(Debug)]
+pub(super) struct Control {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Control {
+    pub(super) fn new() -> Control {
+        Control {
+            state: Mutex::new(State::Running),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// 現在の状態を返す。
+    pub(super) fn state(&self) -> State {
+        *self.state.lock().unwrap()
+    }
+
+    /// 状態を変更し、待機中のデーモンを起こす。
+    pub(super) fn set(&self, state: State) {
+        *self.state.lock().unwrap() = state;
+        self.changed.notify_all();
+    }
+
+    // 状態が変更されるか`timeout`が経過するまで待ち、その時点の状態を返す。
+    fn wait(&self, timeout: Duration) -> State {
+        let state = self.state.lock().unwrap();
+        let current = *state;
+        let (state, _) = self
+            .changed
+            .wait_timeout_while(state, timeout, |s| *s == current)
+            .unwrap();
+        *state
+    }
+}
+
+/// 予定時刻を過ぎたBankのバックアップを`interval`ごとに、`control`で終了されるまで繰り返す。
+///
+/// 各回のバックアップの失敗はログに記録し、次の回で再び試みる。
+pub(super) fn run(
+    repo_path: &Path,
+    config: &Config,
+    interval: Duration,
+    control: &Control,
+) -> Result<()> {
+    let watchdog = systemd::watchdog_timeout().map(|t| t / 2);
+    if let Some(t) = watchdog {
+        info!("watchdog enabled: ping every {} ms", t.as_millis());
+    }
+    // ウォッチドッグが無効でも、状態の変化を確認するために定期的に起きる。
+    let tick = watchdog.unwrap_or(interval);
+
+    sd_notify(systemd::ready());
+    info!(
+        "daemon started: repository = {}, interval = {} s",
+        repo_path.display(),
+        interval.as_secs()
+    );
+
+    loop {
+        match control.state() {
+            State::Stopped => break,
+            State::Paused => {
+                sd_notify(systemd::status("paused"));
+                control.wait(tick);
+                sd_notify(systemd::watchdog());
+                continue;
+            }
+            State::Running => {}
+        }
+
+        let started = Instant::now();
+        sd_notify(systemd::status("running backup of due banks"));
+
+        let result = with_watchdog(watchdog, || run_due::backup_due(repo_path, config));
+        let status = match result {
+            Ok(result) => {
+                let errors = result.errors();
+                if errors > 0 {
+                    warn!("{} files or directories were skipped due to errors", errors);
+                }
+                report_broken_banks(&result.broken);
+                result.report_failed();
+                let (backed_up, broken, failed) =
+                    (result.stats.len(), result.broken.len(), result.failed.len());
+                if broken > 0 || failed > 0 {
+                    format!(
+                        "idle, {} banks backed up, {} banks failed, {} banks could not be opened",
+                        backed_up, failed, broken
+                    )
+                } else if backed_up == 0 {
+                    "idle, no banks were due".to_owned()
+                } else {
+                    format!("idle, {} banks backed up", backed_up)
+                }
+            }
+            Err(e) => {
+                error!("scheduled backup failed: {}", e);
+                let mut source = error::Error::source(&e);
+                while let Some(c) = source {
+                    error!("    at: {}", c);
+                    source = c.source();
+                }
+                format!("idle, last backup failed: {}", e)
+            }
+        };
+        sd_notify(systemd::status(&status));
+
+        let next = started + interval;
+        loop {
+            let now = Instant::now();
+            if now >= next {
+                break;
+            }
+            let state = control.wait((next - now).min(tick));
+            sd_notify(systemd::watchdog());
+            if state != State::Running {
+                break;
+            }
+        }
+    }
+
+    sd_notify(systemd::stopping());
+    info!("daemon stopped");
+    Ok(())
+}
+
+// `f`を実行している間、ウォッチドッグへの通知を別スレッドで続ける。
+//
+// 大きなBankのバックアップがウォッチドッグの時間を超えても、サービスが異常終了とみなされないようにする。
+fn with_watchdog<T, F: FnOnce() -> T>(watchdog: Option<Duration>, f: F) -> T {
+    let interval = match watchdog {
+        Some(t) => t,
+        None => return f(),
+    };
+
+    let done = AtomicBool::new(false);
+    thread::scope(|s| {
+        s.spawn(|| {
+            let tick = interval.min(Duration::from_secs(1));
+            let mut last = Instant::now();
+            while !done.load(Ordering::Relaxed) {
+                thread::sleep(tick);
+                if last.elapsed() >= interval {
+                    sd_notify(systemd::watchdog());
+                    last = Instant::now();
+                }
+            }
+        });
+
+        let result = f();
+        done.store(true, Ordering::Relaxed);
+        result
+    })
+}
+
+fn sd_notify(result: io::Result<bool>) {
+    if let Err(e) = result {
+        warn!("failed to notify systemd: {}", e);
+    }
+}
+
+fn current_exe() -> Result<PathBuf> {
+    env::current_exe().map_err(Error::IO)
+}
+
+/// 生成するsystemdのユニットファイル
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Unit {
+    name: String,
+    content: String,
+}
+
+// 予定が設定されたBankごとのサービスとタイマー、及びデーモンとして動かす場合のサービスを生成する。
+fn generate_units(repo_path: &Path, config: &Config, exe: &Path) -> Result<Vec<Unit>> {
+    let exe = quote_arg(&exe.to_string_lossy());
+    let repo = quote_arg(&repo_path.to_string_lossy());
+
+    let mut units = vec![Unit {
+        name: "sbak-daemon.service".to_owned(),
+        content: format!(
+            "[Unit]\n\
+             Description=sbak backup scheduler\n\
+             \n\
+             [Service]\n\
+             Type=notify\n\
+             ExecStart={} daemon --repo {}\n\
+             WatchdogSec={}\n\
+             Restart=on-failure\n\
+             \n\
+             [Install]\n\
+             WantedBy=default.target\n",
+            exe, repo, WATCHDOG_SECS
+        ),
+    }];
+
+    for bank_name in config.bank_names() {
+        let schedule = match config.schedule(bank_name).map_err(Error::Config)? {
+            Some(schedule) => schedule,
+            None => continue,
+        };
+        let unit_name = format!("sbak-backup-{}", escape_unit_name(bank_name));
+
+        units.push(Unit {
+            name: format!("{}.service", unit_name),
+            content: format!(
+                "[Unit]\n\
+                 Description=sbak backup of bank {}\n\
+                 \n\
+                 [Service]\n\
+                 Type=oneshot\n\
+                 ExecStart={} backup --repo {} --bank {}\n",
+                bank_name,
+                exe,
+                repo,
+                quote_arg(bank_name)
+            ),
+        });
+
+        let trigger = match schedule.on_calendar() {
+            Some(calendar) => format!("OnCalendar={}\nPersistent=true\n", calendar),
+            None => {
+                let secs = match schedule {
+                    Schedule::Every(secs) => secs,
+                    _ => unreachable!("only interval schedules have no calendar"),
+                };
+                format!("OnBootSec=5min\nOnUnitActiveSec={}s\n", secs)
+            }
+        };
+        units.push(Unit {
+            name: format!("{}.timer", unit_name),
+            content: format!(
+                "[Unit]\n\
+                 Description=sbak backup of bank {} ({})\n\
+                 \n\
+                 [Timer]\n\
+                 {}\
+                 \n\
+                 [Install]\n\
+                 WantedBy=timers.target\n",
+                bank_name, schedule, trigger
+            ),
+        });
+    }
+
+    Ok(units)
+}
+
+fn print_units(units: &[Unit]) {
+    for (i, unit) in units.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("# {}", unit.name);
+        print!("{}", unit.content);
+    }
+}
+
+fn write_units(dir: &Path, units: &[Unit]) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    for unit in units {
+        let path = dir.join(&unit.name);
+        fs::write(&path, &unit.content)?;
+        info!("unit file written to {}", path.display());
+    }
+    Ok(())
+}
+
+// `systemd-escape`と同様に、ユニット名に使えない文字を`\xNN`の形式に変換する。
+fn escape_unit_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for (i, b) in name.bytes().enumerate() {
+        match b {
+            b'/' => escaped.push('-'),
+            b'.' if i == 0 => escaped.push_str("\\x2e"),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b':' | b'_' | b'.' => escaped.push(b as char),
+            _ => escaped.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    escaped
+}
+
+// ユニットファイルの`ExecStart=`の引数として使えるように文字列を引用する。
+fn quote_arg(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    if !escaped.is_empty() && !escaped.contains(|c: char| c.is_whitespace() || c == '\'') {
+        escaped
+    } else {
+        format!("\"{}\"", escaped)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    RepoSelect(RepoSelectError),
+
+    Config(anyhow::Error),
+
+    InvalidArg(String),
+
+    IO(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Config(e) => write!(f, "invalid config: {}", e),
+            Error::InvalidArg(msg) => write!(f, "Invalid command-line arguments: {}", msg),
+            Error::IO(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::RepoSelect(e) => Some(e),
+            Error::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::RepoSelect(e) => e.kind(),
+            Error::Config(_) => ErrorKind::Config,
+            Error::InvalidArg(_) => ErrorKind::Usage,
+            Error::IO(_) => ErrorKind::IO,
+        }
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IO(e)
+    }
+}
@@ -0,0 +1,248 @@
+use std::error;
+use std::fmt;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::{
+    open_repository, resolve_bank, select_history, BankSelectError, ExecResult, HistorySelectError,
+    RepoSelectError, SubCmd,
+};
+
+use crate::config::Config;
+use crate::core::diff::{diff_trees, stat};
+use crate::core::repo::{self, Bank, History};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::util::size::Size;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Diff::new())
+}
+
+pub struct Diff();
+
+impl Diff {
+    pub fn new() -> Diff {
+        Diff()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let repo = open_repository(&config)?;
+
+        let bank_name = resolve_bank(matches, &config, &repo)?;
+        let bank = repo.open_bank(&bank_name)?;
+
+        let new = select_history(&bank, matches.value_of("new"))?;
+        let old = match matches.value_of("old") {
+            Some(rev) => select_history(&bank, Some(rev))?,
+            None => previous_history(&bank, &new)?,
+        };
+        let changes = diff_trees(&bank, old.id(), new.id())?;
+
+        if matches.is_present("name_only") {
+            for change in &changes {
+                println!("{}", change.path);
+            }
+            return Ok(());
+        }
+
+        if matches.is_present("stat") {
+            let stats = stat(&changes);
+            if matches.is_present("json") {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+                return Ok(());
+            }
+            if changes.is_empty() {
+                println!("{}", Msg::DiffNoChanges);
+                return Ok(());
+            }
+            for s in &stats {
+                println!(
+                    "{:<24} {:>6} {:>6} {:>6} {:>12}",
+                    s.dir,
+                    format!("+{}", s.added),
+                    format!("~{}", s.updated),
+                    format!("-{}", s.removed),
+                    signed_size(s.delta)
+                );
+            }
+            let count = |f: fn(&_) -> usize| stats.iter().map(f).sum::<usize>();
+            println!(
+                "{}",
+                Msg::DiffStatTotal.fill(&[
+                    &count(|s| s.added),
+                    &count(|s| s.updated),
+                    &count(|s| s.removed),
+                    &signed_size(stats.iter().map(|s| s.delta).sum())
+                ])
+            );
+            return Ok(());
+        }
+
+        if matches.is_present("json") {
+            println!("{}", serde_json::to_string_pretty(&changes)?);
+            return Ok(());
+        }
+
+        if changes.is_empty() {
+            println!("{}", Msg::DiffNoChanges);
+            return Ok(());
+        }
+        for change in &changes {
+            let size = change.new_size.or(change.old_size).unwrap_or(0);
+            println!(
+                "{:<7}  {:>12}  {}",
+                change.kind,
+                Size::from(size).to_string(),
+                change.path
+            );
+        }
+
+        Ok(())
+    }
+}
+
+// `history`の直前の履歴を返す。
+fn previous_history(bank: &Bank, history: &History) -> Result<History> {
+    bank.histories()?
+        .into_iter()
+        .rfind(|h| h.timestamp() < history.timestamp())
+        .ok_or_else(|| Error::NoPrevious(history.id().to_string()))
+}
+
+// 増減を符号付きで表示する。
+fn signed_size(delta: i64) -> String {
+    let sign = if delta < 0 { '-' } else { '+' };
+    format!("{}{}", sign, Size::from(delta.unsigned_abs()))
+}
+
+impl SubCmd for Diff {
+    fn name(&self) -> &'static str {
+        "diff"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutDiff.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Bank name"),
+            )
+            .arg(
+                Arg::with_name("name_only")
+                    .long("name-only")
+                    .conflicts_with_all(&["stat", "json"])
+                    .help("Show only paths of changed files"),
+            )
+            .arg(
+                Arg::with_name("stat")
+                    .long("stat")
+                    .help("Show counts and byte deltas per top-level directory"),
+            )
+            .arg(Arg::with_name("json").long("json").help("Output as JSON"))
+            .arg(
+                Arg::with_name("old")
+                    .index(1)
+                    .help("Old revision [default: revision before new one]"),
+            )
+            .arg(
+                Arg::with_name("new")
+                    .index(2)
+                    .help("New revision [default: last backup]"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Bank(BankSelectError),
+
+    History(HistorySelectError),
+
+    Json(serde_json::Error),
+
+    NoPrevious(String),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Bank(e) => write!(f, "{}", e),
+            Error::History(e) => write!(f, "{}", e),
+            Error::Json(e) => write!(f, "failed to write JSON: {}", e),
+            Error::NoPrevious(id) => write!(f, "no history before {} to compare with", id),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Bank(e) => Some(e),
+            Error::History(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Bank(e) => e.kind(),
+            Error::History(e) => e.kind(),
+            Error::Json(_) => ErrorKind::Other,
+            Error::NoPrevious(_) => ErrorKind::NotFound,
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Json(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<BankSelectError> for Error {
+    fn from(e: BankSelectError) -> Error {
+        Error::Bank(e)
+    }
+}
+
+impl From<HistorySelectError> for Error {
+    fn from(e: HistorySelectError) -> Error {
+        Error::History(e)
+    }
+}
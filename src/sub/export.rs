@@ -0,0 +1,244 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufWriter};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::warn;
+
+use super::{
+    open_repository, resolve_bank, select_history, BankSelectError, ExecResult, HistorySelectError,
+    RepoSelectError, SubCmd,
+};
+
+use crate::config::Config;
+use crate::core::export::{self, Exported, Exporter, Format};
+use crate::core::repo::{self, Bank, History};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::util::size::Size;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Export::new())
+}
+
+pub struct Export();
+
+impl Export {
+    pub fn new() -> Export {
+        Export()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<usize> {
+        let format_name = matches.value_of("format").unwrap();
+        let format: Format = format_name.parse().map_err(Error::Arg)?;
+        let path = matches.value_of("path").unwrap_or("");
+        let output = matches.value_of("output").unwrap();
+
+        let repo = open_repository(&config)?;
+        let bank_name = resolve_bank(matches, &config, &repo)?;
+        let bank = repo.open_bank(&bank_name)?;
+        let history = select_history(&bank, matches.value_of("revision"))?;
+
+        let exported = if output == "-" {
+            let stdout = io::stdout();
+            let out = BufWriter::new(stdout.lock());
+            export_to(&bank, &history, format, path, out)?
+        } else {
+            let out = BufWriter::new(fs::File::create(output)?);
+            match export_to(&bank, &history, format, path, out) {
+                Ok(exported) => exported,
+                Err(e) => {
+                    // 途中まで書き出したアーカイブは壊れているため残さない
+                    let _ = fs::remove_file(output);
+                    return Err(e);
+                }
+            }
+        };
+
+        for name in &exported.skipped {
+            warn!(
+                "symbolic link {:?} was skipped: {} format doesn't support symbolic links",
+                name, format_name
+            );
+        }
+        if output != "-" && !config.is_quiet() {
+            println!(
+                "{}",
+                Msg::ExportDone.fill(&[
+                    &exported.files,
+                    &exported.dirs,
+                    &Size::from(exported.bytes),
+                    &output
+                ])
+            );
+        }
+
+        Ok(exported.skipped.len())
+    }
+}
+
+fn export_to<W: io::Write>(
+    bank: &Bank,
+    history: &History,
+    format: Format,
+    path: &str,
+    out: W,
+) -> Result<Exported> {
+    let exporter = Exporter::new(bank, format);
+    Ok(exporter.export(history.id(), path, out)?)
+}
+
+impl SubCmd for Export {
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutExport.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Bank name"),
+            )
+            .arg(
+                Arg::with_name("revision")
+                    .short("r")
+                    .long("revision")
+                    .takes_value(true)
+                    .help("Specify revision to export [default: last backup]"),
+            )
+            .arg(
+                Arg::with_name("path")
+                    .short("p")
+                    .long("path")
+                    .takes_value(true)
+                    .help("Export only this file or directory in snapshot"),
+            )
+            .arg(
+                Arg::with_name("format")
+                    .short("f")
+                    .long("format")
+                    .takes_value(true)
+                    .possible_values(&["tar", "zip"])
+                    .default_value("tar")
+                    .help("Archive format"),
+            )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Output archive file ('-' for standard output)"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        let skipped = self.wrapped_exec(matches, config)?;
+        if skipped > 0 {
+            return Ok(ErrorKind::Partial.exit_code());
+        }
+        Ok(0)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Arg(String),
+
+    Bank(BankSelectError),
+
+    Export(export::Error),
+
+    History(HistorySelectError),
+
+    IO(io::Error),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Arg(msg) => write!(f, "{}", msg),
+            Error::Bank(e) => write!(f, "{}", e),
+            Error::Export(e) => write!(f, "failed to export: {}", e),
+            Error::History(e) => write!(f, "{}", e),
+            Error::IO(e) => write!(f, "IO error: {}", e),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Bank(e) => Some(e),
+            Error::Export(e) => Some(e),
+            Error::History(e) => Some(e),
+            Error::IO(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Arg(_) => ErrorKind::Usage,
+            Error::Bank(e) => e.kind(),
+            Error::Export(e) => e.kind(),
+            Error::History(e) => e.kind(),
+            Error::IO(e) => Categorized::kind(e),
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl From<export::Error> for Error {
+    fn from(e: export::Error) -> Error {
+        Error::Export(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IO(e)
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<BankSelectError> for Error {
+    fn from(e: BankSelectError) -> Error {
+        Error::Bank(e)
+    }
+}
+
+impl From<HistorySelectError> for Error {
+    fn from(e: HistorySelectError) -> Error {
+        Error::History(e)
+    }
+}
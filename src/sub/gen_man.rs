@@ -0,0 +1,176 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::{app, sub_commands, ExecResult, SubCmd};
+
+use crate::config::Config;
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::util::man::Help;
+use crate::version::version;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(GenMan::new())
+}
+
+pub struct GenMan();
+
+impl GenMan {
+    pub fn new() -> GenMan {
+        GenMan()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let dir = Path::new(matches.value_of("dir").unwrap());
+        fs::create_dir_all(dir)?;
+
+        let subs = sub_commands();
+        let ver = version(0);
+        // 折り返されたヘルプは解析できないため、端末の幅によらず折り返さないようにする。
+        let app = app(&subs, &ver).set_term_width(0);
+
+        let mut writer = PageWriter {
+            app,
+            dir,
+            source: format!("sbak {}", ver),
+            written: 0,
+        };
+        writer.write_page(&mut Vec::new(), None)?;
+
+        if !config.is_quiet() {
+            println!(
+                "{}",
+                Msg::ManPagesWritten.fill(&[&writer.written, &dir.display()])
+            );
+        }
+        Ok(())
+    }
+}
+
+struct PageWriter<'a> {
+    app: App<'a, 'a>,
+    dir: &'a Path,
+    source: String,
+    written: usize,
+}
+
+impl<'a> PageWriter<'a> {
+    // `path`のサブコマンドのページを書き出し、その下のサブコマンドについて再帰的に繰り返す。
+    fn write_page(&mut self, path: &mut Vec<String>, parent: Option<&str>) -> Result<()> {
+        let help = Help::parse(&self.help(path)?);
+        let name = page_name(path);
+        let subcommands = help
+            .subcommands()
+            .into_iter()
+            .map(|s| s.to_owned())
+            .collect::<Vec<_>>();
+
+        let mut see_also = parent.map(|p| p.to_owned()).into_iter().collect::<Vec<_>>();
+        for sub in &subcommands {
+            path.push(sub.clone());
+            see_also.push(page_name(path));
+            path.pop();
+        }
+
+        let page = help.render(&name, &self.source, &see_also);
+        fs::write(self.dir.join(format!("{}.1", name)), page)?;
+        self.written += 1;
+
+        for sub in subcommands {
+            path.push(sub);
+            self.write_page(path, Some(&name))?;
+            path.pop();
+        }
+        Ok(())
+    }
+
+    // `sbak <path> --help`の出力を返す。
+    fn help(&self, path: &[String]) -> Result<String> {
+        let args = Some("sbak")
+            .into_iter()
+            .chain(path.iter().map(|s| s.as_str()))
+            .chain(Some("--help"));
+        match self.app.clone().get_matches_from_safe(args) {
+            Err(e) if e.kind == clap::ErrorKind::HelpDisplayed => Ok(e.message),
+            Err(e) => Err(Error::Help(path.join(" "), e.message)),
+            Ok(_) => Err(Error::Help(path.join(" "), "help isn't shown".to_owned())),
+        }
+    }
+}
+
+fn page_name(path: &[String]) -> String {
+    Some("sbak")
+        .into_iter()
+        .chain(path.iter().map(|s| s.as_str()))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+impl SubCmd for GenMan {
+    fn name(&self) -> &'static str {
+        "gen-man"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutGenMan.text())
+            .arg(
+                Arg::with_name("dir")
+                    .index(1)
+                    .required(true)
+                    .help("Output directory of man pages"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Help(String, String),
+
+    IO(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Help(cmd, msg) => write!(f, "failed to get help of '{}': {}", cmd, msg),
+            Error::IO(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Help(_, _) => ErrorKind::Other,
+            Error::IO(_) => ErrorKind::IO,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IO(e)
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::warn;
+use regex::Regex;
+
+use super::backup::partial_exit_code;
+use super::{
+    open_repository, resolve_bank, select_history, BankSelectError, ExecResult, HistorySelectError,
+    RepoSelectError, SubCmd,
+};
+
+use crate::config::Config;
+use crate::core::entry::FsHash;
+use crate::core::hash::HashID;
+use crate::core::ignore::pattern::{parse_lines, Match, Patterns};
+use crate::core::repo::{self, Bank, History};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+
+#[cfg(test)]
+mod test;
+
+// バイナリかどうかを判定するために先頭から読み込むバイト数
+const SNIFF_LEN: usize = 8 * 1024;
+// 1行として保持する最大のバイト数。超えた部分は読み捨てる。
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Grep::new())
+}
+
+pub struct Grep();
+
+impl Grep {
+    pub fn new() -> Grep {
+        Grep()
+    }
+
+    // 読み込めなかったファイルの数を返す。
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<u64> {
+        let repo = open_repository(&config)?;
+
+        let bank_name = resolve_bank(matches, &config, &repo)?;
+        let bank = repo.open_bank(&bank_name)?;
+
+        let regex = Regex::new(matches.value_of("pattern").unwrap())?;
+        let paths = parse_lines(matches.values_of("path").into_iter().flatten())
+            .map_err(|e| Error::Arg(format!("invalid path pattern: {}", e)))?;
+        let line_number = matches.is_present("line_number");
+
+        let histories = if matches.is_present("all") {
+            bank.histories()?
+        } else {
+            vec![select_history(&bank, matches.value_of("revision"))?]
+        };
+
+        let mut searcher = Searcher {
+            bank: &bank,
+            regex,
+            found: HashMap::new(),
+            errors: 0,
+        };
+        for history in &histories {
+            searcher.search_history(history, &paths, |path, n, line| {
+                if line_number {
+                    println!("{}:{}:{}:{}", history.id(), path, n, line);
+                } else {
+                    println!("{}:{}:{}", history.id(), path, line);
+                }
+            })?;
+        }
+
+        Ok(searcher.errors)
+    }
+}
+
+// 同じ内容のファイルを何度も読まないよう、オブジェクトごとの結果を保持する。
+struct Searcher<'a> {
+    bank: &'a Bank<'a>,
+    regex: Regex,
+    found: HashMap<HashID, Vec<(usize, String)>>,
+    errors: u64,
+}
+
+impl<'a> Searcher<'a> {
+    // `history`のうち`paths`で選択されたファイルを検索し、マッチした行ごとにパス、行番号、内容を`f`に渡す。
+    fn search_history<F>(&mut self, history: &History, paths: &Patterns, mut f: F) -> Result<()>
+    where
+        F: FnMut(&str, usize, &str),
+    {
+        // 深さごとの、祖先のディレクトリが`--path`で選択されているか
+        let mut selected_dirs = vec![paths.is_empty()];
+        for entry in self.bank.walk(history) {
+            let (path, entry) = entry?;
+            selected_dirs.truncate(path.depth());
+            let selected = match paths.matches(&path) {
+                Match::Ignored => true,
+                Match::Allowed => false,
+                Match::Parent => selected_dirs[path.depth() - 1],
+            };
+
+            let id = match entry {
+                FsHash::Dir(_) => {
+                    selected_dirs.push(selected);
+                    continue;
+                }
+                FsHash::File(file) if selected => file.id(),
+                _ => continue,
+            };
+            let path = path.to_string();
+            for (n, line) in self.search(&id, &path) {
+                f(&path, *n, line);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn search(&mut self, id: &HashID, path: &str) -> &[(usize, String)] {
+        if !self.found.contains_key(id) {
+            let lines = match self.grep_object(id) {
+                Ok(lines) => lines,
+                Err(e) => {
+                    warn!("failed to read {}: {}", path, e);
+                    self.errors += 1;
+                    Vec::new()
+                }
+            };
+            self.found.insert(id.clone(), lines);
+        }
+        &self.found[id]
+    }
+
+    // マッチした行の行番号と内容を返す。NUL文字を含むファイルはバイナリとみなして何も返さない。
+    //
+    // 先頭の`SNIFF_LEN`バイトにNUL文字があれば、行に分けずに読み飛ばす。
+    fn grep_object(&self, id: &HashID) -> std::result::Result<Vec<(usize, String)>, repo::Error> {
+        let mut r = BufReader::with_capacity(SNIFF_LEN, self.bank.stream_object(id)?);
+        if r.fill_buf()?.contains(&0) {
+            // 破損の検出のため、バイナリでも最後まで読み込む
+            io::copy(&mut r, &mut io::sink())?;
+            return Ok(Vec::new());
+        }
+
+        let mut lines = Vec::new();
+        let mut buf = Vec::new();
+        let mut binary = false;
+        for n in 1.. {
+            buf.clear();
+            if read_line(&mut r, &mut buf)? == 0 {
+                break;
+            }
+            // 破損の検出のため、バイナリでも最後まで読み込む
+            if binary || buf.contains(&0) {
+                binary = true;
+                continue;
+            }
+
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if self.regex.is_match(line) {
+                lines.push((n, line.to_owned()));
+            }
+        }
+
+        if binary {
+            lines.clear();
+        }
+        Ok(lines)
+    }
+}
+
+// 改行までを読み込み、先頭の`MAX_LINE_LEN`バイトまでを`buf`に追加する。
+//
+// 行全体のバイト数を返す。
+fn read_line<R: BufRead>(r: &mut R, buf: &mut Vec<u8>) -> io::Result<usize> {
+    let mut total = 0;
+    loop {
+        let (used, done) = {
+            let available = r.fill_buf()?;
+            if available.is_empty() {
+                return Ok(total);
+            }
+            let (used, done) = match available.iter().position(|&b| b == b'\n') {
+                Some(i) => (i + 1, true),
+                None => (available.len(), false),
+            };
+            let room = MAX_LINE_LEN.saturating_sub(buf.len());
+            buf.extend_from_slice(&available[..used.min(room)]);
+            (used, done)
+        };
+        r.consume(used);
+        total += used;
+        if done {
+            return Ok(total);
+        }
+    }
+}
+
+impl SubCmd for Grep {
+    fn name(&self) -> &'static str {
+        "grep"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutGrep.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Bank name"),
+            )
+            .arg(
+                Arg::with_name("revision")
+                    .short("r")
+                    .long("revision")
+                    .takes_value(true)
+                    .help("Specify revision to search [default: last backup]"),
+            )
+            .arg(
+                Arg::with_name("all")
+                    .long("all")
+                    .conflicts_with("revision")
+                    .help("Search all histories"),
+            )
+            .arg(
+                Arg::with_name("path")
+                    .short("p")
+                    .long("path")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Search only files matching pattern (same syntax as exclude patterns)"),
+            )
+            .arg(
+                Arg::with_name("line_number")
+                    .short("n")
+                    .long("line-number")
+                    .help("Show line numbers"),
+            )
+            .arg(
+                Arg::with_name("pattern")
+                    .index(1)
+                    .required(true)
+                    .help("Regular expression to search"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        let errors = self.wrapped_exec(matches, config)?;
+        Ok(partial_exit_code(errors))
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Arg(String),
+
+    Bank(BankSelectError),
+
+    History(HistorySelectError),
+
+    Regex(regex::Error),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Arg(msg) => write!(f, "{}", msg),
+            Error::Bank(e) => write!(f, "{}", e),
+            Error::History(e) => write!(f, "{}", e),
+            Error::Regex(e) => write!(f, "invalid regular expression: {}", e),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Bank(e) => Some(e),
+            Error::History(e) => Some(e),
+            Error::Regex(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Arg(_) => ErrorKind::Usage,
+            Error::Bank(e) => e.kind(),
+            Error::History(e) => e.kind(),
+            Error::Regex(_) => ErrorKind::Usage,
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<BankSelectError> for Error {
+    fn from(e: BankSelectError) -> Error {
+        Error::Bank(e)
+    }
+}
+
+impl From<HistorySelectError> for Error {
+    fn from(e: HistorySelectError) -> Error {
+        Error::History(e)
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(e: regex::Error) -> Error {
+        Error::Regex(e)
+    }
+}
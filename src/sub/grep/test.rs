@@ -0,0 +1,172 @@
+use super::*;
+
+use std::fs;
+use std::path::Path;
+
+use filetime::{set_file_mtime, FileTime};
+use tempfile::tempdir;
+
+use crate::core::repo::Repository;
+use crate::core::scan::Scanner;
+use crate::core::timestamp::Timestamp;
+
+// 内容を書き込み、前回のスキャンと更新日時が重ならないようにする。
+fn write(path: &Path, content: &[u8], mtime: i64) {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).unwrap();
+    }
+    fs::write(path, content).unwrap();
+    set_file_mtime(path, FileTime::from_unix_time(mtime, 0)).unwrap();
+}
+
+fn backup(bank: &Bank, timestamp: u64) -> History {
+    let root = Scanner::new(bank).scan().unwrap();
+    bank.save_history(root.id(), Timestamp::from(timestamp), None)
+        .unwrap()
+}
+
+fn searcher<'a>(bank: &'a Bank<'a>, pattern: &str) -> Searcher<'a> {
+    Searcher {
+        bank,
+        regex: Regex::new(pattern).unwrap(),
+        found: HashMap::new(),
+        errors: 0,
+    }
+}
+
+// マッチした行を`パス:行番号:内容`の形式で返す。
+fn search(searcher: &mut Searcher, history: &History, paths: &[&str]) -> Vec<String> {
+    let paths = parse_lines(paths).unwrap();
+    let mut found = Vec::new();
+    searcher
+        .search_history(history, &paths, |path, n, line| {
+            found.push(format!("{}:{}:{}", path, n, line))
+        })
+        .unwrap();
+    found
+}
+
+#[test]
+fn test_skip_binary() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    write(&target.join("text.txt"), b"foo\nbar\nfoo bar\n", 1);
+    write(&target.join("head.bin"), b"\0foo\n", 1);
+    // 先頭のブロックより後にNUL文字があるもの
+    let mut late = b"foo\n".repeat(SNIFF_LEN);
+    late.push(0);
+    write(&target.join("late.bin"), &late, 1);
+    // 改行の少ない巨大なバイナリ
+    let mut image = vec![0u8; SNIFF_LEN * 4];
+    image.extend_from_slice(b"foo\n");
+    write(&target.join("image.bin"), &image, 1);
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let history = backup(&bank, 1);
+
+    let mut searcher = searcher(&bank, "foo");
+    assert_eq!(
+        search(&mut searcher, &history, &[]),
+        vec!["text.txt:1:foo", "text.txt:3:foo bar"]
+    );
+    assert_eq!(searcher.errors, 0);
+}
+
+#[test]
+fn test_long_line() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    let mut content = b"x".repeat(MAX_LINE_LEN * 3);
+    content.extend_from_slice(b"tail\nfoo\n");
+    write(&target.join("long.txt"), &content, 1);
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let history = backup(&bank, 1);
+
+    // 保持する長さを超えた部分は検索されないが、行番号はずれない
+    assert_eq!(
+        search(&mut searcher(&bank, "foo|tail"), &history, &[]),
+        vec!["long.txt:2:foo"]
+    );
+    let found = search(&mut searcher(&bank, "^x"), &history, &[]);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].len(), "long.txt:1:".len() + MAX_LINE_LEN);
+}
+
+#[test]
+fn test_path_selection() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    write(&target.join("top.txt"), b"foo", 1);
+    write(&target.join("docs/a.txt"), b"foo", 1);
+    write(&target.join("docs/deep/nested/b.md"), b"foo", 1);
+    write(&target.join("src/docs/c.txt"), b"foo", 1);
+    write(&target.join("src/d.md"), b"foo", 1);
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let history = backup(&bank, 1);
+
+    let cases: Vec<(&[&str], Vec<&str>)> = vec![
+        (
+            &[],
+            vec![
+                "docs/a.txt",
+                "docs/deep/nested/b.md",
+                "src/d.md",
+                "src/docs/c.txt",
+                "top.txt",
+            ],
+        ),
+        (
+            &["docs/"],
+            vec!["docs/a.txt", "docs/deep/nested/b.md", "src/docs/c.txt"],
+        ),
+        (&["/docs/"], vec!["docs/a.txt", "docs/deep/nested/b.md"]),
+        (&["*.md"], vec!["docs/deep/nested/b.md", "src/d.md"]),
+        (
+            &["/top.txt", "/src/"],
+            vec!["src/d.md", "src/docs/c.txt", "top.txt"],
+        ),
+    ];
+
+    for (paths, to_be) in cases {
+        let mut found = search(&mut searcher(&bank, "foo"), &history, paths)
+            .into_iter()
+            .map(|l| l.trim_end_matches(":1:foo").to_owned())
+            .collect::<Vec<_>>();
+        found.sort();
+        assert_eq!(to_be, found, "paths = {:?}", paths);
+    }
+}
+
+#[test]
+fn test_all_reads_object_once() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    write(&target.join("a.txt"), b"foo 1", 1);
+    write(&target.join("b.txt"), b"bar", 1);
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let first = backup(&bank, 1);
+    write(&target.join("a.txt"), b"foo 2", 2);
+    // 同じ内容のファイルは別のパスでも一度だけ読み込む
+    write(&target.join("copy.txt"), b"foo 1", 2);
+    let second = backup(&bank, 2);
+
+    let mut searcher = searcher(&bank, "foo");
+    assert_eq!(search(&mut searcher, &first, &[]), vec!["a.txt:1:foo 1"]);
+    assert_eq!(
+        search(&mut searcher, &second, &[]),
+        vec!["a.txt:1:foo 2", "copy.txt:1:foo 1"]
+    );
+    // "foo 1"、"bar"、"foo 2"の3つ
+    assert_eq!(searcher.found.len(), 3);
+}
@@ -1,14 +1,25 @@
-use std::process::exit;
+use std::error;
+use std::fmt;
 
 use clap::{App, Arg, ArgMatches, SubCommand};
-use failure::Fail;
-use log::error;
 
-use super::SubCmd;
+use super::{open_repository, resolve_bank, BankSelectError, ExecResult, RepoSelectError, SubCmd};
 
 use crate::config::Config;
-use crate::core::repo::{self, Repository};
-use crate::util::time::Tz;
+use crate::core::repo::{self, ScanStats};
+use crate::core::timestamp::{self, Timestamp};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::util::format::Template;
+use crate::util::size::Size;
+use crate::util::time::{parse_duration, Tz};
+
+const DEFAULT_SHOW_COUNT: usize = 20;
+
+const FORMAT_FIELDS: &[&str] = &[
+    "id", "time", "epoch", "bank", "host", "user", "offset", "duration", "files", "dirs", "read",
+    "new", "errors", "memo", "kind",
+];
 
 pub fn new() -> Box<dyn SubCmd> {
     Box::new(History::new())
@@ -22,30 +33,43 @@ impl History {
     }
 
     fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
-        let repository = Repository::open(
-            config
-                .repository_path()
-                .ok_or(Error::NoValue("repository"))?,
-        )?;
-
-        let bank_name = matches.value_of("bank").unwrap();
-        let timezone =
-            Tz::from_name(matches.value_of("timezone")).map_err(Error::InvalidTimezone)?;
-
-        let show_count_str = matches.value_of("show_count").unwrap();
-        let show_count: usize = if show_count_str == "all" {
-            std::usize::MAX
+        let repository = open_repository(&config)?;
+
+        let bank_name = resolve_bank(matches, &config, &repository)?;
+        let timezone = Tz::from_name(
+            matches
+                .value_of("timezone")
+                .or_else(|| config.history_timezone()),
+        )
+        .map_err(Error::InvalidTimezone)?;
+
+        let template = matches
+            .value_of("format")
+            .map(|f| Template::parse(f, FORMAT_FIELDS))
+            .transpose()
+            .map_err(Error::InvalidCmdArg)?;
+
+        let show_count: usize = if let Some(show_count_str) = matches.value_of("show_count") {
+            if show_count_str == "all" {
+                std::usize::MAX
+            } else {
+                show_count_str.parse().map_err(|_| {
+                    Error::InvalidCmdArg(format!(
+                        "-n / --show-count '{}' is not number.",
+                        show_count_str
+                    ))
+                })?
+            }
         } else {
-            show_count_str.parse().map_err(|_| {
-                Error::InvalidCmdArg(format!(
-                    "-n / --show-count '{}' is not number.",
-                    show_count_str
-                ))
-            })?
+            config.history_show_count().unwrap_or(DEFAULT_SHOW_COUNT)
         };
 
-        let bank = repository.open_bank(bank_name)?;
-        let mut histories = bank.histories()?;
+        let now = Timestamp::now()?;
+        let since = parse_time_arg(matches, "since", timezone, now)?;
+        let until = parse_time_arg(matches, "until", timezone, now)?;
+
+        let bank = repository.open_bank(&bank_name)?;
+        let mut histories = bank.histories_between(since, until)?;
 
         let l = histories.len();
         if l > show_count {
@@ -55,77 +79,250 @@ impl History {
             histories = tail_histories;
         }
 
+        let show_stats = matches.is_present("stats");
+        let show_changes = matches.is_present("changes");
         for history in &histories {
             let at = history.timestamp().unix_epoch();
-            println!("{}    {}", timezone.at(at).format_datetime(), history.id());
+            let stats = history.stats();
+            let stat = |f: fn(&ScanStats) -> String| stats.map(f).unwrap_or_else(|| "-".to_owned());
+            if let Some(template) = &template {
+                println!(
+                    "{}",
+                    template.render(|field| match field {
+                        "id" => history.id().to_string(),
+                        "time" => timezone.at(at).format_datetime().to_string(),
+                        "epoch" => at.to_string(),
+                        "bank" => bank.name().to_owned(),
+                        "host" => history.host().unwrap_or("-").to_owned(),
+                        "user" => history.user().unwrap_or("-").to_owned(),
+                        "offset" => history
+                            .utc_offset()
+                            .map(format_utc_offset)
+                            .unwrap_or_else(|| "-".to_owned()),
+                        "duration" => stat(|s| format_duration(s.duration_ms)),
+                        "files" => stat(|s| s.files.to_string()),
+                        "dirs" => stat(|s| s.dirs.to_string()),
+                        "read" => stat(|s| Size::from(s.bytes_read).to_string()),
+                        "new" => stat(|s| Size::from(s.new_bytes).to_string()),
+                        "errors" => stat(|s| s.errors.to_string()),
+                        "memo" => history.memo().unwrap_or("").to_owned(),
+                        "kind" => history_kind(history).to_owned(),
+                        _ => unreachable!(),
+                    })
+                );
+            } else {
+                print!("{}    {}", timezone.at(at).format_datetime(), history.id());
+                if history.is_inventory() {
+                    print!("    ({})", history_kind(history));
+                }
+                // 実行環境が記録されていない古い履歴では表示しない
+                if let (Some(host), Some(user)) = (history.host(), history.user()) {
+                    print!("    {}@{}", user, host);
+                    if let Some(offset) = history.utc_offset() {
+                        print!(" ({})", format_utc_offset(offset));
+                    }
+                }
+                // 統計が記録されていない古い履歴では表示しない
+                if let Some(stats) = stats.filter(|_| show_stats) {
+                    print!(
+                        "    {} files, {} dirs, {} read, {} new, {} errors, {}",
+                        stats.files,
+                        stats.dirs,
+                        Size::from(stats.bytes_read),
+                        Size::from(stats.new_bytes),
+                        stats.errors,
+                        format_duration(stats.duration_ms)
+                    );
+                }
+                if let Some(memo) = history.memo() {
+                    print!("    {:?}", memo);
+                }
+                println!();
+                if show_changes {
+                    for entry in bank.load_inventory(history)?.into_iter().flatten() {
+                        println!(
+                            "    {:<8}{:>12}  {}",
+                            entry.kind,
+                            Size::from(entry.size).to_string(),
+                            entry.path
+                        );
+                    }
+                }
+            }
         }
 
         Ok(())
     }
 }
 
+// 日時を指定するオプションの値を解釈する。
+//
+// `7d`のような経過時間は`now`からさかのぼった時刻、それ以外は`timezone`での日時として扱う。
+fn parse_time_arg(
+    matches: &ArgMatches,
+    name: &str,
+    timezone: Tz,
+    now: Timestamp,
+) -> Result<Option<Timestamp>> {
+    matches
+        .value_of(name)
+        .map(|s| {
+            parse_duration(s)
+                .map(|secs| now.unix_epoch().saturating_sub(secs))
+                .or_else(|| timezone.parse_datetime(s))
+                .map(Timestamp::from)
+                .ok_or_else(|| {
+                    Error::InvalidCmdArg(format!(
+                        "--{} '{}' is not valid date, datetime or duration.",
+                        name, s
+                    ))
+                })
+        })
+        .transpose()
+}
+
+// UTCからの時差を`+09:00`の形式で表す。
+fn format_utc_offset(secs: i32) -> String {
+    let sign = if secs < 0 { '-' } else { '+' };
+    let minutes = secs.abs() / 60;
+    format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+}
+
+// 履歴の種類を、通常の履歴は`full`、棚卸し履歴は`inventory`と表す。
+fn history_kind(history: &repo::History) -> &'static str {
+    if history.is_inventory() {
+        "inventory"
+    } else {
+        "full"
+    }
+}
+
+// 所要時間を`12.3s`の形式で表す。
+fn format_duration(ms: u64) -> String {
+    format!("{:.1}s", ms as f64 / 1000.0)
+}
+
 impl SubCmd for History {
     fn name(&self) -> &'static str {
         "history"
     }
 
+    fn aliases(&self) -> &[&'static str] {
+        &["hist"]
+    }
+
     fn command_args(&self) -> App {
         SubCommand::with_name(self.name())
-            .about("show history")
+            .about(Msg::AboutHistory.text())
             .arg(
                 Arg::with_name("bank")
                     .short("b")
                     .long("bank")
                     .takes_value(true)
-                    .required(true)
                     .help("Bank name"),
             )
             .arg(
                 Arg::with_name("show_count")
                     .short("n")
                     .long("show-cownt")
-                    .default_value("20")
-                    .help("How many history to be shown."),
+                    .takes_value(true)
+                    .help("How many history to be shown. [default: 20]"),
             )
             .arg(
                 Arg::with_name("timezone")
                     .short("z")
                     .long("timezone")
-                    .default_value("local")
-                    .help("Show time in specified timezone."),
+                    .takes_value(true)
+                    .help("Show time in specified timezone. [default: local]"),
+            )
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .takes_value(true)
+                    .help("Output format like \"{id:.8} {time}\". Fields: id, time, epoch, bank, host, user, offset, duration, files, dirs, read, new, errors, memo, kind"),
+            )
+            .arg(
+                Arg::with_name("since")
+                    .long("since")
+                    .takes_value(true)
+                    .help("Show histories started at or after this time in --timezone, or this long ago (e.g. 2019-01-01, 2019-01-01T12:00:00, 7d)"),
+            )
+            .arg(
+                Arg::with_name("until")
+                    .long("until")
+                    .takes_value(true)
+                    .help("Show histories started before this time in --timezone, or this long ago"),
+            )
+            .arg(
+                Arg::with_name("stats")
+                    .short("s")
+                    .long("stats")
+                    .help("Show scan statistics recorded at backup"),
+            )
+            .arg(
+                Arg::with_name("changes")
+                    .long("changes")
+                    .help("Show changed files recorded in inventory histories"),
             )
     }
 
-    fn exec(&self, matches: &ArgMatches, config: Config) -> ! {
-        match self.wrapped_exec(matches, config) {
-            Ok(()) => exit(0),
-            Err(e) => {
-                if cfg!(debug_assertions) {
-                    error!("{:#?}", e);
-                } else {
-                    error!("{}", e);
-                }
-                exit(1)
-            }
-        }
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
     }
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
-    #[fail(display = "Invalid command-line arguments: {}", _0)]
+    Bank(BankSelectError),
+
     InvalidCmdArg(String),
 
-    #[fail(display = "Invalid timezone: {}", _0)]
     InvalidTimezone(String),
 
-    #[fail(display = "no config value: {}", _0)]
-    NoValue(&'static str),
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+
+    Timestamp,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Bank(e) => write!(f, "{}", e),
+            Error::InvalidCmdArg(msg) => write!(f, "Invalid command-line arguments: {}", msg),
+            Error::InvalidTimezone(msg) => write!(f, "Invalid timezone: {}", msg),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+            Error::Timestamp => write!(f, "timestamp is older than UNIX epoch"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Bank(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
-    #[fail(display = "repository operation error: {}", _0)]
-    Repo(#[fail(cause)] repo::Error),
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Bank(e) => e.kind(),
+            Error::InvalidCmdArg(_) | Error::InvalidTimezone(_) => ErrorKind::Usage,
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+            Error::Timestamp => ErrorKind::Data,
+        }
+    }
 }
 
 impl From<repo::Error> for Error {
@@ -133,3 +330,21 @@ impl From<repo::Error> for Error {
         Error::Repo(e)
     }
 }
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<BankSelectError> for Error {
+    fn from(e: BankSelectError) -> Error {
+        Error::Bank(e)
+    }
+}
+
+impl From<timestamp::Error> for Error {
+    fn from(_e: timestamp::Error) -> Error {
+        Error::Timestamp
+    }
+}
@@ -0,0 +1,125 @@
+mod import;
+
+use std::error;
+use std::fmt;
+use std::io::{self, stderr};
+
+use clap::{App, ArgMatches, SubCommand};
+
+use super::{BankSelectError, ExecResult, RepoSelectError, SubCmd, SubCommandSet};
+
+use crate::config::Config;
+use crate::core::repo;
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Ignore::new())
+}
+
+pub struct Ignore(SubCommandSet);
+
+impl Ignore {
+    pub fn new() -> Ignore {
+        let mut subs: SubCommandSet = SubCommandSet::new();
+
+        subs.append(import::new());
+
+        Ignore(subs)
+    }
+}
+
+impl SubCmd for Ignore {
+    fn name(&self) -> &'static str {
+        "ignore"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutIgnore.text())
+            .subcommands(self.0.arg_defs())
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        if let (subcmd_name, Some(matches)) = matches.subcommand() {
+            if let Some(result) = self.0.execute(subcmd_name, matches, config) {
+                return result;
+            }
+        }
+
+        let mut out = stderr();
+        self.command_args().write_long_help(&mut out).unwrap();
+        eprintln!();
+        Ok(1)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Bank(BankSelectError),
+
+    IO(io::Error),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Bank(e) => write!(f, "{}", e),
+            Error::IO(e) => write!(f, "IO error: {}", e),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Bank(e) => Some(e),
+            Error::IO(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Bank(e) => e.kind(),
+            Error::IO(e) => Categorized::kind(e),
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IO(e)
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<BankSelectError> for Error {
+    fn from(e: BankSelectError) -> Error {
+        Error::Bank(e)
+    }
+}
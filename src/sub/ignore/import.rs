@@ -0,0 +1,106 @@
+use std::fs;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::warn;
+
+use super::super::{open_repository, resolve_bank, ExecResult, SubCmd};
+use super::Result;
+
+use crate::config::Config;
+use crate::core::ignore::pattern::{convert_gitignore, IssueKind};
+use crate::error::ErrorKind;
+use crate::message::Msg;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Import::new())
+}
+
+pub struct Import();
+
+impl Import {
+    pub fn new() -> Import {
+        Import()
+    }
+
+    // 変換できずにコメントとして残した行の数を返す。
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<usize> {
+        let from = matches.value_of("from_gitignore").unwrap();
+
+        let content = fs::read_to_string(from)?;
+        let converted = convert_gitignore(content.lines());
+        for issue in &converted.issues {
+            warn!("{}:{}", from, issue);
+        }
+        let unsupported = converted
+            .issues
+            .iter()
+            .filter(|issue| matches!(issue.kind, IssueKind::Unsupported(_)))
+            .count();
+
+        let mut lines = vec![format!("# imported from {}", from)];
+        lines.extend(converted.lines);
+
+        if matches.is_present("dry_run") {
+            for line in &lines {
+                println!("{}", line);
+            }
+            return Ok(unsupported);
+        }
+
+        let repo = open_repository(&config)?;
+        let bank_name = resolve_bank(matches, &config, &repo)?;
+        let bank = repo.open_bank(&bank_name)?;
+        bank.append_ignore_lines(&lines)?;
+
+        if !config.is_quiet() {
+            let patterns = lines
+                .iter()
+                .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+                .count();
+            println!(
+                "{}",
+                Msg::IgnoreImported.fill(&[&patterns, &from, &bank_name, &converted.issues.len()])
+            );
+        }
+        Ok(unsupported)
+    }
+}
+
+impl SubCmd for Import {
+    fn name(&self) -> &'static str {
+        "import"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutIgnoreImport.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Bank name"),
+            )
+            .arg(
+                Arg::with_name("from_gitignore")
+                    .long("from-gitignore")
+                    .takes_value(true)
+                    .required(true)
+                    .value_name("FILE")
+                    .help(".gitignore file to import"),
+            )
+            .arg(
+                Arg::with_name("dry_run")
+                    .long("dry-run")
+                    .help("Print converted rules instead of adding them to bank"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        let unsupported = self.wrapped_exec(matches, config)?;
+        if unsupported > 0 {
+            return Ok(ErrorKind::Partial.exit_code());
+        }
+        Ok(0)
+    }
+}
@@ -1,13 +1,15 @@
+use std::error;
+use std::fmt;
 use std::io;
-use std::process::exit;
 
 use clap::{App, Arg, ArgMatches, SubCommand};
-use failure::Fail;
 use log::{debug, error, info, trace, warn};
 
-use super::SubCmd;
+use super::{ExecResult, SubCmd};
 
-use crate::config::Config;
+use crate::config::{config_pathes, repository_config_path, Config};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
 use crate::version::version;
 
 pub fn new() -> Box<dyn SubCmd> {
@@ -22,14 +24,45 @@ impl Info {
     }
 
     fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
-        println!("Version:");
+        println!("{}", Msg::InfoVersion);
         println!("    {}", version(10));
         println!();
 
-        println!("Config:");
+        println!("{}", Msg::InfoConfig);
         config.show();
         println!();
 
+        if matches.is_present("config_paths") {
+            let mut candidates = config_pathes().map_err(Error::Config)?;
+            // リポジトリ内の設定は、`--repo`や探索で決まったリポジトリのものが読み込まれる
+            if let Some(repo_path) = config.repository_path() {
+                candidates.push(repository_config_path(repo_path));
+            }
+            // `--config`で指定されたファイルは最後に読み込まれる
+            for path in config.loaded_pathes() {
+                if !candidates.contains(path) {
+                    candidates.push(path.clone());
+                }
+            }
+
+            println!("{}", Msg::InfoConfigSearchPaths);
+            for path in &candidates {
+                let mark = if config.loaded_pathes().contains(path) {
+                    "loaded"
+                } else {
+                    "-"
+                };
+                println!("    {:6}  {}", mark, path.display());
+            }
+            println!();
+
+            println!("{}", Msg::InfoLoadedConfigFiles);
+            for path in config.loaded_pathes() {
+                println!("    {}", path.display());
+            }
+            println!();
+        }
+
         if matches.is_present("log_test") {
             error!("Error log");
             warn!("Warn log");
@@ -49,31 +82,55 @@ impl SubCmd for Info {
 
     fn command_args(&self) -> App {
         SubCommand::with_name(self.name())
-            .about("show informations")
+            .about(Msg::AboutInfo.text())
             .arg(Arg::with_name("log_test").long("log-test"))
+            .arg(
+                Arg::with_name("config_paths")
+                    .long("config-paths")
+                    .help("Show config file search paths and loaded files"),
+            )
     }
 
-    fn exec(&self, matches: &ArgMatches, config: Config) -> ! {
-        match self.wrapped_exec(matches, config) {
-            Ok(()) => exit(0),
-            Err(e) => {
-                if cfg!(debug_assertions) {
-                    error!("{:#?}", e);
-                } else {
-                    error!("{}", e);
-                }
-                exit(1)
-            }
-        }
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
     }
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
-    #[fail(display = "failed scan with IO error: {}", _0)]
-    IO(#[fail(cause)] io::Error),
+    Config(anyhow::Error),
+
+    IO(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Config(e) => write!(f, "failed to get config paths: {}", e),
+            Error::IO(e) => write!(f, "failed scan with IO error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Config(_) => ErrorKind::Config,
+            Error::IO(e) => Categorized::kind(e),
+        }
+    }
 }
 
 impl From<io::Error> for Error {
@@ -2,13 +2,13 @@ mod bank;
 mod repo;
 
 use std::io::stderr;
-use std::process::exit;
 
 use clap::{App, ArgMatches, SubCommand};
 
-use super::{SubCmd, SubCommandSet};
+use super::{ExecResult, SubCmd, SubCommandSet};
 
 use crate::config::Config;
+use crate::message::Msg;
 
 pub fn new() -> Box<dyn SubCmd> {
     Box::new(Init::new())
@@ -34,19 +34,20 @@ impl SubCmd for Init {
 
     fn command_args(&self) -> App {
         SubCommand::with_name(self.name())
-            .about("create or initialize repository/bank")
+            .about(Msg::AboutInit.text())
             .subcommands(self.0.arg_defs())
     }
 
-    fn exec(&self, matches: &ArgMatches, config: Config) -> ! {
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
         if let (subcmd_name, Some(matches)) = matches.subcommand() {
-            self.0.execute(subcmd_name, matches, config); // 成功したらそのままプロセスを終了する
-            exit(0)
+            if let Some(result) = self.0.execute(subcmd_name, matches, config) {
+                return result;
+            }
         }
 
         let mut out = stderr();
         self.command_args().write_long_help(&mut out).unwrap();
         eprintln!();
-        exit(1);
+        Ok(1)
     }
 }
@@ -1,13 +1,14 @@
-use std::process::exit;
+use std::error;
+use std::fmt;
 
 use clap::{App, Arg, ArgMatches, SubCommand};
-use failure::Fail;
-use log::error;
 
-use super::super::SubCmd;
+use super::super::{open_repository, ExecResult, RepoSelectError, SubCmd};
 
 use crate::config::Config;
-use crate::core::repo::{self, Repository};
+use crate::core::repo;
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
 
 pub fn new() -> Box<dyn SubCmd> {
     Box::new(Bank::new())
@@ -21,18 +22,14 @@ impl Bank {
     }
 
     fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
-        let repo_path = matches
-            .value_of("repo")
-            .map(|s| s.parse().unwrap())
-            .or_else(|| config.repository_path().map(|p| p.to_owned()))
-            .ok_or_else(|| Error::Arg("no repository path"))?;
-
-        let repository = Repository::open(&repo_path)?;
+        let repository = open_repository(&config)?;
         let name = matches.value_of("name").unwrap();
         let path = matches.value_of("path").unwrap();
 
         if repository.bank_exists(name)? {
-            println!("bank '{}' already exists.", name);
+            if !config.is_quiet() {
+                println!("{}", Msg::BankAlreadyExists.fill(&[&name]));
+            }
             return Ok(());
         }
 
@@ -49,13 +46,7 @@ impl SubCmd for Bank {
 
     fn command_args(&self) -> App {
         SubCommand::with_name(self.name())
-            .about("create or initialize bank")
-            .arg(
-                Arg::with_name("repo")
-                    .long("repo")
-                    .takes_value(true)
-                    .help("Overwrite repository path"),
-            )
+            .about(Msg::AboutInitBank.text())
             .arg(
                 Arg::with_name("name")
                     .short("n")
@@ -74,30 +65,46 @@ impl SubCmd for Bank {
             )
     }
 
-    fn exec(&self, matches: &ArgMatches, config: Config) -> ! {
-        match self.wrapped_exec(matches, config) {
-            Ok(()) => exit(0),
-            Err(e) => {
-                if cfg!(debug_assertions) {
-                    error!("{:#?}", e);
-                } else {
-                    error!("{}", e);
-                }
-                exit(1)
-            }
-        }
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
     }
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
-    #[fail(display = "repository operation error: {}", _0)]
-    Repo(#[fail(cause)] repo::Error),
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
 
-    #[fail(display = "{}", _0)]
-    Arg(&'static str),
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+        }
+    }
 }
 
 impl From<repo::Error> for Error {
@@ -105,3 +112,9 @@ impl From<repo::Error> for Error {
         Error::Repo(e)
     }
 }
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
@@ -1,13 +1,15 @@
-use std::process::exit;
+use std::error;
+use std::fmt;
 
 use clap::{App, Arg, ArgMatches, SubCommand};
-use failure::Fail;
-use log::error;
 
-use super::super::SubCmd;
+use super::super::{ExecResult, SubCmd};
 
-use crate::config::Config;
+use crate::config::{set_user_repository_path, Config};
+use crate::core::encoding::Encoding;
 use crate::core::repo::{self, Repository};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
 
 pub fn new() -> Box<dyn SubCmd> {
     Box::new(Repo::new())
@@ -20,15 +22,76 @@ impl Repo {
         Repo()
     }
 
-    fn wrapped_exec(&self, matches: &ArgMatches, _config: Config) -> Result<()> {
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
         let path = matches.value_of("path").unwrap();
+        let encoding = matches
+            .value_of("entry_encoding")
+            .map(|s| s.parse::<Encoding>())
+            .transpose()
+            .map_err(Error::Arg)?;
 
-        let _ = Repository::create(path)?;
+        let changes_settings = encoding.is_some()
+            || matches.is_present("parity")
+            || matches.is_present("verify_after_write")
+            || matches.is_present("no_verify_after_write");
 
-        Ok(())
+        let mut repo = if Repository::is_repository(path) {
+            // 既存のリポジトリは、`--force`で設定の変更を指示された場合のみ書き換える
+            if !matches.is_present("force") {
+                if changes_settings {
+                    return Err(Error::Arg(format!(
+                        "repository {:?} already exists (use --force to change its settings)",
+                        path
+                    )));
+                }
+                if !config.is_quiet() {
+                    println!("{}", Msg::RepoAlreadyExists.fill(&[&path]));
+                }
+                return set_default(matches, path, &config);
+            }
+            Repository::open(path)?
+        } else if matches.is_present("force") {
+            Repository::create_force(path)?
+        } else {
+            Repository::create(path).map_err(|e| match e {
+                repo::Error::NotEmptyDirectory(p) => Error::Arg(format!(
+                    "directory {:?} is not empty and is not a repository (use --force to create repository there)",
+                    p
+                )),
+                e => Error::Repo(e),
+            })?
+        };
+        if let Some(encoding) = encoding {
+            repo.set_entry_encoding(encoding)?;
+        }
+        if matches.is_present("parity") {
+            repo.set_parity_enabled(true)?;
+        }
+        if matches.is_present("verify_after_write") {
+            repo.set_verify_after_write(true)?;
+        } else if matches.is_present("no_verify_after_write") {
+            repo.set_verify_after_write(false)?;
+        }
+
+        set_default(matches, path, &config)
     }
 }
 
+// `--set-default`が指定されていれば、`path`をユーザー設定ファイルに書き込む。
+fn set_default(matches: &ArgMatches, path: &str, config: &Config) -> Result<()> {
+    if matches.is_present("set_default") {
+        let config_path = set_user_repository_path(path).map_err(Error::Config)?;
+        if !config.is_quiet() {
+            println!(
+                "{}",
+                Msg::RepoSetDefault.fill(&[&path, &config_path.display()])
+            );
+        }
+    }
+
+    Ok(())
+}
+
 impl SubCmd for Repo {
     fn name(&self) -> &'static str {
         "repo"
@@ -36,7 +99,7 @@ impl SubCmd for Repo {
 
     fn command_args(&self) -> App {
         SubCommand::with_name(self.name())
-            .about("create or initialize repository")
+            .about(Msg::AboutInitRepo.text())
             .arg(
                 Arg::with_name("path")
                     .short("p")
@@ -44,29 +107,85 @@ impl SubCmd for Repo {
                     .takes_value(true)
                     .required(true),
             )
+            .arg(
+                Arg::with_name("force")
+                    .long("force")
+                    .help("Create repository even if directory has other files, or change settings of existing repository"),
+            )
+            .arg(
+                Arg::with_name("entry_encoding")
+                    .long("entry-encoding")
+                    .takes_value(true)
+                    .possible_values(&["json", "cbor"])
+                    .help("Format to store directory entries and histories (default: json)"),
+            )
+            .arg(
+                Arg::with_name("parity")
+                    .long("parity")
+                    .help("Store parity data to repair corrupted objects"),
+            )
+            .arg(
+                Arg::with_name("verify_after_write")
+                    .long("verify-after-write")
+                    .help("Read back and verify each object right after saving it"),
+            )
+            .arg(
+                Arg::with_name("no_verify_after_write")
+                    .long("no-verify-after-write")
+                    .conflicts_with("verify_after_write")
+                    .help("Stop verifying objects after saving them"),
+            )
+            .arg(
+                Arg::with_name("set_default")
+                    .long("set-default")
+                    .help("Write repository path to user config file"),
+            )
     }
 
-    fn exec(&self, matches: &ArgMatches, config: Config) -> ! {
-        match self.wrapped_exec(matches, config) {
-            Ok(()) => exit(0),
-            Err(e) => {
-                if cfg!(debug_assertions) {
-                    error!("{:#?}", e);
-                } else {
-                    error!("{}", e);
-                }
-                exit(1)
-            }
-        }
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
     }
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
-    #[fail(display = "repository operation error: {}", _0)]
-    Repo(#[fail(cause)] repo::Error),
+    Arg(String),
+
+    Config(anyhow::Error),
+
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Arg(msg) => write!(f, "{}", msg),
+            Error::Config(e) => write!(f, "failed to update config file: {:#}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Arg(_) => ErrorKind::Usage,
+            Error::Config(_) => ErrorKind::Config,
+            Error::Repo(e) => e.kind(),
+        }
+    }
 }
 
 impl From<repo::Error> for Error {
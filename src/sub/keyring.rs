@@ -0,0 +1,106 @@
+mod delete;
+mod set;
+
+use std::error;
+use std::fmt;
+use std::io::{self, stderr};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::{ExecResult, SubCmd, SubCommandSet};
+
+use crate::config::{Config, KEYRING_NAMES};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Keyring::new())
+}
+
+pub struct Keyring(SubCommandSet);
+
+impl Keyring {
+    pub fn new() -> Keyring {
+        let mut subs: SubCommandSet = SubCommandSet::new();
+
+        subs.append(delete::new());
+        subs.append(set::new());
+
+        Keyring(subs)
+    }
+}
+
+impl SubCmd for Keyring {
+    fn name(&self) -> &'static str {
+        "keyring"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutKeyring.text())
+            .subcommands(self.0.arg_defs())
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        if let (subcmd_name, Some(matches)) = matches.subcommand() {
+            if let Some(result) = self.0.execute(subcmd_name, matches, config) {
+                return result;
+            }
+        }
+
+        let mut out = stderr();
+        self.command_args().write_long_help(&mut out).unwrap();
+        eprintln!();
+        Ok(1)
+    }
+}
+
+// 秘密情報の名前を指定する引数
+fn name_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("name")
+        .required(true)
+        .possible_values(KEYRING_NAMES)
+        .help("Name of secret")
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Empty,
+
+    IO(io::Error),
+
+    Keyring(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Empty => write!(f, "secret is empty"),
+            Error::IO(e) => write!(f, "failed to read secret: {}", e),
+            Error::Keyring(e) => write!(f, "keyring operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::IO(e) => Some(e),
+            Error::Keyring(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Empty => ErrorKind::Usage,
+            Error::IO(e) => Categorized::kind(e),
+            Error::Keyring(e) if e.kind() == io::ErrorKind::Unsupported => ErrorKind::Usage,
+            Error::Keyring(_) => ErrorKind::IO,
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use clap::{App, ArgMatches, SubCommand};
+
+use super::super::{ExecResult, SubCmd};
+use super::{name_arg, Error, Result};
+
+use crate::config::Config;
+use crate::message::Msg;
+use crate::util::keyring;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Delete::new())
+}
+
+pub struct Delete();
+
+impl Delete {
+    pub fn new() -> Delete {
+        Delete()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<bool> {
+        let name = matches.value_of("name").unwrap();
+        let deleted = keyring::delete(name).map_err(Error::Keyring)?;
+
+        if !config.is_quiet() {
+            if deleted {
+                println!("{}", Msg::KeyringDeleted.fill(&[&name]));
+            } else {
+                println!("{}", Msg::KeyringNotStored.fill(&[&name]));
+            }
+        }
+        Ok(deleted)
+    }
+}
+
+impl SubCmd for Delete {
+    fn name(&self) -> &'static str {
+        "delete"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutKeyringDelete.text())
+            .arg(name_arg())
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        let deleted = self.wrapped_exec(matches, config)?;
+        Ok(if deleted { 0 } else { 1 })
+    }
+}
@@ -0,0 +1,120 @@
+use std::io::{self, stderr, stdin, BufRead, Write};
+
+use clap::{App, ArgMatches, SubCommand};
+
+use super::super::{ExecResult, SubCmd};
+use super::{name_arg, Error, Result};
+
+use crate::config::Config;
+use crate::message::Msg;
+use crate::util::keyring;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Set::new())
+}
+
+pub struct Set();
+
+impl Set {
+    pub fn new() -> Set {
+        Set()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let name = matches.value_of("name").unwrap();
+
+        let secret = read_secret(&Msg::KeyringPrompt.fill(&[&name])).map_err(Error::IO)?;
+        if secret.is_empty() {
+            return Err(Error::Empty);
+        }
+        keyring::set(name, &secret).map_err(Error::Keyring)?;
+
+        if !config.is_quiet() {
+            println!("{}", Msg::KeyringStored.fill(&[&name]));
+        }
+        Ok(())
+    }
+}
+
+impl SubCmd for Set {
+    fn name(&self) -> &'static str {
+        "set"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutKeyringSet.text())
+            .arg(name_arg())
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
+
+// 標準入力から秘密情報を1行読み込む。
+//
+// 標準入力が端末の場合は`prompt`を表示し、入力内容を表示しない。
+fn read_secret(prompt: &str) -> io::Result<String> {
+    let tty = atty::is(atty::Stream::Stdin);
+
+    let mut line = String::new();
+    if tty {
+        let mut err = stderr();
+        write!(err, "{}", prompt)?;
+        err.flush()?;
+
+        let _no_echo = NoEcho::enable()?;
+        stdin().lock().read_line(&mut line)?;
+        writeln!(err)?;
+    } else {
+        stdin().lock().read_line(&mut line)?;
+    }
+
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
+
+// 生きている間、標準入力のエコーを止める。
+#[cfg(unix)]
+struct NoEcho {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl NoEcho {
+    fn enable() -> io::Result<NoEcho> {
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut no_echo = original;
+        no_echo.c_lflag &= !libc::ECHO;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &no_echo) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(NoEcho { original })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for NoEcho {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+// エコーを止められない環境では、そのまま入力させる。
+#[cfg(not(unix))]
+struct NoEcho;
+
+#[cfg(not(unix))]
+impl NoEcho {
+    fn enable() -> io::Result<NoEcho> {
+        Ok(NoEcho)
+    }
+}
@@ -1,13 +1,18 @@
-use std::process::exit;
+use std::error;
+use std::fmt;
 
 use clap::{App, Arg, ArgMatches, SubCommand};
-use failure::Fail;
-use log::error;
 
-use super::SubCmd;
+use super::{open_repository, report_broken_banks, ExecResult, RepoSelectError, SubCmd};
 
 use crate::config::Config;
-use crate::core::repo::{self, Repository};
+use crate::core::repo;
+use crate::core::timestamp::Timestamp;
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::util::format::Template;
+
+const FORMAT_FIELDS: &[&str] = &["bank", "time", "id", "target", "created", "count"];
 
 pub fn new() -> Box<dyn SubCmd> {
     Box::new(List::new())
@@ -20,31 +25,67 @@ impl List {
         List()
     }
 
-    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
-        let repository = Repository::open(
-            config
-                .repository_path()
-                .ok_or(Error::NoValue("repository"))?,
-        )?;
+    // 概要を読み込めなかったBankのエラーを返す。
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<Vec<repo::Error>> {
+        let repository = open_repository(&config)?;
 
         let utc = matches.is_present("utc");
+        let template = matches
+            .value_of("format")
+            .map(|f| Template::parse(f, FORMAT_FIELDS))
+            .transpose()
+            .map_err(Error::InvalidCmdArg)?;
+
+        let (infos, broken) = repository.bank_infos()?;
+        for info in infos {
+            let last = info.last_scan();
+
+            if let Some(template) = &template {
+                println!(
+                    "{}",
+                    template.render(|field| match field {
+                        "bank" => info.name().to_owned(),
+                        "time" => match last {
+                            Some(h) => format_time(h.timestamp(), utc),
+                            None => String::new(),
+                        },
+                        "id" => last.map(|h| h.id().to_string()).unwrap_or_default(),
+                        "target" => info.target_path().display().to_string(),
+                        "created" => info
+                            .created_at()
+                            .map(|t| format_time(t, utc))
+                            .unwrap_or_default(),
+                        "count" => info.history_count().to_string(),
+                        _ => unreachable!(),
+                    })
+                );
+                continue;
+            }
 
-        for bank in repository.open_all_banks()? {
-            let bank = bank?;
-
-            println!("{}", bank.name());
-            if let Some(h) = bank.last_scan()? {
+            println!("{}", info.name());
+            if let Some(h) = last {
                 if utc {
-                    println!("last backup at {:#}", h.timestamp());
+                    println!(
+                        "{}",
+                        Msg::LastBackupAt.fill(&[&format_time(h.timestamp(), utc)])
+                    );
                 } else {
                     println!("    {}", h.timestamp());
                 }
             } else {
-                println!("    No backups");
+                println!("    {}", Msg::NoBackups);
             }
         }
 
-        Ok(())
+        Ok(broken)
+    }
+}
+
+fn format_time(timestamp: Timestamp, utc: bool) -> String {
+    if utc {
+        format!("{:#}", timestamp)
+    } else {
+        timestamp.to_string()
     }
 }
 
@@ -55,39 +96,68 @@ impl SubCmd for List {
 
     fn command_args(&self) -> App {
         SubCommand::with_name(self.name())
-            .about("show buckets")
+            .about(Msg::AboutList.text())
             .arg(
                 Arg::with_name("utc")
                     .short("u")
                     .long("utc")
                     .help("show time in UTC"),
             )
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .takes_value(true)
+                    .help(
+                        "Output format like \"{bank:16} {time}\". Fields: bank, time, id, target, created, count",
+                    ),
+            )
     }
 
-    fn exec(&self, matches: &ArgMatches, config: Config) -> ! {
-        match self.wrapped_exec(matches, config) {
-            Ok(()) => exit(0),
-            Err(e) => {
-                if cfg!(debug_assertions) {
-                    error!("{:#?}", e);
-                } else {
-                    error!("{}", e);
-                }
-                exit(1)
-            }
-        }
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        let broken = self.wrapped_exec(matches, config)?;
+        Ok(report_broken_banks(&broken).unwrap_or(0))
     }
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
-    #[fail(display = "no config value: {}", _0)]
-    NoValue(&'static str),
+    InvalidCmdArg(String),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidCmdArg(msg) => write!(f, "Invalid command-line arguments: {}", msg),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
-    #[fail(display = "repository operation error: {}", _0)]
-    Repo(#[fail(cause)] repo::Error),
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::InvalidCmdArg(_) => ErrorKind::Usage,
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+        }
+    }
 }
 
 impl From<repo::Error> for Error {
@@ -95,3 +165,9 @@ impl From<repo::Error> for Error {
         Error::Repo(e)
     }
 }
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
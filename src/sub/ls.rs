@@ -0,0 +1,275 @@
+use std::error;
+use std::fmt;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::{
+    open_repository, resolve_bank, select_history, BankSelectError, ExecResult, HistorySelectError,
+    RepoSelectError, SubCmd,
+};
+
+use crate::config::Config;
+use crate::core::entry::{DirEntry, FsHash, SymlinkEntry};
+use crate::core::repo::{self, Bank};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::util::size::Size;
+use crate::util::time::Tz;
+
+#[cfg(test)]
+mod test;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Ls::new())
+}
+
+pub struct Ls();
+
+impl Ls {
+    pub fn new() -> Ls {
+        Ls()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let repo = open_repository(&config)?;
+
+        let bank_name = resolve_bank(matches, &config, &repo)?;
+        let bank = repo.open_bank(&bank_name)?;
+
+        let timezone = Tz::from_name(
+            matches
+                .value_of("timezone")
+                .or_else(|| config.history_timezone()),
+        )
+        .map_err(|s| Error::Arg(format!("invalid timezone: {}", s)))?;
+
+        let max_depth = matches
+            .value_of("depth")
+            .map(|s| {
+                s.parse::<usize>()
+                    .map_err(|_| Error::Arg(format!("--depth '{}' is not number.", s)))
+            })
+            .transpose()?;
+
+        let history = select_history(&bank, matches.value_of("revision"))?;
+        let path = matches.value_of("path").unwrap_or("");
+
+        let mut lazy = bank.lazy_dir(history.id().clone());
+        for name in path.split('/').filter(|s| !s.is_empty() && *s != ".") {
+            lazy = lazy
+                .child_dir(name)?
+                .ok_or_else(|| Error::Arg(format!("'{}' is not a directory in snapshot", path)))?;
+        }
+        let dir = lazy.entry()?;
+
+        if matches.is_present("tree") {
+            println!("{}", if path.is_empty() { "." } else { path });
+            let tree = Tree {
+                bank: &bank,
+                max_depth,
+            };
+            tree.show(dir, "", 1, &mut |line| println!("{}", line))?;
+        } else {
+            for ch in dir.children() {
+                println!("{}", list_line(&bank, timezone, ch)?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Tree<'a> {
+    bank: &'a Bank<'a>,
+    max_depth: Option<usize>,
+}
+
+impl<'a> Tree<'a> {
+    // `dir`の子を木の形で1行ずつ`out`に渡す。
+    fn show<F: FnMut(String)>(
+        &self,
+        dir: &DirEntry,
+        prefix: &str,
+        depth: usize,
+        out: &mut F,
+    ) -> Result<()> {
+        let children = dir.children().collect::<Vec<_>>();
+
+        for (i, ch) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            out(format!(
+                "{}{}{}",
+                prefix,
+                if last { "└── " } else { "├── " },
+                display_name(self.bank, ch)?
+            ));
+
+            if let FsHash::Dir(sub) = ch {
+                if self.max_depth.map(|max| depth < max).unwrap_or(true) {
+                    let sub_dir = self.bank.load_dir(&sub.id())?;
+                    let sub_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+                    self.show(&sub_dir, &sub_prefix, depth + 1, out)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// 一覧の1行として、更新日時とサイズ、表示用の名前を並べた文字列を返す。
+pub(super) fn list_line(
+    bank: &Bank,
+    timezone: Tz,
+    entry: &FsHash,
+) -> std::result::Result<String, repo::Error> {
+    let size = match entry {
+        FsHash::File(file) => Size::from(bank.object_size(&file.id())?).to_string(),
+        _ => "-".to_owned(),
+    };
+    Ok(format!(
+        "{}  {:>10}  {}",
+        timezone.at(entry.attr().modified().unix_epoch()),
+        size,
+        display_name(bank, entry)?
+    ))
+}
+
+// ディレクトリには`/`を付け、シンボリックリンクにはリンク先を付けた名前を返す。
+fn display_name(bank: &Bank, entry: &FsHash) -> std::result::Result<String, repo::Error> {
+    let name = entry.attr().name();
+    Ok(match entry {
+        FsHash::Dir(_) => format!("{}/", name),
+        FsHash::File(_) => name.to_owned(),
+        FsHash::Symlink(symlink) => {
+            let symlink_entry: SymlinkEntry = bank.load_entry(&symlink.id())?;
+            format!("{} -> {}", name, symlink_entry.target().display())
+        }
+    })
+}
+
+impl SubCmd for Ls {
+    fn name(&self) -> &'static str {
+        "ls"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutLs.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Bank name"),
+            )
+            .arg(
+                Arg::with_name("revision")
+                    .short("r")
+                    .long("revision")
+                    .takes_value(true)
+                    .help("Specify revision to show [default: last backup]"),
+            )
+            .arg(Arg::with_name("tree").long("tree").help("Show as tree"))
+            .arg(
+                Arg::with_name("depth")
+                    .short("d")
+                    .long("depth")
+                    .takes_value(true)
+                    .requires("tree")
+                    .help("Max depth of tree"),
+            )
+            .arg(
+                Arg::with_name("timezone")
+                    .short("z")
+                    .long("timezone")
+                    .takes_value(true)
+                    .help("Show time in specified timezone. [default: local]"),
+            )
+            .arg(
+                Arg::with_name("path")
+                    .index(1)
+                    .help("Directory in backup [default: root]"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Arg(String),
+
+    Bank(BankSelectError),
+
+    History(HistorySelectError),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Arg(msg) => write!(f, "{}", msg),
+            Error::Bank(e) => write!(f, "{}", e),
+            Error::History(e) => write!(f, "{}", e),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Bank(e) => Some(e),
+            Error::History(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Arg(_) => ErrorKind::Usage,
+            Error::Bank(e) => e.kind(),
+            Error::History(e) => e.kind(),
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<BankSelectError> for Error {
+    fn from(e: BankSelectError) -> Error {
+        Error::Bank(e)
+    }
+}
+
+impl From<HistorySelectError> for Error {
+    fn from(e: HistorySelectError) -> Error {
+        Error::History(e)
+    }
+}
@@ -0,0 +1,97 @@
+use super::*;
+
+use std::fs;
+use std::path::Path;
+
+use tempfile::tempdir;
+
+use crate::core::repo::Repository;
+use crate::core::scan::Scanner;
+use crate::core::timestamp::Timestamp;
+
+fn write(path: &Path, content: &str) {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).unwrap();
+    }
+    fs::write(path, content).unwrap();
+}
+
+// `max_depth`までの木を1行ずつ返す。
+fn tree_lines(bank: &Bank, dir: &DirEntry, max_depth: Option<usize>) -> Vec<String> {
+    let tree = Tree { bank, max_depth };
+    let mut lines = Vec::new();
+    tree.show(dir, "", 1, &mut |line| lines.push(line)).unwrap();
+    lines
+}
+
+#[test]
+fn test_tree() {
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    write(&target.join("a.txt"), "a");
+    write(&target.join("docs/b.txt"), "b");
+    write(&target.join("docs/deep/c.txt"), "c");
+    write(&target.join("docs/deep/d.txt"), "d");
+    fs::create_dir_all(target.join("empty")).unwrap();
+    write(&target.join("src/e.txt"), "e");
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let root = Scanner::new(&bank).scan().unwrap();
+    let history = bank
+        .save_history(root.id(), Timestamp::from(1), None)
+        .unwrap();
+    let dir = bank.load_dir(history.id()).unwrap();
+
+    // ディレクトリが先に並ぶ。最後の子は`└──`で、その下の行には縦線を引かない
+    assert_eq!(
+        tree_lines(&bank, &dir, None),
+        vec![
+            "├── docs/",
+            "│   ├── deep/",
+            "│   │   ├── c.txt",
+            "│   │   └── d.txt",
+            "│   └── b.txt",
+            "├── empty/",
+            "├── src/",
+            "│   └── e.txt",
+            "└── a.txt",
+        ]
+    );
+
+    let cases = vec![
+        (1, vec!["├── docs/", "├── empty/", "├── src/", "└── a.txt"]),
+        (
+            2,
+            vec![
+                "├── docs/",
+                "│   ├── deep/",
+                "│   └── b.txt",
+                "├── empty/",
+                "├── src/",
+                "│   └── e.txt",
+                "└── a.txt",
+            ],
+        ),
+    ];
+    for (depth, to_be) in cases {
+        assert_eq!(
+            tree_lines(&bank, &dir, Some(depth)),
+            to_be,
+            "depth = {}",
+            depth
+        );
+    }
+
+    // 部分木も同じ形で表示する
+    let docs = bank
+        .lazy_dir(history.id().clone())
+        .child_dir("docs")
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        tree_lines(&bank, docs.entry().unwrap(), None),
+        vec!["├── deep/", "│   ├── c.txt", "│   └── d.txt", "└── b.txt"]
+    );
+}
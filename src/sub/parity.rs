@@ -0,0 +1,133 @@
+use std::error;
+use std::fmt;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::warn;
+
+use super::{open_repository, progress, ExecResult, RepoSelectError, SubCmd};
+
+use crate::config::Config;
+use crate::core::repo;
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Parity::new())
+}
+
+pub struct Parity();
+
+impl Parity {
+    pub fn new() -> Parity {
+        Parity()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: &Config) -> Result<u64> {
+        let mut repo = open_repository(config)?;
+
+        if matches.is_present("disable") {
+            repo.set_parity_enabled(false)?;
+            if !config.is_quiet() {
+                println!("{}", Msg::ParityDisabled.text());
+            }
+            return Ok(0);
+        }
+
+        // 生成中に保存されたオブジェクトにもパリティが付くよう、先に有効にする
+        repo.set_parity_enabled(true)?;
+
+        let progress = progress(config, "parity");
+        let mut created = 0;
+        let mut failed = 0;
+        for id in repo.object_ids()? {
+            match repo.save_parity(&id) {
+                Ok(true) => created += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    warn!("failed to create parity of object {}: {}", id, e);
+                    failed += 1;
+                }
+            }
+            progress.add_file(repo.object_size(&id).unwrap_or(0));
+        }
+        progress.finish();
+
+        if !config.is_quiet() {
+            println!("{}", Msg::ParityDone.fill(&[&created, &failed]));
+        }
+        Ok(failed)
+    }
+}
+
+impl SubCmd for Parity {
+    fn name(&self) -> &'static str {
+        "parity"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutParity.text())
+            .arg(
+                Arg::with_name("disable")
+                    .long("disable")
+                    .help("Stop storing parity for new objects (existing parity is kept)"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        let failed = self.wrapped_exec(matches, &config)?;
+        Ok(if failed == 0 {
+            0
+        } else {
+            ErrorKind::VerifyFailed.exit_code()
+        })
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
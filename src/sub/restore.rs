@@ -1,16 +1,24 @@
-use std::collections::HashSet;
-use std::process::exit;
+use std::error;
+use std::fmt;
+use std::io;
 
 use clap::{App, Arg, ArgMatches, SubCommand};
-use failure::Fail;
-use log::error;
+use log::warn;
 
-use super::SubCmd;
+use super::{
+    open_repository, progress, resolve_bank, select_history, BankSelectError, ExecResult,
+    HistorySelectError, RepoSelectError, SubCmd,
+};
 
 use crate::config::Config;
-use crate::core::extend::{self, Extender};
-use crate::core::hash::HashID;
-use crate::core::repo::{self, Repository};
+use crate::core::extend::{self, Extender, LinkResult};
+use crate::core::repo::{self, Bank, History};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::util::picker::pick;
+use crate::util::time::Tz;
+
+const PICK_COUNT: usize = 20;
 
 pub fn new() -> Box<dyn SubCmd> {
     Box::new(Restore::new())
@@ -23,57 +31,116 @@ impl Restore {
         Restore()
     }
 
-    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
-        let repo_path = matches
-            .value_of("repo")
-            .map(|s| s.parse().unwrap())
-            .or_else(|| config.repository_path().map(|p| p.to_owned()))
-            .ok_or_else(|| Error::Arg("no repository path"))?;
-
-        let bank_name = matches.value_of("bank").unwrap();
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<usize> {
         let target_path = matches.value_of("to").unwrap();
 
-        let repo = Repository::open(&repo_path)?;
-        let bank = repo.open_bank(bank_name)?;
+        let repo = open_repository(&config)?;
+        let bank_name = resolve_bank(matches, &config, &repo)?;
+        let bank = repo.open_bank(&bank_name)?;
 
         let mut extender = Extender::new(&bank);
-        extender.allow_overwrite(matches.is_present("overwrite"));
-        extender.allow_remove(matches.is_present("remove"));
-
-        if let Some(hash_prefix) = matches.value_of("revision") {
-            let histories = bank.find_hash(hash_prefix)?;
-            if histories.is_empty() {
-                eprintln!("Error: no histories with hash {}", hash_prefix);
-                exit(1);
-            }
+        extender.allow_overwrite(switch(matches, "overwrite", config.restore_overwrite()));
+        extender.allow_remove(switch(matches, "remove", config.restore_remove()));
+        let create_symlinks = switch(matches, "create_symlinks", config.restore_create_symlinks());
+        extender.allow_symlinks(create_symlinks);
+        let case_collision = match matches.value_of("case_collision") {
+            Some(s) => s
+                .parse()
+                .map_err(|_| Error::Arg("invalid --case-collision"))?,
+            None => config.restore_case_collision(),
+        };
+        extender.set_case_collision(case_collision);
+        extender.set_progress(progress(&config, &format!("restore {}", bank.name())));
 
-            let hashes = histories
-                .iter()
-                .map(|h| h.id().clone())
-                .collect::<HashSet<HashID>>();
-            if hashes.len() > 1 {
-                eprintln!("Error: multiple hash matched:");
-                for h in hashes {
-                    eprintln!("    {}", h);
-                }
-                exit(1);
+        let history = if matches.is_present("interactive") {
+            if !atty::is(atty::Stream::Stdin) || !atty::is(atty::Stream::Stderr) {
+                return Err(Error::Arg("--interactive requires a terminal"));
             }
-
-            let hist = histories.last().unwrap();
-            extender.extend(target_path, hist)?;
-        } else if let Some(hist) = bank.last_scan()? {
-            extender.extend(target_path, &hist)?;
+            pick_history(&bank, &config)?.ok_or(Error::Canceled)?
         } else {
-            eprintln!("No scans in bank.");
-            exit(1);
+            select_history(&bank, matches.value_of("revision"))?
+        };
+        if history.is_inventory() {
+            // 棚卸し履歴のルートは元にした履歴のものであり、その後の変更は保存されていない
+            warn!(
+                "history at {} is an inventory; files are restored as of the history it was taken from",
+                history.timestamp()
+            );
         }
+        extender.extend(target_path, &history)?;
+
+        // 衝突ごとの警告は展開中に出力済みで、ここでは飛ばしたものを数える
+        let skipped_collisions = extender
+            .collisions()
+            .iter()
+            .filter(|c| c.renamed().is_none())
+            .count();
 
         let symlinks = extender.symlinks();
-        if matches.is_present("show_symlinks") {
+        if switch(matches, "show_symlinks", config.restore_show_symlinks()) {
             symlinks.show();
         }
+        if !create_symlinks {
+            return Ok(skipped_collisions);
+        }
+
+        // 作成できなかったリンクは一覧の表示を指定していなくても報告する
+        let mut created = 0;
+        let mut junctions = 0;
+        for symlink in symlinks.iter() {
+            match symlink.result() {
+                Some(LinkResult::Symlink) => created += 1,
+                Some(LinkResult::Junction) => junctions += 1,
+                Some(LinkResult::Skipped(reason)) => {
+                    warn!("symbolic link {:?} was skipped: {}", symlink.path(), reason)
+                }
+                None => {}
+            }
+        }
+        if !config.is_quiet() {
+            println!(
+                "{}",
+                Msg::RestoreSymlinks.fill(&[&created, &junctions, &symlinks.skipped()])
+            );
+        }
+
+        Ok(skipped_collisions + symlinks.skipped())
+    }
+}
+
+// 直近の履歴を新しい順に表示して選ばせる。
+fn pick_history(bank: &Bank, config: &Config) -> Result<Option<History>> {
+    let timezone = Tz::from_name(config.history_timezone()).map_err(Error::InvalidTimezone)?;
+
+    let mut histories = bank.histories()?;
+    histories.reverse();
+    histories.truncate(PICK_COUNT);
+
+    let items = histories
+        .iter()
+        .map(|h| {
+            let mut item = format!("{}    {}", timezone.at(h.timestamp().unix_epoch()), h.id());
+            if let Some(memo) = h.memo() {
+                item.push_str(&format!("    {:?}", memo));
+            }
+            item
+        })
+        .collect::<Vec<_>>();
+
+    let picked =
+        pick(&Msg::RestoreSelectHistory.fill(&[&bank.name()]), &items).map_err(Error::Terminal)?;
 
-        Ok(())
+    Ok(picked.map(|i| histories.swap_remove(i)))
+}
+
+// `name`と`no_<name>`のフラグのうち指定された方を返し、どちらも無ければ設定値`default`を返す。
+fn switch(matches: &ArgMatches, name: &str, default: bool) -> bool {
+    if matches.is_present(name) {
+        true
+    } else if matches.is_present(format!("no_{}", name)) {
+        false
+    } else {
+        default
     }
 }
 
@@ -84,19 +151,12 @@ impl SubCmd for Restore {
 
     fn command_args(&self) -> App {
         SubCommand::with_name(self.name())
-            .about("Restore files")
-            .arg(
-                Arg::with_name("repo")
-                    .long("repo")
-                    .takes_value(true)
-                    .help("Overwrite repository path"),
-            )
+            .about(Msg::AboutRestore.text())
             .arg(
                 Arg::with_name("bank")
                     .short("b")
                     .long("bank")
                     .takes_value(true)
-                    .required(true)
                     .help("Bank name"),
             )
             .arg(
@@ -114,52 +174,144 @@ impl SubCmd for Restore {
                     .takes_value(true)
                     .help("Specify revision to restore"),
             )
+            .arg(
+                Arg::with_name("interactive")
+                    .short("i")
+                    .long("interactive")
+                    .conflicts_with("revision")
+                    .help("Select revision to restore from recent histories"),
+            )
             .arg(
                 Arg::with_name("overwrite")
                     .short("O")
                     .long("overwrite")
                     .help("Overwrite existing files."),
             )
+            .arg(
+                Arg::with_name("no_overwrite")
+                    .long("no-overwrite")
+                    .conflicts_with("overwrite")
+                    .help("Don't overwrite existing files even if enabled in config."),
+            )
             .arg(
                 Arg::with_name("remove")
                     .short("R")
                     .long("remove")
                     .help("Remove existing files if not contained in backup."),
             )
+            .arg(
+                Arg::with_name("no_remove")
+                    .long("no-remove")
+                    .conflicts_with("remove")
+                    .help("Don't remove existing files even if enabled in config."),
+            )
+            .arg(
+                Arg::with_name("case_collision")
+                    .long("case-collision")
+                    .takes_value(true)
+                    .possible_values(&["rename", "skip", "error"])
+                    .help("How to handle names differing only in case on case-insensitive filesystems (default: rename)"),
+            )
+            .arg(
+                Arg::with_name("create_symlinks")
+                    .long("create-symlinks")
+                    .help("Create symbolic links (junctions for directories if not permitted)"),
+            )
+            .arg(
+                Arg::with_name("no_create_symlinks")
+                    .long("no-create-symlinks")
+                    .conflicts_with("create_symlinks")
+                    .help("Don't create symbolic links even if enabled in config"),
+            )
             .arg(
                 Arg::with_name("show_symlinks")
                     .long("show-symlinks")
                     .help("Show symbolic link list"),
             )
+            .arg(
+                Arg::with_name("no_show_symlinks")
+                    .long("no-show-symlinks")
+                    .conflicts_with("show_symlinks")
+                    .help("Don't show symbolic link list even if enabled in config"),
+            )
     }
 
-    fn exec(&self, matches: &ArgMatches, config: Config) -> ! {
-        match self.wrapped_exec(matches, config) {
-            Ok(()) => exit(0),
-            Err(e) => {
-                if cfg!(debug_assertions) {
-                    error!("{:#?}", e);
-                } else {
-                    error!("{}", e);
-                }
-                exit(1)
-            }
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        let skipped = self.wrapped_exec(matches, config)?;
+        if skipped == 0 {
+            Ok(0)
+        } else {
+            Ok(ErrorKind::Partial.exit_code())
         }
     }
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
-    #[fail(display = "{}", _0)]
+    Bank(BankSelectError),
+
+    History(HistorySelectError),
+
     Arg(&'static str),
 
-    #[fail(display = "failed extend: {}", _0)]
-    Extend(#[fail(cause)] extend::Error),
+    Canceled,
+
+    Extend(extend::Error),
+
+    InvalidTimezone(String),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
 
-    #[fail(display = "repository operation error: {}", _0)]
-    Repo(#[fail(cause)] repo::Error),
+    Terminal(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Bank(e) => write!(f, "{}", e),
+            Error::History(e) => write!(f, "{}", e),
+            Error::Arg(msg) => write!(f, "{}", msg),
+            Error::Canceled => write!(f, "restore canceled"),
+            Error::Extend(e) => write!(f, "failed extend: {}", e),
+            Error::InvalidTimezone(msg) => write!(f, "Invalid timezone: {}", msg),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+            Error::Terminal(e) => write!(f, "terminal error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Bank(e) => Some(e),
+            Error::History(e) => Some(e),
+            Error::Extend(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            Error::Terminal(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Bank(e) => e.kind(),
+            Error::History(e) => e.kind(),
+            Error::Arg(_) | Error::InvalidTimezone(_) => ErrorKind::Usage,
+            Error::Canceled => ErrorKind::Canceled,
+            Error::Extend(e) => e.kind(),
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+            Error::Terminal(e) => Categorized::kind(e),
+        }
+    }
 }
 
 impl From<extend::Error> for Error {
@@ -173,3 +325,21 @@ impl From<repo::Error> for Error {
         Error::Repo(e)
     }
 }
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<BankSelectError> for Error {
+    fn from(e: BankSelectError) -> Error {
+        Error::Bank(e)
+    }
+}
+
+impl From<HistorySelectError> for Error {
+    fn from(e: HistorySelectError) -> Error {
+        Error::History(e)
+    }
+}
@@ -0,0 +1,252 @@
+use std::error;
+use std::fmt;
+use std::path::Path;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::{error, info};
+
+use super::backup;
+use super::{report_broken_banks, repository_path, ExecResult, RepoSelectError, SubCmd};
+
+use crate::config::schedule::Schedule;
+use crate::config::Config;
+use crate::core::repo::{self, Bank, Repository, ScanStats};
+use crate::core::timestamp::{self, Timestamp};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+
+#[cfg(test)]
+mod test;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(RunDue::new())
+}
+
+pub struct RunDue();
+
+impl RunDue {
+    pub fn new() -> RunDue {
+        RunDue()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<DueBackups> {
+        let repo_path = repository_path(&config)?;
+
+        if !matches.is_present("dry_run") {
+            return backup_due(&repo_path, &config);
+        }
+
+        let repo = Repository::open(&repo_path)?;
+        let (banks, broken) = due_banks(&repo, &config)?;
+        for (bank, schedule, due) in banks {
+            println!(
+                "{}\t{}\t{}",
+                if due { "due" } else { "not-due" },
+                bank.name(),
+                schedule
+            );
+        }
+        Ok(DueBackups {
+            stats: Vec::new(),
+            broken,
+            failed: Vec::new(),
+        })
+    }
+}
+
+/// [`backup_due`](fn.backup_due.html)の結果
+pub(super) struct DueBackups {
+    /// バックアップしたBankそれぞれのスキャンの統計
+    pub stats: Vec<ScanStats>,
+    /// 開けなかったBankのエラー
+    pub broken: Vec<repo::Error>,
+    /// バックアップに失敗したBankの名前とエラー
+    pub failed: Vec<(String, backup::Error)>,
+}
+
+impl DueBackups {
+    /// バックアップしたBankで飛ばしたエラーの合計を返す。
+    pub fn errors(&self) -> u64 {
+        self.stats.iter().map(|s| s.errors).sum()
+    }
+
+    /// バックアップに失敗したBankのエラーを表示し、最初のエラーに対応する終了コードを返す。
+    ///
+    /// 失敗したBankが無ければ`None`を返す。
+    pub fn report_failed(&self) -> Option<i32> {
+        for (name, e) in &self.failed {
+            error!("backup of bank '{}' failed: {}", name, e);
+        }
+        self.failed.first().map(|(_, e)| e.kind().exit_code())
+    }
+}
+
+/// `repo_path`のリポジトリのBankのうち、予定時刻を過ぎたものをすべてバックアップする。
+///
+/// あるBankのバックアップに失敗しても、残りのBankのバックアップは続ける。
+pub(super) fn backup_due(repo_path: &Path, config: &Config) -> Result<DueBackups> {
+    let repo = Repository::open(repo_path)?;
+    repo.check_free_space()?;
+    repo.load_object_index()?;
+
+    let mut stats = Vec::new();
+    let mut failed = Vec::new();
+    let (banks, broken) = due_banks(&repo, config)?;
+    for (bank, _, due) in banks {
+        if !due {
+            continue;
+        }
+        let name = bank.name().to_owned();
+        match backup::scan(bank, config, &backup::ScanOptions::default()) {
+            Ok(s) => stats.push(s),
+            Err(e) => failed.push((name, e)),
+        }
+    }
+
+    repo.save_object_index()?;
+    Ok(DueBackups {
+        stats,
+        broken,
+        failed,
+    })
+}
+
+// 予定が設定されたBankと、その予定、現在予定時刻を過ぎているかどうかの一覧を返す。
+//
+// 開けなかったBankのエラーは別に返す。
+type DueBanks<'a> = (Vec<(Bank<'a>, Schedule, bool)>, Vec<repo::Error>);
+
+fn due_banks<'a>(repo: &'a Repository, config: &Config) -> Result<DueBanks<'a>> {
+    let now = Timestamp::now()?;
+
+    let mut banks = Vec::new();
+    let mut broken = Vec::new();
+    for bank in repo.open_all_banks()? {
+        let bank = match bank {
+            Ok(bank) => bank,
+            Err(e) => {
+                broken.push(e);
+                continue;
+            }
+        };
+
+        let schedule = match config.schedule(bank.name()).map_err(Error::Config)? {
+            Some(schedule) => schedule,
+            None => {
+                info!("bank '{}' has no schedule", bank.name());
+                continue;
+            }
+        };
+
+        let last = bank.last_scan()?.map(|h| h.timestamp());
+        let due = schedule.is_due(last, now);
+        info!(
+            "bank '{}': schedule = {}, due = {}",
+            bank.name(),
+            schedule,
+            due
+        );
+
+        banks.push((bank, schedule, due));
+    }
+    Ok((banks, broken))
+}
+
+impl SubCmd for RunDue {
+    fn name(&self) -> &'static str {
+        "run-due"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutRunDue.text())
+            .arg(
+                Arg::with_name("dry_run")
+                    .short("n")
+                    .long("dry-run")
+                    .help("Only show which banks are due"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        let result = self.wrapped_exec(matches, config)?;
+        let code = backup::partial_exit_code(result.errors());
+        let broken = report_broken_banks(&result.broken);
+        let failed = result.report_failed();
+        Ok(broken.or(failed).unwrap_or(code))
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Backup(backup::Error),
+
+    Config(anyhow::Error),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+
+    Timestamp,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Backup(e) => write!(f, "backup failed: {}", e),
+            Error::Config(e) => write!(f, "invalid config: {}", e),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+            Error::Timestamp => write!(f, "timestamp is older than UNIX epoch"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Backup(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Backup(e) => e.kind(),
+            Error::Config(_) => ErrorKind::Config,
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+            Error::Timestamp => ErrorKind::Data,
+        }
+    }
+}
+
+impl From<backup::Error> for Error {
+    fn from(e: backup::Error) -> Error {
+        Error::Backup(e)
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<timestamp::Error> for Error {
+    fn from(_e: timestamp::Error) -> Error {
+        Error::Timestamp
+    }
+}
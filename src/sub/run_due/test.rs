@@ -0,0 +1,50 @@
+use super::*;
+
+use std::fs;
+
+use tempfile::tempdir;
+
+#[test]
+fn test_backup_due_continues_after_failure() {
+    let temp = tempdir().unwrap();
+    let repo_path = temp.path().join("repo");
+    let repo = Repository::create(&repo_path).unwrap();
+    for name in &["a", "b", "c"] {
+        let target = temp.path().join(name);
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("file.txt"), name).unwrap();
+        repo.create_bank(name, &target).unwrap();
+    }
+    // 対象ディレクトリが無いBankはバックアップに失敗する
+    fs::remove_dir_all(temp.path().join("b")).unwrap();
+
+    let mut config: Config = toml::from_str(
+        "[banks.a]\nschedule = 'every 1h'\n[banks.b]\nschedule = 'every 1h'\n[banks.c]\nschedule = 'every 1h'\n",
+    )
+    .unwrap();
+    config.set_repository_path(&repo_path);
+    config.set_verbosity(true, 0);
+
+    let result = backup_due(&repo_path, &config).unwrap();
+    assert_eq!(result.stats.len(), 2);
+    assert!(result.broken.is_empty());
+    assert_eq!(
+        result
+            .failed
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["b"]
+    );
+    for name in &["a", "c"] {
+        let bank = repo.open_bank(name).unwrap();
+        assert!(bank.last_scan().unwrap().is_some(), "bank = {}", name);
+    }
+
+    // 予定時刻を過ぎたまま失敗したBankがあれば、その原因に応じた終了コードで終了する
+    let run_due = RunDue::new();
+    let matches = run_due.command_args().get_matches_from(vec!["run-due"]);
+    let code = run_due.exec(&matches, config).unwrap();
+    assert_ne!(code, 0);
+    assert_eq!(code, result.failed[0].1.kind().exit_code());
+}
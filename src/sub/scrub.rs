@@ -0,0 +1,190 @@
+use std::error;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::verify::{failure_causes, print_results};
+use super::{open_repository, progress, ExecResult, RepoSelectError, SubCmd};
+
+use crate::config::Config;
+use crate::core::repo;
+use crate::core::timestamp::{self, Timestamp};
+use crate::core::verify::{Verifier, VerifyReport};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::notify::{notify, Report};
+use crate::util::size::Size;
+use crate::util::time::parse_duration;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Scrub::new())
+}
+
+pub struct Scrub();
+
+impl Scrub {
+    pub fn new() -> Scrub {
+        Scrub()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: &Config) -> Result<VerifyReport> {
+        let threads = match matches.value_of("threads") {
+            Some(s) => s
+                .parse::<usize>()
+                .map_err(|e| Error::Arg(format!("invalid thread count: {}", e)))?,
+            None => thread::available_parallelism().map_or(1, |n| n.get()),
+        };
+        let budget = matches
+            .value_of("budget")
+            .map(|s| {
+                parse_duration(s)
+                    .map(Duration::from_secs)
+                    .ok_or_else(|| Error::Arg(format!("--budget '{}' is not duration.", s)))
+            })
+            .transpose()?;
+        let repo = open_repository(config)?;
+
+        let mut verifier = Verifier::new(&repo)
+            .threads(threads)
+            .repair(matches.is_present("repair"))
+            .progress(progress(config, "scrub"));
+        if let Some(budget) = budget {
+            verifier = verifier.budget(budget);
+        }
+        let report = verifier.scrub(Timestamp::now()?)?;
+
+        print_results(&report);
+        if !config.is_quiet() {
+            println!(
+                "{}",
+                Msg::ScrubDone.fill(&[
+                    &report.objects(),
+                    &Size::from(report.bytes()),
+                    &report.failures().len(),
+                    &report.unchecked(),
+                ])
+            );
+        }
+
+        Ok(report)
+    }
+}
+
+impl SubCmd for Scrub {
+    fn name(&self) -> &'static str {
+        "scrub"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutScrub.text())
+            .arg(
+                Arg::with_name("budget")
+                    .long("budget")
+                    .takes_value(true)
+                    .help(
+                    "Stop verifying after this time like 30m or 2h (default: verify all objects)",
+                ),
+            )
+            .arg(
+                Arg::with_name("threads")
+                    .short("j")
+                    .long("threads")
+                    .takes_value(true)
+                    .help("Number of threads to hash objects (default: number of CPUs)"),
+            )
+            .arg(
+                Arg::with_name("repair")
+                    .long("repair")
+                    .help("Repair corrupted objects from parity data"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        let result = self.wrapped_exec(matches, &config);
+
+        let notification = match result {
+            Ok(ref report) if report.is_ok() => Report::success("scrub", None, None),
+            Ok(ref report) => Report::failure_message(
+                "scrub",
+                None,
+                format!("{} objects failed verification", report.failures().len()),
+                failure_causes(report),
+            ),
+            Err(ref e) => Report::failure("scrub", None, e),
+        };
+        notify(&config, &notification);
+
+        let report = result?;
+        Ok(if report.is_ok() {
+            0
+        } else {
+            ErrorKind::VerifyFailed.exit_code()
+        })
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Arg(String),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+
+    Timestamp,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Arg(msg) => write!(f, "{}", msg),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+            Error::Timestamp => write!(f, "timestamp is older than UNIX epoch"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Arg(_) => ErrorKind::Usage,
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+            Error::Timestamp => ErrorKind::Data,
+        }
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<timestamp::Error> for Error {
+    fn from(_e: timestamp::Error) -> Error {
+        Error::Timestamp
+    }
+}
@@ -0,0 +1,186 @@
+use std::error;
+use std::fmt;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::warn;
+
+use super::{open_repository, resolve_bank, BankSelectError, ExecResult, RepoSelectError, SubCmd};
+
+use crate::config::Config;
+use crate::core::entry::FsHash;
+use crate::core::repo;
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::util::time::Tz;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Search::new())
+}
+
+pub struct Search();
+
+impl Search {
+    pub fn new() -> Search {
+        Search()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let repo = open_repository(&config)?;
+
+        let bank_name = resolve_bank(matches, &config, &repo)?;
+        let bank = repo.open_bank(&bank_name)?;
+
+        let timezone = Tz::from_name(
+            matches
+                .value_of("timezone")
+                .or_else(|| config.history_timezone()),
+        )
+        .map_err(|s| Error::Arg(format!("invalid timezone: {}", s)))?;
+
+        let words = matches.values_of("words").unwrap().collect::<Vec<_>>();
+        let histories = bank.histories()?;
+
+        let mut index = bank.load_content_index()?;
+        if matches.is_present("update") {
+            let _lock = bank.lock()?;
+            let mut added = 0;
+            for history in &histories {
+                added += bank.update_content_index(history, &mut index)?;
+            }
+            bank.save_content_index(&index)?;
+            if !config.is_quiet() {
+                println!("{}", Msg::ContentIndexUpdated.fill(&[&added]));
+            }
+        }
+        if index.is_empty() {
+            warn!(
+                "content index of bank '{}' is empty; enable content_index and backup, or use --update",
+                bank_name
+            );
+        }
+
+        let found = index.search(&words);
+        if found.is_empty() {
+            return Ok(());
+        }
+        for history in &histories {
+            let time = timezone.at(history.timestamp().unix_epoch());
+            for entry in bank.walk(history) {
+                let (path, entry) = entry?;
+                if let FsHash::File(file) = entry {
+                    if found.contains(&file.id()) {
+                        println!("{}  {}  {}", time, history.id(), path);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl SubCmd for Search {
+    fn name(&self) -> &'static str {
+        "search"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutSearch.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Bank name"),
+            )
+            .arg(
+                Arg::with_name("update")
+                    .long("update")
+                    .help("Add files of all histories to content index before search"),
+            )
+            .arg(
+                Arg::with_name("timezone")
+                    .short("z")
+                    .long("timezone")
+                    .takes_value(true)
+                    .help("Show time in specified timezone. [default: local]"),
+            )
+            .arg(
+                Arg::with_name("words")
+                    .index(1)
+                    .multiple(true)
+                    .required(true)
+                    .help("Words that files must contain"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Arg(String),
+
+    Bank(BankSelectError),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Arg(msg) => write!(f, "{}", msg),
+            Error::Bank(e) => write!(f, "{}", e),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Bank(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Arg(_) => ErrorKind::Usage,
+            Error::Bank(e) => e.kind(),
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<BankSelectError> for Error {
+    fn from(e: BankSelectError) -> Error {
+        Error::Bank(e)
+    }
+}
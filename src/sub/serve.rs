@@ -0,0 +1,502 @@
+use std::error;
+use std::fmt;
+use std::io::{self, BufReader};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::{info, warn};
+use serde_json::json;
+
+use super::backup::{self, ScanOptions};
+use super::{
+    repository_path, select_history, ExecResult, HistorySelectError, RepoSelectError, SubCmd,
+};
+
+use crate::config::{Config, KEYRING_SERVE_TOKEN};
+use crate::core::entry::{FsHash, SymlinkEntry};
+use crate::core::repo::{self, Bank, History, Repository};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::util::http::{encode, Request, Response};
+
+#[cfg(test)]
+mod test;
+
+const DEFAULT_LISTEN: &str = "127.0.0.1:8340";
+
+// APIを使って履歴を閲覧するWeb UI
+const INDEX_HTML: &str = include_str!("serve/index.html");
+
+// 認証用のトークンを指定するクエリ文字列のパラメータと、それを保存するクッキーの名前
+const TOKEN_PARAM: &str = "token";
+const TOKEN_COOKIE: &str = "sbak_token";
+
+// リクエストを送らない接続で他のリクエストが待たされ続けないよう、読み込みを打ち切る時間
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+// レスポンスを受け取らないクライアントのためにスレッドが残り続けないよう、書き込みを打ち切る時間
+const WRITE_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Serve::new())
+}
+
+pub struct Serve();
+
+impl Serve {
+    pub fn new() -> Serve {
+        Serve()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let repo_path = repository_path(&config)?;
+        // 起動時にリポジトリを確認しておき、誤ったパスのまま待ち受けないようにする
+        Repository::open(&repo_path)?;
+
+        let listen = matches.value_of("listen").unwrap_or(DEFAULT_LISTEN);
+        let listener = TcpListener::bind(listen).map_err(|e| Error::Bind(listen.to_owned(), e))?;
+        let local_addr = listener.local_addr()?;
+        // ループバック以外のアドレスでは他のマシンから接続できるため、トークンによる認証を必須とする
+        let token = if local_addr.ip().is_loopback() {
+            None
+        } else {
+            Some(
+                config
+                    .serve_token()
+                    .ok_or_else(|| Error::TokenRequired(local_addr))?,
+            )
+        };
+        if !config.is_quiet() {
+            println!("{}", Msg::ServeListening.fill(&[&local_addr]));
+        }
+
+        let server = Arc::new(Server {
+            repo_path,
+            config,
+            allow_backup: matches.is_present("allow_backup"),
+            local_addr,
+            token,
+        });
+        // 時間のかかるバックアップなどで他のリクエストが待たされないよう、接続ごとにスレッドで処理する
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let server = Arc::clone(&server);
+                    thread::spawn(move || server.handle(stream));
+                }
+                Err(e) => warn!("failed to accept connection: {}", e),
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Server {
+    repo_path: PathBuf,
+    config: Config,
+    allow_backup: bool,
+    local_addr: SocketAddr,
+    token: Option<String>,
+}
+
+impl Server {
+    fn handle(&self, mut stream: TcpStream) {
+        if let Err(e) = stream.set_read_timeout(Some(READ_TIMEOUT)) {
+            warn!("failed to set read timeout: {}", e);
+        }
+        if let Err(e) = stream.set_write_timeout(Some(WRITE_TIMEOUT)) {
+            warn!("failed to set write timeout: {}", e);
+        }
+
+        let response = match Request::read_from(BufReader::new(&stream)) {
+            Ok(req) if !req.host().is_some_and(|h| allowed_host(h, self.local_addr)) => {
+                warn!(
+                    "{} {}: host {:?} is not allowed",
+                    req.method(),
+                    req.path(),
+                    req.host()
+                );
+                Response::error(403, "host is not allowed")
+            }
+            Ok(req) if !self.authorized(&req) => {
+                warn!("{} {}: unauthorized", req.method(), req.path());
+                Response::error(401, "authentication required")
+            }
+            Ok(req) => {
+                let mut response = self.respond(&req).unwrap_or_else(|e| {
+                    warn!("{} {}: {}", req.method(), req.path(), e);
+                    Response::error(status_of(&e), &e.to_string())
+                });
+                // ブラウザがクエリ文字列で認証した場合は、以降のリクエストのためにクッキーに保存させる
+                if let (Some(token), Some(_)) = (&self.token, req.query(TOKEN_PARAM)) {
+                    let cookie = format!(
+                        "{}={}; Path=/; HttpOnly; SameSite=Strict",
+                        TOKEN_COOKIE,
+                        encode(token)
+                    );
+                    response = response.with_header("Set-Cookie", cookie);
+                }
+                info!("{} {} {}", req.method(), req.path(), response.status());
+                response
+            }
+            Err(e) => {
+                warn!("invalid request: {}", e);
+                Response::error(400, &e.to_string())
+            }
+        };
+
+        if let Err(e) = response.write_to(&mut stream) {
+            warn!("failed to send response: {}", e);
+        }
+    }
+
+    // トークンが必要な場合に、`Authorization`ヘッダー、クッキー、クエリ文字列のいずれかで正しいトークンが渡されたかを返す。
+    fn authorized(&self, req: &Request) -> bool {
+        let token = match self.token {
+            Some(ref token) => token,
+            None => return true,
+        };
+        let bearer = req
+            .header("Authorization")
+            .and_then(|h| h.strip_prefix("Bearer "));
+        let cookie = req.cookie(TOKEN_COOKIE);
+        let query = req.query(TOKEN_PARAM);
+
+        bearer.is_some_and(|t| same_token(t, token))
+            || cookie.is_some_and(|t| same_token(t, &encode(token)))
+            || query.is_some_and(|t| same_token(t, token))
+    }
+
+    fn respond(&self, req: &Request) -> Result<Response> {
+        let repo = Repository::open(&self.repo_path)?;
+        match (req.method(), req.segments().as_slice()) {
+            ("GET", []) => Ok(Response::bytes(
+                200,
+                "text/html; charset=utf-8",
+                INDEX_HTML.as_bytes().to_vec(),
+            )),
+            ("GET", ["banks"]) => {
+                let (infos, broken) = repo.bank_infos()?;
+                for e in broken {
+                    warn!("{}", e);
+                }
+                let mut banks = Vec::new();
+                for info in infos {
+                    banks.push(json!({
+                        "name": info.name(),
+                        "target_path": info.target_path(),
+                        "created_at": info.created_at(),
+                        "history_count": info.history_count(),
+                        "last_scan": info.last_scan(),
+                    }));
+                }
+                Ok(Response::json(200, &banks))
+            }
+            ("GET", ["banks", bank, "histories"]) => {
+                let bank = open_bank(&repo, bank)?;
+                Ok(Response::json(200, &bank.histories()?))
+            }
+            ("GET", ["banks", bank, "histories", revision, "tree"]) => {
+                let bank = open_bank(&repo, bank)?;
+                tree(&bank, revision, req.query("path").unwrap_or(""))
+            }
+            ("GET", ["banks", bank, "histories", revision, "file"]) => {
+                let bank = open_bank(&repo, bank)?;
+                let path = req
+                    .query("path")
+                    .ok_or_else(|| Error::BadRequest("'path' parameter is required".to_owned()))?;
+                file(&bank, revision, path)
+            }
+            ("POST", ["banks", bank, "backup"]) => {
+                if !self.allow_backup {
+                    return Err(Error::Forbidden(
+                        "backup is disabled; start server with --allow-backup".to_owned(),
+                    ));
+                }
+                let bank = open_bank(&repo, bank)?;
+                repo.check_free_space()?;
+                repo.load_object_index()?;
+                let stats = backup::scan(bank, &self.config, &ScanOptions::default())?;
+                repo.save_object_index()?;
+                Ok(Response::json(200, &stats))
+            }
+            (_, [])
+            | (_, ["banks"])
+            | (_, ["banks", _, "histories"])
+            | (_, ["banks", _, "histories", _, "tree"])
+            | (_, ["banks", _, "histories", _, "file"])
+            | (_, ["banks", _, "backup"]) => Err(Error::MethodNotAllowed(req.method().to_owned())),
+            _ => Err(Error::NotFound(format!("no such endpoint: {}", req.path()))),
+        }
+    }
+}
+
+fn open_bank<'a>(repo: &'a Repository, name: &str) -> Result<Bank<'a>> {
+    if !repo.bank_exists(name)? {
+        return Err(Error::NotFound(format!("no such bank: {}", name)));
+    }
+    Ok(repo.open_bank(name)?)
+}
+
+// `latest`は最新の履歴、それ以外はハッシュ値のプレフィックスとして履歴を選ぶ。
+fn select_revision(bank: &Bank, revision: &str) -> Result<History> {
+    Ok(select_history(
+        bank,
+        Some(revision).filter(|&r| r != "latest"),
+    )?)
+}
+
+// `path`のディレクトリの子の一覧を返す。
+fn tree(bank: &Bank, revision: &str, path: &str) -> Result<Response> {
+    let history = select_revision(bank, revision)?;
+    let mut lazy = bank.lazy_dir(history.id().clone());
+    for name in components(path) {
+        lazy = lazy
+            .child_dir(name)?
+            .ok_or_else(|| Error::NotFound(format!("'{}' is not a directory in snapshot", path)))?;
+    }
+
+    let mut children = Vec::new();
+    for ch in lazy.entry()?.children() {
+        let attr = ch.attr();
+        children.push(match ch {
+            FsHash::Dir(_) => json!({
+                "name": attr.name(),
+                "type": "dir",
+                "modified": attr.modified(),
+            }),
+            FsHash::File(f) => json!({
+                "name": attr.name(),
+                "type": "file",
+                "modified": attr.modified(),
+                "size": bank.object_size(&f.id())?,
+            }),
+            FsHash::Symlink(s) => {
+                let entry: SymlinkEntry = bank.load_entry(&s.id())?;
+                json!({
+                    "name": attr.name(),
+                    "type": "symlink",
+                    "modified": attr.modified(),
+                    "target": entry.target(),
+                })
+            }
+        });
+    }
+    Ok(Response::json(200, &children))
+}
+
+// `path`のファイルの内容を返す。
+fn file(bank: &Bank, revision: &str, path: &str) -> Result<Response> {
+    let history = select_revision(bank, revision)?;
+    let not_found = || Error::NotFound(format!("'{}' is not a file in snapshot", path));
+
+    let names = components(path).collect::<Vec<_>>();
+    let (file_name, dirs) = names.split_last().ok_or_else(not_found)?;
+    let mut lazy = bank.lazy_dir(history.id().clone());
+    for name in dirs {
+        lazy = lazy.child_dir(name)?.ok_or_else(not_found)?;
+    }
+    let id = lazy
+        .entry()?
+        .find_file(file_name)
+        .ok_or_else(not_found)?
+        .id();
+
+    let len = bank.object_size(&id)?;
+    let reader = bank.stream_object(&id)?;
+    Ok(Response::stream(200, "application/octet-stream", reader, len).attachment(file_name))
+}
+
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    Path::new(path)
+        .iter()
+        .filter_map(|c| c.to_str())
+        .filter(|c| *c != "/" && *c != ".")
+}
+
+// DNSリバインディングを防ぐため、`Host`ヘッダーが待ち受けているアドレスか`localhost`の場合のみ許可する。
+//
+// 全てのアドレスで待ち受けている場合は、IPアドレスであれば許可する。
+fn allowed_host(host: &str, local: SocketAddr) -> bool {
+    let (name, port) = match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => (&host[..i], Some(&host[i + 1..])),
+        _ => (host, None),
+    };
+    if port.is_some_and(|p| p.parse() != Ok(local.port())) {
+        return false;
+    }
+    if name.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    match name
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        Ok(ip) => local.ip().is_unspecified() || ip == local.ip(),
+        Err(_) => false,
+    }
+}
+
+// 応答時間から一致した長さを推測されないよう、常に全体を比較する。
+fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+// エラーに対応するHTTPのステータスコードを返す。
+fn status_of(e: &Error) -> u16 {
+    match e {
+        Error::Forbidden(_) => 403,
+        Error::MethodNotAllowed(_) => 405,
+        _ => match e.kind() {
+            ErrorKind::Usage => 400,
+            ErrorKind::NotFound => 404,
+            ErrorKind::Locked => 409,
+            _ => 500,
+        },
+    }
+}
+
+impl SubCmd for Serve {
+    fn name(&self) -> &'static str {
+        "serve"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutServe.text())
+            .arg(
+                Arg::with_name("listen")
+                    .short("l")
+                    .long("listen")
+                    .takes_value(true)
+                    .help("Address and port to listen [default: 127.0.0.1:8340]"),
+            )
+            .arg(
+                Arg::with_name("allow_backup")
+                    .long("allow-backup")
+                    .help("Allow clients to start backups"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Backup(backup::Error),
+
+    BadRequest(String),
+
+    Bind(String, io::Error),
+
+    Forbidden(String),
+
+    History(HistorySelectError),
+
+    IO(io::Error),
+
+    MethodNotAllowed(String),
+
+    NotFound(String),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+
+    TokenRequired(SocketAddr),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Backup(e) => write!(f, "{}", e),
+            Error::BadRequest(msg) => write!(f, "bad request: {}", msg),
+            Error::Bind(addr, e) => write!(f, "failed to listen on {}: {}", addr, e),
+            Error::Forbidden(msg) => write!(f, "{}", msg),
+            Error::History(e) => write!(f, "{}", e),
+            Error::IO(e) => write!(f, "IO error: {}", e),
+            Error::MethodNotAllowed(method) => write!(f, "method {} is not allowed", method),
+            Error::NotFound(msg) => write!(f, "{}", msg),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+            Error::TokenRequired(addr) => write!(
+                f,
+                "listening on {} requires token; set `token` in [serve] section of config or store '{}' in keyring",
+                addr, KEYRING_SERVE_TOKEN
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Backup(e) => Some(e),
+            Error::Bind(_, e) => Some(e),
+            Error::History(e) => Some(e),
+            Error::IO(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Backup(e) => e.kind(),
+            Error::BadRequest(_) | Error::MethodNotAllowed(_) => ErrorKind::Usage,
+            Error::Bind(_, _) | Error::IO(_) => ErrorKind::IO,
+            Error::Forbidden(_) => ErrorKind::Other,
+            Error::History(e) => e.kind(),
+            Error::NotFound(_) => ErrorKind::NotFound,
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+            Error::TokenRequired(_) => ErrorKind::Config,
+        }
+    }
+}
+
+impl From<backup::Error> for Error {
+    fn from(e: backup::Error) -> Error {
+        Error::Backup(e)
+    }
+}
+
+impl From<HistorySelectError> for Error {
+    fn from(e: HistorySelectError) -> Error {
+        Error::History(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IO(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
@@ -0,0 +1,128 @@
+use super::*;
+
+use std::fs;
+
+use tempfile::tempdir;
+
+use crate::core::scan::Scanner;
+use crate::core::timestamp::Timestamp;
+
+// 1つのファイルを含むバンクを作成してバックアップし、そのファイルのオブジェクトのパスを返す。
+fn backup_file(repo_path: &Path, target: &Path, content: &str) -> PathBuf {
+    fs::create_dir_all(target).unwrap();
+    fs::write(target.join("data.txt"), content).unwrap();
+
+    let repo = Repository::create(repo_path).unwrap();
+    repo.create_bank("bank", target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let root = Scanner::new(&bank).scan().unwrap();
+    let history = bank
+        .save_history(root.id(), Timestamp::from(1), None)
+        .unwrap();
+
+    let id = bank
+        .load_root(&history)
+        .unwrap()
+        .children()
+        .next()
+        .unwrap()
+        .id();
+    repo_path.join("objects").join(id.shard_path())
+}
+
+#[test]
+fn test_file() {
+    let temp = tempdir().unwrap();
+    let repo_path = temp.path().join("repo");
+    backup_file(&repo_path, &temp.path().join("target"), "backed up");
+
+    let repo = Repository::open(&repo_path).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let mut out = Vec::new();
+    file(&bank, "latest", "data.txt")
+        .unwrap()
+        .write_to(&mut out)
+        .unwrap();
+    assert!(String::from_utf8(out)
+        .unwrap()
+        .ends_with("\r\n\r\nbacked up"));
+}
+
+#[test]
+fn test_file_broken_object() {
+    let temp = tempdir().unwrap();
+    let repo_path = temp.path().join("repo");
+    let obj_path = backup_file(&repo_path, &temp.path().join("target"), "backed up");
+
+    // 大きさが同じで内容の異なるオブジェクトに置き換える
+    fs::remove_file(&obj_path).unwrap();
+    fs::write(&obj_path, "corrupted").unwrap();
+
+    let repo = Repository::open(&repo_path).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let mut out = Vec::new();
+    let res = file(&bank, "latest", "data.txt").unwrap();
+    assert!(res.write_to(&mut out).is_err());
+    // Content-Lengthに満たないため、クライアントは不完全なレスポンスとして扱う
+    assert!(String::from_utf8(out)
+        .unwrap()
+        .ends_with("\r\n\r\ncorrupte"));
+}
+
+#[test]
+fn test_allowed_host() {
+    let local: SocketAddr = "127.0.0.1:8340".parse().unwrap();
+    assert!(allowed_host("127.0.0.1:8340", local));
+    assert!(allowed_host("127.0.0.1", local));
+    assert!(allowed_host("localhost:8340", local));
+    assert!(allowed_host("LocalHost", local));
+    assert!(!allowed_host("localhost:80", local));
+    assert!(!allowed_host("127.0.0.2:8340", local));
+    assert!(!allowed_host("attacker.example:8340", local));
+    assert!(!allowed_host("", local));
+
+    let local: SocketAddr = "[::1]:8340".parse().unwrap();
+    assert!(allowed_host("[::1]:8340", local));
+    assert!(allowed_host("[::1]", local));
+    assert!(!allowed_host("[::2]:8340", local));
+
+    // 全てのアドレスで待ち受けている場合
+    let local: SocketAddr = "0.0.0.0:8340".parse().unwrap();
+    assert!(allowed_host("192.168.0.10:8340", local));
+    assert!(!allowed_host("nas.example:8340", local));
+}
+
+#[test]
+fn test_authorized() {
+    let server = |token: Option<&str>| Server {
+        repo_path: PathBuf::new(),
+        config: Config::default(),
+        allow_backup: false,
+        local_addr: "0.0.0.0:8340".parse().unwrap(),
+        token: token.map(|t| t.to_owned()),
+    };
+    let req = |headers: &str, query: &str| {
+        let raw = format!("GET /banks{} HTTP/1.1\r\n{}\r\n", query, headers);
+        Request::read_from(raw.as_bytes()).unwrap()
+    };
+
+    // トークンが無ければ認証しない
+    assert!(server(None).authorized(&req("", "")));
+
+    let server = server(Some("s3cret;x"));
+    assert!(!server.authorized(&req("", "")));
+    assert!(server.authorized(&req("Authorization: Bearer s3cret;x\r\n", "")));
+    assert!(!server.authorized(&req("Authorization: Bearer s3cret\r\n", "")));
+    assert!(server.authorized(&req("Cookie: a=1; sbak_token=s3cret%3Bx\r\n", "")));
+    assert!(!server.authorized(&req("Cookie: sbak_token=wrong\r\n", "")));
+    assert!(server.authorized(&req("", "?token=s3cret%3Bx")));
+    assert!(!server.authorized(&req("", "?token=s3cret")));
+}
+
+#[test]
+fn test_same_token() {
+    assert!(same_token("abc", "abc"));
+    assert!(!same_token("abc", "abd"));
+    assert!(!same_token("abc", "abcd"));
+    assert!(same_token("", ""));
+}
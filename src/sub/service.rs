@@ -0,0 +1,135 @@
+mod install;
+mod run;
+mod uninstall;
+
+use std::error;
+use std::fmt;
+use std::io::{self, stderr};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::daemon;
+use super::{ExecResult, RepoSelectError, SubCmd, SubCommandSet};
+
+use crate::config::Config;
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+
+// サービス名の既定値
+const DEFAULT_NAME: &str = "sbak";
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Service::new())
+}
+
+pub struct Service(SubCommandSet);
+
+impl Service {
+    pub fn new() -> Service {
+        let mut subs: SubCommandSet = SubCommandSet::new();
+
+        subs.append(install::new());
+        subs.append(run::new());
+        subs.append(uninstall::new());
+
+        Service(subs)
+    }
+}
+
+impl SubCmd for Service {
+    fn name(&self) -> &'static str {
+        "service"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutService.text())
+            .subcommands(self.0.arg_defs())
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        if let (subcmd_name, Some(matches)) = matches.subcommand() {
+            if let Some(result) = self.0.execute(subcmd_name, matches, config) {
+                return result;
+            }
+        }
+
+        let mut out = stderr();
+        self.command_args().write_long_help(&mut out).unwrap();
+        eprintln!();
+        Ok(1)
+    }
+}
+
+// サービス名を指定する引数
+fn name_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("name")
+        .long("name")
+        .takes_value(true)
+        .help("Service name (default: sbak)")
+}
+
+// コマンドライン引数で指定されたサービス名を返す。
+fn service_name<'a>(matches: &'a ArgMatches) -> &'a str {
+    matches.value_of("name").unwrap_or(DEFAULT_NAME)
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    RepoSelect(RepoSelectError),
+
+    Daemon(daemon::Error),
+
+    Service(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Daemon(e) => write!(f, "{}", e),
+            Error::Service(e) => write!(f, "service operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::RepoSelect(e) => Some(e),
+            Error::Daemon(e) => Some(e),
+            Error::Service(e) => Some(e),
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::RepoSelect(e) => e.kind(),
+            Error::Daemon(e) => e.kind(),
+            Error::Service(e) if e.kind() == io::ErrorKind::Unsupported => ErrorKind::Usage,
+            Error::Service(_) => ErrorKind::IO,
+        }
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<daemon::Error> for Error {
+    fn from(e: daemon::Error) -> Error {
+        Error::Daemon(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Service(e)
+    }
+}
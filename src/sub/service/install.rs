@@ -0,0 +1,130 @@
+use std::env;
+use std::path::PathBuf;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::super::{repository_path, ExecResult, SubCmd};
+use super::{daemon, name_arg, service_name, Result};
+
+use crate::config::Config;
+use crate::message::Msg;
+use crate::util::service;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Install::new())
+}
+
+pub struct Install();
+
+impl Install {
+    pub fn new() -> Install {
+        Install()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let repo_path = repository_path(&config)?;
+        // サービスは別のユーザー・作業ディレクトリで実行されるため、絶対パスにしておく。
+        let repo_path = repo_path.canonicalize()?;
+        let interval = daemon::interval_arg(matches)?;
+        let name = service_name(matches);
+
+        let mut args = vec![env::current_exe()?.to_string_lossy().into_owned()];
+        if let Some(config_file) = matches.value_of("config") {
+            args.push("--config".to_owned());
+            args.push(
+                PathBuf::from(config_file)
+                    .canonicalize()?
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+        }
+        args.extend(vec![
+            "service".to_owned(),
+            "run".to_owned(),
+            "--name".to_owned(),
+            name.to_owned(),
+            "--repo".to_owned(),
+            repo_path.to_string_lossy().into_owned(),
+            "--interval".to_owned(),
+            interval.as_secs().to_string(),
+        ]);
+        let command_line = args
+            .iter()
+            .map(|a| quote_arg(a))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        service::install(
+            name,
+            &format!("sbak ({})", name),
+            "Backup banks whose schedule is due",
+            &command_line,
+        )?;
+
+        if !config.is_quiet() {
+            println!("{}", Msg::ServiceInstalled.fill(&[&name]));
+        }
+        Ok(())
+    }
+}
+
+impl SubCmd for Install {
+    fn name(&self) -> &'static str {
+        "install"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutServiceInstall.text())
+            .arg(name_arg())
+            .arg(
+                Arg::with_name("interval")
+                    .long("interval")
+                    .takes_value(true)
+                    .value_name("SECS")
+                    .help("Seconds between schedule checks (default: 60)"),
+            )
+            .arg(
+                Arg::with_name("config")
+                    .long("config")
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .help("Extra config file loaded by the service"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
+
+// Windowsのコマンドラインの引数として使えるように文字列を引用する。
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || c == '"') {
+        return arg.to_owned();
+    }
+
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // 引用符の直前のバックスラッシュは2倍にしてエスケープする。
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                backslashes = 0;
+            }
+        }
+        if c != '\\' {
+            quoted.push(c);
+        }
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
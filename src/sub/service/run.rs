@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::{error, warn};
+
+use super::super::{repository_path, ExecResult, SubCmd};
+use super::{daemon, name_arg, service_name, Result};
+
+use crate::config::Config;
+use crate::error::Categorized;
+use crate::message::Msg;
+use crate::smalllog;
+use crate::util::service::{self, Request};
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Run::new())
+}
+
+pub struct Run();
+
+impl Run {
+    pub fn new() -> Run {
+        Run()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let repo_path = repository_path(&config)?;
+        let interval = daemon::interval_arg(matches)?;
+
+        // サービスには標準エラー出力がないため、イベントログに記録する。
+        if let Err(e) = smalllog::use_syslog() {
+            warn!("can't use event log: {}", e);
+        }
+
+        let control = Arc::new(daemon::Control::new());
+        let handler_control = Arc::clone(&control);
+        let handler = Box::new(move |request| {
+            handler_control.set(match request {
+                Request::Stop => daemon::State::Stopped,
+                Request::Pause => daemon::State::Paused,
+                Request::Continue => daemon::State::Running,
+            })
+        });
+        let body = Box::new(
+            move || match daemon::run(&repo_path, &config, interval, &control) {
+                Ok(()) => 0,
+                Err(e) => {
+                    error!("service failed: {}", e);
+                    e.kind().exit_code() as u32
+                }
+            },
+        );
+
+        service::run(service_name(matches), handler, body)?;
+        Ok(())
+    }
+}
+
+impl SubCmd for Run {
+    fn name(&self) -> &'static str {
+        "run"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutServiceRun.text())
+            .arg(name_arg())
+            .arg(
+                Arg::with_name("interval")
+                    .long("interval")
+                    .takes_value(true)
+                    .value_name("SECS")
+                    .help("Seconds between schedule checks (default: 60)"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
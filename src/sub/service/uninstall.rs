@@ -0,0 +1,47 @@
+use clap::{App, ArgMatches, SubCommand};
+
+use super::super::{ExecResult, SubCmd};
+use super::{name_arg, service_name, Result};
+
+use crate::config::Config;
+use crate::message::Msg;
+use crate::util::service;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Uninstall::new())
+}
+
+pub struct Uninstall();
+
+impl Uninstall {
+    pub fn new() -> Uninstall {
+        Uninstall()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let name = service_name(matches);
+        service::uninstall(name)?;
+
+        if !config.is_quiet() {
+            println!("{}", Msg::ServiceUninstalled.fill(&[&name]));
+        }
+        Ok(())
+    }
+}
+
+impl SubCmd for Uninstall {
+    fn name(&self) -> &'static str {
+        "uninstall"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutServiceUninstall.text())
+            .arg(name_arg())
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
@@ -0,0 +1,424 @@
+use std::error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::ls::list_line;
+use super::{
+    open_repository, resolve_bank, select_history, BankSelectError, ExecResult, HistorySelectError,
+    RepoSelectError, SubCmd,
+};
+
+use crate::config::Config;
+use crate::core::entry::FsHash;
+use crate::core::extend::{self, Extender};
+use crate::core::hash::HashID;
+use crate::core::repo::{self, Bank};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::util::time::Tz;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Shell::new())
+}
+
+pub struct Shell();
+
+impl Shell {
+    pub fn new() -> Shell {
+        Shell()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let repo = open_repository(&config)?;
+        let bank_name = resolve_bank(matches, &config, &repo)?;
+        let bank = repo.open_bank(&bank_name)?;
+        let history = select_history(&bank, matches.value_of("revision"))?;
+        let timezone = Tz::from_name(config.history_timezone()).map_err(Error::Arg)?;
+
+        let mut session = Session::new(&bank, history.id().clone(), timezone);
+        // 端末から使う場合のみプロンプトを表示し、パイプから渡したコマンドの出力には混ぜない
+        let interactive = atty::is(atty::Stream::Stdin);
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        session.run(stdin.lock(), &mut stdout.lock(), interactive)
+    }
+}
+
+/// スナップショットを辿る対話的なシェルの状態
+pub(super) struct Session<'a> {
+    bank: &'a Bank<'a>,
+    root: HashID,
+    cwd: Vec<String>,
+    timezone: Tz,
+}
+
+impl<'a> Session<'a> {
+    pub(super) fn new(bank: &'a Bank<'a>, root: HashID, timezone: Tz) -> Session<'a> {
+        Session {
+            bank,
+            root,
+            cwd: Vec::new(),
+            timezone,
+        }
+    }
+
+    /// `input`から1行ずつコマンドを読んで実行し、結果を`out`に書き込む。
+    ///
+    /// コマンドの失敗は標準エラー出力に表示して続行する。入力の終わりか`exit`で終了する。
+    pub(super) fn run<R: BufRead, W: Write>(
+        &mut self,
+        input: R,
+        out: &mut W,
+        prompt: bool,
+    ) -> Result<()> {
+        let mut lines = input.lines();
+        loop {
+            if prompt {
+                write!(out, "{}:/{}> ", self.bank.name(), self.cwd.join("/"))?;
+                out.flush()?;
+            }
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => break,
+            };
+
+            let args = match split_args(&line) {
+                Ok(args) => args,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    continue;
+                }
+            };
+            match self.execute(&args, out) {
+                Ok(true) => {}
+                Ok(false) => break,
+                // 出力先に書き込めなくなった場合は続けても意味が無い
+                Err(Error::IO(e)) => return Err(Error::IO(e)),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        if prompt {
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    // コマンドを実行し、シェルを続けるかどうかを返す。
+    fn execute<W: Write>(&mut self, args: &[String], out: &mut W) -> Result<bool> {
+        let (command, args) = match args.split_first() {
+            Some((command, args)) => (command.as_str(), args),
+            None => return Ok(true),
+        };
+        match (command, args) {
+            ("exit", []) | ("quit", []) => return Ok(false),
+            ("help", []) => writeln!(out, "{}", Msg::ShellHelp)?,
+            ("pwd", []) => writeln!(out, "/{}", self.cwd.join("/"))?,
+            ("cd", []) => self.cwd.clear(),
+            ("cd", [path]) => {
+                let parts = resolve(&self.cwd, path);
+                self.dir_id(&parts)?;
+                self.cwd = parts;
+            }
+            ("ls", []) => self.list(&self.cwd.clone(), out)?,
+            ("ls", [path]) => self.list(&resolve(&self.cwd, path), out)?,
+            ("cat", [path]) => {
+                let parts = resolve(&self.cwd, path);
+                match self.entry(&parts)? {
+                    Some(FsHash::File(file)) => {
+                        let mut reader = self.bank.stream_object(&file.id())?;
+                        io::copy(&mut reader, out)?;
+                    }
+                    _ => return Err(Error::NotFile(display_path(&parts))),
+                }
+            }
+            ("get", [path]) => self.get(path, None, out)?,
+            ("get", [path, local]) => self.get(path, Some(Path::new(local)), out)?,
+            ("exit", _)
+            | ("quit", _)
+            | ("help", _)
+            | ("pwd", _)
+            | ("cd", _)
+            | ("ls", _)
+            | ("cat", _)
+            | ("get", _) => {
+                return Err(Error::Arg(format!(
+                    "wrong number of arguments for '{}'",
+                    command
+                )))
+            }
+            _ => return Err(Error::UnknownCommand(command.to_owned())),
+        }
+        Ok(true)
+    }
+
+    fn list<W: Write>(&self, parts: &[String], out: &mut W) -> Result<()> {
+        let dir = self.bank.load_dir(&self.dir_id(parts)?)?;
+        for ch in dir.children() {
+            writeln!(out, "{}", list_line(self.bank, self.timezone, ch)?)?;
+        }
+        Ok(())
+    }
+
+    // スナップショット内のファイルかディレクトリを、手元の`local`に取り出す。
+    //
+    // `local`を省略した場合は、現在のディレクトリに同じ名前で取り出す。
+    fn get<W: Write>(&self, path: &str, local: Option<&Path>, out: &mut W) -> Result<()> {
+        let parts = resolve(&self.cwd, path);
+        let entry = match self.entry(&parts)? {
+            Some(entry) => entry,
+            None => {
+                return Err(Error::Arg(
+                    "use 'restore' to extract whole snapshot".to_owned(),
+                ))
+            }
+        };
+        let local = match local {
+            Some(local) => local.to_owned(),
+            None => PathBuf::from(entry.attr().name()),
+        };
+        // ディレクトリは既存のものに追加できるが、ファイルは上書きしない
+        if !matches!(entry, FsHash::Dir(_)) && local.symlink_metadata().is_ok() {
+            return Err(Error::Exists(local));
+        }
+
+        let mut extender = Extender::new(self.bank);
+        extender.extend_entry(&local, &entry)?;
+        writeln!(
+            out,
+            "{}",
+            Msg::ShellSaved.fill(&[&display_path(&parts), &local.display()])
+        )?;
+        Ok(())
+    }
+
+    // スナップショット内のディレクトリ`parts`のIDを返す。
+    fn dir_id(&self, parts: &[String]) -> Result<HashID> {
+        let mut lazy = self.bank.lazy_dir(self.root.clone());
+        for (i, name) in parts.iter().enumerate() {
+            lazy = lazy
+                .child_dir(name)?
+                .ok_or_else(|| Error::NotDir(display_path(&parts[..=i])))?;
+        }
+        Ok(lazy.id().clone())
+    }
+
+    // スナップショット内の`parts`のエントリを返す。ルートディレクトリの場合は`None`を返す。
+    fn entry(&self, parts: &[String]) -> Result<Option<FsHash>> {
+        let (name, parent) = match parts.split_last() {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+        let dir = self.bank.load_dir(&self.dir_id(parent)?)?;
+        match dir.find_child(name) {
+            Some(entry) => Ok(Some(entry.clone())),
+            None => Err(Error::NotFound(display_path(parts))),
+        }
+    }
+}
+
+/// 現在のディレクトリ`cwd`から見た`path`を、ルートからの名前の列にする。
+///
+/// `/`で始まる場合はルートからのパスとして扱う。`..`はルートより上には辿らない。
+pub(super) fn resolve(cwd: &[String], path: &str) -> Vec<String> {
+    let mut parts = if path.starts_with('/') {
+        Vec::new()
+    } else {
+        cwd.to_vec()
+    };
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            name => parts.push(name.to_owned()),
+        }
+    }
+    parts
+}
+
+/// コマンドの行を空白で区切る。
+///
+/// 引用符(`'`, `"`)で囲んだ部分や`\`の直後の文字は、空白を含めてそのまま1つの引数にする。
+pub(super) fn split_args(line: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| Error::Arg("trailing backslash".to_owned()))?;
+                current.get_or_insert_with(String::new).push(escaped);
+            }
+            (Some(_), c) => current.get_or_insert_with(String::new).push(c),
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => args.extend(current.take()),
+            (None, c) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(Error::Arg("unterminated quote".to_owned()));
+    }
+    args.extend(current);
+    Ok(args)
+}
+
+fn display_path(parts: &[String]) -> String {
+    format!("/{}", parts.join("/"))
+}
+
+impl SubCmd for Shell {
+    fn name(&self) -> &'static str {
+        "shell"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutShell.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Bank name"),
+            )
+            .arg(
+                Arg::with_name("revision")
+                    .short("r")
+                    .long("revision")
+                    .takes_value(true)
+                    .help("Specify revision to browse [default: last backup]"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Arg(String),
+
+    Bank(BankSelectError),
+
+    Exists(PathBuf),
+
+    Extend(extend::Error),
+
+    History(HistorySelectError),
+
+    IO(io::Error),
+
+    NotDir(String),
+
+    NotFile(String),
+
+    NotFound(String),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+
+    UnknownCommand(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Arg(msg) => write!(f, "{}", msg),
+            Error::Bank(e) => write!(f, "{}", e),
+            Error::Exists(path) => write!(f, "{:?} already exists", path),
+            Error::Extend(e) => write!(f, "failed to extract: {}", e),
+            Error::History(e) => write!(f, "{}", e),
+            Error::IO(e) => write!(f, "IO error: {}", e),
+            Error::NotDir(path) => write!(f, "'{}' is not a directory in snapshot", path),
+            Error::NotFile(path) => write!(f, "'{}' is not a file in snapshot", path),
+            Error::NotFound(path) => write!(f, "'{}' is not found in snapshot", path),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+            Error::UnknownCommand(command) => {
+                write!(f, "unknown command '{}' (type 'help' for usage)", command)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Bank(e) => Some(e),
+            Error::Extend(e) => Some(e),
+            Error::History(e) => Some(e),
+            Error::IO(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Arg(_) | Error::UnknownCommand(_) => ErrorKind::Usage,
+            Error::Bank(e) => e.kind(),
+            Error::Exists(_) => ErrorKind::Other,
+            Error::Extend(e) => e.kind(),
+            Error::History(e) => e.kind(),
+            Error::IO(e) => Categorized::kind(e),
+            Error::NotDir(_) | Error::NotFile(_) | Error::NotFound(_) => ErrorKind::NotFound,
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl From<extend::Error> for Error {
+    fn from(e: extend::Error) -> Error {
+        Error::Extend(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IO(e)
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<BankSelectError> for Error {
+    fn from(e: BankSelectError) -> Error {
+        Error::Bank(e)
+    }
+}
+
+impl From<HistorySelectError> for Error {
+    fn from(e: HistorySelectError) -> Error {
+        Error::History(e)
+    }
+}
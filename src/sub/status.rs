@@ -0,0 +1,214 @@
+use std::error;
+use std::fmt;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::backup::{self, scanner_builder, ScanOptions};
+use super::{
+    open_repository, progress, resolve_bank, BankSelectError, ExecResult, RepoSelectError, SubCmd,
+};
+
+use crate::config::Config;
+use crate::core::repo;
+use crate::core::scan::{self, Change, ChangeKind};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::util::size::Size;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Status::new())
+}
+
+pub struct Status();
+
+impl Status {
+    pub fn new() -> Status {
+        Status()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let repo = open_repository(&config)?;
+
+        let bank_name = resolve_bank(matches, &config, &repo)?;
+        let bank = repo.open_bank(&bank_name)?;
+
+        let options = ScanOptions::from_matches(matches)?;
+        let scanner = scanner_builder(&bank, &config, &options)?
+            .progress(progress(&config, &format!("status {}", bank.name())))
+            .build();
+        let changes = scanner.preview()?;
+
+        if changes.is_empty() {
+            println!("{}", Msg::StatusNoChanges);
+            return Ok(());
+        }
+
+        let summary = matches.is_present("summary");
+        let target = bank.target_path();
+        for (kind, msg) in &[
+            (ChangeKind::Added, Msg::StatusAdded),
+            (ChangeKind::Updated, Msg::StatusUpdated),
+            (ChangeKind::Removed, Msg::StatusRemoved),
+        ] {
+            let group = changes
+                .iter()
+                .filter(|c| c.kind() == *kind)
+                .collect::<Vec<&Change>>();
+            if group.is_empty() {
+                continue;
+            }
+
+            let total: u64 = group.iter().map(|c| c.size()).sum();
+            println!("{}", msg.fill(&[&group.len(), &Size::from(total)]));
+            if summary {
+                continue;
+            }
+            for change in group {
+                let path = change.path().strip_prefix(target).unwrap_or(change.path());
+                println!(
+                    "{:>12}  {}",
+                    Size::from(change.size()).to_string(),
+                    path.display()
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl SubCmd for Status {
+    fn name(&self) -> &'static str {
+        "status"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutStatus.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Bank name"),
+            )
+            .arg(
+                Arg::with_name("summary")
+                    .short("s")
+                    .long("summary")
+                    .help("Show only counts and sizes of changes"),
+            )
+            .arg(
+                Arg::with_name("exclude")
+                    .short("e")
+                    .long("exclude")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Additional exclude pattern (same syntax as .sbakignore)"),
+            )
+            .arg(
+                Arg::with_name("follow_symlinks")
+                    .long("follow-symlinks")
+                    .help("Follow symbolic links as backup does with the same option"),
+            )
+            .arg(
+                Arg::with_name("max_size")
+                    .long("max-size")
+                    .takes_value(true)
+                    .help("Skip files larger than this size (e.g. 500MiB)"),
+            )
+            .arg(
+                Arg::with_name("max_depth")
+                    .long("max-depth")
+                    .takes_value(true)
+                    .help("Skip directories deeper than this depth"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Backup(backup::Error),
+
+    Bank(BankSelectError),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+
+    Scan(scan::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Backup(e) => write!(f, "{}", e),
+            Error::Bank(e) => write!(f, "{}", e),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+            Error::Scan(e) => write!(f, "file scan error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Backup(e) => Some(e),
+            Error::Bank(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            Error::Scan(e) => Some(e),
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Backup(e) => e.kind(),
+            Error::Bank(e) => e.kind(),
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+            Error::Scan(e) => e.kind(),
+        }
+    }
+}
+
+impl From<backup::Error> for Error {
+    fn from(e: backup::Error) -> Error {
+        Error::Backup(e)
+    }
+}
+
+impl From<BankSelectError> for Error {
+    fn from(e: BankSelectError) -> Error {
+        Error::Bank(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<scan::Error> for Error {
+    fn from(e: scan::Error) -> Error {
+        Error::Scan(e)
+    }
+}
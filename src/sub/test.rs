@@ -0,0 +1,179 @@
+use super::*;
+
+use std::fs;
+
+use clap::{App, AppSettings};
+use tempfile::tempdir;
+
+fn run(args: &[&str], config: Config) -> ExecResult {
+    let subs = sub_commands();
+    let matches = App::new("sbak")
+        .global_setting(AppSettings::InferSubcommands)
+        .subcommands(subs.arg_defs())
+        .get_matches_from(args);
+    let (name, sub_matches) = matches.subcommand();
+    subs.execute(name, sub_matches.unwrap(), config)
+        .expect("unknown subcommand")
+}
+
+#[test]
+fn test_execute_returns_result() {
+    let temp = tempdir().unwrap();
+    let repo_path = temp.path().join("repo");
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("a.txt"), "a").unwrap();
+
+    let mut config = Config::default();
+    config.set_repository_path(&repo_path);
+    config.set_verbosity(true, 0);
+
+    let repo_str = repo_path.to_str().unwrap();
+    let target_str = target.to_str().unwrap();
+
+    assert_eq!(
+        run(&["sbak", "init", "repo", "-p", repo_str], config.clone()).unwrap(),
+        0
+    );
+    assert_eq!(
+        run(
+            &["sbak", "init", "bank", "-n", "bank", "-p", target_str],
+            config.clone()
+        )
+        .unwrap(),
+        0
+    );
+    assert_eq!(
+        run(&["sbak", "backup", "-b", "bank"], config.clone()).unwrap(),
+        0
+    );
+
+    assert!(run(&["sbak", "backup", "-b", "missing"], config).is_err());
+}
+
+#[test]
+fn test_execute_prefix() {
+    let temp = tempdir().unwrap();
+    let repo_path = temp.path().join("repo");
+    let target = temp.path().join("target");
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("a.txt"), "a").unwrap();
+
+    let mut config = Config::default();
+    config.set_repository_path(&repo_path);
+    config.set_verbosity(true, 0);
+
+    let repo_str = repo_path.to_str().unwrap();
+    let target_str = target.to_str().unwrap();
+
+    assert_eq!(
+        run(&["sbak", "ini", "re", "-p", repo_str], config.clone()).unwrap(),
+        0
+    );
+    assert_eq!(
+        run(
+            &["sbak", "init", "ba", "-n", "bank", "-p", target_str],
+            config.clone()
+        )
+        .unwrap(),
+        0
+    );
+    assert_eq!(run(&["sbak", "back"], config.clone()).unwrap(), 0);
+    assert_eq!(run(&["sbak", "hist", "-b", "bank"], config).unwrap(), 0);
+}
+
+#[test]
+fn test_alias() {
+    let subs = sub_commands();
+    let matches = App::new("sbak")
+        .subcommands(subs.arg_defs())
+        .get_matches_from(["sbak", "hist"]);
+    assert_eq!(matches.subcommand_name(), Some("history"));
+}
+
+#[test]
+fn test_ambiguous_prefix() {
+    let subs = sub_commands();
+    let result = App::new("sbak")
+        .global_setting(AppSettings::InferSubcommands)
+        .subcommands(subs.arg_defs())
+        .get_matches_from_safe(["sbak", "in"]);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_execute_unknown() {
+    let subs = sub_commands();
+    let matches = App::new("sbak").get_matches_from(["sbak"]);
+    assert!(subs
+        .execute("unknown", &matches, Config::default())
+        .is_none());
+}
+
+#[test]
+fn test_shell_split_args() {
+    assert_eq!(
+        shell::split_args(r#"  get "my file.txt" 'it''s'  out\ dir "#).unwrap(),
+        vec!["get", "my file.txt", "its", "out dir"]
+    );
+    assert_eq!(
+        shell::split_args(r#"cat "" x"#).unwrap(),
+        vec!["cat", "", "x"]
+    );
+    assert!(shell::split_args(r#"cat "open"#).is_err());
+    assert!(shell::split_args(r"cat trailing\").is_err());
+}
+
+#[test]
+fn test_shell_resolve() {
+    let cwd = vec!["a".to_owned(), "b".to_owned()];
+    assert_eq!(shell::resolve(&cwd, "c/./d"), vec!["a", "b", "c", "d"]);
+    assert_eq!(shell::resolve(&cwd, "../c"), vec!["a", "c"]);
+    assert_eq!(shell::resolve(&cwd, "/x//y/"), vec!["x", "y"]);
+    assert!(shell::resolve(&cwd, "../../..").is_empty());
+}
+
+#[test]
+fn test_shell_session() {
+    use crate::core::repo::Repository;
+    use crate::core::scan::Scanner;
+    use crate::core::timestamp::Timestamp;
+    use crate::util::time::Tz;
+
+    let temp = tempdir().unwrap();
+    let target = temp.path().join("target");
+    fs::create_dir_all(target.join("sub dir")).unwrap();
+    fs::write(target.join("top.txt"), "top\n").unwrap();
+    fs::write(target.join("sub dir/a.txt"), "hello\n").unwrap();
+
+    let repo = Repository::create(temp.path().join("repo")).unwrap();
+    repo.create_bank("bank", &target).unwrap();
+    let bank = repo.open_bank("bank").unwrap();
+    let id = Scanner::new(&bank).scan().unwrap();
+    let history = bank
+        .save_history(id.id(), Timestamp::now().unwrap(), None)
+        .unwrap();
+
+    let local = temp.path().join("local");
+    let script = format!(
+        "cd 'sub dir'\npwd\ncat a.txt\ncd /missing\ncat ../top.txt\nget . {:?}\nexit\nls\n",
+        local
+    );
+    let mut session = shell::Session::new(&bank, history.id().clone(), Tz::Utc);
+    let mut out = Vec::new();
+    session.run(script.as_bytes(), &mut out, false).unwrap();
+
+    let out = String::from_utf8(out).unwrap();
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "/sub dir");
+    assert_eq!(lines[1], "hello");
+    assert_eq!(lines[2], "top");
+    assert!(lines[3].starts_with("'/sub dir'"), "{:?}", lines);
+    assert_eq!(
+        lines.len(),
+        4,
+        "commands after exit must not run: {:?}",
+        lines
+    );
+    assert_eq!(fs::read_to_string(local.join("a.txt")).unwrap(), "hello\n");
+}
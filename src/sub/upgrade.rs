@@ -0,0 +1,162 @@
+use std::error;
+use std::fmt;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::{open_repository, report_broken_banks, ExecResult, RepoSelectError, SubCmd};
+
+use crate::config::Config;
+use crate::core::repo::{self, Bank};
+use crate::core::upgrade::{self, Upgrader};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Upgrade::new())
+}
+
+pub struct Upgrade();
+
+impl Upgrade {
+    pub fn new() -> Upgrade {
+        Upgrade()
+    }
+
+    // 開けなかったBankのエラーを返す。
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<Vec<repo::Error>> {
+        let repo = open_repository(&config)?;
+
+        let dry_run = matches.is_present("dry_run");
+        let mut broken = Vec::new();
+
+        if let Some(bank_name) = matches.value_of("bank") {
+            let bank = repo.open_bank(bank_name)?;
+            upgrade_bank(&bank, dry_run, &config)?;
+        } else {
+            for bank in repo.open_all_banks()? {
+                match bank {
+                    Ok(bank) => upgrade_bank(&bank, dry_run, &config)?,
+                    Err(e) => broken.push(e),
+                }
+            }
+        }
+
+        Ok(broken)
+    }
+}
+
+fn upgrade_bank(bank: &Bank, dry_run: bool, config: &Config) -> Result<()> {
+    let _lock = bank.lock()?;
+
+    let mut upgrader = Upgrader::new(bank);
+    upgrader.dry_run(dry_run);
+    upgrader.upgrade()?;
+
+    if !config.is_quiet() {
+        let stats = upgrader.stats();
+        let msg = if dry_run {
+            Msg::UpgradeDryRun
+        } else {
+            Msg::UpgradeDone
+        };
+        println!(
+            "{}    {}",
+            bank.name(),
+            msg.fill(&[
+                &stats.upgraded_histories,
+                &stats.histories,
+                &stats.upgraded_entries,
+                &stats.entries,
+            ])
+        );
+    }
+
+    Ok(())
+}
+
+impl SubCmd for Upgrade {
+    fn name(&self) -> &'static str {
+        "upgrade"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutUpgrade.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Bank name"),
+            )
+            .arg(
+                Arg::with_name("dry_run")
+                    .long("dry-run")
+                    .help("Only count entries to be upgraded"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        let broken = self.wrapped_exec(matches, config)?;
+        Ok(report_broken_banks(&broken).unwrap_or(0))
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+
+    Upgrade(upgrade::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+            Error::Upgrade(e) => write!(f, "upgrade error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            Error::Upgrade(e) => Some(e),
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+            Error::Upgrade(e) => e.kind(),
+        }
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<upgrade::Error> for Error {
+    fn from(e: upgrade::Error) -> Error {
+        Error::Upgrade(e)
+    }
+}
@@ -0,0 +1,244 @@
+use std::error;
+use std::fmt;
+use std::thread;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::warn;
+
+use super::{
+    open_repository, progress, select_history, ExecResult, HistorySelectError, RepoSelectError,
+    SubCmd,
+};
+
+use crate::config::Config;
+use crate::core::repo;
+use crate::core::timestamp::{self, Timestamp};
+use crate::core::verify::{record_verified, Verifier, VerifyReport};
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::notify::{notify, Report};
+use crate::util::size::Size;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(Verify::new())
+}
+
+pub struct Verify();
+
+impl Verify {
+    pub fn new() -> Verify {
+        Verify()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: &Config) -> Result<VerifyReport> {
+        let threads = match matches.value_of("threads") {
+            Some(s) => s
+                .parse::<usize>()
+                .map_err(|e| Error::Arg(format!("invalid thread count: {}", e)))?,
+            None => thread::available_parallelism().map_or(1, |n| n.get()),
+        };
+        let repo = open_repository(config)?;
+        let started = Timestamp::now()?;
+
+        let verifier = Verifier::new(&repo)
+            .threads(threads)
+            .repair(matches.is_present("repair"))
+            .progress(progress(config, "verify"));
+        let report = match matches.value_of("bank") {
+            Some(bank_name) => {
+                let bank = repo.open_bank(bank_name)?;
+                let history = select_history(&bank, matches.value_of("revision"))?;
+                verifier.verify_history(&bank, &history)?
+            }
+            None => verifier.verify_all()?,
+        };
+
+        if let Err(e) = record_verified(&repo, &report, started) {
+            warn!("failed to record verified objects: {}", e);
+        }
+
+        print_results(&report);
+        if !config.is_quiet() {
+            println!(
+                "{}",
+                Msg::VerifyDone.fill(&[
+                    &report.objects(),
+                    &Size::from(report.bytes()),
+                    &report.failures().len(),
+                ])
+            );
+        }
+
+        Ok(report)
+    }
+}
+
+impl SubCmd for Verify {
+    fn name(&self) -> &'static str {
+        "verify"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutVerify.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Verify only objects referenced by a history of this bank"),
+            )
+            .arg(
+                Arg::with_name("revision")
+                    .short("r")
+                    .long("revision")
+                    .takes_value(true)
+                    .requires("bank")
+                    .help("Specify revision to verify (default: latest)"),
+            )
+            .arg(
+                Arg::with_name("threads")
+                    .short("j")
+                    .long("threads")
+                    .takes_value(true)
+                    .help("Number of threads to hash objects (default: number of CPUs)"),
+            )
+            .arg(
+                Arg::with_name("repair")
+                    .long("repair")
+                    .help("Repair corrupted objects from parity data"),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        let result = self.wrapped_exec(matches, &config);
+
+        let bank = matches.value_of("bank");
+        let notification = match result {
+            Ok(ref report) if report.is_ok() => Report::success("verify", bank, None),
+            Ok(ref report) => Report::failure_message(
+                "verify",
+                bank,
+                format!("{} objects failed verification", report.failures().len()),
+                failure_causes(report),
+            ),
+            Err(ref e) => Report::failure("verify", bank, e),
+        };
+        notify(&config, &notification);
+
+        let report = result?;
+        Ok(if report.is_ok() {
+            0
+        } else {
+            ErrorKind::VerifyFailed.exit_code()
+        })
+    }
+}
+
+// 通知に含める検証失敗の件数の上限
+const MAX_NOTIFIED_FAILURES: usize = 20;
+
+// 修復したオブジェクトと検証に失敗したオブジェクトを、原因の種類とともに1行ずつ表示する。
+pub(super) fn print_results(report: &VerifyReport) {
+    for id in report.repaired() {
+        println!("repaired\t{}", id);
+    }
+    for failure in report.failures() {
+        let label = match failure.error {
+            repo::Error::EntryNotFound(_) => "missing",
+            repo::Error::BrokenObject { .. } => "broken",
+            _ => "error",
+        };
+        println!("{}\t{}\t{}", label, failure.id, failure.error);
+    }
+}
+
+pub(super) fn failure_causes(report: &VerifyReport) -> Vec<String> {
+    let failures = report.failures();
+    let mut causes: Vec<String> = failures
+        .iter()
+        .take(MAX_NOTIFIED_FAILURES)
+        .map(|f| format!("{}: {}", f.id, f.error))
+        .collect();
+    if failures.len() > MAX_NOTIFIED_FAILURES {
+        causes.push(format!(
+            "and {} more",
+            failures.len() - MAX_NOTIFIED_FAILURES
+        ));
+    }
+    causes
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Arg(String),
+
+    History(HistorySelectError),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+
+    Timestamp,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Arg(msg) => write!(f, "{}", msg),
+            Error::History(e) => write!(f, "{}", e),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+            Error::Timestamp => write!(f, "timestamp is older than UNIX epoch"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::History(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Arg(_) => ErrorKind::Usage,
+            Error::History(e) => e.kind(),
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+            Error::Timestamp => ErrorKind::Data,
+        }
+    }
+}
+
+impl From<HistorySelectError> for Error {
+    fn from(e: HistorySelectError) -> Error {
+        Error::History(e)
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<timestamp::Error> for Error {
+    fn from(_e: timestamp::Error) -> Error {
+        Error::Timestamp
+    }
+}
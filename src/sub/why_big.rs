@@ -0,0 +1,216 @@
+use std::error;
+use std::fmt;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::{open_repository, resolve_bank, BankSelectError, ExecResult, RepoSelectError, SubCmd};
+
+use crate::config::Config;
+use crate::core::growth::analyze;
+use crate::core::repo;
+use crate::error::{Categorized, ErrorKind};
+use crate::message::Msg;
+use crate::util::size::Size;
+use crate::util::time::Tz;
+
+pub fn new() -> Box<dyn SubCmd> {
+    Box::new(WhyBig::new())
+}
+
+pub struct WhyBig();
+
+impl WhyBig {
+    pub fn new() -> WhyBig {
+        WhyBig()
+    }
+
+    fn wrapped_exec(&self, matches: &ArgMatches, config: Config) -> Result<()> {
+        let repository = open_repository(&config)?;
+
+        let bank_name = resolve_bank(matches, &config, &repository)?;
+        let count = parse_count(matches, "count")?;
+        let top = parse_count(matches, "top")?;
+        let depth = parse_count(matches, "depth")?;
+        let min_size: Size = matches
+            .value_of("min_size")
+            .unwrap()
+            .parse()
+            .map_err(|s| Error::Arg(format!("--min-size '{}' is not size.", s)))?;
+        let timezone = Tz::from_name(None).map_err(Error::Arg)?;
+
+        let bank = repository.open_bank(&bank_name)?;
+        let histories = bank.histories()?;
+
+        let l = histories.len();
+        let start = l.saturating_sub(count);
+        let base = if start > 0 {
+            Some(&histories[start - 1])
+        } else {
+            None
+        };
+        let report = analyze(&bank, base, &histories[start..], depth)?;
+
+        println!(
+            "{}",
+            Msg::WhyBigGrowth.fill(&[
+                &(l - start),
+                &Size::from(report.total_bytes()),
+                &Size::from(report.metadata_bytes()),
+            ])
+        );
+        println!();
+
+        println!("{}", Msg::WhyBigLargest);
+        for obj in report
+            .largest_objects()
+            .into_iter()
+            .filter(|o| o.size >= min_size.bytes())
+            .take(top)
+        {
+            println!(
+                "{:>12}  {}  {}",
+                Size::from(obj.size).to_string(),
+                timezone.at(obj.timestamp.unix_epoch()),
+                obj.path
+            );
+        }
+        println!();
+
+        println!("{}", Msg::WhyBigFrequent);
+        for (path, changes) in report.frequent_changes().into_iter().take(top) {
+            println!("{:>12}  {}", changes, path);
+        }
+        println!();
+
+        println!("{}", Msg::WhyBigByDirectory);
+        for (dir, bytes) in report.directory_bytes().into_iter().take(top) {
+            println!("{:>12}  {}", Size::from(bytes).to_string(), dir);
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_count(matches: &ArgMatches, name: &str) -> Result<usize> {
+    let s = matches.value_of(name).unwrap();
+    s.parse()
+        .map_err(|_| Error::Arg(format!("--{} '{}' is not number.", name, s)))
+}
+
+impl SubCmd for WhyBig {
+    fn name(&self) -> &'static str {
+        "why-big"
+    }
+
+    fn command_args(&self) -> App {
+        SubCommand::with_name(self.name())
+            .about(Msg::AboutWhyBig.text())
+            .arg(
+                Arg::with_name("bank")
+                    .short("b")
+                    .long("bank")
+                    .takes_value(true)
+                    .help("Bank name"),
+            )
+            .arg(
+                Arg::with_name("count")
+                    .short("n")
+                    .long("count")
+                    .takes_value(true)
+                    .default_value("10")
+                    .help("How many recent histories to be analyzed."),
+            )
+            .arg(
+                Arg::with_name("top")
+                    .short("k")
+                    .long("top")
+                    .takes_value(true)
+                    .default_value("10")
+                    .help("How many entries to be shown in each ranking."),
+            )
+            .arg(
+                Arg::with_name("min_size")
+                    .long("min-size")
+                    .takes_value(true)
+                    .default_value("0")
+                    .help("Hide new objects smaller than this size (e.g. 10MiB)."),
+            )
+            .arg(
+                Arg::with_name("depth")
+                    .short("d")
+                    .long("depth")
+                    .takes_value(true)
+                    .default_value("1")
+                    .help("Directory depth to aggregate new bytes."),
+            )
+    }
+
+    fn exec(&self, matches: &ArgMatches, config: Config) -> ExecResult {
+        self.wrapped_exec(matches, config)?;
+        Ok(0)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Bank(BankSelectError),
+
+    Arg(String),
+
+    RepoSelect(RepoSelectError),
+
+    Repo(repo::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Bank(e) => write!(f, "{}", e),
+            Error::Arg(msg) => write!(f, "{}", msg),
+            Error::RepoSelect(e) => write!(f, "{}", e),
+            Error::Repo(e) => write!(f, "repository operation error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Bank(e) => Some(e),
+            Error::RepoSelect(e) => Some(e),
+            Error::Repo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Categorized for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Bank(e) => e.kind(),
+            Error::Arg(_) => ErrorKind::Usage,
+            Error::RepoSelect(e) => e.kind(),
+            Error::Repo(e) => e.kind(),
+        }
+    }
+}
+
+impl From<repo::Error> for Error {
+    fn from(e: repo::Error) -> Error {
+        Error::Repo(e)
+    }
+}
+
+impl From<RepoSelectError> for Error {
+    fn from(e: RepoSelectError) -> Error {
+        Error::RepoSelect(e)
+    }
+}
+
+impl From<BankSelectError> for Error {
+    fn from(e: BankSelectError) -> Error {
+        Error::Bank(e)
+    }
+}
@@ -1,3 +1,16 @@
 //! 他のクレートとの接続用などのユーティリティ集。
 
+pub mod copy;
+pub mod disk;
+pub mod format;
+pub mod host;
+pub mod http;
+pub mod keyring;
+pub mod long_path;
+pub mod lru;
+pub mod man;
+pub mod picker;
+pub mod service;
+pub mod size;
+pub mod systemd;
 pub mod time;
@@ -0,0 +1,97 @@
+//! バッファ付きのファイルコピー。
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+#[cfg(test)]
+mod test;
+
+/// コピーに使うバッファサイズの既定値
+pub const DEFAULT_BUFFER_SIZE: usize = 256 * 1024;
+
+/// `reader`から`writer`に内容をコピーし、コピーしたバイト数を返す。
+///
+/// 読み込みと書き込みのそれぞれに`buffer_size`バイトのバッファを挟む。
+pub fn copy_buffered<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    buffer_size: usize,
+) -> io::Result<u64> {
+    let mut reader = BufReader::with_capacity(buffer_size, reader);
+    let mut writer = BufWriter::with_capacity(buffer_size, writer);
+
+    let bytes = io::copy(&mut reader, &mut writer)?;
+    writer.flush()?;
+
+    Ok(bytes)
+}
+
+/// ファイル`f`に`len`バイト分の領域を事前に確保する。
+///
+/// 断片化を減らすためのもので、対応していない環境やファイルシステムでは何もしない。
+#[cfg(target_os = "linux")]
+pub fn preallocate(f: &File, len: u64) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if len == 0 {
+        return Ok(());
+    }
+
+    if unsafe { libc::fallocate(f.as_raw_fd(), 0, 0, len as libc::off_t) } != 0 {
+        let e = io::Error::last_os_error();
+        return match e.raw_os_error() {
+            Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) | Some(libc::EINVAL) => Ok(()),
+            _ => Err(e),
+        };
+    }
+
+    Ok(())
+}
+
+/// ファイル`f`に`len`バイト分の領域を事前に確保する。
+///
+/// 断片化を減らすためのもので、対応していない環境やファイルシステムでは何もしない。
+#[cfg(not(target_os = "linux"))]
+pub fn preallocate(_f: &File, _len: u64) -> io::Result<()> {
+    Ok(())
+}
+
+/// ディレクトリ`dir`への変更（ファイルの作成や名前の変更）をディスクに書き込む。
+///
+/// ディレクトリを開けない環境では何もしない。
+#[cfg(unix)]
+pub fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+/// ディレクトリ`dir`への変更（ファイルの作成や名前の変更）をディスクに書き込む。
+///
+/// ディレクトリを開けない環境では何もしない。
+#[cfg(not(unix))]
+pub fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// ファイル`f`の内容をページキャッシュから追い出し、次の読み込みでディスクから読み直させる。
+///
+/// 書き込み済みのページだけが対象になるため、先に`sync_all`しておく必要がある。
+/// 対応していない環境では何もしない。
+#[cfg(target_os = "linux")]
+pub fn drop_cache(f: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let ret = unsafe { libc::posix_fadvise(f.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret));
+    }
+    Ok(())
+}
+
+/// ファイル`f`の内容をページキャッシュから追い出し、次の読み込みでディスクから読み直させる。
+///
+/// 対応していない環境では何もしない。
+#[cfg(not(target_os = "linux"))]
+pub fn drop_cache(_f: &File) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use super::*;
+
+use std::fs;
+use std::io::{Seek, SeekFrom};
+
+use tempfile::{tempdir, tempfile};
+
+#[test]
+fn test_copy_buffered() {
+    let data = (0..10_000).map(|i| (i % 256) as u8).collect::<Vec<_>>();
+
+    let mut out = Vec::new();
+    let bytes = copy_buffered(data.as_slice(), &mut out, 64).unwrap();
+
+    assert_eq!(bytes, 10_000);
+    assert_eq!(out, data);
+}
+
+#[test]
+fn test_preallocate_then_copy() {
+    let data = vec![7u8; 100_000];
+
+    let mut out = tempfile().unwrap();
+    preallocate(&out, data.len() as u64).unwrap();
+    copy_buffered(data.as_slice(), &mut out, DEFAULT_BUFFER_SIZE).unwrap();
+
+    assert_eq!(out.metadata().unwrap().len(), data.len() as u64);
+    out.seek(SeekFrom::Start(0)).unwrap();
+    let mut read = Vec::new();
+    out.read_to_end(&mut read).unwrap();
+    assert_eq!(read, data);
+}
+
+#[test]
+fn test_drop_cache_keeps_content() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    fs::write(&path, b"content").unwrap();
+    sync_dir(dir.path()).unwrap();
+
+    let f = File::open(&path).unwrap();
+    f.sync_all().unwrap();
+    drop_cache(&f).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"content");
+}
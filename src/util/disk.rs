@@ -0,0 +1,54 @@
+//! ファイルシステムの空き容量を取得する。
+
+use std::io;
+use std::path::Path;
+
+/// ファイルシステムの空き状況
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskSpace {
+    /// 一般ユーザーが利用可能なバイト数
+    pub available_bytes: u64,
+    /// 一般ユーザーが利用可能なinode数
+    ///
+    /// ファイルシステムがinode数に上限を持たない場合は`None`になる。
+    pub available_inodes: Option<u64>,
+}
+
+/// `path`が含まれるファイルシステムの空き状況を取得する。
+///
+/// 空き状況の取得に対応していない環境では`None`を返す。
+#[cfg(unix)]
+pub fn disk_space<P: AsRef<Path>>(path: P) -> io::Result<Option<DiskSpace>> {
+    use std::ffi::CString;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_ref().as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let available_bytes = (stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64);
+    // btrfsなどinodeを動的に確保するファイルシステムは総数0を報告する。
+    let available_inodes = if stat.f_files == 0 {
+        None
+    } else {
+        Some(stat.f_favail as u64)
+    };
+
+    Ok(Some(DiskSpace {
+        available_bytes,
+        available_inodes,
+    }))
+}
+
+/// `path`が含まれるファイルシステムの空き状況を取得する。
+///
+/// 空き状況の取得に対応していない環境では`None`を返す。
+#[cfg(not(unix))]
+pub fn disk_space<P: AsRef<Path>>(_path: P) -> io::Result<Option<DiskSpace>> {
+    Ok(None)
+}
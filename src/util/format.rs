@@ -0,0 +1,158 @@
+//! `--format`オプションで指定される出力テンプレートを扱う。
+//!
+//! テンプレートは`{id:.8} {time}`のように、文字列中に`{フィールド名}`または`{フィールド名:書式}`を埋め込んだものである。
+//! 書式は`[<|>][幅][.最大文字数]`の形で、`<`は左寄せ(既定)、`>`は右寄せを表す。
+//! `{`と`}`そのものは`{{`と`}}`で表す。
+
+use std::fmt::Write;
+
+#[cfg(test)]
+mod test;
+
+/// 解析済みのテンプレート
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// テンプレート文字列を解析する。
+    ///
+    /// `fields`はテンプレート中で使用できるフィールド名の一覧である。
+    ///
+    /// # Failures
+    ///
+    /// 構文が不正な場合や未知のフィールド名が含まれる場合、その理由を表す`String`を返す。
+    pub fn parse(template: &str, fields: &[&str]) -> Result<Template, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut body = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => body.push(c),
+                            None => return Err(format!("unclosed '{{' in '{}'", template)),
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(literal.split_off(0)));
+                    }
+                    parts.push(Part::Field(Field::parse(&body, fields)?));
+                }
+                '}' => return Err(format!("unmatched '}}' in '{}'", template)),
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Template { parts })
+    }
+
+    /// フィールドの値を`value`で取得し、テンプレートを展開する。
+    pub fn render<F: Fn(&str) -> String>(&self, value: F) -> String {
+        let mut out = String::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => out.push_str(s),
+                Part::Field(field) => field.write(&mut out, &value(&field.name)),
+            }
+        }
+
+        out
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Field {
+    name: String,
+    right_align: bool,
+    width: usize,
+    max_chars: Option<usize>,
+}
+
+impl Field {
+    fn parse(body: &str, fields: &[&str]) -> Result<Field, String> {
+        let (name, spec) = match body.find(':') {
+            Some(i) => (&body[..i], &body[i + 1..]),
+            None => (body, ""),
+        };
+        let name = name.trim();
+
+        if !fields.contains(&name) {
+            return Err(format!(
+                "unknown field '{}' (available: {})",
+                name,
+                fields.join(", ")
+            ));
+        }
+
+        let (right_align, spec) = if let Some(rest) = spec.strip_prefix('>') {
+            (true, rest)
+        } else if let Some(rest) = spec.strip_prefix('<') {
+            (false, rest)
+        } else {
+            (false, spec)
+        };
+
+        let (width_str, max_chars_str) = match spec.find('.') {
+            Some(i) => (&spec[..i], Some(&spec[i + 1..])),
+            None => (spec, None),
+        };
+
+        let invalid_spec = || format!("invalid format spec '{}' for field '{}'", spec, name);
+
+        let width = if width_str.is_empty() {
+            0
+        } else {
+            width_str.parse().map_err(|_| invalid_spec())?
+        };
+        let max_chars = match max_chars_str {
+            Some(s) => Some(s.parse().map_err(|_| invalid_spec())?),
+            None => None,
+        };
+
+        Ok(Field {
+            name: name.to_owned(),
+            right_align,
+            width,
+            max_chars,
+        })
+    }
+
+    fn write(&self, out: &mut String, value: &str) {
+        let value: String = match self.max_chars {
+            Some(n) => value.chars().take(n).collect(),
+            None => value.to_owned(),
+        };
+
+        if self.right_align {
+            write!(out, "{:>width$}", value, width = self.width).unwrap();
+        } else {
+            write!(out, "{:<width$}", value, width = self.width).unwrap();
+        }
+    }
+}
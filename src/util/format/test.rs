@@ -0,0 +1,44 @@
+use super::*;
+
+const FIELDS: &[&str] = &["id", "time", "bank"];
+
+fn render(template: &str) -> String {
+    let template = Template::parse(template, FIELDS).unwrap();
+    template.render(|name| match name {
+        "id" => "0123456789abcdef".to_owned(),
+        "time" => "2019-08-15 08:56:43".to_owned(),
+        "bank" => "home".to_owned(),
+        _ => unreachable!(),
+    })
+}
+
+#[test]
+fn test_render() {
+    let cases = vec![
+        ("{id}", "0123456789abcdef"),
+        ("{id:.8} {time}", "01234567 2019-08-15 08:56:43"),
+        ("[{bank:6}]", "[home  ]"),
+        ("[{bank:>6}]", "[  home]"),
+        ("[{bank:<6.2}]", "[ho    ]"),
+        ("{{{bank}}}", "{home}"),
+        ("no fields", "no fields"),
+        ("", ""),
+    ];
+
+    for (template, to_be) in cases {
+        assert_eq!(to_be, render(template), "template: {}", template);
+    }
+}
+
+#[test]
+fn test_parse_error() {
+    let cases = vec!["{memo}", "{id", "id}", "{id:x}", "{id:.y}"];
+
+    for template in cases {
+        assert!(
+            Template::parse(template, FIELDS).is_err(),
+            "template: {}",
+            template
+        );
+    }
+}
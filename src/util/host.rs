@@ -0,0 +1,70 @@
+//! 実行環境のホストの情報を取得する。
+
+use std::env;
+
+/// 実行中のマシンのホスト名を返す。
+///
+/// 取得できない場合は`"unknown"`を返す。
+pub fn hostname() -> String {
+    system_hostname()
+        .or_else(|| env::var("COMPUTERNAME").ok())
+        .or_else(|| env::var("HOSTNAME").ok())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+/// 実行中のユーザーの名前を返す。
+///
+/// 取得できない場合は`"unknown"`を返す。
+pub fn username() -> String {
+    env::var("USER")
+        .ok()
+        .or_else(|| env::var("USERNAME").ok())
+        .or_else(|| env::var("LOGNAME").ok())
+        .or_else(system_username)
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+#[cfg(unix)]
+fn system_username() -> Option<String> {
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = [0 as libc::c_char; 1024];
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let ret = unsafe {
+        libc::getpwuid_r(
+            libc::getuid(),
+            &mut pwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if ret != 0 || result.is_null() || pwd.pw_name.is_null() {
+        return None;
+    }
+
+    let name = unsafe { std::ffi::CStr::from_ptr(pwd.pw_name) };
+    name.to_str().ok().map(|s| s.to_owned())
+}
+
+#[cfg(not(unix))]
+fn system_username() -> Option<String> {
+    None
+}
+
+#[cfg(unix)]
+fn system_hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return None;
+    }
+
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8(buf[..len].to_vec()).ok()
+}
+
+#[cfg(not(unix))]
+fn system_hostname() -> Option<String> {
+    None
+}
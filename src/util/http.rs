@@ -0,0 +1,294 @@
+//! `serve`サブコマンドのための最小限のHTTP/1.1の実装。
+//!
+//! 1つの接続で1つのリクエストのみを扱い、レスポンスを返したら接続を閉じる。
+//! リクエストの本文は読まないため、本文を必要とするAPIには使えない。
+
+use std::io::{self, BufRead, Read, Write};
+
+use serde::Serialize;
+
+#[cfg(test)]
+mod test;
+
+// リクエスト行とヘッダーの1行あたり、およびヘッダーの数の上限
+const MAX_LINE_LEN: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+
+/// HTTPリクエスト
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// `r`からリクエスト行とヘッダーを読み込む。
+    ///
+    pub fn read_from<R: BufRead>(mut r: R) -> io::Result<Request> {
+        let line = read_line(&mut r)?;
+        let mut parts = line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/") => {
+                (method, target)
+            }
+            _ => return Err(invalid(format!("invalid request line: {:?}", line))),
+        };
+
+        let mut headers = Vec::new();
+        for _ in 0..MAX_HEADERS {
+            let header = read_line(&mut r)?;
+            if let Some(i) = header.find(':') {
+                headers.push((header[..i].to_owned(), header[i + 1..].trim().to_owned()));
+                continue;
+            }
+            if header.is_empty() {
+                let (path, query) = match target.find('?') {
+                    Some(i) => (&target[..i], parse_query(&target[i + 1..])?),
+                    None => (target, Vec::new()),
+                };
+                return Ok(Request {
+                    method: method.to_owned(),
+                    path: decode(path, false)?,
+                    query,
+                    headers,
+                });
+            }
+            return Err(invalid(format!("invalid header: {:?}", header)));
+        }
+        Err(invalid("too many headers".to_owned()))
+    }
+
+    /// メソッドを返す。
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// デコード済みのパスを返す。
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// パスを`/`で区切った、空でない要素を返す。
+    pub fn segments(&self) -> Vec<&str> {
+        self.path.split('/').filter(|s| !s.is_empty()).collect()
+    }
+
+    /// ヘッダー`name`の値を返す。
+    ///
+    /// ヘッダーの名前は大文字と小文字を区別しない。
+    /// 同じ名前のヘッダーが複数ある場合は最初のものを返す。
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// `Host`ヘッダーの値を返す。
+    pub fn host(&self) -> Option<&str> {
+        self.header("Host")
+    }
+
+    /// `Cookie`ヘッダーのクッキー`name`の値を返す。
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.header("Cookie")?
+            .split(';')
+            .filter_map(|c| c.trim().split_once('='))
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v)
+    }
+
+    /// クエリ文字列のパラメータ`name`の値を返す。
+    ///
+    /// 同じ名前のパラメータが複数ある場合は最初のものを返す。
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// HTTPレスポンス
+pub struct Response {
+    status: u16,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Box<dyn Read>,
+    len: u64,
+}
+
+impl Response {
+    /// `value`をJSONとして返すレスポンスを作る。
+    pub fn json<T: Serialize>(status: u16, value: &T) -> Response {
+        let body = serde_json::to_vec(value).expect("JSON serialization never fails");
+        Response::bytes(status, "application/json", body)
+    }
+
+    /// `{"error": message}`を返すレスポンスを作る。
+    pub fn error(status: u16, message: &str) -> Response {
+        Response::json(status, &serde_json::json!({ "error": message }))
+    }
+
+    /// `body`を返すレスポンスを作る。
+    pub fn bytes(status: u16, content_type: &'static str, body: Vec<u8>) -> Response {
+        let len = body.len() as u64;
+        Response::stream(status, content_type, io::Cursor::new(body), len)
+    }
+
+    /// `body`から`len`バイトを読み込んで返すレスポンスを作る。
+    pub fn stream<R: Read + 'static>(
+        status: u16,
+        content_type: &'static str,
+        body: R,
+        len: u64,
+    ) -> Response {
+        Response {
+            status,
+            content_type,
+            headers: Vec::new(),
+            body: Box::new(body),
+            len,
+        }
+    }
+
+    /// ヘッダー`name`を追加する。
+    pub fn with_header(mut self, name: &'static str, value: String) -> Response {
+        self.headers.push((name, value));
+        self
+    }
+
+    /// ブラウザに`file_name`という名前で保存させるヘッダーを追加する。
+    pub fn attachment(self, file_name: &str) -> Response {
+        let value = format!("attachment; filename*=UTF-8''{}", encode(file_name));
+        self.with_header("Content-Disposition", value)
+    }
+
+    /// ステータスコードを返す。
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// ヘッダーと本文を`w`に書き込む。
+    ///
+    /// 本文は終端まで読み込み、読み込みが失敗した場合は最後の1バイトを送らずにエラーを返す。
+    /// 終端で内容を検証するストリームでも、壊れた内容をクライアントが完全なレスポンスとして受け取らない。
+    pub fn write_to<W: Write>(mut self, w: &mut W) -> io::Result<()> {
+        write!(
+            w,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.len
+        )?;
+        for (name, value) in &self.headers {
+            write!(w, "{}: {}\r\n", name, value)?;
+        }
+        w.write_all(b"\r\n")?;
+        let head_len = self.len.saturating_sub(1);
+        let mut copied = io::copy(&mut self.body.by_ref().take(head_len), w)?;
+        let mut last = Vec::with_capacity(1);
+        copied += self.body.by_ref().take(1).read_to_end(&mut last)? as u64;
+        if copied != self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("body is shorter than {} bytes", self.len),
+            ));
+        }
+        if io::copy(&mut self.body, &mut io::sink())? != 0 {
+            return Err(invalid(format!("body is longer than {} bytes", self.len)));
+        }
+        w.write_all(&last)?;
+        w.flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+// CRLFまたはLFで終わる1行を、改行を除いて読み込む。
+fn read_line<R: BufRead>(r: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    r.by_ref().take(MAX_LINE_LEN).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(invalid("line is too long or unterminated".to_owned()));
+    }
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
+
+fn parse_query(query: &str) -> io::Result<Vec<(String, String)>> {
+    query
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (name, value) = match p.find('=') {
+                Some(i) => (&p[..i], &p[i + 1..]),
+                None => (p, ""),
+            };
+            Ok((decode(name, true)?, decode(value, true)?))
+        })
+        .collect()
+}
+
+/// `%XX`形式でエンコードされた文字列をデコードする。
+///
+/// `plus_as_space`が`true`の場合は、クエリ文字列と同様に`+`を空白とみなす。
+pub fn decode(s: &str, plus_as_space: bool) -> io::Result<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|h| std::str::from_utf8(h).ok())
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                    .ok_or_else(|| invalid(format!("invalid percent encoding: {:?}", s)))?;
+                decoded.push(hex);
+                i += 3;
+            }
+            b'+' if plus_as_space => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            b => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| invalid(format!("decoded {:?} is not UTF-8", s)))
+}
+
+/// 英数字と`-._~`以外のバイトを`%XX`形式でエンコードする。
+pub fn encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for &b in s.as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
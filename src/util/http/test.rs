@@ -0,0 +1,127 @@
+use super::*;
+
+#[test]
+fn test_read_request() {
+    let raw = "GET /banks/my%20bank/tree?path=dir%2Fa+b&x HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let req = Request::read_from(raw.as_bytes()).unwrap();
+    assert_eq!(req.method(), "GET");
+    assert_eq!(req.path(), "/banks/my bank/tree");
+    assert_eq!(req.segments(), vec!["banks", "my bank", "tree"]);
+    assert_eq!(req.query("path"), Some("dir/a b"));
+    assert_eq!(req.query("x"), Some(""));
+    assert_eq!(req.query("y"), None);
+    assert_eq!(req.host(), Some("localhost"));
+}
+
+#[test]
+fn test_read_request_lf() {
+    let req = Request::read_from("POST / HTTP/1.0\n\n".as_bytes()).unwrap();
+    assert_eq!(req.method(), "POST");
+    assert!(req.segments().is_empty());
+    assert_eq!(req.host(), None);
+}
+
+#[test]
+fn test_read_request_invalid() {
+    assert!(Request::read_from("GET /\r\n\r\n".as_bytes()).is_err());
+    assert!(Request::read_from("GET / HTTP/1.1\r\nHost: x\r\n".as_bytes()).is_err());
+    assert!(Request::read_from("GET /%zz HTTP/1.1\r\n\r\n".as_bytes()).is_err());
+    assert!(Request::read_from("GET / HTTP/1.1\r\nHost\r\n\r\n".as_bytes()).is_err());
+    let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(10 * 1024));
+    assert!(Request::read_from(long.as_bytes()).is_err());
+}
+
+#[test]
+fn test_read_request_host() {
+    let raw = "GET / HTTP/1.1\r\nAccept: */*\r\nhost:  127.0.0.1:8340 \r\n\r\n";
+    let req = Request::read_from(raw.as_bytes()).unwrap();
+    assert_eq!(req.host(), Some("127.0.0.1:8340"));
+    assert_eq!(req.header("ACCEPT"), Some("*/*"));
+    assert_eq!(req.header("Cookie"), None);
+}
+
+#[test]
+fn test_read_request_cookie() {
+    let raw = "GET / HTTP/1.1\r\nCookie: a=1; sbak_token=x=y;b\r\n\r\n";
+    let req = Request::read_from(raw.as_bytes()).unwrap();
+    assert_eq!(req.cookie("a"), Some("1"));
+    assert_eq!(req.cookie("sbak_token"), Some("x=y"));
+    assert_eq!(req.cookie("b"), None);
+    assert_eq!(req.cookie("c"), None);
+}
+
+#[test]
+fn test_decode() {
+    assert_eq!(decode("a%2Fb+c", false).unwrap(), "a/b+c");
+    assert_eq!(decode("a%2Fb+c", true).unwrap(), "a/b c");
+    assert_eq!(decode("%E3%81%82", false).unwrap(), "あ");
+    assert!(decode("%E3%81", false).is_err());
+    assert!(decode("%4", false).is_err());
+}
+
+#[test]
+fn test_encode() {
+    assert_eq!(encode("a-b_c.d~e"), "a-b_c.d~e");
+    assert_eq!(encode("a b/あ"), "a%20b%2F%E3%81%82");
+    assert_eq!(decode(&encode("メモ 1.txt"), false).unwrap(), "メモ 1.txt");
+}
+
+#[test]
+fn test_write_response() {
+    let mut out = Vec::new();
+    Response::error(404, "no such bank")
+        .write_to(&mut out)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "HTTP/1.1 404 Not Found\r\nContent-Type: application/json\r\nContent-Length: 24\r\nConnection: close\r\n\r\n{\"error\":\"no such bank\"}"
+    );
+}
+
+#[test]
+fn test_write_response_attachment() {
+    let mut out = Vec::new();
+    Response::bytes(200, "application/octet-stream", b"data".to_vec())
+        .attachment("a b.txt")
+        .write_to(&mut out)
+        .unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(
+        out.contains("\r\nContent-Disposition: attachment; filename*=UTF-8''a%20b.txt\r\n\r\ndata")
+    );
+}
+
+#[test]
+fn test_write_response_short_body() {
+    let mut out = Vec::new();
+    let res = Response::stream(200, "text/plain", "abc".as_bytes(), 5);
+    assert!(res.write_to(&mut out).is_err());
+}
+
+// 終端で失敗するストリーム
+struct FailAtEof<R>(R);
+
+impl<R: Read> Read for FailAtEof<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf)? {
+            0 => Err(invalid("hash mismatch".to_owned())),
+            n => Ok(n),
+        }
+    }
+}
+
+#[test]
+fn test_write_response_fail_at_eof() {
+    let mut out = Vec::new();
+    let res = Response::stream(200, "text/plain", FailAtEof("abc".as_bytes()), 3);
+    assert!(res.write_to(&mut out).is_err());
+    // 最後のバイトは送らない
+    assert!(String::from_utf8(out).unwrap().ends_with("\r\n\r\nab"));
+}
+
+#[test]
+fn test_write_response_long_body() {
+    let mut out = Vec::new();
+    let res = Response::stream(200, "text/plain", "abcde".as_bytes(), 3);
+    assert!(res.write_to(&mut out).is_err());
+}
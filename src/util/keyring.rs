@@ -0,0 +1,390 @@
+//! OSのキーリングにパスワードなどの秘密情報を保存する。
+//!
+//! macOSではキーチェーン(`security`コマンド)、Windowsでは資格情報マネージャー、
+//! それ以外のUnix系ではSecret Service(`secret-tool`コマンド)を使用する。
+//! 秘密情報はサービス名`sbak`と、呼び出し側が決める名前の組で識別される。
+
+use std::io;
+
+#[cfg(test)]
+mod test;
+
+/// キーリング上で使用するサービス名
+pub const SERVICE: &str = "sbak";
+
+/// `name`の秘密情報を取得する。
+///
+/// 保存されていない場合は`None`を返す。
+pub fn get(name: &str) -> io::Result<Option<String>> {
+    imp::get(name)
+}
+
+/// `name`の秘密情報として`secret`を保存する。
+///
+/// 既に保存されている場合は上書きする。
+pub fn set(name: &str, secret: &str) -> io::Result<()> {
+    imp::set(name, secret)
+}
+
+/// `name`の秘密情報を削除する。
+///
+/// 保存されていなかった場合は`false`を返す。
+pub fn delete(name: &str) -> io::Result<bool> {
+    imp::delete(name)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod imp {
+    use std::io;
+
+    use super::{secret_tool, SystemRunner};
+
+    pub fn get(name: &str) -> io::Result<Option<String>> {
+        secret_tool::get(&SystemRunner, name)
+    }
+
+    pub fn set(name: &str, secret: &str) -> io::Result<()> {
+        secret_tool::set(&SystemRunner, name, secret)
+    }
+
+    pub fn delete(name: &str) -> io::Result<bool> {
+        secret_tool::delete(&SystemRunner, name)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::io;
+
+    use super::{security, SystemRunner};
+
+    pub fn get(name: &str) -> io::Result<Option<String>> {
+        security::get(&SystemRunner, name)
+    }
+
+    pub fn set(name: &str, secret: &str) -> io::Result<()> {
+        security::set(&SystemRunner, name, secret)
+    }
+
+    pub fn delete(name: &str) -> io::Result<bool> {
+        security::delete(&SystemRunner, name)
+    }
+}
+
+/// 外部コマンドの実行結果
+#[cfg(any(test, unix))]
+#[derive(Debug, Default)]
+struct Output {
+    /// 終了コード。シグナルで終了した場合は`None`
+    code: Option<i32>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+#[cfg(any(test, unix))]
+impl Output {
+    fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// 外部コマンドの実行方法
+///
+/// テストでは実際のコマンドを実行せずに引数と出力を確かめるために差し替える。
+#[cfg(any(test, unix))]
+trait Runner {
+    /// `program`を`args`を引数として実行し、終了を待つ。
+    ///
+    /// `input`が`Some`の場合はその内容を標準入力に書き込む。
+    fn run(&self, program: &str, args: &[&str], input: Option<&[u8]>) -> io::Result<Output>;
+}
+
+/// 実際にコマンドを実行する`Runner`
+#[cfg(unix)]
+struct SystemRunner;
+
+#[cfg(unix)]
+impl Runner for SystemRunner {
+    fn run(&self, program: &str, args: &[&str], input: Option<&[u8]>) -> io::Result<Output> {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| spawn_error(program, e))?;
+        if let Some(input) = input {
+            child.stdin.take().unwrap().write_all(input)?;
+        }
+
+        let output = child.wait_with_output()?;
+        Ok(Output {
+            code: output.status.code(),
+            stdout: output.stdout,
+            stderr: output.stderr,
+        })
+    }
+}
+
+#[cfg(any(test, unix))]
+fn spawn_error(program: &str, e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::NotFound {
+        io::Error::new(e.kind(), format!("{} command isn't installed", program))
+    } else {
+        e
+    }
+}
+
+#[cfg(any(test, unix))]
+fn command_error(program: &str, stderr: &[u8]) -> io::Error {
+    io::Error::other(format!(
+        "{} failed: {}",
+        program,
+        String::from_utf8_lossy(stderr).trim()
+    ))
+}
+
+/// Secret Service(`secret-tool`コマンド)を使う実装
+#[cfg(any(test, all(unix, not(target_os = "macos"))))]
+mod secret_tool {
+    use std::io;
+
+    use super::{command_error, Runner, SERVICE};
+
+    pub const SECRET_TOOL: &str = "secret-tool";
+
+    pub fn get<R: Runner>(runner: &R, name: &str) -> io::Result<Option<String>> {
+        let output = runner.run(
+            SECRET_TOOL,
+            &["lookup", "service", SERVICE, "name", name],
+            None,
+        )?;
+
+        // 見つからない場合は何も出力せずに終了コード1で終了する。
+        if !output.success() {
+            if output.stderr.is_empty() {
+                return Ok(None);
+            }
+            return Err(command_error(SECRET_TOOL, &output.stderr));
+        }
+        Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+    }
+
+    pub fn set<R: Runner>(runner: &R, name: &str, secret: &str) -> io::Result<()> {
+        let label = format!("{} {}", SERVICE, name);
+        let output = runner.run(
+            SECRET_TOOL,
+            &["store", "--label", &label, "service", SERVICE, "name", name],
+            Some(secret.as_bytes()),
+        )?;
+        if !output.success() {
+            return Err(command_error(SECRET_TOOL, &output.stderr));
+        }
+        Ok(())
+    }
+
+    pub fn delete<R: Runner>(runner: &R, name: &str) -> io::Result<bool> {
+        if get(runner, name)?.is_none() {
+            return Ok(false);
+        }
+
+        let output = runner.run(
+            SECRET_TOOL,
+            &["clear", "service", SERVICE, "name", name],
+            None,
+        )?;
+        if !output.success() {
+            return Err(command_error(SECRET_TOOL, &output.stderr));
+        }
+        Ok(true)
+    }
+}
+
+/// macOSのキーチェーン(`security`コマンド)を使う実装
+#[cfg(any(test, target_os = "macos"))]
+mod security {
+    use std::io;
+
+    use super::{command_error, Runner, SERVICE};
+
+    pub const SECURITY: &str = "security";
+
+    // 項目が見つからない場合の終了コード(errSecItemNotFound)
+    pub const NOT_FOUND: i32 = 44;
+
+    pub fn get<R: Runner>(runner: &R, name: &str) -> io::Result<Option<String>> {
+        let output = runner.run(
+            SECURITY,
+            &["find-generic-password", "-s", SERVICE, "-a", name, "-w"],
+            None,
+        )?;
+
+        if output.code == Some(NOT_FOUND) {
+            return Ok(None);
+        }
+        if !output.success() {
+            return Err(command_error(SECURITY, &output.stderr));
+        }
+
+        let mut secret = String::from_utf8_lossy(&output.stdout).into_owned();
+        if secret.ends_with('\n') {
+            secret.pop();
+        }
+        Ok(Some(secret))
+    }
+
+    pub fn set<R: Runner>(runner: &R, name: &str, secret: &str) -> io::Result<()> {
+        // パスワードがプロセス一覧に表示されないよう、対話モードの標準入力からコマンドを渡す。
+        let command = format!(
+            "add-generic-password -U -s {} -a {} -w {}\n",
+            quote(SERVICE),
+            quote(name),
+            quote(secret)
+        );
+        let output = runner.run(SECURITY, &["-i"], Some(command.as_bytes()))?;
+        if !output.success() || !output.stderr.is_empty() {
+            return Err(command_error(SECURITY, &output.stderr));
+        }
+        Ok(())
+    }
+
+    pub fn delete<R: Runner>(runner: &R, name: &str) -> io::Result<bool> {
+        let output = runner.run(
+            SECURITY,
+            &["delete-generic-password", "-s", SERVICE, "-a", name],
+            None,
+        )?;
+
+        if output.code == Some(NOT_FOUND) {
+            return Ok(false);
+        }
+        if !output.success() {
+            return Err(command_error(SECURITY, &output.stderr));
+        }
+        Ok(true)
+    }
+
+    // `security -i`のコマンド行の引数として使えるように、二重引用符で囲んでエスケープする。
+    pub fn quote(s: &str) -> String {
+        format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::ffi::OsStr;
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use std::slice;
+
+    use winapi::shared::minwindef::{DWORD, FILETIME};
+    use winapi::shared::winerror::ERROR_NOT_FOUND;
+    use winapi::um::wincred::{
+        CredDeleteW, CredFree, CredReadW, CredWriteW, CREDENTIALW, CRED_PERSIST_LOCAL_MACHINE,
+        CRED_TYPE_GENERIC, PCREDENTIALW,
+    };
+
+    use super::SERVICE;
+
+    pub fn get(name: &str) -> io::Result<Option<String>> {
+        let target = target_name(name);
+        let mut cred: PCREDENTIALW = ptr::null_mut();
+        if unsafe { CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut cred) } == 0 {
+            return not_found_or_error().map(|_| None);
+        }
+
+        let secret = unsafe {
+            let blob =
+                slice::from_raw_parts((*cred).CredentialBlob, (*cred).CredentialBlobSize as usize);
+            String::from_utf8_lossy(blob).into_owned()
+        };
+        unsafe { CredFree(cred as *mut _) };
+        Ok(Some(secret))
+    }
+
+    pub fn set(name: &str, secret: &str) -> io::Result<()> {
+        let mut target = target_name(name);
+        let mut user: Vec<u16> = OsStr::new(name).encode_wide().chain(Some(0)).collect();
+        let mut blob = secret.as_bytes().to_vec();
+
+        let mut cred = CREDENTIALW {
+            Flags: 0,
+            Type: CRED_TYPE_GENERIC,
+            TargetName: target.as_mut_ptr(),
+            Comment: ptr::null_mut(),
+            LastWritten: FILETIME {
+                dwLowDateTime: 0,
+                dwHighDateTime: 0,
+            },
+            CredentialBlobSize: blob.len() as DWORD,
+            CredentialBlob: blob.as_mut_ptr(),
+            Persist: CRED_PERSIST_LOCAL_MACHINE,
+            AttributeCount: 0,
+            Attributes: ptr::null_mut(),
+            TargetAlias: ptr::null_mut(),
+            UserName: user.as_mut_ptr(),
+        };
+        if unsafe { CredWriteW(&mut cred, 0) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn delete(name: &str) -> io::Result<bool> {
+        let target = target_name(name);
+        if unsafe { CredDeleteW(target.as_ptr(), CRED_TYPE_GENERIC, 0) } == 0 {
+            return not_found_or_error().map(|_| false);
+        }
+        Ok(true)
+    }
+
+    // 資格情報マネージャー上の名前(`sbak:<name>`)を返す。
+    fn target_name(name: &str) -> Vec<u16> {
+        OsStr::new(&format!("{}:{}", SERVICE, name))
+            .encode_wide()
+            .chain(Some(0))
+            .collect()
+    }
+
+    // 直前のエラーが「見つからない」であれば`Ok`を、そうでなければそのエラーを返す。
+    fn not_found_or_error() -> io::Result<()> {
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() == Some(ERROR_NOT_FOUND as i32) {
+            Ok(())
+        } else {
+            Err(e)
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    use std::io;
+
+    pub fn get(_name: &str) -> io::Result<Option<String>> {
+        Err(unsupported())
+    }
+
+    pub fn set(_name: &str, _secret: &str) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn delete(_name: &str) -> io::Result<bool> {
+        Err(unsupported())
+    }
+
+    fn unsupported() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "keyring isn't supported on this platform",
+        )
+    }
+}
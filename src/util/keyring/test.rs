@@ -0,0 +1,208 @@
+use super::*;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+// 実行されたコマンドのプログラム名、引数、標準入力
+type Call = (String, Vec<String>, Option<String>);
+
+// 実行されたコマンドを記録し、あらかじめ指定した結果を順に返す`Runner`
+#[derive(Default)]
+struct FakeRunner {
+    calls: RefCell<Vec<Call>>,
+    outputs: RefCell<VecDeque<Output>>,
+}
+
+impl FakeRunner {
+    fn new(outputs: Vec<Output>) -> FakeRunner {
+        FakeRunner {
+            calls: RefCell::default(),
+            outputs: RefCell::new(outputs.into()),
+        }
+    }
+
+    fn calls(&self) -> Vec<Call> {
+        self.calls.borrow().clone()
+    }
+}
+
+impl Runner for FakeRunner {
+    fn run(&self, program: &str, args: &[&str], input: Option<&[u8]>) -> io::Result<Output> {
+        self.calls.borrow_mut().push((
+            program.to_owned(),
+            args.iter().map(|&a| a.to_owned()).collect(),
+            input.map(|i| String::from_utf8(i.to_vec()).unwrap()),
+        ));
+        Ok(self.outputs.borrow_mut().pop_front().unwrap())
+    }
+}
+
+fn output(code: i32, stdout: &str, stderr: &str) -> Output {
+    Output {
+        code: Some(code),
+        stdout: stdout.as_bytes().to_vec(),
+        stderr: stderr.as_bytes().to_vec(),
+    }
+}
+
+fn call(program: &str, args: &[&str], input: Option<&str>) -> Call {
+    (
+        program.to_owned(),
+        args.iter().map(|&a| a.to_owned()).collect(),
+        input.map(|i| i.to_owned()),
+    )
+}
+
+#[test]
+fn test_secret_tool_get() {
+    let runner = FakeRunner::new(vec![output(0, "p@ss\n", "")]);
+    // secret-toolは保存された値をそのまま出力する
+    assert_eq!(
+        secret_tool::get(&runner, "serve").unwrap(),
+        Some("p@ss\n".to_owned())
+    );
+    assert_eq!(
+        runner.calls(),
+        vec![call(
+            "secret-tool",
+            &["lookup", "service", "sbak", "name", "serve"],
+            None
+        )]
+    );
+
+    // 見つからない場合は何も出力せずに失敗する
+    let runner = FakeRunner::new(vec![output(1, "", "")]);
+    assert_eq!(secret_tool::get(&runner, "serve").unwrap(), None);
+
+    let runner = FakeRunner::new(vec![output(1, "", "No such secret collection\n")]);
+    let err = secret_tool::get(&runner, "serve").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "secret-tool failed: No such secret collection"
+    );
+}
+
+#[test]
+fn test_secret_tool_set() {
+    let runner = FakeRunner::new(vec![output(0, "", "")]);
+    secret_tool::set(&runner, "notify", "secret value").unwrap();
+    // 秘密情報は引数ではなく標準入力で渡す
+    assert_eq!(
+        runner.calls(),
+        vec![call(
+            "secret-tool",
+            &[
+                "store",
+                "--label",
+                "sbak notify",
+                "service",
+                "sbak",
+                "name",
+                "notify"
+            ],
+            Some("secret value")
+        )]
+    );
+
+    let runner = FakeRunner::new(vec![output(1, "", "locked\n")]);
+    assert!(secret_tool::set(&runner, "notify", "x").is_err());
+}
+
+#[test]
+fn test_secret_tool_delete() {
+    // 保存されていなければ削除しない
+    let runner = FakeRunner::new(vec![output(1, "", "")]);
+    assert!(!secret_tool::delete(&runner, "serve").unwrap());
+    assert_eq!(runner.calls().len(), 1);
+
+    let runner = FakeRunner::new(vec![output(0, "x", ""), output(0, "", "")]);
+    assert!(secret_tool::delete(&runner, "serve").unwrap());
+    assert_eq!(
+        runner.calls()[1],
+        call(
+            "secret-tool",
+            &["clear", "service", "sbak", "name", "serve"],
+            None
+        )
+    );
+}
+
+#[test]
+fn test_security_get() {
+    let runner = FakeRunner::new(vec![output(0, "p@ss\n", "")]);
+    // 末尾の改行は取り除く
+    assert_eq!(
+        security::get(&runner, "serve").unwrap(),
+        Some("p@ss".to_owned())
+    );
+    assert_eq!(
+        runner.calls(),
+        vec![call(
+            "security",
+            &["find-generic-password", "-s", "sbak", "-a", "serve", "-w"],
+            None
+        )]
+    );
+
+    let runner = FakeRunner::new(vec![output(security::NOT_FOUND, "", "not found")]);
+    assert_eq!(security::get(&runner, "serve").unwrap(), None);
+
+    let runner = FakeRunner::new(vec![output(1, "", "denied\n")]);
+    assert_eq!(
+        security::get(&runner, "serve").unwrap_err().to_string(),
+        "security failed: denied"
+    );
+}
+
+#[test]
+fn test_security_set() {
+    let runner = FakeRunner::new(vec![output(0, "", "")]);
+    security::set(&runner, "notify", r#"a "b" \c"#).unwrap();
+    // 秘密情報は対話モードのコマンドとして標準入力で渡す
+    assert_eq!(
+        runner.calls(),
+        vec![call(
+            "security",
+            &["-i"],
+            Some("add-generic-password -U -s \"sbak\" -a \"notify\" -w \"a \\\"b\\\" \\\\c\"\n")
+        )]
+    );
+
+    // 対話モードは失敗しても終了コードが0になるため、エラー出力でも判定する
+    let runner = FakeRunner::new(vec![output(
+        0,
+        "",
+        "SecKeychainItemCreateFromContent: failed\n",
+    )]);
+    assert!(security::set(&runner, "notify", "x").is_err());
+}
+
+#[test]
+fn test_security_delete() {
+    let runner = FakeRunner::new(vec![output(security::NOT_FOUND, "", "")]);
+    assert!(!security::delete(&runner, "serve").unwrap());
+
+    let runner = FakeRunner::new(vec![output(0, "", "")]);
+    assert!(security::delete(&runner, "serve").unwrap());
+    assert_eq!(
+        runner.calls(),
+        vec![call(
+            "security",
+            &["delete-generic-password", "-s", "sbak", "-a", "serve"],
+            None
+        )]
+    );
+}
+
+#[test]
+fn test_spawn_error() {
+    let e = spawn_error("secret-tool", io::Error::from(io::ErrorKind::NotFound));
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+    assert_eq!(e.to_string(), "secret-tool command isn't installed");
+
+    let e = spawn_error(
+        "secret-tool",
+        io::Error::from(io::ErrorKind::PermissionDenied),
+    );
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+}
@@ -0,0 +1,112 @@
+//! Windowsの`MAX_PATH`(260文字)を超えるパスを扱うための、拡張パス形式(`\\?\`)への変換。
+//!
+//! Windows以外ではパスをそのまま返す。
+
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod test;
+
+const VERBATIM: &str = r"\\?\";
+const VERBATIM_UNC: &str = r"\\?\UNC\";
+
+/// `path`を拡張パス形式に変換する。
+///
+/// 拡張パス形式では`.`や`..`が解釈されないため、絶対パスにした上で取り除く。
+/// 既に拡張パス形式のものや、変換できない形式のものはそのまま返す。
+#[cfg(windows)]
+pub fn extended(path: &Path) -> PathBuf {
+    let absolute = if path.is_absolute() {
+        path.to_owned()
+    } else {
+        match std::env::current_dir() {
+            Ok(dir) => dir.join(path),
+            Err(_) => return path.to_owned(),
+        }
+    };
+    absolute
+        .to_str()
+        .and_then(to_extended)
+        .map(PathBuf::from)
+        .unwrap_or(absolute)
+}
+
+/// `path`を拡張パス形式に変換する。
+#[cfg(not(windows))]
+pub fn extended(path: &Path) -> PathBuf {
+    path.to_owned()
+}
+
+/// 拡張パス形式の`path`を通常の形式に戻す。
+///
+/// 表示や、利用者が指定したパスとの比較に使う。
+#[cfg(windows)]
+pub fn normal(path: &Path) -> PathBuf {
+    path.to_str()
+        .and_then(to_normal)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| path.to_owned())
+}
+
+/// 拡張パス形式の`path`を通常の形式に戻す。
+#[cfg(not(windows))]
+pub fn normal(path: &Path) -> PathBuf {
+    path.to_owned()
+}
+
+// ドライブ(`C:\`)かUNC(`\\server\share\`)で始まる絶対パスを拡張パス形式にする。
+#[cfg_attr(not(windows), allow(dead_code))]
+fn to_extended(absolute: &str) -> Option<String> {
+    if absolute.starts_with(VERBATIM) || absolute.starts_with(r"\\.\") {
+        return None;
+    }
+
+    let path = absolute.replace('/', r"\");
+    let (mut extended, rest) = if let Some(unc) = path.strip_prefix(r"\\") {
+        let mut parts = unc.splitn(3, '\\');
+        let server = parts.next().filter(|s| !s.is_empty())?;
+        let share = parts.next().filter(|s| !s.is_empty())?;
+        (
+            format!(r"{}{}\{}", VERBATIM_UNC, server, share),
+            parts.next().unwrap_or(""),
+        )
+    } else {
+        let bytes = path.as_bytes();
+        if bytes.len() < 3
+            || !bytes[0].is_ascii_alphabetic()
+            || bytes[1] != b':'
+            || bytes[2] != b'\\'
+        {
+            return None;
+        }
+        (format!("{}{}", VERBATIM, &path[..2]), &path[3..])
+    };
+
+    let mut names = Vec::new();
+    for name in rest.split('\\') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                names.pop();
+            }
+            name => names.push(name),
+        }
+    }
+    extended.push('\\');
+    extended.push_str(&names.join(r"\"));
+    Some(extended)
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+fn to_normal(path: &str) -> Option<String> {
+    if let Some(unc) = path.strip_prefix(VERBATIM_UNC) {
+        return Some(format!(r"\\{}", unc));
+    }
+    let rest = path.strip_prefix(VERBATIM)?;
+    let bytes = rest.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        Some(rest.to_owned())
+    } else {
+        None
+    }
+}
@@ -0,0 +1,49 @@
+use super::*;
+
+#[test]
+fn test_to_extended() {
+    let cases = vec![
+        (r"C:\data", r"\\?\C:\data"),
+        (r"C:\", r"\\?\C:\"),
+        ("C:/data/sub", r"\\?\C:\data\sub"),
+        (r"C:\data\.\old\..\new\", r"\\?\C:\data\new"),
+        (r"C:\..\data", r"\\?\C:\data"),
+        (r"\\server\share\data", r"\\?\UNC\server\share\data"),
+        (r"\\server\share", r"\\?\UNC\server\share\"),
+    ];
+    for (input, to_be) in cases {
+        assert_eq!(
+            to_extended(input).as_deref(),
+            Some(to_be),
+            "input = {}",
+            input
+        );
+    }
+}
+
+#[test]
+fn test_to_extended_unchanged() {
+    for input in &[
+        r"\\?\C:\data",
+        r"\\.\pipe\sbak",
+        r"\\server",
+        "data",
+        "/data",
+    ] {
+        assert_eq!(to_extended(input), None, "input = {}", input);
+    }
+}
+
+#[test]
+fn test_to_normal() {
+    assert_eq!(to_normal(r"\\?\C:\data").as_deref(), Some(r"C:\data"));
+    assert_eq!(
+        to_normal(r"\\?\UNC\server\share\data").as_deref(),
+        Some(r"\\server\share\data")
+    );
+    assert_eq!(to_normal(r"\\?\Volume{1234}\data"), None);
+    assert_eq!(to_normal(r"C:\data"), None);
+
+    let long = format!(r"C:\{}", vec!["a".repeat(100); 5].join(r"\"));
+    assert_eq!(to_normal(&to_extended(&long).unwrap()), Some(long));
+}
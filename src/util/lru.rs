@@ -0,0 +1,101 @@
+//! 最近使われていないものから捨てるキャッシュ。
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+#[cfg(test)]
+mod test;
+
+/// 容量を超えると最も長く使われていない値を捨てるキャッシュ
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    tick: u64,
+    values: HashMap<K, (V, u64)>,
+    order: BTreeMap<u64, K>,
+}
+
+impl<K: Clone + Eq + Hash, V> LruCache<K, V> {
+    /// 最大で`capacity`個の値を保持する`LruCache`を生成する。
+    ///
+    /// `capacity`が0の場合は何も保持しない。
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        LruCache {
+            capacity,
+            tick: 0,
+            values: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    /// 保持できる値の最大数を返す。
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// 保持できる値の最大数を変更する。
+    ///
+    /// 現在の個数が`capacity`を超える場合は、古いものから捨てられる。
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// 保持している値の個数を返す。
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// 値を保持していないかどうかを返す。
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// `key`に対応する値を返し、最近使われたものとして記録する。
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let tick = self.next_tick();
+        let (value, used) = self.values.get_mut(key)?;
+        let k = self
+            .order
+            .remove(used)
+            .expect("order must contain used tick");
+        self.order.insert(tick, k);
+        *used = tick;
+        Some(value)
+    }
+
+    /// `key`に対応する値として`value`を保持する。
+    ///
+    /// 既に値がある場合は置き換えられる。
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let tick = self.next_tick();
+        if let Some((_, used)) = self.values.insert(key.clone(), (value, tick)) {
+            self.order.remove(&used);
+        }
+        self.order.insert(tick, key);
+        self.evict();
+    }
+
+    /// 全ての値を捨てる。
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.order.clear();
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn evict(&mut self) {
+        while self.values.len() > self.capacity {
+            let oldest = *self.order.keys().next().expect("order must not be empty");
+            let key = self.order.remove(&oldest).expect("oldest tick must exist");
+            self.values.remove(&key);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use super::*;
+
+#[test]
+fn test_evict_least_recently_used() {
+    let mut cache = LruCache::new(2);
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+
+    // "a"を使うと"b"が最も古くなる
+    assert_eq!(cache.get(&"a"), Some(&1));
+    cache.insert("c", 3);
+
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(&"a"), Some(&1));
+    assert_eq!(cache.get(&"b"), None);
+    assert_eq!(cache.get(&"c"), Some(&3));
+}
+
+#[test]
+fn test_insert_replaces_value() {
+    let mut cache = LruCache::new(2);
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("a", 10);
+    cache.insert("c", 3);
+
+    assert_eq!(cache.get(&"a"), Some(&10));
+    assert_eq!(cache.get(&"b"), None);
+    assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn test_zero_capacity_and_shrink() {
+    let mut cache = LruCache::new(0);
+    cache.insert("a", 1);
+    assert!(cache.is_empty());
+
+    cache.set_capacity(3);
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+    cache.set_capacity(1);
+
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.get(&"c"), Some(&3));
+}
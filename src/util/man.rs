@@ -0,0 +1,150 @@
+//! clapのヘルプ出力からmanページ(roff形式)を生成する。
+//!
+//! ヘルプ出力は、コマンド名と説明からなる先頭部分と、`USAGE:`や`OPTIONS:`のような見出しに続く字下げされた行の並びとして解析する。
+//! 折り返されていると解析できないため、ヘルプは`App::set_term_width(0)`を設定して生成しておくこと。
+
+use std::fmt::Write;
+
+#[cfg(test)]
+mod test;
+
+/// 解析済みのヘルプ出力
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Help {
+    header: Vec<String>,
+    sections: Vec<Section>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Section {
+    title: String,
+    lines: Vec<String>,
+}
+
+impl Help {
+    /// ヘルプ出力`text`を解析する。
+    pub fn parse(text: &str) -> Help {
+        let mut lines = text.lines().map(str::trim_end);
+
+        let header = lines
+            .by_ref()
+            .take_while(|l| !l.is_empty())
+            .map(|l| l.to_owned())
+            .collect();
+
+        let mut sections: Vec<Section> = Vec::new();
+        for line in lines {
+            if line.is_empty() {
+                continue;
+            }
+            if !line.starts_with(' ') && line.ends_with(':') {
+                sections.push(Section {
+                    title: line.trim_end_matches(':').to_owned(),
+                    lines: Vec::new(),
+                });
+                continue;
+            }
+            match sections.last_mut() {
+                Some(section) => section.lines.push(line.trim_start().to_owned()),
+                None => sections.push(Section {
+                    title: "DESCRIPTION".to_owned(),
+                    lines: vec![line.to_owned()],
+                }),
+            }
+        }
+
+        Help { header, sections }
+    }
+
+    /// コマンドの説明を返す。
+    ///
+    /// 先頭部分の最終行を説明とみなす。先頭部分がコマンド名のみの場合は`None`を返す。
+    pub fn about(&self) -> Option<&str> {
+        if self.header.len() < 2 {
+            return None;
+        }
+        self.header.last().map(|s| s.as_str())
+    }
+
+    /// `SUBCOMMANDS`に列挙されたサブコマンドの名前を返す。
+    ///
+    /// clapが自動で追加する`help`は含まない。
+    pub fn subcommands(&self) -> Vec<&str> {
+        self.sections
+            .iter()
+            .filter(|s| s.title == "SUBCOMMANDS")
+            .flat_map(|s| s.lines.iter())
+            .filter_map(|l| l.split_whitespace().next())
+            .filter(|&name| name != "help")
+            .collect()
+    }
+
+    /// `name`という名前のmanページとしてroff形式で出力する。
+    ///
+    /// `source`はフッターに表示するソフトウェア名とバージョン、`see_also`は関連するページの名前である。
+    pub fn render(&self, name: &str, source: &str, see_also: &[String]) -> String {
+        let mut s = String::new();
+        writeln!(
+            s,
+            ".TH \"{}\" \"1\" \"\" \"{}\" \"User Commands\"",
+            escape(&name.to_uppercase()),
+            escape(source)
+        )
+        .unwrap();
+
+        s.push_str(".SH NAME\n");
+        match self.about() {
+            Some(about) => writeln!(s, "{} \\- {}", escape(name), escape(about)).unwrap(),
+            None => writeln!(s, "{}", escape(name)).unwrap(),
+        }
+
+        for section in &self.sections {
+            if section.title == "USAGE" {
+                s.push_str(".SH SYNOPSIS\n");
+                for line in &section.lines {
+                    writeln!(s, "{}\n.br", escape(line)).unwrap();
+                }
+                if let Some(about) = self.about() {
+                    writeln!(s, ".SH DESCRIPTION\n{}", escape(about)).unwrap();
+                }
+                continue;
+            }
+
+            writeln!(s, ".SH {}", escape(&section.title.to_uppercase())).unwrap();
+            for line in &section.lines {
+                // 項目名と説明は2文字以上の空白で区切られている
+                match line.find("  ") {
+                    Some(i) => writeln!(
+                        s,
+                        ".TP\n\\fB{}\\fR\n{}",
+                        escape(&line[..i]),
+                        escape(line[i..].trim_start())
+                    )
+                    .unwrap(),
+                    None => writeln!(s, ".TP\n\\fB{}\\fR", escape(line)).unwrap(),
+                }
+            }
+        }
+
+        if !see_also.is_empty() {
+            s.push_str(".SH SEE ALSO\n");
+            let refs = see_also
+                .iter()
+                .map(|n| format!("\\fB{}\\fR(1)", escape(n)))
+                .collect::<Vec<_>>();
+            writeln!(s, "{}", refs.join(", ")).unwrap();
+        }
+
+        s
+    }
+}
+
+/// `text`をroffの本文として解釈されないようにエスケープする。
+pub fn escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    if escaped.starts_with('.') || escaped.starts_with('\'') {
+        format!("\\&{}", escaped)
+    } else {
+        escaped
+    }
+}
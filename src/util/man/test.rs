@@ -0,0 +1,58 @@
+use super::*;
+
+const HELP: &str = "sbak-init
+Create or initialize repository/bank
+
+USAGE:
+    sbak init [OPTIONS] [SUBCOMMAND]
+
+FLAGS:
+    -h, --help       Prints help information
+
+OPTIONS:
+        --repo <repo>    Overwrite repository path
+
+SUBCOMMANDS:
+    bank    Create or initialize bank
+    help    Prints this message or the help of the given subcommand(s)
+    repo    Create or initialize repository
+";
+
+#[test]
+fn test_parse() {
+    let help = Help::parse(HELP);
+    assert_eq!(help.about(), Some("Create or initialize repository/bank"));
+    assert_eq!(help.subcommands(), vec!["bank", "repo"]);
+}
+
+#[test]
+fn test_about_missing() {
+    let help = Help::parse("sbak-foo \n\nUSAGE:\n    sbak foo\n");
+    assert_eq!(help.about(), None);
+    assert!(help.subcommands().is_empty());
+}
+
+#[test]
+fn test_render() {
+    let help = Help::parse(HELP);
+    let page = help.render(
+        "sbak-init",
+        "sbak 0.1.3",
+        &["sbak".to_owned(), "sbak-init-bank".to_owned()],
+    );
+
+    assert!(page.starts_with(".TH \"SBAK\\-INIT\" \"1\" \"\" \"sbak 0.1.3\" \"User Commands\"\n"));
+    assert!(page.contains(".SH NAME\nsbak\\-init \\- Create or initialize repository/bank\n"));
+    assert!(page.contains(".SH SYNOPSIS\nsbak init [OPTIONS] [SUBCOMMAND]\n.br\n"));
+    assert!(page.contains(".TP\n\\fB\\-h, \\-\\-help\\fR\nPrints help information\n"));
+    assert!(page.contains(".TP\n\\fB\\-\\-repo <repo>\\fR\nOverwrite repository path\n"));
+    assert!(page.contains(".SH SEE ALSO\n\\fBsbak\\fR(1), \\fBsbak\\-init\\-bank\\fR(1)\n"));
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(escape("a-b"), "a\\-b");
+    assert_eq!(escape("C:\\dir"), "C:\\edir");
+    assert_eq!(escape(".hidden"), "\\&.hidden");
+    assert_eq!(escape("'quoted'"), "\\&'quoted'");
+}
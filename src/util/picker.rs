@@ -0,0 +1,145 @@
+//! 端末での対話的な項目選択。
+
+use std::io::{self, stderr, Write};
+
+#[cfg(not(unix))]
+use crate::message::Msg;
+
+const WINDOW_SIZE: usize = 10;
+
+/// `items`の中から1つを対話的に選ばせ、そのインデックスを返す。
+///
+/// 矢印キー(または`j`/`k`)で移動し、Enterで決定する。`q`かEscで中断した場合は`None`を返す。
+/// 端末の生入力モードに対応していない環境では、番号を入力させる。
+///
+/// 標準入力と標準エラー出力が端末であることを前提とする。
+pub fn pick(title: &str, items: &[String]) -> io::Result<Option<usize>> {
+    if items.is_empty() {
+        return Ok(None);
+    }
+
+    pick_impl(title, items)
+}
+
+#[cfg(unix)]
+fn pick_impl(title: &str, items: &[String]) -> io::Result<Option<usize>> {
+    use std::io::{stdin, Read};
+
+    let _raw = RawMode::enable()?;
+    let mut err = stderr();
+    let mut input = stdin();
+
+    let mut selected = 0;
+    let mut drawn_lines = 0;
+
+    loop {
+        drawn_lines = draw(&mut err, title, items, selected, drawn_lines)?;
+
+        let mut buf = [0u8; 3];
+        let n = input.read(&mut buf)?;
+        match &buf[..n] {
+            b"\r" | b"\n" => return Ok(Some(selected)),
+            b"q" | b"\x1b" | b"\x03" | b"" => return Ok(None),
+            b"\x1b[A" | b"k" => selected = selected.saturating_sub(1),
+            b"\x1b[B" | b"j" => selected = (selected + 1).min(items.len() - 1),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn pick_impl(title: &str, items: &[String]) -> io::Result<Option<usize>> {
+    use std::io::stdin;
+
+    let mut err = stderr();
+    writeln!(err, "{}", title)?;
+    for (i, item) in items.iter().enumerate() {
+        writeln!(err, "{:3}: {}", i + 1, item)?;
+    }
+
+    loop {
+        write!(err, "{}", Msg::PickerNumber)?;
+        err.flush()?;
+
+        let mut line = String::new();
+        stdin().read_line(&mut line)?;
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        match line.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= items.len() => return Ok(Some(n - 1)),
+            _ => writeln!(err, "{}", Msg::PickerInvalidNumber.fill(&[&line]))?,
+        }
+    }
+}
+
+// 選択肢を描画し、描画した行数を返す。
+//
+// 前回の描画分の行を消去してから描画する。
+#[cfg(unix)]
+fn draw<W: Write>(
+    w: &mut W,
+    title: &str,
+    items: &[String],
+    selected: usize,
+    drawn_lines: usize,
+) -> io::Result<usize> {
+    if drawn_lines > 0 {
+        write!(w, "\x1b[{}A", drawn_lines)?;
+    }
+
+    let start = (selected + 1).saturating_sub(WINDOW_SIZE);
+    let end = (start + WINDOW_SIZE).min(items.len());
+
+    write!(
+        w,
+        "\r\x1b[K{} ({}/{})\r\n",
+        title,
+        selected + 1,
+        items.len()
+    )?;
+    for (i, item) in items.iter().enumerate().take(end).skip(start) {
+        let marker = if i == selected { ">" } else { " " };
+        write!(w, "\r\x1b[K{} {}\r\n", marker, item)?;
+    }
+    w.flush()?;
+
+    Ok(end - start + 1)
+}
+
+// 生きている間、標準入力を非カノニカル・エコーなしのモードにする。
+#[cfg(unix)]
+struct RawMode {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(RawMode { original })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
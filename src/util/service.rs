@@ -0,0 +1,320 @@
+//! Windowsのサービスとして登録・実行する。
+//!
+//! Windows以外の環境では、全ての操作が`io::ErrorKind::Unsupported`のエラーになる。
+
+use std::io;
+
+/// サービス制御マネージャーからの要求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    /// 停止（システムのシャットダウンを含む）
+    Stop,
+    /// 一時停止
+    Pause,
+    /// 一時停止からの再開
+    Continue,
+}
+
+/// 制御要求を受け取る関数
+pub type Handler = Box<dyn Fn(Request) + Send + Sync>;
+
+/// サービスの本体
+///
+/// 戻り値はサービスの終了コードとして報告される。
+pub type Body = Box<dyn FnOnce() -> u32 + Send>;
+
+/// `command_line`を実行するサービスを`name`という名前で登録する。
+///
+/// サービスはシステムの起動時に自動的に開始される。
+#[cfg(windows)]
+pub fn install(
+    name: &str,
+    display_name: &str,
+    description: &str,
+    command_line: &str,
+) -> io::Result<()> {
+    windows::install(name, display_name, description, command_line)
+}
+
+/// `command_line`を実行するサービスを`name`という名前で登録する。
+#[cfg(not(windows))]
+pub fn install(
+    _name: &str,
+    _display_name: &str,
+    _description: &str,
+    _command_line: &str,
+) -> io::Result<()> {
+    Err(unsupported())
+}
+
+/// `name`のサービスを停止し、登録を削除する。
+#[cfg(windows)]
+pub fn uninstall(name: &str) -> io::Result<()> {
+    windows::uninstall(name)
+}
+
+/// `name`のサービスを停止し、登録を削除する。
+#[cfg(not(windows))]
+pub fn uninstall(_name: &str) -> io::Result<()> {
+    Err(unsupported())
+}
+
+/// サービス制御マネージャーに接続し、`name`のサービスとして`body`を実行する。
+///
+/// `body`が終了するまで返らない。
+/// 実行中に受け取った制御要求は`handler`に渡される。
+/// サービス制御マネージャーから起動されていない場合はエラーになる。
+#[cfg(windows)]
+pub fn run(name: &str, handler: Handler, body: Body) -> io::Result<()> {
+    windows::run(name, handler, body)
+}
+
+/// サービス制御マネージャーに接続し、`name`のサービスとして`body`を実行する。
+#[cfg(not(windows))]
+pub fn run(_name: &str, _handler: Handler, _body: Body) -> io::Result<()> {
+    Err(unsupported())
+}
+
+#[cfg(not(windows))]
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Windows service isn't supported on this platform",
+    )
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::OsStr;
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use lazy_static::lazy_static;
+    use winapi::shared::minwindef::{DWORD, LPVOID};
+    use winapi::shared::winerror::{
+        ERROR_CALL_NOT_IMPLEMENTED, ERROR_SERVICE_SPECIFIC_ERROR, NO_ERROR,
+    };
+    use winapi::um::winnt::{
+        DELETE, LPWSTR, SERVICE_AUTO_START, SERVICE_ERROR_NORMAL, SERVICE_WIN32_OWN_PROCESS,
+    };
+    use winapi::um::winsvc::{
+        ChangeServiceConfig2W, CloseServiceHandle, ControlService, CreateServiceW, DeleteService,
+        OpenSCManagerW, OpenServiceW, RegisterServiceCtrlHandlerExW, SetServiceStatus,
+        StartServiceCtrlDispatcherW, SC_HANDLE, SC_MANAGER_CONNECT, SC_MANAGER_CREATE_SERVICE,
+        SERVICE_ACCEPT_PAUSE_CONTINUE, SERVICE_ACCEPT_SHUTDOWN, SERVICE_ACCEPT_STOP,
+        SERVICE_ALL_ACCESS, SERVICE_CONFIG_DESCRIPTION, SERVICE_CONTROL_CONTINUE,
+        SERVICE_CONTROL_INTERROGATE, SERVICE_CONTROL_PAUSE, SERVICE_CONTROL_SHUTDOWN,
+        SERVICE_CONTROL_STOP, SERVICE_DESCRIPTIONW, SERVICE_PAUSED, SERVICE_QUERY_STATUS,
+        SERVICE_RUNNING, SERVICE_START_PENDING, SERVICE_STATUS, SERVICE_STATUS_HANDLE,
+        SERVICE_STOP, SERVICE_STOPPED, SERVICE_STOP_PENDING, SERVICE_TABLE_ENTRYW,
+    };
+
+    use super::{Body, Handler, Request};
+
+    // 停止や開始の処理に掛かる時間の目安（ミリ秒）
+    const WAIT_HINT_MS: DWORD = 30_000;
+
+    lazy_static! {
+        static ref SERVICE: Mutex<Option<(Vec<u16>, Handler, Body)>> = Mutex::new(None);
+        static ref HANDLER: Mutex<Option<Handler>> = Mutex::new(None);
+    }
+
+    // `SERVICE_STATUS_HANDLE`はスレッド間で共有できないため、アドレスとして保持する。
+    static STATUS_HANDLE: AtomicUsize = AtomicUsize::new(0);
+
+    pub fn install(
+        name: &str,
+        display_name: &str,
+        description: &str,
+        command_line: &str,
+    ) -> io::Result<()> {
+        let manager = ScHandle::manager(SC_MANAGER_CREATE_SERVICE)?;
+
+        let name = wide(name);
+        let display_name = wide(display_name);
+        let command_line = wide(command_line);
+        let service = ScHandle::new(unsafe {
+            CreateServiceW(
+                manager.0,
+                name.as_ptr(),
+                display_name.as_ptr(),
+                SERVICE_ALL_ACCESS,
+                SERVICE_WIN32_OWN_PROCESS,
+                SERVICE_AUTO_START,
+                SERVICE_ERROR_NORMAL,
+                command_line.as_ptr(),
+                ptr::null(),
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+            )
+        })?;
+
+        let mut description = wide(description);
+        let mut info = SERVICE_DESCRIPTIONW {
+            lpDescription: description.as_mut_ptr(),
+        };
+        let ok = unsafe {
+            ChangeServiceConfig2W(
+                service.0,
+                SERVICE_CONFIG_DESCRIPTION,
+                &mut info as *mut SERVICE_DESCRIPTIONW as LPVOID,
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    pub fn uninstall(name: &str) -> io::Result<()> {
+        let manager = ScHandle::manager(SC_MANAGER_CONNECT)?;
+
+        let name = wide(name);
+        let service = ScHandle::new(unsafe {
+            OpenServiceW(
+                manager.0,
+                name.as_ptr(),
+                SERVICE_STOP | SERVICE_QUERY_STATUS | DELETE,
+            )
+        })?;
+
+        // 停止していない場合に備えて停止を要求する。停止済みの場合のエラーは無視する。
+        let mut status: SERVICE_STATUS = unsafe { std::mem::zeroed() };
+        unsafe { ControlService(service.0, SERVICE_CONTROL_STOP, &mut status) };
+
+        if unsafe { DeleteService(service.0) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn run(name: &str, handler: Handler, body: Body) -> io::Result<()> {
+        let mut name = wide(name);
+        let table = [
+            SERVICE_TABLE_ENTRYW {
+                lpServiceName: name.as_mut_ptr(),
+                lpServiceProc: Some(service_main),
+            },
+            SERVICE_TABLE_ENTRYW {
+                lpServiceName: ptr::null_mut(),
+                lpServiceProc: None,
+            },
+        ];
+        *SERVICE.lock().unwrap() = Some((name.clone(), handler, body));
+
+        // サービスが停止するまで返らない。
+        if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    unsafe extern "system" fn service_main(_argc: DWORD, _argv: *mut LPWSTR) {
+        let (name, handler, body) = match SERVICE.lock().unwrap().take() {
+            Some(service) => service,
+            None => return,
+        };
+        *HANDLER.lock().unwrap() = Some(handler);
+
+        let handle =
+            RegisterServiceCtrlHandlerExW(name.as_ptr(), Some(control_handler), ptr::null_mut());
+        if handle.is_null() {
+            return;
+        }
+        STATUS_HANDLE.store(handle as usize, Ordering::SeqCst);
+
+        set_status(SERVICE_START_PENDING, 0);
+        set_status(SERVICE_RUNNING, 0);
+        let code = body();
+        set_status(SERVICE_STOPPED, code);
+    }
+
+    unsafe extern "system" fn control_handler(
+        control: DWORD,
+        _event_type: DWORD,
+        _event_data: LPVOID,
+        _context: LPVOID,
+    ) -> DWORD {
+        let (request, next) = match control {
+            SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+                (Request::Stop, SERVICE_STOP_PENDING)
+            }
+            SERVICE_CONTROL_PAUSE => (Request::Pause, SERVICE_PAUSED),
+            SERVICE_CONTROL_CONTINUE => (Request::Continue, SERVICE_RUNNING),
+            SERVICE_CONTROL_INTERROGATE => return NO_ERROR,
+            _ => return ERROR_CALL_NOT_IMPLEMENTED,
+        };
+
+        // 要求を受けて本体が終了した後に状態を上書きしないよう、先に状態を報告する。
+        set_status(next, 0);
+        if let Some(ref handler) = *HANDLER.lock().unwrap() {
+            handler(request);
+        }
+        NO_ERROR
+    }
+
+    fn set_status(state: DWORD, exit_code: u32) {
+        let handle = STATUS_HANDLE.load(Ordering::SeqCst) as SERVICE_STATUS_HANDLE;
+        if handle.is_null() {
+            return;
+        }
+
+        let accepted = match state {
+            SERVICE_START_PENDING | SERVICE_STOP_PENDING | SERVICE_STOPPED => 0,
+            _ => SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN | SERVICE_ACCEPT_PAUSE_CONTINUE,
+        };
+        let (win32_exit_code, specific_exit_code) = if exit_code == 0 {
+            (NO_ERROR, 0)
+        } else {
+            (ERROR_SERVICE_SPECIFIC_ERROR, exit_code)
+        };
+        let wait_hint = match state {
+            SERVICE_START_PENDING | SERVICE_STOP_PENDING => WAIT_HINT_MS,
+            _ => 0,
+        };
+
+        let mut status = SERVICE_STATUS {
+            dwServiceType: SERVICE_WIN32_OWN_PROCESS,
+            dwCurrentState: state,
+            dwControlsAccepted: accepted,
+            dwWin32ExitCode: win32_exit_code,
+            dwServiceSpecificExitCode: specific_exit_code,
+            dwCheckPoint: 0,
+            dwWaitHint: wait_hint,
+        };
+        unsafe { SetServiceStatus(handle, &mut status) };
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        OsStr::new(s).encode_wide().chain(Some(0)).collect()
+    }
+
+    // 閉じ忘れを防ぐための`SC_HANDLE`のラッパー
+    struct ScHandle(SC_HANDLE);
+
+    impl ScHandle {
+        fn manager(access: DWORD) -> io::Result<ScHandle> {
+            ScHandle::new(unsafe { OpenSCManagerW(ptr::null(), ptr::null(), access) })
+        }
+
+        fn new(handle: SC_HANDLE) -> io::Result<ScHandle> {
+            if handle.is_null() {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(ScHandle(handle))
+            }
+        }
+    }
+
+    impl Drop for ScHandle {
+        fn drop(&mut self) {
+            unsafe { CloseServiceHandle(self.0) };
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! バイト数の解析と表示を扱う。
+
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(test)]
+mod test;
+
+const UNITS: &[(&str, u64)] = &[
+    ("TiB", 1 << 40),
+    ("GiB", 1 << 30),
+    ("MiB", 1 << 20),
+    ("KiB", 1 << 10),
+];
+
+/// バイト数
+///
+/// `Display`では`1.4 GiB`のように2進接頭辞で概数を表示する。
+/// 代替形式(`{:#}`)ではバイト単位の正確な値を表示する。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Size(u64);
+
+impl Size {
+    /// バイト数を返す。
+    pub fn bytes(self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for Size {
+    fn from(bytes: u64) -> Size {
+        Size(bytes)
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return write!(f, "{} B", self.0);
+        }
+
+        for (unit, scale) in UNITS {
+            if self.0 >= *scale {
+                return write!(f, "{:.1} {}", self.0 as f64 / *scale as f64, unit);
+            }
+        }
+        write!(f, "{} B", self.0)
+    }
+}
+
+impl FromStr for Size {
+    type Err = String;
+
+    /// `500MiB`, `1.5 GB`, `4096`のような表記を解析する。
+    ///
+    /// `K`, `M`, `G`, `T`と`KiB`, `MiB`, `GiB`, `TiB`は2進接頭辞、`KB`, `MB`, `GB`, `TB`は10進接頭辞として扱う。
+    /// 単位を省略した場合はバイト単位とみなす。
+    ///
+    /// # Failures
+    ///
+    /// 解析できない場合、その文字列をコピーした`String`を返す。
+    fn from_str(s: &str) -> Result<Size, String> {
+        let trimmed = s.trim();
+        let split = trimmed
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(trimmed.len());
+        let (num_str, unit) = trimmed.split_at(split);
+
+        let scale: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kib" => 1 << 10,
+            "m" | "mib" => 1 << 20,
+            "g" | "gib" => 1 << 30,
+            "t" | "tib" => 1 << 40,
+            "kb" => 1_000,
+            "mb" => 1_000_000,
+            "gb" => 1_000_000_000,
+            "tb" => 1_000_000_000_000,
+            _ => return Err(s.to_owned()),
+        };
+
+        if let Ok(n) = num_str.parse::<u64>() {
+            return n.checked_mul(scale).map(Size).ok_or_else(|| s.to_owned());
+        }
+
+        let n: f64 = num_str.parse().map_err(|_| s.to_owned())?;
+        let bytes = n * scale as f64;
+        if bytes.is_finite() && bytes >= 0.0 && bytes < u64::MAX as f64 {
+            Ok(Size(bytes.round() as u64))
+        } else {
+            Err(s.to_owned())
+        }
+    }
+}
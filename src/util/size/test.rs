@@ -0,0 +1,46 @@
+use super::*;
+
+#[test]
+fn test_parse_success() {
+    let cases = vec![
+        ("0", 0),
+        ("4096", 4096),
+        ("12B", 12),
+        ("1K", 1024),
+        ("1KiB", 1024),
+        ("1kb", 1000),
+        ("500MiB", 500 * 1024 * 1024),
+        ("500 MB", 500_000_000),
+        ("1.5G", 1024 * 1024 * 1024 * 3 / 2),
+        ("2TiB", 2 << 40),
+    ];
+
+    for (input, to_be) in cases {
+        let actual: Size = input.parse().unwrap();
+        assert_eq!(to_be, actual.bytes(), "input = {}", input);
+    }
+}
+
+#[test]
+fn test_parse_fails() {
+    for input in &["", "MiB", "1.2.3K", "10 XB", "-1", "99999999999T"] {
+        assert!(input.parse::<Size>().is_err(), "input = {}", input);
+    }
+}
+
+#[test]
+fn test_display() {
+    let cases = vec![
+        (0, "0 B"),
+        (1023, "1023 B"),
+        (1024, "1.0 KiB"),
+        (1536, "1.5 KiB"),
+        (1_503_238_553, "1.4 GiB"),
+        (3 << 40, "3.0 TiB"),
+    ];
+
+    for (bytes, to_be) in cases {
+        assert_eq!(to_be, Size::from(bytes).to_string());
+    }
+    assert_eq!("1536 B", format!("{:#}", Size::from(1536)));
+}
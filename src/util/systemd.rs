@@ -0,0 +1,97 @@
+//! systemdのサービスとして実行されている場合に、状態を通知する。
+//!
+//! 通知は環境変数`NOTIFY_SOCKET`で指定されたソケットに`sd_notify(3)`と同じ形式で送る。
+//! systemd以外から起動された場合や、Unix系以外の環境では何もしない。
+
+use std::env;
+use std::io;
+use std::time::Duration;
+
+/// サービスの起動が完了したことを通知する。
+pub fn ready() -> io::Result<bool> {
+    notify("READY=1")
+}
+
+/// サービスの状態を表す文字列を通知する。
+///
+/// 通知した文字列は`systemctl status`で表示される。
+pub fn status(msg: &str) -> io::Result<bool> {
+    notify(&format!("STATUS={}", msg.replace('\n', " ")))
+}
+
+/// サービスが停止処理中であることを通知する。
+pub fn stopping() -> io::Result<bool> {
+    notify("STOPPING=1")
+}
+
+/// ウォッチドッグのタイマーをリセットする。
+pub fn watchdog() -> io::Result<bool> {
+    notify("WATCHDOG=1")
+}
+
+/// ウォッチドッグが有効な場合、タイマーの時間を返す。
+///
+/// この時間内に[`watchdog`](fn.watchdog.html)を呼び出さなければ、サービスは異常終了したとみなされる。
+pub fn watchdog_timeout() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+
+    env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|&usec| usec > 0)
+        .map(Duration::from_micros)
+}
+
+/// `state`をsystemdに通知する。
+///
+/// 通知先のソケットが存在しない場合は`false`を返す。
+pub fn notify(state: &str) -> io::Result<bool> {
+    let path = match env::var_os("NOTIFY_SOCKET") {
+        Some(path) if !path.is_empty() => path,
+        _ => return Ok(false),
+    };
+
+    send(&path, state)?;
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn send(path: &std::ffi::OsStr, state: &str) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+    let bytes = path.as_bytes();
+    let addr = if bytes.starts_with(b"@") {
+        abstract_addr(&bytes[1..])?
+    } else {
+        SocketAddr::from_pathname(path)?
+    };
+
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn abstract_addr(name: &[u8]) -> io::Result<std::os::unix::net::SocketAddr> {
+    use std::os::linux::net::SocketAddrExt;
+
+    std::os::unix::net::SocketAddr::from_abstract_name(name)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn abstract_addr(_name: &[u8]) -> io::Result<std::os::unix::net::SocketAddr> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "abstract socket is not supported",
+    ))
+}
+
+#[cfg(not(unix))]
+fn send(_path: &std::ffi::OsStr, _state: &str) -> io::Result<()> {
+    Ok(())
+}
@@ -2,7 +2,9 @@
 
 use std::fmt;
 
-use chrono::{DateTime, Local, TimeZone, Utc};
+use std::convert::TryFrom;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use chrono_tz::Tz as ChronoTz;
 
 const FORMAT_DATETIME: &str = "%Y-%m-%d %H:%M:%S";
@@ -48,6 +50,24 @@ impl Tz {
         name.parse().map(Tz::Tz)
     }
 
+    /// `2019-01-01`または`2019-01-01T12:00:00`形式のこのタイムゾーンでの日時を、UNIX epochからの経過秒数に変換する。
+    ///
+    /// 解釈できない場合や、UNIX epochより前の場合は`None`を返す。
+    pub fn parse_datetime(self, s: &str) -> Option<u64> {
+        let datetime = match NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S") {
+            Ok(datetime) => datetime,
+            Err(_) => NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()?
+                .and_hms(0, 0, 0),
+        };
+        let timestamp = match self {
+            Tz::Utc => Utc.from_local_datetime(&datetime).earliest()?.timestamp(),
+            Tz::Local => Local.from_local_datetime(&datetime).earliest()?.timestamp(),
+            Tz::Tz(ref tz) => tz.from_local_datetime(&datetime).earliest()?.timestamp(),
+        };
+        u64::try_from(timestamp).ok()
+    }
+
     /// 指定された`unix_epoch`の日時表現`OutputDateTime`を返す。
     pub fn at(self, unix_epoch: u64) -> OutputDateTime {
         OutputDateTime {
@@ -79,6 +99,15 @@ impl OutputDateTime {
         }
     }
 
+    /// このタイムゾーンでの日時を、タイムゾーンを持たない`NaiveDateTime`として返す。
+    pub fn naive(&self) -> NaiveDateTime {
+        match self.zone {
+            Tz::Utc => self.datetime_in(&Utc).naive_local(),
+            Tz::Local => self.datetime_in(&Local).naive_local(),
+            Tz::Tz(ref tz) => self.datetime_in(tz).naive_local(),
+        }
+    }
+
     fn datetime_in<Z: TimeZone>(&self, zone: &Z) -> DateTime<Z> {
         zone.timestamp(self.unix_epoch as i64, 0)
     }
@@ -89,3 +118,33 @@ impl fmt::Display for OutputDateTime {
         write!(f, "{}", self.format_datetime())
     }
 }
+
+/// `2019-01-01`または`2019-01-01T12:00:00`形式の現地時刻を、UNIX epochからの経過秒数に変換する。
+///
+/// 解釈できない場合や、UNIX epochより前の場合は`None`を返す。
+pub fn parse_local_datetime(s: &str) -> Option<u64> {
+    Tz::Local.parse_datetime(s)
+}
+
+/// `30d`のような経過時間を秒数に変換する。
+///
+/// 単位は`s`(秒), `m`(分), `h`(時間), `d`(日), `w`(週)のいずれか。
+pub fn parse_duration(s: &str) -> Option<u64> {
+    if s.len() < 2 {
+        return None;
+    }
+    let (num, unit) = s.split_at(s.len() - 1);
+    let num: u64 = num.parse().ok()?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return None,
+    };
+    num.checked_mul(unit_secs)
+}
+
+#[cfg(test)]
+mod test;
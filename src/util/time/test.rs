@@ -0,0 +1,40 @@
+use super::*;
+
+#[test]
+fn test_parse_datetime() {
+    let cases = vec![
+        (Tz::Utc, "1970-01-02", Some(86400)),
+        (Tz::Utc, "2019-01-01T12:00:00", Some(1_546_344_000)),
+        (
+            Tz::Tz(chrono_tz::Asia::Tokyo),
+            "2019-01-01",
+            Some(1_546_268_400),
+        ),
+        (Tz::Tz(chrono_tz::Asia::Tokyo), "1970-01-01", None),
+        (Tz::Utc, "2019/01/01", None),
+        (Tz::Utc, "7d", None),
+    ];
+
+    for (tz, input, to_be) in cases {
+        assert_eq!(to_be, tz.parse_datetime(input), "input = {}", input);
+    }
+}
+
+#[test]
+fn test_parse_duration() {
+    let cases = vec![
+        ("30s", Some(30)),
+        ("5m", Some(300)),
+        ("12h", Some(43200)),
+        ("7d", Some(604_800)),
+        ("2w", Some(1_209_600)),
+        ("d", None),
+        ("7", None),
+        ("7y", None),
+        ("-1d", None),
+    ];
+
+    for (input, to_be) in cases {
+        assert_eq!(to_be, parse_duration(input), "input = {}", input);
+    }
+}